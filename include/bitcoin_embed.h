@@ -0,0 +1,73 @@
+/*
+ * C interface to bitcoin-embed, built with the `capi` feature:
+ *
+ *     cargo rustc --lib --release --crate-type cdylib --features capi
+ *
+ * Every list returned by the library is owned by the caller and must be released exactly once
+ * with the matching _free function.
+ */
+
+#ifndef BITCOIN_EMBED_H
+#define BITCOIN_EMBED_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum {
+    EMBED_OK = 0,
+    EMBED_NULL_POINTER = 1,
+    EMBED_INVALID_TRANSACTION = 2,
+    EMBED_INVALID_MESSAGES = 3,
+} EmbedStatus;
+
+typedef struct {
+    uint8_t *data; /* null if empty */
+    size_t len;
+} EmbedBuffer;
+
+typedef struct {
+    char *id;          /* NUL-terminated, e.g. "<txid>:rt:0" */
+    char type_code[3]; /* NUL-terminated, e.g. "rt" */
+    EmbedBuffer bytes;
+} EmbedEmbedding;
+
+typedef struct {
+    EmbedEmbedding *items; /* null if empty */
+    size_t len;
+} EmbedEmbeddings;
+
+typedef struct {
+    uint64_t tag_lo;
+    uint64_t tag_hi;
+    EmbedBuffer body;
+} EmbedMessage;
+
+typedef struct {
+    EmbedMessage *items; /* null if empty */
+    size_t len;
+} EmbedMessages;
+
+/* Extracts the embeddings from a consensus-encoded transaction, excluding bare multisig outputs */
+EmbedStatus embed_extract(const uint8_t *tx, size_t tx_len, EmbedEmbeddings *out);
+
+/* Releases a list returned by embed_extract */
+void embed_embeddings_free(EmbedEmbeddings list);
+
+/* Decodes TLV messages */
+EmbedStatus embed_decode_messages(const uint8_t *bytes, size_t len, EmbedMessages *out);
+
+/* Releases a list returned by embed_decode_messages */
+void embed_messages_free(EmbedMessages list);
+
+/* Releases a buffer owned by the library */
+void embed_buffer_free(EmbedBuffer buffer);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* BITCOIN_EMBED_H */
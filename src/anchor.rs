@@ -0,0 +1,159 @@
+//! # Anchor Outputs
+//!
+//! Recognizes and builds anchor outputs, which anyone can spend to bump a transaction's fee
+//! with a child (CPFP). A pay-to-anchor (P2A) output is the witness v1 program `4e73`, spent
+//! with an empty witness; a bare `OP_TRUE` output is the older, non-segwit equivalent. Anchors
+//! carry no data, so they are never returned as embeddings, but they let zero-fee commit and
+//! reveal transactions be bumped as a package. An anchor below the dust limit (an ephemeral
+//! anchor) is only relayed in a zero-fee transaction whose child spends it in the same package.
+
+use bitcoin::opcodes::OP_TRUE;
+use bitcoin::transaction::InputWeightPrediction;
+use bitcoin::{Amount, Script, ScriptBuf, Transaction, TxOut};
+
+/// The script pubkey of a pay-to-anchor output: `OP_1 <4e73>`
+pub const P2A_SCRIPT: [u8; 4] = [0x51, 0x02, 0x4e, 0x73];
+
+/// The dust limit of a pay-to-anchor output at the default dust relay feerate
+pub const P2A_DUST: Amount = Amount::from_sat(240);
+
+/// The predicted satisfaction of a pay-to-anchor input: an empty script sig and witness
+pub const P2A_SATISFACTION: InputWeightPrediction = InputWeightPrediction::from_slice(0, &[]);
+
+/// The kind of an anchor output
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum AnchorType {
+    /// A pay-to-anchor (P2A) witness output
+    PayToAnchor,
+    /// A bare `OP_TRUE` output
+    OpTrue,
+}
+
+/// Returns the kind of anchor `script` is, if it is one
+pub fn anchor_type(script: &Script) -> Option<AnchorType> {
+    match script.as_bytes() {
+        bytes if bytes == P2A_SCRIPT => Some(AnchorType::PayToAnchor),
+        [opcode] if *opcode == OP_TRUE.to_u8() => Some(AnchorType::OpTrue),
+        _ => None,
+    }
+}
+
+/// Returns the index and kind of each anchor output in `tx`
+pub fn anchors(tx: &Transaction) -> Vec<(usize, AnchorType)> {
+    tx.output
+        .iter()
+        .enumerate()
+        .filter_map(|(index, output)| Some((index, anchor_type(&output.script_pubkey)?)))
+        .collect()
+}
+
+/// Returns a pay-to-anchor output holding `value`
+pub fn p2a_output(value: Amount) -> TxOut {
+    TxOut {
+        value,
+        script_pubkey: ScriptBuf::from_bytes(P2A_SCRIPT.to_vec()),
+    }
+}
+
+/// Appends a pay-to-anchor output holding `value` to `tx`, returning its index
+pub fn append_anchor(tx: &mut Transaction, value: Amount) -> usize {
+    tx.output.push(p2a_output(value));
+    tx.output.len() - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Embedding;
+    use crate::feebump;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::transaction::Version;
+    use bitcoin::{
+        FeeRate, OutPoint, Sequence, TxIn, Txid, Witness, WitnessProgram, WitnessVersion,
+        absolute::LockTime,
+    };
+
+    fn transaction() -> Transaction {
+        Transaction {
+            version: Version(3),
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::new_op_return([1; 8]),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_anchor_type() {
+        let program = WitnessProgram::new(WitnessVersion::V1, &[0x4e, 0x73]).unwrap();
+        assert_eq!(
+            ScriptBuf::new_witness_program(&program).as_bytes(),
+            P2A_SCRIPT
+        );
+        assert_eq!(
+            p2a_output(Amount::ZERO).script_pubkey.minimal_non_dust(),
+            P2A_DUST
+        );
+
+        assert_eq!(
+            anchor_type(Script::from_bytes(&P2A_SCRIPT)),
+            Some(AnchorType::PayToAnchor)
+        );
+        assert_eq!(
+            anchor_type(Script::from_bytes(&[0x51])),
+            Some(AnchorType::OpTrue)
+        );
+        assert_eq!(anchor_type(Script::from_bytes(&[0x51, 0x51])), None);
+        assert_eq!(anchor_type(Script::new()), None);
+    }
+
+    #[test]
+    fn test_append_anchor() {
+        let mut tx = transaction();
+        assert_eq!(append_anchor(&mut tx, Amount::ZERO), 1);
+        tx.output.push(TxOut {
+            value: Amount::ZERO,
+            script_pubkey: ScriptBuf::from_bytes(vec![0x51]),
+        });
+
+        assert_eq!(
+            anchors(&tx),
+            vec![(1, AnchorType::PayToAnchor), (2, AnchorType::OpTrue)]
+        );
+        assert_eq!(Embedding::from_transaction(&tx).len(), 1);
+        assert!(Embedding::from_bare_multisig(&tx).is_empty());
+
+        // A zero-fee parent is bumped by a child spending its anchor
+        let prevouts = vec![TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: ScriptBuf::new(),
+        }];
+        let mut parent = transaction();
+        parent.output[0].value = Amount::ZERO;
+        append_anchor(&mut parent, Amount::from_sat(10_000));
+        assert_eq!(feebump::fee(&parent, &prevouts), Ok(Amount::ZERO));
+
+        let child = feebump::child_pays_for_parent(
+            &parent,
+            &prevouts,
+            1,
+            P2A_SATISFACTION,
+            ScriptBuf::from_bytes(P2A_SCRIPT.to_vec()),
+            FeeRate::from_sat_per_vb(2).unwrap(),
+        )
+        .unwrap();
+        let package_vsize = (parent.weight() + child.weight()).to_vbytes_ceil();
+        assert_eq!(
+            Amount::from_sat(10_000) - child.output[0].value,
+            Amount::from_sat(2 * package_vsize)
+        );
+    }
+}
@@ -0,0 +1,508 @@
+//! # Annex Records
+//!
+//! A taproot annex is `0x50` followed by records, so several protocols can share one annex.
+//! Each record is a tag byte, a LEB128 length, and the value, except this crate's data record:
+//! the data tag ([`TAPROOT_ANNEX_DATA_TAG`]) followed by the data, which runs to the end of the
+//! annex and so is always last. An annex holding only data is therefore `0x50 0x00 <data>`.
+//!
+//! A batch record (tag [`TAPROOT_ANNEX_BATCH_TAG`]) also runs to the end of the annex, and holds
+//! several independent payloads, each a LEB128 length followed by the payload, which extraction
+//! returns as separate embeddings. An annex has at most one data or batch record.
+//!
+//! Protocols that carry data under another tag are read with a [`DataTags`] set, which makes
+//! each tag in it a data record, and written with [`append_tagged_data`]. Payloads report the
+//! tag of the record they came from.
+//!
+//! [`append_data`] and [`append_batch`] add a record to a witness after any records already in
+//! its annex, instead of replacing the annex and breaking the protocols that wrote them.
+
+use crate::{TAPROOT_ANNEX_BATCH_TAG, TAPROOT_ANNEX_DATA_TAG, varint};
+
+use bitcoin::Witness;
+use bitcoin::taproot::TAPROOT_ANNEX_PREFIX;
+use std::fmt;
+
+/// A record in an annex
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct AnnexRecord<'a> {
+    /// The tag
+    pub tag: u8,
+    /// The value, or the data of a data or batch record
+    pub value: &'a [u8],
+}
+
+/// The set of tags whose records are data records, running to the end of the annex. The batch
+/// tag ([`TAPROOT_ANNEX_BATCH_TAG`]) is never a data tag.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct DataTags([u64; 4]);
+
+impl DataTags {
+    /// No data tags, so only batch records carry payloads
+    pub const EMPTY: Self = Self([0; 4]);
+
+    /// Only [`TAPROOT_ANNEX_DATA_TAG`]
+    pub const DEFAULT: Self = Self::EMPTY.with(TAPROOT_ANNEX_DATA_TAG);
+
+    /// Returns the set with `tag` added, or unchanged if `tag` is the batch tag
+    pub const fn with(mut self, tag: u8) -> Self {
+        if tag != TAPROOT_ANNEX_BATCH_TAG {
+            self.0[tag as usize / 64] |= 1 << (tag % 64);
+        }
+        self
+    }
+
+    /// Returns whether `tag` is in the set
+    pub const fn contains(&self, tag: u8) -> bool {
+        self.0[tag as usize / 64] & (1 << (tag % 64)) != 0
+    }
+
+    /// Returns the tags in the set, in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..=u8::MAX).filter(|tag| self.contains(*tag))
+    }
+}
+
+impl Default for DataTags {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl FromIterator<u8> for DataTags {
+    fn from_iter<I: IntoIterator<Item = u8>>(tags: I) -> Self {
+        tags.into_iter().fold(Self::EMPTY, Self::with)
+    }
+}
+
+/// Error types for reading and writing annex records
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AnnexError {
+    /// The annex does not begin with `0x50`
+    MissingPrefix,
+    /// A record's length is invalid or runs past the end of the annex
+    Malformed {
+        /// The byte offset of the record
+        offset: usize,
+    },
+    /// The annex already has a data or batch record
+    DataExists,
+    /// The witness is empty, so it has no spend to attach an annex to
+    EmptyWitness,
+    /// The batch tag cannot tag a data record
+    BatchTag,
+}
+
+/// Returns the records in `annex`, including its `0x50` prefix
+pub fn records(annex: &[u8]) -> Result<Vec<AnnexRecord<'_>>, AnnexError> {
+    records_with(annex, &DataTags::DEFAULT)
+}
+
+/// Returns the records in `annex`, treating each tag in `data_tags` as a data record
+pub fn records_with<'a>(
+    annex: &'a [u8],
+    data_tags: &DataTags,
+) -> Result<Vec<AnnexRecord<'a>>, AnnexError> {
+    let Some((&TAPROOT_ANNEX_PREFIX, mut rest)) = annex.split_first() else {
+        return Err(AnnexError::MissingPrefix);
+    };
+
+    let mut records = Vec::new();
+    while let Some((&tag, after_tag)) = rest.split_first() {
+        if is_final(tag, data_tags) {
+            records.push(AnnexRecord {
+                tag,
+                value: after_tag,
+            });
+            break;
+        }
+
+        let offset = annex.len() - rest.len();
+        let malformed = AnnexError::Malformed { offset };
+        let (length, size) = varint::decode(after_tag).map_err(|_| malformed)?;
+        let value = usize::try_from(length)
+            .ok()
+            .and_then(|length| after_tag.get(size..size.checked_add(length)?))
+            .ok_or(malformed)?;
+
+        records.push(AnnexRecord { tag, value });
+        rest = &after_tag[size + value.len()..];
+    }
+
+    Ok(records)
+}
+
+/// Returns the data in `annex`, if it has a non-empty data record and its records are well
+/// formed
+pub fn data(annex: &[u8]) -> Option<&[u8]> {
+    records(annex)
+        .ok()?
+        .last()
+        .filter(|record| record.tag == TAPROOT_ANNEX_DATA_TAG && !record.value.is_empty())
+        .map(|record| record.value)
+}
+
+/// Returns the payloads in `annex`: the data of a data record, or each non-empty payload of a
+/// batch record. Returns no payloads if the annex has neither, or if its records or batch are
+/// malformed.
+pub fn payloads(annex: &[u8]) -> Vec<&[u8]> {
+    payloads_with(annex, &DataTags::DEFAULT)
+        .into_iter()
+        .map(|payload| payload.value)
+        .collect()
+}
+
+/// Returns the payloads in `annex` as in [`payloads`], treating each tag in `data_tags` as a
+/// data record. Each payload has the tag of its record.
+pub fn payloads_with<'a>(annex: &'a [u8], data_tags: &DataTags) -> Vec<AnnexRecord<'a>> {
+    indexed_payloads_with(annex, data_tags)
+        .into_iter()
+        .map(|(_, payload)| payload)
+        .collect()
+}
+
+/// Returns the payloads in `annex` as in [`payloads_with`], each with its index: the position of
+/// its record among the annex's records, plus its position among the payloads of a batch record.
+/// The index does not depend on `data_tags`, so [`payload_at`] finds the payload without them.
+pub fn indexed_payloads_with<'a>(
+    annex: &'a [u8],
+    data_tags: &DataTags,
+) -> Vec<(usize, AnnexRecord<'a>)> {
+    let Some((position, record)) = records_with(annex, data_tags)
+        .ok()
+        .and_then(|records| Some((records.len().checked_sub(1)?, *records.last()?)))
+    else {
+        return Vec::new();
+    };
+
+    match record.tag {
+        TAPROOT_ANNEX_BATCH_TAG => decode_batch(record.value)
+            .unwrap_or_default()
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| {
+                let payload = AnnexRecord {
+                    tag: record.tag,
+                    value,
+                };
+                (position + index, payload)
+            })
+            .collect(),
+        tag if data_tags.contains(tag) && !record.value.is_empty() => vec![(position, record)],
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the payload at `index` in `annex`, as numbered by [`indexed_payloads_with`] under
+/// any data tags. The record at the index is read as a data record whatever its tag.
+pub fn payload_at(annex: &[u8], index: usize) -> Option<AnnexRecord<'_>> {
+    let (&TAPROOT_ANNEX_PREFIX, mut rest) = annex.split_first()? else {
+        return None;
+    };
+
+    let mut position = 0;
+    loop {
+        let (&tag, after_tag) = rest.split_first()?;
+        if tag == TAPROOT_ANNEX_BATCH_TAG {
+            let value = *decode_batch(after_tag)?.get(index.checked_sub(position)?)?;
+            return Some(AnnexRecord { tag, value });
+        }
+        if position == index {
+            return (!after_tag.is_empty()).then_some(AnnexRecord {
+                tag,
+                value: after_tag,
+            });
+        }
+
+        let (length, size) = varint::decode(after_tag).ok()?;
+        let length = usize::try_from(length).ok()?;
+        after_tag.get(size..size.checked_add(length)?)?;
+        rest = &after_tag[size + length..];
+        position += 1;
+    }
+}
+
+/// Returns the value of a batch record holding `payloads`
+pub fn encode_batch(payloads: &[&[u8]]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for payload in payloads {
+        varint::encode_to_vec(payload.len() as u128, &mut bytes);
+        bytes.extend_from_slice(payload);
+    }
+    bytes
+}
+
+/// Returns the non-empty payloads in the value of a batch record, or `None` if a length is
+/// invalid or runs past the end
+fn decode_batch(mut value: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut payloads = Vec::new();
+    while !value.is_empty() {
+        let (length, size) = varint::decode(value).ok()?;
+        let length = usize::try_from(length).ok()?;
+        let payload = value.get(size..size.checked_add(length)?)?;
+        if !payload.is_empty() {
+            payloads.push(payload);
+        }
+        value = &value[size + length..];
+    }
+    Some(payloads)
+}
+
+/// Returns the bytes of a record with `tag` and `value`, for building an annex. A data or
+/// batch record takes no length.
+pub fn encode_record(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![tag];
+    if !is_final(tag, &DataTags::DEFAULT) {
+        varint::encode_to_vec(value.len() as u128, &mut bytes);
+    }
+    bytes.extend_from_slice(value);
+    bytes
+}
+
+/// Appends a data record holding `data` to the annex of `witness`, keeping the records already
+/// there, or adds an annex if the witness has none. Fails without changing the witness if the
+/// annex cannot be parsed or already holds data.
+pub fn append_data(witness: &mut Witness, data: &[u8]) -> Result<(), AnnexError> {
+    append_tagged_data(witness, TAPROOT_ANNEX_DATA_TAG, data)
+}
+
+/// Appends a data record with `tag` holding `data` to the annex of `witness`, as in
+/// [`append_data`]. The record is read back with a [`DataTags`] set containing `tag`.
+pub fn append_tagged_data(witness: &mut Witness, tag: u8, data: &[u8]) -> Result<(), AnnexError> {
+    if tag == TAPROOT_ANNEX_BATCH_TAG {
+        return Err(AnnexError::BatchTag);
+    }
+    append_record(
+        witness,
+        &[&[tag], data].concat(),
+        &DataTags::DEFAULT.with(tag),
+    )
+}
+
+/// Appends a batch record holding `payloads` to the annex of `witness`, as in [`append_data`]
+pub fn append_batch(witness: &mut Witness, payloads: &[&[u8]]) -> Result<(), AnnexError> {
+    append_record(
+        witness,
+        &encode_record(TAPROOT_ANNEX_BATCH_TAG, &encode_batch(payloads)),
+        &DataTags::DEFAULT,
+    )
+}
+
+/// Returns whether a record with `tag` runs to the end of the annex
+fn is_final(tag: u8, data_tags: &DataTags) -> bool {
+    tag == TAPROOT_ANNEX_BATCH_TAG || data_tags.contains(tag)
+}
+
+fn append_record(
+    witness: &mut Witness,
+    record: &[u8],
+    data_tags: &DataTags,
+) -> Result<(), AnnexError> {
+    if witness.is_empty() {
+        return Err(AnnexError::EmptyWitness);
+    }
+
+    let mut elements = witness.to_vec();
+    match witness.taproot_annex() {
+        Some(annex) => {
+            if records_with(annex, data_tags)?
+                .iter()
+                .any(|record| is_final(record.tag, data_tags))
+            {
+                return Err(AnnexError::DataExists);
+            }
+            let annex = elements.last_mut().expect("annex is the last element");
+            annex.extend_from_slice(record);
+        }
+        None => elements.push([&[TAPROOT_ANNEX_PREFIX], record].concat()),
+    }
+
+    *witness = Witness::from_slice(&elements);
+    Ok(())
+}
+
+impl fmt::Display for AnnexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnnexError::MissingPrefix => write!(f, "Annex does not begin with 0x50"),
+            AnnexError::Malformed { offset } => {
+                write!(f, "Malformed annex record at byte {offset}")
+            }
+            AnnexError::DataExists => write!(f, "Annex already has a data or batch record"),
+            AnnexError::EmptyWitness => write!(f, "Witness is empty"),
+            AnnexError::BatchTag => write!(f, "The batch tag cannot tag a data record"),
+        }
+    }
+}
+
+impl std::error::Error for AnnexError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records() {
+        let annex = [&[0x50][..], &encode_record(3, b"abc"), &[0x00], b"data"].concat();
+        assert_eq!(
+            records(&annex),
+            Ok(vec![
+                AnnexRecord {
+                    tag: 3,
+                    value: b"abc"
+                },
+                AnnexRecord {
+                    tag: 0,
+                    value: b"data"
+                },
+            ])
+        );
+        assert_eq!(data(&annex), Some(&b"data"[..]));
+
+        assert_eq!(records(&[0x50, 0x00, 1, 2]).unwrap().len(), 1);
+        assert_eq!(data(&[0x50, 0x00, 1, 2]), Some(&[1, 2][..]));
+        assert_eq!(data(&[0x50, 0x00]), None);
+        assert_eq!(records(&[0x50]), Ok(vec![]));
+        assert_eq!(records(&[0x51, 0x00]), Err(AnnexError::MissingPrefix));
+
+        // A length running past the end
+        let annex = [0x50, 0x02, 0x05, 1, 2];
+        assert_eq!(records(&annex), Err(AnnexError::Malformed { offset: 1 }));
+        assert_eq!(data(&annex), None);
+    }
+
+    #[test]
+    fn test_append_data() {
+        let signature = vec![1; 64];
+
+        // No annex yet
+        let mut witness = Witness::from_slice(&[&signature]);
+        append_data(&mut witness, b"hello").unwrap();
+        assert_eq!(witness.len(), 2);
+        assert_eq!(witness.taproot_annex(), Some(&b"\x50\x00hello"[..]));
+
+        // Another protocol's record is kept ahead of the data
+        let other = [&[0x50][..], &encode_record(7, &[9; 3])].concat();
+        let mut witness = Witness::from_slice(&[signature.clone(), other.clone()]);
+        append_data(&mut witness, b"hello").unwrap();
+        let annex = witness.taproot_annex().unwrap();
+        assert_eq!(annex, [&other[..], b"\x00hello"].concat());
+        assert_eq!(records(annex).unwrap()[0].value, &[9; 3]);
+        assert_eq!(data(annex), Some(&b"hello"[..]));
+
+        // Conflicts leave the witness unchanged
+        let before = witness.clone();
+        assert_eq!(
+            append_data(&mut witness, b"again"),
+            Err(AnnexError::DataExists)
+        );
+        assert_eq!(witness, before);
+
+        let mut witness = Witness::from_slice(&[signature, vec![0x50, 0x02, 0x05]]);
+        assert_eq!(
+            append_data(&mut witness, b"x"),
+            Err(AnnexError::Malformed { offset: 1 })
+        );
+        assert_eq!(
+            append_data(&mut Witness::new(), b"x"),
+            Err(AnnexError::EmptyWitness)
+        );
+    }
+
+    #[test]
+    fn test_payloads() {
+        let batch = encode_batch(&[b"one", b"", b"three"]);
+        let annex = [&[0x50, TAPROOT_ANNEX_BATCH_TAG][..], &batch].concat();
+        assert_eq!(records(&annex).unwrap().len(), 1);
+        assert_eq!(payloads(&annex), vec![&b"one"[..], b"three"]);
+        assert_eq!(data(&annex), None);
+
+        assert_eq!(payloads(b"\x50\x00data"), vec![&b"data"[..]]);
+        assert!(payloads(&[0x50, 0x00]).is_empty());
+        assert!(payloads(&[0x50, TAPROOT_ANNEX_BATCH_TAG]).is_empty());
+        assert!(payloads(&[0x50, 0x07, 0x01, 0x00]).is_empty());
+
+        // A payload running past the end
+        assert!(payloads(&[0x50, TAPROOT_ANNEX_BATCH_TAG, 0x03, 1, 2]).is_empty());
+    }
+
+    #[test]
+    fn test_append_batch() {
+        let other = [&[0x50][..], &encode_record(7, &[9; 3])].concat();
+        let mut witness = Witness::from_slice(&[vec![1; 64], other]);
+        append_batch(&mut witness, &[b"a", b"bc"]).unwrap();
+        let annex = witness.taproot_annex().unwrap();
+        assert_eq!(records(annex).unwrap().len(), 2);
+        assert_eq!(payloads(annex), vec![&b"a"[..], b"bc"]);
+
+        // A batch conflicts with data and with another batch
+        let before = witness.clone();
+        assert_eq!(append_data(&mut witness, b"x"), Err(AnnexError::DataExists));
+        assert_eq!(
+            append_batch(&mut witness, &[b"x"]),
+            Err(AnnexError::DataExists)
+        );
+        assert_eq!(witness, before);
+    }
+
+    #[test]
+    fn test_data_tags() {
+        let tags = DataTags::DEFAULT.with(0x42).with(TAPROOT_ANNEX_BATCH_TAG);
+        assert_eq!(tags.iter().collect::<Vec<_>>(), vec![0, 0x42]);
+        assert_eq!([0x42, 0].into_iter().collect::<DataTags>(), tags);
+        assert!(tags.contains(0x42) && !tags.contains(0x43));
+        assert_eq!(DataTags::EMPTY.iter().count(), 0);
+        assert!(DataTags::default().contains(TAPROOT_ANNEX_DATA_TAG));
+
+        // Another protocol's data under tag 0x42 is a length-prefixed record by default
+        let annex = b"\x50\x42\x02hi";
+        assert_eq!(records(annex).unwrap()[0].value, b"hi");
+        assert!(payloads(annex).is_empty());
+        assert_eq!(
+            payloads_with(annex, &tags),
+            vec![AnnexRecord {
+                tag: 0x42,
+                value: b"\x02hi"
+            }]
+        );
+
+        // Indexes count the records before the payload's, whatever the data tags
+        let annex = [&encode_record(7, b"x")[..], b"\x42hi\x00"].concat();
+        let annex = [&[0x50][..], &annex].concat();
+        let indexed = indexed_payloads_with(&annex, &tags);
+        assert_eq!(indexed.len(), 1);
+        assert_eq!(indexed[0].0, 1);
+        assert_eq!(payload_at(&annex, 1), Some(indexed[0].1));
+        assert_eq!(payload_at(&annex, 0).unwrap().tag, 7);
+        assert_eq!(payload_at(&annex, 2), None);
+
+        let batch = [
+            &[0x50, TAPROOT_ANNEX_BATCH_TAG][..],
+            &encode_batch(&[b"a", b"b"]),
+        ]
+        .concat();
+        assert_eq!(payloads_with(&batch, &DataTags::EMPTY)[0].tag, 1);
+        assert_eq!(payload_at(&batch, 1).unwrap().value, b"b");
+        assert!(payloads_with(b"\x50\x00data", &DataTags::EMPTY).is_empty());
+    }
+
+    #[test]
+    fn test_append_tagged_data() {
+        let mut witness = Witness::from_slice(&[vec![1; 64]]);
+        append_tagged_data(&mut witness, 0x42, b"hello").unwrap();
+        let annex = witness.taproot_annex().unwrap();
+        assert_eq!(annex, b"\x50\x42hello");
+        let tags = DataTags::DEFAULT.with(0x42);
+        assert_eq!(payloads_with(annex, &tags)[0].value, b"hello");
+
+        let before = witness.clone();
+        assert_eq!(
+            append_tagged_data(&mut witness, 0x42, b"x"),
+            Err(AnnexError::DataExists)
+        );
+        assert_eq!(
+            append_tagged_data(&mut witness, TAPROOT_ANNEX_BATCH_TAG, b"x"),
+            Err(AnnexError::BatchTag)
+        );
+        assert_eq!(witness, before);
+    }
+}
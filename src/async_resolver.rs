@@ -0,0 +1,320 @@
+//! # Async Resolvers
+//!
+//! Async counterparts of [`Resolver`] and [`ChainSource`] for services running on an async
+//! runtime. [`Blocking`] adapts any blocking implementation by running its calls on tokio's
+//! blocking thread pool.
+
+use crate::follower::ChainSource;
+use crate::resolver::{self, ConfirmedEmbedding, ResolveError, Resolver};
+use crate::{Embedding, EmbeddingId};
+
+use bitcoin::block::Header;
+use bitcoin::{Block, BlockHash, Transaction, Txid};
+use std::future::Future;
+use std::sync::Arc;
+
+/// An async source of transactions and blocks from which embeddings can be resolved
+pub trait AsyncResolver: Sync {
+    /// Returns the transaction with the given txid
+    fn transaction(
+        &self,
+        txid: &Txid,
+    ) -> impl Future<Output = Result<Transaction, ResolveError>> + Send;
+
+    /// Returns the block with the given hash
+    fn block(&self, hash: &BlockHash) -> impl Future<Output = Result<Block, ResolveError>> + Send;
+
+    /// Returns multiple transactions, in order. Backends that support batching should override
+    /// this to fetch them in a single round trip.
+    fn transactions(
+        &self,
+        txids: &[Txid],
+    ) -> impl Future<Output = Result<Vec<Transaction>, ResolveError>> + Send {
+        async move {
+            let mut txs = Vec::with_capacity(txids.len());
+            for txid in txids {
+                txs.push(self.transaction(txid).await?);
+            }
+            Ok(txs)
+        }
+    }
+
+    /// Returns the embedding referenced by an id
+    fn embedding(
+        &self,
+        id: &EmbeddingId,
+    ) -> impl Future<Output = Result<Embedding, ResolveError>> + Send {
+        async move {
+            let tx = self.transaction(&id.txid).await?;
+            id.locate(&tx).ok_or(ResolveError::EmbeddingNotFound(*id))
+        }
+    }
+
+    /// Returns the embeddings referenced by multiple ids, in order. Each transaction is fetched
+    /// once, however many ids reference it.
+    fn embeddings(
+        &self,
+        ids: &[EmbeddingId],
+    ) -> impl Future<Output = Result<Vec<Embedding>, ResolveError>> + Send {
+        async move {
+            let txids = resolver::unique_txids(ids);
+            let txs = self.transactions(&txids).await?;
+            resolver::locate_all(ids, &txids, &txs)
+        }
+    }
+
+    /// Returns the embedding holding the content of the embedding referenced by `id`,
+    /// following pointer messages at most `max_depth` times
+    fn resolve_pointer(
+        &self,
+        id: &EmbeddingId,
+        max_depth: usize,
+    ) -> impl Future<Output = Result<Embedding, ResolveError>> + Send {
+        async move {
+            let mut chain = resolver::PointerChain::new(id, max_depth);
+            let mut embedding = self.embedding(id).await?;
+            while let Some(target) = chain.next(&embedding)? {
+                embedding = self.embedding(&target).await?;
+            }
+            Ok(embedding)
+        }
+    }
+
+    /// Returns every embedding in a transaction
+    fn embeddings_in_tx(
+        &self,
+        txid: &Txid,
+    ) -> impl Future<Output = Result<Vec<Embedding>, ResolveError>> + Send {
+        async move {
+            let tx = self.transaction(txid).await?;
+            Ok(Embedding::from_transaction(&tx))
+        }
+    }
+
+    /// Returns every embedding in a block, in transaction order
+    fn embeddings_in_block(
+        &self,
+        hash: &BlockHash,
+    ) -> impl Future<Output = Result<Vec<Embedding>, ResolveError>> + Send {
+        async move {
+            let block = self.block(hash).await?;
+            Ok(block
+                .txdata
+                .iter()
+                .flat_map(Embedding::from_transaction)
+                .collect())
+        }
+    }
+
+    /// Returns every embedding in the block at `height`, in transaction order, with its
+    /// confirmation metadata
+    fn confirmed_embeddings_in_block(
+        &self,
+        hash: &BlockHash,
+        height: u64,
+    ) -> impl Future<Output = Result<Vec<ConfirmedEmbedding>, ResolveError>> + Send {
+        async move {
+            let block = self.block(hash).await?;
+            Ok(ConfirmedEmbedding::from_block(&block, height))
+        }
+    }
+}
+
+/// An async resolver that can also report the state of the best chain
+pub trait AsyncChainSource: AsyncResolver {
+    /// Returns the height of the best chain tip
+    fn best_height(&self) -> impl Future<Output = Result<u64, ResolveError>> + Send;
+
+    /// Returns the hash of the block at a height in the best chain
+    fn block_hash_at(
+        &self,
+        height: u64,
+    ) -> impl Future<Output = Result<BlockHash, ResolveError>> + Send;
+
+    /// Returns the header of a block
+    fn block_header(
+        &self,
+        hash: &BlockHash,
+    ) -> impl Future<Output = Result<Header, ResolveError>> + Send;
+}
+
+/// Runs a blocking [`Resolver`] or [`ChainSource`] on tokio's blocking thread pool. Must be
+/// used from within a tokio runtime.
+#[derive(Debug)]
+pub struct Blocking<R>(Arc<R>);
+
+impl<R> Blocking<R> {
+    /// Wraps a blocking resolver
+    pub fn new(resolver: R) -> Self {
+        Self(Arc::new(resolver))
+    }
+
+    /// Returns the wrapped resolver
+    pub fn inner(&self) -> &R {
+        &self.0
+    }
+
+    async fn run<T, F>(&self, f: F) -> Result<T, ResolveError>
+    where
+        R: Send + Sync + 'static,
+        T: Send + 'static,
+        F: FnOnce(&R) -> Result<T, ResolveError> + Send + 'static,
+    {
+        let resolver = self.0.clone();
+        tokio::task::spawn_blocking(move || f(&resolver))
+            .await
+            .map_err(|e| ResolveError::Backend(e.to_string()))?
+    }
+}
+
+impl<R> Clone for Blocking<R> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<R: Resolver + Send + Sync + 'static> AsyncResolver for Blocking<R> {
+    async fn transaction(&self, txid: &Txid) -> Result<Transaction, ResolveError> {
+        let txid = *txid;
+        self.run(move |r| r.transaction(&txid)).await
+    }
+
+    async fn block(&self, hash: &BlockHash) -> Result<Block, ResolveError> {
+        let hash = *hash;
+        self.run(move |r| r.block(&hash)).await
+    }
+
+    async fn transactions(&self, txids: &[Txid]) -> Result<Vec<Transaction>, ResolveError> {
+        let txids = txids.to_vec();
+        self.run(move |r| r.transactions(&txids)).await
+    }
+
+    async fn embedding(&self, id: &EmbeddingId) -> Result<Embedding, ResolveError> {
+        let id = *id;
+        self.run(move |r| r.embedding(&id)).await
+    }
+
+    async fn embeddings(&self, ids: &[EmbeddingId]) -> Result<Vec<Embedding>, ResolveError> {
+        let ids = ids.to_vec();
+        self.run(move |r| r.embeddings(&ids)).await
+    }
+
+    async fn embeddings_in_tx(&self, txid: &Txid) -> Result<Vec<Embedding>, ResolveError> {
+        let txid = *txid;
+        self.run(move |r| r.embeddings_in_tx(&txid)).await
+    }
+
+    async fn embeddings_in_block(&self, hash: &BlockHash) -> Result<Vec<Embedding>, ResolveError> {
+        let hash = *hash;
+        self.run(move |r| r.embeddings_in_block(&hash)).await
+    }
+
+    async fn confirmed_embeddings_in_block(
+        &self,
+        hash: &BlockHash,
+        height: u64,
+    ) -> Result<Vec<ConfirmedEmbedding>, ResolveError> {
+        let hash = *hash;
+        self.run(move |r| r.confirmed_embeddings_in_block(&hash, height))
+            .await
+    }
+}
+
+impl<R: ChainSource + Send + Sync + 'static> AsyncChainSource for Blocking<R> {
+    async fn best_height(&self) -> Result<u64, ResolveError> {
+        self.run(|r| r.best_height()).await
+    }
+
+    async fn block_hash_at(&self, height: u64) -> Result<BlockHash, ResolveError> {
+        self.run(move |r| r.block_hash_at(height)).await
+    }
+
+    async fn block_header(&self, hash: &BlockHash) -> Result<Header, ResolveError> {
+        let hash = *hash;
+        self.run(move |r| r.block_header(&hash)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::MemoryResolver;
+    use crate::testing::op_return_tx;
+
+    use bitcoin::hashes::Hash;
+    use std::str::FromStr;
+
+    #[tokio::test]
+    async fn test_blocking_resolver() {
+        let tx = op_return_tx(&[1, 2, 3]);
+        let txid = tx.compute_txid();
+
+        let mut memory = MemoryResolver::new();
+        memory.insert_transaction(tx.clone());
+        let resolver = Blocking::new(memory);
+
+        assert_eq!(
+            AsyncResolver::transaction(&resolver, &txid).await,
+            Ok(tx.clone())
+        );
+
+        let embeddings = AsyncResolver::embeddings_in_tx(&resolver, &txid)
+            .await
+            .unwrap();
+        assert_eq!(embeddings, Embedding::from_transaction(&tx));
+        assert_eq!(
+            AsyncResolver::embedding(&resolver, &embeddings[0].id()).await,
+            Ok(embeddings[0].clone())
+        );
+
+        let ids = [embeddings[0].id(), embeddings[0].id()];
+        assert_eq!(
+            AsyncResolver::embeddings(&resolver, &ids).await,
+            Ok(vec![embeddings[0].clone(), embeddings[0].clone()])
+        );
+
+        let missing = EmbeddingId::from_str(&format!("{txid}:rt:1")).unwrap();
+        assert_eq!(
+            AsyncResolver::embedding(&resolver, &missing).await,
+            Err(ResolveError::EmbeddingNotFound(missing))
+        );
+        assert_eq!(
+            AsyncResolver::block(&resolver, &BlockHash::all_zeros()).await,
+            Err(ResolveError::BlockNotFound(BlockHash::all_zeros()))
+        );
+    }
+
+    /// A resolver relying on the provided methods
+    struct Fixed(Transaction);
+
+    impl AsyncResolver for Fixed {
+        async fn transaction(&self, txid: &Txid) -> Result<Transaction, ResolveError> {
+            if *txid == self.0.compute_txid() {
+                Ok(self.0.clone())
+            } else {
+                Err(ResolveError::TransactionNotFound(*txid))
+            }
+        }
+
+        async fn block(&self, hash: &BlockHash) -> Result<Block, ResolveError> {
+            Err(ResolveError::BlockNotFound(*hash))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_provided_methods() {
+        let tx = op_return_tx(&[1, 2, 3]);
+        let resolver = Fixed(tx.clone());
+
+        let embeddings = resolver.embeddings_in_tx(&tx.compute_txid()).await.unwrap();
+        assert_eq!(embeddings, Embedding::from_transaction(&tx));
+        assert_eq!(
+            resolver.embedding(&embeddings[0].id()).await,
+            Ok(embeddings[0].clone())
+        );
+        assert_eq!(
+            resolver.embeddings_in_tx(&Txid::all_zeros()).await,
+            Err(ResolveError::TransactionNotFound(Txid::all_zeros()))
+        );
+    }
+}
@@ -0,0 +1,392 @@
+//! # Payload Attestations
+//!
+//! Proves authorship of an embedding with a BIP-322 "simple" signature by an address, whatever
+//! keys signed the transaction carrying it. The signed message is the binary embedding id
+//! followed by the payload's content hash, so an attestation cannot be moved to another
+//! embedding or to other data. Attestations are carried as a [`tags::ATTESTATION`] message,
+//! which can be published in a later embedding or off chain.
+//!
+//! P2WPKH and P2TR (key path) addresses are supported.
+
+use crate::Embedding;
+use crate::message::{Message, tagged_hash, tags};
+use crate::varint;
+
+use bitcoin::consensus::encode::{deserialize, serialize};
+use bitcoin::hashes::Hash;
+use bitcoin::key::{CompressedPublicKey, Keypair, TapTweak};
+use bitcoin::secp256k1::{self, Secp256k1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType};
+use bitcoin::{
+    Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness, XOnlyPublicKey,
+    absolute::LockTime, opcodes::OP_0, opcodes::all::OP_RETURN, script::Builder,
+    transaction::Version,
+};
+use std::fmt;
+
+/// The BIP-322 message hash tag
+const MESSAGE_TAG: &str = "BIP0322-signed-message";
+
+/// Error types for verifying attestations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttestationError {
+    /// The message is not an attestation
+    NotAttestation,
+    /// The attestation body is malformed
+    InvalidEncoding,
+    /// The address type is not supported
+    UnsupportedScript,
+    /// The signature does not verify
+    InvalidSignature,
+    /// The attested payload is not the expected content
+    ContentMismatch,
+}
+
+/// Returns an attestation of `embedding` signed by the P2TR address of `keypair` (as an
+/// untweaked internal key with no script tree)
+pub fn attest_taproot(embedding: &Embedding, keypair: &Keypair) -> Message {
+    let secp = Secp256k1::new();
+    let script_pubkey = ScriptBuf::new_p2tr(&secp, keypair.x_only_public_key().0, None);
+
+    let to_sign = to_sign(&script_pubkey, &attested_message(embedding));
+    let sighash = SighashCache::new(&to_sign)
+        .taproot_key_spend_signature_hash(
+            0,
+            &Prevouts::All(&[to_sign_prevout(&script_pubkey)]),
+            TapSighashType::Default,
+        )
+        .expect("single input with prevout");
+
+    let tweaked = keypair.tap_tweak(&secp, None).to_keypair();
+    let msg = secp256k1::Message::from_digest(sighash.to_byte_array());
+    let signature = secp.sign_schnorr_no_aux_rand(&msg, &tweaked);
+
+    attestation(
+        &script_pubkey,
+        &Witness::from_slice(&[signature.serialize()]),
+    )
+}
+
+/// Returns an attestation of `embedding` signed by the P2WPKH address of `seckey`
+pub fn attest_p2wpkh(embedding: &Embedding, seckey: &SecretKey) -> Message {
+    let secp = Secp256k1::new();
+    let pubkey = CompressedPublicKey(seckey.public_key(&secp));
+    let script_pubkey = ScriptBuf::new_p2wpkh(&pubkey.wpubkey_hash());
+
+    let to_sign = to_sign(&script_pubkey, &attested_message(embedding));
+    let sighash = SighashCache::new(&to_sign)
+        .p2wpkh_signature_hash(0, &script_pubkey, Amount::ZERO, EcdsaSighashType::All)
+        .expect("p2wpkh script");
+
+    let msg = secp256k1::Message::from_digest(sighash.to_byte_array());
+    let signature = bitcoin::ecdsa::Signature::sighash_all(secp.sign_ecdsa(&msg, seckey));
+
+    attestation(&script_pubkey, &Witness::p2wpkh(&signature, &pubkey.0))
+}
+
+/// Verifies an attestation of `embedding`, returning the script pubkey of the signing address
+pub fn verify_attestation(
+    embedding: &Embedding,
+    message: &Message,
+) -> Result<ScriptBuf, AttestationError> {
+    if message.tag != tags::ATTESTATION {
+        return Err(AttestationError::NotAttestation);
+    }
+
+    let (length, n) =
+        varint::decode_strict(&message.body).map_err(|_| AttestationError::InvalidEncoding)?;
+    let script_end = usize::try_from(length)
+        .ok()
+        .and_then(|length| n.checked_add(length))
+        .filter(|end| *end <= message.body.len())
+        .ok_or(AttestationError::InvalidEncoding)?;
+
+    let script_pubkey = ScriptBuf::from_bytes(message.body[n..script_end].to_vec());
+    let witness = deserialize::<Witness>(&message.body[script_end..])
+        .map_err(|_| AttestationError::InvalidEncoding)?;
+
+    verify_simple(&script_pubkey, &witness, &attested_message(embedding))?;
+
+    Ok(script_pubkey)
+}
+
+/// Verifies an attestation of `embedding`, as in [`verify_attestation`], and checks that the
+/// attested payload is the canonical JSON serialization of `value` ([`crate::jcs`]), returning
+/// the script pubkey of the signing address.
+#[cfg(feature = "jcs")]
+pub fn verify_json_attestation<T: serde::Serialize + ?Sized>(
+    embedding: &Embedding,
+    message: &Message,
+    value: &T,
+) -> Result<ScriptBuf, AttestationError> {
+    let script_pubkey = verify_attestation(embedding, message)?;
+
+    let expected =
+        crate::jcs::canonical_json_hash(value).map_err(|_| AttestationError::ContentMismatch)?;
+    if !embedding.verify_content(&expected) {
+        return Err(AttestationError::ContentMismatch);
+    }
+
+    Ok(script_pubkey)
+}
+
+/// Returns the message an attestation signs: the binary embedding id followed by the content
+/// hash
+fn attested_message(embedding: &Embedding) -> Vec<u8> {
+    [embedding.id().to_bytes(), embedding.content_hash().to_vec()].concat()
+}
+
+fn attestation(script_pubkey: &ScriptBuf, witness: &Witness) -> Message {
+    let mut body = varint::encode(script_pubkey.len() as u128);
+    body.extend(script_pubkey.as_bytes());
+    body.extend(serialize(witness));
+    Message::new(tags::ATTESTATION, body).expect("valid tag")
+}
+
+/// Verifies a BIP-322 simple signature, given as the witness of the `to_sign` transaction
+fn verify_simple(
+    script_pubkey: &ScriptBuf,
+    witness: &Witness,
+    message: &[u8],
+) -> Result<(), AttestationError> {
+    let secp = Secp256k1::verification_only();
+    let to_sign = to_sign(script_pubkey, message);
+    let mut cache = SighashCache::new(&to_sign);
+
+    if script_pubkey.is_p2wpkh() {
+        let [signature, pubkey] = &witness.to_vec()[..] else {
+            return Err(AttestationError::InvalidSignature);
+        };
+        let signature = bitcoin::ecdsa::Signature::from_slice(signature)
+            .map_err(|_| AttestationError::InvalidSignature)?;
+        let pubkey = CompressedPublicKey::from_slice(pubkey)
+            .map_err(|_| AttestationError::InvalidSignature)?;
+
+        if ScriptBuf::new_p2wpkh(&pubkey.wpubkey_hash()) != *script_pubkey
+            || signature.sighash_type != EcdsaSighashType::All
+        {
+            return Err(AttestationError::InvalidSignature);
+        }
+
+        let sighash = cache
+            .p2wpkh_signature_hash(0, script_pubkey, Amount::ZERO, EcdsaSighashType::All)
+            .expect("p2wpkh script");
+        let msg = secp256k1::Message::from_digest(sighash.to_byte_array());
+        secp.verify_ecdsa(&msg, &signature.signature, &pubkey.0)
+            .map_err(|_| AttestationError::InvalidSignature)
+    } else if script_pubkey.is_p2tr() {
+        let [signature] = &witness.to_vec()[..] else {
+            return Err(AttestationError::InvalidSignature);
+        };
+        let signature = bitcoin::taproot::Signature::from_slice(signature)
+            .map_err(|_| AttestationError::InvalidSignature)?;
+        let output_key = XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..])
+            .map_err(|_| AttestationError::InvalidSignature)?;
+
+        let sighash = cache
+            .taproot_key_spend_signature_hash(
+                0,
+                &Prevouts::All(&[to_sign_prevout(script_pubkey)]),
+                signature.sighash_type,
+            )
+            .map_err(|_| AttestationError::InvalidSignature)?;
+        let msg = secp256k1::Message::from_digest(sighash.to_byte_array());
+        secp.verify_schnorr(&signature.signature, &msg, &output_key)
+            .map_err(|_| AttestationError::InvalidSignature)
+    } else {
+        Err(AttestationError::UnsupportedScript)
+    }
+}
+
+/// Returns the BIP-322 `to_spend` transaction
+fn to_spend(script_pubkey: &ScriptBuf, message: &[u8]) -> Transaction {
+    let script_sig = Builder::new()
+        .push_opcode(OP_0)
+        .push_slice(tagged_hash(MESSAGE_TAG, message))
+        .into_script();
+
+    Transaction {
+        version: Version(0),
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::all_zeros(), 0xFFFF_FFFF),
+            script_sig,
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![to_sign_prevout(script_pubkey)],
+    }
+}
+
+/// Returns the unsigned BIP-322 `to_sign` transaction
+fn to_sign(script_pubkey: &ScriptBuf, message: &[u8]) -> Transaction {
+    Transaction {
+        version: Version(0),
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(to_spend(script_pubkey, message).compute_txid(), 0),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: Builder::new().push_opcode(OP_RETURN).into_script(),
+        }],
+    }
+}
+
+fn to_sign_prevout(script_pubkey: &ScriptBuf) -> TxOut {
+    TxOut {
+        value: Amount::ZERO,
+        script_pubkey: script_pubkey.clone(),
+    }
+}
+
+impl fmt::Display for AttestationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttestationError::NotAttestation => write!(f, "Message is not an attestation"),
+            AttestationError::InvalidEncoding => write!(f, "Invalid attestation encoding"),
+            AttestationError::UnsupportedScript => write!(f, "Unsupported address type"),
+            AttestationError::InvalidSignature => write!(f, "Invalid attestation signature"),
+            AttestationError::ContentMismatch => {
+                write!(f, "Attested payload does not match the expected content")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AttestationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EmbeddingLocation;
+
+    use bitcoin::Address;
+    use bitcoin::hex::FromHex;
+    use std::str::FromStr;
+
+    fn embedding(bytes: &[u8]) -> Embedding {
+        Embedding {
+            bytes: bytes.to_vec(),
+            txid: Txid::from_byte_array([1; 32]),
+            location: EmbeddingLocation::OpReturn { output: 0 },
+        }
+    }
+
+    #[test]
+    fn test_bip322_vector() {
+        assert_eq!(
+            tagged_hash(MESSAGE_TAG, b""),
+            <[u8; 32]>::from_hex(
+                "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1"
+            )
+            .unwrap()
+        );
+
+        let script_pubkey = Address::from_str("bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l")
+            .unwrap()
+            .assume_checked()
+            .script_pubkey();
+        let witness = deserialize::<Witness>(
+            &Vec::from_hex(
+                "0247304402206517c8637a7bfc3a154edcba6196d64bbd5b73955cb7da7d1626bcdde466c3640220\
+                 22bf10d19fc0bb69b4596e306b362acaa835293cf693bb176f7324b531f5afec012102c7f12003\
+                 196442943d8588e01aee840423cc54fc1521526a3b85c2b0cbd58872",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            verify_simple(&script_pubkey, &witness, b"Hello World"),
+            Ok(())
+        );
+        assert_eq!(
+            verify_simple(&script_pubkey, &witness, b""),
+            Err(AttestationError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_attestations() {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_seckey_slice(&secp, &[3; 32]).unwrap();
+
+        let data = embedding(b"data");
+        let other = embedding(b"other");
+
+        for attestation in [
+            attest_taproot(&data, &keypair),
+            attest_p2wpkh(&data, &keypair.secret_key()),
+        ] {
+            let script_pubkey = verify_attestation(&data, &attestation).unwrap();
+            assert!(script_pubkey.is_p2tr() || script_pubkey.is_p2wpkh());
+
+            let decoded = Message::decode(&Message::encode(vec![attestation.clone()])).unwrap();
+            assert_eq!(verify_attestation(&data, &decoded[0]), Ok(script_pubkey));
+
+            assert_eq!(
+                verify_attestation(&other, &attestation),
+                Err(AttestationError::InvalidSignature)
+            );
+        }
+    }
+
+    #[cfg(feature = "jcs")]
+    #[test]
+    fn test_json_attestation() {
+        use serde_json::json;
+
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_seckey_slice(&secp, &[3; 32]).unwrap();
+
+        let value = json!({"name": "bitcoin", "supply": 21000000});
+        let data = Embedding {
+            bytes: crate::jcs::to_vec(&value).unwrap(),
+            txid: Txid::from_byte_array([1; 32]),
+            location: EmbeddingLocation::TaprootAnnex {
+                input: 0,
+                index: 0,
+                tag: 0,
+            },
+        };
+        let attestation = attest_taproot(&data, &keypair);
+
+        let reordered = json!({"supply": 21000000.0, "name": "bitcoin"});
+        let script_pubkey = verify_attestation(&data, &attestation).unwrap();
+        assert_eq!(
+            verify_json_attestation(&data, &attestation, &reordered),
+            Ok(script_pubkey)
+        );
+        assert_eq!(
+            verify_json_attestation(&data, &attestation, &json!({"name": "bitcoin"})),
+            Err(AttestationError::ContentMismatch)
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let data = embedding(b"data");
+
+        let message = Message::new(1, vec![]).unwrap();
+        assert_eq!(
+            verify_attestation(&data, &message),
+            Err(AttestationError::NotAttestation)
+        );
+
+        let message = Message::new(tags::ATTESTATION, vec![5, 0]).unwrap();
+        assert_eq!(
+            verify_attestation(&data, &message),
+            Err(AttestationError::InvalidEncoding)
+        );
+
+        let message = attestation(&ScriptBuf::new_op_return([]), &Witness::new());
+        assert_eq!(
+            verify_attestation(&data, &message),
+            Err(AttestationError::UnsupportedScript)
+        );
+    }
+}
@@ -0,0 +1,345 @@
+//! # bitcoin-embed
+//!
+//! A command-line tool for debugging embeddings: decode the embeddings in a transaction, encode
+//! data for each location, compare what a payload costs in each location, and scan a range of
+//! blocks. Commands that read the chain use Bitcoin Core's JSON-RPC interface, configured with
+//! `--rpc-url`, `--rpc-user`, and `--rpc-pass` or the matching `BITCOIN_EMBED_RPC_*` variables.
+
+use bitcoin_embed::envelope::EnvelopeBuilder;
+use bitcoin_embed::pipeline::{self, Pipeline};
+use bitcoin_embed::planner::{self, Planner};
+use bitcoin_embed::policy::OpReturnPolicy;
+use bitcoin_embed::resolver::Resolver;
+use bitcoin_embed::rpc::RpcResolver;
+use bitcoin_embed::schema::EmbeddingRecord;
+use bitcoin_embed::{Embedding, EmbeddingType, ScriptType, TAPROOT_ANNEX_DATA_TAG};
+
+use bitcoin::consensus::encode::deserialize_hex;
+use bitcoin::hex::{DisplayHex, FromHex};
+use bitcoin::script::{Builder, PushBytesBuf};
+use bitcoin::taproot::TAPROOT_ANNEX_PREFIX;
+use bitcoin::{Amount, FeeRate, ScriptBuf, Transaction, TxOut, Txid, Weight};
+use std::env;
+use std::error::Error;
+use std::ops::RangeInclusive;
+use std::process::ExitCode;
+use std::slice;
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+const USAGE: &str = "\
+Usage: bitcoin-embed [--rpc-url URL] [--rpc-user USER] [--rpc-pass PASS] [--json] <command>
+
+Commands:
+  decode <txhex|txid>              Print the embeddings in a transaction
+  encode op_return <hex>           Print an OP_RETURN script pubkey carrying the data
+  encode annex <hex>               Print a taproot annex carrying the data
+  encode envelope <hex>...         Print an envelope script carrying the fields
+  plan <hex> [--fee-rate SAT_VB]   Compare the cost of the data in each location and split
+                                   across them
+  scan <start>..<end> [--workers N]
+                                   Print the embeddings in a range of blocks
+
+With --json, decode and scan print one JSON record per embedding";
+
+/// The number of payload bytes shown in a decoded embedding's hex dump
+const MAX_DUMP_BYTES: usize = 256;
+
+/// The default Bitcoin Core RPC endpoint
+const DEFAULT_RPC_URL: &str = "http://127.0.0.1:8332";
+
+/// Connection settings for the RPC backend
+struct Backend {
+    url: String,
+    user: Option<String>,
+    pass: Option<String>,
+}
+
+fn main() -> ExitCode {
+    match run(env::args().skip(1).collect()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Vec<String>) -> Result<()> {
+    let mut backend = Backend {
+        url: env::var("BITCOIN_EMBED_RPC_URL").unwrap_or_else(|_| DEFAULT_RPC_URL.to_string()),
+        user: env::var("BITCOIN_EMBED_RPC_USER").ok(),
+        pass: env::var("BITCOIN_EMBED_RPC_PASS").ok(),
+    };
+
+    let mut args = args.into_iter();
+    let mut positional = Vec::new();
+    let mut fee_rate = 1;
+    let mut workers = None;
+    let mut json = false;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value for {arg}"));
+        match arg.as_str() {
+            "--rpc-url" => backend.url = value()?,
+            "--rpc-user" => backend.user = Some(value()?),
+            "--rpc-pass" => backend.pass = Some(value()?),
+            "--fee-rate" => fee_rate = value()?.parse()?,
+            "--workers" => workers = Some(value()?.parse()?),
+            "--json" => json = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    let positional = positional.iter().map(String::as_str).collect::<Vec<_>>();
+    match positional.as_slice() {
+        ["decode", tx] => decode(tx, json, &backend),
+        ["encode", "op_return", data] => {
+            println!("{}", op_return(data)?.to_hex_string());
+            Ok(())
+        }
+        ["encode", "annex", data] => {
+            println!("{}", annex(data)?.to_lower_hex_string());
+            Ok(())
+        }
+        ["encode", "envelope", fields @ ..] if !fields.is_empty() => {
+            println!("{}", envelope(fields)?.to_hex_string());
+            Ok(())
+        }
+        ["plan", data] => {
+            let fee_rate = FeeRate::from_sat_per_vb(fee_rate).ok_or("fee rate is too high")?;
+            for line in plan(data, fee_rate)? {
+                println!("{line}");
+            }
+            Ok(())
+        }
+        ["scan", range] => scan(parse_range(range)?, workers, json, &backend),
+        _ => Err(USAGE.into()),
+    }
+}
+
+impl Backend {
+    fn connect(&self) -> Result<RpcResolver> {
+        Ok(RpcResolver::new(
+            &self.url,
+            self.user.as_deref(),
+            self.pass.as_deref(),
+        )?)
+    }
+}
+
+/// Prints the embeddings in a hex-encoded transaction, or one fetched by txid
+fn decode(tx: &str, json: bool, backend: &Backend) -> Result<()> {
+    let embeddings = match deserialize_hex::<Transaction>(tx) {
+        Ok(tx) => Embedding::from_transaction(&tx)
+            .into_iter()
+            .chain(Embedding::from_bare_multisig(&tx))
+            .collect(),
+        Err(_) => {
+            let txid = tx
+                .parse::<Txid>()
+                .map_err(|_| "expected a transaction or txid")?;
+            backend.connect()?.embeddings_in_tx(&txid)?
+        }
+    };
+
+    for embedding in &embeddings {
+        if json {
+            println!("{}", serde_json::to_string(embedding)?);
+            continue;
+        }
+        println!(
+            "{} ({}, {} bytes)",
+            embedding.id(),
+            embedding.location,
+            embedding.bytes.len()
+        );
+        let dump = embedding.hex_dump().max_bytes(MAX_DUMP_BYTES).to_string();
+        for line in dump.lines() {
+            println!("  {line}");
+        }
+        let record = EmbeddingRecord::from(embedding);
+        for message in record.messages.iter().flatten() {
+            println!("  message {}: {}", message.tag, message.body);
+        }
+    }
+    Ok(())
+}
+
+fn op_return(data: &str) -> Result<ScriptBuf> {
+    let data = PushBytesBuf::try_from(Vec::from_hex(data)?)?;
+    Ok(ScriptBuf::new_op_return(data))
+}
+
+fn annex(data: &str) -> Result<Vec<u8>> {
+    Ok([
+        &[TAPROOT_ANNEX_PREFIX, TAPROOT_ANNEX_DATA_TAG][..],
+        &Vec::from_hex(data)?,
+    ]
+    .concat())
+}
+
+fn envelope(fields: &[&str]) -> Result<ScriptBuf> {
+    let fields = fields
+        .iter()
+        .map(|field| Vec::from_hex(field))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let builder = EnvelopeBuilder::new()
+        .max_script_size(Weight::MAX_BLOCK.to_wu() as usize)
+        .try_append_to_builder(fields, Builder::new())?;
+    Ok(builder.into_script())
+}
+
+/// Describes the weight, fee, and relay policy of the data in each location, and of the data
+/// split across the locations of one transaction
+fn plan(data: &str, fee_rate: FeeRate) -> Result<Vec<String>> {
+    let data = Vec::from_hex(data)?;
+    let mut lines = vec![format!("{} bytes at {fee_rate:#}", data.len())];
+
+    let describe = |location: &str, weight: Weight, note: &str| {
+        let fee = fee_rate.fee_wu(weight).unwrap_or(Amount::MAX);
+        format!(
+            "{location:<20} {:>8} WU {:>10.2} vB {:>10} sat  {note}",
+            weight.to_wu(),
+            weight.to_wu() as f64 / 4.0,
+            fee.to_sat()
+        )
+    };
+    let weight = |embedding_type| planner::data_weight(embedding_type, data.len()).expect("weight");
+
+    let output = TxOut {
+        value: Amount::ZERO,
+        script_pubkey: ScriptBuf::new_op_return(PushBytesBuf::try_from(data.clone())?),
+    };
+    let note = if OpReturnPolicy::STANDARD
+        .check_outputs(slice::from_ref(&output))
+        .is_ok()
+    {
+        "standard"
+    } else if OpReturnPolicy::CORE_V30
+        .check_outputs(slice::from_ref(&output))
+        .is_ok()
+    {
+        "standard since Bitcoin Core v30"
+    } else {
+        "non-standard"
+    };
+    lines.push(describe(
+        "OP_RETURN output",
+        weight(EmbeddingType::OpReturn),
+        note,
+    ));
+
+    lines.push(describe(
+        "taproot annex",
+        weight(EmbeddingType::TaprootAnnex),
+        "non-standard (requires a cooperating miner)",
+    ));
+
+    lines.push(describe(
+        "tapscript envelope",
+        weight(EmbeddingType::WitnessEnvelope(ScriptType::Tapscript)),
+        "standard (plus a commit output and reveal input)",
+    ));
+
+    let layout = Planner::new().control_record(true).plan(&data)?;
+    let segments = layout
+        .segments
+        .iter()
+        .map(|segment| format!("{} {}", segment.bytes.len(), segment.embedding_type.code()))
+        .collect::<Vec<_>>();
+    lines.push(describe(
+        "split with control",
+        layout.weight(),
+        &format!("{} + ta control record", segments.join(" + ")),
+    ));
+
+    Ok(lines)
+}
+
+/// Parses an inclusive range of heights, such as `800000..800010`
+fn parse_range(range: &str) -> Result<RangeInclusive<u64>> {
+    let (start, end) = range
+        .split_once("..")
+        .ok_or("expected a range such as 800000..800010")?;
+    let (start, end) = (start.parse()?, end.trim_start_matches('=').parse()?);
+    if start > end {
+        return Err("range start is after its end".into());
+    }
+    Ok(start..=end)
+}
+
+/// Prints the embeddings in a range of blocks, in chain order
+fn scan(
+    heights: RangeInclusive<u64>,
+    workers: Option<usize>,
+    json: bool,
+    backend: &Backend,
+) -> Result<()> {
+    let mut pipeline = Pipeline::new();
+    if let Some(workers) = workers {
+        pipeline = pipeline.workers(workers);
+    }
+
+    let blocks = pipeline::chain_blocks(backend.connect()?, heights);
+    for confirmed in pipeline.run(blocks) {
+        let confirmed = confirmed?;
+        if json {
+            println!("{}", serde_json::to_string(&confirmed)?);
+            continue;
+        }
+        println!(
+            "{} {} ({}, {} bytes)",
+            confirmed.height,
+            confirmed.embedding.id(),
+            confirmed.embedding.location,
+            confirmed.embedding.bytes.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("5..10").unwrap(), 5..=10);
+        assert_eq!(parse_range("5..=10").unwrap(), 5..=10);
+        assert!(parse_range("10..5").is_err());
+        assert!(parse_range("5").is_err());
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(op_return("0102").unwrap().to_hex_string(), "6a020102");
+        assert_eq!(annex("ff").unwrap(), vec![0x50, 0, 0xff]);
+        assert!(envelope(&["6f7264", "zz"]).is_err());
+    }
+
+    #[test]
+    fn test_plan() {
+        let rate = FeeRate::from_sat_per_vb(2).unwrap();
+        let lines = plan(&"00".repeat(80), rate).unwrap();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[1].starts_with("OP_RETURN output"));
+        assert!(lines[1].ends_with(" standard"));
+        assert!(lines[4].ends_with("80 rt + ta control record"));
+
+        let lines = plan(&"00".repeat(1000), rate).unwrap();
+        assert!(lines[1].ends_with("standard since Bitcoin Core v30"));
+        assert!(lines[4].ends_with("80 rt + 920 te + ta control record"));
+    }
+
+    #[test]
+    fn test_usage() {
+        assert!(run(vec!["encode".into(), "op_return".into(), "0102".into()]).is_ok());
+        assert!(run(vec!["bogus".into()]).is_err());
+        assert!(run(vec!["--json".into(), "decode".into(), "00".into()]).is_err());
+        assert!(run(vec!["plan".into(), "00".into(), "--fee-rate".into()]).is_err());
+    }
+}
@@ -0,0 +1,220 @@
+//! # Extraction Budgets
+//!
+//! An [`ExtractionBudget`] bounds the data extracted from one transaction. Extraction stops at the
+//! first embedding that would exceed a limit, and the result flags which limit was reached.
+
+use crate::Embedding;
+use crate::annex::DataTags;
+
+use bitcoin::{Transaction, TxOut};
+use std::fmt;
+
+/// Limits on the embeddings extracted from one transaction
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ExtractionBudget {
+    /// The maximum total size of the extracted data bytes, or `None` for no limit
+    pub max_total_payload_bytes: Option<usize>,
+    /// The maximum number of embeddings extracted, or `None` for no limit
+    pub max_embeddings_per_tx: Option<usize>,
+}
+
+/// The limit a transaction reached during extraction
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BudgetExceeded {
+    /// The next embedding would have exceeded the total size limit
+    PayloadBytes {
+        /// The limit
+        limit: usize,
+    },
+    /// The transaction has more embeddings than the limit
+    Embeddings {
+        /// The limit
+        limit: usize,
+    },
+}
+
+/// The embeddings extracted within a budget
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extraction {
+    /// The embeddings extracted before any limit was reached, in the order of
+    /// [`Embedding::from_transaction`]
+    pub embeddings: Vec<Embedding>,
+    /// The limit that stopped extraction, if any. The embeddings are then incomplete.
+    pub exceeded: Option<BudgetExceeded>,
+}
+
+impl ExtractionBudget {
+    /// No limits, as in [`Embedding::from_transaction`]
+    pub const UNLIMITED: Self = Self {
+        max_total_payload_bytes: None,
+        max_embeddings_per_tx: None,
+    };
+
+    /// At most 400,000 data bytes and 1,000 embeddings per transaction, which every standard
+    /// transaction carrying ordinary amounts of data fits within
+    pub const INDEXER: Self = Self {
+        max_total_payload_bytes: Some(400_000),
+        max_embeddings_per_tx: Some(1_000),
+    };
+
+    /// Extracts the embeddings in `tx` within the budget, classifying witness data with
+    /// `prevouts` as in [`Embedding::from_transaction_with_prevouts`]
+    pub fn extract(&self, tx: &Transaction, prevouts: &[TxOut]) -> Extraction {
+        let txid = tx.compute_txid();
+
+        let outputs = tx
+            .output
+            .iter()
+            .enumerate()
+            .filter_map(|(output, txout)| Embedding::from_output(txid, output, txout));
+        let envelopes = tx.input.iter().enumerate().flat_map(|(input, txin)| {
+            Embedding::from_witness_envelopes(txid, input, txin, prevouts.get(input))
+        });
+        let annexes = tx.input.iter().enumerate().flat_map(|(input, txin)| {
+            Embedding::from_annex(txid, input, txin, prevouts.get(input), &DataTags::DEFAULT)
+        });
+
+        let mut embeddings = Vec::new();
+        let mut total = 0;
+        for embedding in outputs.chain(envelopes).chain(annexes) {
+            if let Some(limit) = self.max_embeddings_per_tx {
+                if embeddings.len() == limit {
+                    return Extraction::exceeded(embeddings, BudgetExceeded::Embeddings { limit });
+                }
+            }
+
+            total += embedding.bytes.len();
+            if let Some(limit) = self.max_total_payload_bytes {
+                if total > limit {
+                    return Extraction::exceeded(
+                        embeddings,
+                        BudgetExceeded::PayloadBytes { limit },
+                    );
+                }
+            }
+
+            embeddings.push(embedding);
+        }
+
+        Extraction {
+            embeddings,
+            exceeded: None,
+        }
+    }
+}
+
+impl Default for ExtractionBudget {
+    fn default() -> Self {
+        Self::UNLIMITED
+    }
+}
+
+impl Extraction {
+    fn exceeded(embeddings: Vec<Embedding>, exceeded: BudgetExceeded) -> Self {
+        Self {
+            embeddings,
+            exceeded: Some(exceeded),
+        }
+    }
+
+    /// Returns whether every embedding in the transaction was extracted
+    pub fn is_complete(&self) -> bool {
+        self.exceeded.is_none()
+    }
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetExceeded::PayloadBytes { limit } => {
+                write!(f, "Embedded data exceeds the limit of {limit} bytes")
+            }
+            BudgetExceeded::Embeddings { limit } => {
+                write!(f, "Embeddings exceed the limit of {limit}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::envelope::EnvelopeBuilder;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::script::Builder;
+    use bitcoin::{
+        Amount, OutPoint, ScriptBuf, Sequence, TxIn, Txid, Witness, absolute::LockTime,
+        transaction::Version,
+    };
+
+    fn transaction(envelopes: usize) -> Transaction {
+        let mut builder = Builder::new();
+        for _ in 0..envelopes {
+            builder = EnvelopeBuilder::new().append_to_builder(vec![vec![7; 100]], builder);
+        }
+        let control_block = [vec![0xc0], vec![1; 32]].concat();
+
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::from_slice(&[builder.into_script().to_bytes(), control_block]),
+            }],
+            output: vec![TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::new_op_return([1, 2]),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_within_budget() {
+        let tx = transaction(3);
+        let extraction = ExtractionBudget::INDEXER.extract(&tx, &[]);
+        assert!(extraction.is_complete());
+        assert_eq!(extraction.embeddings, Embedding::from_transaction(&tx));
+
+        let extraction = ExtractionBudget::default().extract(&tx, &[]);
+        assert_eq!(extraction.embeddings.len(), 4);
+    }
+
+    #[test]
+    fn test_budget_exceeded() {
+        let tx = transaction(50);
+        let all = Embedding::from_transaction(&tx);
+
+        let budget = ExtractionBudget {
+            max_total_payload_bytes: None,
+            max_embeddings_per_tx: Some(10),
+        };
+        let extraction = budget.extract(&tx, &[]);
+        assert_eq!(
+            extraction.exceeded,
+            Some(BudgetExceeded::Embeddings { limit: 10 })
+        );
+        assert_eq!(extraction.embeddings, all[..10]);
+
+        // The OP_RETURN carries 3 bytes and each envelope 100
+        let budget = ExtractionBudget {
+            max_total_payload_bytes: Some(303),
+            max_embeddings_per_tx: None,
+        };
+        let extraction = budget.extract(&tx, &[]);
+        assert_eq!(
+            extraction.exceeded,
+            Some(BudgetExceeded::PayloadBytes { limit: 303 })
+        );
+        assert_eq!(extraction.embeddings, all[..4]);
+        assert!(!extraction.is_complete());
+
+        // Exactly at the limit is within budget
+        let tx = transaction(3);
+        assert!(budget.extract(&tx, &[]).is_complete());
+    }
+}
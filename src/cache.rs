@@ -0,0 +1,333 @@
+//! # Caching Resolver
+//!
+//! [`CachingResolver`] wraps any [`Resolver`] with least-recently-used caches of fetched
+//! transactions, located embeddings, and the embeddings extracted from each transaction.
+//! Lookups of missing transactions and embeddings are cached too, so repeated requests for an
+//! unknown id do not reach the backend; call [`CachingResolver::clear`] after the source learns
+//! of new transactions. Backend failures are never cached, and blocks are not cached.
+
+use crate::resolver::{ResolveError, Resolver};
+use crate::{Embedding, EmbeddingId};
+
+use bitcoin::{Block, BlockHash, Transaction, Txid};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::{Mutex, MutexGuard};
+
+/// The default number of entries in each cache
+pub const DEFAULT_CACHE_CAPACITY: usize = 1024;
+
+/// A resolver that caches the lookups of another
+#[derive(Debug)]
+pub struct CachingResolver<R> {
+    inner: R,
+    caches: Mutex<Caches>,
+}
+
+/// The caches, with `None` recording that the transaction or embedding does not exist
+#[derive(Debug)]
+struct Caches {
+    transactions: Lru<Txid, Option<Transaction>>,
+    embeddings: Lru<EmbeddingId, Option<Embedding>>,
+    tx_embeddings: Lru<Txid, Vec<Embedding>>,
+}
+
+/// A map that evicts its least recently used entry when full
+#[derive(Debug)]
+struct Lru<K, V> {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<K, (V, u64)>,
+    order: BTreeMap<u64, K>,
+}
+
+impl<R: Resolver> CachingResolver<R> {
+    /// Returns a resolver caching up to `capacity` (e.g. [`DEFAULT_CACHE_CAPACITY`])
+    /// transactions, embeddings, and extracted transactions from `inner`
+    pub fn new(inner: R, capacity: usize) -> Self {
+        Self {
+            inner,
+            caches: Mutex::new(Caches {
+                transactions: Lru::new(capacity),
+                embeddings: Lru::new(capacity),
+                tx_embeddings: Lru::new(capacity),
+            }),
+        }
+    }
+
+    /// Returns the wrapped resolver
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns the wrapped resolver, dropping the caches
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Empties every cache
+    pub fn clear(&self) -> Result<(), ResolveError> {
+        let mut caches = self.lock_caches()?;
+        caches.transactions.clear();
+        caches.embeddings.clear();
+        caches.tx_embeddings.clear();
+        Ok(())
+    }
+
+    fn lock_caches(&self) -> Result<MutexGuard<'_, Caches>, ResolveError> {
+        self.caches
+            .lock()
+            .map_err(|_| ResolveError::Backend("cache lock poisoned".to_string()))
+    }
+}
+
+impl<R: Resolver> Resolver for CachingResolver<R> {
+    fn transaction(&self, txid: &Txid) -> Result<Transaction, ResolveError> {
+        if let Some(cached) = self.lock_caches()?.transactions.get(txid) {
+            return cached
+                .clone()
+                .ok_or(ResolveError::TransactionNotFound(*txid));
+        }
+
+        let result = self.inner.transaction(txid);
+        match &result {
+            Ok(tx) => self
+                .lock_caches()?
+                .transactions
+                .insert(*txid, Some(tx.clone())),
+            Err(ResolveError::TransactionNotFound(_)) => {
+                self.lock_caches()?.transactions.insert(*txid, None)
+            }
+            Err(_) => {}
+        }
+        result
+    }
+
+    fn block(&self, hash: &BlockHash) -> Result<Block, ResolveError> {
+        self.inner.block(hash)
+    }
+
+    fn transactions(&self, txids: &[Txid]) -> Result<Vec<Transaction>, ResolveError> {
+        let mut cached = HashMap::new();
+        let mut misses = Vec::new();
+        {
+            let mut caches = self.lock_caches()?;
+            for txid in txids {
+                match caches.transactions.get(txid) {
+                    Some(Some(tx)) => {
+                        cached.insert(*txid, tx.clone());
+                    }
+                    Some(None) => return Err(ResolveError::TransactionNotFound(*txid)),
+                    None => misses.push(*txid),
+                }
+            }
+        }
+
+        if !misses.is_empty() {
+            let fetched = self.inner.transactions(&misses).inspect_err(|e| {
+                if let ResolveError::TransactionNotFound(txid) = e {
+                    if let Ok(mut caches) = self.lock_caches() {
+                        caches.transactions.insert(*txid, None);
+                    }
+                }
+            })?;
+
+            let mut caches = self.lock_caches()?;
+            for (txid, tx) in misses.into_iter().zip(fetched) {
+                caches.transactions.insert(txid, Some(tx.clone()));
+                cached.insert(txid, tx);
+            }
+        }
+
+        Ok(txids.iter().map(|txid| cached[txid].clone()).collect())
+    }
+
+    fn embedding(&self, id: &EmbeddingId) -> Result<Embedding, ResolveError> {
+        if let Some(cached) = self.lock_caches()?.embeddings.get(id) {
+            return cached.clone().ok_or(ResolveError::EmbeddingNotFound(*id));
+        }
+
+        let embedding = id.locate(&self.transaction(&id.txid)?);
+        self.lock_caches()?
+            .embeddings
+            .insert(*id, embedding.clone());
+        embedding.ok_or(ResolveError::EmbeddingNotFound(*id))
+    }
+
+    fn embeddings_in_tx(&self, txid: &Txid) -> Result<Vec<Embedding>, ResolveError> {
+        if let Some(cached) = self.lock_caches()?.tx_embeddings.get(txid) {
+            return Ok(cached.clone());
+        }
+
+        let embeddings = Embedding::from_transaction(&self.transaction(txid)?);
+        self.lock_caches()?
+            .tx_embeddings
+            .insert(*txid, embeddings.clone());
+        Ok(embeddings)
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Lru<K, V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the value for `key`, marking it as most recently used
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let tick = self.next_tick();
+        let (_, used) = self.entries.get_mut(key)?;
+        self.order.remove(used);
+        *used = tick;
+        self.order.insert(tick, key.clone());
+        self.entries.get(key).map(|(value, _)| value)
+    }
+
+    /// Inserts `value` for `key`, evicting the least recently used entry if full
+    fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if let Some((_, used)) = self.entries.remove(&key) {
+            self.order.remove(&used);
+        } else if self.entries.len() == self.capacity {
+            if let Some((_, oldest)) = self.order.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        let tick = self.next_tick();
+        self.order.insert(tick, key.clone());
+        self.entries.insert(key, (value, tick));
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::MemoryResolver;
+    use crate::testing::op_return_tx;
+
+    use bitcoin::hashes::Hash as _;
+    use std::cell::Cell;
+
+    /// Counts the transactions fetched from a [`MemoryResolver`]
+    struct Counting(MemoryResolver, Cell<usize>);
+
+    impl Resolver for Counting {
+        fn transaction(&self, txid: &Txid) -> Result<Transaction, ResolveError> {
+            self.1.set(self.1.get() + 1);
+            self.0.transaction(txid)
+        }
+
+        fn block(&self, hash: &BlockHash) -> Result<Block, ResolveError> {
+            self.0.block(hash)
+        }
+    }
+
+    fn caching(txs: &[Transaction], capacity: usize) -> CachingResolver<Counting> {
+        let mut inner = MemoryResolver::new();
+        for tx in txs {
+            inner.insert_transaction(tx.clone());
+        }
+        CachingResolver::new(Counting(inner, Cell::new(0)), capacity)
+    }
+
+    #[test]
+    fn test_caching() {
+        let txs = [op_return_tx(&[1]), op_return_tx(&[2])];
+        let resolver = caching(&txs, 2);
+        let txid = txs[0].compute_txid();
+
+        for _ in 0..3 {
+            assert_eq!(resolver.transaction(&txid), Ok(txs[0].clone()));
+        }
+        assert_eq!(resolver.inner().1.get(), 1);
+
+        let embeddings = resolver.embeddings_in_tx(&txid).unwrap();
+        let id = embeddings[0].id();
+        assert_eq!(resolver.embedding(&id), Ok(embeddings[0].clone()));
+        assert_eq!(resolver.embedding(&id), Ok(embeddings[0].clone()));
+        assert_eq!(resolver.inner().1.get(), 1);
+
+        // Batches only fetch misses
+        let txids = [txs[1].compute_txid(), txid];
+        assert_eq!(
+            resolver.transactions(&txids),
+            Ok(vec![txs[1].clone(), txs[0].clone()])
+        );
+        assert_eq!(resolver.inner().1.get(), 2);
+
+        resolver.clear().unwrap();
+        resolver.transaction(&txid).unwrap();
+        assert_eq!(resolver.inner().1.get(), 3);
+    }
+
+    #[test]
+    fn test_negative_caching() {
+        let txs = [op_return_tx(&[1])];
+        let resolver = caching(&txs, 4);
+        let missing = Txid::from_byte_array([9; 32]);
+
+        for _ in 0..2 {
+            assert_eq!(
+                resolver.transaction(&missing),
+                Err(ResolveError::TransactionNotFound(missing))
+            );
+        }
+        assert_eq!(
+            resolver.transactions(&[txs[0].compute_txid(), missing]),
+            Err(ResolveError::TransactionNotFound(missing))
+        );
+        assert_eq!(resolver.inner().1.get(), 1);
+
+        let mut id = Embedding::from_transaction(&txs[0])[0].id();
+        id.index = 5;
+        for _ in 0..2 {
+            assert_eq!(
+                resolver.embedding(&id),
+                Err(ResolveError::EmbeddingNotFound(id))
+            );
+        }
+        assert_eq!(resolver.inner().1.get(), 2);
+    }
+
+    #[test]
+    fn test_eviction() {
+        let txs = [op_return_tx(&[1]), op_return_tx(&[2]), op_return_tx(&[3])];
+        let resolver = caching(&txs, 2);
+        let txids = txs.iter().map(|tx| tx.compute_txid()).collect::<Vec<_>>();
+
+        resolver.transaction(&txids[0]).unwrap();
+        resolver.transaction(&txids[1]).unwrap();
+        resolver.transaction(&txids[0]).unwrap();
+        resolver.transaction(&txids[2]).unwrap();
+        assert_eq!(resolver.inner().1.get(), 3);
+
+        // The least recently used transaction was evicted
+        resolver.transaction(&txids[0]).unwrap();
+        assert_eq!(resolver.inner().1.get(), 3);
+        resolver.transaction(&txids[1]).unwrap();
+        assert_eq!(resolver.inner().1.get(), 4);
+
+        let resolver = caching(&txs, 0);
+        resolver.transaction(&txids[0]).unwrap();
+        resolver.transaction(&txids[0]).unwrap();
+        assert_eq!(resolver.inner().1.get(), 2);
+    }
+}
@@ -0,0 +1,342 @@
+//! # C Interface
+//!
+//! A stable C ABI for extracting embeddings and decoding messages from software that cannot
+//! link Rust directly, declared in `include/bitcoin_embed.h`. Every list returned by the library
+//! is owned by the caller and must be released with the matching `_free` function, exactly once.
+//! Functions never unwind across the boundary; failures are reported as an [`EmbedStatus`].
+//! Build the shared library with `cargo rustc --lib --release --crate-type cdylib --features capi`.
+
+// Raw pointers from C are dereferenced only after the null checks each function documents
+#![allow(unsafe_code)]
+
+use crate::Embedding;
+use crate::message::Message;
+
+use bitcoin::Transaction;
+use bitcoin::consensus::deserialize;
+use std::ffi::{CString, c_char};
+use std::ptr;
+
+/// The result of a C interface call
+#[repr(i32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EmbedStatus {
+    /// The call succeeded
+    Ok = 0,
+    /// A required pointer was null
+    NullPointer = 1,
+    /// The bytes are not a consensus-encoded transaction
+    InvalidTransaction = 2,
+    /// The bytes are not valid TLV messages
+    InvalidMessages = 3,
+}
+
+/// A byte buffer owned by the library
+#[repr(C)]
+#[derive(Debug)]
+pub struct EmbedBuffer {
+    /// The bytes, or null if empty
+    pub data: *mut u8,
+    /// The number of bytes
+    pub len: usize,
+}
+
+/// An embedding extracted from a transaction
+#[repr(C)]
+#[derive(Debug)]
+pub struct EmbedEmbedding {
+    /// The NUL-terminated embedding id, e.g. `<txid>:rt:0`
+    pub id: *mut c_char,
+    /// The NUL-terminated two-letter type code
+    pub type_code: [c_char; 3],
+    /// The data bytes
+    pub bytes: EmbedBuffer,
+}
+
+/// A list of embeddings owned by the library
+#[repr(C)]
+#[derive(Debug)]
+pub struct EmbedEmbeddings {
+    /// The embeddings, or null if empty
+    pub items: *mut EmbedEmbedding,
+    /// The number of embeddings
+    pub len: usize,
+}
+
+/// A decoded TLV message
+#[repr(C)]
+#[derive(Debug)]
+pub struct EmbedMessage {
+    /// The low 64 bits of the tag
+    pub tag_lo: u64,
+    /// The high 64 bits of the tag
+    pub tag_hi: u64,
+    /// The message body
+    pub body: EmbedBuffer,
+}
+
+/// A list of messages owned by the library
+#[repr(C)]
+#[derive(Debug)]
+pub struct EmbedMessages {
+    /// The messages, or null if empty
+    pub items: *mut EmbedMessage,
+    /// The number of messages
+    pub len: usize,
+}
+
+/// Extracts the embeddings from `tx_len` bytes of a consensus-encoded transaction at `tx`,
+/// writing them to `out`, as in [`Embedding::from_transaction`], so bare multisig outputs are
+/// not included. Release the list with [`embed_embeddings_free`].
+///
+/// # Safety
+///
+/// `tx` must point to `tx_len` readable bytes (or may be null if `tx_len` is zero), and `out`
+/// must point to writable memory for an [`EmbedEmbeddings`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn embed_extract(
+    tx: *const u8,
+    tx_len: usize,
+    out: *mut EmbedEmbeddings,
+) -> EmbedStatus {
+    if out.is_null() {
+        return EmbedStatus::NullPointer;
+    }
+    // SAFETY: the caller guarantees `tx` points to `tx_len` bytes
+    let Some(bytes) = (unsafe { slice(tx, tx_len) }) else {
+        return EmbedStatus::NullPointer;
+    };
+    let Ok(tx) = deserialize::<Transaction>(bytes) else {
+        return EmbedStatus::InvalidTransaction;
+    };
+
+    let items = Embedding::from_transaction(&tx)
+        .into_iter()
+        .map(|embedding| {
+            let code = embedding.to_type().code().as_bytes();
+            EmbedEmbedding {
+                id: CString::new(embedding.id().to_string())
+                    .expect("ids contain no NUL bytes")
+                    .into_raw(),
+                type_code: [code[0] as c_char, code[1] as c_char, 0],
+                bytes: buffer(embedding.bytes),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let (items, len) = into_raw_parts(items);
+    // SAFETY: `out` is non-null and the caller guarantees it is writable
+    unsafe { out.write(EmbedEmbeddings { items, len }) };
+    EmbedStatus::Ok
+}
+
+/// Releases a list returned by [`embed_extract`]
+///
+/// # Safety
+///
+/// `list` must have been returned by [`embed_extract`] and not already released.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn embed_embeddings_free(list: EmbedEmbeddings) {
+    // SAFETY: the caller guarantees the list came from `embed_extract`
+    for item in unsafe { from_raw_parts(list.items, list.len) } {
+        if !item.id.is_null() {
+            // SAFETY: ids are allocated by `CString::into_raw`
+            drop(unsafe { CString::from_raw(item.id) });
+        }
+        // SAFETY: item buffers are allocated by `buffer`
+        unsafe { embed_buffer_free(item.bytes) };
+    }
+}
+
+/// Decodes `len` bytes of TLV messages at `bytes`, writing them to `out`. Release the list with
+/// [`embed_messages_free`].
+///
+/// # Safety
+///
+/// `bytes` must point to `len` readable bytes (or may be null if `len` is zero), and `out` must
+/// point to writable memory for an [`EmbedMessages`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn embed_decode_messages(
+    bytes: *const u8,
+    len: usize,
+    out: *mut EmbedMessages,
+) -> EmbedStatus {
+    if out.is_null() {
+        return EmbedStatus::NullPointer;
+    }
+    // SAFETY: the caller guarantees `bytes` points to `len` bytes
+    let Some(bytes) = (unsafe { slice(bytes, len) }) else {
+        return EmbedStatus::NullPointer;
+    };
+    let Ok(messages) = Message::decode(bytes) else {
+        return EmbedStatus::InvalidMessages;
+    };
+
+    let items = messages
+        .into_iter()
+        .map(|message| EmbedMessage {
+            tag_lo: message.tag as u64,
+            tag_hi: (message.tag >> 64) as u64,
+            body: buffer(message.body),
+        })
+        .collect::<Vec<_>>();
+
+    let (items, len) = into_raw_parts(items);
+    // SAFETY: `out` is non-null and the caller guarantees it is writable
+    unsafe { out.write(EmbedMessages { items, len }) };
+    EmbedStatus::Ok
+}
+
+/// Releases a list returned by [`embed_decode_messages`]
+///
+/// # Safety
+///
+/// `list` must have been returned by [`embed_decode_messages`] and not already released.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn embed_messages_free(list: EmbedMessages) {
+    // SAFETY: the caller guarantees the list came from `embed_decode_messages`
+    for item in unsafe { from_raw_parts(list.items, list.len) } {
+        // SAFETY: message bodies are allocated by `buffer`
+        unsafe { embed_buffer_free(item.body) };
+    }
+}
+
+/// Releases a buffer owned by the library
+///
+/// # Safety
+///
+/// `buffer` must have been returned by the library and not already released.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn embed_buffer_free(buffer: EmbedBuffer) {
+    // SAFETY: the caller guarantees the buffer came from `buffer`
+    drop(unsafe { from_raw_parts(buffer.data, buffer.len) });
+}
+
+/// Returns the bytes at `data`, or `None` if `data` is null but `len` is not zero
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes or be null.
+unsafe fn slice<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+    match (data.is_null(), len) {
+        (_, 0) => Some(&[]),
+        (true, _) => None,
+        // SAFETY: `data` is non-null and the caller guarantees it points to `len` bytes
+        (false, _) => Some(unsafe { std::slice::from_raw_parts(data, len) }),
+    }
+}
+
+fn buffer(bytes: Vec<u8>) -> EmbedBuffer {
+    let (data, len) = into_raw_parts(bytes);
+    EmbedBuffer { data, len }
+}
+
+/// Leaks `items` as a pointer and length, with a null pointer for an empty list
+fn into_raw_parts<T>(items: Vec<T>) -> (*mut T, usize) {
+    if items.is_empty() {
+        return (ptr::null_mut(), 0);
+    }
+    let len = items.len();
+    (Box::into_raw(items.into_boxed_slice()).cast(), len)
+}
+
+/// Reclaims a list leaked by [`into_raw_parts`]
+///
+/// # Safety
+///
+/// `items` and `len` must have been returned by [`into_raw_parts`] and not already reclaimed.
+unsafe fn from_raw_parts<T>(items: *mut T, len: usize) -> Vec<T> {
+    if items.is_null() {
+        return Vec::new();
+    }
+    // SAFETY: the pointer and length describe a boxed slice leaked by `into_raw_parts`
+    unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(items, len)) }.into_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FixtureBuilder;
+
+    use bitcoin::consensus::serialize;
+    use bitcoin::{Amount, ScriptBuf, TxOut};
+    use std::ffi::CStr;
+    use std::mem::MaybeUninit;
+
+    #[test]
+    fn test_extract() {
+        let tx = FixtureBuilder::new(1)
+            .op_return(&[1, 2, 3])
+            .output(TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::from_bytes(vec![0x6a]),
+            })
+            .bare_multisig(b"not extracted")
+            .build()
+            .tx;
+        let bytes = serialize(&tx);
+        let mut out = MaybeUninit::uninit();
+
+        let status = unsafe { embed_extract(bytes.as_ptr(), bytes.len(), out.as_mut_ptr()) };
+        assert_eq!(status, EmbedStatus::Ok);
+        let list = unsafe { out.assume_init() };
+        assert_eq!(list.len, 2);
+
+        let items = unsafe { std::slice::from_raw_parts(list.items, list.len) };
+        let id = unsafe { CStr::from_ptr(items[0].id) }.to_str().unwrap();
+        assert_eq!(id, format!("{}:rt:0", tx.compute_txid()));
+        let type_code = unsafe { CStr::from_ptr(items[0].type_code.as_ptr()) };
+        assert_eq!(type_code.to_bytes(), b"rt");
+        let data = unsafe { std::slice::from_raw_parts(items[0].bytes.data, items[0].bytes.len) };
+        assert_eq!(data, [3, 1, 2, 3]);
+        assert!(items[1].bytes.data.is_null());
+        assert_eq!(items[1].bytes.len, 0);
+
+        unsafe { embed_embeddings_free(list) };
+    }
+
+    #[test]
+    fn test_extract_errors() {
+        let mut out = MaybeUninit::uninit();
+        assert_eq!(
+            unsafe { embed_extract([0u8].as_ptr(), 1, out.as_mut_ptr()) },
+            EmbedStatus::InvalidTransaction
+        );
+        assert_eq!(
+            unsafe { embed_extract(ptr::null(), 5, out.as_mut_ptr()) },
+            EmbedStatus::NullPointer
+        );
+        assert_eq!(
+            unsafe { embed_extract(ptr::null(), 0, ptr::null_mut()) },
+            EmbedStatus::NullPointer
+        );
+    }
+
+    #[test]
+    fn test_decode_messages() {
+        let tag = u128::from(u64::MAX) + 2;
+        let bytes = Message::encode(vec![
+            Message::new(1, vec![9, 9]).unwrap(),
+            Message::new(tag, vec![]).unwrap(),
+        ]);
+        let mut out = MaybeUninit::uninit();
+
+        let status =
+            unsafe { embed_decode_messages(bytes.as_ptr(), bytes.len(), out.as_mut_ptr()) };
+        assert_eq!(status, EmbedStatus::Ok);
+        let list = unsafe { out.assume_init() };
+
+        let items = unsafe { std::slice::from_raw_parts(list.items, list.len) };
+        assert_eq!((items[0].tag_lo, items[0].tag_hi), (1, 0));
+        let body = unsafe { std::slice::from_raw_parts(items[0].body.data, items[0].body.len) };
+        assert_eq!(body, [9, 9]);
+        assert_eq!((items[1].tag_lo, items[1].tag_hi), (1, 1));
+        unsafe { embed_messages_free(list) };
+
+        let mut out = MaybeUninit::uninit();
+        assert_eq!(
+            unsafe { embed_decode_messages([0x80].as_ptr(), 1, out.as_mut_ptr()) },
+            EmbedStatus::InvalidMessages
+        );
+    }
+}
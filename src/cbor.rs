@@ -0,0 +1,192 @@
+//! # CBOR Bodies
+//!
+//! Encodes message bodies as deterministic CBOR (RFC 8949 §4.2.1), the representation used
+//! for ordinals inscription metadata: integers and lengths are minimal, lengths are definite,
+//! floats use the shortest lossless width, and map keys are sorted by their encoded bytes.
+//! The same value therefore always has the same body and content hash.
+
+use crate::message::{Message, Tag};
+
+use ciborium::Value;
+use serde::{Serialize, de::DeserializeOwned};
+use std::fmt;
+
+/// Error types for encoding and decoding CBOR bodies
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CborError {
+    /// The value could not be serialized
+    Serialize(String),
+    /// The body is not valid CBOR or does not match the requested type
+    Deserialize(String),
+    /// A map has two equal keys
+    DuplicateKey,
+    /// The body has bytes after the first item
+    TrailingBytes,
+}
+
+/// Returns `value` encoded as deterministic CBOR
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, CborError> {
+    let value = Value::serialized(value).map_err(|e| CborError::Serialize(e.to_string()))?;
+    encode(canonicalize(value)?)
+}
+
+/// Decodes a single CBOR item, rejecting trailing bytes. Non-canonical encodings are
+/// accepted; use [`is_canonical`] to check.
+pub fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CborError> {
+    decode(bytes)?
+        .deserialized()
+        .map_err(|e| CborError::Deserialize(e.to_string()))
+}
+
+/// Returns a message with `value` encoded as deterministic CBOR as its body
+pub fn to_message<T: Serialize + ?Sized>(tag: Tag, value: &T) -> Result<Message, CborError> {
+    Message::new(tag, to_vec(value)?).map_err(|e| CborError::Serialize(e.to_string()))
+}
+
+/// Re-encodes a CBOR item deterministically
+pub fn canonicalize_bytes(bytes: &[u8]) -> Result<Vec<u8>, CborError> {
+    encode(canonicalize(decode(bytes)?)?)
+}
+
+/// Returns whether `bytes` is a single deterministically encoded CBOR item
+pub fn is_canonical(bytes: &[u8]) -> bool {
+    canonicalize_bytes(bytes).is_ok_and(|canonical| canonical == bytes)
+}
+
+fn decode(bytes: &[u8]) -> Result<Value, CborError> {
+    let mut reader = bytes;
+    let value: Value =
+        ciborium::from_reader(&mut reader).map_err(|e| CborError::Deserialize(e.to_string()))?;
+
+    if !reader.is_empty() {
+        return Err(CborError::TrailingBytes);
+    }
+
+    Ok(value)
+}
+
+fn encode(value: Value) -> Result<Vec<u8>, CborError> {
+    let mut bytes = Vec::new();
+    ciborium::into_writer(&value, &mut bytes).map_err(|e| CborError::Serialize(e.to_string()))?;
+    Ok(bytes)
+}
+
+/// Sorts map entries by their encoded keys, recursively
+fn canonicalize(value: Value) -> Result<Value, CborError> {
+    Ok(match value {
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(canonicalize)
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Tag(tag, value) => Value::Tag(tag, Box::new(canonicalize(*value)?)),
+        Value::Map(entries) => {
+            let mut entries = entries
+                .into_iter()
+                .map(|(key, value)| {
+                    let key = canonicalize(key)?;
+                    Ok((encode(key.clone())?, key, canonicalize(value)?))
+                })
+                .collect::<Result<Vec<_>, CborError>>()?;
+
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            if entries.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+                return Err(CborError::DuplicateKey);
+            }
+
+            Value::Map(
+                entries
+                    .into_iter()
+                    .map(|(_, key, value)| (key, value))
+                    .collect(),
+            )
+        }
+        value => value,
+    })
+}
+
+impl fmt::Display for CborError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CborError::Serialize(e) => write!(f, "CBOR serialization error: {e}"),
+            CborError::Deserialize(e) => write!(f, "CBOR deserialization error: {e}"),
+            CborError::DuplicateKey => write!(f, "Duplicate CBOR map key"),
+            CborError::TrailingBytes => write!(f, "Trailing bytes after CBOR item"),
+        }
+    }
+}
+
+impl std::error::Error for CborError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::hex::{DisplayHex, FromHex};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_canonical_map_order() {
+        // Shorter keys sort first, then bytewise
+        let map = HashMap::from([
+            ("bb".to_string(), 1),
+            ("a".to_string(), 2),
+            ("aa".to_string(), 3),
+            ("b".to_string(), 4),
+        ]);
+        let bytes = to_vec(&map).unwrap();
+        assert_eq!(
+            bytes.to_lower_hex_string(),
+            "a46161026162046261610362626201"
+        );
+        assert!(is_canonical(&bytes));
+        assert_eq!(from_slice(&bytes), Ok(map));
+
+        // Integer keys sort before text keys, and 10 before -1
+        let value = Value::Map(vec![
+            ("z".into(), 0.into()),
+            ((-1).into(), 0.into()),
+            (10.into(), 0.into()),
+        ]);
+        assert_eq!(
+            to_vec(&value).unwrap().to_lower_hex_string(),
+            "a30a002000617a00"
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_bytes() {
+        // {"b": 1, "a": [1.5]} with a non-minimal integer and float
+        let bytes = Vec::from_hex("a261621801616181fb3ff8000000000000").unwrap();
+        assert!(!is_canonical(&bytes));
+
+        let canonical = canonicalize_bytes(&bytes).unwrap();
+        assert_eq!(canonical.to_lower_hex_string(), "a2616181f93e00616201");
+        assert!(is_canonical(&canonical));
+
+        let message = to_message(5, &Value::Map(vec![("b".into(), 1.into())])).unwrap();
+        assert_eq!(message.body, Vec::from_hex("a1616201").unwrap());
+    }
+
+    #[test]
+    fn test_invalid() {
+        // {"a": 1, "a": 2}
+        let bytes = Vec::from_hex("a2616101616102").unwrap();
+        assert_eq!(canonicalize_bytes(&bytes), Err(CborError::DuplicateKey));
+        assert!(!is_canonical(&bytes));
+
+        assert_eq!(
+            from_slice::<u8>(&[0x01, 0x02]),
+            Err(CborError::TrailingBytes)
+        );
+        assert!(matches!(
+            from_slice::<u8>(&[0x61, 0x61]),
+            Err(CborError::Deserialize(_))
+        ));
+        assert!(matches!(
+            from_slice::<u8>(&[0x18]),
+            Err(CborError::Deserialize(_))
+        ));
+    }
+}
@@ -0,0 +1,279 @@
+//! # Key and Signature Commitments
+//!
+//! Commitments to data that leave no extra bytes on chain. Pay-to-contract tweaks a public key
+//! so that it commits to the data; using the tweaked key as a taproot internal key commits the
+//! output to it. Sign-to-contract tweaks a BIP-340 signature nonce so that the signature
+//! commits to the data. Either commitment can be verified from the data and the untweaked key
+//! or nonce point.
+
+use crate::message::tagged_hash;
+
+use bitcoin::key::{Keypair, Parity, TapTweak, XOnlyPublicKey};
+use bitcoin::secp256k1::{
+    self, PublicKey, Scalar, Secp256k1, SecretKey, Signing, Verification, schnorr,
+};
+use std::fmt;
+
+/// The tag used to derive pay-to-contract tweaks
+pub const PAY_TO_CONTRACT_TAG: &str = "bitcoin-embed/pay-to-contract";
+
+/// The tag used to derive sign-to-contract nonce tweaks
+pub const SIGN_TO_CONTRACT_TAG: &str = "bitcoin-embed/sign-to-contract";
+
+/// The tag used to derive deterministic sign-to-contract nonces
+const NONCE_TAG: &str = "bitcoin-embed/sign-to-contract/nonce";
+
+/// The BIP-340 challenge tag
+const CHALLENGE_TAG: &str = "BIP0340/challenge";
+
+/// Error types for creating commitments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitmentError {
+    /// A derived tweak or nonce is not a valid scalar. This happens with negligible
+    /// probability.
+    InvalidTweak,
+}
+
+/// Returns the pay-to-contract tweak committing `base` to `data`
+pub fn pay_to_contract_tweak(
+    base: &XOnlyPublicKey,
+    data: &[u8],
+) -> Result<Scalar, CommitmentError> {
+    let hash = tagged_hash(PAY_TO_CONTRACT_TAG, &[&base.serialize()[..], data].concat());
+    Scalar::from_be_bytes(hash).map_err(|_| CommitmentError::InvalidTweak)
+}
+
+/// Returns `base + H(base || data)·G`, a key committing to `data`, and its parity
+pub fn pay_to_contract<C: Verification>(
+    secp: &Secp256k1<C>,
+    base: &XOnlyPublicKey,
+    data: &[u8],
+) -> Result<(XOnlyPublicKey, Parity), CommitmentError> {
+    let tweak = pay_to_contract_tweak(base, data)?;
+    base.add_tweak(secp, &tweak)
+        .map_err(|_| CommitmentError::InvalidTweak)
+}
+
+/// Returns the keypair for a pay-to-contract key, for signing with the committed key
+pub fn pay_to_contract_keypair<C: Verification>(
+    secp: &Secp256k1<C>,
+    base: &Keypair,
+    data: &[u8],
+) -> Result<Keypair, CommitmentError> {
+    let tweak = pay_to_contract_tweak(&base.x_only_public_key().0, data)?;
+    base.add_xonly_tweak(secp, &tweak)
+        .map_err(|_| CommitmentError::InvalidTweak)
+}
+
+/// Returns whether `committed` is the pay-to-contract key for `base` and `data`
+pub fn verify_pay_to_contract<C: Verification>(
+    secp: &Secp256k1<C>,
+    base: &XOnlyPublicKey,
+    data: &[u8],
+    committed: &XOnlyPublicKey,
+) -> bool {
+    pay_to_contract(secp, base, data).is_ok_and(|(key, _)| key == *committed)
+}
+
+/// Returns whether a key-path-only taproot output key commits to `data` through its internal
+/// key `base`
+pub fn verify_taproot_commitment<C: Verification>(
+    secp: &Secp256k1<C>,
+    base: &XOnlyPublicKey,
+    data: &[u8],
+    output_key: &XOnlyPublicKey,
+) -> bool {
+    pay_to_contract(secp, base, data).is_ok_and(|(internal_key, _)| {
+        internal_key.tap_tweak(secp, None).0.to_x_only_public_key() == *output_key
+    })
+}
+
+/// Creates a BIP-340 signature whose nonce commits to `data`, returning the signature and the
+/// untweaked nonce point needed to verify the commitment. The nonce is derived
+/// deterministically from the secret key, message, and data.
+pub fn sign_to_contract<C: Signing + Verification>(
+    secp: &Secp256k1<C>,
+    msg: &secp256k1::Message,
+    keypair: &Keypair,
+    data: &[u8],
+) -> Result<(schnorr::Signature, PublicKey), CommitmentError> {
+    let (pubkey, parity) = keypair.x_only_public_key();
+    let mut seckey = keypair.secret_key();
+    if parity == Parity::Odd {
+        seckey = seckey.negate();
+    }
+
+    let nonce = tagged_hash(
+        NONCE_TAG,
+        &[
+            &seckey.secret_bytes()[..],
+            &pubkey.serialize(),
+            msg.as_ref(),
+            data,
+        ]
+        .concat(),
+    );
+    let nonce = SecretKey::from_slice(&nonce).map_err(|_| CommitmentError::InvalidTweak)?;
+    let nonce_point = nonce.public_key(secp);
+
+    let tweak = sign_to_contract_tweak(&nonce_point, data)?;
+    let mut nonce = nonce
+        .add_tweak(&tweak)
+        .map_err(|_| CommitmentError::InvalidTweak)?;
+    let (r, parity) = nonce.x_only_public_key(secp);
+    if parity == Parity::Odd {
+        nonce = nonce.negate();
+    }
+
+    let challenge = tagged_hash(
+        CHALLENGE_TAG,
+        &[&r.serialize()[..], &pubkey.serialize(), msg.as_ref()].concat(),
+    );
+    let challenge = Scalar::from_be_bytes(challenge).map_err(|_| CommitmentError::InvalidTweak)?;
+
+    let s = seckey
+        .mul_tweak(&challenge)
+        .and_then(|ex| ex.add_tweak(&Scalar::from(nonce)))
+        .map_err(|_| CommitmentError::InvalidTweak)?;
+
+    let mut signature = [0; 64];
+    signature[..32].copy_from_slice(&r.serialize());
+    signature[32..].copy_from_slice(&s.secret_bytes());
+    let signature =
+        schnorr::Signature::from_slice(&signature).map_err(|_| CommitmentError::InvalidTweak)?;
+
+    Ok((signature, nonce_point))
+}
+
+/// Returns whether a signature is valid for `msg` and `pubkey` and its nonce commits to
+/// `data` through `nonce_point`
+pub fn verify_sign_to_contract<C: Verification>(
+    secp: &Secp256k1<C>,
+    signature: &schnorr::Signature,
+    msg: &secp256k1::Message,
+    pubkey: &XOnlyPublicKey,
+    nonce_point: &PublicKey,
+    data: &[u8],
+) -> bool {
+    if secp.verify_schnorr(signature, msg, pubkey).is_err() {
+        return false;
+    }
+
+    let Ok(tweak) = sign_to_contract_tweak(nonce_point, data) else {
+        return false;
+    };
+
+    nonce_point
+        .add_exp_tweak(secp, &tweak)
+        .is_ok_and(|r| r.x_only_public_key().0.serialize()[..] == signature.as_ref()[..32])
+}
+
+fn sign_to_contract_tweak(nonce_point: &PublicKey, data: &[u8]) -> Result<Scalar, CommitmentError> {
+    let hash = tagged_hash(
+        SIGN_TO_CONTRACT_TAG,
+        &[&nonce_point.serialize()[..], data].concat(),
+    );
+    Scalar::from_be_bytes(hash).map_err(|_| CommitmentError::InvalidTweak)
+}
+
+impl fmt::Display for CommitmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommitmentError::InvalidTweak => write!(f, "Commitment tweak is not a valid scalar"),
+        }
+    }
+}
+
+impl std::error::Error for CommitmentError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::hashes::{Hash, sha256};
+
+    fn keypair(secp: &Secp256k1<secp256k1::All>, byte: u8) -> Keypair {
+        Keypair::from_seckey_slice(secp, &[byte; 32]).unwrap()
+    }
+
+    #[test]
+    fn test_pay_to_contract() {
+        let secp = Secp256k1::new();
+
+        // Exercise both parities of the base key
+        for byte in [1, 2, 3, 4] {
+            let base = keypair(&secp, byte);
+            let (base_key, _) = base.x_only_public_key();
+
+            let (committed, parity) = pay_to_contract(&secp, &base_key, b"contract").unwrap();
+            assert!(verify_pay_to_contract(
+                &secp,
+                &base_key,
+                b"contract",
+                &committed
+            ));
+            assert!(!verify_pay_to_contract(
+                &secp, &base_key, b"other", &committed
+            ));
+
+            let tweaked = pay_to_contract_keypair(&secp, &base, b"contract").unwrap();
+            assert_eq!(tweaked.x_only_public_key(), (committed, parity));
+
+            let output_key = committed.tap_tweak(&secp, None).0.to_x_only_public_key();
+            assert!(verify_taproot_commitment(
+                &secp,
+                &base_key,
+                b"contract",
+                &output_key
+            ));
+            assert!(!verify_taproot_commitment(
+                &secp,
+                &base_key,
+                b"contract",
+                &committed
+            ));
+        }
+    }
+
+    #[test]
+    fn test_sign_to_contract() {
+        let secp = Secp256k1::new();
+        let msg = secp256k1::Message::from_digest(sha256::Hash::hash(b"msg").to_byte_array());
+
+        for byte in [1, 2, 3, 4] {
+            let keypair = keypair(&secp, byte);
+            let (pubkey, _) = keypair.x_only_public_key();
+
+            let (signature, nonce_point) =
+                sign_to_contract(&secp, &msg, &keypair, b"contract").unwrap();
+
+            assert!(secp.verify_schnorr(&signature, &msg, &pubkey).is_ok());
+            assert!(verify_sign_to_contract(
+                &secp,
+                &signature,
+                &msg,
+                &pubkey,
+                &nonce_point,
+                b"contract"
+            ));
+            assert!(!verify_sign_to_contract(
+                &secp,
+                &signature,
+                &msg,
+                &pubkey,
+                &nonce_point,
+                b"other"
+            ));
+
+            let other = secp256k1::Message::from_digest([0; 32]);
+            assert!(!verify_sign_to_contract(
+                &secp,
+                &signature,
+                &other,
+                &pubkey,
+                &nonce_point,
+                b"contract"
+            ));
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! # Compression
+//!
+//! Compresses payloads behind a one-byte codec id, so readers can decompress without knowing
+//! in advance how a payload was written. Each codec other than [`Codec::None`] is behind a
+//! feature of the same name (`flate2`, `brotli`, `zstd`); ids are fixed regardless of which
+//! features are enabled.
+
+use std::fmt;
+#[cfg(any(feature = "flate2", feature = "brotli", feature = "zstd"))]
+use std::io::{self, Read};
+
+/// The default limit on the size of an unwrapped payload, to guard against compression bombs
+pub const MAX_UNWRAPPED_SIZE: usize = 4_000_000;
+
+/// A compression codec
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Codec {
+    /// Stored uncompressed
+    None,
+    /// Raw DEFLATE (`flate2` feature)
+    Deflate,
+    /// Brotli (`brotli` feature)
+    Brotli,
+    /// Zstandard (`zstd` feature)
+    Zstd,
+}
+
+/// Error types for wrapping and unwrapping payloads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressError {
+    /// The payload is missing its codec id
+    Empty,
+    /// The codec id is not assigned
+    UnknownCodec(u8),
+    /// The codec's feature is not enabled
+    Unsupported(Codec),
+    /// The unwrapped payload exceeds the size limit
+    TooLarge,
+    /// The compressed data is malformed
+    Corrupt,
+}
+
+impl Codec {
+    /// Every codec, in id order
+    pub const ALL: [Codec; 4] = [Codec::None, Codec::Deflate, Codec::Brotli, Codec::Zstd];
+
+    /// Returns the codec id
+    pub fn id(&self) -> u8 {
+        match self {
+            Codec::None => 0,
+            Codec::Deflate => 1,
+            Codec::Brotli => 2,
+            Codec::Zstd => 3,
+        }
+    }
+
+    /// Returns the codec with the given id
+    pub fn from_id(id: u8) -> Result<Self, CompressError> {
+        Self::ALL
+            .into_iter()
+            .find(|codec| codec.id() == id)
+            .ok_or(CompressError::UnknownCodec(id))
+    }
+
+    /// Returns whether the codec's feature is enabled
+    pub fn is_enabled(&self) -> bool {
+        match self {
+            Codec::None => true,
+            Codec::Deflate => cfg!(feature = "flate2"),
+            Codec::Brotli => cfg!(feature = "brotli"),
+            Codec::Zstd => cfg!(feature = "zstd"),
+        }
+    }
+}
+
+/// Returns the codec id followed by `bytes` compressed with `codec`
+pub fn wrap(bytes: &[u8], codec: Codec) -> Result<Vec<u8>, CompressError> {
+    let mut wrapped = vec![codec.id()];
+
+    match codec {
+        Codec::None => wrapped.extend_from_slice(bytes),
+        #[cfg(feature = "flate2")]
+        Codec::Deflate => {
+            use std::io::Write;
+
+            let mut encoder =
+                flate2::write::DeflateEncoder::new(wrapped, flate2::Compression::best());
+            encoder.write_all(bytes).expect("in-memory write");
+            wrapped = encoder.finish().expect("in-memory write");
+        }
+        #[cfg(feature = "brotli")]
+        Codec::Brotli => {
+            let params = brotli::enc::BrotliEncoderParams::default();
+            brotli::BrotliCompress(&mut &bytes[..], &mut wrapped, &params)
+                .expect("in-memory write");
+        }
+        #[cfg(feature = "zstd")]
+        Codec::Zstd => {
+            wrapped.extend(
+                zstd::stream::encode_all(bytes, zstd::zstd_safe::max_c_level())
+                    .expect("in-memory write"),
+            );
+        }
+        #[cfg(not(feature = "flate2"))]
+        Codec::Deflate => return Err(CompressError::Unsupported(codec)),
+        #[cfg(not(feature = "brotli"))]
+        Codec::Brotli => return Err(CompressError::Unsupported(codec)),
+        #[cfg(not(feature = "zstd"))]
+        Codec::Zstd => return Err(CompressError::Unsupported(codec)),
+    }
+
+    Ok(wrapped)
+}
+
+/// Returns `bytes` wrapped with whichever enabled codec is smallest
+pub fn wrap_smallest(bytes: &[u8]) -> Vec<u8> {
+    Codec::ALL
+        .into_iter()
+        .filter_map(|codec| wrap(bytes, codec).ok())
+        .min_by_key(Vec::len)
+        .expect("uncompressed is always available")
+}
+
+/// Returns the payload of a wrapped payload, up to [`MAX_UNWRAPPED_SIZE`] bytes
+pub fn unwrap(bytes: &[u8]) -> Result<Vec<u8>, CompressError> {
+    unwrap_with_limit(bytes, MAX_UNWRAPPED_SIZE)
+}
+
+/// Returns the payload of a wrapped payload, up to `limit` bytes
+pub fn unwrap_with_limit(bytes: &[u8], limit: usize) -> Result<Vec<u8>, CompressError> {
+    let (&id, data) = bytes.split_first().ok_or(CompressError::Empty)?;
+
+    let codec = Codec::from_id(id)?;
+    match codec {
+        Codec::None if data.len() > limit => Err(CompressError::TooLarge),
+        Codec::None => Ok(data.to_vec()),
+        #[cfg(feature = "flate2")]
+        Codec::Deflate => read_limited(flate2::read::DeflateDecoder::new(data), limit),
+        #[cfg(feature = "brotli")]
+        Codec::Brotli => read_limited(brotli::Decompressor::new(data, 4096), limit),
+        #[cfg(feature = "zstd")]
+        Codec::Zstd => read_limited(
+            zstd::stream::read::Decoder::new(data).map_err(|_| CompressError::Corrupt)?,
+            limit,
+        ),
+        #[cfg(not(feature = "flate2"))]
+        Codec::Deflate => Err(CompressError::Unsupported(codec)),
+        #[cfg(not(feature = "brotli"))]
+        Codec::Brotli => Err(CompressError::Unsupported(codec)),
+        #[cfg(not(feature = "zstd"))]
+        Codec::Zstd => Err(CompressError::Unsupported(codec)),
+    }
+}
+
+#[cfg(any(feature = "flate2", feature = "brotli", feature = "zstd"))]
+fn read_limited<R: Read>(reader: R, limit: usize) -> Result<Vec<u8>, CompressError> {
+    let mut bytes = Vec::new();
+    reader
+        .take(limit as u64 + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|_: io::Error| CompressError::Corrupt)?;
+
+    if bytes.len() > limit {
+        return Err(CompressError::TooLarge);
+    }
+
+    Ok(bytes)
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Codec::None => write!(f, "none"),
+            Codec::Deflate => write!(f, "deflate"),
+            Codec::Brotli => write!(f, "brotli"),
+            Codec::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+impl fmt::Display for CompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompressError::Empty => write!(f, "Missing codec id"),
+            CompressError::UnknownCodec(id) => write!(f, "Unknown codec id: {id}"),
+            CompressError::Unsupported(codec) => write!(f, "Codec not enabled: {codec}"),
+            CompressError::TooLarge => write!(f, "Unwrapped payload exceeds size limit"),
+            CompressError::Corrupt => write!(f, "Corrupt compressed data"),
+        }
+    }
+}
+
+impl std::error::Error for CompressError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let text = b"bitcoin embed ".repeat(100);
+
+        for codec in Codec::ALL {
+            assert_eq!(Codec::from_id(codec.id()), Ok(codec));
+
+            if !codec.is_enabled() {
+                assert_eq!(wrap(&text, codec), Err(CompressError::Unsupported(codec)));
+                continue;
+            }
+
+            let wrapped = wrap(&text, codec).unwrap();
+            assert_eq!(wrapped[0], codec.id());
+            assert_eq!(unwrap(&wrapped), Ok(text.clone()));
+            assert_eq!(
+                unwrap_with_limit(&wrapped, text.len() - 1),
+                Err(CompressError::TooLarge)
+            );
+
+            if codec != Codec::None {
+                assert!(wrapped.len() < text.len());
+            }
+        }
+
+        let smallest = wrap_smallest(&text);
+        assert_eq!(unwrap(&smallest), Ok(text));
+
+        // Incompressible data is stored
+        assert_eq!(wrap_smallest(&[1, 2, 3]), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(unwrap(&[]), Err(CompressError::Empty));
+        assert_eq!(unwrap(&[4, 1]), Err(CompressError::UnknownCodec(4)));
+
+        for codec in Codec::ALL {
+            if codec != Codec::None && codec.is_enabled() {
+                assert_eq!(
+                    unwrap(&[codec.id(), 0xff, 0xff, 0xff]),
+                    Err(CompressError::Corrupt)
+                );
+            }
+        }
+    }
+}
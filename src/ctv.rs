@@ -0,0 +1,158 @@
+//! # Template Commitments
+//!
+//! Binds an embedding to a planned follow-up transaction with a BIP-119
+//! (`OP_CHECKTEMPLATEVERIFY`) default template hash, carried in a [`tags::TEMPLATE_HASH`]
+//! message. The hash commits to the version, lock time, script sigs, input count, sequences,
+//! outputs, and the index of the spending input, but not to the outpoints being spent, so it
+//! can be computed before the transaction's inputs are funded.
+
+use crate::message::{Message, tags};
+
+use bitcoin::Transaction;
+use bitcoin::consensus::Encodable;
+use bitcoin::hashes::{Hash, sha256};
+
+/// Returns the BIP-119 default template hash of `tx` for the input at `input_index`
+pub fn template_hash(tx: &Transaction, input_index: u32) -> [u8; 32] {
+    let mut preimage = Vec::new();
+    tx.version
+        .consensus_encode(&mut preimage)
+        .expect("in-memory write");
+    tx.lock_time
+        .consensus_encode(&mut preimage)
+        .expect("in-memory write");
+
+    if tx.input.iter().any(|input| !input.script_sig.is_empty()) {
+        let mut script_sigs = Vec::new();
+        for input in &tx.input {
+            input
+                .script_sig
+                .consensus_encode(&mut script_sigs)
+                .expect("in-memory write");
+        }
+        preimage.extend(sha256::Hash::hash(&script_sigs).to_byte_array());
+    }
+
+    let mut sequences = Vec::new();
+    for input in &tx.input {
+        input
+            .sequence
+            .consensus_encode(&mut sequences)
+            .expect("in-memory write");
+    }
+
+    let mut outputs = Vec::new();
+    for output in &tx.output {
+        output
+            .consensus_encode(&mut outputs)
+            .expect("in-memory write");
+    }
+
+    preimage.extend((tx.input.len() as u32).to_le_bytes());
+    preimage.extend(sha256::Hash::hash(&sequences).to_byte_array());
+    preimage.extend((tx.output.len() as u32).to_le_bytes());
+    preimage.extend(sha256::Hash::hash(&outputs).to_byte_array());
+    preimage.extend(input_index.to_le_bytes());
+
+    sha256::Hash::hash(&preimage).to_byte_array()
+}
+
+/// Returns a message committing to `tx` as spent by the input at `input_index`
+pub fn template_message(tx: &Transaction, input_index: u32) -> Message {
+    Message::new(tags::TEMPLATE_HASH, template_hash(tx, input_index).to_vec()).expect("valid tag")
+}
+
+/// Returns the hash in the first well-formed template message
+pub fn committed_template(messages: &[Message]) -> Option<[u8; 32]> {
+    messages
+        .iter()
+        .filter(|message| message.tag == tags::TEMPLATE_HASH)
+        .find_map(|message| message.body.as_slice().try_into().ok())
+}
+
+/// Returns the index of the first input at which `tx` matches the committed template hash
+pub fn verify_template(tx: &Transaction, hash: &[u8; 32]) -> Option<u32> {
+    (0..tx.input.len() as u32).find(|index| template_hash(tx, *index) == *hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::{
+        Amount, OutPoint, ScriptBuf, Sequence, TxIn, TxOut, Txid, Witness, absolute::LockTime,
+        transaction::Version,
+    };
+
+    fn transaction() -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![
+                TxIn {
+                    previous_output: OutPoint::null(),
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                };
+                2
+            ],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: ScriptBuf::new_op_return([1; 8]),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_template_hash() {
+        let tx = transaction();
+
+        // Computed field by field as specified in BIP-119
+        let mut preimage = Vec::new();
+        preimage.extend(2i32.to_le_bytes());
+        preimage.extend(0u32.to_le_bytes());
+        preimage.extend(2u32.to_le_bytes());
+        preimage.extend(sha256::Hash::hash(&[0xff; 8]).to_byte_array());
+        preimage.extend(1u32.to_le_bytes());
+        preimage.extend(
+            sha256::Hash::hash(&bitcoin::consensus::serialize(&tx.output[0])).to_byte_array(),
+        );
+        preimage.extend(1u32.to_le_bytes());
+        assert_eq!(
+            template_hash(&tx, 1),
+            sha256::Hash::hash(&preimage).to_byte_array()
+        );
+        assert_ne!(template_hash(&tx, 0), template_hash(&tx, 1));
+
+        // Outpoints and witnesses are not committed; script sigs and outputs are
+        let mut other = tx.clone();
+        other.input[0].previous_output = OutPoint::new(Txid::from_byte_array([1; 32]), 1);
+        other.input[0].witness = Witness::from_slice(&[[1]]);
+        assert_eq!(template_hash(&other, 0), template_hash(&tx, 0));
+
+        other.input[1].script_sig = ScriptBuf::from_bytes(vec![0x51]);
+        assert_ne!(template_hash(&other, 0), template_hash(&tx, 0));
+
+        let mut other = tx.clone();
+        other.output[0].value = Amount::from_sat(999);
+        assert_ne!(template_hash(&other, 0), template_hash(&tx, 0));
+    }
+
+    #[test]
+    fn test_template_message() {
+        let tx = transaction();
+        let messages = vec![
+            Message::new(tags::TEMPLATE_HASH, vec![0; 31]).unwrap(),
+            template_message(&tx, 1),
+        ];
+
+        let hash = committed_template(&messages).unwrap();
+        assert_eq!(verify_template(&tx, &hash), Some(1));
+
+        let mut other = tx.clone();
+        other.lock_time = LockTime::from_height(100).unwrap();
+        assert_eq!(verify_template(&other, &hash), None);
+        assert_eq!(committed_template(&messages[..1]), None);
+    }
+}
@@ -0,0 +1,474 @@
+//! # Commit Output Descriptors
+//!
+//! Describes the taproot output that commits to one or more envelope leaves. Envelope leaves are
+//! not expressible in miniscript, so a `tr(KEY,TREE)` descriptor cannot describe them; instead the
+//! output is described by its output key with `rawtr(...)` or by its address with `addr(...)`, and
+//! the leaf scripts and control blocks needed for the reveal are kept alongside. Descriptors carry
+//! a BIP-380 checksum.
+//!
+//! To add a data leaf to an output with real spending conditions, [`insert_envelope_leaf`]
+//! places an envelope leaf in an existing [`TaprootBuilder`] and finalizes the tree.
+//!
+//! When the reveal is seen on chain, [`verify_tapscript_commitment`] checks that its control
+//! block really commits the envelope leaf to the spent output, distinguishing valid reveals
+//! from witnesses that merely parse.
+
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::secp256k1::{Secp256k1, Verification};
+use bitcoin::taproot::{
+    ControlBlock, LeafVersion, NodeInfo, TapNodeHash, TapTree, TaprootBuilder, TaprootSpendInfo,
+};
+use bitcoin::{Address, KnownHrp, Script, ScriptBuf, Witness};
+use std::fmt;
+
+/// The characters allowed in a descriptor, in checksum order
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+
+/// The characters used to encode a checksum
+const CHECKSUM_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// A taproot output committing to envelope leaves
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitOutput {
+    spend_info: TaprootSpendInfo,
+    leaves: Vec<ScriptBuf>,
+}
+
+/// Error types for building commit outputs and descriptors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptorError {
+    /// The commit output has no leaves
+    NoLeaves,
+    /// The descriptor has a character outside the descriptor character set
+    InvalidCharacter(char),
+    /// The envelope leaf cannot be placed at this depth of the tree
+    InvalidDepth(u8),
+    /// The tree still has empty branches after the envelope leaf is placed
+    IncompleteTree,
+}
+
+impl CommitOutput {
+    /// Returns the output committing to `leaves` under `internal_key`, with the leaves in a
+    /// balanced tree
+    pub fn new<C: Verification>(
+        secp: &Secp256k1<C>,
+        internal_key: XOnlyPublicKey,
+        leaves: Vec<ScriptBuf>,
+    ) -> Result<Self, DescriptorError> {
+        let spend_info = TaprootSpendInfo::with_huffman_tree(
+            secp,
+            internal_key,
+            leaves.iter().map(|leaf| (1, leaf.clone())),
+        )
+        .map_err(|_| DescriptorError::NoLeaves)?;
+
+        Ok(Self { spend_info, leaves })
+    }
+
+    /// Returns the internal key
+    pub fn internal_key(&self) -> XOnlyPublicKey {
+        self.spend_info.internal_key()
+    }
+
+    /// Returns the tweaked output key
+    pub fn output_key(&self) -> XOnlyPublicKey {
+        self.spend_info.output_key().to_x_only_public_key()
+    }
+
+    /// Returns the leaf scripts
+    pub fn leaves(&self) -> &[ScriptBuf] {
+        &self.leaves
+    }
+
+    /// Returns the merkle root of the leaves
+    pub fn merkle_root(&self) -> Option<TapNodeHash> {
+        self.spend_info.merkle_root()
+    }
+
+    /// Returns the tree of leaves, as carried in a PSBT output's `PSBT_OUT_TAP_TREE` field
+    pub fn tap_tree(&self) -> TapTree {
+        let builder =
+            TaprootBuilder::with_huffman_tree(self.leaves.iter().map(|leaf| (1, leaf.clone())))
+                .expect("leaves form a tree");
+        TapTree::try_from(builder).expect("complete tree")
+    }
+
+    /// Returns the commit output's script pubkey
+    pub fn script_pubkey(&self) -> ScriptBuf {
+        ScriptBuf::new_p2tr_tweaked(self.spend_info.output_key())
+    }
+
+    /// Returns the commit address
+    pub fn address(&self, hrp: impl Into<KnownHrp>) -> Address {
+        Address::p2tr_tweaked(self.spend_info.output_key(), hrp)
+    }
+
+    /// Returns the control block for revealing `leaf`, if it is one of the leaves
+    pub fn control_block(&self, leaf: &Script) -> Option<ControlBlock> {
+        self.spend_info
+            .control_block(&(leaf.to_owned(), LeafVersion::TapScript))
+    }
+
+    /// Returns a `rawtr(...)` descriptor for the output key, with checksum
+    pub fn descriptor(&self) -> String {
+        with_checksum(&format!("rawtr({})", self.output_key())).expect("valid characters")
+    }
+
+    /// Returns an `addr(...)` descriptor for the commit address, with checksum
+    pub fn addr_descriptor(&self, hrp: impl Into<KnownHrp>) -> String {
+        with_checksum(&format!("addr({})", self.address(hrp))).expect("valid characters")
+    }
+}
+
+/// Places an envelope leaf in a tree of spending leaves and finalizes it under
+/// `internal_key`. If `builder` is incomplete, the leaf is added at `depth` as the next leaf
+/// in depth-first order, as by [`TaprootBuilder::add_leaf`], and must complete the tree. If
+/// `builder` is complete, its tree and the leaf become the two branches of a new root, so
+/// `depth` must be 1. An empty builder with `depth` 0 gives a key-path output with only the
+/// envelope leaf.
+pub fn insert_envelope_leaf<C: Verification>(
+    secp: &Secp256k1<C>,
+    builder: TaprootBuilder,
+    depth: u8,
+    leaf: ScriptBuf,
+    internal_key: XOnlyPublicKey,
+) -> Result<TaprootSpendInfo, DescriptorError> {
+    if builder.is_finalizable() {
+        if depth != 1 {
+            return Err(DescriptorError::InvalidDepth(depth));
+        }
+        let tree = builder
+            .try_into_node_info()
+            .map_err(|_| DescriptorError::IncompleteTree)?;
+        let root = NodeInfo::combine(
+            tree,
+            NodeInfo::new_leaf_with_ver(leaf, LeafVersion::TapScript),
+        )
+        .map_err(|_| DescriptorError::InvalidDepth(depth))?;
+        return Ok(TaprootSpendInfo::from_node_info(secp, internal_key, root));
+    }
+
+    builder
+        .add_leaf(depth, leaf)
+        .map_err(|_| DescriptorError::InvalidDepth(depth))?
+        .finalize(secp, internal_key)
+        .map_err(|_| DescriptorError::IncompleteTree)
+}
+
+/// Returns whether `witness` is a script-path spend whose control block commits its leaf
+/// script to the P2TR output `prevout_script_pubkey`. Returns false for other outputs and
+/// witnesses.
+pub fn verify_tapscript_commitment(witness: &Witness, prevout_script_pubkey: &Script) -> bool {
+    if !prevout_script_pubkey.is_p2tr() {
+        return false;
+    }
+    let Ok(output_key) = XOnlyPublicKey::from_slice(&prevout_script_pubkey.as_bytes()[2..]) else {
+        return false;
+    };
+    let (Some(leaf), Some(control_block)) = (
+        witness.taproot_leaf_script(),
+        witness.taproot_control_block(),
+    ) else {
+        return false;
+    };
+    let Ok(control_block) = ControlBlock::decode(control_block) else {
+        return false;
+    };
+
+    control_block.leaf_version == leaf.version
+        && control_block.verify_taproot_commitment(
+            &Secp256k1::verification_only(),
+            output_key,
+            leaf.script,
+        )
+}
+
+/// Returns the BIP-380 checksum of a descriptor without its `#` suffix
+pub fn checksum(descriptor: &str) -> Result<String, DescriptorError> {
+    const GENERATOR: [u64; 5] = [
+        0xf5dee51989,
+        0xa9fdca3312,
+        0x1bab10e32d,
+        0x3706b1677a,
+        0x644d626ffd,
+    ];
+
+    let polymod = |c: u64, value: u64| {
+        let top = c >> 35;
+        let mut c = ((c & 0x7ffffffff) << 5) ^ value;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                c ^= generator;
+            }
+        }
+        c
+    };
+
+    let mut c = 1;
+    let mut class = 0;
+    let mut class_count = 0;
+    for ch in descriptor.chars() {
+        let position = INPUT_CHARSET
+            .find(ch)
+            .ok_or(DescriptorError::InvalidCharacter(ch))? as u64;
+        c = polymod(c, position & 31);
+        class = class * 3 + (position >> 5);
+        class_count += 1;
+        if class_count == 3 {
+            c = polymod(c, class);
+            class = 0;
+            class_count = 0;
+        }
+    }
+    if class_count > 0 {
+        c = polymod(c, class);
+    }
+    for _ in 0..8 {
+        c = polymod(c, 0);
+    }
+    c ^= 1;
+
+    Ok((0..8)
+        .map(|i| CHECKSUM_CHARSET[((c >> (5 * (7 - i))) & 31) as usize] as char)
+        .collect())
+}
+
+/// Returns `descriptor` followed by `#` and its checksum
+pub fn with_checksum(descriptor: &str) -> Result<String, DescriptorError> {
+    Ok(format!("{descriptor}#{}", checksum(descriptor)?))
+}
+
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DescriptorError::NoLeaves => write!(f, "Commit output has no leaves"),
+            DescriptorError::InvalidCharacter(ch) => {
+                write!(f, "Invalid descriptor character: {ch:?}")
+            }
+            DescriptorError::InvalidDepth(depth) => {
+                write!(f, "Cannot place the envelope leaf at depth {depth}")
+            }
+            DescriptorError::IncompleteTree => write!(f, "Taproot tree is incomplete"),
+        }
+    }
+}
+
+impl std::error::Error for DescriptorError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::envelope::EnvelopeBuilder;
+
+    use bitcoin::Network;
+    use bitcoin::opcodes::all::OP_CHECKSIG;
+    use bitcoin::script::Builder;
+    use bitcoin::secp256k1::{Keypair, SecretKey};
+
+    #[test]
+    fn test_checksum() {
+        // BIP-380 test vector
+        assert_eq!(checksum("raw(deadbeef)"), Ok("89f8spxm".into()));
+        assert_eq!(
+            with_checksum("raw(deadbeef)"),
+            Ok("raw(deadbeef)#89f8spxm".into())
+        );
+        assert_eq!(
+            checksum("raw(deadbeef)\n"),
+            Err(DescriptorError::InvalidCharacter('\n'))
+        );
+    }
+
+    #[test]
+    fn test_commit_output() {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let (internal_key, _) = keypair.x_only_public_key();
+
+        let leaves = (0..3u8)
+            .map(|i| {
+                let prefix = Builder::new()
+                    .push_x_only_key(&internal_key)
+                    .push_opcode(OP_CHECKSIG);
+                EnvelopeBuilder::new()
+                    .append_to_builder(vec![vec![i; 100]], prefix)
+                    .into_script()
+            })
+            .collect::<Vec<_>>();
+
+        let output = CommitOutput::new(&secp, internal_key, leaves.clone()).unwrap();
+        assert_eq!(output.internal_key(), internal_key);
+        assert_eq!(output.leaves(), &leaves[..]);
+        assert_eq!(
+            output.address(Network::Bitcoin).script_pubkey(),
+            output.script_pubkey()
+        );
+
+        for leaf in &leaves {
+            let control_block = output.control_block(leaf).unwrap();
+            assert!(control_block.verify_taproot_commitment(&secp, output.output_key(), leaf));
+        }
+        assert_eq!(output.control_block(Script::new()), None);
+
+        let tap_tree = output.tap_tree();
+        assert_eq!(Some(tap_tree.root_hash()), output.merkle_root());
+        assert_eq!(tap_tree.script_leaves().count(), 3);
+
+        let descriptor = output.descriptor();
+        let (body, sum) = descriptor.split_once('#').unwrap();
+        assert_eq!(body, format!("rawtr({})", output.output_key()));
+        assert_eq!(checksum(body), Ok(sum.into()));
+
+        let descriptor = output.addr_descriptor(Network::Bitcoin);
+        assert!(descriptor.starts_with(&format!("addr({})#", output.address(Network::Bitcoin))));
+
+        assert_eq!(
+            CommitOutput::new(&secp, internal_key, vec![]),
+            Err(DescriptorError::NoLeaves)
+        );
+    }
+
+    #[test]
+    fn test_insert_envelope_leaf() {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let (internal_key, _) = keypair.x_only_public_key();
+
+        let spend = |i: u8| {
+            Builder::new()
+                .push_slice([i; 32])
+                .push_opcode(OP_CHECKSIG)
+                .into_script()
+        };
+        let envelope = EnvelopeBuilder::new()
+            .append_to_builder(vec![b"data".to_vec()], Builder::new())
+            .into_script();
+        let verify = |info: &TaprootSpendInfo, leaf: &ScriptBuf| {
+            let control_block = info
+                .control_block(&(leaf.clone(), LeafVersion::TapScript))
+                .unwrap();
+            assert!(control_block.verify_taproot_commitment(
+                &secp,
+                info.output_key().to_x_only_public_key(),
+                leaf
+            ));
+            control_block.merkle_branch.len()
+        };
+
+        // A key-path output plus a data leaf
+        let info = insert_envelope_leaf(
+            &secp,
+            TaprootBuilder::new(),
+            0,
+            envelope.clone(),
+            internal_key,
+        )
+        .unwrap();
+        assert_eq!(verify(&info, &envelope), 0);
+
+        // The envelope fills the open slot of an incomplete tree
+        let builder = TaprootBuilder::new()
+            .add_leaf(1, spend(1))
+            .unwrap()
+            .add_leaf(2, spend(2))
+            .unwrap();
+        let info = insert_envelope_leaf(&secp, builder.clone(), 2, envelope.clone(), internal_key)
+            .unwrap();
+        assert_eq!(verify(&info, &envelope), 2);
+        assert_eq!(verify(&info, &spend(1)), 1);
+        assert_eq!(
+            insert_envelope_leaf(&secp, builder, 3, envelope.clone(), internal_key),
+            Err(DescriptorError::IncompleteTree)
+        );
+
+        // A complete tree becomes a branch beside the envelope
+        let builder = TaprootBuilder::new()
+            .add_leaf(1, spend(1))
+            .unwrap()
+            .add_leaf(1, spend(2))
+            .unwrap();
+        let info = insert_envelope_leaf(&secp, builder.clone(), 1, envelope.clone(), internal_key)
+            .unwrap();
+        assert_eq!(verify(&info, &envelope), 1);
+        assert_eq!(verify(&info, &spend(2)), 2);
+        assert_eq!(
+            insert_envelope_leaf(&secp, builder, 2, envelope, internal_key),
+            Err(DescriptorError::InvalidDepth(2))
+        );
+    }
+
+    #[test]
+    fn test_verify_tapscript_commitment() {
+        use crate::{Embedding, EmbeddingType, ScriptType};
+        use bitcoin::hashes::Hash;
+        use bitcoin::{
+            Amount, OutPoint, Sequence, Transaction, TxIn, TxOut, Txid, absolute::LockTime,
+            transaction::Version,
+        };
+
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let (internal_key, _) = keypair.x_only_public_key();
+        let leaves = (0..2u8)
+            .map(|i| {
+                EnvelopeBuilder::new()
+                    .append_to_builder(vec![vec![i; 10]], Builder::new())
+                    .into_script()
+            })
+            .collect::<Vec<_>>();
+        let output = CommitOutput::new(&secp, internal_key, leaves.clone()).unwrap();
+        let control_block = output.control_block(&leaves[0]).unwrap().serialize();
+
+        let witness = Witness::from_slice(&[leaves[0].to_bytes(), control_block.clone()]);
+        assert!(verify_tapscript_commitment(
+            &witness,
+            &output.script_pubkey()
+        ));
+
+        // The wrong leaf, the wrong output, or a non-taproot output
+        let wrong_leaf = Witness::from_slice(&[leaves[1].to_bytes(), control_block.clone()]);
+        assert!(!verify_tapscript_commitment(
+            &wrong_leaf,
+            &output.script_pubkey()
+        ));
+        let other = CommitOutput::new(&secp, internal_key, vec![leaves[1].clone()]).unwrap();
+        assert!(!verify_tapscript_commitment(
+            &witness,
+            &other.script_pubkey()
+        ));
+        assert!(!verify_tapscript_commitment(&witness, &leaves[0]));
+        assert!(!verify_tapscript_commitment(
+            &Witness::new(),
+            &output.script_pubkey()
+        ));
+
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness,
+            }],
+            output: vec![TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::new_op_return([1]),
+            }],
+        };
+        let prevouts = [TxOut {
+            value: Amount::from_sat(1_000),
+            script_pubkey: output.script_pubkey(),
+        }];
+        let embeddings = Embedding::from_transaction(&tx);
+        let envelope = embeddings
+            .iter()
+            .find(|embedding| {
+                embedding.to_type() == EmbeddingType::WitnessEnvelope(ScriptType::Tapscript)
+            })
+            .unwrap();
+        assert_eq!(envelope.verify_commitment(&tx, &prevouts), Some(true));
+        assert_eq!(envelope.verify_commitment(&tx, &[]), None);
+        assert_eq!(embeddings[0].verify_commitment(&tx, &prevouts), None);
+    }
+}
@@ -0,0 +1,161 @@
+//! # Embedding Diffs
+//!
+//! Compares the embeddings of two versions of a transaction, such as an original and its RBF
+//! replacement. Embeddings are matched by location and bytes first, then by payload alone: a
+//! payload found at another location is relocated, and anything else is removed or added. A payload
+//! changed in place shows up as one removal and one addition at the same location.
+
+use crate::Embedding;
+
+use bitcoin::Transaction;
+
+/// An embedding whose payload moved to another location
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relocation {
+    /// The embedding in the original transaction
+    pub from: Embedding,
+    /// The embedding with the same payload in the replacement
+    pub to: Embedding,
+}
+
+/// The differences between the embeddings of two transactions
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EmbeddingDiff {
+    /// Embeddings only in the replacement
+    pub added: Vec<Embedding>,
+    /// Embeddings only in the original transaction
+    pub removed: Vec<Embedding>,
+    /// Payloads in both transactions at different locations
+    pub relocated: Vec<Relocation>,
+    /// Embeddings with the same location and bytes in both transactions, from the replacement
+    pub unchanged: Vec<Embedding>,
+}
+
+impl EmbeddingDiff {
+    /// Returns whether every embedding is unchanged
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.relocated.is_empty()
+    }
+}
+
+/// Compares the embeddings of `original` and `replacement`, as extracted by
+/// [`Embedding::from_transaction`]. Embeddings appear in each list in extraction order.
+pub fn compare(original: &Transaction, replacement: &Transaction) -> EmbeddingDiff {
+    compare_embeddings(
+        Embedding::from_transaction(original),
+        Embedding::from_transaction(replacement),
+    )
+}
+
+/// Compares two lists of embeddings, as in [`compare`]
+pub fn compare_embeddings(original: Vec<Embedding>, replacement: Vec<Embedding>) -> EmbeddingDiff {
+    let mut diff = EmbeddingDiff::default();
+    let mut removed = original.into_iter().map(Some).collect::<Vec<_>>();
+    let mut added = Vec::new();
+
+    for embedding in replacement {
+        let same = removed.iter_mut().find(|old| {
+            old.as_ref().is_some_and(|old| {
+                old.location == embedding.location && old.bytes == embedding.bytes
+            })
+        });
+        match same {
+            Some(old) => {
+                *old = None;
+                diff.unchanged.push(embedding);
+            }
+            None => added.push(embedding),
+        }
+    }
+
+    for embedding in added {
+        let payload = embedding.payload();
+        let moved = removed
+            .iter_mut()
+            .find(|old| old.as_ref().is_some_and(|old| old.payload() == payload));
+        match moved.and_then(Option::take) {
+            Some(from) => diff.relocated.push(Relocation {
+                from,
+                to: embedding,
+            }),
+            None => diff.added.push(embedding),
+        }
+    }
+
+    diff.removed = removed.into_iter().flatten().collect();
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EmbeddingLocation;
+    use crate::testing::FixtureBuilder;
+
+    fn transaction(payloads: &[&[u8]], annex: Option<&[u8]>) -> Transaction {
+        let mut builder = FixtureBuilder::new(1);
+        if let Some(data) = annex {
+            builder = builder.annex(data);
+        }
+        payloads
+            .iter()
+            .fold(builder, |builder, payload| builder.op_return(payload))
+            .build()
+            .tx
+    }
+
+    #[test]
+    fn test_compare() {
+        let original = transaction(&[b"keep", b"move", b"drop"], None);
+        let replacement = transaction(&[b"keep", b"new"], Some(b"move"));
+        let diff = compare(&original, &replacement);
+
+        assert!(!diff.is_empty());
+        assert_eq!(diff.unchanged.len(), 1);
+        assert_eq!(diff.unchanged[0].payload().as_ref(), b"keep");
+
+        assert_eq!(diff.relocated.len(), 1);
+        let relocation = &diff.relocated[0];
+        assert_eq!(
+            relocation.from.location,
+            EmbeddingLocation::OpReturn { output: 1 }
+        );
+        assert_eq!(
+            relocation.to.location,
+            EmbeddingLocation::TaprootAnnex {
+                input: 0,
+                index: 0,
+                tag: 0
+            }
+        );
+
+        // "new" replaced "move" in output 1, and "drop" is gone
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].payload().as_ref(), b"new");
+        assert_eq!(
+            diff.added[0].location,
+            EmbeddingLocation::OpReturn { output: 1 }
+        );
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].payload().as_ref(), b"drop");
+    }
+
+    #[test]
+    fn test_identical_and_duplicate_payloads() {
+        let original = transaction(&[b"a", b"a"], Some(b"b"));
+        let diff = compare(&original, &original);
+        assert!(diff.is_empty());
+        assert_eq!(diff.unchanged.len(), 3);
+
+        // Only one of the duplicate payloads is kept
+        let replacement = transaction(&[b"a"], Some(b"b"));
+        let diff = compare(&original, &replacement);
+        assert_eq!(diff.unchanged.len(), 2);
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(
+            diff.removed[0].location,
+            EmbeddingLocation::OpReturn { output: 1 }
+        );
+        assert!(diff.added.is_empty() && diff.relocated.is_empty());
+    }
+}
@@ -0,0 +1,315 @@
+//! # Electrum Backend
+//!
+//! A [`Resolver`] backed by an Electrum server. Electrum servers index transactions by script
+//! rather than by block, so this backend is suited to extracting embeddings from a wallet's own
+//! history. Full blocks are not available.
+//!
+//! [`ElectrumResolver::new`] connects over plaintext TCP. For TLS, enable a TLS feature on
+//! `electrum-client` and pass its `Client` to [`ElectrumResolver::with_client`].
+
+use crate::Embedding;
+use crate::resolver::{ResolveError, Resolver};
+
+use bitcoin::{Block, BlockHash, Script, ScriptBuf, Transaction, Txid};
+use electrum_client::ElectrumApi;
+use electrum_client::raw_client::{ElectrumPlaintextStream, RawClient};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A resolver backed by an Electrum server
+pub struct ElectrumResolver<E = RawClient<ElectrumPlaintextStream>> {
+    client: E,
+    subscriptions: Mutex<HashMap<ScriptBuf, HashSet<Txid>>>,
+}
+
+impl ElectrumResolver<RawClient<ElectrumPlaintextStream>> {
+    /// Connects to an Electrum server over plaintext TCP (e.g. `electrum.example.com:50001`)
+    pub fn new(addr: &str, timeout: Option<Duration>) -> Result<Self, ResolveError> {
+        let client = RawClient::new(addr, timeout).map_err(backend_error)?;
+        Ok(Self::with_client(client))
+    }
+}
+
+impl<E: ElectrumApi> ElectrumResolver<E> {
+    /// Wraps an existing Electrum client
+    pub fn with_client(client: E) -> Self {
+        Self {
+            client,
+            subscriptions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns every embedding in the transaction history of a script
+    pub fn embeddings_for_script(&self, script: &Script) -> Result<Vec<Embedding>, ResolveError> {
+        let txids = self.history(script)?;
+        self.embeddings_in_txs(&txids)
+    }
+
+    /// Subscribes to a script and returns the embeddings already in its history. Embeddings in
+    /// later transactions are returned by [`ElectrumResolver::poll`].
+    pub fn subscribe(&self, script: &Script) -> Result<Vec<Embedding>, ResolveError> {
+        self.client
+            .script_subscribe(script)
+            .map_err(backend_error)?;
+
+        let txids = self.history(script)?;
+        let embeddings = self.embeddings_in_txs(&txids)?;
+
+        self.lock_subscriptions()?
+            .insert(script.to_owned(), txids.into_iter().collect());
+
+        Ok(embeddings)
+    }
+
+    /// Unsubscribes from a script
+    pub fn unsubscribe(&self, script: &Script) -> Result<(), ResolveError> {
+        self.lock_subscriptions()?.remove(script);
+        self.client
+            .script_unsubscribe(script)
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    /// Returns the embeddings in transactions that have appeared in the history of a subscribed
+    /// script since it was subscribed or last polled
+    pub fn poll(&self) -> Result<Vec<Embedding>, ResolveError> {
+        // Process any pending notifications
+        self.client.ping().map_err(backend_error)?;
+
+        let mut subscriptions = self.lock_subscriptions()?;
+        let mut new_txids = Vec::new();
+
+        for (script, seen) in subscriptions.iter_mut() {
+            let mut changed = false;
+            while self
+                .client
+                .script_pop(script)
+                .map_err(backend_error)?
+                .is_some()
+            {
+                changed = true;
+            }
+
+            if !changed {
+                continue;
+            }
+
+            for txid in self.history(script)? {
+                if seen.insert(txid) && !new_txids.contains(&txid) {
+                    new_txids.push(txid);
+                }
+            }
+        }
+
+        drop(subscriptions);
+        self.embeddings_in_txs(&new_txids)
+    }
+
+    fn history(&self, script: &Script) -> Result<Vec<Txid>, ResolveError> {
+        Ok(self
+            .client
+            .script_get_history(script)
+            .map_err(backend_error)?
+            .into_iter()
+            .map(|entry| entry.tx_hash)
+            .collect())
+    }
+
+    fn embeddings_in_txs(&self, txids: &[Txid]) -> Result<Vec<Embedding>, ResolveError> {
+        Ok(self
+            .transactions(txids)?
+            .iter()
+            .flat_map(Embedding::from_transaction)
+            .collect())
+    }
+
+    fn lock_subscriptions(
+        &self,
+    ) -> Result<std::sync::MutexGuard<'_, HashMap<ScriptBuf, HashSet<Txid>>>, ResolveError> {
+        self.subscriptions
+            .lock()
+            .map_err(|_| ResolveError::Backend("subscription lock poisoned".to_string()))
+    }
+}
+
+impl<E: ElectrumApi> Resolver for ElectrumResolver<E> {
+    fn transaction(&self, txid: &Txid) -> Result<Transaction, ResolveError> {
+        self.client.transaction_get(txid).map_err(|e| match e {
+            // The server rejected the request for this txid
+            electrum_client::Error::Protocol(_) => ResolveError::TransactionNotFound(*txid),
+            e => backend_error(e),
+        })
+    }
+
+    /// Fetches all transactions in a single batched request
+    fn transactions(&self, txids: &[Txid]) -> Result<Vec<Transaction>, ResolveError> {
+        if txids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        self.client
+            .batch_transaction_get(txids)
+            .map_err(backend_error)
+    }
+
+    fn block(&self, _hash: &BlockHash) -> Result<Block, ResolveError> {
+        Err(ResolveError::Backend(
+            "Electrum servers do not serve blocks".to_string(),
+        ))
+    }
+}
+
+fn backend_error(e: electrum_client::Error) -> ResolveError {
+    ResolveError::Backend(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::envelope_tx;
+
+    use bitcoin::consensus::encode::serialize_hex;
+    use bitcoin::hashes::{Hash, sha256};
+    use bitcoin::hex::DisplayHex;
+    use serde_json::{Value, json};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serves line-delimited JSON-RPC requests on a local port. The handler returns any
+    /// notifications to send before the response, and the result or error for the request.
+    fn serve<F>(mut handler: F) -> String
+    where
+        F: FnMut(&str, &Value) -> (Vec<Value>, Result<Value, Value>) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+
+            for line in BufReader::new(stream).lines() {
+                let Ok(line) = line else { break };
+                let request: Value = serde_json::from_str(&line).unwrap();
+                let method = request["method"].as_str().unwrap();
+                let (notifications, result) = handler(method, &request["params"]);
+
+                let response = match result {
+                    Ok(result) => {
+                        json!({ "jsonrpc": "2.0", "id": request["id"], "result": result })
+                    }
+                    Err(error) => json!({ "jsonrpc": "2.0", "id": request["id"], "error": error }),
+                };
+
+                for message in notifications.iter().chain([&response]) {
+                    writeln!(writer, "{message}").unwrap();
+                }
+            }
+        });
+
+        addr
+    }
+
+    fn script_hash(script: &Script) -> String {
+        let mut hash = sha256::Hash::hash(script.as_bytes()).to_byte_array();
+        hash.reverse();
+        hash.to_lower_hex_string()
+    }
+
+    #[test]
+    fn test_transaction() {
+        let tx = envelope_tx(b"data");
+        let txid = tx.compute_txid();
+        let hex = serialize_hex(&tx);
+
+        let addr = serve(move |method, params| {
+            let result = match method {
+                "blockchain.transaction.get" if params[0] == txid.to_string() => Ok(json!(hex)),
+                _ => Err(json!({ "code": 2, "message": "not found" })),
+            };
+            (vec![], result)
+        });
+
+        let resolver = ElectrumResolver::new(&addr, None).unwrap();
+        assert_eq!(resolver.transaction(&txid), Ok(tx.clone()));
+        assert_eq!(
+            resolver.embeddings_in_tx(&txid),
+            Ok(Embedding::from_transaction(&tx))
+        );
+        assert_eq!(
+            resolver.transaction(&Txid::all_zeros()),
+            Err(ResolveError::TransactionNotFound(Txid::all_zeros()))
+        );
+        assert!(matches!(
+            resolver.block(&BlockHash::all_zeros()),
+            Err(ResolveError::Backend(_))
+        ));
+    }
+
+    #[test]
+    fn test_subscribe_and_poll() {
+        let tx1 = envelope_tx(b"first");
+        let tx2 = envelope_tx(b"second");
+        let script = ScriptBuf::from_hex("0014000102030405060708090a0b0c0d0e0f10111213").unwrap();
+
+        let txs = [tx1.clone(), tx2.clone()];
+        let hash = script_hash(&script);
+        let mut confirmed = 1;
+        let mut pings = 0;
+
+        let addr = serve(move |method, params| {
+            let mut notifications = vec![];
+            let result = match method {
+                "blockchain.scripthash.subscribe" => Value::Null,
+                "blockchain.scripthash.get_history" => {
+                    assert_eq!(params[0], hash);
+                    txs[..confirmed]
+                        .iter()
+                        .map(|tx| json!({ "height": 1, "tx_hash": tx.compute_txid().to_string() }))
+                        .collect()
+                }
+                "blockchain.transaction.get" => {
+                    let tx = txs
+                        .iter()
+                        .find(|tx| params[0] == tx.compute_txid().to_string())
+                        .unwrap();
+                    json!(serialize_hex(tx))
+                }
+                "server.ping" => {
+                    // The second transaction arrives before the first ping
+                    pings += 1;
+                    if pings == 1 {
+                        confirmed = 2;
+                        notifications.push(json!({
+                            "jsonrpc": "2.0",
+                            "method": "blockchain.scripthash.subscribe",
+                            "params": [hash, "11".repeat(32)],
+                        }));
+                    }
+                    Value::Null
+                }
+                _ => unreachable!("{method}"),
+            };
+            (notifications, Ok(result))
+        });
+
+        let resolver = ElectrumResolver::new(&addr, None).unwrap();
+
+        assert_eq!(
+            resolver.subscribe(&script),
+            Ok(Embedding::from_transaction(&tx1))
+        );
+        assert_eq!(resolver.poll(), Ok(Embedding::from_transaction(&tx2)));
+        assert_eq!(resolver.poll(), Ok(vec![]));
+        assert_eq!(
+            resolver.embeddings_for_script(&script),
+            Ok([
+                Embedding::from_transaction(&tx1),
+                Embedding::from_transaction(&tx2)
+            ]
+            .concat())
+        );
+    }
+}
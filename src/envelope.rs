@@ -4,38 +4,229 @@
 
 use {
     bitcoin::{
-        Script,
+        Opcode, Script, ScriptBuf,
         blockdata::{
             constants::MAX_SCRIPT_ELEMENT_SIZE,
             opcodes,
             script::{
                 Instruction::{self, Op, PushBytes},
-                Instructions,
+                InstructionIndices,
             },
         },
-        script::{Builder, Error, PushBytes as ScriptPushBytes},
+        script::{Builder, Error},
+    },
+    std::{
+        fmt, io,
+        iter::Peekable,
+        ops::{ControlFlow, Range},
     },
-    std::iter::Peekable,
 };
 
-type Result<T> = std::result::Result<T, Error>;
+type Result<T> = std::result::Result<T, EnvelopeError>;
+
+/// The maximum size of a script (consensus limit for legacy and segwit v0 scripts)
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+
+/// Errors that can occur when strictly parsing envelopes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvelopeError {
+    /// `OP_FALSE OP_IF` at the given byte offset has no matching `OP_ENDIF`
+    Unterminated {
+        /// The byte offset of the envelope's `OP_FALSE`
+        start: usize,
+    },
+    /// A non-push opcode appears inside an envelope
+    DisallowedOpcode {
+        /// The offending opcode
+        opcode: Opcode,
+        /// The byte offset of the opcode
+        position: usize,
+    },
+    /// The script could not be decoded
+    Script(Error),
+    /// The script would exceed the size limit
+    ScriptTooLarge {
+        /// The size of the script in bytes
+        size: usize,
+        /// The size limit in bytes
+        limit: usize,
+    },
+    /// The payload is not valid UTF-8
+    InvalidUtf8 {
+        /// The length of the valid prefix
+        valid_up_to: usize,
+    },
+    /// The value could not be serialized as JSON
+    #[cfg(feature = "serde")]
+    InvalidJson(String),
+    /// A push inside an envelope does not use the minimal encoding
+    NonMinimalPush {
+        /// The byte offset of the envelope's `OP_FALSE`
+        start: usize,
+        /// The index of the push within the envelope
+        index: usize,
+    },
+}
+
+/// An Envelope represents a series of data pushes within an `OP_FALSE OP_IF ... OP_ENDIF`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Envelope {
+    /// The data pushes
+    pub pushes: Vec<Vec<u8>>,
+    /// The opcode used for each push
+    pub opcodes: Vec<Opcode>,
+    /// The byte range of the envelope within the script, from `OP_FALSE` through `OP_ENDIF`
+    pub range: Range<usize>,
+    /// The byte offsets of non-push opcodes tolerated by an [`OpcodePolicy`]
+    pub tolerated: Vec<(usize, Opcode)>,
+}
+
+/// How non-push opcodes inside an envelope are handled
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum OpcodePolicy {
+    /// Discard the envelope
+    #[default]
+    Reject,
+    /// Skip the listed opcodes, recording them in [`Envelope::tolerated`], and discard the
+    /// envelope on any other opcode
+    Tolerate(Vec<Opcode>),
+    /// Stop at the first non-push opcode, malformed push, or the end of the script, keeping
+    /// prior pushes
+    Truncate,
+}
+
+impl OpcodePolicy {
+    /// Tolerates `OP_NOP` and `OP_CODESEPARATOR`
+    pub fn benign() -> Self {
+        OpcodePolicy::Tolerate(vec![opcodes::all::OP_NOP, opcodes::all::OP_CODESEPARATOR])
+    }
+}
+
+impl Envelope {
+    /// Returns the concatenated bytes of all pushes
+    pub fn concat(&self) -> Vec<u8> {
+        self.pushes.concat()
+    }
+
+    /// Returns an iterator over the pushes without copying them
+    pub fn iter_chunks(&self) -> impl Iterator<Item = &[u8]> {
+        self.pushes.iter().map(Vec::as_slice)
+    }
+
+    /// Parses the pushes using the ordinals field/body layout
+    pub fn fields(&self) -> Option<FieldEnvelope> {
+        FieldEnvelope::from_pushes(&self.pushes)
+    }
+
+    /// Returns the index of the first push that does not use the minimal encoding (see
+    /// [`is_minimal_push`]), if any
+    pub fn first_non_minimal_push(&self) -> Option<usize> {
+        self.pushes
+            .iter()
+            .zip(&self.opcodes)
+            .position(|(push, opcode)| !is_minimal_push(push, *opcode))
+    }
+}
+
+/// An envelope in the ordinals field/body layout:
+/// `OP_FALSE OP_IF <protocol> (<tag> <value>)* [OP_0 <body>*] OP_ENDIF`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldEnvelope {
+    /// The protocol identifier (the first push)
+    pub protocol: Vec<u8>,
+    /// The tag/value pairs, in order, including repeated tags
+    pub fields: Vec<(Vec<u8>, Vec<u8>)>,
+    /// The concatenated body chunks following the `OP_0` separator, if present
+    pub body: Option<Vec<u8>>,
+    /// Whether the final tag is missing its value
+    pub incomplete_field: bool,
+}
+
+impl FieldEnvelope {
+    /// Parses pushes using the ordinals field/body layout. Returns `None` if there are no
+    /// pushes.
+    pub fn from_pushes<T: AsRef<[u8]>>(pushes: &[T]) -> Option<Self> {
+        let (protocol, rest) = pushes.split_first()?;
+
+        let mut envelope = Self {
+            protocol: protocol.as_ref().to_vec(),
+            ..Default::default()
+        };
+
+        let mut i = 0;
+        while i < rest.len() {
+            let tag = rest[i].as_ref();
+
+            if tag.is_empty() {
+                envelope.body = Some(
+                    rest[(i + 1)..]
+                        .iter()
+                        .flat_map(AsRef::as_ref)
+                        .copied()
+                        .collect(),
+                );
+                break;
+            }
+
+            let Some(value) = rest.get(i + 1) else {
+                envelope.incomplete_field = true;
+                break;
+            };
+
+            envelope
+                .fields
+                .push((tag.to_vec(), value.as_ref().to_vec()));
+            i += 2;
+        }
 
-/// An Envelope represents a series of data pushes
-pub type Envelope = Vec<Vec<u8>>;
+        Some(envelope)
+    }
+
+    /// Returns the values of all fields with the given tag, in order
+    pub fn field_values<'a>(&'a self, tag: &'a [u8]) -> impl Iterator<Item = &'a [u8]> + 'a {
+        self.fields
+            .iter()
+            .filter(move |(t, _)| t == tag)
+            .map(|(_, value)| value.as_slice())
+    }
+}
 
-/// Adds envelope to a Bitcoin script using the envelope pattern (OP_FALSE OP_IF ... OP_ENDIF)
-pub fn append_to_builder(envelope: Envelope, mut builder: Builder) -> Builder {
-    builder = builder
-        .push_opcode(opcodes::OP_FALSE)
-        .push_opcode(opcodes::all::OP_IF);
+impl From<Vec<Vec<u8>>> for Envelope {
+    /// Converts raw pushes into an envelope, assuming each push uses the opcode selected by
+    /// `Builder::push_slice` and with an empty byte range
+    fn from(pushes: Vec<Vec<u8>>) -> Self {
+        let opcodes = pushes.iter().map(|push| push_opcode(push.len())).collect();
 
-    for bytes in envelope {
-        for chunk in bytes.chunks(MAX_SCRIPT_ELEMENT_SIZE) {
-            builder = builder.push_slice::<&ScriptPushBytes>(chunk.try_into().unwrap());
+        Self {
+            pushes,
+            opcodes,
+            range: 0..0,
+            tolerated: Vec::new(),
         }
     }
+}
+
+impl From<Envelope> for Vec<Vec<u8>> {
+    fn from(envelope: Envelope) -> Self {
+        envelope.pushes
+    }
+}
+
+impl PartialEq<Vec<Vec<u8>>> for Envelope {
+    fn eq(&self, other: &Vec<Vec<u8>>) -> bool {
+        self.pushes == *other
+    }
+}
 
-    builder.push_opcode(opcodes::all::OP_ENDIF)
+/// Adds pushes to a Bitcoin script using the envelope pattern (OP_FALSE OP_IF ... OP_ENDIF)
+pub fn append_to_builder(pushes: Vec<Vec<u8>>, builder: Builder) -> Builder {
+    EnvelopeBuilder::new().append_to_builder(pushes, builder)
+}
+
+/// Adds bytes streamed from `reader` to a Bitcoin script using the envelope pattern
+/// (OP_FALSE OP_IF ... OP_ENDIF)
+pub fn append_from_reader<R: io::Read>(reader: &mut R, builder: Builder) -> io::Result<Builder> {
+    EnvelopeBuilder::new().append_from_reader(reader, builder)
 }
 
 /// Adds bytes to a Bitcoin script using the envelope pattern (OP_FALSE OP_IF ... OP_ENDIF)
@@ -43,25 +234,481 @@ pub fn append_bytes_to_builder(bytes: &[u8], builder: Builder) -> Builder {
     append_to_builder(vec![bytes.to_vec()], builder)
 }
 
+/// Builds envelopes with a configurable chunk size, optional `OP_PUSHDATA2` for every push,
+/// optional `OP_PUSHNUM_<N>` opcodes for small single-byte pushes, and an optional empty push
+/// between fields
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvelopeBuilder {
+    chunk_size: usize,
+    force_pushdata2: bool,
+    use_pushnum: bool,
+    field_separator: bool,
+    max_script_size: usize,
+}
+
+impl Default for EnvelopeBuilder {
+    fn default() -> Self {
+        Self {
+            chunk_size: MAX_SCRIPT_ELEMENT_SIZE,
+            force_pushdata2: false,
+            use_pushnum: false,
+            field_separator: false,
+            max_script_size: MAX_SCRIPT_SIZE,
+        }
+    }
+}
+
+impl EnvelopeBuilder {
+    /// Returns a builder that chunks at `MAX_SCRIPT_ELEMENT_SIZE` with minimal push encoding
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum size of each data push, clamped to `1..=MAX_SCRIPT_ELEMENT_SIZE`
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.clamp(1, MAX_SCRIPT_ELEMENT_SIZE);
+        self
+    }
+
+    /// Encodes every data push with `OP_PUSHDATA2`
+    pub fn force_pushdata2(mut self, force_pushdata2: bool) -> Self {
+        self.force_pushdata2 = force_pushdata2;
+        self
+    }
+
+    /// Encodes single-byte pushes of 1 through 16 and 0x81 as `OP_PUSHNUM_<N>` opcodes
+    pub fn use_pushnum(mut self, use_pushnum: bool) -> Self {
+        self.use_pushnum = use_pushnum;
+        self
+    }
+
+    /// Inserts an empty push (`OP_0`) between consecutive fields
+    pub fn field_separator(mut self, field_separator: bool) -> Self {
+        self.field_separator = field_separator;
+        self
+    }
+
+    /// Sets the script size limit enforced by `try_append_to_builder` and `split` (defaults
+    /// to [`MAX_SCRIPT_SIZE`])
+    pub fn max_script_size(mut self, max_script_size: usize) -> Self {
+        self.max_script_size = max_script_size;
+        self
+    }
+
+    /// Returns the script size limit
+    pub fn script_size_limit(&self) -> usize {
+        self.max_script_size
+    }
+
+    /// Adds fields to a Bitcoin script using the envelope pattern, returning an error if the
+    /// resulting script would exceed the size limit
+    pub fn try_append_to_builder(&self, fields: Vec<Vec<u8>>, builder: Builder) -> Result<Builder> {
+        let builder = self.append_to_builder(fields, builder);
+
+        if builder.len() > self.max_script_size {
+            return Err(EnvelopeError::ScriptTooLarge {
+                size: builder.len(),
+                limit: self.max_script_size,
+            });
+        }
+
+        Ok(builder)
+    }
+
+    /// Adds UTF-8 text to a Bitcoin script using the envelope pattern, returning an error if
+    /// `bytes` is not valid UTF-8 or the script would exceed the size limit
+    pub fn try_append_utf8(&self, bytes: &[u8], builder: Builder) -> Result<Builder> {
+        std::str::from_utf8(bytes).map_err(|e| EnvelopeError::InvalidUtf8 {
+            valid_up_to: e.valid_up_to(),
+        })?;
+
+        self.try_append_to_builder(vec![bytes.to_vec()], builder)
+    }
+
+    /// Adds `value` serialized as JSON to a Bitcoin script using the envelope pattern,
+    /// returning an error if serialization fails or the script would exceed the size limit
+    /// (`serde` feature)
+    #[cfg(feature = "serde")]
+    pub fn try_append_json<T: serde::Serialize + ?Sized>(
+        &self,
+        value: &T,
+        builder: Builder,
+    ) -> Result<Builder> {
+        let bytes =
+            serde_json::to_vec(value).map_err(|e| EnvelopeError::InvalidJson(e.to_string()))?;
+
+        self.try_append_to_builder(vec![bytes], builder)
+    }
+
+    /// Splits fields across as many `<prefix> OP_FALSE OP_IF ... OP_ENDIF` scripts as needed to
+    /// keep each script within the size limit, for use as separate leaves or inputs. Pushes
+    /// are shortened at script boundaries, so readers should concatenate the envelopes in
+    /// order. Returns an error if the limit cannot fit the prefix and a one-byte push.
+    pub fn split(&self, fields: Vec<Vec<u8>>, prefix: &Script) -> Result<Vec<ScriptBuf>> {
+        let minimum = prefix.len() + 3 + self.header_len(1) + 1;
+        if minimum > self.max_script_size {
+            return Err(EnvelopeError::ScriptTooLarge {
+                size: minimum,
+                limit: self.max_script_size,
+            });
+        }
+
+        let open = || {
+            let mut script = prefix.to_bytes();
+            script.push(opcodes::OP_FALSE.to_u8());
+            script.push(opcodes::all::OP_IF.to_u8());
+            script
+        };
+
+        let mut scripts = Vec::new();
+        let mut script = open();
+
+        for (i, bytes) in fields.iter().enumerate() {
+            if self.field_separator && i > 0 {
+                if script.len() + 2 > self.max_script_size {
+                    script.push(opcodes::all::OP_ENDIF.to_u8());
+                    scripts.push(ScriptBuf::from_bytes(script));
+                    script = open();
+                }
+                script.push(opcodes::OP_FALSE.to_u8());
+            }
+
+            let mut remaining = bytes.as_slice();
+            while !remaining.is_empty() {
+                let space = self.max_script_size - script.len() - 1;
+                let len = self.max_chunk_len(space).min(remaining.len());
+
+                if len == 0 {
+                    script.push(opcodes::all::OP_ENDIF.to_u8());
+                    scripts.push(ScriptBuf::from_bytes(script));
+                    script = open();
+                    continue;
+                }
+
+                let (chunk, rest) = remaining.split_at(len);
+                self.push_chunk(chunk, &mut script);
+                remaining = rest;
+            }
+        }
+
+        script.push(opcodes::all::OP_ENDIF.to_u8());
+        scripts.push(ScriptBuf::from_bytes(script));
+
+        Ok(scripts)
+    }
+
+    /// Returns the largest push that fits within `space` bytes
+    fn max_chunk_len(&self, space: usize) -> usize {
+        let mut len = self.chunk_size.min(space);
+        while len > 0 && self.header_len(len) + len > space {
+            len -= 1;
+        }
+        len
+    }
+
+    /// Returns the size of the opcode and length prefix for a push of `len` bytes
+    fn header_len(&self, len: usize) -> usize {
+        if self.force_pushdata2 {
+            return 3;
+        }
+
+        match push_opcode(len) {
+            opcodes::all::OP_PUSHDATA1 => 2,
+            opcodes::all::OP_PUSHDATA2 => 3,
+            opcodes::all::OP_PUSHDATA4 => 5,
+            _ => 1,
+        }
+    }
+
+    /// Adds fields to a Bitcoin script using the envelope pattern (OP_FALSE OP_IF ... OP_ENDIF)
+    pub fn append_to_builder(&self, fields: Vec<Vec<u8>>, builder: Builder) -> Builder {
+        let mut script = builder.into_bytes();
+
+        script.push(opcodes::OP_FALSE.to_u8());
+        script.push(opcodes::all::OP_IF.to_u8());
+
+        for (i, bytes) in fields.iter().enumerate() {
+            if self.field_separator && i > 0 {
+                script.push(opcodes::OP_FALSE.to_u8());
+            }
+
+            for chunk in bytes.chunks(self.chunk_size) {
+                self.push_chunk(chunk, &mut script);
+            }
+        }
+
+        script.push(opcodes::all::OP_ENDIF.to_u8());
+
+        Builder::from(script)
+    }
+
+    /// Adds bytes streamed from `reader` to a Bitcoin script using the envelope pattern,
+    /// pushing each chunk as it is read rather than buffering the whole payload
+    pub fn append_from_reader<R: io::Read>(
+        &self,
+        reader: &mut R,
+        builder: Builder,
+    ) -> io::Result<Builder> {
+        let mut script = builder.into_bytes();
+        let mut buffer = [0u8; MAX_SCRIPT_ELEMENT_SIZE];
+        let buffer = &mut buffer[..self.chunk_size];
+
+        script.push(opcodes::OP_FALSE.to_u8());
+        script.push(opcodes::all::OP_IF.to_u8());
+
+        loop {
+            let mut len = 0;
+            while len < buffer.len() {
+                match reader.read(&mut buffer[len..]) {
+                    Ok(0) => break,
+                    Ok(n) => len += n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+
+            if len == 0 {
+                break;
+            }
+
+            self.push_chunk(&buffer[..len], &mut script);
+
+            if len < buffer.len() {
+                break;
+            }
+        }
+
+        script.push(opcodes::all::OP_ENDIF.to_u8());
+
+        Ok(Builder::from(script))
+    }
+
+    fn push_chunk(&self, chunk: &[u8], script: &mut Vec<u8>) {
+        if self.use_pushnum {
+            match chunk {
+                [0x81] => {
+                    script.push(opcodes::all::OP_PUSHNUM_NEG1.to_u8());
+                    return;
+                }
+                [n @ 1..=16] => {
+                    script.push(opcodes::all::OP_PUSHNUM_1.to_u8() + n - 1);
+                    return;
+                }
+                _ => {}
+            }
+        }
+
+        let opcode = if self.force_pushdata2 {
+            opcodes::all::OP_PUSHDATA2
+        } else {
+            push_opcode(chunk.len())
+        };
+
+        script.push(opcode.to_u8());
+
+        match opcode {
+            opcodes::all::OP_PUSHDATA1 => script.push(chunk.len() as u8),
+            opcodes::all::OP_PUSHDATA2 => script.extend((chunk.len() as u16).to_le_bytes()),
+            opcodes::all::OP_PUSHDATA4 => script.extend((chunk.len() as u32).to_le_bytes()),
+            _ => {}
+        }
+
+        script.extend(chunk);
+    }
+}
+
 /// Extracts envelopes from Bitcoin script
 pub fn from_script(script: &Script) -> Vec<Envelope> {
+    from_script_with_policy(script, &OpcodePolicy::Reject)
+}
+
+/// Extracts envelopes from Bitcoin script, handling non-push opcodes according to `policy`
+pub fn from_script_with_policy(script: &Script, policy: &OpcodePolicy) -> Vec<Envelope> {
+    let mut envelopes = Vec::new();
+    let mut envelope = Envelope::default();
+
+    let _: ControlFlow<()> = scan(script, policy, &mut envelope, |envelope, range| {
+        envelope.range = range;
+        envelopes.push(std::mem::take(envelope));
+        ControlFlow::Continue(())
+    });
+
+    envelopes
+}
+
+/// Receives the contents of an envelope as it is parsed
+pub(crate) trait EnvelopeSink {
+    /// Discards the contents of a previous or abandoned envelope
+    fn clear(&mut self);
+
+    /// Receives a data push and the opcode that pushed it
+    fn push(&mut self, data: &[u8], opcode: Opcode);
+
+    /// Receives a non-push opcode tolerated by the policy
+    fn tolerate(&mut self, position: usize, opcode: Opcode);
+}
+
+impl EnvelopeSink for Envelope {
+    fn clear(&mut self) {
+        *self = Envelope::default();
+    }
+
+    fn push(&mut self, data: &[u8], opcode: Opcode) {
+        self.pushes.push(data.to_vec());
+        self.opcodes.push(opcode);
+    }
+
+    fn tolerate(&mut self, position: usize, opcode: Opcode) {
+        self.tolerated.push((position, opcode));
+    }
+}
+
+/// Parses each envelope in `script` into `sink`, calling `f` with the sink and the envelope's
+/// byte range after each complete envelope until it breaks. Malformed envelopes are skipped, as
+/// in [`from_script_with_policy`].
+pub(crate) fn scan<S: EnvelopeSink, B>(
+    script: &Script,
+    policy: &OpcodePolicy,
+    sink: &mut S,
+    mut f: impl FnMut(&mut S, Range<usize>) -> ControlFlow<B>,
+) -> ControlFlow<B> {
+    if !script_may_contain_envelope(script) {
+        return ControlFlow::Continue(());
+    }
+
+    let mut instructions = script.instruction_indices().peekable();
+
+    while let Ok(Some((start, instruction))) = instructions.next().transpose() {
+        if instruction == PushBytes((&[]).into()) {
+            sink.clear();
+            if let Ok(Some(range)) =
+                from_instructions(script, start, &mut instructions, policy, sink)
+            {
+                f(sink, range)?;
+            }
+        }
+    }
+
+    ControlFlow::Continue(())
+}
+
+/// Extracts envelopes from Bitcoin script, returning an error if an envelope is present but
+/// malformed or if the script cannot be decoded
+pub fn from_script_strict(script: &Script) -> Result<Vec<Envelope>> {
     let mut envelopes = Vec::new();
 
-    let mut instructions = script.instructions().peekable();
+    let mut instructions = script.instruction_indices().peekable();
 
-    while let Ok(Some(instruction)) = instructions.next().transpose() {
+    while let Some((start, instruction)) = instructions.next().transpose()? {
         if instruction == PushBytes((&[]).into()) {
-            if let Ok(Some(envelope)) = from_instructions(&mut instructions) {
+            let mut envelope = Envelope::default();
+            if let Some(range) = from_instructions(
+                script,
+                start,
+                &mut instructions,
+                &OpcodePolicy::Reject,
+                &mut envelope,
+            )? {
+                envelope.range = range;
                 envelopes.push(envelope);
             }
         }
     }
 
-    envelopes
+    Ok(envelopes)
+}
+
+/// Extracts envelopes from Bitcoin script as in [`from_script_strict`], also returning an
+/// error if any push inside an envelope does not use the minimal encoding
+pub fn from_script_minimal(script: &Script) -> Result<Vec<Envelope>> {
+    let envelopes = from_script_strict(script)?;
+
+    for envelope in &envelopes {
+        if let Some(index) = envelope.first_non_minimal_push() {
+            return Err(EnvelopeError::NonMinimalPush {
+                start: envelope.range.start,
+                index,
+            });
+        }
+    }
+
+    Ok(envelopes)
+}
+
+/// Returns whether `opcode` is the minimal encoding of a push of `data`, as in Bitcoin Core's
+/// `MINIMALDATA` rule: `OP_0` for no data, `OP_1` through `OP_16` and `OP_1NEGATE` for their
+/// single bytes, and otherwise the smallest push opcode that fits the length
+pub fn is_minimal_push(data: &[u8], opcode: Opcode) -> bool {
+    let minimal = match data {
+        [n @ 1..=16] => Opcode::from(opcodes::all::OP_PUSHNUM_1.to_u8() + n - 1),
+        [0x81] => opcodes::all::OP_PUSHNUM_NEG1,
+        _ => push_opcode(data.len()),
+    };
+    opcode == minimal
+}
+
+/// Returns false if `script` cannot contain an envelope, because its bytes never have
+/// `OP_FALSE` followed by `OP_IF`. A cheap byte scan run before parsing instructions; a true
+/// result may still be a match inside push data.
+pub fn script_may_contain_envelope(script: &Script) -> bool {
+    let pattern = [opcodes::OP_FALSE.to_u8(), opcodes::all::OP_IF.to_u8()];
+    script.as_bytes().windows(2).any(|window| window == pattern)
+}
+
+/// Returns the pushes of a payload prefixed by a protocol identifier push. Identifiers should
+/// be at most `MAX_SCRIPT_ELEMENT_SIZE` bytes so that they occupy a single push.
+pub fn with_protocol_id(id: &[u8], payload: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+    let mut pushes = Vec::with_capacity(payload.len() + 1);
+    pushes.push(id.to_vec());
+    pushes.extend(payload);
+    pushes
+}
+
+/// Extracts envelopes whose first push is the protocol identifier `id`, with the identifier
+/// push removed. Scripts that do not contain `id` anywhere are skipped without being parsed.
+pub fn filter_by_protocol(script: &Script, id: &[u8]) -> Vec<Envelope> {
+    let bytes = script.as_bytes();
+    if !id.is_empty() && !bytes.windows(id.len()).any(|window| window == id) {
+        return Vec::new();
+    }
+
+    from_script(script)
+        .into_iter()
+        .filter(|envelope| envelope.pushes.first().map(Vec::as_slice) == Some(id))
+        .map(|mut envelope| {
+            envelope.pushes.remove(0);
+            envelope.opcodes.remove(0);
+            envelope
+        })
+        .collect()
+}
+
+/// Returns the opcode `Builder::push_slice` uses for a push of the given length
+fn push_opcode(len: usize) -> Opcode {
+    match len {
+        0..=75 => Opcode::from(len as u8),
+        76..=0xff => opcodes::all::OP_PUSHDATA1,
+        0x100..=0xffff => opcodes::all::OP_PUSHDATA2,
+        _ => opcodes::all::OP_PUSHDATA4,
+    }
+}
+
+/// Returns the position after the instruction at `position`
+fn instruction_end(script: &Script, position: usize, instruction: &Instruction) -> usize {
+    let header = match Opcode::from(script.as_bytes()[position]) {
+        opcodes::all::OP_PUSHDATA1 => 2,
+        opcodes::all::OP_PUSHDATA2 => 3,
+        opcodes::all::OP_PUSHDATA4 => 5,
+        _ => 1,
+    };
+    position + header + instruction.push_bytes().map_or(0, |push| push.len())
 }
 
-fn accept(instructions: &mut Peekable<Instructions>, instruction: Instruction) -> Result<bool> {
-    if instructions.peek() == Some(&Ok(instruction)) {
+fn accept(
+    instructions: &mut Peekable<InstructionIndices>,
+    instruction: Instruction,
+) -> Result<bool> {
+    if matches!(instructions.peek(), Some(Ok((_, next))) if *next == instruction) {
         instructions.next().transpose()?;
         Ok(true)
     } else {
@@ -69,74 +716,95 @@ fn accept(instructions: &mut Peekable<Instructions>, instruction: Instruction) -
     }
 }
 
-fn from_instructions(instructions: &mut Peekable<Instructions>) -> Result<Option<Envelope>> {
+fn from_instructions(
+    script: &Script,
+    start: usize,
+    instructions: &mut Peekable<InstructionIndices>,
+    policy: &OpcodePolicy,
+    sink: &mut impl EnvelopeSink,
+) -> Result<Option<Range<usize>>> {
     if !accept(instructions, Op(opcodes::all::OP_IF))? {
         return Ok(None);
     }
 
-    let mut payload = Vec::new();
-
+    // The end of the last instruction parsed, after `OP_FALSE OP_IF`
+    let mut end = start + 2;
     loop {
-        match instructions.next().transpose()? {
-            None => return Ok(None),
-            Some(Op(opcodes::all::OP_ENDIF)) => {
-                return Ok(Some(payload));
-            }
-            Some(Op(opcodes::all::OP_PUSHNUM_NEG1)) => {
-                payload.push(vec![0x81]);
-            }
-            Some(Op(opcodes::all::OP_PUSHNUM_1)) => {
-                payload.push(vec![1]);
-            }
-            Some(Op(opcodes::all::OP_PUSHNUM_2)) => {
-                payload.push(vec![2]);
-            }
-            Some(Op(opcodes::all::OP_PUSHNUM_3)) => {
-                payload.push(vec![3]);
-            }
-            Some(Op(opcodes::all::OP_PUSHNUM_4)) => {
-                payload.push(vec![4]);
-            }
-            Some(Op(opcodes::all::OP_PUSHNUM_5)) => {
-                payload.push(vec![5]);
-            }
-            Some(Op(opcodes::all::OP_PUSHNUM_6)) => {
-                payload.push(vec![6]);
+        let next = match instructions.next().transpose() {
+            Err(_) if *policy == OpcodePolicy::Truncate => return Ok(Some(start..end)),
+            next => next?,
+        };
+        let Some((position, instruction)) = next else {
+            if *policy == OpcodePolicy::Truncate {
+                return Ok(Some(start..script.len()));
             }
-            Some(Op(opcodes::all::OP_PUSHNUM_7)) => {
-                payload.push(vec![7]);
-            }
-            Some(Op(opcodes::all::OP_PUSHNUM_8)) => {
-                payload.push(vec![8]);
-            }
-            Some(Op(opcodes::all::OP_PUSHNUM_9)) => {
-                payload.push(vec![9]);
-            }
-            Some(Op(opcodes::all::OP_PUSHNUM_10)) => {
-                payload.push(vec![10]);
-            }
-            Some(Op(opcodes::all::OP_PUSHNUM_11)) => {
-                payload.push(vec![11]);
-            }
-            Some(Op(opcodes::all::OP_PUSHNUM_12)) => {
-                payload.push(vec![12]);
+
+            return Err(EnvelopeError::Unterminated { start });
+        };
+        end = instruction_end(script, position, &instruction);
+
+        let pushnum;
+        let push = match instruction {
+            Op(opcodes::all::OP_ENDIF) => return Ok(Some(start..(position + 1))),
+            Op(opcodes::all::OP_PUSHNUM_NEG1) => &[0x81][..],
+            Op(opcode)
+                if (opcodes::all::OP_PUSHNUM_1.to_u8()..=opcodes::all::OP_PUSHNUM_16.to_u8())
+                    .contains(&opcode.to_u8()) =>
+            {
+                pushnum = [opcode.to_u8() - opcodes::all::OP_PUSHNUM_1.to_u8() + 1];
+                &pushnum[..]
             }
-            Some(Op(opcodes::all::OP_PUSHNUM_13)) => {
-                payload.push(vec![13]);
+            PushBytes(push) => push.as_bytes(),
+            Op(opcode) => match policy {
+                OpcodePolicy::Tolerate(allowed) if allowed.contains(&opcode) => {
+                    sink.tolerate(position, opcode);
+                    continue;
+                }
+                OpcodePolicy::Truncate => return Ok(Some(start..position)),
+                _ => return Err(EnvelopeError::DisallowedOpcode { opcode, position }),
+            },
+        };
+
+        sink.push(push, Opcode::from(script.as_bytes()[position]));
+    }
+}
+
+impl From<Error> for EnvelopeError {
+    fn from(e: Error) -> Self {
+        EnvelopeError::Script(e)
+    }
+}
+
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvelopeError::Unterminated { start } => {
+                write!(f, "Envelope at byte {start} is missing OP_ENDIF")
             }
-            Some(Op(opcodes::all::OP_PUSHNUM_14)) => {
-                payload.push(vec![14]);
+            EnvelopeError::DisallowedOpcode { opcode, position } => {
+                write!(f, "Disallowed opcode {opcode} at byte {position}")
             }
-            Some(Op(opcodes::all::OP_PUSHNUM_15)) => {
-                payload.push(vec![15]);
+            EnvelopeError::Script(e) => write!(f, "Script error: {e}"),
+            EnvelopeError::ScriptTooLarge { size, limit } => {
+                write!(f, "Script size {size} exceeds limit of {limit} bytes")
             }
-            Some(Op(opcodes::all::OP_PUSHNUM_16)) => {
-                payload.push(vec![16]);
+            EnvelopeError::InvalidUtf8 { valid_up_to } => {
+                write!(f, "Invalid UTF-8 after byte {valid_up_to}")
             }
-            Some(PushBytes(push)) => {
-                payload.push(push.as_bytes().to_vec());
+            #[cfg(feature = "serde")]
+            EnvelopeError::InvalidJson(e) => write!(f, "JSON serialization error: {e}"),
+            EnvelopeError::NonMinimalPush { start, index } => {
+                write!(f, "Push {index} of envelope at byte {start} is not minimal")
             }
-            Some(_) => return Ok(None),
+        }
+    }
+}
+
+impl std::error::Error for EnvelopeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EnvelopeError::Script(e) => Some(e),
+            _ => None,
         }
     }
 }
@@ -144,6 +812,7 @@ fn from_instructions(instructions: &mut Peekable<Instructions>) -> Result<Option
 #[cfg(test)]
 mod tests {
     use super::*;
+    use bitcoin::script::PushBytes as ScriptPushBytes;
 
     #[test]
     fn test_empty_script() {
@@ -170,6 +839,7 @@ mod tests {
             .into_script();
 
         assert_eq!(from_script(&script), vec![Vec::<Vec<u8>>::new()]);
+        assert_eq!(from_script(&script)[0].range, 0..3);
     }
 
     #[test]
@@ -264,8 +934,9 @@ mod tests {
         let extracted = from_script(&script);
         assert_eq!(extracted.len(), 1);
 
-        let flattened: Vec<u8> = extracted[0].iter().flatten().cloned().collect();
+        let flattened: Vec<u8> = extracted[0].pushes.iter().flatten().cloned().collect();
         assert_eq!(flattened, large_data);
+        assert_eq!(extracted[0].concat(), large_data);
     }
 
     #[test]
@@ -342,4 +1013,658 @@ mod tests {
         let extracted = from_script(&script);
         assert_eq!(extracted, vec![original_data]);
     }
+
+    #[test]
+    fn test_envelope_metadata() {
+        let script = Builder::new()
+            .push_opcode(opcodes::all::OP_DUP)
+            .push_opcode(opcodes::OP_FALSE)
+            .push_opcode(opcodes::all::OP_IF)
+            .push_slice(b"ab")
+            .push_opcode(opcodes::all::OP_PUSHNUM_5)
+            .push_slice([0u8; 76])
+            .push_opcode(opcodes::all::OP_ENDIF)
+            .push_opcode(opcodes::all::OP_DROP)
+            .into_script();
+
+        let envelopes = from_script(&script);
+        assert_eq!(envelopes.len(), 1);
+
+        let envelope = &envelopes[0];
+        assert_eq!(envelope.pushes, vec![b"ab".to_vec(), vec![5], vec![0; 76]]);
+        assert_eq!(
+            envelope.opcodes,
+            vec![
+                opcodes::all::OP_PUSHBYTES_2,
+                opcodes::all::OP_PUSHNUM_5,
+                opcodes::all::OP_PUSHDATA1,
+            ]
+        );
+
+        // OP_FALSE at byte 1 through OP_ENDIF before the trailing OP_DROP
+        assert_eq!(envelope.range, 1..(script.len() - 1));
+        assert_eq!(
+            script.as_bytes()[envelope.range.end - 1],
+            opcodes::all::OP_ENDIF.to_u8()
+        );
+
+        let mut concat = b"ab".to_vec();
+        concat.push(5);
+        concat.extend([0; 76]);
+        assert_eq!(envelope.concat(), concat);
+    }
+
+    #[test]
+    fn test_envelope_conversions() {
+        let pushes = vec![vec![1, 2, 3], vec![0xaa; 300]];
+        let envelope = Envelope::from(pushes.clone());
+
+        assert_eq!(
+            envelope.opcodes,
+            vec![opcodes::all::OP_PUSHBYTES_3, opcodes::all::OP_PUSHDATA2]
+        );
+        assert_eq!(envelope.range, 0..0);
+
+        let script = append_to_builder(pushes.clone(), Builder::new()).into_script();
+        let parsed = from_script(&script).remove(0);
+        assert_eq!(parsed.opcodes, envelope.opcodes);
+
+        assert_eq!(Vec::<Vec<u8>>::from(envelope), pushes);
+    }
+
+    #[test]
+    fn test_envelope_builder_default_matches_append_to_builder() {
+        let pushes = vec![vec![1], vec![0xaa; 100], vec![0xbb; 1000], vec![]];
+
+        let mut expected = Builder::new()
+            .push_opcode(opcodes::OP_FALSE)
+            .push_opcode(opcodes::all::OP_IF)
+            .push_slice([1])
+            .push_slice::<&ScriptPushBytes>([0xaa; 100][..].try_into().unwrap());
+        for chunk in [0xbb; 1000].chunks(MAX_SCRIPT_ELEMENT_SIZE) {
+            expected = expected.push_slice::<&ScriptPushBytes>(chunk.try_into().unwrap());
+        }
+        let expected = expected.push_opcode(opcodes::all::OP_ENDIF).into_script();
+
+        let builder = EnvelopeBuilder::new().append_to_builder(pushes.clone(), Builder::new());
+        assert_eq!(builder.into_script(), expected);
+        assert_eq!(
+            append_to_builder(pushes, Builder::new()).into_script(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_envelope_builder_chunk_size() {
+        let script = EnvelopeBuilder::new()
+            .chunk_size(3)
+            .append_to_builder(vec![vec![1, 2, 3, 4, 5, 6, 7]], Builder::new())
+            .into_script();
+
+        assert_eq!(
+            from_script(&script),
+            vec![vec![vec![1, 2, 3], vec![4, 5, 6], vec![7]]]
+        );
+
+        // Chunk size is clamped to valid push sizes
+        assert_eq!(EnvelopeBuilder::new().chunk_size(0).chunk_size, 1);
+        assert_eq!(
+            EnvelopeBuilder::new().chunk_size(10_000).chunk_size,
+            MAX_SCRIPT_ELEMENT_SIZE
+        );
+    }
+
+    #[test]
+    fn test_envelope_builder_force_pushdata2() {
+        let script = EnvelopeBuilder::new()
+            .force_pushdata2(true)
+            .append_to_builder(vec![vec![1, 2]], Builder::new())
+            .into_script();
+
+        assert_eq!(
+            script.as_bytes(),
+            [
+                opcodes::OP_FALSE.to_u8(),
+                opcodes::all::OP_IF.to_u8(),
+                opcodes::all::OP_PUSHDATA2.to_u8(),
+                2,
+                0,
+                1,
+                2,
+                opcodes::all::OP_ENDIF.to_u8(),
+            ]
+        );
+
+        let envelope = from_script(&script).remove(0);
+        assert_eq!(envelope.pushes, vec![vec![1, 2]]);
+        assert_eq!(envelope.opcodes, vec![opcodes::all::OP_PUSHDATA2]);
+    }
+
+    #[test]
+    fn test_envelope_builder_pushnum() {
+        let fields = vec![vec![1], vec![16], vec![0x81], vec![0], vec![17]];
+        let script = EnvelopeBuilder::new()
+            .use_pushnum(true)
+            .append_to_builder(fields.clone(), Builder::new())
+            .into_script();
+
+        let envelope = from_script(&script).remove(0);
+        assert_eq!(envelope.pushes, fields);
+        assert_eq!(
+            envelope.opcodes,
+            vec![
+                opcodes::all::OP_PUSHNUM_1,
+                opcodes::all::OP_PUSHNUM_16,
+                opcodes::all::OP_PUSHNUM_NEG1,
+                opcodes::all::OP_PUSHBYTES_1,
+                opcodes::all::OP_PUSHBYTES_1,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_envelope_builder_field_separator() {
+        let script = EnvelopeBuilder::new()
+            .field_separator(true)
+            .chunk_size(2)
+            .append_to_builder(vec![vec![1, 2, 3], vec![4]], Builder::new())
+            .into_script();
+
+        assert_eq!(
+            from_script(&script),
+            vec![vec![vec![1, 2], vec![3], vec![], vec![4]]]
+        );
+    }
+
+    #[test]
+    fn test_strict_no_envelopes() {
+        assert_eq!(from_script_strict(Script::new()), Ok(vec![]));
+
+        let script = Builder::new()
+            .push_opcode(opcodes::OP_FALSE)
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .into_script();
+        assert_eq!(from_script_strict(&script), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_strict_valid_envelopes() {
+        let mut builder = append_bytes_to_builder(b"one", Builder::new());
+        builder = append_bytes_to_builder(b"two", builder);
+        let script = builder.into_script();
+
+        assert_eq!(from_script_strict(&script), Ok(from_script(&script)));
+    }
+
+    #[test]
+    fn test_strict_unterminated() {
+        let script = Builder::new()
+            .push_opcode(opcodes::all::OP_DUP)
+            .push_opcode(opcodes::OP_FALSE)
+            .push_opcode(opcodes::all::OP_IF)
+            .push_slice(b"data")
+            .into_script();
+
+        assert_eq!(from_script(&script), Vec::<Envelope>::new());
+        assert_eq!(
+            from_script_strict(&script),
+            Err(EnvelopeError::Unterminated { start: 1 })
+        );
+    }
+
+    #[test]
+    fn test_strict_disallowed_opcode() {
+        let script = Builder::new()
+            .push_opcode(opcodes::OP_FALSE)
+            .push_opcode(opcodes::all::OP_IF)
+            .push_slice([0x01])
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .push_opcode(opcodes::all::OP_ENDIF)
+            .into_script();
+
+        assert_eq!(from_script(&script), Vec::<Envelope>::new());
+        assert_eq!(
+            from_script_strict(&script),
+            Err(EnvelopeError::DisallowedOpcode {
+                opcode: opcodes::all::OP_CHECKSIG,
+                position: 4,
+            })
+        );
+    }
+
+    #[test]
+    fn test_strict_script_error() {
+        // OP_PUSHBYTES_5 with only two bytes following
+        let script = ScriptBuf::from_bytes(vec![0x05, 0x01, 0x02]);
+
+        assert_eq!(from_script(&script), Vec::<Envelope>::new());
+        assert_eq!(
+            from_script_strict(&script),
+            Err(EnvelopeError::Script(Error::EarlyEndOfScript))
+        );
+    }
+
+    #[test]
+    fn test_with_protocol_id() {
+        let pushes = with_protocol_id(b"proto", vec![vec![1, 2], vec![3]]);
+        assert_eq!(pushes, vec![b"proto".to_vec(), vec![1, 2], vec![3]]);
+    }
+
+    #[test]
+    fn test_filter_by_protocol() {
+        let mut builder = append_to_builder(
+            with_protocol_id(b"proto", vec![vec![1, 2], vec![3]]),
+            Builder::new(),
+        );
+        builder = append_to_builder(with_protocol_id(b"other", vec![vec![4]]), builder);
+        builder = append_bytes_to_builder(b"no protocol", builder);
+        builder = append_to_builder(with_protocol_id(b"proto", vec![]), builder);
+        let script = builder.into_script();
+
+        let envelopes = filter_by_protocol(&script, b"proto");
+        assert_eq!(envelopes, vec![vec![vec![1, 2], vec![3]], vec![]]);
+        assert_eq!(envelopes[0].opcodes.len(), 2);
+        assert_eq!(envelopes[0].range, from_script(&script)[0].range);
+
+        assert_eq!(filter_by_protocol(&script, b"other"), vec![vec![vec![4]]]);
+        assert_eq!(
+            filter_by_protocol(&script, b"missing"),
+            Vec::<Envelope>::new()
+        );
+    }
+
+    #[test]
+    fn test_filter_by_protocol_requires_exact_first_push() {
+        let script =
+            append_to_builder(vec![b"protocol".to_vec(), vec![1]], Builder::new()).into_script();
+
+        assert_eq!(
+            filter_by_protocol(&script, b"proto"),
+            Vec::<Envelope>::new()
+        );
+        assert_eq!(
+            filter_by_protocol(&script, b"protocol"),
+            vec![vec![vec![1]]]
+        );
+    }
+
+    #[test]
+    fn test_fields_ord_layout() {
+        let script = Builder::new()
+            .push_opcode(opcodes::OP_FALSE)
+            .push_opcode(opcodes::all::OP_IF)
+            .push_slice(b"ord")
+            .push_opcode(opcodes::all::OP_PUSHNUM_1)
+            .push_slice(b"text/plain")
+            .push_opcode(opcodes::OP_FALSE)
+            .push_slice(b"hello ")
+            .push_slice(b"world")
+            .push_opcode(opcodes::all::OP_ENDIF)
+            .into_script();
+
+        let fields = from_script(&script)[0].fields().unwrap();
+        assert_eq!(fields.protocol, b"ord");
+        assert_eq!(fields.fields, vec![(vec![1], b"text/plain".to_vec())]);
+        assert_eq!(fields.body, Some(b"hello world".to_vec()));
+        assert!(!fields.incomplete_field);
+    }
+
+    #[test]
+    fn test_fields_repeated_and_incomplete() {
+        let fields = FieldEnvelope::from_pushes(&[
+            b"ord".to_vec(),
+            vec![5],
+            b"part1".to_vec(),
+            vec![5],
+            b"part2".to_vec(),
+            vec![7],
+        ])
+        .unwrap();
+
+        assert_eq!(
+            fields.field_values(&[5]).collect::<Vec<_>>(),
+            vec![b"part1".as_slice(), b"part2".as_slice()]
+        );
+        assert_eq!(fields.body, None);
+        assert!(fields.incomplete_field);
+    }
+
+    #[test]
+    fn test_fields_empty_body_and_no_pushes() {
+        let fields = FieldEnvelope::from_pushes(&[b"ord".as_slice(), &[]]).unwrap();
+        assert_eq!(fields.body, Some(vec![]));
+        assert!(fields.fields.is_empty());
+
+        assert_eq!(FieldEnvelope::from_pushes::<Vec<u8>>(&[]), None);
+    }
+
+    #[test]
+    fn test_policy_reject_is_default() {
+        let script = Builder::new()
+            .push_opcode(opcodes::OP_FALSE)
+            .push_opcode(opcodes::all::OP_IF)
+            .push_slice(b"a")
+            .push_opcode(opcodes::all::OP_NOP)
+            .push_slice(b"b")
+            .push_opcode(opcodes::all::OP_ENDIF)
+            .into_script();
+
+        assert_eq!(from_script(&script), Vec::<Envelope>::new());
+        assert_eq!(
+            from_script_with_policy(&script, &OpcodePolicy::default()),
+            Vec::<Envelope>::new()
+        );
+    }
+
+    #[test]
+    fn test_policy_tolerate() {
+        let script = Builder::new()
+            .push_opcode(opcodes::OP_FALSE)
+            .push_opcode(opcodes::all::OP_IF)
+            .push_slice(b"a")
+            .push_opcode(opcodes::all::OP_NOP)
+            .push_slice(b"b")
+            .push_opcode(opcodes::all::OP_CODESEPARATOR)
+            .push_opcode(opcodes::all::OP_ENDIF)
+            .into_script();
+
+        let envelopes = from_script_with_policy(&script, &OpcodePolicy::benign());
+        assert_eq!(envelopes, vec![vec![b"a".to_vec(), b"b".to_vec()]]);
+        assert_eq!(
+            envelopes[0].tolerated,
+            vec![
+                (4, opcodes::all::OP_NOP),
+                (7, opcodes::all::OP_CODESEPARATOR)
+            ]
+        );
+        assert_eq!(envelopes[0].range, 0..script.len());
+
+        // Opcodes outside the allowed set still discard the envelope
+        let policy = OpcodePolicy::Tolerate(vec![opcodes::all::OP_NOP]);
+        assert_eq!(
+            from_script_with_policy(&script, &policy),
+            Vec::<Envelope>::new()
+        );
+    }
+
+    #[test]
+    fn test_policy_truncate() {
+        let script = Builder::new()
+            .push_opcode(opcodes::OP_FALSE)
+            .push_opcode(opcodes::all::OP_IF)
+            .push_slice(b"a")
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .push_slice(b"b")
+            .push_opcode(opcodes::all::OP_ENDIF)
+            .into_script();
+
+        let envelopes = from_script_with_policy(&script, &OpcodePolicy::Truncate);
+        assert_eq!(envelopes, vec![vec![b"a".to_vec()]]);
+        assert_eq!(envelopes[0].range, 0..4);
+
+        // Unterminated envelopes keep their pushes as well
+        let script = Builder::new()
+            .push_opcode(opcodes::OP_FALSE)
+            .push_opcode(opcodes::all::OP_IF)
+            .push_slice(b"a")
+            .into_script();
+
+        let envelopes = from_script_with_policy(&script, &OpcodePolicy::Truncate);
+        assert_eq!(envelopes, vec![vec![b"a".to_vec()]]);
+        assert_eq!(envelopes[0].range, 0..script.len());
+
+        // As do envelopes cut off by a push running past the end of the script
+        let mut bytes = Builder::new()
+            .push_opcode(opcodes::OP_FALSE)
+            .push_opcode(opcodes::all::OP_IF)
+            .push_slice(<&ScriptPushBytes>::try_from(&[7; 80][..]).unwrap())
+            .into_script()
+            .into_bytes();
+        let end = bytes.len();
+        bytes.extend([opcodes::all::OP_PUSHDATA1.to_u8(), 10, 1, 2]);
+        let script = ScriptBuf::from_bytes(bytes);
+
+        let envelopes = from_script_with_policy(&script, &OpcodePolicy::Truncate);
+        assert_eq!(envelopes, vec![vec![vec![7; 80]]]);
+        assert_eq!(envelopes[0].range, 0..end);
+        assert_eq!(
+            from_script_with_policy(&script, &OpcodePolicy::Reject),
+            Vec::<Envelope>::new()
+        );
+    }
+
+    #[test]
+    fn test_try_append_to_builder_limit() {
+        let builder = EnvelopeBuilder::new();
+        assert!(
+            builder
+                .try_append_to_builder(vec![vec![0; 9_000]], Builder::new())
+                .is_ok()
+        );
+
+        let err = builder
+            .try_append_to_builder(vec![vec![0; 10_000]], Builder::new())
+            .unwrap_err();
+        assert_eq!(
+            err,
+            EnvelopeError::ScriptTooLarge {
+                size: 10_000 + 3 + 19 * 3 + 2,
+                limit: MAX_SCRIPT_SIZE,
+            }
+        );
+
+        let builder = EnvelopeBuilder::new().max_script_size(10);
+        assert!(
+            builder
+                .try_append_to_builder(vec![vec![1, 2, 3]], Builder::new())
+                .is_ok()
+        );
+        assert!(
+            builder
+                .try_append_to_builder(vec![vec![0; 7]], Builder::new())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_try_append_utf8_and_json() {
+        let builder = EnvelopeBuilder::new();
+        assert_eq!(
+            builder.try_append_utf8("text".as_bytes(), Builder::new()),
+            Ok(builder.append_to_builder(vec![b"text".to_vec()], Builder::new()))
+        );
+        assert_eq!(
+            builder.try_append_utf8(&[b'a', 0xff], Builder::new()),
+            Err(EnvelopeError::InvalidUtf8 { valid_up_to: 1 })
+        );
+
+        #[cfg(feature = "serde")]
+        {
+            let value = serde_json::json!({"p": "brc-20", "op": "mint"});
+            let script = builder
+                .try_append_json(&value, Builder::new())
+                .unwrap()
+                .into_script();
+            let envelopes = from_script(&script);
+            assert_eq!(
+                serde_json::from_slice::<serde_json::Value>(&envelopes[0].concat()).unwrap(),
+                value
+            );
+
+            let map = std::collections::HashMap::from([(vec![1u8], 1)]);
+            assert!(matches!(
+                builder.try_append_json(&map, Builder::new()),
+                Err(EnvelopeError::InvalidJson(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_split_respects_limit() {
+        let prefix = Builder::new()
+            .push_slice([2; 32])
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .into_script();
+        let payload = (0..25_000u32).map(|i| i as u8).collect::<Vec<_>>();
+
+        let builder = EnvelopeBuilder::new();
+        let scripts = builder.split(vec![payload.clone()], &prefix).unwrap();
+        assert_eq!(scripts.len(), 3);
+
+        let mut reassembled = Vec::new();
+        for script in &scripts {
+            assert!(script.len() <= MAX_SCRIPT_SIZE);
+            assert!(script.as_bytes().starts_with(prefix.as_bytes()));
+
+            let envelopes = from_script(script);
+            assert_eq!(envelopes.len(), 1);
+            reassembled.extend(envelopes[0].concat());
+        }
+        assert_eq!(reassembled, payload);
+
+        // Leaves other than the last are filled to the limit
+        assert_eq!(scripts[0].len(), MAX_SCRIPT_SIZE);
+    }
+
+    #[test]
+    fn test_split_small_limit_and_separators() {
+        let builder = EnvelopeBuilder::new()
+            .max_script_size(8)
+            .field_separator(true);
+        let scripts = builder
+            .split(vec![vec![1, 2, 3, 4], vec![5]], Script::new())
+            .unwrap();
+
+        for script in &scripts {
+            assert!(script.len() <= 8);
+        }
+
+        let pushes = scripts
+            .iter()
+            .flat_map(|script| from_script(script).remove(0).pushes)
+            .collect::<Vec<_>>();
+        assert_eq!(pushes.concat(), vec![1, 2, 3, 4, 5]);
+        assert!(pushes.contains(&vec![]));
+
+        let err = EnvelopeBuilder::new()
+            .max_script_size(4)
+            .split(vec![vec![1]], Script::new())
+            .unwrap_err();
+        assert_eq!(err, EnvelopeError::ScriptTooLarge { size: 5, limit: 4 });
+    }
+
+    #[test]
+    fn test_iter_chunks() {
+        let envelope = Envelope::from(vec![vec![1, 2], vec![3]]);
+        assert_eq!(
+            envelope.iter_chunks().collect::<Vec<_>>(),
+            vec![[1, 2].as_slice(), [3].as_slice()]
+        );
+    }
+
+    #[test]
+    fn test_append_from_reader_matches_append_bytes() {
+        for len in [0, 1, 519, 520, 521, 100_000] {
+            let data = (0..len).map(|i| i as u8).collect::<Vec<_>>();
+
+            let streamed = append_from_reader(&mut io::Cursor::new(&data), Builder::new())
+                .unwrap()
+                .into_script();
+            let expected = append_bytes_to_builder(&data, Builder::new()).into_script();
+
+            assert_eq!(streamed, expected);
+        }
+    }
+
+    #[test]
+    fn test_append_from_reader_short_reads() {
+        // A reader that returns at most 7 bytes per call
+        struct Trickle<'a>(&'a [u8]);
+
+        impl io::Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let n = buf.len().min(7).min(self.0.len());
+                buf[..n].copy_from_slice(&self.0[..n]);
+                self.0 = &self.0[n..];
+                Ok(n)
+            }
+        }
+
+        let data = vec![0xab; 1_500];
+        let script = EnvelopeBuilder::new()
+            .chunk_size(100)
+            .append_from_reader(&mut Trickle(&data), Builder::new())
+            .unwrap()
+            .into_script();
+
+        let envelope = from_script(&script).remove(0);
+        assert_eq!(envelope.pushes.len(), 15);
+        assert!(envelope.iter_chunks().all(|chunk| chunk.len() == 100));
+        assert_eq!(envelope.concat(), data);
+    }
+
+    #[test]
+    fn test_script_may_contain_envelope() {
+        let script = append_to_builder(vec![b"ord".to_vec()], Builder::new()).into_script();
+        assert!(script_may_contain_envelope(&script));
+
+        let p2wpkh = ScriptBuf::from_hex("0014751e76e8199196d454941c45d1b3a323f1433bd6").unwrap();
+        assert!(!script_may_contain_envelope(&p2wpkh));
+        assert!(!script_may_contain_envelope(Script::new()));
+
+        // OP_FALSE and OP_IF separated by another opcode
+        let script = ScriptBuf::from_bytes(vec![0x00, 0x61, 0x63, 0x68]);
+        assert!(!script_may_contain_envelope(&script));
+        assert!(from_script(&script).is_empty());
+
+        // The pattern inside push data is a false positive, not a false negative
+        let script = Builder::new().push_slice([0x00, 0x63]).into_script();
+        assert!(script_may_contain_envelope(&script));
+        assert!(from_script(&script).is_empty());
+    }
+
+    #[test]
+    fn test_minimal_pushes() {
+        use opcodes::all::*;
+
+        assert!(is_minimal_push(&[], opcodes::OP_0));
+        assert!(is_minimal_push(&[5], OP_PUSHNUM_5));
+        assert!(!is_minimal_push(&[5], OP_PUSHBYTES_1));
+        assert!(is_minimal_push(&[0], OP_PUSHBYTES_1));
+        assert!(is_minimal_push(&[0x81], OP_PUSHNUM_NEG1));
+        assert!(is_minimal_push(&[7; 75], OP_PUSHBYTES_75));
+        assert!(!is_minimal_push(&[7; 75], OP_PUSHDATA1));
+        assert!(is_minimal_push(&[7; 76], OP_PUSHDATA1));
+        assert!(is_minimal_push(&[7; 256], OP_PUSHDATA2));
+        assert!(!is_minimal_push(&[7; 256], OP_PUSHDATA4));
+
+        // OP_FALSE OP_IF <"ord"> OP_PUSHDATA1 <"abc"> OP_ENDIF
+        let script = ScriptBuf::from_bytes(
+            [
+                &[0x00, 0x63, 0x03][..],
+                b"ord",
+                &[0x4c, 0x03],
+                b"abc",
+                &[0x68],
+            ]
+            .concat(),
+        );
+        let envelopes = from_script(&script);
+        assert_eq!(envelopes[0].pushes, vec![b"ord".to_vec(), b"abc".to_vec()]);
+        assert_eq!(envelopes[0].first_non_minimal_push(), Some(1));
+        assert_eq!(
+            from_script_minimal(&script),
+            Err(EnvelopeError::NonMinimalPush { start: 0, index: 1 })
+        );
+
+        let script = append_to_builder(vec![b"ord".to_vec(), vec![]], Builder::new()).into_script();
+        assert_eq!(from_script(&script)[0].first_non_minimal_push(), None);
+        assert_eq!(from_script_minimal(&script), from_script_strict(&script));
+
+        let script = EnvelopeBuilder::new()
+            .force_pushdata2(true)
+            .append_to_builder(vec![b"ord".to_vec()], Builder::new())
+            .into_script();
+        assert_eq!(from_script(&script)[0].first_non_minimal_push(), Some(0));
+    }
 }
@@ -0,0 +1,539 @@
+//! # Crate Error
+
+#[cfg(feature = "cbor")]
+use crate::cbor;
+#[cfg(feature = "msgpack")]
+use crate::msgpack;
+#[cfg(feature = "asset")]
+use crate::protocols::asset;
+#[cfg(feature = "did")]
+use crate::protocols::did;
+#[cfg(feature = "dlc")]
+use crate::protocols::dlc;
+#[cfg(feature = "inscriptions")]
+use crate::protocols::inscriptions;
+#[cfg(feature = "omni")]
+use crate::protocols::omni;
+#[cfg(feature = "ots")]
+use crate::protocols::ots;
+#[cfg(feature = "runes")]
+use crate::protocols::runes;
+#[cfg(feature = "serde")]
+use crate::schema;
+#[cfg(feature = "miniscript")]
+use crate::witness_script;
+use crate::{
+    EmbeddingIdError, annex, attestation, commitments, compress, descriptor, envelope, feebump,
+    files, funding, header_chain, media, merkle, message, offer, planner, policy, psbt, receipt,
+    resolver, reveal, template, varint,
+};
+
+use std::fmt;
+
+/// Errors that can occur anywhere in this crate
+///
+/// The I/O-backed errors ([`follower::FollowerError`], [`numbering::NumberingError`], `StoreError`
+/// and `ZmqError`) are not wrapped, so that this type stays `Clone` and `Eq`.
+///
+/// [`follower::FollowerError`]: crate::follower::FollowerError
+/// [`numbering::NumberingError`]: crate::numbering::NumberingError
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// Message encoding or decoding error
+    Message(message::Error),
+    /// LEB128 decoding error
+    VarInt(varint::Error),
+    /// Embedding ID parsing error
+    EmbeddingId(EmbeddingIdError),
+    /// Script parsing error
+    Script(bitcoin::script::Error),
+    /// Envelope parsing error
+    Envelope(envelope::EnvelopeError),
+    /// Embedding resolution error
+    Resolve(resolver::ResolveError),
+    /// Taproot annex parsing error
+    Annex(annex::AnnexError),
+    /// Attestation verification error
+    Attestation(attestation::AttestationError),
+    /// Fee bump error
+    Bump(feebump::BumpError),
+    #[cfg(feature = "cbor")]
+    /// CBOR decoding error
+    Cbor(cbor::CborError),
+    /// Commitment verification error
+    Commitment(commitments::CommitmentError),
+    /// Payload compression error
+    Compress(compress::CompressError),
+    /// Descriptor parsing error
+    Descriptor(descriptor::DescriptorError),
+    /// File chunking or reassembly error
+    File(files::FileError),
+    /// Funding error
+    Funding(funding::FundingError),
+    /// Header chain validation error
+    HeaderChain(header_chain::HeaderChainError),
+    /// Media type parsing error
+    MediaType(media::MediaTypeError),
+    /// PSBT merge error
+    Merge(psbt::MergeError),
+    /// Merkle proof error
+    Merkle(merkle::MerkleError),
+    #[cfg(feature = "msgpack")]
+    /// MessagePack decoding error
+    Msgpack(msgpack::MsgpackError),
+    /// Offer error
+    Offer(offer::OfferError),
+    /// OP_RETURN policy error
+    OpReturn(policy::OpReturnError),
+    /// Embedding planning error
+    Plan(planner::PlanError),
+    /// PSBT error
+    Psbt(psbt::PsbtError),
+    /// Receipt verification error
+    Receipt(receipt::ReceiptError),
+    /// Reveal transaction error
+    Reveal(reveal::RevealError),
+    #[cfg(feature = "serde")]
+    /// Schema validation error
+    Schema(schema::SchemaError),
+    /// Template error
+    Template(template::TemplateError),
+    #[cfg(feature = "miniscript")]
+    /// Witness script error
+    WitnessScript(witness_script::WitnessScriptError),
+    #[cfg(feature = "asset")]
+    /// Asset protocol decoding error
+    Asset(asset::AssetError),
+    #[cfg(feature = "did")]
+    /// DID protocol decoding error
+    Did(did::DidError),
+    #[cfg(feature = "dlc")]
+    /// DLC protocol decoding error
+    Dlc(dlc::DlcError),
+    #[cfg(feature = "inscriptions")]
+    /// Inscription ID parsing error
+    InscriptionId(inscriptions::InscriptionIdError),
+    #[cfg(feature = "omni")]
+    /// Omni Layer decoding error
+    Omni(omni::OmniError),
+    #[cfg(feature = "ots")]
+    /// OpenTimestamps decoding error
+    Ots(ots::OtsError),
+    #[cfg(feature = "runes")]
+    /// Runestone decoding error
+    Rune(runes::RuneError),
+}
+
+impl From<message::Error> for Error {
+    fn from(e: message::Error) -> Self {
+        Error::Message(e)
+    }
+}
+
+impl From<varint::Error> for Error {
+    fn from(e: varint::Error) -> Self {
+        Error::VarInt(e)
+    }
+}
+
+impl From<EmbeddingIdError> for Error {
+    fn from(e: EmbeddingIdError) -> Self {
+        Error::EmbeddingId(e)
+    }
+}
+
+impl From<bitcoin::script::Error> for Error {
+    fn from(e: bitcoin::script::Error) -> Self {
+        Error::Script(e)
+    }
+}
+
+impl From<envelope::EnvelopeError> for Error {
+    fn from(e: envelope::EnvelopeError) -> Self {
+        Error::Envelope(e)
+    }
+}
+
+impl From<resolver::ResolveError> for Error {
+    fn from(e: resolver::ResolveError) -> Self {
+        Error::Resolve(e)
+    }
+}
+
+impl From<annex::AnnexError> for Error {
+    fn from(e: annex::AnnexError) -> Self {
+        Error::Annex(e)
+    }
+}
+
+impl From<attestation::AttestationError> for Error {
+    fn from(e: attestation::AttestationError) -> Self {
+        Error::Attestation(e)
+    }
+}
+
+impl From<feebump::BumpError> for Error {
+    fn from(e: feebump::BumpError) -> Self {
+        Error::Bump(e)
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl From<cbor::CborError> for Error {
+    fn from(e: cbor::CborError) -> Self {
+        Error::Cbor(e)
+    }
+}
+
+impl From<commitments::CommitmentError> for Error {
+    fn from(e: commitments::CommitmentError) -> Self {
+        Error::Commitment(e)
+    }
+}
+
+impl From<compress::CompressError> for Error {
+    fn from(e: compress::CompressError) -> Self {
+        Error::Compress(e)
+    }
+}
+
+impl From<descriptor::DescriptorError> for Error {
+    fn from(e: descriptor::DescriptorError) -> Self {
+        Error::Descriptor(e)
+    }
+}
+
+impl From<files::FileError> for Error {
+    fn from(e: files::FileError) -> Self {
+        Error::File(e)
+    }
+}
+
+impl From<funding::FundingError> for Error {
+    fn from(e: funding::FundingError) -> Self {
+        Error::Funding(e)
+    }
+}
+
+impl From<header_chain::HeaderChainError> for Error {
+    fn from(e: header_chain::HeaderChainError) -> Self {
+        Error::HeaderChain(e)
+    }
+}
+
+impl From<media::MediaTypeError> for Error {
+    fn from(e: media::MediaTypeError) -> Self {
+        Error::MediaType(e)
+    }
+}
+
+impl From<psbt::MergeError> for Error {
+    fn from(e: psbt::MergeError) -> Self {
+        Error::Merge(e)
+    }
+}
+
+impl From<merkle::MerkleError> for Error {
+    fn from(e: merkle::MerkleError) -> Self {
+        Error::Merkle(e)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl From<msgpack::MsgpackError> for Error {
+    fn from(e: msgpack::MsgpackError) -> Self {
+        Error::Msgpack(e)
+    }
+}
+
+impl From<offer::OfferError> for Error {
+    fn from(e: offer::OfferError) -> Self {
+        Error::Offer(e)
+    }
+}
+
+impl From<policy::OpReturnError> for Error {
+    fn from(e: policy::OpReturnError) -> Self {
+        Error::OpReturn(e)
+    }
+}
+
+impl From<planner::PlanError> for Error {
+    fn from(e: planner::PlanError) -> Self {
+        Error::Plan(e)
+    }
+}
+
+impl From<psbt::PsbtError> for Error {
+    fn from(e: psbt::PsbtError) -> Self {
+        Error::Psbt(e)
+    }
+}
+
+impl From<receipt::ReceiptError> for Error {
+    fn from(e: receipt::ReceiptError) -> Self {
+        Error::Receipt(e)
+    }
+}
+
+impl From<reveal::RevealError> for Error {
+    fn from(e: reveal::RevealError) -> Self {
+        Error::Reveal(e)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<schema::SchemaError> for Error {
+    fn from(e: schema::SchemaError) -> Self {
+        Error::Schema(e)
+    }
+}
+
+impl From<template::TemplateError> for Error {
+    fn from(e: template::TemplateError) -> Self {
+        Error::Template(e)
+    }
+}
+
+#[cfg(feature = "miniscript")]
+impl From<witness_script::WitnessScriptError> for Error {
+    fn from(e: witness_script::WitnessScriptError) -> Self {
+        Error::WitnessScript(e)
+    }
+}
+
+#[cfg(feature = "asset")]
+impl From<asset::AssetError> for Error {
+    fn from(e: asset::AssetError) -> Self {
+        Error::Asset(e)
+    }
+}
+
+#[cfg(feature = "did")]
+impl From<did::DidError> for Error {
+    fn from(e: did::DidError) -> Self {
+        Error::Did(e)
+    }
+}
+
+#[cfg(feature = "dlc")]
+impl From<dlc::DlcError> for Error {
+    fn from(e: dlc::DlcError) -> Self {
+        Error::Dlc(e)
+    }
+}
+
+#[cfg(feature = "inscriptions")]
+impl From<inscriptions::InscriptionIdError> for Error {
+    fn from(e: inscriptions::InscriptionIdError) -> Self {
+        Error::InscriptionId(e)
+    }
+}
+
+#[cfg(feature = "omni")]
+impl From<omni::OmniError> for Error {
+    fn from(e: omni::OmniError) -> Self {
+        Error::Omni(e)
+    }
+}
+
+#[cfg(feature = "ots")]
+impl From<ots::OtsError> for Error {
+    fn from(e: ots::OtsError) -> Self {
+        Error::Ots(e)
+    }
+}
+
+#[cfg(feature = "runes")]
+impl From<runes::RuneError> for Error {
+    fn from(e: runes::RuneError) -> Self {
+        Error::Rune(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Message(e) => write!(f, "Message error: {e}"),
+            Error::VarInt(e) => write!(f, "VarInt error: {e}"),
+            Error::EmbeddingId(e) => write!(f, "Embedding ID error: {e}"),
+            Error::Script(e) => write!(f, "Script error: {e}"),
+            Error::Envelope(e) => write!(f, "Envelope error: {e}"),
+            Error::Resolve(e) => write!(f, "Resolve error: {e}"),
+            Error::Annex(e) => write!(f, "Annex error: {e}"),
+            Error::Attestation(e) => write!(f, "Attestation error: {e}"),
+            Error::Bump(e) => write!(f, "Fee bump error: {e}"),
+            #[cfg(feature = "cbor")]
+            Error::Cbor(e) => write!(f, "CBOR error: {e}"),
+            Error::Commitment(e) => write!(f, "Commitment error: {e}"),
+            Error::Compress(e) => write!(f, "Compression error: {e}"),
+            Error::Descriptor(e) => write!(f, "Descriptor error: {e}"),
+            Error::File(e) => write!(f, "File error: {e}"),
+            Error::Funding(e) => write!(f, "Funding error: {e}"),
+            Error::HeaderChain(e) => write!(f, "Header chain error: {e}"),
+            Error::MediaType(e) => write!(f, "Media type error: {e}"),
+            Error::Merge(e) => write!(f, "PSBT merge error: {e}"),
+            Error::Merkle(e) => write!(f, "Merkle error: {e}"),
+            #[cfg(feature = "msgpack")]
+            Error::Msgpack(e) => write!(f, "MessagePack error: {e}"),
+            Error::Offer(e) => write!(f, "Offer error: {e}"),
+            Error::OpReturn(e) => write!(f, "OP_RETURN policy error: {e}"),
+            Error::Plan(e) => write!(f, "Plan error: {e}"),
+            Error::Psbt(e) => write!(f, "PSBT error: {e}"),
+            Error::Receipt(e) => write!(f, "Receipt error: {e}"),
+            Error::Reveal(e) => write!(f, "Reveal error: {e}"),
+            #[cfg(feature = "serde")]
+            Error::Schema(e) => write!(f, "Schema error: {e}"),
+            Error::Template(e) => write!(f, "Template error: {e}"),
+            #[cfg(feature = "miniscript")]
+            Error::WitnessScript(e) => write!(f, "Witness script error: {e}"),
+            #[cfg(feature = "asset")]
+            Error::Asset(e) => write!(f, "Asset error: {e}"),
+            #[cfg(feature = "did")]
+            Error::Did(e) => write!(f, "DID error: {e}"),
+            #[cfg(feature = "dlc")]
+            Error::Dlc(e) => write!(f, "DLC error: {e}"),
+            #[cfg(feature = "inscriptions")]
+            Error::InscriptionId(e) => write!(f, "Inscription ID error: {e}"),
+            #[cfg(feature = "omni")]
+            Error::Omni(e) => write!(f, "Omni error: {e}"),
+            #[cfg(feature = "ots")]
+            Error::Ots(e) => write!(f, "OpenTimestamps error: {e}"),
+            #[cfg(feature = "runes")]
+            Error::Rune(e) => write!(f, "Rune error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Message(e) => Some(e),
+            Error::VarInt(e) => Some(e),
+            Error::EmbeddingId(e) => Some(e),
+            Error::Script(e) => Some(e),
+            Error::Envelope(e) => Some(e),
+            Error::Resolve(e) => Some(e),
+            Error::Annex(e) => Some(e),
+            Error::Attestation(e) => Some(e),
+            Error::Bump(e) => Some(e),
+            #[cfg(feature = "cbor")]
+            Error::Cbor(e) => Some(e),
+            Error::Commitment(e) => Some(e),
+            Error::Compress(e) => Some(e),
+            Error::Descriptor(e) => Some(e),
+            Error::File(e) => Some(e),
+            Error::Funding(e) => Some(e),
+            Error::HeaderChain(e) => Some(e),
+            Error::MediaType(e) => Some(e),
+            Error::Merge(e) => Some(e),
+            Error::Merkle(e) => Some(e),
+            #[cfg(feature = "msgpack")]
+            Error::Msgpack(e) => Some(e),
+            Error::Offer(e) => Some(e),
+            Error::OpReturn(e) => Some(e),
+            Error::Plan(e) => Some(e),
+            Error::Psbt(e) => Some(e),
+            Error::Receipt(e) => Some(e),
+            Error::Reveal(e) => Some(e),
+            #[cfg(feature = "serde")]
+            Error::Schema(e) => Some(e),
+            Error::Template(e) => Some(e),
+            #[cfg(feature = "miniscript")]
+            Error::WitnessScript(e) => Some(e),
+            #[cfg(feature = "asset")]
+            Error::Asset(e) => Some(e),
+            #[cfg(feature = "did")]
+            Error::Did(e) => Some(e),
+            #[cfg(feature = "dlc")]
+            Error::Dlc(e) => Some(e),
+            #[cfg(feature = "inscriptions")]
+            Error::InscriptionId(e) => Some(e),
+            #[cfg(feature = "omni")]
+            Error::Omni(e) => Some(e),
+            #[cfg(feature = "ots")]
+            Error::Ots(e) => Some(e),
+            #[cfg(feature = "runes")]
+            Error::Rune(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_from_conversions() {
+        let err: Error = message::Error::InvalidTag.into();
+        assert_eq!(err, Error::Message(message::Error::InvalidTag));
+
+        let err: Error = varint::Error::Overflow.into();
+        assert_eq!(err, Error::VarInt(varint::Error::Overflow));
+
+        let err: Error = EmbeddingIdError::InvalidTxid.into();
+        assert_eq!(err, Error::EmbeddingId(EmbeddingIdError::InvalidTxid));
+
+        let err: Error = bitcoin::script::Error::EarlyEndOfScript.into();
+        assert_eq!(err, Error::Script(bitcoin::script::Error::EarlyEndOfScript));
+
+        let err: Error = envelope::EnvelopeError::Unterminated { start: 0 }.into();
+        assert_eq!(
+            err,
+            Error::Envelope(envelope::EnvelopeError::Unterminated { start: 0 })
+        );
+
+        let err: Error = resolver::ResolveError::Backend("timeout".to_string()).into();
+        assert_eq!(
+            err,
+            Error::Resolve(resolver::ResolveError::Backend("timeout".to_string()))
+        );
+
+        let err: Error = annex::AnnexError::MissingPrefix.into();
+        assert_eq!(err, Error::Annex(annex::AnnexError::MissingPrefix));
+
+        let err: Error = psbt::MergeError::IncompatibleTransactions.into();
+        assert_eq!(
+            err,
+            Error::Merge(psbt::MergeError::IncompatibleTransactions)
+        );
+    }
+
+    #[test]
+    fn test_question_mark_propagation() {
+        fn parse(s: &str, bytes: &[u8]) -> Result<usize, Error> {
+            let id = crate::EmbeddingId::from_str(s)?;
+            let messages = message::Message::decode(bytes)?;
+            Ok(id.index + messages.len())
+        }
+
+        let txid = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+        assert_eq!(parse(&format!("{txid}:rt:2"), &[3]), Ok(3));
+        assert_eq!(
+            parse("invalid", &[3]),
+            Err(Error::EmbeddingId(EmbeddingIdError::InvalidFormat))
+        );
+        assert_eq!(
+            parse(&format!("{txid}:rt:2"), &[0xFF]),
+            Err(Error::Message(message::Error::InvalidVarInt))
+        );
+    }
+
+    #[test]
+    fn test_source_and_display() {
+        let err = Error::Message(message::Error::MissingBytes);
+        assert_eq!(
+            err.to_string(),
+            "Message error: Variable-length encoding indicates bytes are missing"
+        );
+        assert_eq!(
+            err.source().unwrap().to_string(),
+            message::Error::MissingBytes.to_string()
+        );
+
+        let err = Error::EmbeddingId(EmbeddingIdError::InvalidIndex);
+        assert_eq!(err.source().unwrap().to_string(), "Invalid index");
+    }
+}
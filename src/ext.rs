@@ -0,0 +1,198 @@
+//! # Extension Traits
+//!
+//! Methods on `rust-bitcoin` types that call into this crate's extractors. The witness and script
+//! traits expose the same parsers to code that has no transaction, such as a signer inspecting one
+//! input.
+
+use crate::annex::DataTags;
+use crate::envelope::{self, Envelope};
+use crate::report::{self, FootprintReport};
+use crate::{Embedding, EmbeddingType, ScriptType};
+
+use bitcoin::{Script, Transaction, Witness};
+
+/// Embedding methods on [`Transaction`]
+pub trait TransactionEmbedExt {
+    /// Returns the embeddings in the transaction, as in [`Embedding::from_transaction`]
+    fn embeddings(&self) -> Vec<Embedding>;
+
+    /// Returns the embeddings in the transaction lazily, in the same order as
+    /// [`TransactionEmbedExt::embeddings`]
+    fn embeddings_iter(&self) -> impl Iterator<Item = Embedding> + '_;
+
+    /// Returns true if the transaction carries an embedding of type `embedding_type`. Bare
+    /// multisig outputs are matched as in [`Embedding::from_bare_multisig`].
+    fn has_embedding_type(&self, embedding_type: EmbeddingType) -> bool;
+
+    /// Returns the data footprint of the transaction, as in [`report::data_footprint`]
+    fn data_footprint(&self) -> FootprintReport;
+}
+
+/// Embedding methods on [`Witness`]
+pub trait WitnessEmbedExt {
+    /// Returns the data in the witness's taproot annex, if it is a data-carrying annex
+    fn annex_data(&self) -> Option<&[u8]>;
+
+    /// Returns the payloads in the witness's taproot annex, as in [`crate::annex::payloads`]
+    fn annex_payloads(&self) -> Vec<&[u8]>;
+
+    /// Returns the script that may carry envelopes and its type, classified from the witness
+    /// alone: the tapscript leaf of a script-path spend, or else the witness script of a P2WSH
+    /// spend
+    fn envelope_script(&self) -> Option<(&Script, ScriptType)>;
+}
+
+/// Embedding methods on [`Script`]
+pub trait ScriptEmbedExt {
+    /// Returns the envelopes in the script, as in [`envelope::from_script`]
+    fn envelopes(&self) -> Vec<Envelope>;
+}
+
+impl TransactionEmbedExt for Transaction {
+    fn embeddings(&self) -> Vec<Embedding> {
+        Embedding::from_transaction(self)
+    }
+
+    fn embeddings_iter(&self) -> impl Iterator<Item = Embedding> + '_ {
+        let txid = self.compute_txid();
+
+        let outputs = self
+            .output
+            .iter()
+            .enumerate()
+            .filter_map(move |(output, txout)| Embedding::from_output(txid, output, txout));
+        let envelopes = self
+            .input
+            .iter()
+            .enumerate()
+            .flat_map(move |(input, txin)| {
+                Embedding::from_witness_envelopes(txid, input, txin, None)
+            });
+        let annexes = self
+            .input
+            .iter()
+            .enumerate()
+            .flat_map(move |(input, txin)| {
+                Embedding::from_annex(txid, input, txin, None, &DataTags::DEFAULT)
+            });
+
+        outputs.chain(envelopes).chain(annexes)
+    }
+
+    fn has_embedding_type(&self, embedding_type: EmbeddingType) -> bool {
+        match embedding_type {
+            EmbeddingType::BareMultisig => !Embedding::from_bare_multisig(self).is_empty(),
+            _ => self
+                .embeddings_iter()
+                .any(|embedding| embedding.to_type() == embedding_type),
+        }
+    }
+
+    fn data_footprint(&self) -> FootprintReport {
+        report::data_footprint(self)
+    }
+}
+
+impl WitnessEmbedExt for Witness {
+    fn annex_data(&self) -> Option<&[u8]> {
+        Embedding::annex_data(self)
+    }
+
+    fn annex_payloads(&self) -> Vec<&[u8]> {
+        Embedding::annex_payloads(self, &DataTags::DEFAULT)
+            .into_iter()
+            .map(|(_, payload)| payload.value)
+            .collect()
+    }
+
+    fn envelope_script(&self) -> Option<(&Script, ScriptType)> {
+        Embedding::envelope_script(self, None)
+    }
+}
+
+impl ScriptEmbedExt for Script {
+    fn envelopes(&self) -> Vec<Envelope> {
+        envelope::from_script(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::envelope::EnvelopeBuilder;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::script::Builder;
+    use bitcoin::{
+        Amount, OutPoint, ScriptBuf, Sequence, TxIn, TxOut, Txid, Witness, absolute::LockTime,
+        transaction::Version,
+    };
+
+    fn transaction() -> Transaction {
+        let tapscript = EnvelopeBuilder::new()
+            .append_to_builder(vec![b"ord".to_vec()], Builder::new())
+            .into_script();
+        let control_block = [vec![0xc0], vec![1; 32]].concat();
+
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::from_slice(&[
+                    tapscript.to_bytes(),
+                    control_block,
+                    vec![0x50, 0, 7],
+                ]),
+            }],
+            output: vec![TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::new_op_return([1, 2]),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_transaction_ext() {
+        let tx = transaction();
+        assert_eq!(tx.embeddings(), Embedding::from_transaction(&tx));
+        assert_eq!(tx.embeddings_iter().collect::<Vec<_>>(), tx.embeddings());
+        assert_eq!(tx.embeddings().len(), 3);
+
+        assert!(tx.has_embedding_type(EmbeddingType::OpReturn));
+        assert!(tx.has_embedding_type(EmbeddingType::TaprootAnnex));
+        assert!(tx.has_embedding_type(EmbeddingType::WitnessEnvelope(ScriptType::Tapscript)));
+        assert!(!tx.has_embedding_type(EmbeddingType::WitnessEnvelope(ScriptType::Legacy)));
+        assert!(!tx.has_embedding_type(EmbeddingType::BareMultisig));
+
+        assert_eq!(tx.data_footprint(), report::data_footprint(&tx));
+        assert_eq!(tx.data_footprint().count(), 3);
+    }
+
+    #[test]
+    fn test_witness_and_script_ext() {
+        let tx = transaction();
+        let witness = &tx.input[0].witness;
+        assert_eq!(witness.annex_data(), Some([7].as_slice()));
+
+        let (script, script_type) = witness.envelope_script().unwrap();
+        assert_eq!(script_type, ScriptType::Tapscript);
+        let envelopes = script.envelopes();
+        assert_eq!(envelopes.len(), 1);
+        assert_eq!(envelopes[0], vec![b"ord".to_vec()]);
+
+        // A P2WSH spend: an element followed by the witness script
+        let witness_script = ScriptBuf::from_bytes(script.to_bytes());
+        let p2wsh = Witness::from_slice(&[vec![1], witness_script.to_bytes()]);
+        assert_eq!(p2wsh.annex_data(), None);
+        assert_eq!(
+            p2wsh.envelope_script(),
+            Some((witness_script.as_script(), ScriptType::Legacy))
+        );
+        assert_eq!(witness_script.envelopes().len(), 1);
+
+        assert_eq!(Witness::new().envelope_script(), None);
+    }
+}
@@ -0,0 +1,327 @@
+//! # Reusable Extraction
+//!
+//! An [`Extractor`] extracts the same embeddings as [`Embedding::from_transaction`], but parses
+//! envelopes into scratch buffers it keeps between transactions and appends to a caller-owned
+//! vector, so a full-chain scan allocates only the bytes and push sizes of each embedding it
+//! returns. [`Extractor::visit`] goes further and lends each embedding to a callback, which
+//! can stop early, without copying anything.
+//!
+//! ```no_run
+//! # use bitcoin::Block;
+//! # use bitcoin_embed::extractor::Extractor;
+//! # fn blocks() -> Vec<Block> { Vec::new() }
+//! let mut extractor = Extractor::new();
+//! let mut embeddings = Vec::new();
+//! for block in blocks() {
+//!     for tx in &block.txdata {
+//!         embeddings.clear();
+//!         extractor.extract_into(tx, &mut embeddings);
+//!     }
+//! }
+//! ```
+
+use crate::annex::DataTags;
+use crate::envelope::{self, EnvelopeSink, OpcodePolicy};
+use crate::{Embedding, EmbeddingId, EmbeddingRef, EmbeddingType};
+
+use bitcoin::{Opcode, Transaction, TxOut};
+use std::ops::ControlFlow;
+
+/// An embedding extractor with reusable scratch buffers
+#[derive(Debug, Clone, Default)]
+pub struct Extractor {
+    scratch: Scratch,
+    annex_tags: DataTags,
+}
+
+/// The concatenated pushes and push sizes of the envelope being parsed
+#[derive(Debug, Clone, Default)]
+struct Scratch {
+    bytes: Vec<u8>,
+    pushes: Vec<usize>,
+}
+
+impl Extractor {
+    /// Returns an extractor with empty buffers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the extractor reading annex data records with any tag in `tags`, instead of only
+    /// [`crate::TAPROOT_ANNEX_DATA_TAG`]. Each annex embedding's location records its tag.
+    pub fn with_annex_tags(mut self, tags: DataTags) -> Self {
+        self.annex_tags = tags;
+        self
+    }
+
+    /// Appends the embeddings in `tx` to `embeddings`, in the order of
+    /// [`Embedding::from_transaction`]
+    pub fn extract_into(&mut self, tx: &Transaction, embeddings: &mut Vec<Embedding>) {
+        self.extract_with_prevouts_into(tx, &[], embeddings);
+    }
+
+    /// Appends the embeddings in `tx` to `embeddings`, classifying witness data with
+    /// `prevouts` as in [`Embedding::from_transaction_with_prevouts`]
+    pub fn extract_with_prevouts_into(
+        &mut self,
+        tx: &Transaction,
+        prevouts: &[TxOut],
+        embeddings: &mut Vec<Embedding>,
+    ) {
+        let _: ControlFlow<()> = self.visit(tx, prevouts, |embedding| {
+            embeddings.push(embedding.to_embedding());
+            ControlFlow::Continue(())
+        });
+    }
+
+    /// Calls `f` with each embedding in `tx`, as in [`Embedding::visit`], classifying witness
+    /// data with `prevouts` as in [`Embedding::from_transaction_with_prevouts`]
+    pub fn visit<B>(
+        &mut self,
+        tx: &Transaction,
+        prevouts: &[TxOut],
+        mut f: impl FnMut(EmbeddingRef<'_>) -> ControlFlow<B>,
+    ) -> ControlFlow<B> {
+        let txid = tx.compute_txid();
+        let id = |embedding_type, index, sub_index| EmbeddingId {
+            txid,
+            embedding_type,
+            index,
+            sub_index,
+            _private: false,
+        };
+
+        // OP_RETURN
+        for (output, txout) in tx.output.iter().enumerate() {
+            if txout.script_pubkey.is_op_return() {
+                f(EmbeddingRef {
+                    id: id(EmbeddingType::OpReturn, output, None),
+                    bytes: &txout.script_pubkey.as_bytes()[1..],
+                    pushes: &[],
+                    tag: 0,
+                })?;
+            }
+        }
+
+        // Witness Envelope
+        for (input, txin) in tx.input.iter().enumerate() {
+            let Some((script, script_type)) =
+                Embedding::envelope_script(&txin.witness, prevouts.get(input))
+            else {
+                continue;
+            };
+
+            let embedding_type = EmbeddingType::WitnessEnvelope(script_type);
+            let mut index = 0;
+            envelope::scan(
+                script,
+                &OpcodePolicy::Reject,
+                &mut self.scratch,
+                |scratch, _| {
+                    index += 1;
+                    f(EmbeddingRef {
+                        id: id(embedding_type, input, Some(index - 1)),
+                        bytes: &scratch.bytes,
+                        pushes: &scratch.pushes,
+                        tag: 0,
+                    })
+                },
+            )?;
+        }
+
+        // Annex
+        for (input, txin) in tx.input.iter().enumerate() {
+            if prevouts
+                .get(input)
+                .is_some_and(|prevout| !prevout.script_pubkey.is_p2tr())
+            {
+                continue;
+            }
+
+            for (index, payload) in Embedding::annex_payloads(&txin.witness, &self.annex_tags) {
+                f(EmbeddingRef {
+                    id: id(
+                        EmbeddingType::TaprootAnnex,
+                        input,
+                        crate::annex_sub_index(index),
+                    ),
+                    bytes: payload.value,
+                    pushes: &[],
+                    tag: payload.tag,
+                })?;
+            }
+        }
+
+        ControlFlow::Continue(())
+    }
+}
+
+impl EnvelopeSink for Scratch {
+    fn clear(&mut self) {
+        self.bytes.clear();
+        self.pushes.clear();
+    }
+
+    fn push(&mut self, data: &[u8], _opcode: Opcode) {
+        self.bytes.extend_from_slice(data);
+        self.pushes.push(data.len());
+    }
+
+    fn tolerate(&mut self, _position: usize, _opcode: Opcode) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::envelope::EnvelopeBuilder;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::script::Builder;
+    use bitcoin::{
+        Amount, OutPoint, ScriptBuf, Sequence, TxIn, Txid, Witness, absolute::LockTime,
+        transaction::Version,
+    };
+    use std::str::FromStr;
+
+    fn transaction(seed: u8) -> Transaction {
+        let mut builder = Builder::new();
+        for i in 0..3 {
+            builder = EnvelopeBuilder::new()
+                .append_to_builder(vec![b"ord".to_vec(), vec![seed; 10 * i], vec![1]], builder);
+        }
+        let tapscript = builder.into_script();
+        let control_block = [vec![0xc0], vec![1; 32]].concat();
+        let witness_script = EnvelopeBuilder::new()
+            .append_to_builder(vec![vec![seed; 600]], Builder::new())
+            .into_script();
+
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![
+                TxIn {
+                    previous_output: OutPoint::new(Txid::from_byte_array([seed; 32]), 0),
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::from_slice(&[
+                        tapscript.to_bytes(),
+                        control_block,
+                        vec![0x50, 0, seed],
+                    ]),
+                },
+                TxIn {
+                    previous_output: OutPoint::new(Txid::from_byte_array([seed; 32]), 1),
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::from_slice(&[vec![], witness_script.to_bytes()]),
+                },
+            ],
+            output: vec![TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::new_op_return([seed, 2]),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_extract_into() {
+        let mut extractor = Extractor::new();
+        let mut embeddings = Vec::new();
+
+        for seed in 1..4 {
+            let tx = transaction(seed);
+            embeddings.clear();
+            extractor.extract_into(&tx, &mut embeddings);
+            assert_eq!(embeddings, Embedding::from_transaction(&tx));
+            assert_eq!(embeddings.len(), 6);
+        }
+
+        // Appends rather than replaces
+        let tx = transaction(5);
+        extractor.extract_into(&tx, &mut embeddings);
+        assert_eq!(embeddings.len(), 12);
+        assert_eq!(embeddings[6..], Embedding::from_transaction(&tx));
+    }
+
+    #[test]
+    fn test_visit() {
+        let tx = transaction(1);
+        let all = Embedding::from_transaction(&tx);
+
+        let mut visited = Vec::new();
+        let flow: ControlFlow<()> = Embedding::visit(&tx, |embedding| {
+            assert_eq!(embedding.to_type(), embedding.id.embedding_type);
+            visited.push(embedding.to_embedding());
+            ControlFlow::Continue(())
+        });
+        assert_eq!(flow, ControlFlow::Continue(()));
+        assert_eq!(visited, all);
+
+        // Stops at the second envelope, whose pushes are 3, 10, and 1 bytes
+        let mut count = 0;
+        let flow = Embedding::visit(&tx, |embedding| {
+            count += 1;
+            match embedding.pushes {
+                [3, 10, 1] => ControlFlow::Break(embedding.id),
+                _ => ControlFlow::Continue(()),
+            }
+        });
+        assert_eq!(flow, ControlFlow::Break(all[2].id()));
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn test_annex_tags() {
+        let mut tx = transaction(1);
+        let mut elements = tx.input[0].witness.to_vec();
+        *elements.last_mut().unwrap() = b"\x50\x07\x01x\x42other".to_vec();
+        tx.input[0].witness = Witness::from_slice(&elements);
+        assert_eq!(Embedding::from_transaction(&tx).len(), 5);
+
+        let mut embeddings = Vec::new();
+        Extractor::new()
+            .with_annex_tags(DataTags::DEFAULT.with(0x42))
+            .extract_into(&tx, &mut embeddings);
+        let annex = embeddings.last().unwrap();
+        assert_eq!(annex.bytes, b"other");
+        assert_eq!(
+            annex.location,
+            crate::EmbeddingLocation::TaprootAnnex {
+                input: 0,
+                index: 1,
+                tag: 0x42
+            }
+        );
+        assert_eq!(
+            Embedding::extract_at(&tx, &annex.location).as_ref(),
+            Some(annex)
+        );
+
+        // The id locates the payload without the tags it was extracted with
+        let id = EmbeddingId::from_str(&annex.id().to_string()).unwrap();
+        assert_eq!(id.sub_index, Some(1));
+        assert_eq!(id.locate(&tx).as_ref(), Some(annex));
+    }
+
+    #[test]
+    fn test_extract_with_prevouts_into() {
+        let tx = transaction(1);
+        let prevouts = vec![
+            TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::new_p2wsh(&bitcoin::WScriptHash::all_zeros()),
+            },
+            TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::new_p2wsh(&bitcoin::WScriptHash::all_zeros()),
+            },
+        ];
+
+        let mut embeddings = Vec::new();
+        Extractor::new().extract_with_prevouts_into(&tx, &prevouts, &mut embeddings);
+        assert_eq!(
+            embeddings,
+            Embedding::from_transaction_with_prevouts(&tx, &prevouts)
+        );
+    }
+}
@@ -0,0 +1,349 @@
+//! # Fee Bumping
+//!
+//! Rebuilds a stuck embedding transaction at a higher feerate without moving its embeddings.
+//! A replacement (RBF) takes the extra fee from a change output and leaves every other output,
+//! every input, and every witness as it was, so `OP_RETURN` outputs keep their order and
+//! envelopes and annexes stay on the same inputs. Only the change value moves, so only
+//! signatures committing to the change output need to be redone. A child (CPFP) instead spends
+//! one of the stuck transaction's outputs with enough fee to lift the pair to the target
+//! feerate, leaving the parent untouched.
+
+use crate::{Embedding, EmbeddingLocation};
+
+use bitcoin::transaction::{InputWeightPrediction, Version, predict_weight};
+use bitcoin::{
+    Amount, FeeRate, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness,
+    absolute::LockTime,
+};
+use std::fmt;
+
+/// The minimum feerate by which a replacement must increase the fee, per BIP-125
+pub const INCREMENTAL_RELAY_FEE: FeeRate = FeeRate::BROADCAST_MIN;
+
+/// Error types for bumping fees
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BumpError {
+    /// The number of prevouts does not match the number of inputs
+    MissingPrevouts,
+    /// The outputs are worth more than the prevouts
+    NegativeFee,
+    /// The output does not exist or carries an embedding
+    InvalidOutput(usize),
+    /// The output cannot pay the fee and stay above the dust limit
+    InsufficientFunds {
+        /// The fee the output must pay
+        required: Amount,
+        /// The value of the output
+        available: Amount,
+    },
+    /// The fee at the requested feerate is out of range
+    FeeOverflow,
+}
+
+/// Returns the fee paid by `tx`, given the outputs spent by each input
+pub fn fee(tx: &Transaction, prevouts: &[TxOut]) -> Result<Amount, BumpError> {
+    if prevouts.len() != tx.input.len() {
+        return Err(BumpError::MissingPrevouts);
+    }
+
+    let input: Amount = prevouts.iter().map(|prevout| prevout.value).sum();
+    let output: Amount = tx.output.iter().map(|txout| txout.value).sum();
+    input.checked_sub(output).ok_or(BumpError::NegativeFee)
+}
+
+/// Returns a replacement for `tx` paying at least `fee_rate`, with the extra fee taken from
+/// the output at `change`. The replacement always pays at least the incremental relay fee more
+/// than `tx`, as BIP-125 requires. Sequences are left as they are; the replacement relies on
+/// `tx` signaling replaceability or on full-RBF relay.
+pub fn replace_by_fee(
+    tx: &Transaction,
+    prevouts: &[TxOut],
+    change: usize,
+    fee_rate: FeeRate,
+) -> Result<Transaction, BumpError> {
+    spendable_output(tx, change)?;
+    let fee = fee(tx, prevouts)?;
+
+    let vsize = tx.weight().to_vbytes_ceil();
+    let minimum = fee
+        .checked_add(fee_for(INCREMENTAL_RELAY_FEE, vsize)?)
+        .ok_or(BumpError::FeeOverflow)?;
+    let required = fee_for(fee_rate, vsize)?.max(minimum);
+
+    let mut replacement = tx.clone();
+    let output = &mut replacement.output[change];
+    output.value = remaining(output, required - fee)?;
+    Ok(replacement)
+}
+
+/// Returns an unsigned child spending the output of `parent` at `output` to `script_pubkey`,
+/// paying enough that the parent and child together reach `fee_rate`. `input` predicts the
+/// size of the witness or script sig that will spend the output.
+pub fn child_pays_for_parent(
+    parent: &Transaction,
+    prevouts: &[TxOut],
+    output: usize,
+    input: InputWeightPrediction,
+    script_pubkey: ScriptBuf,
+    fee_rate: FeeRate,
+) -> Result<Transaction, BumpError> {
+    let value = spendable_output(parent, output)?.value;
+    let parent_fee = fee(parent, prevouts)?;
+
+    let child_weight = predict_weight([input], [script_pubkey.len()]);
+    let package_vsize = (parent.weight() + child_weight).to_vbytes_ceil();
+    let child_fee = fee_for(fee_rate, package_vsize)?
+        .checked_sub(parent_fee)
+        .unwrap_or(Amount::ZERO)
+        .max(fee_for(
+            INCREMENTAL_RELAY_FEE,
+            child_weight.to_vbytes_ceil(),
+        )?);
+
+    let mut txout = TxOut {
+        value,
+        script_pubkey,
+    };
+    txout.value = remaining(&txout, child_fee)?;
+
+    Ok(Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(parent.compute_txid(), output as u32),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        }],
+        output: vec![txout],
+    })
+}
+
+/// Returns the output at `index`, if it exists and does not carry an embedding
+fn spendable_output(tx: &Transaction, index: usize) -> Result<&TxOut, BumpError> {
+    let output = tx
+        .output
+        .get(index)
+        .ok_or(BumpError::InvalidOutput(index))?;
+    let multisig = Embedding::from_bare_multisig(tx)
+        .iter()
+        .any(|embedding| embedding.location == EmbeddingLocation::BareMultisig { output: index });
+    if output.script_pubkey.is_op_return() || multisig {
+        return Err(BumpError::InvalidOutput(index));
+    }
+    Ok(output)
+}
+
+/// Returns the value left in `output` after paying `fee`, if it stays above the dust limit
+fn remaining(output: &TxOut, fee: Amount) -> Result<Amount, BumpError> {
+    output
+        .value
+        .checked_sub(fee)
+        .filter(|value| *value >= output.script_pubkey.minimal_non_dust())
+        .ok_or(BumpError::InsufficientFunds {
+            required: fee,
+            available: output.value,
+        })
+}
+
+/// Returns the fee at `fee_rate` for `vsize` virtual bytes
+fn fee_for(fee_rate: FeeRate, vsize: u64) -> Result<Amount, BumpError> {
+    fee_rate.fee_vb(vsize).ok_or(BumpError::FeeOverflow)
+}
+
+impl fmt::Display for BumpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BumpError::MissingPrevouts => write!(f, "Prevouts do not match inputs"),
+            BumpError::NegativeFee => write!(f, "Outputs exceed inputs"),
+            BumpError::InvalidOutput(index) => write!(f, "Output {index} cannot pay the fee"),
+            BumpError::InsufficientFunds {
+                required,
+                available,
+            } => write!(
+                f,
+                "Output of {available} cannot pay a fee of {required} above the dust limit"
+            ),
+            BumpError::FeeOverflow => write!(f, "Fee is out of range"),
+        }
+    }
+}
+
+impl std::error::Error for BumpError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::Txid;
+    use bitcoin::hashes::Hash;
+    use bitcoin::key::{Keypair, Secp256k1, TweakedPublicKey};
+    use bitcoin::secp256k1::SecretKey;
+
+    fn p2tr() -> ScriptBuf {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let (key, _) = keypair.x_only_public_key();
+        ScriptBuf::new_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(key))
+    }
+
+    /// An annex-carrying input with an `OP_RETURN` output before the change output
+    fn transaction() -> (Transaction, Vec<TxOut>) {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::from_slice(&[vec![2; 64], [vec![0x50, 0], vec![3; 40]].concat()]),
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey: ScriptBuf::new_op_return([4; 20]),
+                },
+                TxOut {
+                    value: Amount::from_sat(99_000),
+                    script_pubkey: p2tr(),
+                },
+            ],
+        };
+        let prevouts = vec![TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: p2tr(),
+        }];
+        (tx, prevouts)
+    }
+
+    fn locations(tx: &Transaction, prevouts: &[TxOut]) -> Vec<(Vec<u8>, EmbeddingLocation)> {
+        Embedding::from_transaction_with_prevouts(tx, prevouts)
+            .into_iter()
+            .map(|embedding| (embedding.bytes, embedding.location))
+            .collect()
+    }
+
+    #[test]
+    fn test_replace_by_fee() {
+        let (tx, prevouts) = transaction();
+        assert_eq!(fee(&tx, &prevouts), Ok(Amount::from_sat(1_000)));
+
+        let fee_rate = FeeRate::from_sat_per_vb(50).unwrap();
+        let replacement = replace_by_fee(&tx, &prevouts, 1, fee_rate).unwrap();
+        let vsize = tx.weight().to_vbytes_ceil();
+        assert_eq!(replacement.weight(), tx.weight());
+        assert_eq!(
+            fee(&replacement, &prevouts),
+            Ok(Amount::from_sat(50 * vsize))
+        );
+
+        // Only the change value moves
+        assert_eq!(replacement.input, tx.input);
+        assert_eq!(replacement.output[0], tx.output[0]);
+        assert_eq!(
+            locations(&replacement, &prevouts),
+            locations(&tx, &prevouts)
+        );
+        assert_eq!(locations(&tx, &prevouts).len(), 2);
+
+        // A lower feerate still pays the incremental relay fee
+        let replacement =
+            replace_by_fee(&tx, &prevouts, 1, FeeRate::from_sat_per_vb(1).unwrap()).unwrap();
+        assert_eq!(
+            fee(&replacement, &prevouts),
+            Ok(Amount::from_sat(1_000 + vsize))
+        );
+    }
+
+    #[test]
+    fn test_replace_by_fee_errors() {
+        let (tx, prevouts) = transaction();
+        let fee_rate = FeeRate::from_sat_per_vb(10).unwrap();
+
+        assert_eq!(
+            replace_by_fee(&tx, &prevouts, 0, fee_rate),
+            Err(BumpError::InvalidOutput(0))
+        );
+        assert_eq!(
+            replace_by_fee(&tx, &prevouts, 2, fee_rate),
+            Err(BumpError::InvalidOutput(2))
+        );
+        assert_eq!(
+            replace_by_fee(&tx, &[], 1, fee_rate),
+            Err(BumpError::MissingPrevouts)
+        );
+        assert!(matches!(
+            replace_by_fee(&tx, &prevouts, 1, FeeRate::from_sat_per_vb(2_000).unwrap()),
+            Err(BumpError::InsufficientFunds { .. })
+        ));
+        assert_eq!(
+            replace_by_fee(&tx, &prevouts, 1, FeeRate::MAX),
+            Err(BumpError::FeeOverflow)
+        );
+
+        let mut prevouts = prevouts;
+        prevouts[0].value = Amount::from_sat(1_000);
+        assert_eq!(
+            replace_by_fee(&tx, &prevouts, 1, fee_rate),
+            Err(BumpError::NegativeFee)
+        );
+    }
+
+    #[test]
+    fn test_child_pays_for_parent() {
+        let (parent, prevouts) = transaction();
+        let fee_rate = FeeRate::from_sat_per_vb(20).unwrap();
+
+        let child = child_pays_for_parent(
+            &parent,
+            &prevouts,
+            1,
+            InputWeightPrediction::P2TR_KEY_DEFAULT_SIGHASH,
+            p2tr(),
+            fee_rate,
+        )
+        .unwrap();
+        assert_eq!(
+            child.input[0].previous_output,
+            OutPoint::new(parent.compute_txid(), 1)
+        );
+
+        let child_weight = predict_weight(
+            [InputWeightPrediction::P2TR_KEY_DEFAULT_SIGHASH],
+            [p2tr().len()],
+        );
+        let package_vsize = (parent.weight() + child_weight).to_vbytes_ceil();
+        let child_fee = parent.output[1].value - child.output[0].value;
+        assert_eq!(
+            child_fee + Amount::from_sat(1_000),
+            Amount::from_sat(20 * package_vsize)
+        );
+
+        // The child pays at least the minimum relay fee on its own
+        let child = child_pays_for_parent(
+            &parent,
+            &prevouts,
+            1,
+            InputWeightPrediction::P2TR_KEY_DEFAULT_SIGHASH,
+            p2tr(),
+            FeeRate::from_sat_per_vb(1).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            parent.output[1].value - child.output[0].value,
+            Amount::from_sat(child_weight.to_vbytes_ceil())
+        );
+
+        assert_eq!(
+            child_pays_for_parent(
+                &parent,
+                &prevouts,
+                0,
+                InputWeightPrediction::P2TR_KEY_DEFAULT_SIGHASH,
+                p2tr(),
+                fee_rate,
+            ),
+            Err(BumpError::InvalidOutput(0))
+        );
+    }
+}
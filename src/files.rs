@@ -0,0 +1,352 @@
+//! # Embedded Files
+//!
+//! Files too large for one envelope are split across envelope scripts with
+//! [`EnvelopeBuilder::split`], one per input or transaction, and described by a
+//! [`FileManifest`] embedded once the chunks confirm. The manifest lists the chunk embeddings
+//! in order, together with the file's name, content type, size, and content hash, so readers
+//! can reassemble and check the file.
+
+use crate::envelope::{EnvelopeBuilder, EnvelopeError};
+use crate::media::MediaType;
+use crate::message::{Message, Tag, tagged_hash, tags};
+use crate::resolver::{ResolveError, Resolver};
+use crate::{CONTENT_HASH_TAG, Embedding, EmbeddingId, varint};
+
+use bitcoin::{Script, ScriptBuf};
+use std::fmt;
+
+/// A description of a file embedded in chunks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileManifest {
+    /// The file name
+    pub name: String,
+    /// The MIME type (e.g. `image/png`), validated by [`MediaType::parse`] when decoded
+    pub content_type: String,
+    /// The size of the file in bytes
+    pub size: u64,
+    /// The tagged SHA-256 of the file, as computed by [`Embedding::content_hash`]
+    pub content_hash: [u8; 32],
+    /// The embeddings holding the file, in order
+    pub chunks: Vec<EmbeddingId>,
+}
+
+/// Error types for manifests and reassembly
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileError {
+    /// A required manifest field is missing
+    MissingField(Tag),
+    /// A manifest field is repeated or malformed
+    InvalidField(Tag),
+    /// The chunk at this position is not the embedding listed in the manifest
+    ChunkMismatch(usize),
+    /// The reassembled file does not have the manifest's size
+    SizeMismatch,
+    /// The reassembled file does not have the manifest's content hash
+    HashMismatch,
+    /// A chunk could not be resolved
+    Resolve(ResolveError),
+}
+
+/// Splits a file across as many `<prefix> OP_FALSE OP_IF ... OP_ENDIF` scripts as `builder`
+/// requires. Each script should be revealed as a separate input, and the ids of the
+/// resulting envelopes listed in the manifest in order.
+pub fn split(
+    bytes: &[u8],
+    builder: &EnvelopeBuilder,
+    prefix: &Script,
+) -> Result<Vec<ScriptBuf>, EnvelopeError> {
+    builder.split(vec![bytes.to_vec()], prefix)
+}
+
+impl FileManifest {
+    /// Returns a manifest for `bytes` stored in `chunks`
+    pub fn new(
+        name: impl Into<String>,
+        content_type: impl Into<String>,
+        bytes: &[u8],
+        chunks: Vec<EmbeddingId>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            content_type: content_type.into(),
+            size: bytes.len() as u64,
+            content_hash: tagged_hash(CONTENT_HASH_TAG, bytes),
+            chunks,
+        }
+    }
+
+    /// Returns the manifest as messages: the name, content type, size, content hash, then one
+    /// message per chunk
+    pub fn to_messages(&self) -> Vec<Message> {
+        let mut messages = vec![
+            Message::new(tags::FILE_NAME, self.name.as_bytes().to_vec()),
+            Message::new(tags::CONTENT_TYPE, self.content_type.as_bytes().to_vec()),
+            Message::new(tags::FILE_SIZE, varint::encode(self.size.into())),
+            Message::new(tags::CONTENT_HASH, self.content_hash.to_vec()),
+        ];
+        messages.extend(
+            self.chunks
+                .iter()
+                .map(|id| Message::new(tags::FILE_CHUNK, id.to_bytes())),
+        );
+
+        messages
+            .into_iter()
+            .map(|message| message.expect("valid tag and size"))
+            .collect()
+    }
+
+    /// Parses a manifest from messages, ignoring messages with other tags
+    pub fn from_messages(messages: &[Message]) -> Result<Self, FileError> {
+        let name = String::from_utf8(single(messages, tags::FILE_NAME)?.to_vec())
+            .map_err(|_| FileError::InvalidField(tags::FILE_NAME))?;
+
+        let content_type = String::from_utf8(single(messages, tags::CONTENT_TYPE)?.to_vec())
+            .ok()
+            .filter(|content_type| MediaType::parse(content_type).is_ok())
+            .ok_or(FileError::InvalidField(tags::CONTENT_TYPE))?;
+
+        let size = single(messages, tags::FILE_SIZE)?;
+        let size = varint::decode_strict(size)
+            .ok()
+            .filter(|(_, n)| *n == size.len())
+            .and_then(|(size, _)| u64::try_from(size).ok())
+            .ok_or(FileError::InvalidField(tags::FILE_SIZE))?;
+
+        let content_hash = single(messages, tags::CONTENT_HASH)?
+            .try_into()
+            .map_err(|_| FileError::InvalidField(tags::CONTENT_HASH))?;
+
+        let chunks = messages
+            .iter()
+            .filter(|message| message.tag == tags::FILE_CHUNK)
+            .map(|message| {
+                EmbeddingId::from_bytes(&message.body)
+                    .map_err(|_| FileError::InvalidField(tags::FILE_CHUNK))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            name,
+            content_type,
+            size,
+            content_hash,
+            chunks,
+        })
+    }
+
+    /// Concatenates the chunk payloads ([`Embedding::payload`]), checking them against the
+    /// manifest
+    pub fn reassemble(&self, chunks: &[Embedding]) -> Result<Vec<u8>, FileError> {
+        if chunks.len() != self.chunks.len() {
+            return Err(FileError::ChunkMismatch(
+                chunks.len().min(self.chunks.len()),
+            ));
+        }
+
+        let mut bytes = Vec::new();
+        for (i, (chunk, id)) in chunks.iter().zip(&self.chunks).enumerate() {
+            if chunk.id() != *id {
+                return Err(FileError::ChunkMismatch(i));
+            }
+            bytes.extend_from_slice(&chunk.payload());
+        }
+
+        if bytes.len() as u64 != self.size {
+            return Err(FileError::SizeMismatch);
+        }
+
+        if tagged_hash(CONTENT_HASH_TAG, &bytes) != self.content_hash {
+            return Err(FileError::HashMismatch);
+        }
+
+        Ok(bytes)
+    }
+
+    /// Fetches the chunks from a resolver and reassembles the file
+    pub fn fetch<R: Resolver + ?Sized>(&self, resolver: &R) -> Result<Vec<u8>, FileError> {
+        let chunks = resolver.embeddings(&self.chunks)?;
+        self.reassemble(&chunks)
+    }
+}
+
+/// Returns the body of the only message with `tag`
+fn single(messages: &[Message], tag: Tag) -> Result<&[u8], FileError> {
+    let mut matching = messages.iter().filter(|message| message.tag == tag);
+    let message = matching.next().ok_or(FileError::MissingField(tag))?;
+
+    if matching.next().is_some() {
+        return Err(FileError::InvalidField(tag));
+    }
+
+    Ok(&message.body)
+}
+
+impl From<ResolveError> for FileError {
+    fn from(e: ResolveError) -> Self {
+        FileError::Resolve(e)
+    }
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileError::MissingField(tag) => write!(f, "Missing manifest field: {tag}"),
+            FileError::InvalidField(tag) => write!(f, "Invalid manifest field: {tag}"),
+            FileError::ChunkMismatch(i) => write!(f, "Chunk {i} does not match the manifest"),
+            FileError::SizeMismatch => write!(f, "File size does not match the manifest"),
+            FileError::HashMismatch => write!(f, "File hash does not match the manifest"),
+            FileError::Resolve(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for FileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FileError::Resolve(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::MemoryResolver;
+
+    use bitcoin::{
+        Amount, OutPoint, Sequence, Transaction, TxIn, TxOut, Witness,
+        absolute::LockTime,
+        taproot::{TAPROOT_CONTROL_BASE_SIZE, TAPROOT_LEAF_TAPSCRIPT},
+        transaction::Version,
+    };
+
+    fn reveal(scripts: &[ScriptBuf]) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: scripts
+                .iter()
+                .map(|script| TxIn {
+                    previous_output: OutPoint::null(),
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::from_slice(&[
+                        script.to_bytes(),
+                        vec![TAPROOT_LEAF_TAPSCRIPT; TAPROOT_CONTROL_BASE_SIZE],
+                    ]),
+                })
+                .collect(),
+            output: vec![],
+        }
+    }
+
+    #[test]
+    fn test_file_roundtrip() {
+        let file = (0..25_000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let scripts = split(&file, &EnvelopeBuilder::new(), Script::new()).unwrap();
+        assert_eq!(scripts.len(), 3);
+
+        let tx = reveal(&scripts);
+        let chunks = Embedding::from_transaction(&tx);
+        let ids = chunks.iter().map(Embedding::id).collect::<Vec<_>>();
+
+        let manifest = FileManifest::new("data.bin", "application/octet-stream", &file, ids);
+        let decoded = Message::decode(&Message::encode(manifest.to_messages())).unwrap();
+        assert_eq!(FileManifest::from_messages(&decoded), Ok(manifest.clone()));
+
+        assert_eq!(manifest.reassemble(&chunks), Ok(file.clone()));
+
+        let mut resolver = MemoryResolver::new();
+        resolver.insert_transaction(tx);
+        assert_eq!(manifest.fetch(&resolver), Ok(file));
+    }
+
+    #[test]
+    fn test_reassemble_op_return() {
+        let file = b"a file in an OP_RETURN output".to_vec();
+        let mut tx = reveal(&[]);
+        tx.output.push(TxOut {
+            value: Amount::ZERO,
+            script_pubkey: ScriptBuf::new_op_return(b"a file in an OP_RETURN output"),
+        });
+
+        // The push opcode is not part of the file
+        let chunks = Embedding::from_transaction(&tx);
+        assert_eq!(chunks[0].bytes.len(), file.len() + 1);
+
+        let ids = chunks.iter().map(Embedding::id).collect::<Vec<_>>();
+        let manifest = FileManifest::new("a.txt", "text/plain", &file, ids);
+        assert_eq!(manifest.reassemble(&chunks), Ok(file));
+    }
+
+    #[test]
+    fn test_reassemble_mismatch() {
+        let file = vec![7u8; 1000];
+        let scripts = split(
+            &file,
+            &EnvelopeBuilder::new().max_script_size(600),
+            Script::new(),
+        )
+        .unwrap();
+        let chunks = Embedding::from_transaction(&reveal(&scripts));
+        let ids = chunks.iter().map(Embedding::id).collect::<Vec<_>>();
+        let manifest = FileManifest::new("a", "text/plain", &file, ids);
+        assert_eq!(manifest.reassemble(&chunks), Ok(file.clone()));
+
+        let mut swapped = chunks.clone();
+        swapped.swap(0, 1);
+        assert_eq!(
+            manifest.reassemble(&swapped),
+            Err(FileError::ChunkMismatch(0))
+        );
+        assert_eq!(
+            manifest.reassemble(&chunks[..1]),
+            Err(FileError::ChunkMismatch(1))
+        );
+
+        let mut truncated = chunks.clone();
+        truncated[1].bytes.pop();
+        assert_eq!(
+            manifest.reassemble(&truncated),
+            Err(FileError::SizeMismatch)
+        );
+
+        let mut altered = chunks.clone();
+        altered[1].bytes[0] = 0;
+        assert_eq!(manifest.reassemble(&altered), Err(FileError::HashMismatch));
+    }
+
+    #[test]
+    fn test_invalid_manifest() {
+        let manifest = FileManifest::new("a", "text/plain", b"a", vec![]);
+        let messages = manifest.to_messages();
+
+        assert_eq!(
+            FileManifest::from_messages(&messages[1..]),
+            Err(FileError::MissingField(tags::FILE_NAME))
+        );
+
+        let mut repeated = messages.clone();
+        repeated.push(messages[3].clone());
+        assert_eq!(
+            FileManifest::from_messages(&repeated),
+            Err(FileError::InvalidField(tags::CONTENT_HASH))
+        );
+
+        let mut invalid = messages.clone();
+        invalid[1] = Message::new(tags::CONTENT_TYPE, b"text".to_vec()).unwrap();
+        assert_eq!(
+            FileManifest::from_messages(&invalid),
+            Err(FileError::InvalidField(tags::CONTENT_TYPE))
+        );
+
+        let mut invalid = messages.clone();
+        invalid.push(Message::new(tags::FILE_CHUNK, vec![0; 5]).unwrap());
+        assert_eq!(
+            FileManifest::from_messages(&invalid),
+            Err(FileError::InvalidField(tags::FILE_CHUNK))
+        );
+    }
+}
@@ -0,0 +1,559 @@
+//! # Chain Follower
+//!
+//! A [`Follower`] tracks the best chain through a [`ChainSource`], reporting the embeddings in
+//! each newly connected block and each block disconnected by a reorg to a [`Handler`]. The
+//! hashes of recently connected blocks are kept in a small [`Checkpoint`] that can be persisted
+//! between runs. A [`WatchList`] limits the reported embeddings to those a service tracks.
+
+use crate::resolver::{ConfirmedEmbedding, ResolveError, Resolver};
+use crate::watch::WatchList;
+
+use bitcoin::BlockHash;
+use bitcoin::block::Header;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The default number of recent blocks retained for reorg detection
+pub const DEFAULT_REORG_DEPTH: usize = 100;
+
+/// A resolver that can also report the state of the best chain
+pub trait ChainSource: Resolver {
+    /// Returns the height of the best chain tip
+    fn best_height(&self) -> Result<u64, ResolveError>;
+
+    /// Returns the hash of the block at a height in the best chain
+    fn block_hash_at(&self, height: u64) -> Result<BlockHash, ResolveError>;
+
+    /// Returns the header of a block
+    fn block_header(&self, hash: &BlockHash) -> Result<Header, ResolveError>;
+}
+
+/// Callbacks invoked as the follower moves along the best chain
+pub trait Handler {
+    /// Called for each embedding in a newly connected block, in block order
+    fn on_embedding_confirmed(&mut self, embedding: &ConfirmedEmbedding);
+
+    /// Called when the block at `height` is disconnected by a reorg. Blocks are disconnected
+    /// from the tip down.
+    fn on_block_disconnected(&mut self, height: u64);
+}
+
+/// Error types for the chain follower
+#[derive(Debug)]
+pub enum FollowerError {
+    /// The chain source failed
+    Resolve(ResolveError),
+    /// The checkpoint file could not be read or written
+    Io(io::Error),
+    /// The checkpoint file is malformed
+    InvalidCheckpoint,
+    /// A reorg disconnected every block retained in the checkpoint
+    ReorgTooDeep,
+}
+
+/// The most recently connected blocks, used to resume and to detect reorgs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    blocks: VecDeque<(u64, BlockHash)>,
+    depth: usize,
+}
+
+impl Checkpoint {
+    /// Returns an empty checkpoint retaining up to `depth` blocks
+    pub fn new(depth: usize) -> Self {
+        Self {
+            blocks: VecDeque::new(),
+            depth: depth.max(1),
+        }
+    }
+
+    /// Returns the height and hash of the last connected block
+    pub fn tip(&self) -> Option<(u64, BlockHash)> {
+        self.blocks.back().copied()
+    }
+
+    /// Records a newly connected block
+    fn push(&mut self, height: u64, hash: BlockHash) {
+        self.blocks.push_back((height, hash));
+        while self.blocks.len() > self.depth {
+            self.blocks.pop_front();
+        }
+    }
+
+    /// Removes the last connected block
+    fn pop(&mut self) -> Option<(u64, BlockHash)> {
+        self.blocks.pop_back()
+    }
+
+    /// Reads a checkpoint written by [`Checkpoint::save`]
+    pub fn load(path: impl AsRef<Path>, depth: usize) -> Result<Self, FollowerError> {
+        let mut checkpoint = Self::new(depth);
+
+        for line in fs::read_to_string(path)?.lines() {
+            let (height, hash) = line
+                .split_once(' ')
+                .ok_or(FollowerError::InvalidCheckpoint)?;
+            let height = height
+                .parse()
+                .map_err(|_| FollowerError::InvalidCheckpoint)?;
+            let hash = hash.parse().map_err(|_| FollowerError::InvalidCheckpoint)?;
+            checkpoint.push(height, hash);
+        }
+
+        Ok(checkpoint)
+    }
+
+    /// Writes the checkpoint as one `<height> <hash>` line per block, replacing the file
+    /// atomically
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), FollowerError> {
+        let path = path.as_ref();
+        let contents = self
+            .blocks
+            .iter()
+            .map(|(height, hash)| format!("{height} {hash}\n"))
+            .collect::<String>();
+
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, contents)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+impl Default for Checkpoint {
+    fn default() -> Self {
+        Self::new(DEFAULT_REORG_DEPTH)
+    }
+}
+
+/// Follows the best chain of a [`ChainSource`]
+pub struct Follower<S> {
+    source: S,
+    checkpoint: Checkpoint,
+    start_height: u64,
+    path: Option<PathBuf>,
+    watch: WatchList,
+}
+
+impl<S: ChainSource> Follower<S> {
+    /// Returns a follower starting at the genesis block
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            checkpoint: Checkpoint::default(),
+            start_height: 0,
+            path: None,
+            watch: WatchList::new(),
+        }
+    }
+
+    /// Sets the first height to scan when the checkpoint is empty
+    pub fn start_height(mut self, height: u64) -> Self {
+        self.start_height = height;
+        self
+    }
+
+    /// Resumes from an existing checkpoint
+    pub fn with_checkpoint(mut self, checkpoint: Checkpoint) -> Self {
+        self.checkpoint = checkpoint;
+        self
+    }
+
+    /// Loads the checkpoint from `path` if it exists and saves it there after each block
+    pub fn persist_to(mut self, path: impl Into<PathBuf>) -> Result<Self, FollowerError> {
+        let path = path.into();
+        if path.exists() {
+            self.checkpoint = Checkpoint::load(&path, self.checkpoint.depth)?;
+        }
+        self.path = Some(path);
+        Ok(self)
+    }
+
+    /// Reports only the embeddings that satisfy `watch`
+    pub fn watch(mut self, watch: WatchList) -> Self {
+        self.watch = watch;
+        self
+    }
+
+    /// Returns the current checkpoint
+    pub fn checkpoint(&self) -> &Checkpoint {
+        &self.checkpoint
+    }
+
+    /// Returns the chain source
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    /// Connects blocks until the follower reaches the best chain tip, first disconnecting any
+    /// blocks that are no longer in the best chain. Returns the number of blocks connected.
+    pub fn sync<H: Handler>(&mut self, handler: &mut H) -> Result<usize, FollowerError> {
+        let mut connected = 0;
+
+        while self.step(handler)? {
+            connected += 1;
+        }
+
+        Ok(connected)
+    }
+
+    /// Connects at most one block, returning false if the follower is already at the tip
+    pub fn step<H: Handler>(&mut self, handler: &mut H) -> Result<bool, FollowerError> {
+        loop {
+            self.disconnect_stale(handler)?;
+
+            let height = match self.checkpoint.tip() {
+                Some((height, _)) => height + 1,
+                None => self.start_height,
+            };
+
+            if height > self.source.best_height()? {
+                return Ok(false);
+            }
+
+            let hash = self.source.block_hash_at(height)?;
+
+            // The chain may have reorganized since the stale check
+            if let Some((_, tip)) = self.checkpoint.tip() {
+                if self.source.block_header(&hash)?.prev_blockhash != tip {
+                    continue;
+                }
+            }
+
+            for embedding in self.confirmed_embeddings(&hash, height)? {
+                handler.on_embedding_confirmed(&embedding);
+            }
+
+            self.checkpoint.push(height, hash);
+            self.save()?;
+
+            return Ok(true);
+        }
+    }
+
+    /// Returns the embeddings in a block that satisfy the watch list, copying only those
+    fn confirmed_embeddings(
+        &self,
+        hash: &BlockHash,
+        height: u64,
+    ) -> Result<Vec<ConfirmedEmbedding>, FollowerError> {
+        if self.watch.is_empty() {
+            return Ok(self.source.confirmed_embeddings_in_block(hash, height)?);
+        }
+
+        let block = self.source.block(hash)?;
+        let time = block.header.time;
+        Ok(block
+            .txdata
+            .iter()
+            .enumerate()
+            .flat_map(|(tx_index, tx)| {
+                self.watch
+                    .extract(tx)
+                    .into_iter()
+                    .map(move |embedding| ConfirmedEmbedding {
+                        embedding,
+                        block_hash: *hash,
+                        height,
+                        time,
+                        tx_index,
+                    })
+            })
+            .collect())
+    }
+
+    fn disconnect_stale<H: Handler>(&mut self, handler: &mut H) -> Result<(), FollowerError> {
+        // Find the fork point before touching the checkpoint, so a reorg deeper than the
+        // retained blocks leaves the follower and its handler unchanged
+        let best = self.source.best_height()?;
+        let mut stale = 0;
+        for &(height, hash) in self.checkpoint.blocks.iter().rev() {
+            if height <= best && self.source.block_hash_at(height)? == hash {
+                break;
+            }
+            stale += 1;
+        }
+
+        if stale == self.checkpoint.blocks.len() && stale > 0 {
+            return Err(FollowerError::ReorgTooDeep);
+        }
+
+        let disconnected = stale > 0;
+        for _ in 0..stale {
+            if let Some((height, _)) = self.checkpoint.pop() {
+                handler.on_block_disconnected(height);
+            }
+        }
+
+        if disconnected {
+            self.save()?;
+        }
+
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), FollowerError> {
+        match &self.path {
+            Some(path) => self.checkpoint.save(path),
+            None => Ok(()),
+        }
+    }
+}
+
+impl From<ResolveError> for FollowerError {
+    fn from(e: ResolveError) -> Self {
+        FollowerError::Resolve(e)
+    }
+}
+
+impl From<io::Error> for FollowerError {
+    fn from(e: io::Error) -> Self {
+        FollowerError::Io(e)
+    }
+}
+
+impl fmt::Display for FollowerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FollowerError::Resolve(e) => write!(f, "Resolve error: {e}"),
+            FollowerError::Io(e) => write!(f, "Checkpoint I/O error: {e}"),
+            FollowerError::InvalidCheckpoint => write!(f, "Invalid checkpoint file"),
+            FollowerError::ReorgTooDeep => {
+                write!(
+                    f,
+                    "Reorg is deeper than the blocks retained in the checkpoint"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for FollowerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FollowerError::Resolve(e) => Some(e),
+            FollowerError::Io(e) => Some(e),
+            FollowerError::InvalidCheckpoint | FollowerError::ReorgTooDeep => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::block::Version as BlockVersion;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{
+        Amount, Block, CompactTarget, ScriptBuf, Transaction, TxMerkleNode, TxOut, Txid,
+        absolute::LockTime, transaction::Version,
+    };
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// A chain of blocks shared between the test and the follower
+    #[derive(Clone, Default)]
+    struct MockChain(Rc<RefCell<Vec<Block>>>);
+
+    impl MockChain {
+        /// Replaces every block above `height` with blocks containing `data`
+        fn reorg(&self, height: usize, data: &[&[u8]]) {
+            let mut blocks = self.0.borrow_mut();
+            blocks.truncate(height + 1);
+
+            for data in data {
+                let prev_blockhash = blocks
+                    .last()
+                    .map_or(BlockHash::all_zeros(), Block::block_hash);
+                let tx = Transaction {
+                    version: Version::TWO,
+                    lock_time: LockTime::ZERO,
+                    input: vec![],
+                    output: vec![TxOut {
+                        value: Amount::ZERO,
+                        script_pubkey: ScriptBuf::new_op_return(
+                            <&[u8; 2]>::try_from(*data).unwrap(),
+                        ),
+                    }],
+                };
+
+                let mut block = Block {
+                    header: Header {
+                        version: BlockVersion::ONE,
+                        prev_blockhash,
+                        merkle_root: TxMerkleNode::all_zeros(),
+                        time: 0,
+                        bits: CompactTarget::from_consensus(0),
+                        nonce: 0,
+                    },
+                    txdata: vec![tx],
+                };
+                block.header.merkle_root = block.compute_merkle_root().unwrap();
+                blocks.push(block);
+            }
+        }
+
+        fn find(&self, hash: &BlockHash) -> Result<Block, ResolveError> {
+            self.0
+                .borrow()
+                .iter()
+                .find(|block| block.block_hash() == *hash)
+                .cloned()
+                .ok_or(ResolveError::BlockNotFound(*hash))
+        }
+    }
+
+    impl Resolver for MockChain {
+        fn transaction(&self, txid: &Txid) -> Result<Transaction, ResolveError> {
+            Err(ResolveError::TransactionNotFound(*txid))
+        }
+
+        fn block(&self, hash: &BlockHash) -> Result<Block, ResolveError> {
+            self.find(hash)
+        }
+    }
+
+    impl ChainSource for MockChain {
+        fn best_height(&self) -> Result<u64, ResolveError> {
+            Ok(self.0.borrow().len() as u64 - 1)
+        }
+
+        fn block_hash_at(&self, height: u64) -> Result<BlockHash, ResolveError> {
+            self.0
+                .borrow()
+                .get(height as usize)
+                .map(Block::block_hash)
+                .ok_or(ResolveError::Backend("height out of range".to_string()))
+        }
+
+        fn block_header(&self, hash: &BlockHash) -> Result<Header, ResolveError> {
+            Ok(self.find(hash)?.header)
+        }
+    }
+
+    #[derive(Default)]
+    struct Events(Vec<String>);
+
+    impl Handler for Events {
+        fn on_embedding_confirmed(&mut self, confirmed: &ConfirmedEmbedding) {
+            let data = String::from_utf8_lossy(&confirmed.embedding.bytes[1..]).into_owned();
+            self.0.push(format!("+{}:{data}", confirmed.height));
+        }
+
+        fn on_block_disconnected(&mut self, height: u64) {
+            self.0.push(format!("-{height}"));
+        }
+    }
+
+    #[test]
+    fn test_sync_and_reorg() {
+        let chain = MockChain::default();
+        chain.reorg(0, &[b"g0", b"a1", b"a2"]);
+
+        let mut follower = Follower::new(chain.clone());
+        let mut events = Events::default();
+
+        assert_eq!(follower.sync(&mut events).unwrap(), 3);
+        assert_eq!(events.0, vec!["+0:g0", "+1:a1", "+2:a2"]);
+        assert_eq!(follower.sync(&mut events).unwrap(), 0);
+
+        // Replace blocks 1 and 2 with a longer branch
+        chain.reorg(0, &[b"b1", b"b2", b"b3"]);
+        events.0.clear();
+
+        assert_eq!(follower.sync(&mut events).unwrap(), 3);
+        assert_eq!(events.0, vec!["-2", "-1", "+1:b1", "+2:b2", "+3:b3"]);
+        assert_eq!(
+            follower.checkpoint().tip(),
+            Some((3, chain.block_hash_at(3).unwrap()))
+        );
+
+        // A shorter best chain disconnects blocks above the new tip
+        chain.reorg(1, &[]);
+        events.0.clear();
+
+        assert_eq!(follower.sync(&mut events).unwrap(), 0);
+        assert_eq!(events.0, vec!["-3", "-2"]);
+    }
+
+    #[test]
+    fn test_start_height_and_reorg_depth() {
+        let chain = MockChain::default();
+        chain.reorg(0, &[b"g0", b"a1", b"a2", b"a3"]);
+
+        let mut follower = Follower::new(chain.clone())
+            .start_height(2)
+            .with_checkpoint(Checkpoint::new(1));
+        let mut events = Events::default();
+
+        assert_eq!(follower.sync(&mut events).unwrap(), 2);
+        assert_eq!(events.0, vec!["+2:a2", "+3:a3"]);
+
+        let tip = follower.checkpoint().tip();
+        chain.reorg(1, &[b"b2", b"b3"]);
+        assert!(matches!(
+            follower.sync(&mut events),
+            Err(FollowerError::ReorgTooDeep)
+        ));
+
+        // The failed sync neither rewinds the checkpoint nor notifies the handler
+        assert_eq!(follower.checkpoint().tip(), tip);
+        assert_eq!(events.0, vec!["+2:a2", "+3:a3"]);
+    }
+
+    #[test]
+    fn test_watch() {
+        let chain = MockChain::default();
+        chain.reorg(0, &[b"g0", b"a1", b"b2", b"a3"]);
+
+        let mut follower = Follower::new(chain).watch(WatchList::new().prefix(*b"a"));
+        let mut events = Events::default();
+
+        // Every block is connected, but only matching embeddings are reported
+        assert_eq!(follower.sync(&mut events).unwrap(), 4);
+        assert_eq!(events.0, vec!["+1:a1", "+3:a3"]);
+    }
+
+    #[test]
+    fn test_persisted_checkpoint() {
+        let path = std::env::temp_dir().join(format!(
+            "bitcoin-embed-follower-{}.checkpoint",
+            std::process::id()
+        ));
+
+        let chain = MockChain::default();
+        chain.reorg(0, &[b"g0", b"a1"]);
+
+        let mut events = Events::default();
+        let mut follower = Follower::new(chain.clone()).persist_to(&path).unwrap();
+        assert_eq!(follower.sync(&mut events).unwrap(), 2);
+
+        // A new follower resumes after the last connected block
+        chain.reorg(1, &[b"a2"]);
+        events.0.clear();
+
+        let mut follower = Follower::new(chain.clone()).persist_to(&path).unwrap();
+        assert_eq!(
+            follower.checkpoint().tip(),
+            Some((1, chain.block_hash_at(1).unwrap()))
+        );
+        assert_eq!(follower.sync(&mut events).unwrap(), 1);
+        assert_eq!(events.0, vec!["+2:a2"]);
+
+        assert_eq!(
+            Checkpoint::load(&path, DEFAULT_REORG_DEPTH).unwrap(),
+            *follower.checkpoint()
+        );
+
+        fs::write(&path, "not a checkpoint").unwrap();
+        assert!(matches!(
+            Checkpoint::load(&path, DEFAULT_REORG_DEPTH),
+            Err(FollowerError::InvalidCheckpoint)
+        ));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,552 @@
+//! # Funding
+//!
+//! A [`FundingSource`] supplies the coins and change script that turn embedding outputs into a
+//! ready-to-sign transaction. Sources only need to list their unspent outputs and provide a
+//! change script; coin selection defaults to largest-first. [`fund_outputs`] funds a
+//! transaction with `OP_RETURN` or other outputs, and [`fund_commit_reveal`] funds a commit
+//! transaction paying a [`CommitOutput`] together with the reveal spending one of its leaves.
+//! Both check `OP_RETURN` outputs against an [`OpReturnPolicy`] before selecting coins.
+
+use crate::descriptor::CommitOutput;
+use crate::policy::{OpReturnError, OpReturnPolicy};
+
+use bitcoin::transaction::{InputWeightPrediction, Version, predict_weight};
+use bitcoin::{
+    Amount, FeeRate, OutPoint, Script, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness,
+    absolute::LockTime,
+};
+use std::cmp::Reverse;
+use std::fmt;
+
+/// The size of a Schnorr signature with the default sighash type
+const SCHNORR_SIGNATURE_SIZE: usize = 64;
+
+/// An unspent output available for funding
+#[derive(Debug, Clone)]
+pub struct Utxo {
+    /// The outpoint
+    pub outpoint: OutPoint,
+    /// The output being spent
+    pub txout: TxOut,
+    /// The predicted size of the script sig and witness that will spend it
+    pub satisfaction: InputWeightPrediction,
+}
+
+/// An unsigned transaction together with the outputs spent by each of its inputs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FundedTransaction {
+    /// The transaction, with empty script sigs and witnesses
+    pub tx: Transaction,
+    /// The outputs spent by each input, in order, as needed for signing
+    pub prevouts: Vec<TxOut>,
+}
+
+/// Error types for funding transactions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FundingError {
+    /// The transaction has no outputs
+    NoOutputs,
+    /// The leaf is not committed to by the commit output
+    LeafNotFound,
+    /// The `OP_RETURN` outputs violate the relay policy
+    OpReturn(OpReturnError),
+    /// The source cannot cover the outputs and fee
+    InsufficientFunds {
+        /// The value needed, including the fee
+        required: Amount,
+        /// The value available
+        available: Amount,
+    },
+    /// The fee at the requested feerate is out of range
+    FeeOverflow,
+    /// The total value of the outputs or coins is out of range
+    ValueOverflow,
+    /// The source failed to respond
+    Backend(String),
+}
+
+/// A wallet or other source of coins for funding transactions
+pub trait FundingSource {
+    /// Returns the unspent outputs available for funding
+    fn list_unspent(&self) -> Result<Vec<Utxo>, FundingError>;
+
+    /// Returns the script that receives change
+    fn change_script(&self) -> Result<ScriptBuf, FundingError>;
+
+    /// Returns coins covering `outputs` and the fee at `fee_rate` for a transaction spending
+    /// them, with room for a change output. The default selects the largest coins first.
+    fn select_coins(
+        &self,
+        outputs: &[TxOut],
+        fee_rate: FeeRate,
+    ) -> Result<Vec<Utxo>, FundingError> {
+        let change_script = self.change_script()?;
+        let target = total(outputs)?;
+        let lens = output_script_lens(outputs, Some(&change_script));
+
+        let mut utxos = self.list_unspent()?;
+        utxos.sort_by_key(|utxo| Reverse(utxo.txout.value));
+
+        let mut selected = Vec::new();
+        let mut available = Amount::ZERO;
+        let mut required = target;
+        for utxo in utxos {
+            available = available
+                .checked_add(utxo.txout.value)
+                .ok_or(FundingError::ValueOverflow)?;
+            selected.push(utxo);
+
+            let weight = predict_weight(selected.iter().map(|u| u.satisfaction), lens.clone());
+            required = target
+                .checked_add(fee_for(fee_rate, weight.to_vbytes_ceil())?)
+                .ok_or(FundingError::FeeOverflow)?;
+            if available >= required {
+                return Ok(selected);
+            }
+        }
+
+        Err(FundingError::InsufficientFunds {
+            required,
+            available,
+        })
+    }
+}
+
+/// A funding source over coins held in memory
+#[derive(Debug, Clone)]
+pub struct MemoryFundingSource {
+    utxos: Vec<Utxo>,
+    change_script: ScriptBuf,
+}
+
+impl MemoryFundingSource {
+    /// Returns a source with no coins that sends change to `change_script`
+    pub fn new(change_script: ScriptBuf) -> Self {
+        Self {
+            utxos: Vec::new(),
+            change_script,
+        }
+    }
+
+    /// Adds a coin
+    pub fn insert(&mut self, utxo: Utxo) {
+        self.utxos.push(utxo);
+    }
+}
+
+impl FundingSource for MemoryFundingSource {
+    fn list_unspent(&self) -> Result<Vec<Utxo>, FundingError> {
+        Ok(self.utxos.clone())
+    }
+
+    fn change_script(&self) -> Result<ScriptBuf, FundingError> {
+        Ok(self.change_script.clone())
+    }
+}
+
+/// Returns a transaction paying `outputs`, funded by coins from `source` at `fee_rate`, if its
+/// `OP_RETURN` outputs satisfy `policy`. Change goes to a final output, unless it would be
+/// dust, in which case it is left to the fee.
+pub fn fund_outputs<S: FundingSource + ?Sized>(
+    source: &S,
+    outputs: Vec<TxOut>,
+    fee_rate: FeeRate,
+    policy: &OpReturnPolicy,
+) -> Result<FundedTransaction, FundingError> {
+    if outputs.is_empty() {
+        return Err(FundingError::NoOutputs);
+    }
+    policy.check_outputs(&outputs)?;
+
+    let selected = source.select_coins(&outputs, fee_rate)?;
+    let change_script = source.change_script()?;
+
+    let weight = predict_weight(
+        selected.iter().map(|utxo| utxo.satisfaction),
+        output_script_lens(&outputs, Some(&change_script)),
+    );
+    let prevouts = selected
+        .iter()
+        .map(|utxo| utxo.txout.clone())
+        .collect::<Vec<_>>();
+    let spent = total(&outputs)?
+        .checked_add(fee_for(fee_rate, weight.to_vbytes_ceil())?)
+        .ok_or(FundingError::FeeOverflow)?;
+    let change = total(&prevouts)?
+        .checked_sub(spent)
+        .filter(|change| *change >= change_script.minimal_non_dust());
+
+    let mut tx = unsigned_transaction(selected.iter().map(|utxo| utxo.outpoint), outputs);
+    if let Some(value) = change {
+        tx.output.push(TxOut {
+            value,
+            script_pubkey: change_script,
+        });
+    }
+
+    Ok(FundedTransaction { tx, prevouts })
+}
+
+/// Returns a commit transaction paying `commit` at its first output, funded by coins from
+/// `source`, and a reveal spending it through `leaf` to `reveal_outputs`, both at `fee_rate`.
+/// The commit output holds exactly the reveal outputs and fee, and the reveal outputs are
+/// checked against `policy`. The reveal's fee assumes the
+/// leaf is satisfied by one Schnorr signature with the default sighash type; its witness is
+/// left empty for the signature, leaf, and control block.
+pub fn fund_commit_reveal<S: FundingSource + ?Sized>(
+    source: &S,
+    commit: &CommitOutput,
+    leaf: &Script,
+    reveal_outputs: Vec<TxOut>,
+    fee_rate: FeeRate,
+    policy: &OpReturnPolicy,
+) -> Result<(FundedTransaction, FundedTransaction), FundingError> {
+    if reveal_outputs.is_empty() {
+        return Err(FundingError::NoOutputs);
+    }
+    policy.check_outputs(&reveal_outputs)?;
+    let control_block = commit
+        .control_block(leaf)
+        .ok_or(FundingError::LeafNotFound)?;
+
+    let satisfaction = InputWeightPrediction::new(
+        0,
+        [SCHNORR_SIGNATURE_SIZE, leaf.len(), control_block.size()],
+    );
+    let weight = predict_weight([satisfaction], output_script_lens(&reveal_outputs, None));
+    let commit_output = TxOut {
+        value: total(&reveal_outputs)?
+            .checked_add(fee_for(fee_rate, weight.to_vbytes_ceil())?)
+            .ok_or(FundingError::FeeOverflow)?,
+        script_pubkey: commit.script_pubkey(),
+    };
+
+    let commit_tx = fund_outputs(source, vec![commit_output.clone()], fee_rate, policy)?;
+    let outpoint = OutPoint::new(commit_tx.tx.compute_txid(), 0);
+    let reveal_tx = FundedTransaction {
+        tx: unsigned_transaction([outpoint], reveal_outputs),
+        prevouts: vec![commit_output],
+    };
+
+    Ok((commit_tx, reveal_tx))
+}
+
+/// Returns a transaction spending `outpoints` to `outputs`, with empty script sigs and witnesses
+fn unsigned_transaction(
+    outpoints: impl IntoIterator<Item = OutPoint>,
+    outputs: Vec<TxOut>,
+) -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: outpoints
+            .into_iter()
+            .map(|previous_output| TxIn {
+                previous_output,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            })
+            .collect(),
+        output: outputs,
+    }
+}
+
+/// Returns the script lengths of `outputs` and an optional change output
+fn output_script_lens(outputs: &[TxOut], change: Option<&Script>) -> Vec<usize> {
+    outputs
+        .iter()
+        .map(|output| output.script_pubkey.len())
+        .chain(change.map(Script::len))
+        .collect()
+}
+
+/// Returns the total value of `outputs`
+fn total(outputs: &[TxOut]) -> Result<Amount, FundingError> {
+    outputs
+        .iter()
+        .try_fold(Amount::ZERO, |total, output| {
+            total.checked_add(output.value)
+        })
+        .ok_or(FundingError::ValueOverflow)
+}
+
+/// Returns the fee at `fee_rate` for `vsize` virtual bytes
+fn fee_for(fee_rate: FeeRate, vsize: u64) -> Result<Amount, FundingError> {
+    fee_rate.fee_vb(vsize).ok_or(FundingError::FeeOverflow)
+}
+
+impl From<OpReturnError> for FundingError {
+    fn from(e: OpReturnError) -> Self {
+        FundingError::OpReturn(e)
+    }
+}
+
+impl fmt::Display for FundingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FundingError::NoOutputs => write!(f, "Transaction has no outputs"),
+            FundingError::LeafNotFound => write!(f, "Leaf not found in commit output"),
+            FundingError::OpReturn(e) => write!(f, "{e}"),
+            FundingError::InsufficientFunds {
+                required,
+                available,
+            } => write!(
+                f,
+                "Insufficient funds: {required} required, {available} available"
+            ),
+            FundingError::FeeOverflow => write!(f, "Fee is out of range"),
+            FundingError::ValueOverflow => write!(f, "Total value is out of range"),
+            FundingError::Backend(e) => write!(f, "Funding source error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for FundingError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FundingError::OpReturn(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::envelope::EnvelopeBuilder;
+
+    use bitcoin::Txid;
+    use bitcoin::hashes::Hash;
+    use bitcoin::key::{Keypair, Secp256k1, TweakedPublicKey};
+    use bitcoin::opcodes::all::OP_CHECKSIG;
+    use bitcoin::script::Builder;
+    use bitcoin::secp256k1::SecretKey;
+
+    fn p2tr() -> ScriptBuf {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let (key, _) = keypair.x_only_public_key();
+        ScriptBuf::new_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(key))
+    }
+
+    fn source(values: &[u64]) -> MemoryFundingSource {
+        let mut source = MemoryFundingSource::new(p2tr());
+        for (i, value) in values.iter().enumerate() {
+            source.insert(Utxo {
+                outpoint: OutPoint::new(Txid::from_byte_array([i as u8; 32]), 0),
+                txout: TxOut {
+                    value: Amount::from_sat(*value),
+                    script_pubkey: p2tr(),
+                },
+                satisfaction: InputWeightPrediction::P2TR_KEY_DEFAULT_SIGHASH,
+            });
+        }
+        source
+    }
+
+    /// Returns the weight of `funded` once each input is signed
+    fn signed_weight(funded: &FundedTransaction) -> bitcoin::Weight {
+        let mut tx = funded.tx.clone();
+        for input in &mut tx.input {
+            input.witness = Witness::from_slice(&[[0; 64]]);
+        }
+        tx.weight()
+    }
+
+    #[test]
+    fn test_fund_outputs() {
+        let source = source(&[1_000, 50_000, 20_000]);
+        let fee_rate = FeeRate::from_sat_per_vb(10).unwrap();
+        let op_return = TxOut {
+            value: Amount::ZERO,
+            script_pubkey: ScriptBuf::new_op_return([1; 40]),
+        };
+
+        let funded = fund_outputs(
+            &source,
+            vec![op_return.clone()],
+            fee_rate,
+            &OpReturnPolicy::default(),
+        )
+        .unwrap();
+        assert_eq!(funded.tx.input.len(), 1);
+        assert_eq!(funded.prevouts[0].value, Amount::from_sat(50_000));
+        assert_eq!(funded.tx.output[0], op_return);
+        assert_eq!(funded.tx.output[1].script_pubkey, p2tr());
+
+        let fee = Amount::from_sat(50_000) - funded.tx.output[1].value;
+        assert_eq!(
+            fee,
+            fee_for(fee_rate, signed_weight(&funded).to_vbytes_ceil()).unwrap()
+        );
+
+        // Largest first, until the outputs and fee are covered
+        let payment = TxOut {
+            value: Amount::from_sat(60_000),
+            script_pubkey: p2tr(),
+        };
+        let funded = fund_outputs(
+            &source,
+            vec![payment.clone()],
+            fee_rate,
+            &OpReturnPolicy::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            funded
+                .prevouts
+                .iter()
+                .map(|prevout| prevout.value.to_sat())
+                .collect::<Vec<_>>(),
+            vec![50_000, 20_000]
+        );
+
+        assert!(matches!(
+            fund_outputs(&source, vec![payment; 2], fee_rate, &OpReturnPolicy::default()),
+            Err(FundingError::InsufficientFunds { available, .. })
+                if available == Amount::from_sat(71_000)
+        ));
+        assert_eq!(
+            fund_outputs(&source, vec![], fee_rate, &OpReturnPolicy::default()),
+            Err(FundingError::NoOutputs)
+        );
+        assert_eq!(
+            fund_outputs(
+                &source,
+                vec![op_return.clone()],
+                FeeRate::MAX,
+                &OpReturnPolicy::default()
+            ),
+            Err(FundingError::FeeOverflow)
+        );
+
+        // Output and coin values that overflow when summed
+        let max = TxOut {
+            value: Amount::MAX,
+            script_pubkey: p2tr(),
+        };
+        assert_eq!(
+            fund_outputs(&source, vec![max; 2], fee_rate, &OpReturnPolicy::default()),
+            Err(FundingError::ValueOverflow)
+        );
+        let half = u64::MAX / 2 + 1;
+        let payment = TxOut {
+            value: Amount::from_sat(half + 1),
+            script_pubkey: p2tr(),
+        };
+        assert_eq!(
+            fund_outputs(
+                &self::source(&[half, half]),
+                vec![payment],
+                fee_rate,
+                &OpReturnPolicy::default()
+            ),
+            Err(FundingError::ValueOverflow)
+        );
+
+        // Two `OP_RETURN` outputs need a relaxed policy
+        let outputs = vec![op_return.clone(); 2];
+        assert_eq!(
+            fund_outputs(
+                &source,
+                outputs.clone(),
+                fee_rate,
+                &OpReturnPolicy::default()
+            ),
+            Err(FundingError::OpReturn(OpReturnError::MultipleOutputs))
+        );
+        let funded = fund_outputs(&source, outputs, fee_rate, &OpReturnPolicy::CORE_V30).unwrap();
+        assert_eq!(funded.tx.output.len(), 3);
+    }
+
+    #[test]
+    fn test_dust_change() {
+        // Change below the dust limit is left to the fee
+        let source = source(&[1_500]);
+        let fee_rate = FeeRate::from_sat_per_vb(1).unwrap();
+        let op_return = TxOut {
+            value: Amount::ZERO,
+            script_pubkey: ScriptBuf::new_op_return([1; 40]),
+        };
+
+        let funded = fund_outputs(
+            &source,
+            vec![op_return],
+            fee_rate,
+            &OpReturnPolicy::default(),
+        )
+        .unwrap();
+        assert_eq!(funded.tx.output.len(), 2);
+
+        let payment = TxOut {
+            value: Amount::from_sat(1_100),
+            script_pubkey: p2tr(),
+        };
+        let funded =
+            fund_outputs(&source, vec![payment], fee_rate, &OpReturnPolicy::default()).unwrap();
+        assert_eq!(funded.tx.output.len(), 1);
+    }
+
+    #[test]
+    fn test_fund_commit_reveal() {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[2; 32]).unwrap());
+        let (key, _) = keypair.x_only_public_key();
+        let leaf = EnvelopeBuilder::new()
+            .append_to_builder(
+                vec![vec![7; 300]],
+                Builder::new()
+                    .push_x_only_key(&key)
+                    .push_opcode(OP_CHECKSIG),
+            )
+            .into_script();
+        let commit = CommitOutput::new(&secp, key, vec![leaf.clone()]).unwrap();
+
+        let source = source(&[100_000]);
+        let fee_rate = FeeRate::from_sat_per_vb(5).unwrap();
+        let postage = TxOut {
+            value: Amount::from_sat(546),
+            script_pubkey: p2tr(),
+        };
+
+        let (commit_tx, reveal_tx) = fund_commit_reveal(
+            &source,
+            &commit,
+            &leaf,
+            vec![postage.clone()],
+            fee_rate,
+            &OpReturnPolicy::default(),
+        )
+        .unwrap();
+        assert_eq!(commit_tx.tx.output[0].script_pubkey, commit.script_pubkey());
+        assert_eq!(reveal_tx.prevouts[0], commit_tx.tx.output[0]);
+        assert_eq!(
+            reveal_tx.tx.input[0].previous_output,
+            OutPoint::new(commit_tx.tx.compute_txid(), 0)
+        );
+        assert_eq!(reveal_tx.tx.output, vec![postage.clone()]);
+
+        // The commit output pays exactly the reveal's fee once signed
+        let mut reveal = reveal_tx.tx.clone();
+        let control_block = commit.control_block(&leaf).unwrap();
+        reveal.input[0].witness =
+            Witness::from_slice(&[vec![0; 64], leaf.to_bytes(), control_block.serialize()]);
+        assert_eq!(
+            commit_tx.tx.output[0].value - postage.value,
+            fee_for(fee_rate, reveal.weight().to_vbytes_ceil()).unwrap()
+        );
+
+        assert_eq!(
+            fund_commit_reveal(
+                &source,
+                &commit,
+                Script::new(),
+                vec![postage],
+                fee_rate,
+                &OpReturnPolicy::default(),
+            ),
+            Err(FundingError::LeafNotFound)
+        );
+    }
+}
@@ -0,0 +1,433 @@
+//! # Header Chains
+//!
+//! A [`HeaderChain`] validates block headers offline, starting from a trusted anchor such as
+//! the genesis block: each header must build on the tip, carry the difficulty target required
+//! by the network's retargeting rules, and meet it. The chain accumulates work and can require
+//! known block hashes at given heights (checkpoint hints), so receipts ([`crate::receipt`]) can
+//! be checked completely by third parties with [`HeaderChain::verify_receipt`].
+//!
+//! Retargets need the header at the start of the previous difficulty period, so anchor a chain
+//! at a retarget boundary (a multiple of 2016 on mainnet) to follow it past the next one.
+//! Testnet4's rule that retargets start from the first block of the period is not applied.
+
+use crate::receipt::{self, Receipt, ReceiptError, VerifiedReceipt};
+
+use bitcoin::block::Header;
+use bitcoin::blockdata::constants::genesis_block;
+use bitcoin::pow::Work;
+use bitcoin::{BlockHash, CompactTarget, Network, params::Params};
+use std::fmt;
+
+/// A chain of validated headers built on a trusted anchor
+#[derive(Debug, Clone)]
+pub struct HeaderChain {
+    params: Params,
+    /// The height of the anchor
+    start: u64,
+    /// The anchor followed by the headers connected to it
+    headers: Vec<Header>,
+    work: Work,
+    checkpoints: Vec<(u64, BlockHash)>,
+}
+
+/// Error types for connecting headers and verifying receipts against them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderChainError {
+    /// The header does not build on the tip of the chain
+    Disconnected(BlockHash),
+    /// The header at this height does not have the required difficulty target
+    BadTarget(u64),
+    /// The header at this height does not meet its difficulty target
+    InvalidProofOfWork(u64),
+    /// The header at this height does not have the checkpoint's hash
+    CheckpointMismatch(u64),
+    /// The target at this height depends on a header before the anchor
+    UnknownTarget(u64),
+    /// The block is not in the chain
+    NotInChain(BlockHash),
+    /// The receipt is invalid
+    Receipt(ReceiptError),
+}
+
+impl HeaderChain {
+    /// Returns a chain anchored at the genesis block of `network`
+    pub fn new(network: Network) -> Self {
+        Self::from_anchor(network, 0, genesis_block(network).header)
+    }
+
+    /// Returns a chain anchored at a trusted `header` at `height`
+    pub fn from_anchor(network: Network, height: u64, header: Header) -> Self {
+        Self {
+            params: Params::new(network),
+            start: height,
+            headers: vec![header],
+            work: header.work(),
+            checkpoints: Vec::new(),
+        }
+    }
+
+    /// Requires the block at `height` to have `hash` when it is connected
+    pub fn checkpoint(mut self, height: u64, hash: BlockHash) -> Self {
+        self.checkpoints.push((height, hash));
+        self
+    }
+
+    /// Returns the height of the anchor
+    pub fn anchor_height(&self) -> u64 {
+        self.start
+    }
+
+    /// Returns the height of the tip
+    pub fn tip_height(&self) -> u64 {
+        self.start + self.headers.len() as u64 - 1
+    }
+
+    /// Returns the header at the tip
+    pub fn tip(&self) -> &Header {
+        self.headers.last().expect("at least the anchor")
+    }
+
+    /// Returns the work of the headers from the anchor to the tip
+    pub fn chain_work(&self) -> Work {
+        self.work
+    }
+
+    /// Returns the header at `height`, if it is in the chain
+    pub fn header_at(&self, height: u64) -> Option<&Header> {
+        let offset = height.checked_sub(self.start)?;
+        self.headers.get(usize::try_from(offset).ok()?)
+    }
+
+    /// Returns the height of the block `hash`, if it is in the chain
+    pub fn height_of(&self, hash: &BlockHash) -> Option<u64> {
+        self.headers
+            .iter()
+            .rposition(|header| header.block_hash() == *hash)
+            .map(|offset| self.start + offset as u64)
+    }
+
+    /// Validates `header` and adds it to the tip
+    pub fn connect(&mut self, header: Header) -> Result<(), HeaderChainError> {
+        let height = self.tip_height() + 1;
+        if header.prev_blockhash != self.tip().block_hash() {
+            return Err(HeaderChainError::Disconnected(header.block_hash()));
+        }
+
+        if header.bits != self.required_bits(height, &header)? {
+            return Err(HeaderChainError::BadTarget(height));
+        }
+        let hash = header
+            .validate_pow(header.target())
+            .map_err(|_| HeaderChainError::InvalidProofOfWork(height))?;
+
+        if self
+            .checkpoints
+            .iter()
+            .any(|(h, checkpoint)| *h == height && *checkpoint != hash)
+        {
+            return Err(HeaderChainError::CheckpointMismatch(height));
+        }
+
+        self.headers.push(header);
+        self.work = self.work + header.work();
+        Ok(())
+    }
+
+    /// Connects each of `headers` in turn, stopping at the first invalid one
+    pub fn extend(
+        &mut self,
+        headers: impl IntoIterator<Item = Header>,
+    ) -> Result<(), HeaderChainError> {
+        headers
+            .into_iter()
+            .try_for_each(|header| self.connect(header))
+    }
+
+    /// Verifies `receipt` as in [`receipt::verify`], and checks that its block is in this chain
+    /// once the receipt's headers not already in it are connected. The confirmations and work
+    /// returned are counted to the tip of the extended chain, from the receipt's block and the
+    /// anchor respectively.
+    pub fn verify_receipt(&self, receipt: &Receipt) -> Result<VerifiedReceipt, HeaderChainError> {
+        let verified = receipt::verify(receipt, Work::from_be_bytes([0; 32]))?;
+
+        let mut chain = self.clone();
+        for header in &receipt.headers {
+            if chain.height_of(&header.block_hash()).is_none() {
+                chain.connect(*header)?;
+            }
+        }
+
+        let height = chain
+            .height_of(&verified.block_hash)
+            .ok_or(HeaderChainError::NotInChain(verified.block_hash))?;
+
+        Ok(VerifiedReceipt {
+            confirmations: (chain.tip_height() - height + 1) as usize,
+            chain_work: chain.work,
+            ..verified
+        })
+    }
+
+    /// Returns the bits a header at `height` must have, following Bitcoin Core's `pow.cpp`
+    fn required_bits(
+        &self,
+        height: u64,
+        header: &Header,
+    ) -> Result<CompactTarget, HeaderChainError> {
+        let interval = self.params.difficulty_adjustment_interval();
+        let prev = self.tip();
+
+        if height % interval == 0 {
+            if self.params.no_pow_retargeting {
+                return Ok(prev.bits);
+            }
+            let first = self
+                .header_at(height - interval)
+                .ok_or(HeaderChainError::UnknownTarget(height))?;
+            let timespan = prev.time.saturating_sub(first.time);
+            return Ok(CompactTarget::from_next_work_required(
+                prev.bits,
+                timespan.into(),
+                &self.params,
+            ));
+        }
+
+        if self.params.allow_min_difficulty_blocks {
+            let pow_limit = self.params.max_attainable_target.to_compact_lossy();
+
+            // A block more than twice the target spacing after its parent may use the minimum
+            // difficulty
+            if u64::from(header.time) > u64::from(prev.time) + 2 * self.params.pow_target_spacing {
+                return Ok(pow_limit);
+            }
+
+            // Otherwise it has the target of the last block without the minimum difficulty
+            let last = (self.start..height)
+                .rev()
+                .map(|h| (h, self.header_at(h).expect("height in chain")))
+                .find(|(h, header)| {
+                    h % interval == 0 || *h == self.start || header.bits != pow_limit
+                })
+                .expect("the anchor is in the chain");
+            return Ok(last.1.bits);
+        }
+
+        Ok(prev.bits)
+    }
+}
+
+impl From<ReceiptError> for HeaderChainError {
+    fn from(e: ReceiptError) -> Self {
+        HeaderChainError::Receipt(e)
+    }
+}
+
+impl fmt::Display for HeaderChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeaderChainError::Disconnected(hash) => {
+                write!(f, "Header {hash} does not build on the tip")
+            }
+            HeaderChainError::BadTarget(height) => {
+                write!(
+                    f,
+                    "Header at height {height} has the wrong difficulty target"
+                )
+            }
+            HeaderChainError::InvalidProofOfWork(height) => {
+                write!(
+                    f,
+                    "Header at height {height} does not meet its difficulty target"
+                )
+            }
+            HeaderChainError::CheckpointMismatch(height) => {
+                write!(f, "Header at height {height} does not match the checkpoint")
+            }
+            HeaderChainError::UnknownTarget(height) => {
+                write!(
+                    f,
+                    "Target at height {height} depends on headers before the anchor"
+                )
+            }
+            HeaderChainError::NotInChain(hash) => write!(f, "Block {hash} is not in the chain"),
+            HeaderChainError::Receipt(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for HeaderChainError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Embedding;
+    use crate::receipt::MerkleBranch;
+    use crate::testing::op_return_tx;
+
+    use bitcoin::TxMerkleNode;
+    use bitcoin::block::Version as BlockVersion;
+    use bitcoin::hashes::Hash;
+
+    fn empty_root() -> TxMerkleNode {
+        TxMerkleNode::all_zeros()
+    }
+
+    /// Returns a regtest header mined on `prev`
+    fn mine(prev: &Header, merkle_root: TxMerkleNode) -> Header {
+        let mut header = Header {
+            version: BlockVersion::TWO,
+            prev_blockhash: prev.block_hash(),
+            merkle_root,
+            time: prev.time + 600,
+            bits: prev.bits,
+            nonce: 0,
+        };
+        while header.validate_pow(header.target()).is_err() {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    #[test]
+    fn test_connect() {
+        let mut chain = HeaderChain::new(Network::Regtest);
+        assert_eq!(chain.tip_height(), 0);
+        let genesis = *chain.tip();
+
+        let first = mine(&genesis, empty_root());
+        let second = mine(&first, empty_root());
+        chain.extend([first, second]).unwrap();
+        assert_eq!(chain.tip_height(), 2);
+        assert_eq!(chain.header_at(1), Some(&first));
+        assert_eq!(chain.height_of(&second.block_hash()), Some(2));
+        assert_eq!(
+            chain.chain_work(),
+            genesis.work() + first.work() + second.work()
+        );
+
+        // Headers must build on the tip
+        let fork = mine(&first, TxMerkleNode::from_byte_array([1; 32]));
+        assert_eq!(
+            chain.connect(fork),
+            Err(HeaderChainError::Disconnected(fork.block_hash()))
+        );
+
+        let mut unmined = mine(&second, empty_root());
+        unmined.nonce += 1;
+        while unmined.validate_pow(unmined.target()).is_ok() {
+            unmined.nonce += 1;
+        }
+        assert_eq!(
+            chain.connect(unmined),
+            Err(HeaderChainError::InvalidProofOfWork(3))
+        );
+
+        let mut hard = mine(&second, empty_root());
+        hard.bits = CompactTarget::from_consensus(0x1d00ffff);
+        assert_eq!(chain.connect(hard), Err(HeaderChainError::BadTarget(3)));
+        assert_eq!(chain.tip_height(), 2);
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let genesis = genesis_block(Network::Regtest).header;
+        let first = mine(&genesis, empty_root());
+
+        let mut chain = HeaderChain::new(Network::Regtest).checkpoint(1, first.block_hash());
+        assert_eq!(chain.clone().connect(first), Ok(()));
+
+        let other = mine(&genesis, TxMerkleNode::from_byte_array([1; 32]));
+        assert_eq!(
+            chain.connect(other),
+            Err(HeaderChainError::CheckpointMismatch(1))
+        );
+    }
+
+    #[test]
+    fn test_required_target() {
+        let anchor = Header {
+            version: BlockVersion::TWO,
+            prev_blockhash: BlockHash::all_zeros(),
+            merkle_root: empty_root(),
+            time: 1_700_000_000,
+            bits: CompactTarget::from_consensus(0x1c00ffff),
+            nonce: 0,
+        };
+        let next = |time, bits| Header {
+            prev_blockhash: anchor.block_hash(),
+            time,
+            bits: CompactTarget::from_consensus(bits),
+            ..anchor
+        };
+
+        // Testnet allows the minimum difficulty only after twenty minutes without a block
+        let mut testnet = HeaderChain::from_anchor(Network::Testnet, 100, anchor);
+        assert_eq!(
+            testnet.connect(next(anchor.time + 600, 0x1d00ffff)),
+            Err(HeaderChainError::BadTarget(101))
+        );
+        assert_eq!(
+            testnet.connect(next(anchor.time + 600, 0x1c00ffff)),
+            Err(HeaderChainError::InvalidProofOfWork(101))
+        );
+        assert_eq!(
+            testnet.connect(next(anchor.time + 1201, 0x1d00ffff)),
+            Err(HeaderChainError::InvalidProofOfWork(101))
+        );
+
+        // Mainnet never does
+        let mut mainnet = HeaderChain::from_anchor(Network::Bitcoin, 100, anchor);
+        assert_eq!(
+            mainnet.connect(next(anchor.time + 1201, 0x1d00ffff)),
+            Err(HeaderChainError::BadTarget(101))
+        );
+
+        // A retarget needs the first header of the previous period
+        let mut boundary = HeaderChain::from_anchor(Network::Bitcoin, 2015, anchor);
+        assert_eq!(
+            boundary.connect(next(anchor.time + 600, 0x1c00ffff)),
+            Err(HeaderChainError::UnknownTarget(2016))
+        );
+    }
+
+    #[test]
+    fn test_verify_receipt() {
+        let mut chain = HeaderChain::new(Network::Regtest);
+        let tx = op_return_tx(b"anchored document");
+        let txid = tx.compute_txid();
+        let root = TxMerkleNode::from_byte_array(txid.to_byte_array());
+
+        let block = mine(chain.tip(), root);
+        let confirmation = mine(&block, empty_root());
+        let id = Embedding::from_transaction(&tx)[0].id();
+        let branch = MerkleBranch::from_txids(&[txid], 0).unwrap();
+        let receipt = receipt::create(vec![block, confirmation], branch, tx, id).unwrap();
+
+        // The receipt's headers are connected to the chain
+        let verified = chain.verify_receipt(&receipt).unwrap();
+        assert_eq!(verified.block_hash, block.block_hash());
+        assert_eq!(verified.confirmations, 2);
+
+        // Headers already in the chain are skipped, and later ones count as confirmations
+        chain
+            .extend([block, confirmation, mine(&confirmation, empty_root())])
+            .unwrap();
+        let verified = chain.verify_receipt(&receipt).unwrap();
+        assert_eq!(verified.confirmations, 3);
+        assert_eq!(verified.chain_work, chain.chain_work());
+
+        // A receipt on another chain is rejected
+        let other = HeaderChain::from_anchor(Network::Regtest, 0, confirmation);
+        assert_eq!(
+            other.verify_receipt(&receipt),
+            Err(HeaderChainError::Disconnected(block.block_hash()))
+        );
+
+        let mut broken = receipt.clone();
+        broken.branch.hashes.push(empty_root());
+        assert_eq!(
+            chain.verify_receipt(&broken),
+            Err(HeaderChainError::Receipt(ReceiptError::MerkleMismatch))
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! # Hex Dumps
+//!
+//! [`HexDump`] displays bytes as an offset, hex, and ASCII view like `xxd`, for logging and
+//! rendering payloads whose format is unknown. Long payloads can be truncated to their first
+//! bytes, with a final line counting the bytes left out.
+//!
+//! ```text
+//! 00000000: 6865 6c6c 6f2c 2077 6f72 6c64 0a00 0102  hello, world....
+//! 00000010: ff                                       .
+//! ```
+
+use std::fmt;
+
+/// The default number of bytes shown per line
+pub const DEFAULT_WIDTH: usize = 16;
+
+/// A display adapter rendering bytes as a hex dump
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct HexDump<'a> {
+    bytes: &'a [u8],
+    width: usize,
+    max_bytes: Option<usize>,
+}
+
+impl<'a> HexDump<'a> {
+    /// Returns a dump of every byte in `bytes`, [`DEFAULT_WIDTH`] bytes per line
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            width: DEFAULT_WIDTH,
+            max_bytes: None,
+        }
+    }
+
+    /// Sets the number of bytes shown per line (at least one)
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = width.max(1);
+        self
+    }
+
+    /// Shows only the first `max_bytes` bytes, followed by a line counting the rest
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+}
+
+impl fmt::Display for HexDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shown = self
+            .max_bytes
+            .map_or(self.bytes, |max| &self.bytes[..max.min(self.bytes.len())]);
+        // Two hex digits per byte, plus a space after every pair of bytes
+        let hex_width = 2 * self.width + self.width.div_ceil(2);
+
+        for (line, chunk) in shown.chunks(self.width).enumerate() {
+            if line > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{:08x}: ", line * self.width)?;
+
+            let mut hex = String::with_capacity(hex_width);
+            for (i, byte) in chunk.iter().enumerate() {
+                hex.push_str(&format!("{byte:02x}"));
+                if i % 2 == 1 {
+                    hex.push(' ');
+                }
+            }
+            write!(f, "{hex:<hex_width$} ")?;
+
+            for &byte in chunk {
+                let c = if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                };
+                write!(f, "{c}")?;
+            }
+        }
+
+        let hidden = self.bytes.len() - shown.len();
+        if hidden > 0 {
+            if !shown.is_empty() {
+                writeln!(f)?;
+            }
+            write!(f, "... {hidden} more bytes")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_dump() {
+        let bytes = [b"hello, world\n\x00\x01\x02".as_slice(), &[0xff]].concat();
+        assert_eq!(
+            HexDump::new(&bytes).to_string(),
+            "00000000: 6865 6c6c 6f2c 2077 6f72 6c64 0a00 0102  hello, world....\n\
+             00000010: ff                                       ."
+        );
+        assert_eq!(HexDump::new(&[]).to_string(), "");
+    }
+
+    #[test]
+    fn test_width_and_truncation() {
+        let bytes = (0x41..0x50).collect::<Vec<u8>>();
+        assert_eq!(
+            HexDump::new(&bytes).width(5).max_bytes(7).to_string(),
+            "00000000: 4142 4344 45  ABCDE\n\
+             00000005: 4647          FG\n\
+             ... 8 more bytes"
+        );
+        assert_eq!(
+            HexDump::new(&bytes).max_bytes(0).to_string(),
+            "... 15 more bytes"
+        );
+        assert_eq!(
+            HexDump::new(&bytes[..2]).max_bytes(10).to_string(),
+            "00000000: 4142                                     AB"
+        );
+    }
+}
@@ -0,0 +1,306 @@
+//! # Embedding Index
+//!
+//! [`MemoryIndex`] is a reference implementation of the embedding key/query model. Embeddings are
+//! keyed by [`EmbeddingId`] and can be queried by txid, type, leading bytes, or content hash.
+//! Every query returns embeddings in canonical (id) order.
+
+use crate::{Embedding, EmbeddingId, EmbeddingType};
+
+use bitcoin::Txid;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The number of leading bytes indexed for prefix queries. Longer prefixes are matched by
+/// filtering the results for the first `PREFIX_LEN` bytes.
+pub const PREFIX_LEN: usize = 32;
+
+/// An in-memory embedding index
+#[derive(Debug, Clone, Default)]
+pub struct MemoryIndex {
+    embeddings: BTreeMap<EmbeddingId, Embedding>,
+    by_txid: BTreeMap<Txid, BTreeSet<EmbeddingId>>,
+    by_type: BTreeMap<EmbeddingType, BTreeSet<EmbeddingId>>,
+    by_prefix: BTreeMap<Vec<u8>, BTreeSet<EmbeddingId>>,
+    by_content_hash: BTreeMap<[u8; 32], BTreeSet<EmbeddingId>>,
+}
+
+impl MemoryIndex {
+    /// Returns an empty index
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of indexed embeddings
+    pub fn len(&self) -> usize {
+        self.embeddings.len()
+    }
+
+    /// Returns true if no embeddings are indexed
+    pub fn is_empty(&self) -> bool {
+        self.embeddings.is_empty()
+    }
+
+    /// Adds an embedding, returning the embedding previously indexed under the same id
+    pub fn insert(&mut self, embedding: Embedding) -> Option<Embedding> {
+        let id = embedding.id();
+        let previous = self.remove(&id);
+
+        insert_key(&mut self.by_txid, id.txid, id);
+        insert_key(&mut self.by_type, id.embedding_type, id);
+        insert_key(&mut self.by_prefix, prefix_key(&embedding.payload()), id);
+        insert_key(&mut self.by_content_hash, embedding.content_hash(), id);
+        self.embeddings.insert(id, embedding);
+
+        previous
+    }
+
+    /// Removes an embedding
+    pub fn remove(&mut self, id: &EmbeddingId) -> Option<Embedding> {
+        let embedding = self.embeddings.remove(id)?;
+
+        remove_key(&mut self.by_txid, &id.txid, id);
+        remove_key(&mut self.by_type, &id.embedding_type, id);
+        remove_key(&mut self.by_prefix, &prefix_key(&embedding.payload()), id);
+        remove_key(&mut self.by_content_hash, &embedding.content_hash(), id);
+
+        Some(embedding)
+    }
+
+    /// Returns the embedding with the given id
+    pub fn get(&self, id: &EmbeddingId) -> Option<&Embedding> {
+        self.embeddings.get(id)
+    }
+
+    /// Returns true if an embedding with the given id is indexed
+    pub fn contains(&self, id: &EmbeddingId) -> bool {
+        self.embeddings.contains_key(id)
+    }
+
+    /// Returns every embedding in canonical order
+    pub fn iter(&self) -> impl Iterator<Item = &Embedding> {
+        self.embeddings.values()
+    }
+
+    /// Returns the embeddings in a transaction
+    pub fn by_txid(&self, txid: &Txid) -> impl Iterator<Item = &Embedding> {
+        self.lookup(self.by_txid.get(txid))
+    }
+
+    /// Returns the embeddings of a given type
+    pub fn by_type(&self, embedding_type: EmbeddingType) -> impl Iterator<Item = &Embedding> {
+        self.lookup(self.by_type.get(&embedding_type))
+    }
+
+    /// Returns the embeddings whose payload starts with `prefix` (e.g. a protocol identifier)
+    pub fn by_prefix<'a>(&'a self, prefix: &'a [u8]) -> impl Iterator<Item = &'a Embedding> {
+        let key = &prefix[..prefix.len().min(PREFIX_LEN)];
+
+        let ids = self
+            .by_prefix
+            .range(key.to_vec()..)
+            .take_while(|(k, _)| k.starts_with(key))
+            .flat_map(|(_, ids)| ids)
+            .copied()
+            .collect::<BTreeSet<_>>();
+
+        ids.into_iter()
+            .filter_map(|id| self.embeddings.get(&id))
+            .filter(move |embedding| embedding.payload().starts_with(prefix))
+    }
+
+    /// Returns the embeddings whose payload has the given [`Embedding::content_hash`]
+    pub fn by_content_hash(&self, hash: &[u8; 32]) -> impl Iterator<Item = &Embedding> {
+        self.lookup(self.by_content_hash.get(hash))
+    }
+
+    fn lookup<'a>(
+        &'a self,
+        ids: Option<&'a BTreeSet<EmbeddingId>>,
+    ) -> impl Iterator<Item = &'a Embedding> {
+        ids.into_iter()
+            .flatten()
+            .filter_map(|id| self.embeddings.get(id))
+    }
+}
+
+impl Extend<Embedding> for MemoryIndex {
+    fn extend<I: IntoIterator<Item = Embedding>>(&mut self, iter: I) {
+        for embedding in iter {
+            self.insert(embedding);
+        }
+    }
+}
+
+impl FromIterator<Embedding> for MemoryIndex {
+    fn from_iter<I: IntoIterator<Item = Embedding>>(iter: I) -> Self {
+        let mut index = Self::new();
+        index.extend(iter);
+        index
+    }
+}
+
+fn prefix_key(bytes: &[u8]) -> Vec<u8> {
+    bytes[..bytes.len().min(PREFIX_LEN)].to_vec()
+}
+
+fn insert_key<K: Ord>(map: &mut BTreeMap<K, BTreeSet<EmbeddingId>>, key: K, id: EmbeddingId) {
+    map.entry(key).or_default().insert(id);
+}
+
+fn remove_key<K: Ord>(map: &mut BTreeMap<K, BTreeSet<EmbeddingId>>, key: &K, id: &EmbeddingId) {
+    if let Some(ids) = map.get_mut(key) {
+        ids.remove(id);
+        if ids.is_empty() {
+            map.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EmbeddingLocation, ScriptType};
+
+    use bitcoin::hashes::Hash;
+
+    fn txid(byte: u8) -> Txid {
+        Txid::from_byte_array([byte; 32])
+    }
+
+    fn embedding(txid: Txid, location: EmbeddingLocation, bytes: &[u8]) -> Embedding {
+        Embedding {
+            bytes: bytes.to_vec(),
+            txid,
+            location,
+        }
+    }
+
+    /// Returns an `OP_RETURN` embedding pushing `data`, as extracted from its output
+    fn op_return(txid: Txid, output: usize, data: &[u8]) -> Embedding {
+        let bytes = [&[data.len() as u8], data].concat();
+        embedding(txid, EmbeddingLocation::OpReturn { output }, &bytes)
+    }
+
+    fn envelope(input: usize, index: usize, len: usize) -> EmbeddingLocation {
+        EmbeddingLocation::WitnessEnvelope {
+            input,
+            index,
+            pushes: vec![len],
+            script_type: ScriptType::Tapscript,
+        }
+    }
+
+    fn sample() -> Vec<Embedding> {
+        vec![
+            op_return(txid(2), 0, b"ord"),
+            embedding(txid(1), envelope(0, 1, 6), b"ordabc"),
+            embedding(
+                txid(1),
+                EmbeddingLocation::TaprootAnnex {
+                    input: 0,
+                    index: 0,
+                    tag: 0,
+                },
+                b"xyz",
+            ),
+            embedding(txid(1), envelope(0, 0, 3), b"ord"),
+            op_return(txid(1), 1, b"or"),
+        ]
+    }
+
+    fn ids<'a>(embeddings: impl Iterator<Item = &'a Embedding>) -> Vec<EmbeddingId> {
+        embeddings.map(Embedding::id).collect()
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut index = MemoryIndex::new();
+        assert!(index.is_empty());
+
+        let sample = sample();
+        for embedding in &sample {
+            assert_eq!(index.insert(embedding.clone()), None);
+        }
+        assert_eq!(index.len(), sample.len());
+
+        for embedding in &sample {
+            assert_eq!(index.get(&embedding.id()), Some(embedding));
+        }
+
+        // Replacing an embedding re-indexes it
+        let replacement = op_return(txid(2), 0, b"new");
+        assert_eq!(index.insert(replacement.clone()), Some(sample[0].clone()));
+        assert_eq!(index.len(), sample.len());
+        assert_eq!(index.by_prefix(b"new").count(), 1);
+        assert_eq!(index.by_prefix(b"ord").count(), 2);
+
+        assert_eq!(index.remove(&replacement.id()), Some(replacement.clone()));
+        assert_eq!(index.remove(&replacement.id()), None);
+        assert!(!index.contains(&replacement.id()));
+        assert_eq!(index.by_txid(&txid(2)).count(), 0);
+        assert_eq!(index.by_prefix(b"new").count(), 0);
+    }
+
+    #[test]
+    fn test_canonical_order() {
+        let index = sample().into_iter().collect::<MemoryIndex>();
+
+        let mut expected = ids(sample().iter());
+        expected.sort();
+
+        assert_eq!(ids(index.iter()), expected);
+        assert_eq!(
+            ids(index.by_txid(&txid(1))),
+            expected
+                .iter()
+                .filter(|id| id.txid == txid(1))
+                .copied()
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_queries() {
+        let sample = sample();
+        let index = sample.iter().cloned().collect::<MemoryIndex>();
+
+        assert_eq!(index.by_txid(&txid(1)).count(), 4);
+        assert_eq!(index.by_txid(&txid(3)).count(), 0);
+
+        assert_eq!(index.by_type(EmbeddingType::OpReturn).count(), 2);
+        assert_eq!(index.by_type(EmbeddingType::TaprootAnnex).count(), 1);
+        assert_eq!(
+            index
+                .by_type(EmbeddingType::WitnessEnvelope(ScriptType::Legacy))
+                .count(),
+            0
+        );
+
+        assert_eq!(index.by_prefix(b"").count(), 5);
+        assert_eq!(index.by_prefix(b"or").count(), 4);
+        assert_eq!(index.by_prefix(b"ord").count(), 3);
+        assert_eq!(ids(index.by_prefix(b"orda")), vec![sample[1].id()]);
+        assert_eq!(index.by_prefix(b"ordabcd").count(), 0);
+
+        let hash = sample[0].content_hash();
+        assert_eq!(
+            ids(index.by_content_hash(&hash)),
+            vec![sample[3].id(), sample[0].id()]
+        );
+        assert_eq!(index.by_content_hash(&[0; 32]).count(), 0);
+    }
+
+    #[test]
+    fn test_long_prefix() {
+        let long = vec![7u8; PREFIX_LEN + 10];
+        let mut other = long.clone();
+        other[PREFIX_LEN + 5] = 8;
+
+        let index = [op_return(txid(1), 0, &long), op_return(txid(1), 1, &other)]
+            .into_iter()
+            .collect::<MemoryIndex>();
+
+        assert_eq!(index.by_prefix(&long[..PREFIX_LEN]).count(), 2);
+        assert_eq!(index.by_prefix(&long[..PREFIX_LEN + 6]).count(), 1);
+        assert_eq!(index.by_prefix(&long).count(), 1);
+    }
+}
@@ -0,0 +1,221 @@
+//! # JSON Canonicalization
+//!
+//! Serializes JSON values in the RFC 8785 JSON Canonicalization Scheme (JCS): object members are
+//! sorted by the UTF-16 code units of their names, whitespace is removed, strings use the shortest
+//! escapes, and numbers are written as ECMAScript does for IEEE 754 doubles.
+//!
+//! [`canonical_json_hash`] is the content hash of the canonical form, which is what
+//! [`Embedding::content_hash`](crate::Embedding::content_hash) returns for an embedding holding
+//! it. Merkle trees ([`MerkleTree::from_json`](crate::merkle::MerkleTree::from_json))
+//! and attestations ([`verify_json_attestation`](crate::attestation::verify_json_attestation))
+//! use it to commit to JSON values rather than to one of their many serializations.
+
+use crate::CONTENT_HASH_TAG;
+use crate::message::tagged_hash;
+
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt::Write;
+
+/// Returns the canonical serialization of `value`
+pub fn to_string(value: &Value) -> String {
+    let mut json = String::new();
+    write_value(value, &mut json);
+    json
+}
+
+/// Returns the canonical serialization of any serializable value
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, serde_json::Error> {
+    Ok(to_string(&serde_json::to_value(value)?).into_bytes())
+}
+
+/// Returns the tagged SHA-256 of the canonical serialization of `value`, using
+/// [`CONTENT_HASH_TAG`] as the tag
+pub fn canonical_json_hash<T: Serialize + ?Sized>(
+    value: &T,
+) -> Result<[u8; 32], serde_json::Error> {
+    Ok(tagged_hash(CONTENT_HASH_TAG, &to_vec(value)?))
+}
+
+/// Returns whether `bytes` are JSON in canonical form
+pub fn is_canonical(bytes: &[u8]) -> bool {
+    serde_json::from_slice::<Value>(bytes).is_ok_and(|value| to_string(&value).as_bytes() == bytes)
+}
+
+fn write_value(value: &Value, json: &mut String) {
+    match value {
+        Value::Null => json.push_str("null"),
+        Value::Bool(b) => json.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => write_number(n.as_f64().unwrap_or_default(), json),
+        Value::String(s) => write_string(s, json),
+        Value::Array(values) => {
+            json.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                write_value(value, json);
+            }
+            json.push(']');
+        }
+        Value::Object(members) => {
+            let mut members = members.iter().collect::<Vec<_>>();
+            members.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+
+            json.push('{');
+            for (i, (name, value)) in members.into_iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                write_string(name, json);
+                json.push(':');
+                write_value(value, json);
+            }
+            json.push('}');
+        }
+    }
+}
+
+/// Writes a number as ECMAScript's `Number.prototype.toString` does
+fn write_number(n: f64, json: &mut String) {
+    if n == 0.0 {
+        json.push('0');
+        return;
+    }
+    if n < 0.0 {
+        json.push('-');
+    }
+
+    // The shortest digits that round-trip, and the position of the decimal point
+    let scientific = format!("{:e}", n.abs());
+    let (mantissa, exponent) = scientific.split_once('e').expect("exponent");
+    let digits = mantissa.replace('.', "");
+    let k = digits.len() as i32;
+    let n = exponent.parse::<i32>().expect("integer exponent") + 1;
+
+    if k <= n && n <= 21 {
+        json.push_str(&digits);
+        json.extend(std::iter::repeat_n('0', (n - k) as usize));
+    } else if 0 < n && n <= 21 {
+        json.push_str(&digits[..n as usize]);
+        json.push('.');
+        json.push_str(&digits[n as usize..]);
+    } else if -6 < n && n <= 0 {
+        json.push_str("0.");
+        json.extend(std::iter::repeat_n('0', -n as usize));
+        json.push_str(&digits);
+    } else {
+        json.push_str(&digits[..1]);
+        if k > 1 {
+            json.push('.');
+            json.push_str(&digits[1..]);
+        }
+        let sign = if n > 0 { '+' } else { '-' };
+        write!(json, "e{sign}{}", (n - 1).abs()).expect("writing to a string");
+    }
+}
+
+fn write_string(s: &str, json: &mut String) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\u{8}' => json.push_str("\\b"),
+            '\u{c}' => json.push_str("\\f"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                write!(json, "\\u{:04x}", c as u32).expect("writing to a string")
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    fn number(n: f64) -> String {
+        let mut json = String::new();
+        write_number(n, &mut json);
+        json
+    }
+
+    #[test]
+    fn test_rfc_example() {
+        // RFC 8785, section 3.2.2
+        let input = r#"{
+            "numbers": [333333333.33333329, 1E30, 4.50, 2e-3, 0.000000000000000000000000001],
+            "string": "\u20ac$\u000F\u000aA'\u0042\u0022\u005c\\\"\/",
+            "literals": [null, true, false]
+        }"#;
+        let value: Value = serde_json::from_str(input).unwrap();
+        let canonical = r#"{"literals":[null,true,false],"numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27],"string":"€$\u000f\nA'B\"\\\\\"/"}"#;
+
+        assert_eq!(to_string(&value), canonical);
+        assert!(is_canonical(canonical.as_bytes()));
+        assert!(!is_canonical(input.as_bytes()));
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(number(0.0), "0");
+        assert_eq!(number(-0.0), "0");
+        assert_eq!(number(-1.5), "-1.5");
+        assert_eq!(number(9007199254740991.0), "9007199254740991");
+        assert_eq!(number(1e20), "100000000000000000000");
+        assert_eq!(number(1e21), "1e+21");
+        assert_eq!(number(1.5e300), "1.5e+300");
+        assert_eq!(number(0.000001), "0.000001");
+        assert_eq!(number(1e-7), "1e-7");
+        assert_eq!(number(1.25e-7), "1.25e-7");
+        assert_eq!(number(5e-324), "5e-324");
+    }
+
+    #[test]
+    fn test_sorting() {
+        // RFC 8785, section 3.2.3: names sort by UTF-16 code units, not by UTF-8 bytes
+        let value = json!({
+            "\u{20ac}": "Euro Sign",
+            "\r": "Carriage Return",
+            "\u{fb33}": "Hebrew Letter Dalet With Dagesh",
+            "1": "One",
+            "\u{1f600}": "Emoji: Grinning Face",
+            "\u{80}": "Control",
+            "\u{f6}": "Latin Small Letter O With Diaeresis"
+        });
+        let canonical = to_string(&value);
+        let positions = [
+            "\\r",
+            "1",
+            "\u{80}",
+            "\u{f6}",
+            "\u{20ac}",
+            "\u{1f600}",
+            "\u{fb33}",
+        ]
+        .map(|name| canonical.find(&format!("\"{name}\":")).unwrap());
+        assert!(positions.is_sorted());
+    }
+
+    #[test]
+    fn test_hash() {
+        let a = json!({"b": 1, "a": [1.0, "x"]});
+        let b: Value = serde_json::from_str(r#"{ "a": [1, "x"], "b": 1.00 }"#).unwrap();
+        assert_eq!(
+            canonical_json_hash(&a).unwrap(),
+            canonical_json_hash(&b).unwrap()
+        );
+        assert_eq!(to_vec(&a).unwrap(), br#"{"a":[1,"x"],"b":1}"#);
+        assert_ne!(
+            canonical_json_hash(&a).unwrap(),
+            canonical_json_hash(&json!({"b": 2, "a": [1, "x"]})).unwrap()
+        );
+    }
+}
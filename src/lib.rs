@@ -18,17 +18,97 @@
 #[cfg(not(any(feature = "std")))]
 compile_error!("`std` must be enabled");
 
-use bitcoin::{Transaction, Txid, taproot::LeafVersion};
+use bitcoin::script::{Instruction, PushBytes};
+use bitcoin::{Script, Transaction, TxIn, TxOut, Txid, Witness, taproot::LeafVersion};
+use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::fmt;
+use std::ops::ControlFlow;
 use std::str::FromStr;
 
+pub mod anchor;
+pub mod annex;
+#[cfg(feature = "async")]
+pub mod async_resolver;
+pub mod attestation;
+pub mod budget;
+pub mod cache;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "cbor")]
+pub mod cbor;
+pub mod commitments;
+pub mod compress;
+pub mod ctv;
+pub mod descriptor;
+pub mod diff;
+#[cfg(feature = "backend-electrum")]
+pub mod electrum;
 pub mod envelope;
+mod error;
+pub mod ext;
+pub mod extractor;
+pub mod feebump;
+pub mod files;
+pub mod follower;
+pub mod funding;
+pub mod header_chain;
+pub mod hexdump;
+pub mod index;
+#[cfg(feature = "jcs")]
+pub mod jcs;
+pub mod media;
+pub mod merkle;
 pub mod message;
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
+pub mod numbering;
+pub mod offer;
+pub mod pipeline;
+pub mod planner;
+pub mod policy;
+pub mod prefilter;
+pub mod protocols;
+pub mod psbt;
+pub mod receipt;
+pub mod report;
+pub mod resolver;
+pub mod reveal;
+#[cfg(feature = "backend-rpc")]
+pub mod rpc;
+#[cfg(feature = "serde")]
+pub mod schema;
+#[cfg(feature = "store")]
+pub mod store;
+pub mod strip;
+pub mod template;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod uri;
 pub mod varint;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod watch;
+#[cfg(feature = "miniscript")]
+pub mod witness_script;
+#[cfg(feature = "zmq")]
+pub mod zmq;
 
-/// The initial byte in a data-carrying taproot annex
+pub use error::Error;
+
+/// The tag of the data record in a taproot annex (see [`annex`])
 pub const TAPROOT_ANNEX_DATA_TAG: u8 = 0;
 
+/// The tag of the batch record in a taproot annex, which holds several payloads (see
+/// [`annex`])
+pub const TAPROOT_ANNEX_BATCH_TAG: u8 = 1;
+
+/// The tag used to hash embedding payloads into content hashes
+pub const CONTENT_HASH_TAG: &str = "bitcoin-embed/content";
+
+/// The human-readable part of bech32m-encoded embedding ids
+pub const EMBEDDING_ID_HRP: &str = "embd";
+
 /// The script type used by an envelope
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum ScriptType {
@@ -47,6 +127,21 @@ pub enum EmbeddingType {
     TaprootAnnex,
     /// An `OP_FALSE OP_IF <DATA> OP_ENDIF` envelope
     WitnessEnvelope(ScriptType),
+    /// Fake public keys in a bare multisig output
+    BareMultisig,
+}
+
+impl EmbeddingType {
+    /// Returns the two-letter code used in the string form of an [`EmbeddingId`]
+    pub fn code(&self) -> &'static str {
+        match self {
+            EmbeddingType::OpReturn => "rt",
+            EmbeddingType::TaprootAnnex => "ta",
+            EmbeddingType::WitnessEnvelope(ScriptType::Legacy) => "le",
+            EmbeddingType::WitnessEnvelope(ScriptType::Tapscript) => "te",
+            EmbeddingType::BareMultisig => "bm",
+        }
+    }
 }
 
 /// The location where data exists in a transaction
@@ -58,10 +153,17 @@ pub enum EmbeddingLocation {
         output: usize,
     },
 
-    /// A taproot annex with the input index
+    /// A taproot annex with the input index, payload index, and record tag
     TaprootAnnex {
         /// The index of the transaction input
         input: usize,
+        /// The index of the payload: the position of its record among the annex's records, plus
+        /// its position within a batch record (see [`annex::indexed_payloads_with`]). An annex
+        /// holding only a data or batch record numbers its payloads from 0.
+        index: usize,
+        /// The tag of the record holding the payload: a data tag, such as
+        /// [`TAPROOT_ANNEX_DATA_TAG`], or [`TAPROOT_ANNEX_BATCH_TAG`]
+        tag: u8,
     },
 
     /// An `OP_FALSE OP_IF <DATA> OP_ENDIF` envelope with the input index, envelope index, and data push sizes
@@ -79,6 +181,13 @@ pub enum EmbeddingLocation {
         /// The script type
         script_type: ScriptType,
     },
+
+    /// Fake public keys in a bare `OP_1 <keys> OP_N OP_CHECKMULTISIG` output with the output
+    /// index
+    BareMultisig {
+        /// The index of the transaction output
+        output: usize,
+    },
 }
 
 impl EmbeddingLocation {
@@ -90,6 +199,21 @@ impl EmbeddingLocation {
             EmbeddingLocation::WitnessEnvelope { script_type, .. } => {
                 EmbeddingType::WitnessEnvelope(*script_type)
             }
+            EmbeddingLocation::BareMultisig { .. } => EmbeddingType::BareMultisig,
+        }
+    }
+
+    fn sort_key(&self) -> (usize, usize, &[usize], u8) {
+        match self {
+            EmbeddingLocation::OpReturn { output } => (*output, 0, &[], 0),
+            EmbeddingLocation::TaprootAnnex { input, index, tag } => (*input, *index, &[], *tag),
+            EmbeddingLocation::WitnessEnvelope {
+                input,
+                index,
+                pushes,
+                ..
+            } => (*input, *index, pushes, 0),
+            EmbeddingLocation::BareMultisig { output } => (*output, 0, &[], 0),
         }
     }
 }
@@ -120,6 +244,145 @@ pub enum EmbeddingIdError {
     InvalidType,
     /// Invalid index value
     InvalidIndex,
+    /// Invalid bech32m encoding or checksum
+    InvalidEncoding,
+}
+
+impl EmbeddingId {
+    /// Returns the checksummed bech32m encoding of [`EmbeddingId::to_bytes`] (e.g. `embd1...`)
+    pub fn to_bech32m(&self) -> String {
+        let hrp = bitcoin::bech32::Hrp::parse_unchecked(EMBEDDING_ID_HRP);
+        bitcoin::bech32::encode::<bitcoin::bech32::Bech32m>(hrp, &self.to_bytes())
+            .expect("encoded ids are well under the bech32m length limit")
+    }
+
+    /// Returns the binary encoding of the id: the txid (in internal byte order), a type byte
+    /// (0 for OP_RETURN, 1 for Taproot annex, 2 for Legacy envelope, 3 for Tapscript envelope, 4
+    /// for bare multisig), the LEB128 index, and for envelopes the LEB128 sub-index. Annexes
+    /// include the sub-index only if it is not zero.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        use bitcoin::hashes::Hash;
+
+        let mut data = self.txid.to_byte_array().to_vec();
+
+        data.push(match self.embedding_type {
+            EmbeddingType::OpReturn => 0,
+            EmbeddingType::TaprootAnnex => 1,
+            EmbeddingType::WitnessEnvelope(ScriptType::Legacy) => 2,
+            EmbeddingType::WitnessEnvelope(ScriptType::Tapscript) => 3,
+            EmbeddingType::BareMultisig => 4,
+        });
+
+        varint::encode_to_vec(self.index as u128, &mut data);
+
+        match (self.embedding_type, self.sub_index) {
+            (EmbeddingType::WitnessEnvelope(_), sub_index) => {
+                varint::encode_to_vec(sub_index.unwrap_or(0) as u128, &mut data);
+            }
+            (EmbeddingType::TaprootAnnex, Some(sub_index)) if sub_index > 0 => {
+                varint::encode_to_vec(sub_index as u128, &mut data);
+            }
+            _ => {}
+        }
+
+        data
+    }
+
+    /// Re-extracts the referenced embedding from a transaction, parsing only the referenced
+    /// input or output. Returns `None` if the txid does not match or no such embedding exists.
+    /// An annex payload is found by its index, whatever data tags it was extracted with.
+    pub fn locate(&self, tx: &Transaction) -> Option<Embedding> {
+        if tx.compute_txid() != self.txid {
+            return None;
+        }
+
+        Embedding::extract_indexed(
+            self.txid,
+            tx,
+            self.embedding_type,
+            self.index,
+            self.sub_index,
+        )
+    }
+
+    /// Returns an `embed:` URI referencing the embedding
+    pub fn to_uri(&self) -> String {
+        uri::EmbeddingUri::new(*self).to_string()
+    }
+
+    /// Parses an `embed:` URI, including any network and byte range parameters
+    pub fn from_uri(s: &str) -> Result<uri::EmbeddingUri, EmbeddingIdError> {
+        uri::EmbeddingUri::from_str(s)
+    }
+
+    /// Decodes an id from its bech32m encoding
+    pub fn from_bech32m(s: &str) -> Result<Self, EmbeddingIdError> {
+        use bitcoin::bech32::{Bech32m, primitives::decode::CheckedHrpstring};
+
+        let checked =
+            CheckedHrpstring::new::<Bech32m>(s).map_err(|_| EmbeddingIdError::InvalidEncoding)?;
+
+        if !checked
+            .hrp()
+            .as_str()
+            .eq_ignore_ascii_case(EMBEDDING_ID_HRP)
+        {
+            return Err(EmbeddingIdError::InvalidEncoding);
+        }
+
+        Self::from_bytes(&checked.byte_iter().collect::<Vec<u8>>())
+    }
+
+    /// Parses the binary encoding produced by [`EmbeddingId::to_bytes`]
+    pub fn from_bytes(data: &[u8]) -> Result<Self, EmbeddingIdError> {
+        use bitcoin::hashes::Hash;
+
+        if data.len() < 33 {
+            return Err(EmbeddingIdError::InvalidFormat);
+        }
+
+        let txid = Txid::from_slice(&data[..32]).map_err(|_| EmbeddingIdError::InvalidTxid)?;
+
+        let embedding_type = match data[32] {
+            0 => EmbeddingType::OpReturn,
+            1 => EmbeddingType::TaprootAnnex,
+            2 => EmbeddingType::WitnessEnvelope(ScriptType::Legacy),
+            3 => EmbeddingType::WitnessEnvelope(ScriptType::Tapscript),
+            4 => EmbeddingType::BareMultisig,
+            _ => return Err(EmbeddingIdError::InvalidType),
+        };
+
+        let mut rest = &data[33..];
+        let read_index = |rest: &mut &[u8]| -> Result<usize, EmbeddingIdError> {
+            let (n, size) =
+                varint::decode_strict(rest).map_err(|_| EmbeddingIdError::InvalidIndex)?;
+            *rest = &rest[size..];
+            usize::try_from(n).map_err(|_| EmbeddingIdError::InvalidIndex)
+        };
+
+        let index = read_index(&mut rest)?;
+        let sub_index = match embedding_type {
+            EmbeddingType::WitnessEnvelope(_) => Some(read_index(&mut rest)?),
+            // Zero is omitted, so an explicit zero is not canonical
+            EmbeddingType::TaprootAnnex if !rest.is_empty() => match read_index(&mut rest)? {
+                0 => return Err(EmbeddingIdError::InvalidFormat),
+                sub_index => Some(sub_index),
+            },
+            _ => None,
+        };
+
+        if !rest.is_empty() {
+            return Err(EmbeddingIdError::InvalidFormat);
+        }
+
+        Ok(Self {
+            txid,
+            embedding_type,
+            index,
+            sub_index,
+            _private: false,
+        })
+    }
 }
 
 /// A struct containing data and its location in a transaction
@@ -133,14 +396,90 @@ pub struct Embedding {
     pub location: EmbeddingLocation,
 }
 
+/// An embedding borrowing its data from a transaction or an extractor's buffers, as passed to
+/// [`Embedding::visit`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmbeddingRef<'a> {
+    /// The embedding id
+    pub id: EmbeddingId,
+    /// The data
+    pub bytes: &'a [u8],
+    /// The sizes of the data pushes of an envelope, or empty for other embedding types
+    pub pushes: &'a [usize],
+    /// The record tag of an annex payload, or 0 for other embedding types
+    pub tag: u8,
+}
+
+impl<'a> EmbeddingRef<'a> {
+    /// Returns the embedding type
+    pub fn to_type(&self) -> EmbeddingType {
+        self.id.embedding_type
+    }
+
+    /// Returns the location in the transaction
+    pub fn location(&self) -> EmbeddingLocation {
+        let index = self.id.index;
+        match self.id.embedding_type {
+            EmbeddingType::OpReturn => EmbeddingLocation::OpReturn { output: index },
+            EmbeddingType::TaprootAnnex => EmbeddingLocation::TaprootAnnex {
+                input: index,
+                index: self.id.sub_index.unwrap_or(0),
+                tag: self.tag,
+            },
+            EmbeddingType::BareMultisig => EmbeddingLocation::BareMultisig { output: index },
+            EmbeddingType::WitnessEnvelope(script_type) => EmbeddingLocation::WitnessEnvelope {
+                input: index,
+                index: self.id.sub_index.unwrap_or(0),
+                pushes: self.pushes.to_vec(),
+                script_type,
+            },
+        }
+    }
+
+    /// Returns the payload, as in [`Embedding::payload`]. Only an `OP_RETURN` with several
+    /// pushes is copied.
+    pub fn payload(&self) -> Cow<'a, [u8]> {
+        match self.id.embedding_type {
+            EmbeddingType::OpReturn => op_return_payload(self.bytes),
+            _ => Cow::Borrowed(self.bytes),
+        }
+    }
+
+    /// Copies the data into an owned [`Embedding`]
+    pub fn to_embedding(&self) -> Embedding {
+        Embedding {
+            bytes: self.bytes.to_vec(),
+            txid: self.id.txid,
+            location: self.location(),
+        }
+    }
+}
+
 impl Embedding {
+    /// Returns the embedding as an [`EmbeddingRef`] borrowing its data
+    pub fn to_ref(&self) -> EmbeddingRef<'_> {
+        let (pushes, tag) = match &self.location {
+            EmbeddingLocation::WitnessEnvelope { pushes, .. } => (pushes.as_slice(), 0),
+            EmbeddingLocation::TaprootAnnex { tag, .. } => (&[][..], *tag),
+            _ => (&[][..], 0),
+        };
+
+        EmbeddingRef {
+            id: self.id(),
+            bytes: &self.bytes,
+            pushes,
+            tag,
+        }
+    }
+
     /// Returns the embedding id
     pub fn id(&self) -> EmbeddingId {
         let embedding_type = self.location.to_type();
 
         let (index, sub_index) = match self.location {
             EmbeddingLocation::OpReturn { output } => (output, None),
-            EmbeddingLocation::TaprootAnnex { input } => (input, None),
+            EmbeddingLocation::BareMultisig { output } => (output, None),
+            EmbeddingLocation::TaprootAnnex { input, index, .. } => (input, annex_sub_index(index)),
             EmbeddingLocation::WitnessEnvelope { input, index, .. } => (input, Some(index)),
         };
 
@@ -158,97 +497,502 @@ impl Embedding {
         self.location.to_type()
     }
 
+    /// Returns the tagged SHA-256 of the payload, using [`CONTENT_HASH_TAG`] as the tag. Unlike
+    /// the id, this is the same wherever the payload is embedded.
+    pub fn content_hash(&self) -> [u8; 32] {
+        message::tagged_hash(CONTENT_HASH_TAG, &self.payload())
+    }
+
+    /// Returns whether the payload has the given content hash
+    pub fn verify_content(&self, expected_hash: &[u8; 32]) -> bool {
+        self.content_hash() == *expected_hash
+    }
+
+    /// Returns the payload: the concatenated pushes of an `OP_RETURN` output, or the bytes of
+    /// any other embedding. An `OP_RETURN` with non-push opcodes is returned unchanged.
+    pub fn payload(&self) -> Cow<'_, [u8]> {
+        match self.location {
+            EmbeddingLocation::OpReturn { .. } => op_return_payload(&self.bytes),
+            _ => Cow::Borrowed(&self.bytes),
+        }
+    }
+
+    /// Returns the payload as UTF-8 text
+    pub fn as_utf8(&self) -> Result<Cow<'_, str>, std::str::Utf8Error> {
+        match self.payload() {
+            Cow::Borrowed(bytes) => std::str::from_utf8(bytes).map(Cow::Borrowed),
+            Cow::Owned(bytes) => String::from_utf8(bytes)
+                .map(Cow::Owned)
+                .map_err(|e| e.utf8_error()),
+        }
+    }
+
+    /// Deserializes the payload as JSON (`serde` feature)
+    #[cfg(feature = "serde")]
+    pub fn as_json<T: serde::de::DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_slice(&self.payload())
+    }
+
+    /// Returns a display adapter rendering the data bytes as an `xxd`-style hex dump
+    pub fn hex_dump(&self) -> hexdump::HexDump<'_> {
+        hexdump::HexDump::new(&self.bytes)
+    }
+
+    /// Returns the payload split at its push boundaries: each push of a witness envelope or
+    /// `OP_RETURN` output, or the whole payload of any other embedding. Yields nothing for an
+    /// envelope whose push sizes are inconsistent (see [`Embedding::has_consistent_pushes`]),
+    /// and the bytes unchanged for an `OP_RETURN` with non-push opcodes.
+    pub fn chunks(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        let chunks = match &self.location {
+            EmbeddingLocation::WitnessEnvelope { pushes, .. } if self.has_consistent_pushes() => {
+                let mut offset = 0;
+                pushes
+                    .iter()
+                    .map(|&size| {
+                        offset += size;
+                        &self.bytes[(offset - size)..offset]
+                    })
+                    .collect()
+            }
+            EmbeddingLocation::WitnessEnvelope { .. } => Vec::new(),
+            EmbeddingLocation::OpReturn { .. } => match op_return_pushes(&self.bytes) {
+                Some(pushes) => pushes
+                    .into_iter()
+                    .map(|(_, push)| push.as_bytes())
+                    .collect(),
+                None => vec![self.bytes.as_slice()],
+            },
+            _ => vec![self.bytes.as_slice()],
+        };
+        chunks.into_iter()
+    }
+
+    /// Returns whether the push sizes of a witness envelope sum to the length of its bytes.
+    /// Always true for other embedding types.
+    pub fn has_consistent_pushes(&self) -> bool {
+        match &self.location {
+            EmbeddingLocation::WitnessEnvelope { pushes, .. } => pushes
+                .iter()
+                .try_fold(0usize, |total, &size| total.checked_add(size))
+                .is_some_and(|total| total == self.bytes.len()),
+            _ => true,
+        }
+    }
+
+    /// Returns the index of the first push of an `OP_RETURN` output that does not use the
+    /// minimal encoding (see [`envelope::is_minimal_push`]), if any. Pushes are counted as in
+    /// [`Embedding::chunks`], so an `OP_RETURN` with non-push opcodes, including
+    /// `OP_1NEGATE` and `OP_1` through `OP_16`, has no pushes to check. Extracted envelopes keep
+    /// only their push sizes, so this returns `None` for them and other embedding types; check
+    /// envelopes with [`envelope::Envelope::first_non_minimal_push`] or
+    /// [`envelope::from_script_minimal`].
+    pub fn first_non_minimal_push(&self) -> Option<usize> {
+        let EmbeddingLocation::OpReturn { .. } = self.location else {
+            return None;
+        };
+
+        op_return_pushes(&self.bytes)?
+            .into_iter()
+            .position(|(position, push)| {
+                !envelope::is_minimal_push(push.as_bytes(), self.bytes[position].into())
+            })
+    }
+
+    /// Parses a witness envelope using the ordinals field/body layout. Returns `None` for
+    /// other embedding types, or if the push sizes are inconsistent.
+    pub fn fields(&self) -> Option<envelope::FieldEnvelope> {
+        let EmbeddingLocation::WitnessEnvelope { .. } = self.location else {
+            return None;
+        };
+        if !self.has_consistent_pushes() {
+            return None;
+        }
+
+        envelope::FieldEnvelope::from_pushes(&self.chunks().collect::<Vec<_>>())
+    }
+
     /// Extracts the tape in a transaction
     pub fn from_transaction(tx: &Transaction) -> Vec<Self> {
+        Self::from_transaction_with_prevouts(tx, &[])
+    }
+
+    /// Extracts the tape in a transaction, using the outputs spent by each input to classify
+    /// witness data. Envelopes are only extracted from inputs spending P2TR (Tapscript) or P2WSH
+    /// (Legacy) outputs, and annexes only from inputs spending P2TR outputs. Inputs without a
+    /// corresponding prevout are classified from the witness alone.
+    pub fn from_transaction_with_prevouts(tx: &Transaction, prevouts: &[TxOut]) -> Vec<Self> {
         let mut embeddings = Vec::new();
         let txid = tx.compute_txid();
 
         // OP_RETURN
         for (output, txout) in tx.output.iter().enumerate() {
-            if !txout.script_pubkey.is_op_return() {
-                continue;
-            }
+            embeddings.extend(Self::from_output(txid, output, txout));
+        }
 
-            let location = EmbeddingLocation::OpReturn { output };
+        // Witness Envelope
+        for (input, txin) in tx.input.iter().enumerate() {
+            let prevout = prevouts.get(input);
+            embeddings.extend(Self::from_witness_envelopes(txid, input, txin, prevout));
+        }
 
-            embeddings.push(Self {
-                bytes: txout.script_pubkey.to_bytes()[1..].to_vec(),
+        // Annex
+        for (input, txin) in tx.input.iter().enumerate() {
+            let prevout = prevouts.get(input);
+            embeddings.extend(Self::from_annex(
                 txid,
-                location,
-            });
+                input,
+                txin,
+                prevout,
+                &annex::DataTags::DEFAULT,
+            ));
         }
 
-        // Witness Envelope
-        for (input, txin) in tx.input.iter().enumerate() {
-            let mut script = None;
-            let mut script_type = None;
-            let witness = &txin.witness;
-
-            // Tapscript
-            if let Some(leaf_script) = witness.taproot_leaf_script() {
-                if leaf_script.version == LeafVersion::TapScript {
-                    script = Some(leaf_script.script);
-                    script_type = Some(ScriptType::Tapscript);
-                }
-            }
+        embeddings
+    }
 
-            // P2WSH (no tapscript, no annex, and at least 2 elements)
-            if script.is_none() && witness.taproot_annex().is_none() && witness.len() > 1 {
-                if let Some(witness_script) = witness.witness_script() {
-                    script = Some(witness_script);
-                    script_type = Some(ScriptType::Legacy);
-                }
-            }
+    /// Calls `f` with each embedding in a transaction, in the order of
+    /// [`Embedding::from_transaction`], until it returns [`ControlFlow::Break`]. The embeddings
+    /// borrow their data, so nothing is copied unless `f` copies it. Use
+    /// [`extractor::Extractor::visit`] to reuse buffers across transactions.
+    pub fn visit<B>(
+        tx: &Transaction,
+        f: impl FnMut(EmbeddingRef<'_>) -> ControlFlow<B>,
+    ) -> ControlFlow<B> {
+        extractor::Extractor::new().visit(tx, &[], f)
+    }
 
-            let (Some(script), Some(script_type)) = (script, script_type) else {
-                continue;
-            };
+    /// Extracts the embedding at `location` in a transaction, parsing only the referenced input
+    /// or output. Returns `None` if there is no embedding of that type there. The push sizes of
+    /// an envelope location are not compared; the returned embedding has the sizes found in the
+    /// transaction. An annex payload is found by its index, and has the tag found in the
+    /// transaction.
+    pub fn extract_at(tx: &Transaction, location: &EmbeddingLocation) -> Option<Self> {
+        let (index, sub_index) = match *location {
+            EmbeddingLocation::OpReturn { output } => (output, None),
+            EmbeddingLocation::BareMultisig { output } => (output, None),
+            EmbeddingLocation::TaprootAnnex { input, index, .. } => (input, annex_sub_index(index)),
+            EmbeddingLocation::WitnessEnvelope { input, index, .. } => (input, Some(index)),
+        };
+
+        Self::extract_indexed(tx.compute_txid(), tx, location.to_type(), index, sub_index)
+    }
+
+    /// Returns whether the control block of a tapscript envelope's input commits its leaf to
+    /// the output it spends, as in [`descriptor::verify_tapscript_commitment`], given `tx` and
+    /// the outputs it spends. Returns `None` for other embedding types, or if `tx` is not the
+    /// embedding's transaction or the prevout is missing.
+    pub fn verify_commitment(&self, tx: &Transaction, prevouts: &[TxOut]) -> Option<bool> {
+        let EmbeddingLocation::WitnessEnvelope {
+            input,
+            script_type: ScriptType::Tapscript,
+            ..
+        } = self.location
+        else {
+            return None;
+        };
+        if tx.compute_txid() != self.txid {
+            return None;
+        }
 
-            let envelopes = envelope::from_script(script);
+        Some(descriptor::verify_tapscript_commitment(
+            &tx.input.get(input)?.witness,
+            &prevouts.get(input)?.script_pubkey,
+        ))
+    }
 
-            for (index, envelope) in envelopes.into_iter().enumerate() {
-                let mut bytes = Vec::new();
-                let mut pushes = Vec::new();
+    fn extract_indexed(
+        txid: Txid,
+        tx: &Transaction,
+        embedding_type: EmbeddingType,
+        index: usize,
+        sub_index: Option<usize>,
+    ) -> Option<Self> {
+        match embedding_type {
+            EmbeddingType::OpReturn => Self::from_output(txid, index, tx.output.get(index)?),
+            EmbeddingType::TaprootAnnex => {
+                let annex = tx.input.get(index)?.witness.taproot_annex()?;
+                let sub_index = sub_index.unwrap_or(0);
+                let payload = annex::payload_at(annex, sub_index)?;
+                Some(Self {
+                    bytes: payload.value.to_vec(),
+                    txid,
+                    location: EmbeddingLocation::TaprootAnnex {
+                        input: index,
+                        index: sub_index,
+                        tag: payload.tag,
+                    },
+                })
+            }
+            EmbeddingType::WitnessEnvelope(script_type) => {
+                let sub_index = sub_index?;
+                let txin = tx.input.get(index)?;
+                let (script, actual_type) = Self::envelope_script(&txin.witness, None)?;
 
-                for chunk in envelope {
-                    bytes.extend(chunk.clone());
-                    pushes.push(chunk.len());
+                if actual_type != script_type {
+                    return None;
                 }
 
-                let location = EmbeddingLocation::WitnessEnvelope {
-                    input,
+                let envelope = envelope::from_script(script).into_iter().nth(sub_index)?;
+                Some(Self::from_envelope(
+                    txid,
                     index,
-                    pushes,
+                    sub_index,
+                    envelope,
                     script_type,
-                };
+                ))
+            }
+            EmbeddingType::BareMultisig => {
+                Self::from_multisig_output(txid, index, tx.output.get(index)?)
+            }
+        }
+    }
 
-                embeddings.push(Self {
-                    bytes,
-                    txid,
-                    location,
-                });
+    /// Extracts data from the fake public keys of bare multisig outputs, as used by
+    /// Counterparty and Stamps. Matches `OP_1 <key>... OP_N OP_CHECKMULTISIG` outputs with two
+    /// or three compressed keys, treating the last key as the spender's real key. The data is
+    /// bytes 1 through 31 of each remaining key, concatenated; protocol-level obfuscation
+    /// (e.g. Counterparty's ARC4 keyed by the first input's txid) is not removed.
+    ///
+    /// This is not included in [`Embedding::from_transaction`], since ordinary bare multisig
+    /// outputs also match.
+    pub fn from_bare_multisig(tx: &Transaction) -> Vec<Self> {
+        let txid = tx.compute_txid();
+
+        tx.output
+            .iter()
+            .enumerate()
+            .filter_map(|(output, txout)| Self::from_multisig_output(txid, output, txout))
+            .collect()
+    }
+
+    fn from_multisig_output(txid: Txid, output: usize, txout: &TxOut) -> Option<Self> {
+        use bitcoin::opcodes::all::{OP_CHECKMULTISIG, OP_PUSHNUM_1, OP_PUSHNUM_2, OP_PUSHNUM_3};
+        use bitcoin::script::Instruction;
+
+        let mut instructions = txout.script_pubkey.instructions();
+        if instructions.next()?.ok()? != Instruction::Op(OP_PUSHNUM_1) {
+            return None;
+        }
+
+        let mut keys = Vec::new();
+        let count = loop {
+            match instructions.next()?.ok()? {
+                Instruction::PushBytes(key) if key.len() == 33 => keys.push(key.as_bytes()),
+                Instruction::Op(OP_PUSHNUM_2) => break 2,
+                Instruction::Op(OP_PUSHNUM_3) => break 3,
+                _ => return None,
             }
+        };
+
+        if count != keys.len()
+            || instructions.next()?.ok()? != Instruction::Op(OP_CHECKMULTISIG)
+            || instructions.next().is_some()
+        {
+            return None;
         }
 
-        // Annex
-        for (input, txin) in tx.input.iter().enumerate() {
-            if let Some(annex) = txin.witness.taproot_annex() {
-                if annex.len() > 2 && annex[1] == TAPROOT_ANNEX_DATA_TAG {
-                    let location = EmbeddingLocation::TaprootAnnex { input };
-
-                    embeddings.push(Self {
-                        bytes: annex[2..].to_vec(),
-                        txid,
-                        location,
-                    });
-                }
+        Some(Self {
+            bytes: keys[..keys.len() - 1]
+                .iter()
+                .flat_map(|key| &key[1..32])
+                .copied()
+                .collect(),
+            txid,
+            location: EmbeddingLocation::BareMultisig { output },
+        })
+    }
+
+    fn from_output(txid: Txid, output: usize, txout: &TxOut) -> Option<Self> {
+        if !txout.script_pubkey.is_op_return() {
+            return None;
+        }
+
+        Some(Self {
+            bytes: txout.script_pubkey.as_bytes()[1..].to_vec(),
+            txid,
+            location: EmbeddingLocation::OpReturn { output },
+        })
+    }
+
+    fn from_witness_envelopes(
+        txid: Txid,
+        input: usize,
+        txin: &TxIn,
+        prevout: Option<&TxOut>,
+    ) -> Vec<Self> {
+        let Some((script, script_type)) = Self::envelope_script(&txin.witness, prevout) else {
+            return Vec::new();
+        };
+
+        envelope::from_script(script)
+            .into_iter()
+            .enumerate()
+            .map(|(index, envelope)| Self::from_envelope(txid, input, index, envelope, script_type))
+            .collect()
+    }
+
+    fn from_envelope(
+        txid: Txid,
+        input: usize,
+        index: usize,
+        envelope: envelope::Envelope,
+        script_type: ScriptType,
+    ) -> Self {
+        let bytes = envelope.concat();
+        let pushes = envelope.pushes.iter().map(Vec::len).collect();
+
+        let location = EmbeddingLocation::WitnessEnvelope {
+            input,
+            index,
+            pushes,
+            script_type,
+        };
+
+        Self {
+            bytes,
+            txid,
+            location,
+        }
+    }
+
+    fn envelope_script<'a>(
+        witness: &'a Witness,
+        prevout: Option<&TxOut>,
+    ) -> Option<(&'a Script, ScriptType)> {
+        if let Some(prevout) = prevout {
+            return if prevout.script_pubkey.is_p2tr() {
+                witness
+                    .taproot_leaf_script()
+                    .filter(|leaf_script| leaf_script.version == LeafVersion::TapScript)
+                    .map(|leaf_script| (leaf_script.script, ScriptType::Tapscript))
+            } else if prevout.script_pubkey.is_p2wsh() {
+                witness
+                    .witness_script()
+                    .map(|witness_script| (witness_script, ScriptType::Legacy))
+            } else {
+                None
+            };
+        }
+
+        // Tapscript
+        if let Some(leaf_script) = witness.taproot_leaf_script() {
+            if leaf_script.version == LeafVersion::TapScript {
+                return Some((leaf_script.script, ScriptType::Tapscript));
             }
         }
 
-        embeddings
+        // P2WSH (no tapscript, no annex, and at least 2 elements)
+        if witness.taproot_annex().is_none() && witness.len() > 1 {
+            if let Some(witness_script) = witness.witness_script() {
+                return Some((witness_script, ScriptType::Legacy));
+            }
+        }
+
+        None
+    }
+
+    fn from_annex(
+        txid: Txid,
+        input: usize,
+        txin: &TxIn,
+        prevout: Option<&TxOut>,
+        data_tags: &annex::DataTags,
+    ) -> Vec<Self> {
+        if prevout.is_some_and(|prevout| !prevout.script_pubkey.is_p2tr()) {
+            return Vec::new();
+        }
+
+        Self::annex_payloads(&txin.witness, data_tags)
+            .into_iter()
+            .map(|(index, payload)| Self {
+                bytes: payload.value.to_vec(),
+                txid,
+                location: EmbeddingLocation::TaprootAnnex {
+                    input,
+                    index,
+                    tag: payload.tag,
+                },
+            })
+            .collect()
+    }
+
+    fn annex_data(witness: &Witness) -> Option<&[u8]> {
+        annex::data(witness.taproot_annex()?)
+    }
+
+    fn annex_payloads<'a>(
+        witness: &'a Witness,
+        data_tags: &annex::DataTags,
+    ) -> Vec<(usize, annex::AnnexRecord<'a>)> {
+        witness
+            .taproot_annex()
+            .map(|annex| annex::indexed_payloads_with(annex, data_tags))
+            .unwrap_or_default()
+    }
+}
+
+/// Embedding types are ordered by type code: `bm` (bare multisig), `le` (Legacy envelope), `rt`
+/// (OP_RETURN), `ta` (Taproot annex), then `te` (Tapscript envelope)
+impl Ord for EmbeddingType {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.code().cmp(other.code())
+    }
+}
+
+impl PartialOrd for EmbeddingType {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Locations are ordered by type code, then input or output index, then envelope or annex
+/// payload index, then push sizes or annex tag
+impl Ord for EmbeddingLocation {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.to_type()
+            .cmp(&other.to_type())
+            .then_with(|| self.sort_key().cmp(&other.sort_key()))
+    }
+}
+
+impl PartialOrd for EmbeddingLocation {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Ids are ordered by txid (in internal byte order), then type code, then index, then
+/// sub-index, so that embeddings iterate deterministically across indexer implementations
+impl Ord for EmbeddingId {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.txid
+            .cmp(&other.txid)
+            .then_with(|| self.embedding_type.cmp(&other.embedding_type))
+            .then_with(|| self.index.cmp(&other.index))
+            .then_with(|| self.sub_index.cmp(&other.sub_index))
+    }
+}
+
+impl PartialOrd for EmbeddingId {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
+impl fmt::Display for EmbeddingIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmbeddingIdError::InvalidFormat => write!(f, "Invalid format"),
+            EmbeddingIdError::InvalidTxid => write!(f, "Invalid transaction ID"),
+            EmbeddingIdError::InvalidType => write!(f, "Invalid embedding type"),
+            EmbeddingIdError::InvalidIndex => write!(f, "Invalid index"),
+            EmbeddingIdError::InvalidEncoding => write!(f, "Invalid bech32m encoding"),
+        }
+    }
+}
+
+impl std::error::Error for EmbeddingIdError {}
+
 impl fmt::Display for ScriptType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -264,6 +1008,7 @@ impl fmt::Display for EmbeddingType {
             EmbeddingType::OpReturn => write!(f, "OP_RETURN"),
             EmbeddingType::TaprootAnnex => write!(f, "Taproot Annex"),
             EmbeddingType::WitnessEnvelope(script_type) => write!(f, "{script_type} Envelope"),
+            EmbeddingType::BareMultisig => write!(f, "Bare Multisig"),
         }
     }
 }
@@ -274,9 +1019,14 @@ impl fmt::Display for EmbeddingLocation {
             EmbeddingLocation::OpReturn { output } => {
                 write!(f, "OP_RETURN at output {output}")
             }
-            EmbeddingLocation::TaprootAnnex { input } => {
+            EmbeddingLocation::TaprootAnnex {
+                input, index: 0, ..
+            } => {
                 write!(f, "Taproot Annex at input {input}")
             }
+            EmbeddingLocation::TaprootAnnex { input, index, .. } => {
+                write!(f, "Taproot Annex at input {input} (index {index})")
+            }
             EmbeddingLocation::WitnessEnvelope {
                 input,
                 index,
@@ -285,6 +1035,9 @@ impl fmt::Display for EmbeddingLocation {
             } => {
                 write!(f, "{script_type} Envelope at input {input} (index {index})",)
             }
+            EmbeddingLocation::BareMultisig { output } => {
+                write!(f, "Bare Multisig at output {output}")
+            }
         }
     }
 }
@@ -295,8 +1048,14 @@ impl fmt::Display for EmbeddingId {
             EmbeddingType::OpReturn => {
                 write!(f, "{}:rt:{}", self.txid, self.index)
             }
-            EmbeddingType::TaprootAnnex => {
-                write!(f, "{}:ta:{}", self.txid, self.index)
+            EmbeddingType::TaprootAnnex => match self.sub_index {
+                Some(sub_index) if sub_index > 0 => {
+                    write!(f, "{}:ta:{}:{}", self.txid, self.index, sub_index)
+                }
+                _ => write!(f, "{}:ta:{}", self.txid, self.index),
+            },
+            EmbeddingType::BareMultisig => {
+                write!(f, "{}:bm:{}", self.txid, self.index)
             }
             EmbeddingType::WitnessEnvelope(script_type) => {
                 let type_code = match script_type {
@@ -324,6 +1083,13 @@ impl FromStr for EmbeddingId {
     type Err = EmbeddingIdError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let prefix = format!("{EMBEDDING_ID_HRP}1");
+        if s.get(..prefix.len())
+            .is_some_and(|p| p.eq_ignore_ascii_case(&prefix))
+        {
+            return Self::from_bech32m(s);
+        }
+
         let parts: Vec<&str> = s.split(':').collect();
 
         if parts.len() < 3 || parts.len() > 4 {
@@ -337,6 +1103,7 @@ impl FromStr for EmbeddingId {
             "ta" => EmbeddingType::TaprootAnnex,
             "le" => EmbeddingType::WitnessEnvelope(ScriptType::Legacy),
             "te" => EmbeddingType::WitnessEnvelope(ScriptType::Tapscript),
+            "bm" => EmbeddingType::BareMultisig,
             _ => return Err(EmbeddingIdError::InvalidType),
         };
 
@@ -344,7 +1111,7 @@ impl FromStr for EmbeddingId {
             .parse::<usize>()
             .map_err(|_| EmbeddingIdError::InvalidIndex)?;
 
-        let mut sub_index = if parts.len() == 4 {
+        let sub_index = if parts.len() == 4 {
             Some(
                 parts[3]
                     .parse::<usize>()
@@ -354,16 +1121,17 @@ impl FromStr for EmbeddingId {
             None
         };
 
-        // sub_index should only be present in envelopes
-        match embedding_type {
-            EmbeddingType::WitnessEnvelope(_) => {
-                if sub_index.is_none() {
-                    sub_index = Some(0);
-                }
+        // sub_index should only be present in envelopes and annexes
+        let sub_index = match embedding_type {
+            EmbeddingType::WitnessEnvelope(_) => Some(sub_index.unwrap_or(0)),
+            // Zero is omitted, so an explicit zero is not canonical
+            EmbeddingType::TaprootAnnex if sub_index == Some(0) => {
+                return Err(EmbeddingIdError::InvalidFormat);
             }
+            EmbeddingType::TaprootAnnex => sub_index,
             _ if sub_index.is_some() => return Err(EmbeddingIdError::InvalidFormat),
-            _ => {}
-        }
+            _ => None,
+        };
 
         Ok(Self {
             txid,
@@ -375,6 +1143,44 @@ impl FromStr for EmbeddingId {
     }
 }
 
+/// Returns the concatenated pushes of the `OP_RETURN` script `bytes` (after `OP_RETURN`),
+/// borrowing a single push, or `bytes` unchanged if it has non-push opcodes
+fn op_return_payload(bytes: &[u8]) -> Cow<'_, [u8]> {
+    let Some(pushes) = op_return_pushes(bytes) else {
+        return Cow::Borrowed(bytes);
+    };
+
+    match pushes.as_slice() {
+        [(_, push)] => Cow::Borrowed(push.as_bytes()),
+        pushes => Cow::Owned(
+            pushes
+                .iter()
+                .flat_map(|(_, push)| push.as_bytes())
+                .copied()
+                .collect(),
+        ),
+    }
+}
+
+/// Returns the byte position and data of each push in the bytes of an `OP_RETURN` output, or
+/// `None` if it has any other opcode (including `OP_1NEGATE` and `OP_1` through `OP_16`) or
+/// cannot be parsed
+fn op_return_pushes(bytes: &[u8]) -> Option<Vec<(usize, &PushBytes)>> {
+    Script::from_bytes(bytes)
+        .instruction_indices()
+        .map(|instruction| match instruction {
+            Ok((position, Instruction::PushBytes(push))) => Some((position, push)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the id sub-index of the annex payload at `index`, which is omitted for the first
+/// payload so that ids of single-payload annexes do not change
+fn annex_sub_index(index: usize) -> Option<usize> {
+    (index > 0).then_some(index)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -390,7 +1196,11 @@ mod tests {
     #[test]
     fn test_embedding_to_type() {
         let op_return_loc = EmbeddingLocation::OpReturn { output: 0 };
-        let annex_loc = EmbeddingLocation::TaprootAnnex { input: 1 };
+        let annex_loc = EmbeddingLocation::TaprootAnnex {
+            input: 1,
+            index: 0,
+            tag: 0,
+        };
         let legacy_loc = EmbeddingLocation::WitnessEnvelope {
             input: 2,
             index: 0,
@@ -404,26 +1214,198 @@ mod tests {
             script_type: ScriptType::Tapscript,
         };
 
-        assert_eq!(op_return_loc.to_type(), EmbeddingType::OpReturn);
-        assert_eq!(annex_loc.to_type(), EmbeddingType::TaprootAnnex);
+        assert_eq!(op_return_loc.to_type(), EmbeddingType::OpReturn);
+        assert_eq!(annex_loc.to_type(), EmbeddingType::TaprootAnnex);
+        assert_eq!(
+            legacy_loc.to_type(),
+            EmbeddingType::WitnessEnvelope(ScriptType::Legacy)
+        );
+        assert_eq!(
+            tapscript_loc.to_type(),
+            EmbeddingType::WitnessEnvelope(ScriptType::Tapscript)
+        );
+
+        for loc in [op_return_loc, annex_loc, legacy_loc, tapscript_loc] {
+            let embedding = Embedding {
+                bytes: vec![1, 2, 3],
+                txid: Txid::all_zeros(),
+                location: loc.clone(),
+            };
+
+            assert_eq!(embedding.to_type(), loc.to_type());
+        }
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::new_op_return(b"document"),
+            }],
+        };
+        let op_return = Embedding::from_transaction(&tx).remove(0);
+        assert_eq!(op_return.bytes[0], 0x08);
+        let annex = Embedding {
+            bytes: b"document".to_vec(),
+            txid: Txid::from_byte_array([1; 32]),
+            location: EmbeddingLocation::TaprootAnnex {
+                input: 0,
+                index: 0,
+                tag: 0,
+            },
+        };
+
+        let hash = op_return.content_hash();
+        assert_eq!(hash, annex.content_hash());
+        assert_eq!(hash, message::tagged_hash(CONTENT_HASH_TAG, b"document"));
+        assert_ne!(
+            hash,
+            bitcoin::hashes::sha256::Hash::hash(b"document").to_byte_array()
+        );
+
+        assert!(annex.verify_content(&hash));
+        assert!(!annex.verify_content(&[0; 32]));
+    }
+
+    #[test]
+    fn test_payload_as_utf8() {
+        let op_return = Embedding {
+            bytes: ScriptBuf::new_op_return(b"{\"p\":\"brc-20\"}").as_bytes()[1..].to_vec(),
+            txid: Txid::all_zeros(),
+            location: EmbeddingLocation::OpReturn { output: 0 },
+        };
+        assert_eq!(op_return.payload().as_ref(), b"{\"p\":\"brc-20\"}");
+        assert_eq!(op_return.as_utf8().unwrap(), "{\"p\":\"brc-20\"}");
+
+        let annex = Embedding {
+            bytes: vec![0x66, 0xff],
+            txid: Txid::all_zeros(),
+            location: EmbeddingLocation::TaprootAnnex {
+                input: 0,
+                index: 0,
+                tag: 0,
+            },
+        };
+        assert_eq!(annex.payload().as_ref(), &annex.bytes[..]);
+        assert_eq!(annex.as_utf8().unwrap_err().valid_up_to(), 1);
+
+        #[cfg(feature = "serde")]
+        {
+            let value: serde_json::Value = op_return.as_json().unwrap();
+            assert_eq!(value["p"], "brc-20");
+            assert!(annex.as_json::<serde_json::Value>().is_err());
+        }
+    }
+
+    #[test]
+    fn test_first_non_minimal_push() {
+        let op_return = |bytes: Vec<u8>| Embedding {
+            bytes,
+            txid: Txid::all_zeros(),
+            location: EmbeddingLocation::OpReturn { output: 0 },
+        };
+
+        let minimal = Builder::new()
+            .push_slice(b"abc")
+            .push_slice([0x80])
+            .push_slice([7; 64])
+            .into_script();
+        assert_eq!(op_return(minimal.to_bytes()).first_non_minimal_push(), None);
+
+        // <"abc"> OP_PUSHBYTES_1 <7> is pushed as OP_7 when minimal
+        let bytes = vec![0x03, b'a', b'b', b'c', 0x01, 0x07];
+        assert_eq!(op_return(bytes.clone()).first_non_minimal_push(), Some(1));
+
+        // A runestone-style OP_13 has no push boundaries, as in `chunks`
+        let pushnum = op_return(vec![0x5d, 0x01, 0x07]);
         assert_eq!(
-            legacy_loc.to_type(),
-            EmbeddingType::WitnessEnvelope(ScriptType::Legacy)
+            pushnum.chunks().collect::<Vec<_>>(),
+            vec![&pushnum.bytes[..]]
         );
+        assert_eq!(pushnum.first_non_minimal_push(), None);
+
+        let annex = Embedding {
+            bytes,
+            txid: Txid::all_zeros(),
+            location: EmbeddingLocation::TaprootAnnex {
+                input: 0,
+                index: 0,
+                tag: 0,
+            },
+        };
+        assert_eq!(annex.first_non_minimal_push(), None);
+    }
+
+    #[test]
+    fn test_chunks() {
+        let mut envelope = Embedding {
+            bytes: b"ordtext/plainhello".to_vec(),
+            txid: Txid::all_zeros(),
+            location: EmbeddingLocation::WitnessEnvelope {
+                input: 0,
+                index: 0,
+                pushes: vec![3, 10, 0, 5],
+                script_type: ScriptType::Tapscript,
+            },
+        };
+        assert!(envelope.has_consistent_pushes());
         assert_eq!(
-            tapscript_loc.to_type(),
-            EmbeddingType::WitnessEnvelope(ScriptType::Tapscript)
+            envelope.chunks().collect::<Vec<_>>(),
+            vec![&b"ord"[..], b"text/plain", b"", b"hello"]
         );
 
-        for loc in [op_return_loc, annex_loc, legacy_loc, tapscript_loc] {
-            let embedding = Embedding {
-                bytes: vec![1, 2, 3],
-                txid: Txid::all_zeros(),
-                location: loc.clone(),
+        // Push sizes that don't cover the bytes, or overrun them, yield nothing
+        for pushes in [vec![3, 10], vec![3, 10, 6], vec![usize::MAX, 19]] {
+            envelope.location = EmbeddingLocation::WitnessEnvelope {
+                input: 0,
+                index: 0,
+                pushes,
+                script_type: ScriptType::Tapscript,
             };
-
-            assert_eq!(embedding.to_type(), loc.to_type());
+            assert!(!envelope.has_consistent_pushes());
+            assert_eq!(envelope.chunks().count(), 0);
+            assert!(envelope.fields().is_none());
         }
+
+        let script = Builder::new()
+            .push_slice([1, 2])
+            .push_slice([3; 64])
+            .into_script();
+        let op_return = Embedding {
+            bytes: script.to_bytes(),
+            txid: Txid::all_zeros(),
+            location: EmbeddingLocation::OpReturn { output: 0 },
+        };
+        assert_eq!(
+            op_return.chunks().collect::<Vec<_>>(),
+            vec![&[1, 2][..], &[3; 64]]
+        );
+
+        let non_push = Embedding {
+            bytes: vec![0x51, 0x02, 1, 2],
+            txid: Txid::all_zeros(),
+            location: EmbeddingLocation::OpReturn { output: 0 },
+        };
+        assert_eq!(
+            non_push.chunks().collect::<Vec<_>>(),
+            vec![&non_push.bytes[..]]
+        );
+
+        let annex = Embedding {
+            bytes: vec![1, 2, 3],
+            txid: Txid::all_zeros(),
+            location: EmbeddingLocation::TaprootAnnex {
+                input: 0,
+                index: 0,
+                tag: 0,
+            },
+        };
+        assert!(annex.has_consistent_pushes());
+        assert_eq!(annex.chunks().collect::<Vec<_>>(), vec![&[1, 2, 3][..]]);
     }
 
     #[test]
@@ -642,18 +1624,93 @@ mod tests {
         assert_eq!(embeddings[0].txid, tx.compute_txid());
         assert_eq!(
             embeddings[0].location,
-            EmbeddingLocation::TaprootAnnex { input: 0 }
+            EmbeddingLocation::TaprootAnnex {
+                input: 0,
+                index: 0,
+                tag: 0
+            }
         );
         assert_eq!(embeddings[1].bytes, b"World");
         assert_eq!(embeddings[1].txid, tx.compute_txid());
         assert_eq!(
             embeddings[1].location,
-            EmbeddingLocation::TaprootAnnex { input: 2 }
+            EmbeddingLocation::TaprootAnnex {
+                input: 2,
+                index: 0,
+                tag: 0
+            }
         );
     }
 
     #[test]
-    fn test_from_transaction_complex() {
+    fn test_from_transaction_batch_annex() {
+        let batch = annex::encode_batch(&[b"one", b"two", b"three"]);
+        let witness = Witness::from_slice(&[
+            vec![1],
+            [&[TAPROOT_ANNEX_PREFIX, TAPROOT_ANNEX_BATCH_TAG][..], &batch].concat(),
+        ]);
+        let tx = Transaction {
+            version: Version::ONE,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ZERO,
+                witness,
+            }],
+            output: vec![],
+        };
+
+        let embeddings = Embedding::from_transaction(&tx);
+        assert_eq!(embeddings.len(), 3);
+        assert_eq!(embeddings[2].bytes, b"three");
+        assert_eq!(
+            embeddings[2].location,
+            EmbeddingLocation::TaprootAnnex {
+                input: 0,
+                index: 2,
+                tag: TAPROOT_ANNEX_BATCH_TAG
+            }
+        );
+        let mut extracted = Vec::new();
+        extractor::Extractor::new().extract_into(&tx, &mut extracted);
+        assert_eq!(extracted, embeddings);
+
+        // The first payload keeps the id of a single-payload annex
+        let txid = tx.compute_txid();
+        assert_eq!(embeddings[0].id().to_string(), format!("{txid}:ta:0"));
+        assert_eq!(embeddings[1].id().to_string(), format!("{txid}:ta:0:1"));
+
+        for embedding in &embeddings {
+            let id = embedding.id();
+            assert_eq!(EmbeddingId::from_str(&id.to_string()), Ok(id));
+            assert_eq!(EmbeddingId::from_bytes(&id.to_bytes()), Ok(id));
+            assert_eq!(
+                Embedding::extract_at(&tx, &embedding.location).as_ref(),
+                Some(embedding)
+            );
+        }
+        assert_eq!(
+            Embedding::extract_at(
+                &tx,
+                &EmbeddingLocation::TaprootAnnex {
+                    input: 0,
+                    index: 3,
+                    tag: TAPROOT_ANNEX_BATCH_TAG
+                }
+            ),
+            None
+        );
+
+        // An explicit zero sub-index is not canonical
+        let bytes = [&embeddings[0].id().to_bytes()[..], &[0]].concat();
+        assert_eq!(
+            EmbeddingId::from_bytes(&bytes),
+            Err(EmbeddingIdError::InvalidFormat)
+        );
+    }
+
+    fn complex_transaction() -> Transaction {
         // 1. Create OP_RETURN outputs
         let op_return_output0 = TxOut {
             value: Amount::ZERO,
@@ -692,7 +1749,7 @@ mod tests {
         ]);
 
         // Create the transaction
-        let tx = Transaction {
+        Transaction {
             version: Version::ONE,
             lock_time: LockTime::ZERO,
             input: vec![
@@ -733,8 +1790,12 @@ mod tests {
                 },
                 op_return_output1,
             ],
-        };
+        }
+    }
 
+    #[test]
+    fn test_from_transaction_complex() {
+        let tx = complex_transaction();
         let embeddings = Embedding::from_transaction(&tx);
 
         assert_eq!(embeddings.len(), 6);
@@ -793,7 +1854,11 @@ mod tests {
         assert_eq!(embeddings[5].txid, tx.compute_txid());
         assert_eq!(
             embeddings[5].location,
-            EmbeddingLocation::TaprootAnnex { input: 3 }
+            EmbeddingLocation::TaprootAnnex {
+                input: 3,
+                index: 0,
+                tag: 0
+            }
         );
 
         for embedding in &embeddings {
@@ -801,6 +1866,182 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_from_bare_multisig() {
+        let key = |fill: u8| {
+            let mut key = [fill; 33];
+            key[0] = 0x02;
+            key
+        };
+        let multisig = |keys: &[&[u8]], n| {
+            let builder = keys.iter().fold(
+                Builder::new().push_opcode(bitcoin::opcodes::all::OP_PUSHNUM_1),
+                |builder, key| {
+                    builder.push_slice(<&bitcoin::script::PushBytes>::try_from(*key).unwrap())
+                },
+            );
+            builder
+                .push_int(n)
+                .push_opcode(bitcoin::opcodes::all::OP_CHECKMULTISIG)
+                .into_script()
+        };
+        let output = |script_pubkey| TxOut {
+            value: Amount::from_sat(7800),
+            script_pubkey,
+        };
+
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![
+                output(multisig(&[&key(1), &key(2), &key(3)], 3)),
+                output(ScriptBuf::new_op_return([1])),
+                output(multisig(&[&key(4), &key(5)], 2)),
+                // Mismatched key count
+                output(multisig(&[&key(6), &key(7)], 3)),
+                // Uncompressed keys
+                output(multisig(&[&[4; 65], &[4; 65]], 2)),
+            ],
+        };
+        let txid = tx.compute_txid();
+
+        let embeddings = Embedding::from_bare_multisig(&tx);
+        assert_eq!(
+            embeddings,
+            vec![
+                Embedding {
+                    bytes: [[1; 31], [2; 31]].concat(),
+                    txid,
+                    location: EmbeddingLocation::BareMultisig { output: 0 },
+                },
+                Embedding {
+                    bytes: vec![4; 31],
+                    txid,
+                    location: EmbeddingLocation::BareMultisig { output: 2 },
+                },
+            ]
+        );
+
+        // Bare multisig is opt-in
+        assert_eq!(Embedding::from_transaction(&tx).len(), 1);
+
+        for embedding in &embeddings {
+            assert_eq!(embedding.id().locate(&tx).as_ref(), Some(embedding));
+        }
+    }
+
+    #[test]
+    fn test_from_transaction_with_prevouts() {
+        let tx = complex_transaction();
+        let p2tr = ScriptBuf::from_hex(&format!("5120{}", "02".repeat(32))).unwrap();
+        let p2wsh = ScriptBuf::new_p2wsh(&ScriptBuf::new().wscript_hash());
+        let p2wpkh = ScriptBuf::from_hex(&format!("0014{}", "02".repeat(20))).unwrap();
+        let prevouts = |scripts: [&ScriptBuf; 4]| {
+            scripts.map(|script_pubkey| TxOut {
+                value: Amount::ZERO,
+                script_pubkey: script_pubkey.clone(),
+            })
+        };
+
+        // Matching prevouts agree with the witness heuristics
+        let matching = prevouts([&p2wpkh, &p2wsh, &p2tr, &p2tr]);
+        assert_eq!(
+            Embedding::from_transaction_with_prevouts(&tx, &matching),
+            Embedding::from_transaction(&tx)
+        );
+
+        // No prevouts falls back to the witness heuristics
+        assert_eq!(
+            Embedding::from_transaction_with_prevouts(&tx, &[]),
+            Embedding::from_transaction(&tx)
+        );
+
+        // Mismatched prevouts exclude the witness data
+        let mismatched = prevouts([&p2wpkh, &p2tr, &p2wsh, &p2wpkh]);
+        let embeddings = Embedding::from_transaction_with_prevouts(&tx, &mismatched);
+        assert_eq!(embeddings.len(), 2);
+        assert!(
+            embeddings
+                .iter()
+                .all(|e| e.to_type() == EmbeddingType::OpReturn)
+        );
+    }
+
+    #[test]
+    fn test_embedding_id_locate() {
+        let tx = complex_transaction();
+
+        for embedding in Embedding::from_transaction(&tx) {
+            assert_eq!(embedding.id().locate(&tx), Some(embedding));
+        }
+
+        let txid = tx.compute_txid();
+        let missing = [
+            // Wrong txid
+            format!("{}:rt:0", Txid::all_zeros()),
+            // Not an OP_RETURN
+            format!("{txid}:rt:1"),
+            // Output out of range
+            format!("{txid}:rt:3"),
+            // Missing annex
+            format!("{txid}:ta:2"),
+            // Wrong script type
+            format!("{txid}:le:2:0"),
+            format!("{txid}:te:1:0"),
+            // Envelope out of range
+            format!("{txid}:te:2:2"),
+            // Input out of range
+            format!("{txid}:te:4:0"),
+        ];
+
+        for id in missing {
+            assert_eq!(
+                EmbeddingId::from_str(&id).unwrap().locate(&tx),
+                None,
+                "{id}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_extract_at() {
+        let tx = complex_transaction();
+
+        for embedding in Embedding::from_transaction(&tx) {
+            assert_eq!(
+                Embedding::extract_at(&tx, &embedding.location),
+                Some(embedding)
+            );
+        }
+
+        // Push sizes are read from the transaction
+        let location = EmbeddingLocation::WitnessEnvelope {
+            input: 2,
+            index: 0,
+            pushes: vec![],
+            script_type: ScriptType::Tapscript,
+        };
+        let embedding = Embedding::extract_at(&tx, &location).unwrap();
+        assert_eq!(
+            embedding.id().to_string(),
+            format!("{}:te:2", tx.compute_txid())
+        );
+        assert!(!embedding.bytes.is_empty());
+
+        for location in [
+            EmbeddingLocation::OpReturn { output: 1 },
+            EmbeddingLocation::TaprootAnnex {
+                input: 9,
+                index: 0,
+                tag: 0,
+            },
+            EmbeddingLocation::BareMultisig { output: 0 },
+        ] {
+            assert_eq!(Embedding::extract_at(&tx, &location), None);
+        }
+    }
+
     #[test]
     fn test_embedding_id_from_str() {
         let txid_str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
@@ -925,9 +2166,11 @@ mod tests {
         let err = EmbeddingId::from_str(&format!("{txid_str}:rt:2:1")).unwrap_err();
         assert_eq!(err, EmbeddingIdError::InvalidFormat);
 
-        // Sub_index not allowed for TaprootAnnex
-        let err = EmbeddingId::from_str(&format!("{txid_str}:ta:1:2")).unwrap_err();
+        // A TaprootAnnex sub_index of zero is omitted, so an explicit zero is rejected
+        let err = EmbeddingId::from_str(&format!("{txid_str}:ta:1:0")).unwrap_err();
         assert_eq!(err, EmbeddingIdError::InvalidFormat);
+        let id = EmbeddingId::from_str(&format!("{txid_str}:ta:1:2")).unwrap();
+        assert_eq!(id.sub_index, Some(2));
     }
 
     #[test]
@@ -951,7 +2194,11 @@ mod tests {
         let annex_embedding = Embedding {
             bytes: vec![4, 5, 6],
             txid,
-            location: EmbeddingLocation::TaprootAnnex { input: 1 },
+            location: EmbeddingLocation::TaprootAnnex {
+                input: 1,
+                index: 0,
+                tag: 0,
+            },
         };
 
         let annex_id = annex_embedding.id();
@@ -1092,4 +2339,241 @@ mod tests {
         assert_eq!(tapscript_id.index, tapscript_id2.index);
         assert_eq!(Some(0), tapscript_id2.sub_index);
     }
+
+    #[test]
+    fn test_embedding_fields() {
+        let script = Builder::new()
+            .push_opcode(bitcoin::opcodes::OP_FALSE)
+            .push_opcode(bitcoin::opcodes::all::OP_IF)
+            .push_slice(b"ord")
+            .push_opcode(bitcoin::opcodes::all::OP_PUSHNUM_1)
+            .push_slice(b"text/plain")
+            .push_opcode(bitcoin::opcodes::OP_FALSE)
+            .push_slice(b"body")
+            .push_opcode(bitcoin::opcodes::all::OP_ENDIF)
+            .into_script();
+        let witness = Witness::from_slice(&[
+            script.into_bytes(),
+            vec![TAPROOT_LEAF_TAPSCRIPT; TAPROOT_CONTROL_BASE_SIZE],
+        ]);
+
+        let tx = Transaction {
+            version: Version::ONE,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ZERO,
+                witness,
+            }],
+            output: vec![TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::from_hex("6a48656c6c6f").unwrap(),
+            }],
+        };
+
+        let embeddings = Embedding::from_transaction(&tx);
+        assert_eq!(embeddings[0].fields(), None);
+
+        let fields = embeddings[1].fields().unwrap();
+        assert_eq!(fields.protocol, b"ord");
+        assert_eq!(fields.fields, vec![(vec![1], b"text/plain".to_vec())]);
+        assert_eq!(fields.body, Some(b"body".to_vec()));
+    }
+
+    #[test]
+    fn test_embedding_type_order() {
+        let mut types = [
+            EmbeddingType::WitnessEnvelope(ScriptType::Tapscript),
+            EmbeddingType::TaprootAnnex,
+            EmbeddingType::OpReturn,
+            EmbeddingType::WitnessEnvelope(ScriptType::Legacy),
+            EmbeddingType::BareMultisig,
+        ];
+        types.sort();
+
+        assert_eq!(
+            types.iter().map(EmbeddingType::code).collect::<Vec<_>>(),
+            vec!["bm", "le", "rt", "ta", "te"]
+        );
+    }
+
+    #[test]
+    fn test_embedding_location_order() {
+        let envelope = |input, index, pushes| EmbeddingLocation::WitnessEnvelope {
+            input,
+            index,
+            pushes,
+            script_type: ScriptType::Tapscript,
+        };
+
+        let mut locations = vec![
+            envelope(1, 0, vec![1]),
+            envelope(0, 1, vec![1]),
+            envelope(0, 0, vec![2]),
+            envelope(0, 0, vec![1, 1]),
+            EmbeddingLocation::TaprootAnnex {
+                input: 0,
+                index: 0,
+                tag: 7,
+            },
+            EmbeddingLocation::TaprootAnnex {
+                input: 0,
+                index: 0,
+                tag: 0,
+            },
+            EmbeddingLocation::OpReturn { output: 3 },
+            EmbeddingLocation::OpReturn { output: 1 },
+        ];
+        locations.sort();
+
+        assert_eq!(
+            locations,
+            vec![
+                EmbeddingLocation::OpReturn { output: 1 },
+                EmbeddingLocation::OpReturn { output: 3 },
+                EmbeddingLocation::TaprootAnnex {
+                    input: 0,
+                    index: 0,
+                    tag: 0
+                },
+                EmbeddingLocation::TaprootAnnex {
+                    input: 0,
+                    index: 0,
+                    tag: 7
+                },
+                envelope(0, 0, vec![1, 1]),
+                envelope(0, 0, vec![2]),
+                envelope(0, 1, vec![1]),
+                envelope(1, 0, vec![1]),
+            ]
+        );
+
+        // Locations that differ only in annex tag are distinct in ordered collections
+        let set = locations
+            .into_iter()
+            .collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(set.len(), 8);
+    }
+
+    #[test]
+    fn test_embedding_id_order() {
+        let txid_a = "0000000000000000000000000000000000000000000000000000000000000001";
+        let txid_b = "0000000000000000000000000000000000000000000000000000000000000002";
+
+        let mut ids = [
+            format!("{txid_b}:le:0"),
+            format!("{txid_a}:te:0:1"),
+            format!("{txid_a}:te:0"),
+            format!("{txid_a}:rt:10"),
+            format!("{txid_a}:rt:2"),
+            format!("{txid_a}:le:5"),
+        ]
+        .iter()
+        .map(|s| EmbeddingId::from_str(s).unwrap())
+        .collect::<Vec<_>>();
+        ids.sort();
+
+        assert_eq!(
+            ids.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                format!("{txid_a}:le:5"),
+                format!("{txid_a}:rt:2"),
+                format!("{txid_a}:rt:10"),
+                format!("{txid_a}:te:0"),
+                format!("{txid_a}:te:0:1"),
+                format!("{txid_b}:le:0"),
+            ]
+        );
+
+        let set = ids
+            .iter()
+            .copied()
+            .collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(set.into_iter().collect::<Vec<_>>(), ids);
+    }
+
+    #[test]
+    fn test_embedding_id_bech32m_roundtrip() {
+        let txid = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+        for s in [
+            format!("{txid}:rt:2"),
+            format!("{txid}:ta:1000"),
+            format!("{txid}:le:0"),
+            format!("{txid}:te:3:7"),
+            format!("{txid}:bm:4"),
+        ] {
+            let id = EmbeddingId::from_str(&s).unwrap();
+            assert_eq!(id.to_string(), s);
+            let encoded = id.to_bech32m();
+
+            assert!(encoded.starts_with("embd1"));
+            assert_eq!(EmbeddingId::from_bech32m(&encoded), Ok(id));
+            assert_eq!(EmbeddingId::from_str(&encoded), Ok(id));
+            assert_eq!(EmbeddingId::from_str(&encoded.to_uppercase()), Ok(id));
+        }
+    }
+
+    #[test]
+    fn test_embedding_id_bech32m_detects_typos() {
+        let txid = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+        let id = EmbeddingId::from_str(&format!("{txid}:te:3:7")).unwrap();
+        let encoded = id.to_bech32m();
+
+        let mut chars = encoded.chars().collect::<Vec<_>>();
+        chars[10] = if chars[10] == 'q' { 'p' } else { 'q' };
+        let typo = chars.into_iter().collect::<String>();
+
+        assert_eq!(
+            EmbeddingId::from_bech32m(&typo),
+            Err(EmbeddingIdError::InvalidEncoding)
+        );
+
+        // Wrong human-readable part
+        let hrp = bitcoin::bech32::Hrp::parse_unchecked("other");
+        let other = bitcoin::bech32::encode::<bitcoin::bech32::Bech32m>(hrp, &[0; 34]).unwrap();
+        assert_eq!(
+            EmbeddingId::from_bech32m(&other),
+            Err(EmbeddingIdError::InvalidEncoding)
+        );
+
+        // Bech32 (not bech32m) checksum
+        let hrp = bitcoin::bech32::Hrp::parse_unchecked(EMBEDDING_ID_HRP);
+        let bech32 = bitcoin::bech32::encode::<bitcoin::bech32::Bech32>(hrp, &[0; 34]).unwrap();
+        assert_eq!(
+            EmbeddingId::from_bech32m(&bech32),
+            Err(EmbeddingIdError::InvalidEncoding)
+        );
+    }
+
+    #[test]
+    fn test_embedding_id_bech32m_invalid_data() {
+        let hrp = bitcoin::bech32::Hrp::parse_unchecked(EMBEDDING_ID_HRP);
+        let encode =
+            |data: &[u8]| bitcoin::bech32::encode::<bitcoin::bech32::Bech32m>(hrp, data).unwrap();
+
+        let txid = [1u8; 32];
+
+        assert_eq!(
+            EmbeddingId::from_bech32m(&encode(&txid)),
+            Err(EmbeddingIdError::InvalidFormat)
+        );
+        assert_eq!(
+            EmbeddingId::from_bech32m(&encode(&[&txid[..], &[5, 0]].concat())),
+            Err(EmbeddingIdError::InvalidType)
+        );
+        assert_eq!(
+            EmbeddingId::from_bech32m(&encode(&[&txid[..], &[0, 0, 0]].concat())),
+            Err(EmbeddingIdError::InvalidFormat)
+        );
+        assert_eq!(
+            EmbeddingId::from_bech32m(&encode(&[&txid[..], &[3, 0]].concat())),
+            Err(EmbeddingIdError::InvalidIndex)
+        );
+        assert_eq!(
+            EmbeddingId::from_bech32m(&encode(&[&txid[..], &[0, 0x80, 0]].concat())),
+            Err(EmbeddingIdError::InvalidIndex)
+        );
+    }
 }
@@ -0,0 +1,314 @@
+//! # Media Types
+//!
+//! Labels a payload with a MIME type and, optionally, a content encoding, using the
+//! [`tags::CONTENT_TYPE`] and [`tags::CONTENT_ENCODING`] messages. Media types are validated
+//! against RFC 6838 before they are embedded or returned.
+
+use crate::message::{Message, tags};
+
+use std::fmt;
+use std::str::FromStr;
+
+/// The maximum length of a type or subtype name
+pub const MAX_NAME_LENGTH: usize = 127;
+
+/// A parsed media type, such as `text/plain;charset=utf-8`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MediaType {
+    /// The top-level type, lowercased (e.g. `image`)
+    pub type_name: String,
+    /// The subtype, lowercased (e.g. `png`)
+    pub subtype: String,
+    /// Parameter names (lowercased) and values, in order
+    pub parameters: Vec<(String, String)>,
+}
+
+/// Error types for parsing media types and content encodings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MediaTypeError {
+    /// The media type is missing the `/` between type and subtype
+    MissingSubtype,
+    /// A type or subtype name is empty, too long, or has disallowed characters
+    InvalidName,
+    /// A parameter is not a `name=value` pair of valid tokens or quoted strings
+    InvalidParameter,
+    /// The content encoding is not a valid token
+    InvalidEncoding,
+    /// The message body is not UTF-8
+    InvalidUtf8,
+}
+
+impl MediaType {
+    /// Parses and validates a media type
+    pub fn parse(s: &str) -> Result<Self, MediaTypeError> {
+        let (essence, mut rest) = s.split_once(';').map_or((s, None), |(a, b)| (a, Some(b)));
+        let (type_name, subtype) = essence
+            .trim()
+            .split_once('/')
+            .ok_or(MediaTypeError::MissingSubtype)?;
+
+        if !is_restricted_name(type_name) || !is_restricted_name(subtype) {
+            return Err(MediaTypeError::InvalidName);
+        }
+
+        let mut parameters = Vec::new();
+        while let Some(params) = rest {
+            let (name, value) = params
+                .split_once('=')
+                .ok_or(MediaTypeError::InvalidParameter)?;
+            let name = name.trim_start();
+            if !is_token(name) {
+                return Err(MediaTypeError::InvalidParameter);
+            }
+
+            let (value, remaining) = parse_value(value)?;
+            parameters.push((name.to_ascii_lowercase(), value));
+            rest = remaining;
+        }
+
+        Ok(Self {
+            type_name: type_name.to_ascii_lowercase(),
+            subtype: subtype.to_ascii_lowercase(),
+            parameters,
+        })
+    }
+
+    /// Returns `type/subtype` without parameters
+    pub fn essence(&self) -> String {
+        format!("{}/{}", self.type_name, self.subtype)
+    }
+
+    /// Returns the value of the first parameter named `name`
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns whether this is a `text/*` type
+    pub fn is_text(&self) -> bool {
+        self.type_name == "text"
+    }
+
+    /// Returns whether this is an `image/*` type
+    pub fn is_image(&self) -> bool {
+        self.type_name == "image"
+    }
+
+    /// Returns whether this is `application/json` or a `+json` structured syntax type
+    pub fn is_json(&self) -> bool {
+        (self.type_name == "application" && self.subtype == "json")
+            || self.subtype.ends_with("+json")
+    }
+}
+
+/// Returns a content type message labeling the payload as `media_type`
+pub fn content_type(media_type: &MediaType) -> Message {
+    Message::new(tags::CONTENT_TYPE, media_type.to_string().into_bytes()).expect("valid tag")
+}
+
+/// Returns a content encoding message (e.g. `br`, `gzip`), meaning the payload must be
+/// decoded before it is of the labeled content type
+pub fn content_encoding(encoding: &str) -> Result<Message, MediaTypeError> {
+    if !is_token(encoding) {
+        return Err(MediaTypeError::InvalidEncoding);
+    }
+
+    Ok(Message::new(
+        tags::CONTENT_ENCODING,
+        encoding.to_ascii_lowercase().into_bytes(),
+    )
+    .expect("valid tag"))
+}
+
+/// Returns the media type of the first content type message, if any
+pub fn content_type_of(messages: &[Message]) -> Option<Result<MediaType, MediaTypeError>> {
+    let message = messages.iter().find(|m| m.tag == tags::CONTENT_TYPE)?;
+    Some(
+        std::str::from_utf8(&message.body)
+            .map_err(|_| MediaTypeError::InvalidUtf8)
+            .and_then(MediaType::parse),
+    )
+}
+
+/// Returns the content encoding of the first content encoding message, if any
+pub fn content_encoding_of(messages: &[Message]) -> Option<Result<&str, MediaTypeError>> {
+    let message = messages.iter().find(|m| m.tag == tags::CONTENT_ENCODING)?;
+    Some(match std::str::from_utf8(&message.body) {
+        Ok(encoding) if is_token(encoding) => Ok(encoding),
+        Ok(_) => Err(MediaTypeError::InvalidEncoding),
+        Err(_) => Err(MediaTypeError::InvalidUtf8),
+    })
+}
+
+/// Parses a token or quoted-string parameter value, returning it and the text after the next
+/// `;`, if any
+fn parse_value(s: &str) -> Result<(String, Option<&str>), MediaTypeError> {
+    let (value, rest) = match s.strip_prefix('"') {
+        Some(quoted) => {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i, '"')) => break i,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, c)) if is_quoted_char(c) => value.push(c),
+                        _ => return Err(MediaTypeError::InvalidParameter),
+                    },
+                    Some((_, c)) if is_quoted_char(c) => value.push(c),
+                    _ => return Err(MediaTypeError::InvalidParameter),
+                }
+            };
+            (value, &quoted[end + 1..])
+        }
+        None => {
+            let end = s.find(';').unwrap_or(s.len());
+            let value = s[..end].trim_end();
+            if !is_token(value) {
+                return Err(MediaTypeError::InvalidParameter);
+            }
+            (value.to_string(), &s[end..])
+        }
+    };
+
+    let rest = rest.trim_start();
+    match rest.strip_prefix(';') {
+        Some(rest) => Ok((value, Some(rest))),
+        None if rest.is_empty() => Ok((value, None)),
+        None => Err(MediaTypeError::InvalidParameter),
+    }
+}
+
+/// Returns whether `s` is an RFC 6838 restricted name
+fn is_restricted_name(s: &str) -> bool {
+    s.len() <= MAX_NAME_LENGTH
+        && s.bytes().next().is_some_and(|b| b.is_ascii_alphanumeric())
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$&-^_.+".contains(&b))
+}
+
+/// Returns whether `s` is an RFC 9110 token
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+fn is_quoted_char(c: char) -> bool {
+    c == '\t' || (' '..='~').contains(&c)
+}
+
+impl FromStr for MediaType {
+    type Err = MediaTypeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for MediaType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.type_name, self.subtype)?;
+        for (name, value) in &self.parameters {
+            if is_token(value) {
+                write!(f, ";{name}={value}")?;
+            } else {
+                let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+                write!(f, ";{name}=\"{escaped}\"")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for MediaTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MediaTypeError::MissingSubtype => write!(f, "Media type is missing a subtype"),
+            MediaTypeError::InvalidName => write!(f, "Invalid media type name"),
+            MediaTypeError::InvalidParameter => write!(f, "Invalid media type parameter"),
+            MediaTypeError::InvalidEncoding => write!(f, "Invalid content encoding"),
+            MediaTypeError::InvalidUtf8 => write!(f, "Content type is not UTF-8"),
+        }
+    }
+}
+
+impl std::error::Error for MediaTypeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let media_type =
+            MediaType::parse("Text/HTML; Charset=\"utf-8\" ;q=\"a \\\"b\\\"\"").unwrap();
+        assert_eq!(media_type.essence(), "text/html");
+        assert_eq!(media_type.parameter("charset"), Some("utf-8"));
+        assert_eq!(media_type.parameter("q"), Some("a \"b\""));
+        assert!(media_type.is_text());
+        assert_eq!(
+            media_type.to_string(),
+            "text/html;charset=utf-8;q=\"a \\\"b\\\"\""
+        );
+        assert_eq!(MediaType::parse(&media_type.to_string()), Ok(media_type));
+
+        assert!(MediaType::parse("image/svg+xml").unwrap().is_image());
+        assert!(MediaType::parse("application/json").unwrap().is_json());
+        assert!(MediaType::parse("application/ld+json").unwrap().is_json());
+    }
+
+    #[test]
+    fn test_invalid() {
+        for (s, e) in [
+            ("text", MediaTypeError::MissingSubtype),
+            ("text/", MediaTypeError::InvalidName),
+            ("/plain", MediaTypeError::InvalidName),
+            (".text/plain", MediaTypeError::InvalidName),
+            ("text/pl ain", MediaTypeError::InvalidName),
+            ("text/plain;", MediaTypeError::InvalidParameter),
+            ("text/plain;charset", MediaTypeError::InvalidParameter),
+            ("text/plain;charset=", MediaTypeError::InvalidParameter),
+            (
+                "text/plain;charset=\"utf-8",
+                MediaTypeError::InvalidParameter,
+            ),
+            (
+                "text/plain;charset=\"utf-8\"x",
+                MediaTypeError::InvalidParameter,
+            ),
+            ("text/plain;charset=a b", MediaTypeError::InvalidParameter),
+        ] {
+            assert_eq!(MediaType::parse(s), Err(e), "{s}");
+        }
+
+        let long = format!("text/{}", "a".repeat(MAX_NAME_LENGTH + 1));
+        assert_eq!(MediaType::parse(&long), Err(MediaTypeError::InvalidName));
+    }
+
+    #[test]
+    fn test_messages() {
+        let media_type = MediaType::parse("image/png").unwrap();
+        let messages = vec![
+            content_type(&media_type),
+            content_encoding("BR").unwrap(),
+            Message::new(1, vec![0]).unwrap(),
+        ];
+        let decoded = Message::decode(&Message::encode(messages)).unwrap();
+
+        assert_eq!(content_type_of(&decoded), Some(Ok(media_type)));
+        assert_eq!(content_encoding_of(&decoded), Some(Ok("br")));
+        assert_eq!(content_type_of(&decoded[2..]), None);
+        assert_eq!(
+            content_encoding("a b"),
+            Err(MediaTypeError::InvalidEncoding)
+        );
+
+        let invalid = [Message::new(tags::CONTENT_TYPE, vec![0xff]).unwrap()];
+        assert_eq!(
+            content_type_of(&invalid),
+            Some(Err(MediaTypeError::InvalidUtf8))
+        );
+    }
+}
@@ -0,0 +1,401 @@
+//! # Merkle Batch Commitments
+//!
+//! Commits many payloads with a single 32-byte root, embedded in an `OP_RETURN` output or a
+//! taproot annex, and proves the inclusion of each payload against it.
+//!
+//! Leaves are tagged hashes of the payloads and parents are tagged hashes of their two
+//! children, so a leaf can never be mistaken for a parent. A node without a sibling is carried
+//! up to the next level unchanged rather than paired with itself, so no two distinct payload
+//! lists share a root.
+
+use crate::message::tagged_hash;
+use crate::{Embedding, EmbeddingLocation, TAPROOT_ANNEX_DATA_TAG, varint};
+
+use bitcoin::ScriptBuf;
+use bitcoin::script::{Instruction, Script};
+use bitcoin::taproot::TAPROOT_ANNEX_PREFIX;
+use std::fmt;
+
+/// The tag used to hash payloads into leaves
+pub const LEAF_TAG: &str = "bitcoin-embed/merkle/leaf";
+
+/// The tag used to hash two children into a parent
+pub const BRANCH_TAG: &str = "bitcoin-embed/merkle/branch";
+
+/// A Merkle tree over a list of payloads
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleTree {
+    /// The levels of the tree, from the leaves to the root
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+/// A proof that a payload is included under a root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    /// The index of the payload
+    pub index: usize,
+    /// The number of payloads in the tree
+    pub leaf_count: usize,
+    /// The sibling hashes from the leaf to the root, skipping levels where the node has none
+    pub path: Vec<[u8; 32]>,
+}
+
+/// Error types for decoding Merkle proofs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MerkleError {
+    /// LEB128 decoding error
+    VarInt(varint::Error),
+    /// The path is not a whole number of hashes
+    InvalidLength,
+}
+
+impl MerkleTree {
+    /// Builds a tree over `payloads`. Returns `None` if there are none.
+    pub fn new<T: AsRef<[u8]>>(payloads: &[T]) -> Option<Self> {
+        if payloads.is_empty() {
+            return None;
+        }
+
+        let mut levels = vec![
+            payloads
+                .iter()
+                .map(|payload| leaf_hash(payload.as_ref()))
+                .collect::<Vec<_>>(),
+        ];
+
+        while levels.last().expect("at least one level").len() > 1 {
+            let level = levels
+                .last()
+                .expect("at least one level")
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => branch_hash(left, right),
+                    [node] => *node,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(level);
+        }
+
+        Some(Self { levels })
+    }
+
+    /// Builds a tree over the canonical JSON serializations of `values` ([`crate::jcs`]), so
+    /// any serialization of a value proves its inclusion. Returns `None` if there are none.
+    #[cfg(feature = "jcs")]
+    pub fn from_json<T: serde::Serialize>(values: &[T]) -> Result<Option<Self>, serde_json::Error> {
+        let payloads = values
+            .iter()
+            .map(crate::jcs::to_vec)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(&payloads))
+    }
+
+    /// Returns the root
+    pub fn root(&self) -> [u8; 32] {
+        self.levels.last().expect("at least one level")[0]
+    }
+
+    /// Returns the number of payloads
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// Returns whether the tree is empty. Always false, since trees have at least one payload.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Returns the inclusion proof for the payload at `index`
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.len() {
+            return None;
+        }
+
+        let mut path = Vec::new();
+        let mut position = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            if let Some(sibling) = level.get(position ^ 1) {
+                path.push(*sibling);
+            }
+            position /= 2;
+        }
+
+        Some(MerkleProof {
+            index,
+            leaf_count: self.len(),
+            path,
+        })
+    }
+
+    /// Returns an `OP_RETURN` script committing to the root
+    pub fn to_op_return_script(&self) -> ScriptBuf {
+        ScriptBuf::new_op_return(self.root())
+    }
+
+    /// Returns a data-carrying taproot annex committing to the root
+    pub fn to_annex(&self) -> Vec<u8> {
+        [
+            &[TAPROOT_ANNEX_PREFIX, TAPROOT_ANNEX_DATA_TAG][..],
+            &self.root(),
+        ]
+        .concat()
+    }
+}
+
+impl MerkleProof {
+    /// Returns the root implied by `payload` and this proof, or `None` if the path does not
+    /// match the index and leaf count
+    pub fn root(&self, payload: &[u8]) -> Option<[u8; 32]> {
+        if self.index >= self.leaf_count {
+            return None;
+        }
+
+        let mut hash = leaf_hash(payload);
+        let mut path = self.path.iter();
+        let mut position = self.index;
+        let mut width = self.leaf_count;
+
+        while width > 1 {
+            if position ^ 1 < width {
+                let sibling = path.next()?;
+                hash = match position % 2 {
+                    0 => branch_hash(&hash, sibling),
+                    _ => branch_hash(sibling, &hash),
+                };
+            }
+            position /= 2;
+            width = width.div_ceil(2);
+        }
+
+        match path.next() {
+            Some(_) => None,
+            None => Some(hash),
+        }
+    }
+
+    /// Returns whether `payload` is included under `root`
+    pub fn verify(&self, root: &[u8; 32], payload: &[u8]) -> bool {
+        self.root(payload).is_some_and(|hash| hash == *root)
+    }
+
+    /// Returns whether the canonical JSON serialization of `value` is included under `root`,
+    /// as in a tree built with [`MerkleTree::from_json`]
+    #[cfg(feature = "jcs")]
+    pub fn verify_json<T: serde::Serialize + ?Sized>(&self, root: &[u8; 32], value: &T) -> bool {
+        crate::jcs::to_vec(value).is_ok_and(|payload| self.verify(root, &payload))
+    }
+
+    /// Serializes the proof as the LEB128 index and leaf count followed by the path
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(4 + 32 * self.path.len());
+        varint::encode_to_vec(self.index as u128, &mut bytes);
+        varint::encode_to_vec(self.leaf_count as u128, &mut bytes);
+        for hash in &self.path {
+            bytes.extend(hash);
+        }
+        bytes
+    }
+
+    /// Deserializes a proof produced by [`MerkleProof::to_bytes`]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MerkleError> {
+        let (index, n) = varint::decode_strict(bytes)?;
+        let (leaf_count, m) = varint::decode_strict(&bytes[n..])?;
+
+        let path = &bytes[n + m..];
+        if path.len() % 32 != 0 {
+            return Err(MerkleError::InvalidLength);
+        }
+
+        Ok(Self {
+            index: usize::try_from(index).map_err(|_| varint::Error::Overflow)?,
+            leaf_count: usize::try_from(leaf_count).map_err(|_| varint::Error::Overflow)?,
+            path: path
+                .chunks_exact(32)
+                .map(|hash| hash.try_into().expect("32 bytes"))
+                .collect(),
+        })
+    }
+}
+
+/// Returns the root committed by an embedding: a single 32-byte push in an `OP_RETURN` output,
+/// or 32 bytes of annex data
+pub fn root_from_embedding(embedding: &Embedding) -> Option<[u8; 32]> {
+    match embedding.location {
+        EmbeddingLocation::OpReturn { .. } => {
+            let mut instructions = Script::from_bytes(&embedding.bytes).instructions();
+            let Some(Ok(Instruction::PushBytes(push))) = instructions.next() else {
+                return None;
+            };
+            match instructions.next() {
+                None => push.as_bytes().try_into().ok(),
+                Some(_) => None,
+            }
+        }
+        EmbeddingLocation::TaprootAnnex { .. } => embedding.bytes.as_slice().try_into().ok(),
+        _ => None,
+    }
+}
+
+/// Returns whether `payload` is included under the root committed by `embedding`
+pub fn verify_embedding(embedding: &Embedding, payload: &[u8], proof: &MerkleProof) -> bool {
+    root_from_embedding(embedding).is_some_and(|root| proof.verify(&root, payload))
+}
+
+fn leaf_hash(payload: &[u8]) -> [u8; 32] {
+    tagged_hash(LEAF_TAG, payload)
+}
+
+fn branch_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    tagged_hash(BRANCH_TAG, &[&left[..], &right[..]].concat())
+}
+
+impl From<varint::Error> for MerkleError {
+    fn from(e: varint::Error) -> Self {
+        MerkleError::VarInt(e)
+    }
+}
+
+impl fmt::Display for MerkleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MerkleError::VarInt(e) => write!(f, "{e}"),
+            MerkleError::InvalidLength => write!(f, "Merkle path is not a multiple of 32 bytes"),
+        }
+    }
+}
+
+impl std::error::Error for MerkleError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::{
+        Amount, OutPoint, Sequence, Transaction, TxIn, TxOut, Witness, absolute::LockTime,
+        transaction::Version,
+    };
+
+    fn payloads(n: usize) -> Vec<Vec<u8>> {
+        (0..n)
+            .map(|i| format!("document {i}").into_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn test_proofs() {
+        assert_eq!(MerkleTree::new::<Vec<u8>>(&[]), None);
+
+        for n in 1..=9 {
+            let payloads = payloads(n);
+            let tree = MerkleTree::new(&payloads).unwrap();
+            assert_eq!(tree.len(), n);
+            assert_eq!(tree.proof(n), None);
+
+            for (index, payload) in payloads.iter().enumerate() {
+                let proof = tree.proof(index).unwrap();
+                assert!(proof.verify(&tree.root(), payload));
+                assert!(!proof.verify(&tree.root(), b"other"));
+                assert_eq!(MerkleProof::from_bytes(&proof.to_bytes()), Ok(proof));
+            }
+        }
+    }
+
+    #[test]
+    fn test_root() {
+        let tree = MerkleTree::new(&[b"a", b"b", b"c"]).unwrap();
+        let ab = branch_hash(&leaf_hash(b"a"), &leaf_hash(b"b"));
+        assert_eq!(tree.root(), branch_hash(&ab, &leaf_hash(b"c")));
+
+        // An unpaired node is not duplicated
+        let padded = MerkleTree::new(&[b"a", b"b", b"c", b"c"]).unwrap();
+        assert_ne!(tree.root(), padded.root());
+
+        let single = MerkleTree::new(&[b"a"]).unwrap();
+        assert_eq!(single.root(), leaf_hash(b"a"));
+        assert_eq!(single.proof(0).unwrap().path, Vec::<[u8; 32]>::new());
+    }
+
+    #[test]
+    fn test_invalid_proof() {
+        let payloads = payloads(5);
+        let tree = MerkleTree::new(&payloads).unwrap();
+        let proof = tree.proof(2).unwrap();
+
+        let mut moved = proof.clone();
+        moved.index = 3;
+        assert!(!moved.verify(&tree.root(), &payloads[2]));
+
+        let mut short = proof.clone();
+        short.path.pop();
+        assert_eq!(short.root(&payloads[2]), None);
+
+        let mut long = proof.clone();
+        long.path.push([0; 32]);
+        assert_eq!(long.root(&payloads[2]), None);
+
+        let mut out_of_range = proof.clone();
+        out_of_range.index = 5;
+        assert_eq!(out_of_range.root(&payloads[2]), None);
+
+        let mut bytes = proof.to_bytes();
+        bytes.pop();
+        assert_eq!(
+            MerkleProof::from_bytes(&bytes),
+            Err(MerkleError::InvalidLength)
+        );
+    }
+
+    #[cfg(feature = "jcs")]
+    #[test]
+    fn test_json() {
+        use serde_json::{Value, json};
+
+        let values = [json!({"name": "a", "n": 1}), json!({"name": "b", "n": 2})];
+        let tree = MerkleTree::from_json(&values).unwrap().unwrap();
+        let proof = tree.proof(1).unwrap();
+
+        // Another serialization of the same value verifies
+        let reordered: Value = serde_json::from_str(r#"{ "n": 2.0, "name": "b" }"#).unwrap();
+        assert!(proof.verify_json(&tree.root(), &reordered));
+        assert!(!proof.verify_json(&tree.root(), &values[0]));
+        assert_eq!(MerkleTree::from_json::<Value>(&[]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_embedding() {
+        let payloads = payloads(4);
+        let tree = MerkleTree::new(&payloads).unwrap();
+        let proof = tree.proof(1).unwrap();
+
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::from_slice(&[vec![1; 64], tree.to_annex()]),
+            }],
+            output: vec![TxOut {
+                value: Amount::ZERO,
+                script_pubkey: tree.to_op_return_script(),
+            }],
+        };
+
+        let embeddings = Embedding::from_transaction(&tx);
+        assert_eq!(embeddings.len(), 2);
+        for embedding in &embeddings {
+            assert_eq!(root_from_embedding(embedding), Some(tree.root()));
+            assert!(verify_embedding(embedding, &payloads[1], &proof));
+            assert!(!verify_embedding(embedding, &payloads[2], &proof));
+        }
+
+        let other = Embedding {
+            bytes: vec![0x01, 0x00],
+            txid: tx.compute_txid(),
+            location: EmbeddingLocation::OpReturn { output: 0 },
+        };
+        assert_eq!(root_from_embedding(&other), None);
+    }
+}
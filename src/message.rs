@@ -1,9 +1,18 @@
 //! # Message Encoding
 
-use crate::varint;
+use crate::{EmbeddingId, varint};
 
+use bitcoin::hashes::{Hash, HashEngine, sha256};
+use bitcoin::secp256k1::{self, Keypair, Secp256k1, XOnlyPublicKey, schnorr};
 use std::fmt;
 
+/// The tag used to domain-separate signatures over a set of messages
+pub const SIGNATURE_HASH_TAG: &str = "bitcoin-embed/signature";
+
+/// The tag used to derive encryption keys from an ECDH shared secret
+#[cfg(feature = "encryption")]
+pub const ENCRYPTION_KEY_TAG: &str = "bitcoin-embed/encryption";
+
 /// Errors that can occur during encoding/decoding
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
@@ -15,8 +24,16 @@ pub enum Error {
     InvalidByteCount,
     /// Final size byte must be zero
     InvalidFinalSizeByte,
+    /// Final message must use a terminal tag rather than a zero size byte (strict decoding)
+    NonTerminalFinalMessage,
     /// Variable-length encoding indicates bytes are missing
     MissingBytes,
+    /// Final message is not a signature
+    MissingSignature,
+    /// Signature is malformed or does not verify
+    InvalidSignature,
+    /// Ciphertext is malformed or cannot be decrypted
+    InvalidCiphertext,
 }
 
 /// Type representing a protocol tag
@@ -28,6 +45,78 @@ pub mod tags {
 
     /// Repeat
     pub const REPEAT: Tag = 0;
+
+    // Tags 4096 through 8191 are reserved for conventions defined by this crate
+
+    /// BIP-340 signature over the preceding messages
+    pub const SIGNATURE: Tag = 4096;
+
+    /// ECIES-encrypted body
+    pub const ENCRYPTED: Tag = 4097;
+
+    /// Binary-encoded id of the embedding holding the real content
+    pub const POINTER: Tag = 4098;
+
+    /// BIP-322 attestation of an embedding's payload
+    pub const ATTESTATION: Tag = 4099;
+
+    /// UTF-8 file name
+    pub const FILE_NAME: Tag = 4100;
+
+    /// UTF-8 MIME type of the content
+    pub const CONTENT_TYPE: Tag = 4101;
+
+    /// LEB128-encoded file size in bytes
+    pub const FILE_SIZE: Tag = 4102;
+
+    /// Tagged SHA-256 of the content
+    pub const CONTENT_HASH: Tag = 4103;
+
+    /// Binary-encoded id of the next embedding holding part of a file
+    pub const FILE_CHUNK: Tag = 4104;
+
+    /// Encoding applied to the content, such as `br` or `gzip`
+    pub const CONTENT_ENCODING: Tag = 4105;
+
+    /// BIP-119 template hash of a planned follow-up transaction
+    pub const TEMPLATE_HASH: Tag = 4106;
+
+    /// Asset issuance (see `protocols::asset`)
+    pub const ASSET_ISSUE: Tag = 4107;
+
+    /// Asset transfer to outputs of the carrying transaction (see `protocols::asset`)
+    pub const ASSET_TRANSFER: Tag = 4108;
+
+    /// Asset burn (see `protocols::asset`)
+    pub const ASSET_BURN: Tag = 4109;
+
+    /// DLC oracle announcement TLV record (see `protocols::dlc`)
+    pub const DLC_ANNOUNCEMENT: Tag = 4110;
+
+    /// DLC oracle attestation TLV record (see `protocols::dlc`)
+    pub const DLC_ATTESTATION: Tag = 4111;
+
+    /// Signed DID create, update, or deactivate operation (see `protocols::did`)
+    pub const DID_OPERATION: Tag = 4112;
+
+    /// NIP-01 id of an anchored Nostr event (see `protocols::nostr`)
+    pub const NOSTR_EVENT: Tag = 4113;
+
+    /// Merkle root of anchored Nostr event ids (see `protocols::nostr`)
+    pub const NOSTR_ROOT: Tag = 4114;
+
+    /// Layout of a payload split across locations (see `planner`)
+    pub const LAYOUT: Tag = 4115;
+}
+
+/// The framing used to encode a series of messages
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Framing {
+    /// Terminal-flagged tags, repeat markers, and an implicit final length
+    #[default]
+    Compact,
+    /// Each message is encoded as a LEB128 tag, a LEB128 length, and the body
+    LengthPrefixed,
 }
 
 /// A struct containing a tag and associated bytes
@@ -91,18 +180,63 @@ impl Message {
         bytes
     }
 
+    /// Encodes messages as raw bytes using the given framing
+    pub fn encode_with(messages: Vec<Self>, framing: Framing) -> Vec<u8> {
+        match framing {
+            Framing::Compact => Self::encode(messages),
+            Framing::LengthPrefixed => {
+                let mut bytes = Vec::new();
+
+                for message in messages {
+                    varint::encode_to_vec(message.tag, &mut bytes);
+                    varint::encode_to_vec(message.body.len() as u128, &mut bytes);
+                    bytes.extend(message.body);
+                }
+
+                bytes
+            }
+        }
+    }
+
+    /// Decodes messages from raw bytes using the given framing
+    pub fn decode_with(bytes: &[u8], framing: Framing) -> Result<Vec<Self>, Error> {
+        match framing {
+            Framing::Compact => Self::decode_compact(bytes, false),
+            Framing::LengthPrefixed => Self::decode_length_prefixed(bytes, false),
+        }
+    }
+
+    /// Decodes messages from raw bytes using the given framing, rejecting non-minimal
+    /// LEB128 encodings and, with compact framing, a final message sized by a zero size byte
+    /// instead of a terminal tag, so that every message set has a single canonical encoding
+    pub fn decode_strict_with(bytes: &[u8], framing: Framing) -> Result<Vec<Self>, Error> {
+        match framing {
+            Framing::Compact => Self::decode_compact(bytes, true),
+            Framing::LengthPrefixed => Self::decode_length_prefixed(bytes, true),
+        }
+    }
+
+    /// Decodes messages from raw bytes, rejecting non-minimal LEB128 encodings and a final
+    /// message sized by a zero size byte instead of a terminal tag
+    pub fn decode_strict(bytes: &[u8]) -> Result<Vec<Self>, Error> {
+        Self::decode_compact(bytes, true)
+    }
+
     /// Decodes messages from raw bytes.
     ///
     /// Returns an empty array if an invalid varint encoding or a chunk length that exceeds
     /// the remaining array length is encountered.
     pub fn decode(bytes: &[u8]) -> Result<Vec<Self>, Error> {
+        Self::decode_compact(bytes, false)
+    }
+
+    fn decode_compact(bytes: &[u8], strict: bool) -> Result<Vec<Self>, Error> {
         let mut messages = Vec::new();
         let mut index = 0;
         let mut last_tag = 0;
 
         while index < bytes.len() {
-            let (value, size) =
-                varint::decode(&bytes[index..]).map_err(|_| Error::InvalidVarInt)?;
+            let (value, size) = decode_varint(&bytes[index..], strict)?;
             index += size;
 
             let is_last = value % 2 == 1;
@@ -132,10 +266,13 @@ impl Message {
                 return Err(Error::MissingBytes);
             }
 
-            let (n, size) = varint::decode(&bytes[index..]).map_err(|_| Error::InvalidVarInt)?;
+            let (n, size) = decode_varint(&bytes[index..], strict)?;
             index += size;
 
             let length: usize = if n == 0 {
+                if strict {
+                    return Err(Error::NonTerminalFinalMessage);
+                }
                 bytes.len() - index
             } else if n > u32::MAX.into() {
                 return Err(Error::InvalidByteCount);
@@ -161,6 +298,211 @@ impl Message {
 
         Ok(messages)
     }
+
+    fn decode_length_prefixed(bytes: &[u8], strict: bool) -> Result<Vec<Self>, Error> {
+        let mut messages = Vec::new();
+        let mut index = 0;
+
+        while index < bytes.len() {
+            let (tag, size) = decode_varint(&bytes[index..], strict)?;
+            index += size;
+
+            if tag == tags::REPEAT || tag > ((1 << 127) - 1) {
+                return Err(Error::InvalidTag);
+            }
+
+            if index >= bytes.len() {
+                return Err(Error::MissingBytes);
+            }
+
+            let (n, size) = decode_varint(&bytes[index..], strict)?;
+            index += size;
+
+            if n > u32::MAX.into() {
+                return Err(Error::InvalidByteCount);
+            }
+
+            let length = n as usize;
+
+            if index + length > bytes.len() {
+                return Err(Error::MissingBytes);
+            }
+
+            messages.push(Self {
+                tag,
+                body: bytes[index..(index + length)].to_vec(),
+                _private: false,
+            });
+            index += length;
+        }
+
+        Ok(messages)
+    }
+}
+
+fn decode_varint(bytes: &[u8], strict: bool) -> Result<(u128, usize), Error> {
+    let result = if strict {
+        varint::decode_strict(bytes)
+    } else {
+        varint::decode(bytes)
+    };
+
+    result.map_err(|_| Error::InvalidVarInt)
+}
+
+/// Signs messages by appending a BIP-340 signature message.
+///
+/// The signature commits to a tagged hash of the encoding of `messages`, using
+/// [`SIGNATURE_HASH_TAG`] as the tag.
+pub fn sign(mut messages: Vec<Message>, keypair: &Keypair) -> Vec<Message> {
+    let secp = Secp256k1::signing_only();
+    let msg = signature_hash(&messages);
+    let signature = secp.sign_schnorr_no_aux_rand(&msg, keypair);
+
+    messages.push(Message {
+        tag: tags::SIGNATURE,
+        body: signature.serialize().to_vec(),
+        _private: false,
+    });
+
+    messages
+}
+
+/// Verifies that the final message is a valid BIP-340 signature by `pubkey` over
+/// the preceding messages.
+pub fn verify(messages: &[Message], pubkey: &XOnlyPublicKey) -> Result<(), Error> {
+    let Some((last, signed)) = messages.split_last() else {
+        return Err(Error::MissingSignature);
+    };
+
+    if last.tag != tags::SIGNATURE {
+        return Err(Error::MissingSignature);
+    }
+
+    let signature =
+        schnorr::Signature::from_slice(&last.body).map_err(|_| Error::InvalidSignature)?;
+
+    let secp = Secp256k1::verification_only();
+    secp.verify_schnorr(&signature, &signature_hash(signed), pubkey)
+        .map_err(|_| Error::InvalidSignature)
+}
+
+/// Returns a pointer message, meaning the content is the embedding referenced by `id`
+pub fn pointer_to(id: &EmbeddingId) -> Message {
+    Message {
+        tag: tags::POINTER,
+        body: id.to_bytes(),
+        _private: false,
+    }
+}
+
+/// Returns the target of the first pointer message. Malformed pointer messages are ignored.
+pub fn pointer_target(messages: &[Message]) -> Option<EmbeddingId> {
+    messages
+        .iter()
+        .filter(|message| message.tag == tags::POINTER)
+        .find_map(|message| EmbeddingId::from_bytes(&message.body).ok())
+}
+
+/// Computes a BIP-340 tagged hash of `data`
+pub(crate) fn tagged_hash(tag: &str, data: &[u8]) -> [u8; 32] {
+    let tag_hash = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag_hash.as_ref());
+    engine.input(tag_hash.as_ref());
+    engine.input(data);
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// Encrypts `body` to `pubkey`, returning an encrypted message.
+///
+/// Details:
+/// - An ephemeral key is combined with `pubkey` (lifted to even Y) via ECDH
+/// - The ChaCha20-Poly1305 key is a tagged hash of the shared secret and ephemeral key
+/// - The message body is the 32-byte ephemeral x-only key followed by the ciphertext
+#[cfg(feature = "encryption")]
+pub fn encrypt_to(pubkey: &XOnlyPublicKey, body: &[u8]) -> Result<Message, Error> {
+    use chacha20poly1305::aead::Aead;
+
+    let secp = Secp256k1::new();
+    let ephemeral = Keypair::new(&secp, &mut secp256k1::rand::thread_rng());
+    let (ephemeral_pubkey, parity) = ephemeral.x_only_public_key();
+
+    let mut ephemeral_seckey = ephemeral.secret_key();
+    if parity == secp256k1::Parity::Odd {
+        ephemeral_seckey = ephemeral_seckey.negate();
+    }
+
+    let cipher = encryption_cipher(
+        &pubkey.public_key(secp256k1::Parity::Even),
+        &ephemeral_seckey,
+        &ephemeral_pubkey,
+    );
+
+    let ciphertext = cipher
+        .encrypt(&Default::default(), body)
+        .map_err(|_| Error::InvalidCiphertext)?;
+
+    Message::new(
+        tags::ENCRYPTED,
+        [ephemeral_pubkey.serialize().to_vec(), ciphertext].concat(),
+    )
+}
+
+/// Decrypts a message produced by [`encrypt_to`] using the recipient's secret key
+#[cfg(feature = "encryption")]
+pub fn decrypt_with(seckey: &secp256k1::SecretKey, message: &Message) -> Result<Vec<u8>, Error> {
+    use chacha20poly1305::aead::Aead;
+
+    if message.tag != tags::ENCRYPTED || message.body.len() < 32 {
+        return Err(Error::InvalidCiphertext);
+    }
+
+    let (ephemeral_pubkey, ciphertext) = message.body.split_at(32);
+    let ephemeral_pubkey =
+        XOnlyPublicKey::from_slice(ephemeral_pubkey).map_err(|_| Error::InvalidCiphertext)?;
+
+    let secp = Secp256k1::signing_only();
+    let mut seckey = *seckey;
+    if seckey.x_only_public_key(&secp).1 == secp256k1::Parity::Odd {
+        seckey = seckey.negate();
+    }
+
+    let cipher = encryption_cipher(
+        &ephemeral_pubkey.public_key(secp256k1::Parity::Even),
+        &seckey,
+        &ephemeral_pubkey,
+    );
+
+    cipher
+        .decrypt(&Default::default(), ciphertext)
+        .map_err(|_| Error::InvalidCiphertext)
+}
+
+#[cfg(feature = "encryption")]
+fn encryption_cipher(
+    point: &secp256k1::PublicKey,
+    scalar: &secp256k1::SecretKey,
+    ephemeral_pubkey: &XOnlyPublicKey,
+) -> chacha20poly1305::ChaCha20Poly1305 {
+    use chacha20poly1305::KeyInit;
+
+    let shared_secret = secp256k1::ecdh::SharedSecret::new(point, scalar);
+    let key = tagged_hash(
+        ENCRYPTION_KEY_TAG,
+        &[
+            shared_secret.secret_bytes().as_slice(),
+            &ephemeral_pubkey.serialize(),
+        ]
+        .concat(),
+    );
+
+    chacha20poly1305::ChaCha20Poly1305::new(&key.into())
+}
+
+fn signature_hash(messages: &[Message]) -> secp256k1::Message {
+    let encoded = Message::encode(messages.to_vec());
+    secp256k1::Message::from_digest(tagged_hash(SIGNATURE_HASH_TAG, &encoded))
 }
 
 impl std::error::Error for Error {}
@@ -172,9 +514,15 @@ impl fmt::Display for Error {
             Error::InvalidVarInt => write!(f, "Invalid variable integer encoding"),
             Error::InvalidByteCount => write!(f, "Byte count exceeds 2^32 - 1"),
             Error::InvalidFinalSizeByte => write!(f, "Final size byte must be zero"),
+            Error::NonTerminalFinalMessage => {
+                write!(f, "Final message must use a terminal tag")
+            }
             Error::MissingBytes => {
                 write!(f, "Variable-length encoding indicates bytes are missing")
             }
+            Error::MissingSignature => write!(f, "Final message is not a signature"),
+            Error::InvalidSignature => write!(f, "Invalid signature"),
+            Error::InvalidCiphertext => write!(f, "Invalid ciphertext"),
         }
     }
 }
@@ -365,4 +713,249 @@ mod tests {
 
         assert_eq!(chunks, decoded);
     }
+
+    #[test]
+    fn test_encode_length_prefixed() {
+        let messages = vec![
+            Message::new(1, vec![1, 2]).unwrap(),
+            Message::new(1, vec![3]).unwrap(),
+            Message::new(200, vec![]).unwrap(),
+        ];
+
+        let encoded = Message::encode_with(messages.clone(), Framing::LengthPrefixed);
+
+        // Tag(1) + Size(2) + [1,2] + Tag(1) + Size(1) + [3] + Tag(200) + Size(0)
+        assert_eq!(encoded, vec![1, 2, 1, 2, 1, 1, 3, 200, 1, 0]);
+
+        let decoded = Message::decode_with(&encoded, Framing::LengthPrefixed).unwrap();
+        assert_eq!(decoded, messages);
+    }
+
+    #[test]
+    fn test_framing_default_is_compact() {
+        let messages = vec![
+            Message::new(1, vec![1, 2]).unwrap(),
+            Message::new(2, vec![3, 4, 5]).unwrap(),
+        ];
+
+        assert_eq!(
+            Message::encode_with(messages.clone(), Framing::default()),
+            Message::encode(messages.clone())
+        );
+
+        let encoded = Message::encode(messages.clone());
+        assert_eq!(
+            Message::decode_with(&encoded, Framing::Compact).unwrap(),
+            messages
+        );
+    }
+
+    #[test]
+    fn test_decode_length_prefixed_invalid() {
+        // Zero tag
+        let result = Message::decode_with(&[0, 1, 1], Framing::LengthPrefixed);
+        assert_eq!(result.err(), Some(Error::InvalidTag));
+
+        // Missing length
+        let result = Message::decode_with(&[1], Framing::LengthPrefixed);
+        assert_eq!(result.err(), Some(Error::MissingBytes));
+
+        // Length exceeds remaining bytes
+        let result = Message::decode_with(&[1, 3, 1, 2], Framing::LengthPrefixed);
+        assert_eq!(result.err(), Some(Error::MissingBytes));
+
+        // Unterminated varint
+        let result = Message::decode_with(&[0xFF], Framing::LengthPrefixed);
+        assert_eq!(result.err(), Some(Error::InvalidVarInt));
+    }
+
+    #[test]
+    fn test_decode_strict_rejects_non_minimal_varints() {
+        // Terminal tag 1 encoded as 0x83 0x00 instead of 0x03
+        let encoded = vec![0x83, 0x00, 5, 6, 7];
+        assert_eq!(Message::decode(&encoded).unwrap()[0].body, vec![5, 6, 7]);
+        assert_eq!(
+            Message::decode_strict(&encoded).err(),
+            Some(Error::InvalidVarInt)
+        );
+
+        // Size 2 encoded as 0x82 0x00 instead of 0x02
+        let encoded = vec![2, 0x82, 0x00, 1, 2, 5, 3];
+        assert_eq!(Message::decode(&encoded).unwrap().len(), 2);
+        assert_eq!(
+            Message::decode_strict(&encoded).err(),
+            Some(Error::InvalidVarInt)
+        );
+
+        // A zero size byte in place of the terminal tag
+        let encoded = vec![2, 0, 5, 6, 7];
+        assert_eq!(
+            Message::decode(&encoded).unwrap(),
+            Message::decode(&[3, 5, 6, 7]).unwrap()
+        );
+        assert_eq!(
+            Message::decode_strict(&encoded).err(),
+            Some(Error::NonTerminalFinalMessage)
+        );
+
+        // Canonical encodings are accepted
+        let encoded = vec![2, 2, 1, 2, 5, 3, 4, 5];
+        assert_eq!(
+            Message::decode_strict(&encoded).unwrap(),
+            Message::decode(&encoded).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_decode_strict_with_length_prefixed() {
+        let encoded = vec![0x81, 0x00, 1, 9];
+        assert_eq!(
+            Message::decode_with(&encoded, Framing::LengthPrefixed).unwrap()[0].body,
+            vec![9]
+        );
+        assert_eq!(
+            Message::decode_strict_with(&encoded, Framing::LengthPrefixed).err(),
+            Some(Error::InvalidVarInt)
+        );
+        assert_eq!(
+            Message::decode_strict_with(&[1, 1, 9], Framing::LengthPrefixed)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    fn test_keypair() -> Keypair {
+        let secp = Secp256k1::signing_only();
+        Keypair::from_seckey_slice(&secp, &[0xcd; 32]).unwrap()
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let keypair = test_keypair();
+        let messages = vec![
+            Message::new(1, vec![1, 2]).unwrap(),
+            Message::new(2, vec![3, 4, 5]).unwrap(),
+        ];
+
+        let signed = sign(messages.clone(), &keypair);
+        assert_eq!(signed.len(), 3);
+        assert_eq!(signed[..2], messages[..]);
+        assert_eq!(signed[2].tag, tags::SIGNATURE);
+        assert_eq!(signed[2].body.len(), 64);
+
+        let (pubkey, _) = keypair.x_only_public_key();
+        assert_eq!(verify(&signed, &pubkey), Ok(()));
+
+        // Signature survives an encoding roundtrip
+        let decoded = Message::decode(&Message::encode(signed)).unwrap();
+        assert_eq!(verify(&decoded, &pubkey), Ok(()));
+    }
+
+    #[test]
+    fn test_verify_wrong_key() {
+        let signed = sign(vec![Message::new(1, vec![1]).unwrap()], &test_keypair());
+
+        let secp = Secp256k1::signing_only();
+        let other = Keypair::from_seckey_slice(&secp, &[0xab; 32]).unwrap();
+        let (pubkey, _) = other.x_only_public_key();
+
+        assert_eq!(verify(&signed, &pubkey), Err(Error::InvalidSignature));
+    }
+
+    #[test]
+    fn test_verify_tampered_messages() {
+        let keypair = test_keypair();
+        let (pubkey, _) = keypair.x_only_public_key();
+        let mut signed = sign(vec![Message::new(1, vec![1, 2, 3]).unwrap()], &keypair);
+
+        signed[0].body[0] = 9;
+        assert_eq!(verify(&signed, &pubkey), Err(Error::InvalidSignature));
+    }
+
+    #[test]
+    fn test_verify_missing_signature() {
+        let (pubkey, _) = test_keypair().x_only_public_key();
+
+        assert_eq!(verify(&[], &pubkey), Err(Error::MissingSignature));
+
+        let unsigned = vec![Message::new(1, vec![1, 2, 3]).unwrap()];
+        assert_eq!(verify(&unsigned, &pubkey), Err(Error::MissingSignature));
+
+        let malformed = vec![Message::new(tags::SIGNATURE, vec![0; 10]).unwrap()];
+        assert_eq!(verify(&malformed, &pubkey), Err(Error::InvalidSignature));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_encrypt_and_decrypt() {
+        let keypair = test_keypair();
+        let (pubkey, _) = keypair.x_only_public_key();
+
+        let message = encrypt_to(&pubkey, b"secret").unwrap();
+        assert_eq!(message.tag, tags::ENCRYPTED);
+        assert_eq!(message.body.len(), 32 + 6 + 16);
+
+        let decrypted = decrypt_with(&keypair.secret_key(), &message).unwrap();
+        assert_eq!(decrypted, b"secret");
+
+        // Odd-parity recipient keys decrypt as well
+        let secp = Secp256k1::new();
+        for i in 1..8u8 {
+            let keypair = Keypair::from_seckey_slice(&secp, &[i; 32]).unwrap();
+            let (pubkey, _) = keypair.x_only_public_key();
+            let message = encrypt_to(&pubkey, &[i; 100]).unwrap();
+            assert_eq!(
+                decrypt_with(&keypair.secret_key(), &message).unwrap(),
+                vec![i; 100]
+            );
+        }
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_decrypt_invalid() {
+        let keypair = test_keypair();
+        let (pubkey, _) = keypair.x_only_public_key();
+
+        let secp = Secp256k1::signing_only();
+        let other = Keypair::from_seckey_slice(&secp, &[0xab; 32]).unwrap();
+
+        let mut message = encrypt_to(&pubkey, b"secret").unwrap();
+        assert_eq!(
+            decrypt_with(&other.secret_key(), &message),
+            Err(Error::InvalidCiphertext)
+        );
+
+        let last = message.body.len() - 1;
+        message.body[last] ^= 1;
+        assert_eq!(
+            decrypt_with(&keypair.secret_key(), &message),
+            Err(Error::InvalidCiphertext)
+        );
+
+        let short = Message::new(tags::ENCRYPTED, vec![0; 31]).unwrap();
+        assert_eq!(
+            decrypt_with(&keypair.secret_key(), &short),
+            Err(Error::InvalidCiphertext)
+        );
+    }
+
+    #[test]
+    fn test_pointer() {
+        use std::str::FromStr;
+
+        let txid = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+        let id = EmbeddingId::from_str(&format!("{txid}:te:3:7")).unwrap();
+
+        let messages = vec![
+            Message::new(1, vec![1]).unwrap(),
+            Message::new(tags::POINTER, vec![0; 32]).unwrap(),
+            pointer_to(&id),
+        ];
+        let decoded = Message::decode(&Message::encode(messages)).unwrap();
+        assert_eq!(pointer_target(&decoded), Some(id));
+
+        assert_eq!(pointer_target(&decoded[..2]), None);
+    }
 }
@@ -0,0 +1,152 @@
+//! # MessagePack Bodies
+//!
+//! Encodes message bodies as MessagePack with serde. Encoding uses the smallest width for each
+//! integer, length, and header, and keeps map entries in serialization order. Structs are maps of
+//! field names, and enum variants other than unit variants (names) are single-entry maps from the
+//! variant name, as in JSON.
+//!
+//! Decoding accepts any valid MessagePack, including wider encodings than needed, and
+//! [`decode`] exposes the raw [`Value`], with extension types, for protocols that need it.
+
+use crate::message::{Message, Tag};
+
+pub use rmpv::Value;
+use serde::{Serialize, de::DeserializeOwned};
+use std::fmt;
+
+/// Error types for encoding and decoding MessagePack bodies
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MsgpackError {
+    /// The value could not be serialized
+    Serialize(String),
+    /// The body is not valid MessagePack or does not match the requested type
+    Deserialize(String),
+    /// The body has bytes after the first value
+    TrailingBytes,
+}
+
+/// Returns `value` encoded as MessagePack
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, MsgpackError> {
+    rmp_serde::to_vec_named(value).map_err(|e| MsgpackError::Serialize(e.to_string()))
+}
+
+/// Decodes a single MessagePack value into `T`, rejecting trailing bytes
+pub fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, MsgpackError> {
+    let mut reader = bytes;
+    let value =
+        rmp_serde::from_read(&mut reader).map_err(|e| MsgpackError::Deserialize(e.to_string()))?;
+
+    if !reader.is_empty() {
+        return Err(MsgpackError::TrailingBytes);
+    }
+
+    Ok(value)
+}
+
+/// Returns a message with `value` encoded as MessagePack as its body
+pub fn to_message<T: Serialize + ?Sized>(tag: Tag, value: &T) -> Result<Message, MsgpackError> {
+    Message::new(tag, to_vec(value)?).map_err(|e| MsgpackError::Serialize(e.to_string()))
+}
+
+/// Encodes a [`Value`]
+pub fn encode(value: &Value) -> Result<Vec<u8>, MsgpackError> {
+    let mut bytes = Vec::new();
+    rmpv::encode::write_value(&mut bytes, value)
+        .map_err(|e| MsgpackError::Serialize(e.to_string()))?;
+    Ok(bytes)
+}
+
+/// Decodes a single value, rejecting trailing bytes
+pub fn decode(bytes: &[u8]) -> Result<Value, MsgpackError> {
+    let mut reader = bytes;
+    let value = rmpv::decode::read_value(&mut reader)
+        .map_err(|e| MsgpackError::Deserialize(e.to_string()))?;
+
+    if !reader.is_empty() {
+        return Err(MsgpackError::TrailingBytes);
+    }
+
+    Ok(value)
+}
+
+impl fmt::Display for MsgpackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MsgpackError::Serialize(e) => write!(f, "MessagePack serialization error: {e}"),
+            MsgpackError::Deserialize(e) => write!(f, "MessagePack deserialization error: {e}"),
+            MsgpackError::TrailingBytes => write!(f, "Trailing bytes after MessagePack value"),
+        }
+    }
+}
+
+impl std::error::Error for MsgpackError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::hex::FromHex;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Example {
+        compact: bool,
+        schema: u8,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Op {
+        Mint,
+        Move { to: u32, memo: Option<String> },
+    }
+
+    #[test]
+    fn test_spec_example() {
+        // The example from msgpack.org
+        let bytes = Vec::from_hex("82a7636f6d70616374c3a6736368656d6100").unwrap();
+        let example = Example {
+            compact: true,
+            schema: 0,
+        };
+        assert_eq!(to_vec(&example).unwrap(), bytes);
+        assert_eq!(from_slice(&bytes), Ok(example));
+
+        let message = to_message(5, &true).unwrap();
+        assert_eq!(message.body, vec![0xc3]);
+
+        assert_eq!(to_vec(&Op::Mint).unwrap(), b"\xa4Mint");
+        let op = Op::Move {
+            to: 3,
+            memo: Some("gm".to_string()),
+        };
+        assert_eq!(from_slice::<Op>(&to_vec(&op).unwrap()), Ok(op));
+    }
+
+    #[test]
+    fn test_values() {
+        let value = Value::Ext(-1, vec![0; 4]);
+        assert_eq!(encode(&value).unwrap(), [0xd6, 0xff, 0, 0, 0, 0]);
+        assert_eq!(decode(&encode(&value).unwrap()), Ok(value));
+
+        // Wider encodings than needed decode to the same value
+        assert_eq!(decode(&[0xd0, 0x05]), Ok(Value::from(5)));
+        assert_eq!(from_slice::<u8>(&[0xcd, 0x00, 0x05]), Ok(5));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(
+            from_slice::<u8>(&[0x01, 0x02]),
+            Err(MsgpackError::TrailingBytes)
+        );
+        assert_eq!(decode(&[0x01, 0x02]), Err(MsgpackError::TrailingBytes));
+        assert!(matches!(
+            decode(&[0xcd, 0x01]),
+            Err(MsgpackError::Deserialize(_))
+        ));
+        assert!(matches!(
+            from_slice::<u8>(&[0xa1, 0x61]),
+            Err(MsgpackError::Deserialize(_))
+        ));
+    }
+}
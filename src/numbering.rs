@@ -0,0 +1,352 @@
+//! # Embedding Numbering
+//!
+//! [`number_block`] numbers the embeddings of one block from zero, by position in the block and
+//! then by position in the transaction. A [`Counter`] continues the numbering across the chain,
+//! assigning sequential numbers to confirmed embeddings in chain order: by block height, then by
+//! position in the block, then by position in the transaction. Feeding it every embedding from a
+//! [`Follower`](crate::follower::Follower) gives each embedding the same number on every node, in
+//! the same way inscription numbers are assigned.
+//!
+//! Numbers assigned in a block are released when that block is disconnected, so a reorg
+//! renumbers the replacing blocks from where the disconnected ones started.
+
+use crate::follower::DEFAULT_REORG_DEPTH;
+use crate::resolver::ConfirmedEmbedding;
+
+use bitcoin::Block;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Error types for embedding numbering
+#[derive(Debug)]
+pub enum NumberingError {
+    /// The counter file could not be read or written
+    Io(io::Error),
+    /// The counter file is malformed
+    InvalidCounter,
+    /// A reorg disconnected every block retained in the counter
+    ReorgTooDeep,
+}
+
+/// Assigns chain-ordered numbers to confirmed embeddings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counter {
+    next: u64,
+    /// The height of each recent block with embeddings and the first number assigned in it
+    blocks: VecDeque<(u64, u64)>,
+    /// The height of the last block dropped from `blocks`
+    pruned: Option<u64>,
+    depth: usize,
+}
+
+impl Counter {
+    /// Returns a counter starting at zero that can rewind up to `depth` blocks with embeddings
+    pub fn new(depth: usize) -> Self {
+        Self {
+            next: 0,
+            blocks: VecDeque::new(),
+            pruned: None,
+            depth: depth.max(1),
+        }
+    }
+
+    /// Returns the number the next embedding will be assigned
+    pub fn next(&self) -> u64 {
+        self.next
+    }
+
+    /// Assigns the next number to an embedding. Embeddings must be passed in chain order.
+    pub fn assign(&mut self, embedding: &ConfirmedEmbedding) -> u64 {
+        if self.blocks.back().map(|(height, _)| *height) != Some(embedding.height) {
+            self.blocks.push_back((embedding.height, self.next));
+            while self.blocks.len() > self.depth {
+                self.pruned = self.blocks.pop_front().map(|(height, _)| height);
+            }
+        }
+
+        let number = self.next;
+        self.next += 1;
+        number
+    }
+
+    /// Releases the numbers assigned at or above `height`, leaving the counter unchanged if
+    /// that reaches past the retained blocks
+    pub fn disconnect(&mut self, height: u64) -> Result<(), NumberingError> {
+        if self.pruned.is_some_and(|pruned| pruned >= height) {
+            return Err(NumberingError::ReorgTooDeep);
+        }
+
+        while let Some(&(block_height, first)) = self.blocks.back() {
+            if block_height < height {
+                break;
+            }
+            self.blocks.pop_back();
+            self.next = first;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a counter written by [`Counter::save`]
+    pub fn load(path: impl AsRef<Path>, depth: usize) -> Result<Self, NumberingError> {
+        let contents = fs::read_to_string(path)?;
+        let mut lines = contents.lines();
+        let mut counter = Self::new(depth);
+
+        let mut first_line = lines
+            .next()
+            .ok_or(NumberingError::InvalidCounter)?
+            .split(' ');
+        counter.next = first_line
+            .next()
+            .and_then(|next| next.parse().ok())
+            .ok_or(NumberingError::InvalidCounter)?;
+        counter.pruned = first_line
+            .next()
+            .map(|pruned| pruned.parse())
+            .transpose()
+            .map_err(|_| NumberingError::InvalidCounter)?;
+        if first_line.next().is_some() {
+            return Err(NumberingError::InvalidCounter);
+        }
+
+        for line in lines {
+            let (height, first) = line.split_once(' ').ok_or(NumberingError::InvalidCounter)?;
+            let height = height.parse().map_err(|_| NumberingError::InvalidCounter)?;
+            let first = first.parse().map_err(|_| NumberingError::InvalidCounter)?;
+            counter.blocks.push_back((height, first));
+            while counter.blocks.len() > counter.depth {
+                counter.pruned = counter.blocks.pop_front().map(|(height, _)| height);
+            }
+        }
+
+        Ok(counter)
+    }
+
+    /// Writes the next number and the height of the last pruned block, if any, followed by one
+    /// `<height> <first number>` line per retained block, replacing the file atomically
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), NumberingError> {
+        let path = path.as_ref();
+        let first_line = match self.pruned {
+            Some(pruned) => format!("{} {pruned}\n", self.next),
+            None => format!("{}\n", self.next),
+        };
+        let contents = std::iter::once(first_line)
+            .chain(
+                self.blocks
+                    .iter()
+                    .map(|(height, first)| format!("{height} {first}\n")),
+            )
+            .collect::<String>();
+
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, contents)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self::new(DEFAULT_REORG_DEPTH)
+    }
+}
+
+/// Returns every embedding in a block at `height`, as in [`ConfirmedEmbedding::from_block`],
+/// numbered from zero in block order
+pub fn number_block(block: &Block, height: u64) -> Vec<(u64, ConfirmedEmbedding)> {
+    (0..)
+        .zip(ConfirmedEmbedding::from_block(block, height))
+        .collect()
+}
+
+impl From<io::Error> for NumberingError {
+    fn from(e: io::Error) -> Self {
+        NumberingError::Io(e)
+    }
+}
+
+impl fmt::Display for NumberingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NumberingError::Io(e) => write!(f, "Counter I/O error: {e}"),
+            NumberingError::InvalidCounter => write!(f, "Invalid counter file"),
+            NumberingError::ReorgTooDeep => {
+                write!(f, "Reorg is deeper than the blocks retained in the counter")
+            }
+        }
+    }
+}
+
+impl std::error::Error for NumberingError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NumberingError::Io(e) => Some(e),
+            NumberingError::InvalidCounter | NumberingError::ReorgTooDeep => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Embedding;
+
+    use bitcoin::block::{Header, Version as BlockVersion};
+    use bitcoin::hashes::Hash;
+    use bitcoin::{
+        Amount, BlockHash, CompactTarget, ScriptBuf, Transaction, TxMerkleNode, TxOut,
+        absolute::LockTime, transaction::Version,
+    };
+
+    fn confirmed(height: u64, tx_index: usize) -> ConfirmedEmbedding {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::from_consensus(tx_index as u32),
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::new_op_return([height as u8]),
+            }],
+        };
+
+        ConfirmedEmbedding {
+            embedding: Embedding::from_transaction(&tx).remove(0),
+            block_hash: BlockHash::all_zeros(),
+            height,
+            time: 0,
+            tx_index,
+        }
+    }
+
+    #[test]
+    fn test_number_block() {
+        let txdata = [2, 0, 1].map(|outputs| Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: (0..outputs)
+                .map(|i| TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey: ScriptBuf::new_op_return([i]),
+                })
+                .collect(),
+        });
+        let block = Block {
+            header: Header {
+                version: BlockVersion::ONE,
+                prev_blockhash: BlockHash::all_zeros(),
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: 0,
+                bits: CompactTarget::from_consensus(0),
+                nonce: 0,
+            },
+            txdata: txdata.to_vec(),
+        };
+
+        let numbered = number_block(&block, 7);
+        assert_eq!(
+            numbered
+                .iter()
+                .map(|(number, confirmed)| (*number, confirmed.tx_index, confirmed.height))
+                .collect::<Vec<_>>(),
+            vec![(0, 0, 7), (1, 0, 7), (2, 2, 7)]
+        );
+        assert_eq!(numbered[1].1.embedding.bytes, vec![1, 1]);
+
+        // A counter continues the block numbering across the chain
+        let mut counter = Counter::default();
+        counter.assign(&confirmed(6, 0));
+        for (number, confirmed) in &numbered {
+            assert_eq!(counter.assign(confirmed), number + 1);
+        }
+    }
+
+    #[test]
+    fn test_assign_and_disconnect() {
+        let mut counter = Counter::default();
+
+        let numbers = [(1, 0), (1, 1), (3, 0), (4, 0), (4, 2)]
+            .map(|(height, tx_index)| counter.assign(&confirmed(height, tx_index)));
+        assert_eq!(numbers, [0, 1, 2, 3, 4]);
+
+        // Disconnecting an empty block releases nothing
+        counter.disconnect(5).unwrap();
+        assert_eq!(counter.next(), 5);
+
+        // Disconnecting blocks 4 and 3 releases their numbers
+        counter.disconnect(4).unwrap();
+        assert_eq!(counter.next(), 3);
+        counter.disconnect(2).unwrap();
+        assert_eq!(counter.next(), 2);
+
+        assert_eq!(counter.assign(&confirmed(2, 0)), 2);
+    }
+
+    #[test]
+    fn test_reorg_too_deep() {
+        let mut counter = Counter::new(2);
+        for height in 0..4 {
+            counter.assign(&confirmed(height, 0));
+        }
+
+        // A reorg past the retained blocks releases nothing
+        let before = counter.clone();
+        assert!(matches!(
+            counter.disconnect(1),
+            Err(NumberingError::ReorgTooDeep)
+        ));
+        assert_eq!(counter, before);
+
+        counter.disconnect(2).unwrap();
+        assert_eq!(counter.next(), 2);
+        assert!(matches!(
+            counter.disconnect(1),
+            Err(NumberingError::ReorgTooDeep)
+        ));
+    }
+
+    #[test]
+    fn test_persisted_counter() {
+        let path = std::env::temp_dir().join(format!(
+            "bitcoin-embed-numbering-{}.counter",
+            std::process::id()
+        ));
+
+        let mut counter = Counter::default();
+        for height in [10, 10, 12] {
+            counter.assign(&confirmed(height, 0));
+        }
+        counter.save(&path).unwrap();
+
+        let mut loaded = Counter::load(&path, DEFAULT_REORG_DEPTH).unwrap();
+        assert_eq!(loaded, counter);
+
+        loaded.disconnect(12).unwrap();
+        assert_eq!(loaded.next(), 2);
+
+        // The pruned height survives a restart, so a deep reorg is still detected
+        let mut counter = Counter::new(2);
+        for height in 1..=3 {
+            counter.assign(&confirmed(height, 0));
+        }
+        counter.save(&path).unwrap();
+        let mut loaded = Counter::load(&path, 2).unwrap();
+        assert_eq!(loaded, counter);
+        assert!(matches!(
+            loaded.disconnect(1),
+            Err(NumberingError::ReorgTooDeep)
+        ));
+
+        fs::write(&path, "not a counter").unwrap();
+        assert!(matches!(
+            Counter::load(&path, DEFAULT_REORG_DEPTH),
+            Err(NumberingError::InvalidCounter)
+        ));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,373 @@
+//! # Conditional Offers
+//!
+//! A maker commits offer data to a taproot output whose leaf is an envelope followed by
+//! `<maker key> OP_CHECKSIG`, then signs a script-path spend of that output with
+//! `SIGHASH_SINGLE|ANYONECANPAY`. The signature covers only the maker's input, which reveals
+//! the offer data, and the output at the same index, which pays the maker's price. Any taker
+//! can complete the transaction by placing both at the same index alongside their own inputs
+//! and outputs, so the data is published if and only if the maker is paid.
+//!
+//! The maker's input spends the commit output built with [`CommitOutput`].
+
+use crate::descriptor::CommitOutput;
+use crate::envelope::{self, Envelope, EnvelopeBuilder};
+
+use bitcoin::hashes::Hash;
+use bitcoin::key::{Keypair, XOnlyPublicKey};
+use bitcoin::opcodes::all::OP_CHECKSIG;
+use bitcoin::script::{Builder, Instruction};
+use bitcoin::secp256k1::{self, Secp256k1, Signing, Verification};
+use bitcoin::sighash::{Prevouts, SighashCache, TapSighashType};
+use bitcoin::taproot::{self, ControlBlock, LeafVersion, TapLeafHash};
+use bitcoin::{
+    OutPoint, Script, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness, absolute::LockTime,
+    transaction::Version,
+};
+use std::fmt;
+
+/// The sighash type of an offer signature
+pub const OFFER_SIGHASH_TYPE: TapSighashType = TapSighashType::SinglePlusAnyoneCanPay;
+
+/// A signed offer: the maker's input and the output paying the maker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Offer {
+    /// The transaction version the signature commits to
+    pub version: Version,
+    /// The lock time the signature commits to
+    pub lock_time: LockTime,
+    /// The maker's input, with a witness of the signature, the offer leaf, and its control block
+    pub input: TxIn,
+    /// The output spent by the maker's input
+    pub prevout: TxOut,
+    /// The output paying the maker
+    pub output: TxOut,
+}
+
+/// Error types for signing, verifying, and completing offers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OfferError {
+    /// The leaf is not in the commit output
+    LeafNotFound,
+    /// The witness is not a signature, an offer leaf, and a control block
+    InvalidWitness,
+    /// The signature does not use [`OFFER_SIGHASH_TYPE`]
+    InvalidSighashType,
+    /// The control block does not commit the leaf to the spent output
+    InvalidCommitment,
+    /// The signature does not verify
+    InvalidSignature,
+    /// The transaction's version or lock time differs from the offer's
+    TemplateMismatch,
+    /// The index is past the end of the transaction's inputs or outputs
+    InvalidIndex(usize),
+}
+
+/// Returns the offer leaf: an envelope of `fields` followed by `<maker> OP_CHECKSIG`
+pub fn offer_leaf(
+    builder: &EnvelopeBuilder,
+    fields: Vec<Vec<u8>>,
+    maker: &XOnlyPublicKey,
+) -> ScriptBuf {
+    builder
+        .append_to_builder(fields, Builder::new())
+        .push_x_only_key(maker)
+        .push_opcode(OP_CHECKSIG)
+        .into_script()
+}
+
+impl Offer {
+    /// Signs an offer spending `outpoint`, an output of `commit` worth `prevout`, through
+    /// `leaf`, in exchange for `payment`
+    pub fn sign<C: Signing + Verification>(
+        secp: &Secp256k1<C>,
+        keypair: &Keypair,
+        commit: &CommitOutput,
+        leaf: &Script,
+        outpoint: OutPoint,
+        prevout: TxOut,
+        payment: TxOut,
+    ) -> Result<Self, OfferError> {
+        let control_block = commit.control_block(leaf).ok_or(OfferError::LeafNotFound)?;
+
+        let mut offer = Self {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: TxIn {
+                previous_output: outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            },
+            prevout,
+            output: payment,
+        };
+
+        let msg = offer.sighash(leaf);
+        let signature = taproot::Signature {
+            signature: secp.sign_schnorr_no_aux_rand(&msg, keypair),
+            sighash_type: OFFER_SIGHASH_TYPE,
+        };
+
+        offer.input.witness = Witness::from_slice(&[
+            signature.to_vec(),
+            leaf.to_bytes(),
+            control_block.serialize(),
+        ]);
+
+        Ok(offer)
+    }
+
+    /// Verifies the maker's signature and the leaf's commitment to the spent output
+    pub fn verify<C: Verification>(&self, secp: &Secp256k1<C>) -> Result<(), OfferError> {
+        let (signature, leaf, control_block) = self.parse_witness()?;
+
+        if signature.sighash_type != OFFER_SIGHASH_TYPE {
+            return Err(OfferError::InvalidSighashType);
+        }
+
+        let output_key = self
+            .prevout
+            .script_pubkey
+            .is_p2tr()
+            .then(|| XOnlyPublicKey::from_slice(&self.prevout.script_pubkey.as_bytes()[2..]).ok())
+            .flatten()
+            .ok_or(OfferError::InvalidCommitment)?;
+        if !control_block.verify_taproot_commitment(secp, output_key, leaf) {
+            return Err(OfferError::InvalidCommitment);
+        }
+
+        let maker = maker_key(leaf).ok_or(OfferError::InvalidWitness)?;
+        secp.verify_schnorr(&signature.signature, &self.sighash(leaf), &maker)
+            .map_err(|_| OfferError::InvalidSignature)
+    }
+
+    /// Returns the envelopes in the offer leaf
+    pub fn envelopes(&self) -> Vec<Envelope> {
+        self.input
+            .witness
+            .nth(1)
+            .map(|leaf| envelope::from_script(Script::from_bytes(leaf)))
+            .unwrap_or_default()
+    }
+
+    /// Inserts the maker's input and output at `index` in a taker's transaction. The taker
+    /// signs their own inputs afterwards.
+    pub fn complete(&self, tx: &mut Transaction, index: usize) -> Result<(), OfferError> {
+        if tx.version != self.version || tx.lock_time != self.lock_time {
+            return Err(OfferError::TemplateMismatch);
+        }
+
+        if index > tx.input.len() || index > tx.output.len() {
+            return Err(OfferError::InvalidIndex(index));
+        }
+
+        tx.input.insert(index, self.input.clone());
+        tx.output.insert(index, self.output.clone());
+
+        Ok(())
+    }
+
+    /// Returns the signature hash, which is the same at any index of any completed transaction
+    fn sighash(&self, leaf: &Script) -> secp256k1::Message {
+        let tx = Transaction {
+            version: self.version,
+            lock_time: self.lock_time,
+            input: vec![TxIn {
+                witness: Witness::new(),
+                ..self.input.clone()
+            }],
+            output: vec![self.output.clone()],
+        };
+
+        let sighash = SighashCache::new(&tx)
+            .taproot_script_spend_signature_hash(
+                0,
+                &Prevouts::One(0, &self.prevout),
+                TapLeafHash::from_script(leaf, LeafVersion::TapScript),
+                OFFER_SIGHASH_TYPE,
+            )
+            .expect("single input with prevout");
+
+        secp256k1::Message::from_digest(sighash.to_byte_array())
+    }
+
+    fn parse_witness(&self) -> Result<(taproot::Signature, &Script, ControlBlock), OfferError> {
+        let witness = &self.input.witness;
+        if witness.len() != 3 {
+            return Err(OfferError::InvalidWitness);
+        }
+
+        let signature =
+            taproot::Signature::from_slice(&witness[0]).map_err(|_| OfferError::InvalidWitness)?;
+        let leaf = Script::from_bytes(&witness[1]);
+        let control_block =
+            ControlBlock::decode(&witness[2]).map_err(|_| OfferError::InvalidWitness)?;
+
+        Ok((signature, leaf, control_block))
+    }
+}
+
+/// Returns the maker key of an offer leaf: the key in the `<key> OP_CHECKSIG` after the
+/// leaf's envelopes
+fn maker_key(leaf: &Script) -> Option<XOnlyPublicKey> {
+    let end = envelope::from_script(leaf)
+        .last()
+        .map_or(0, |envelope| envelope.range.end);
+
+    let mut instructions = Script::from_bytes(&leaf.as_bytes()[end..]).instructions();
+    let Some(Ok(Instruction::PushBytes(key))) = instructions.next() else {
+        return None;
+    };
+    let Some(Ok(Instruction::Op(OP_CHECKSIG))) = instructions.next() else {
+        return None;
+    };
+    if instructions.next().is_some() {
+        return None;
+    }
+
+    XOnlyPublicKey::from_slice(key.as_bytes()).ok()
+}
+
+impl fmt::Display for OfferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OfferError::LeafNotFound => write!(f, "Leaf is not in the commit output"),
+            OfferError::InvalidWitness => write!(f, "Invalid offer witness"),
+            OfferError::InvalidSighashType => write!(f, "Offer is not signed SINGLE|ANYONECANPAY"),
+            OfferError::InvalidCommitment => {
+                write!(f, "Offer leaf is not committed to the prevout")
+            }
+            OfferError::InvalidSignature => write!(f, "Invalid offer signature"),
+            OfferError::TemplateMismatch => {
+                write!(f, "Transaction version or lock time differs from the offer")
+            }
+            OfferError::InvalidIndex(index) => write!(f, "Invalid offer index: {index}"),
+        }
+    }
+}
+
+impl std::error::Error for OfferError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Embedding, EmbeddingType};
+
+    use bitcoin::secp256k1::SecretKey;
+    use bitcoin::{Amount, Txid};
+
+    fn offer() -> (Secp256k1<secp256k1::All>, Offer) {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[3; 32]).unwrap());
+        let maker = keypair.x_only_public_key().0;
+
+        let leaf = offer_leaf(
+            &EnvelopeBuilder::new(),
+            vec![b"offer".to_vec(), vec![9; 300]],
+            &maker,
+        );
+        let commit = CommitOutput::new(&secp, maker, vec![leaf.clone()]).unwrap();
+        let prevout = TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: commit.script_pubkey(),
+        };
+        let payment = TxOut {
+            value: Amount::from_sat(50_000),
+            script_pubkey: ScriptBuf::new_p2tr(&secp, maker, None),
+        };
+
+        let offer = Offer::sign(
+            &secp,
+            &keypair,
+            &commit,
+            &leaf,
+            OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+            prevout,
+            payment,
+        )
+        .unwrap();
+
+        (secp, offer)
+    }
+
+    #[test]
+    fn test_offer() {
+        let (secp, offer) = offer();
+        assert_eq!(offer.verify(&secp), Ok(()));
+        assert_eq!(
+            offer.envelopes()[0].concat(),
+            [b"offer".to_vec(), vec![9; 300]].concat()
+        );
+
+        let mut tampered = offer.clone();
+        tampered.output.value = Amount::from_sat(1);
+        assert_eq!(tampered.verify(&secp), Err(OfferError::InvalidSignature));
+
+        let mut tampered = offer.clone();
+        tampered.prevout.script_pubkey = offer.output.script_pubkey.clone();
+        assert_eq!(tampered.verify(&secp), Err(OfferError::InvalidCommitment));
+
+        let mut tampered = offer.clone();
+        tampered.input.witness = Witness::from_slice(&[vec![0; 64]]);
+        assert_eq!(tampered.verify(&secp), Err(OfferError::InvalidWitness));
+    }
+
+    #[test]
+    fn test_complete() {
+        let (secp, offer) = offer();
+
+        let taker_input = TxIn {
+            previous_output: OutPoint::new(Txid::from_byte_array([2; 32]), 1),
+            ..TxIn::default()
+        };
+        let taker_output = TxOut {
+            value: Amount::from_sat(1_000),
+            script_pubkey: ScriptBuf::new_op_return([1; 4]),
+        };
+        let mut tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![taker_input.clone(), taker_input],
+            output: vec![taker_output.clone(), taker_output],
+        };
+
+        assert_eq!(
+            offer.complete(&mut tx.clone(), 3),
+            Err(OfferError::InvalidIndex(3))
+        );
+        let mut other = tx.clone();
+        other.lock_time = LockTime::from_height(1).unwrap();
+        assert_eq!(
+            offer.complete(&mut other, 0),
+            Err(OfferError::TemplateMismatch)
+        );
+
+        offer.complete(&mut tx, 1).unwrap();
+        assert_eq!(tx.input[1], offer.input);
+        assert_eq!(tx.output[1], offer.output);
+
+        // The signature is valid in the completed transaction
+        let (signature, leaf, _) = offer.parse_witness().unwrap();
+        let sighash = SighashCache::new(&tx)
+            .taproot_script_spend_signature_hash(
+                1,
+                &Prevouts::One(1, &offer.prevout),
+                TapLeafHash::from_script(leaf, LeafVersion::TapScript),
+                OFFER_SIGHASH_TYPE,
+            )
+            .unwrap();
+        let msg = secp256k1::Message::from_digest(sighash.to_byte_array());
+        let maker = maker_key(leaf).unwrap();
+        assert!(
+            secp.verify_schnorr(&signature.signature, &msg, &maker)
+                .is_ok()
+        );
+
+        // The offer data is extracted from the completed transaction
+        let embedding = Embedding::from_transaction(&tx)
+            .into_iter()
+            .find(|embedding| embedding.to_type() != EmbeddingType::OpReturn)
+            .unwrap();
+        assert_eq!(embedding.bytes, [b"offer".to_vec(), vec![9; 300]].concat());
+    }
+}
@@ -0,0 +1,298 @@
+//! # Block-Scanning Pipeline
+//!
+//! A [`Pipeline`] scans a sequence of blocks with several extraction workers and delivers every
+//! [`ConfirmedEmbedding`] on one channel, in block order, whatever order the workers finish in.
+//! Every stage is connected by a bounded channel, and at most `capacity + workers` blocks are
+//! in flight at once, so a slow consumer or a slow block pauses the workers and the block source
+//! instead of buffering the chain in memory. Dropping the receiver stops the pipeline.
+//!
+//! ```no_run
+//! # use bitcoin_embed::pipeline::{self, Pipeline};
+//! # fn scan<S: bitcoin_embed::follower::ChainSource + Send + 'static>(source: S) {
+//! for embedding in Pipeline::new().workers(8).run(pipeline::chain_blocks(source, 0..=900_000)) {
+//!     println!("{}", embedding.unwrap().embedding.id());
+//! }
+//! # }
+//! ```
+
+use crate::extractor::Extractor;
+use crate::follower::ChainSource;
+use crate::resolver::{ConfirmedEmbedding, ResolveError};
+
+use bitcoin::Block;
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// The default number of blocks or embeddings buffered between stages
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 16;
+
+/// A block and its height
+pub type HeightBlock = (u64, Block);
+
+/// The embeddings extracted from the block at a position in the sequence
+type Extracted = (usize, Result<Vec<ConfirmedEmbedding>, ResolveError>);
+
+/// A block-scanning pipeline with parallel extraction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pipeline {
+    workers: usize,
+    capacity: usize,
+}
+
+impl Pipeline {
+    /// Returns a pipeline with one worker per available core and the default channel capacity
+    pub fn new() -> Self {
+        Self {
+            workers: thread::available_parallelism().map_or(1, |n| n.get()),
+            capacity: DEFAULT_CHANNEL_CAPACITY,
+        }
+    }
+
+    /// Sets the number of extraction workers (at least one)
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Sets the number of blocks or embeddings buffered between stages (at least one)
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Starts scanning `blocks` and returns the channel of their embeddings, in block order.
+    /// The first error from the source is delivered in its place in the sequence, after which
+    /// the channel closes.
+    pub fn run<I>(&self, blocks: I) -> Receiver<Result<ConfirmedEmbedding, ResolveError>>
+    where
+        I: IntoIterator<Item = Result<HeightBlock, ResolveError>>,
+        I::IntoIter: Send + 'static,
+    {
+        let blocks = blocks.into_iter();
+        let (work_tx, work_rx) = mpsc::sync_channel::<(usize, HeightBlock)>(self.capacity);
+        let (extracted_tx, extracted_rx) = mpsc::sync_channel::<Extracted>(self.capacity);
+        let (output_tx, output_rx) = mpsc::sync_channel(self.capacity);
+
+        // One credit per block in flight, returned as each block is delivered
+        let window = self.capacity + self.workers;
+        let (credit_tx, credit_rx) = mpsc::sync_channel(window);
+        for _ in 0..window {
+            credit_tx.send(()).expect("channel has room");
+        }
+
+        let source_tx = extracted_tx.clone();
+        thread::spawn(move || feed(blocks, credit_rx, work_tx, source_tx));
+
+        let work_rx = Arc::new(Mutex::new(work_rx));
+        for _ in 0..self.workers {
+            let work_rx = Arc::clone(&work_rx);
+            let extracted_tx = extracted_tx.clone();
+            thread::spawn(move || extract(&work_rx, &extracted_tx));
+        }
+        drop(extracted_tx);
+
+        thread::spawn(move || reorder(extracted_rx, credit_tx, output_tx));
+        output_rx
+    }
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the blocks at `heights` in the best chain of `source`, in order
+pub fn chain_blocks<S: ChainSource>(
+    source: S,
+    heights: RangeInclusive<u64>,
+) -> impl Iterator<Item = Result<HeightBlock, ResolveError>> {
+    heights.map(move |height| {
+        let hash = source.block_hash_at(height)?;
+        Ok((height, source.block(&hash)?))
+    })
+}
+
+/// Numbers the blocks and hands them to the workers, or an error straight to the reorder stage
+fn feed(
+    blocks: impl Iterator<Item = Result<HeightBlock, ResolveError>>,
+    credit_rx: Receiver<()>,
+    work_tx: SyncSender<(usize, HeightBlock)>,
+    extracted_tx: SyncSender<Extracted>,
+) {
+    for (sequence, block) in blocks.enumerate() {
+        if credit_rx.recv().is_err() {
+            return;
+        }
+        let sent = match block {
+            Ok(block) => work_tx.send((sequence, block)).is_ok(),
+            Err(e) => {
+                let _ = extracted_tx.send((sequence, Err(e)));
+                false
+            }
+        };
+        if !sent {
+            return;
+        }
+    }
+}
+
+/// Extracts the embeddings of blocks until the source is exhausted
+fn extract(work_rx: &Mutex<Receiver<(usize, HeightBlock)>>, extracted_tx: &SyncSender<Extracted>) {
+    let mut extractor = Extractor::new();
+    let mut extracted = Vec::new();
+
+    loop {
+        let next = match work_rx.lock() {
+            Ok(work_rx) => work_rx.recv(),
+            Err(_) => return,
+        };
+        let Ok((sequence, (height, block))) = next else {
+            return;
+        };
+
+        let block_hash = block.block_hash();
+        let mut embeddings = Vec::new();
+        for (tx_index, tx) in block.txdata.iter().enumerate() {
+            extractor.extract_into(tx, &mut extracted);
+            embeddings.extend(extracted.drain(..).map(|embedding| ConfirmedEmbedding {
+                embedding,
+                block_hash,
+                height,
+                time: block.header.time,
+                tx_index,
+            }));
+        }
+
+        if extracted_tx.send((sequence, Ok(embeddings))).is_err() {
+            return;
+        }
+    }
+}
+
+/// Forwards extracted embeddings in block order, stopping after the first error
+fn reorder(
+    extracted_rx: Receiver<Extracted>,
+    credit_tx: SyncSender<()>,
+    output_tx: SyncSender<Result<ConfirmedEmbedding, ResolveError>>,
+) {
+    let mut pending = BTreeMap::new();
+    let mut next = 0;
+
+    for (sequence, extracted) in extracted_rx {
+        pending.insert(sequence, extracted);
+
+        while let Some(extracted) = pending.remove(&next) {
+            next += 1;
+            let _ = credit_tx.send(());
+            match extracted {
+                Ok(embeddings) => {
+                    for embedding in embeddings {
+                        if output_tx.send(Ok(embedding)).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(e) => {
+                    let _ = output_tx.send(Err(e));
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::block::{Header, Version as BlockVersion};
+    use bitcoin::hashes::Hash;
+    use bitcoin::{
+        Amount, BlockHash, CompactTarget, OutPoint, ScriptBuf, Sequence, Transaction, TxIn,
+        TxMerkleNode, TxOut, Witness, absolute::LockTime, transaction::Version,
+    };
+
+    fn block(height: u64) -> Block {
+        let txdata = (0..3)
+            .map(|i| Transaction {
+                version: Version::TWO,
+                lock_time: LockTime::from_consensus(height as u32),
+                input: vec![TxIn {
+                    previous_output: OutPoint::null(),
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                }],
+                output: vec![TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey: ScriptBuf::new_op_return([i as u8]),
+                }],
+            })
+            .collect();
+
+        Block {
+            header: Header {
+                version: BlockVersion::TWO,
+                prev_blockhash: BlockHash::all_zeros(),
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: height as u32,
+                bits: CompactTarget::from_consensus(0x207fffff),
+                nonce: 0,
+            },
+            txdata,
+        }
+    }
+
+    #[test]
+    fn test_ordered_output() {
+        let blocks = (0..50u64).map(|height| Ok((height, block(height))));
+        let embeddings = Pipeline::new()
+            .workers(4)
+            .capacity(1)
+            .run(blocks)
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(embeddings.len(), 150);
+        let positions = embeddings
+            .iter()
+            .map(|e| (e.height, e.tx_index))
+            .collect::<Vec<_>>();
+        let mut sorted = positions.clone();
+        sorted.sort();
+        assert_eq!(positions, sorted);
+        assert_eq!(embeddings[149].height, 49);
+        assert_eq!(embeddings[149].embedding.bytes, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_source_error() {
+        let blocks = (0..10u64).map(|height| match height {
+            5 => Err(ResolveError::Backend("offline".into())),
+            _ => Ok((height, block(height))),
+        });
+        let results = Pipeline::new()
+            .workers(3)
+            .run(blocks)
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        assert_eq!(results.len(), 5 * 3 + 1);
+        assert!(results[..15].iter().all(Result::is_ok));
+        assert_eq!(results[15], Err(ResolveError::Backend("offline".into())));
+    }
+
+    #[test]
+    fn test_dropped_receiver() {
+        let blocks = (0..).map(|height| Ok((height, block(height))));
+        let mut output = Pipeline::new().workers(2).run(blocks).into_iter();
+        assert_eq!(output.next().unwrap().unwrap().height, 0);
+        // Dropping the receiver stops the otherwise endless source
+        drop(output);
+    }
+}
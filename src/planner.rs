@@ -0,0 +1,744 @@
+//! # Payload Planning
+//!
+//! Splits one payload across several locations of a single transaction: a header in an
+//! `OP_RETURN` output, the bulk in a tapscript envelope, and the rest in a taproot annex, each
+//! up to a configurable capacity. The resulting [`Layout`] lists the segments in reassembly
+//! order. With a control record, the annex instead carries a [`tags::LAYOUT`] message with the
+//! payload's length, content hash, and segment order, so decoders can reassemble and check
+//! the payload from the transaction alone ([`reassemble`]).
+//!
+//! Under a weight or fee [`Budget`], [`Planner::allocate`] gives bytes to the cheapest
+//! locations first and returns what must spill to a second transaction.
+
+use crate::message::{Message, tagged_hash, tags};
+use crate::varint;
+use crate::{CONTENT_HASH_TAG, Embedding, EmbeddingType, ScriptType};
+
+use bitcoin::blockdata::constants::MAX_SCRIPT_ELEMENT_SIZE;
+use bitcoin::{Amount, FeeRate, VarInt, Weight};
+use std::fmt;
+
+/// The default `OP_RETURN` capacity, the data that fits in a standard output before Bitcoin
+/// Core v30
+pub const DEFAULT_OP_RETURN_BYTES: usize = 80;
+
+/// Plans the layout of payloads across the locations of a transaction
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Planner {
+    op_return: usize,
+    envelope: usize,
+    annex: usize,
+    control_record: bool,
+}
+
+/// A part of a payload placed in one location
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    /// The location type: `OP_RETURN`, tapscript envelope, or taproot annex
+    pub embedding_type: EmbeddingType,
+    /// The payload bytes
+    pub bytes: Vec<u8>,
+}
+
+/// The segments of a payload, in reassembly order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    /// The segments, in the order their bytes are concatenated
+    pub segments: Vec<Segment>,
+    /// The control record to place in the annex, if planned with one
+    pub control: Option<ControlRecord>,
+}
+
+/// Describes a payload split across locations, carried in a [`tags::LAYOUT`] message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlRecord {
+    /// The payload length
+    pub len: u64,
+    /// The content hash of the payload ([`Embedding::content_hash`] of the whole payload)
+    pub content_hash: [u8; 32],
+    /// The location type of each segment, in reassembly order
+    pub order: Vec<EmbeddingType>,
+}
+
+/// A limit on the weight of the data in one transaction
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Budget {
+    /// A maximum weight
+    Weight(Weight),
+    /// A maximum fee for the data at a feerate
+    Fee {
+        /// The maximum fee
+        max_fee: Amount,
+        /// The feerate
+        fee_rate: FeeRate,
+    },
+}
+
+/// The part of a payload that fits within a budget, and the rest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Allocation {
+    /// The layout of the bytes that fit
+    pub layout: Layout,
+    /// The bytes left for another transaction
+    pub spill: Vec<u8>,
+}
+
+/// Error types for planning and reassembling payloads
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PlanError {
+    /// The payload is larger than the capacity of the enabled locations
+    TooLarge {
+        /// The payload length
+        len: usize,
+        /// The total capacity
+        capacity: usize,
+    },
+    /// No embedding of a segment's type is left to read it from
+    MissingSegment(EmbeddingType),
+    /// No annex carries a control record
+    MissingControlRecord,
+    /// The reassembled payload does not match the control record's length or content hash
+    ContentMismatch,
+}
+
+impl Default for Planner {
+    fn default() -> Self {
+        Self {
+            op_return: DEFAULT_OP_RETURN_BYTES,
+            envelope: usize::MAX,
+            annex: 0,
+            control_record: false,
+        }
+    }
+}
+
+impl Planner {
+    /// Returns a planner with an 80-byte `OP_RETURN` header, an unlimited envelope, and no
+    /// annex
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the payload bytes placed in the `OP_RETURN` output (0 for none)
+    pub fn op_return(mut self, max_bytes: usize) -> Self {
+        self.op_return = max_bytes;
+        self
+    }
+
+    /// Sets the payload bytes placed in the tapscript envelope (0 for none)
+    pub fn envelope(mut self, max_bytes: usize) -> Self {
+        self.envelope = max_bytes;
+        self
+    }
+
+    /// Sets the payload bytes placed in the taproot annex (0 for none). Ignored with a
+    /// control record, which takes the annex.
+    pub fn annex(mut self, max_bytes: usize) -> Self {
+        self.annex = max_bytes;
+        self
+    }
+
+    /// Places a control record describing the layout in the annex
+    pub fn control_record(mut self, control_record: bool) -> Self {
+        self.control_record = control_record;
+        self
+    }
+
+    /// Splits `payload` across the enabled locations, filling the `OP_RETURN` output, then
+    /// the envelope, then the annex. Empty locations are left out.
+    pub fn plan(&self, payload: &[u8]) -> Result<Layout, PlanError> {
+        let mut rest = payload.len();
+        let sizes = self.capacities().map(|(_, capacity)| {
+            let size = capacity.min(rest);
+            rest -= size;
+            size
+        });
+
+        if rest > 0 {
+            return Err(PlanError::TooLarge {
+                len: payload.len(),
+                capacity: payload.len() - rest,
+            });
+        }
+
+        Ok(self.layout(payload, sizes))
+    }
+
+    /// Allocates as much of `payload` as fits within `budget`, giving bytes to the locations
+    /// with the lowest weight per byte first, and returns the layout with the bytes that must
+    /// spill to another transaction. The weight of a control record is reserved first.
+    /// Allocated bytes keep the reassembly order of [`Planner::plan`].
+    pub fn allocate(&self, payload: &[u8], budget: Budget) -> Allocation {
+        let mut remaining = budget.max_weight();
+        if self.control_record {
+            let control = ControlRecord {
+                len: payload.len() as u64,
+                content_hash: [0; 32],
+                order: self.capacities().map(|(t, _)| t).to_vec(),
+            };
+            let size = Message::encode(vec![control.to_message()]).len();
+            let weight = data_weight(EmbeddingType::TaprootAnnex, size).expect("annex");
+            if weight > remaining {
+                return Allocation {
+                    layout: Layout {
+                        segments: Vec::new(),
+                        control: None,
+                    },
+                    spill: payload.to_vec(),
+                };
+            }
+            remaining -= weight;
+        }
+
+        let capacities = self.capacities();
+        let mut order = [0, 1, 2];
+        order.sort_by(|&a, &b| {
+            let (a, b) = (&capacities[a], &capacities[b]);
+            cost_per_byte(a.0, a.1, payload.len()).total_cmp(&cost_per_byte(
+                b.0,
+                b.1,
+                payload.len(),
+            ))
+        });
+
+        let mut sizes = [0; 3];
+        let mut rest = payload.len();
+        for i in order {
+            let (embedding_type, capacity) = capacities[i];
+            let size = max_fit(embedding_type, capacity.min(rest), remaining);
+            if size > 0 {
+                remaining -= data_weight(embedding_type, size).expect("planned location");
+                rest -= size;
+                sizes[i] = size;
+            }
+        }
+
+        let allocated = payload.len() - rest;
+        Allocation {
+            layout: self.layout(&payload[..allocated], sizes),
+            spill: payload[allocated..].to_vec(),
+        }
+    }
+
+    /// Returns the location types in reassembly order, with their capacities
+    fn capacities(&self) -> [(EmbeddingType, usize); 3] {
+        let annex = if self.control_record { 0 } else { self.annex };
+        [
+            (EmbeddingType::OpReturn, self.op_return),
+            (
+                EmbeddingType::WitnessEnvelope(ScriptType::Tapscript),
+                self.envelope,
+            ),
+            (EmbeddingType::TaprootAnnex, annex),
+        ]
+    }
+
+    /// Splits `payload` into segments of `sizes` bytes, in reassembly order
+    fn layout(&self, payload: &[u8], sizes: [usize; 3]) -> Layout {
+        let mut segments = Vec::new();
+        let mut rest = payload;
+        for ((embedding_type, _), size) in self.capacities().into_iter().zip(sizes) {
+            let (bytes, remaining) = rest.split_at(size);
+            if !bytes.is_empty() {
+                segments.push(Segment {
+                    embedding_type,
+                    bytes: bytes.to_vec(),
+                });
+            }
+            rest = remaining;
+        }
+
+        let control = (self.control_record && !segments.is_empty()).then(|| ControlRecord {
+            len: payload.len() as u64,
+            content_hash: tagged_hash(CONTENT_HASH_TAG, payload),
+            order: segments.iter().map(|s| s.embedding_type).collect(),
+        });
+
+        Layout { segments, control }
+    }
+}
+
+impl Budget {
+    /// Returns the maximum weight of the embedded data
+    pub fn max_weight(&self) -> Weight {
+        match *self {
+            Budget::Weight(weight) => weight,
+            Budget::Fee { max_fee, fee_rate } => match fee_rate.to_sat_per_kwu() {
+                0 => Weight::MAX,
+                rate => Weight::from_wu(max_fee.to_sat().saturating_mul(1000) / rate),
+            },
+        }
+    }
+}
+
+impl Allocation {
+    /// Returns whether the whole payload fits
+    pub fn is_complete(&self) -> bool {
+        self.spill.is_empty()
+    }
+}
+
+impl Segment {
+    /// Returns the weight of the segment in its location, as in [`data_weight`]
+    pub fn weight(&self) -> Weight {
+        data_weight(self.embedding_type, self.bytes.len()).expect("planned location")
+    }
+}
+
+impl Layout {
+    /// Returns the location type of each segment, in reassembly order
+    pub fn order(&self) -> Vec<EmbeddingType> {
+        self.segments.iter().map(|s| s.embedding_type).collect()
+    }
+
+    /// Returns the annex data holding the control record, if any
+    pub fn control_payload(&self) -> Option<Vec<u8>> {
+        let control = self.control.as_ref()?;
+        Some(Message::encode(vec![control.to_message()]))
+    }
+
+    /// Returns the total weight of the segments and control record in their locations
+    pub fn weight(&self) -> Weight {
+        let control = self.control_payload().map_or(Weight::ZERO, |payload| {
+            data_weight(EmbeddingType::TaprootAnnex, payload.len()).expect("annex")
+        });
+        self.segments.iter().map(Segment::weight).sum::<Weight>() + control
+    }
+
+    /// Reassembles the payload from a transaction's embeddings, checking it against the
+    /// control record if there is one
+    pub fn reassemble(&self, embeddings: &[Embedding]) -> Result<Vec<u8>, PlanError> {
+        match &self.control {
+            Some(control) => control.reassemble(embeddings),
+            None => concatenate(&self.order(), embeddings),
+        }
+    }
+}
+
+impl ControlRecord {
+    /// Returns the record as a [`tags::LAYOUT`] message: the LEB128 payload length, the
+    /// content hash, and one byte per segment with its type's code in an embedding id
+    pub fn to_message(&self) -> Message {
+        let mut body = Vec::new();
+        varint::encode_to_vec(self.len as u128, &mut body);
+        body.extend(self.content_hash);
+        body.extend(self.order.iter().map(|t| type_byte(*t)));
+        Message::new(tags::LAYOUT, body).expect("valid tag and size")
+    }
+
+    /// Decodes a control record message. Returns `None` for other tags and malformed bodies.
+    pub fn from_message(message: &Message) -> Option<Self> {
+        if message.tag != tags::LAYOUT {
+            return None;
+        }
+
+        let (len, consumed) = varint::decode(&message.body).ok()?;
+        let rest = &message.body[consumed..];
+        if rest.len() < 32 {
+            return None;
+        }
+        let (content_hash, order) = rest.split_at(32);
+
+        Some(Self {
+            len: u64::try_from(len).ok()?,
+            content_hash: content_hash.try_into().expect("32 bytes"),
+            order: order
+                .iter()
+                .map(|b| from_type_byte(*b))
+                .collect::<Option<_>>()?,
+        })
+    }
+
+    /// Returns the first control record in the annexes among `embeddings`
+    pub fn from_embeddings(embeddings: &[Embedding]) -> Option<Self> {
+        embeddings
+            .iter()
+            .filter(|e| e.to_type() == EmbeddingType::TaprootAnnex)
+            .flat_map(|e| Message::decode(&e.bytes).unwrap_or_default())
+            .find_map(|message| Self::from_message(&message))
+    }
+
+    /// Reassembles the payload from a transaction's embeddings and checks its length and
+    /// content hash
+    pub fn reassemble(&self, embeddings: &[Embedding]) -> Result<Vec<u8>, PlanError> {
+        let payload = concatenate(&self.order, embeddings)?;
+        if payload.len() as u64 != self.len
+            || tagged_hash(CONTENT_HASH_TAG, &payload) != self.content_hash
+        {
+            return Err(PlanError::ContentMismatch);
+        }
+        Ok(payload)
+    }
+}
+
+/// Reassembles a payload from a transaction's embeddings using the control record in its
+/// annex
+pub fn reassemble(embeddings: &[Embedding]) -> Result<Vec<u8>, PlanError> {
+    ControlRecord::from_embeddings(embeddings)
+        .ok_or(PlanError::MissingControlRecord)?
+        .reassemble(embeddings)
+}
+
+/// Returns the weight of carrying `len` payload bytes in a location: the whole `OP_RETURN`
+/// output, or the witness element holding the annex or an envelope script built by a default
+/// [`EnvelopeBuilder`](crate::envelope::EnvelopeBuilder). Envelopes don't include the commit
+/// output or the rest of the reveal input. Returns `None` for bare multisig.
+pub fn data_weight(embedding_type: EmbeddingType, len: usize) -> Option<Weight> {
+    let element = |size: usize| Weight::from_wu((VarInt::from(size).size() + size) as u64);
+    match embedding_type {
+        EmbeddingType::OpReturn => {
+            let script_len = 1 + push_len(len);
+            Some(element(script_len) * 4 + Weight::from_vb_unchecked(8))
+        }
+        EmbeddingType::TaprootAnnex => Some(element(2 + len)),
+        EmbeddingType::WitnessEnvelope(_) => {
+            let full = len / MAX_SCRIPT_ELEMENT_SIZE;
+            let last = len % MAX_SCRIPT_ELEMENT_SIZE;
+            let mut script_len = 3 + full * push_len(MAX_SCRIPT_ELEMENT_SIZE);
+            if last > 0 {
+                script_len += push_len(last);
+            }
+            Some(element(script_len))
+        }
+        EmbeddingType::BareMultisig => None,
+    }
+}
+
+/// Returns the weight per byte of filling a location with up to `capacity` of `len` bytes, or
+/// infinity if it holds none
+fn cost_per_byte(embedding_type: EmbeddingType, capacity: usize, len: usize) -> f64 {
+    match capacity.min(len) {
+        0 => f64::INFINITY,
+        size => {
+            data_weight(embedding_type, size)
+                .expect("planned location")
+                .to_wu() as f64
+                / size as f64
+        }
+    }
+}
+
+/// Returns the most bytes, up to `limit`, whose weight in a location is within `budget`
+fn max_fit(embedding_type: EmbeddingType, limit: usize, budget: Weight) -> usize {
+    let fits = |size| data_weight(embedding_type, size).expect("planned location") <= budget;
+    if limit == 0 || !fits(1) {
+        return 0;
+    }
+
+    // Weight grows with size, so search for the last size that fits
+    let (mut low, mut high) = (1, limit);
+    while low < high {
+        let mid = low + (high - low).div_ceil(2);
+        if fits(mid) {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    low
+}
+
+/// Returns the size of a minimal push of `len` bytes, with its opcode and length prefix
+fn push_len(len: usize) -> usize {
+    let header = match len {
+        0..=75 => 1,
+        76..=0xff => 2,
+        0x100..=0xffff => 3,
+        _ => 5,
+    };
+    header + len
+}
+
+/// Concatenates the payloads of the embeddings of each type in `order`, taking the embeddings
+/// of each type in their order in `embeddings`
+fn concatenate(order: &[EmbeddingType], embeddings: &[Embedding]) -> Result<Vec<u8>, PlanError> {
+    let mut used = vec![false; embeddings.len()];
+    let mut payload = Vec::new();
+    for embedding_type in order {
+        let i = (0..embeddings.len())
+            .find(|&i| !used[i] && embeddings[i].to_type() == *embedding_type)
+            .ok_or(PlanError::MissingSegment(*embedding_type))?;
+        used[i] = true;
+        payload.extend_from_slice(&embeddings[i].payload());
+    }
+    Ok(payload)
+}
+
+fn type_byte(embedding_type: EmbeddingType) -> u8 {
+    match embedding_type {
+        EmbeddingType::OpReturn => 0,
+        EmbeddingType::TaprootAnnex => 1,
+        EmbeddingType::WitnessEnvelope(ScriptType::Legacy) => 2,
+        EmbeddingType::WitnessEnvelope(ScriptType::Tapscript) => 3,
+        EmbeddingType::BareMultisig => 4,
+    }
+}
+
+fn from_type_byte(byte: u8) -> Option<EmbeddingType> {
+    match byte {
+        0 => Some(EmbeddingType::OpReturn),
+        1 => Some(EmbeddingType::TaprootAnnex),
+        2 => Some(EmbeddingType::WitnessEnvelope(ScriptType::Legacy)),
+        3 => Some(EmbeddingType::WitnessEnvelope(ScriptType::Tapscript)),
+        4 => Some(EmbeddingType::BareMultisig),
+        _ => None,
+    }
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanError::TooLarge { len, capacity } => {
+                write!(
+                    f,
+                    "Payload of {len} bytes exceeds the capacity of {capacity} bytes"
+                )
+            }
+            PlanError::MissingSegment(embedding_type) => {
+                write!(f, "Missing {} segment", embedding_type.code())
+            }
+            PlanError::MissingControlRecord => write!(f, "Missing layout control record"),
+            PlanError::ContentMismatch => {
+                write!(f, "Reassembled payload does not match the control record")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PlanError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annex;
+    use crate::envelope::EnvelopeBuilder;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::script::{Builder, PushBytesBuf};
+    use bitcoin::{
+        Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+        absolute::LockTime, transaction::Version,
+    };
+
+    fn payload() -> Vec<u8> {
+        (0..2000).map(|i| i as u8).collect()
+    }
+
+    /// Builds a transaction carrying a layout's segments and control record
+    fn transaction(layout: &Layout) -> Transaction {
+        let mut output = Vec::new();
+        let mut witness = Witness::new();
+        let mut annex_data = layout.control_payload();
+        for segment in &layout.segments {
+            match segment.embedding_type {
+                EmbeddingType::OpReturn => output.push(TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey: ScriptBuf::new_op_return(
+                        PushBytesBuf::try_from(segment.bytes.clone()).unwrap(),
+                    ),
+                }),
+                EmbeddingType::TaprootAnnex => annex_data = Some(segment.bytes.clone()),
+                _ => {
+                    let script = EnvelopeBuilder::new()
+                        .append_to_builder(vec![segment.bytes.clone()], Builder::new())
+                        .into_script();
+                    witness.push(script);
+                    witness.push([vec![0xc0], vec![1; 32]].concat());
+                }
+            }
+        }
+        if let Some(data) = annex_data {
+            annex::append_data(&mut witness, &data).unwrap();
+        }
+
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness,
+            }],
+            output,
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let payload = payload();
+        let layout = Planner::new().plan(&payload).unwrap();
+        assert_eq!(
+            layout.order(),
+            vec![
+                EmbeddingType::OpReturn,
+                EmbeddingType::WitnessEnvelope(ScriptType::Tapscript)
+            ]
+        );
+        assert_eq!(layout.segments[0].bytes, payload[..80]);
+        assert_eq!(layout.control, None);
+
+        let embeddings = Embedding::from_transaction(&transaction(&layout));
+        assert_eq!(layout.reassemble(&embeddings), Ok(payload.clone()));
+
+        // Small payloads fit in the header alone
+        let layout = Planner::new().plan(&payload[..10]).unwrap();
+        assert_eq!(layout.order(), vec![EmbeddingType::OpReturn]);
+
+        let planner = Planner::new().op_return(0).envelope(1000).annex(500);
+        assert_eq!(
+            planner.plan(&payload),
+            Err(PlanError::TooLarge {
+                len: 2000,
+                capacity: 1500
+            })
+        );
+        let layout = planner.annex(1000).plan(&payload).unwrap();
+        assert_eq!(
+            layout.order(),
+            vec![
+                EmbeddingType::WitnessEnvelope(ScriptType::Tapscript),
+                EmbeddingType::TaprootAnnex
+            ]
+        );
+        let embeddings = Embedding::from_transaction(&transaction(&layout));
+        assert_eq!(layout.reassemble(&embeddings), Ok(payload));
+    }
+
+    #[test]
+    fn test_control_record() {
+        let payload = payload();
+        let layout = Planner::new()
+            .annex(100)
+            .control_record(true)
+            .plan(&payload)
+            .unwrap();
+        let control = layout.control.clone().unwrap();
+        assert_eq!(control.len, 2000);
+        assert_eq!(control.order, layout.order());
+        assert_eq!(
+            ControlRecord::from_message(&control.to_message()),
+            Some(control.clone())
+        );
+
+        // Decoders need only the transaction
+        let tx = transaction(&layout);
+        let embeddings = Embedding::from_transaction(&tx);
+        assert_eq!(reassemble(&embeddings), Ok(payload.clone()));
+        assert_eq!(layout.reassemble(&embeddings), Ok(payload.clone()));
+
+        assert_eq!(
+            reassemble(&embeddings[..2]),
+            Err(PlanError::MissingControlRecord)
+        );
+        assert_eq!(
+            control.reassemble(&embeddings[1..]),
+            Err(PlanError::MissingSegment(EmbeddingType::OpReturn))
+        );
+
+        let mut other = control;
+        other.content_hash = [0; 32];
+        assert_eq!(
+            other.reassemble(&embeddings),
+            Err(PlanError::ContentMismatch)
+        );
+    }
+
+    #[test]
+    fn test_allocate() {
+        let payload = payload();
+        let planner = Planner::new().envelope(1000).annex(500);
+        let weight = |t, len| data_weight(t, len).unwrap();
+        let envelope = EmbeddingType::WitnessEnvelope(ScriptType::Tapscript);
+
+        // Witness locations fill first, and the OP_RETURN output gets what the budget allows
+        let witness = weight(envelope, 1000) + weight(EmbeddingType::TaprootAnnex, 500);
+        let budget = witness + weight(EmbeddingType::OpReturn, 20);
+        let allocation = planner.allocate(&payload, Budget::Weight(budget));
+        assert_eq!(
+            allocation
+                .layout
+                .segments
+                .iter()
+                .map(|s| s.bytes.len())
+                .collect::<Vec<_>>(),
+            vec![20, 1000, 500]
+        );
+        assert!(allocation.layout.weight() <= budget);
+        assert_eq!(allocation.spill, payload[1520..]);
+        assert!(!allocation.is_complete());
+        let mut bytes = allocation
+            .layout
+            .segments
+            .iter()
+            .flat_map(|s| s.bytes.clone())
+            .collect::<Vec<_>>();
+        bytes.extend(&allocation.spill);
+        assert_eq!(bytes, payload);
+
+        // The annex, without push opcodes, is the cheapest per byte
+        let budget = weight(EmbeddingType::TaprootAnnex, 300);
+        let allocation = planner.allocate(&payload, Budget::Weight(budget));
+        assert_eq!(allocation.layout.order(), vec![EmbeddingType::TaprootAnnex]);
+        assert_eq!(allocation.layout.segments[0].bytes, payload[..300]);
+        assert_eq!(allocation.layout.weight(), budget);
+
+        // A fee budget at 2 sat/vB allows half the weight of the same fee at 1 sat/vB
+        let fee = |sat_vb| Budget::Fee {
+            max_fee: Amount::from_sat(1000),
+            fee_rate: FeeRate::from_sat_per_vb(sat_vb).unwrap(),
+        };
+        assert_eq!(fee(1).max_weight(), Weight::from_wu(4000));
+        assert_eq!(fee(2).max_weight(), Weight::from_wu(2000));
+
+        let allocation = Planner::new().allocate(&payload, fee(1));
+        assert!(allocation.is_complete());
+        assert_eq!(allocation.layout.order(), vec![envelope]);
+
+        // The control record's weight is reserved, and describes the allocated bytes
+        let planner = planner.control_record(true);
+        let allocation = planner.allocate(&payload, Budget::Weight(Weight::from_wu(1000)));
+        let tx = transaction(&allocation.layout);
+        assert!(allocation.layout.weight() <= Weight::from_wu(1000));
+        assert_eq!(
+            reassemble(&Embedding::from_transaction(&tx)).unwrap(),
+            payload[..payload.len() - allocation.spill.len()]
+        );
+
+        let allocation = planner.allocate(&payload, Budget::Weight(Weight::from_wu(10)));
+        assert_eq!(allocation.layout.segments, vec![]);
+        assert_eq!(allocation.spill, payload);
+    }
+
+    #[test]
+    fn test_data_weight() {
+        for len in [0, 1, 75, 76, 80, 255, 256, 520, 521, 1040, 5000] {
+            let data = vec![7; len];
+
+            let output = TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::new_op_return(
+                    PushBytesBuf::try_from(data.clone()).unwrap(),
+                ),
+            };
+            assert_eq!(
+                data_weight(EmbeddingType::OpReturn, len),
+                Some(output.weight())
+            );
+
+            let script = EnvelopeBuilder::new()
+                .append_to_builder(vec![data.clone()], Builder::new())
+                .into_script();
+            let element = VarInt::from(script.len()).size() + script.len();
+            assert_eq!(
+                data_weight(EmbeddingType::WitnessEnvelope(ScriptType::Tapscript), len),
+                Some(Weight::from_wu(element as u64))
+            );
+        }
+        assert_eq!(
+            data_weight(EmbeddingType::TaprootAnnex, 10),
+            Some(Weight::from_wu(13))
+        );
+        assert_eq!(data_weight(EmbeddingType::BareMultisig, 10), None);
+    }
+}
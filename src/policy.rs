@@ -0,0 +1,756 @@
+//! # Embedding Policy
+//!
+//! Checks whether an embedding can still be attached to a partially signed transaction. A
+//! new output (`OP_RETURN` or bare multisig) is appended after the existing outputs, a
+//! witness envelope arrives in a new input, and an annex goes on an existing taproot input.
+//! Whether each existing signature survives depends on what its sighash flags commit to:
+//! `ALL` commits to every output, `SINGLE` to the output at its own index, and anything
+//! without `ANYONECANPAY` to every input. Only a taproot signature commits to its own
+//! input's annex.
+//!
+//! Separately, [`OpReturnPolicy`] checks `OP_RETURN` outputs against a node's datacarrier
+//! relay policy, which differs between node versions and configurations.
+//!
+//! Likewise, [`AnnexPolicy`] checks taproot annexes, which Bitcoin Core does not relay today
+//! but which other nodes and mining pools may accept under rules that are still in flux.
+
+use crate::{EmbeddingType, anchor, annex};
+
+use bitcoin::sighash::{EcdsaSighashType, TapSighashType};
+use bitcoin::{Script, Transaction, TxIn, TxOut};
+use std::fmt;
+
+/// The default datacarrier limit before Bitcoin Core v30, in script bytes: `OP_RETURN`, a
+/// push opcode, and 80 bytes of data
+pub const DEFAULT_DATACARRIER_SIZE: usize = 83;
+
+/// The annex size limit of [`AnnexPolicy::LIMITED`], in bytes including the `0x50` prefix
+pub const DEFAULT_MAX_ANNEX_SIZE: usize = 256;
+
+/// The signature on an input, or its absence
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum InputSignature {
+    /// A non-taproot input that is not yet signed
+    Unsigned,
+    /// A taproot input that is not yet signed
+    UnsignedTaproot,
+    /// An ECDSA signature on a legacy input
+    Legacy(EcdsaSighashType),
+    /// An ECDSA signature on a segwit v0 input
+    SegwitV0(EcdsaSighashType),
+    /// A Schnorr signature on a taproot input
+    Taproot(TapSighashType),
+}
+
+/// The effect of an embedding on an input's signature
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum InputImpact {
+    /// The signature remains valid
+    Unaffected,
+    /// The signature would no longer be valid
+    Invalidated,
+    /// The input cannot carry the embedding (annexes are only valid on taproot inputs)
+    Unsupported,
+}
+
+/// The effect of adding an embedding on each input of a transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddReport {
+    /// The type of embedding
+    pub embedding_type: EmbeddingType,
+    /// The effect on each input. For an annex, this is the effect of placing the annex on that
+    /// input; an annex never affects other inputs.
+    pub inputs: Vec<InputImpact>,
+    /// The indices of anchor outputs, through which a child can bump the fee after the
+    /// embedding is added. A new output is appended, so these indices do not change.
+    pub anchors: Vec<usize>,
+}
+
+/// The relay policy for `OP_RETURN` outputs
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct OpReturnPolicy {
+    /// The maximum total size of all `OP_RETURN` scripts in a transaction, in bytes, or `None`
+    /// for no limit (Bitcoin Core's `-datacarriersize`)
+    pub max_size: Option<usize>,
+    /// Whether a transaction may have more than one `OP_RETURN` output
+    pub multiple: bool,
+}
+
+/// Error types for `OP_RETURN` policy checks
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OpReturnError {
+    /// The `OP_RETURN` scripts are larger than the limit
+    TooLarge {
+        /// The total size of the `OP_RETURN` scripts
+        size: usize,
+        /// The limit
+        limit: usize,
+    },
+    /// The transaction has more than one `OP_RETURN` output
+    MultipleOutputs,
+}
+
+/// A reason a node's mempool policy rejects a transaction, as reported by Bitcoin Core's
+/// `testmempoolaccept` and `sendrawtransaction`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PolicyViolation {
+    /// The `OP_RETURN` outputs exceed the node's `-datacarriersize` (`datacarrier`). Nodes before
+    /// Bitcoin Core 30 report [`PolicyViolation::NonStandardScriptPubKey`] instead.
+    DatacarrierTooLarge,
+    /// The transaction has more than one `OP_RETURN` output (`multi-op-return`)
+    MultipleOpReturn,
+    /// An output script is non-standard, including an `OP_RETURN` output larger than
+    /// `-datacarriersize` before Bitcoin Core 30 (`scriptpubkey`)
+    NonStandardScriptPubKey,
+    /// An input's scriptSig contains more than data pushes (`scriptsig-not-pushonly`)
+    ScriptSigNotPushOnly,
+    /// An input's scriptSig is larger than 1,650 bytes (`scriptsig-size`)
+    ScriptSigTooLarge,
+    /// The transaction's weight exceeds the standard limit of 400,000 (`tx-size`)
+    TxTooLarge,
+    /// An output is below the dust threshold (`dust`)
+    Dust,
+    /// An output is bare multisig and the node does not relay them (`bare-multisig`)
+    BareMultisig,
+    /// A witness is non-standard, such as an annex or an oversized witness script
+    /// (`bad-witness-nonstandard`)
+    NonStandardWitness,
+    /// An input spends an unknown or spent output (`missing-inputs`)
+    MissingInputs,
+    /// The fee is below the node's relay or mempool minimum (`min relay fee not met`,
+    /// `mempool min fee not met`)
+    InsufficientFee,
+    /// Any other reason, as reported by the node
+    Other(String),
+}
+
+impl PolicyViolation {
+    /// Maps a Bitcoin Core reject reason to a violation
+    pub fn from_reject_reason(reason: &str) -> Self {
+        match reason {
+            "datacarrier" => Self::DatacarrierTooLarge,
+            "multi-op-return" => Self::MultipleOpReturn,
+            "scriptpubkey" => Self::NonStandardScriptPubKey,
+            "scriptsig-not-pushonly" => Self::ScriptSigNotPushOnly,
+            "scriptsig-size" => Self::ScriptSigTooLarge,
+            "tx-size" => Self::TxTooLarge,
+            "dust" => Self::Dust,
+            "bare-multisig" => Self::BareMultisig,
+            "bad-witness-nonstandard" => Self::NonStandardWitness,
+            "missing-inputs" | "bad-txns-inputs-missingorspent" => Self::MissingInputs,
+            "min relay fee not met" | "mempool min fee not met" => Self::InsufficientFee,
+            reason => Self::Other(reason.to_string()),
+        }
+    }
+}
+
+/// The relay policy for taproot annexes
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct AnnexPolicy {
+    /// Whether annexes are relayed at all
+    pub relay: bool,
+    /// The maximum size of an annex, including its `0x50` prefix, or `None` for no limit
+    pub max_size: Option<usize>,
+    /// Whether an annex must hold a data or batch record ([`crate::TAPROOT_ANNEX_DATA_TAG`],
+    /// [`crate::TAPROOT_ANNEX_BATCH_TAG`])
+    pub require_data_tag: bool,
+}
+
+/// A reason an annex does not meet an [`AnnexPolicy`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum AnnexIssue {
+    /// The policy does not relay annexes
+    NotRelayed,
+    /// The annex is larger than the limit
+    TooLarge {
+        /// The size of the annex
+        size: usize,
+        /// The limit
+        limit: usize,
+    },
+    /// The input does not spend a taproot output, so the element is not an annex to
+    /// consensus, and data placed there as one is lost to annex-aware parsers
+    NonTaprootInput,
+    /// The annex has no data or batch record
+    MissingDataTag,
+}
+
+/// The policy check of one input's annex
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnnexCheck {
+    /// The input index
+    pub input: usize,
+    /// The size of the annex, including its `0x50` prefix
+    pub size: usize,
+    /// The ways the annex does not meet the policy, empty if it does
+    pub issues: Vec<AnnexIssue>,
+}
+
+/// What adding an embedding changes in a transaction
+enum Addition {
+    Output(usize),
+    Input,
+    Annex,
+}
+
+impl InputSignature {
+    /// Returns whether the input is a taproot input
+    pub fn is_taproot(&self) -> bool {
+        matches!(
+            self,
+            InputSignature::UnsignedTaproot | InputSignature::Taproot(_)
+        )
+    }
+
+    /// Returns whether the signature commits to all outputs, to the output at its own index
+    /// only, and to all inputs
+    pub(crate) fn commitments(&self) -> (bool, bool, bool) {
+        match *self {
+            InputSignature::Unsigned | InputSignature::UnsignedTaproot => (false, false, false),
+            InputSignature::Legacy(sighash) | InputSignature::SegwitV0(sighash) => {
+                let base = sighash.to_u32() & 0x1f;
+                (
+                    base == EcdsaSighashType::All.to_u32(),
+                    base == EcdsaSighashType::Single.to_u32(),
+                    sighash.to_u32() & 0x80 == 0,
+                )
+            }
+            InputSignature::Taproot(sighash) => {
+                let base = sighash as u8 & 0x03;
+                (
+                    base == TapSighashType::Default as u8 || base == TapSighashType::All as u8,
+                    base == TapSighashType::Single as u8,
+                    sighash as u8 & 0x80 == 0,
+                )
+            }
+        }
+    }
+
+    /// Returns the effect of `addition` on this signature, on the input at `index`
+    fn impact(&self, index: usize, addition: &Addition) -> InputImpact {
+        let (all, single, inputs) = self.commitments();
+        let invalidated = match *addition {
+            Addition::Output(output) => all || (single && index == output),
+            Addition::Input => inputs,
+            Addition::Annex if !self.is_taproot() => return InputImpact::Unsupported,
+            Addition::Annex => matches!(self, InputSignature::Taproot(_)),
+        };
+
+        if invalidated {
+            InputImpact::Invalidated
+        } else {
+            InputImpact::Unaffected
+        }
+    }
+}
+
+impl OpReturnPolicy {
+    /// One `OP_RETURN` output of at most 80 bytes of data, the default before Bitcoin Core v30
+    pub const STANDARD: Self = Self {
+        max_size: Some(DEFAULT_DATACARRIER_SIZE),
+        multiple: false,
+    };
+
+    /// Any number of `OP_RETURN` outputs totaling at most 100,000 bytes, the default from
+    /// Bitcoin Core v30
+    pub const CORE_V30: Self = Self {
+        max_size: Some(100_000),
+        multiple: true,
+    };
+
+    /// Any number of `OP_RETURN` outputs of any size, for nodes or miners with relaxed policy
+    pub const UNLIMITED: Self = Self {
+        max_size: None,
+        multiple: true,
+    };
+
+    /// Checks the `OP_RETURN` outputs among `outputs`
+    pub fn check_outputs(&self, outputs: &[TxOut]) -> Result<(), OpReturnError> {
+        self.check_scripts(
+            outputs
+                .iter()
+                .map(|output| output.script_pubkey.as_script()),
+        )
+    }
+
+    /// Checks the `OP_RETURN` outputs of `tx`
+    pub fn check(&self, tx: &Transaction) -> Result<(), OpReturnError> {
+        self.check_outputs(&tx.output)
+    }
+
+    /// Checks whether an output with `script` can be added to `tx`
+    pub fn can_add(&self, tx: &Transaction, script: &Script) -> Result<(), OpReturnError> {
+        self.check_scripts(
+            tx.output
+                .iter()
+                .map(|output| output.script_pubkey.as_script())
+                .chain([script]),
+        )
+    }
+
+    fn check_scripts<'a>(
+        &self,
+        scripts: impl IntoIterator<Item = &'a Script>,
+    ) -> Result<(), OpReturnError> {
+        let (count, size) = scripts
+            .into_iter()
+            .filter(|script| script.is_op_return())
+            .fold((0, 0), |(count, size), script| {
+                (count + 1, size + script.len())
+            });
+
+        if count > 1 && !self.multiple {
+            return Err(OpReturnError::MultipleOutputs);
+        }
+        match self.max_size {
+            Some(limit) if size > limit => Err(OpReturnError::TooLarge { size, limit }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Default for OpReturnPolicy {
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
+impl AnnexPolicy {
+    /// No annexes, the policy of Bitcoin Core
+    pub const STANDARD: Self = Self {
+        relay: false,
+        max_size: None,
+        require_data_tag: true,
+    };
+
+    /// Data-tagged annexes of at most [`DEFAULT_MAX_ANNEX_SIZE`] bytes, for nodes or mining
+    /// pools that accept bounded annexes
+    pub const LIMITED: Self = Self {
+        relay: true,
+        max_size: Some(DEFAULT_MAX_ANNEX_SIZE),
+        require_data_tag: true,
+    };
+
+    /// Annexes of any size or format, for nodes or miners with relaxed policy
+    pub const UNLIMITED: Self = Self {
+        relay: true,
+        max_size: None,
+        require_data_tag: false,
+    };
+
+    /// Checks the annex of every input of `tx` that has one. An input is taproot if the
+    /// output it spends, from `prevouts`, is P2TR; inputs without a corresponding prevout are
+    /// taproot unless they have a `scriptSig`.
+    pub fn validate(&self, tx: &Transaction, prevouts: &[TxOut]) -> Vec<AnnexCheck> {
+        tx.input
+            .iter()
+            .enumerate()
+            .filter_map(|(input, txin)| {
+                let annex = txin.witness.taproot_annex()?;
+                let issues = self.issues(annex, txin, prevouts.get(input));
+                Some(AnnexCheck {
+                    input,
+                    size: annex.len(),
+                    issues,
+                })
+            })
+            .collect()
+    }
+
+    fn issues(&self, annex: &[u8], txin: &TxIn, prevout: Option<&TxOut>) -> Vec<AnnexIssue> {
+        let mut issues = Vec::new();
+
+        if !self.relay {
+            issues.push(AnnexIssue::NotRelayed);
+        }
+        if let Some(limit) = self.max_size.filter(|limit| annex.len() > *limit) {
+            issues.push(AnnexIssue::TooLarge {
+                size: annex.len(),
+                limit,
+            });
+        }
+        let taproot = match prevout {
+            Some(prevout) => prevout.script_pubkey.is_p2tr(),
+            None => txin.script_sig.is_empty(),
+        };
+        if !taproot {
+            issues.push(AnnexIssue::NonTaprootInput);
+        }
+        if self.require_data_tag && annex::payloads(annex).is_empty() {
+            issues.push(AnnexIssue::MissingDataTag);
+        }
+
+        issues
+    }
+}
+
+impl Default for AnnexPolicy {
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
+impl AnnexCheck {
+    /// Returns whether the annex meets the policy
+    pub fn is_acceptable(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl AddReport {
+    /// Returns whether the embedding can be added without invalidating any signature. For an
+    /// annex, this means some input can carry it.
+    pub fn can_add(&self) -> bool {
+        match self.embedding_type {
+            EmbeddingType::TaprootAnnex => self.inputs.contains(&InputImpact::Unaffected),
+            _ => !self.inputs.contains(&InputImpact::Invalidated),
+        }
+    }
+
+    /// Returns the indices of inputs whose signatures would be invalidated
+    pub fn invalidated(&self) -> Vec<usize> {
+        self.inputs_with(InputImpact::Invalidated)
+    }
+
+    /// Returns the indices of inputs that can carry an annex without invalidating a signature
+    pub fn annex_candidates(&self) -> Vec<usize> {
+        match self.embedding_type {
+            EmbeddingType::TaprootAnnex => self.inputs_with(InputImpact::Unaffected),
+            _ => vec![],
+        }
+    }
+
+    fn inputs_with(&self, impact: InputImpact) -> Vec<usize> {
+        self.inputs
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| **i == impact)
+            .map(|(index, _)| index)
+            .collect()
+    }
+}
+
+/// Checks every annex in `tx` against Bitcoin Core's policy ([`AnnexPolicy::STANDARD`]),
+/// classifying inputs from the witness alone. Use [`AnnexPolicy::validate`] for other policies
+/// or to classify inputs by the outputs they spend.
+pub fn validate_annex(tx: &Transaction) -> Vec<AnnexCheck> {
+    AnnexPolicy::STANDARD.validate(tx, &[])
+}
+
+/// Reports whether adding an embedding of `embedding_type` to `tx` would invalidate existing
+/// signatures. `input_signatures` describes each input in order; inputs beyond it are
+/// treated as unsigned non-taproot inputs.
+pub fn can_add_embedding(
+    tx: &Transaction,
+    embedding_type: EmbeddingType,
+    input_signatures: &[InputSignature],
+) -> AddReport {
+    let addition = match embedding_type {
+        EmbeddingType::OpReturn | EmbeddingType::BareMultisig => Addition::Output(tx.output.len()),
+        EmbeddingType::WitnessEnvelope(_) => Addition::Input,
+        EmbeddingType::TaprootAnnex => Addition::Annex,
+    };
+
+    let inputs = (0..tx.input.len())
+        .map(|index| {
+            input_signatures
+                .get(index)
+                .unwrap_or(&InputSignature::Unsigned)
+                .impact(index, &addition)
+        })
+        .collect();
+
+    AddReport {
+        embedding_type,
+        inputs,
+        anchors: anchor::anchors(tx)
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect(),
+    }
+}
+
+impl fmt::Display for OpReturnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpReturnError::TooLarge { size, limit } => {
+                write!(
+                    f,
+                    "OP_RETURN scripts of {size} bytes exceed limit of {limit}"
+                )
+            }
+            OpReturnError::MultipleOutputs => write!(f, "Multiple OP_RETURN outputs"),
+        }
+    }
+}
+
+impl std::error::Error for OpReturnError {}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyViolation::DatacarrierTooLarge => write!(f, "OP_RETURN data exceeds the limit"),
+            PolicyViolation::MultipleOpReturn => write!(f, "Multiple OP_RETURN outputs"),
+            PolicyViolation::NonStandardScriptPubKey => write!(f, "Non-standard output script"),
+            PolicyViolation::ScriptSigNotPushOnly => write!(f, "scriptSig is not push-only"),
+            PolicyViolation::ScriptSigTooLarge => write!(f, "scriptSig is too large"),
+            PolicyViolation::TxTooLarge => write!(f, "Transaction is too large"),
+            PolicyViolation::Dust => write!(f, "Output is dust"),
+            PolicyViolation::BareMultisig => write!(f, "Bare multisig output"),
+            PolicyViolation::NonStandardWitness => write!(f, "Non-standard witness"),
+            PolicyViolation::MissingInputs => write!(f, "Inputs are missing or spent"),
+            PolicyViolation::InsufficientFee => write!(f, "Fee is below the minimum"),
+            PolicyViolation::Other(reason) => write!(f, "Rejected: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
+impl fmt::Display for AnnexIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnnexIssue::NotRelayed => write!(f, "Annexes are not relayed"),
+            AnnexIssue::TooLarge { size, limit } => {
+                write!(f, "Annex of {size} bytes exceeds limit of {limit}")
+            }
+            AnnexIssue::NonTaprootInput => write!(f, "Annex on a non-taproot input"),
+            AnnexIssue::MissingDataTag => write!(f, "Annex has no data or batch record"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ScriptType;
+
+    use bitcoin::script::PushBytesBuf;
+    use bitcoin::{
+        Amount, OutPoint, ScriptBuf, Sequence, TxIn, Witness, absolute::LockTime,
+        transaction::Version,
+    };
+
+    fn transaction(inputs: usize, outputs: usize) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![
+                TxIn {
+                    previous_output: OutPoint::null(),
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                };
+                inputs
+            ],
+            output: vec![
+                TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey: ScriptBuf::new(),
+                };
+                outputs
+            ],
+        }
+    }
+
+    #[test]
+    fn test_add_output() {
+        let tx = transaction(4, 2);
+        let signatures = [
+            InputSignature::SegwitV0(EcdsaSighashType::SinglePlusAnyoneCanPay),
+            InputSignature::Taproot(TapSighashType::NonePlusAnyoneCanPay),
+            InputSignature::Legacy(EcdsaSighashType::Single),
+            InputSignature::Taproot(TapSighashType::Default),
+        ];
+
+        let report = can_add_embedding(&tx, EmbeddingType::OpReturn, &signatures);
+        assert_eq!(
+            report.inputs,
+            vec![
+                InputImpact::Unaffected,
+                InputImpact::Unaffected,
+                InputImpact::Invalidated,
+                InputImpact::Invalidated,
+            ]
+        );
+        assert_eq!(report.invalidated(), vec![2, 3]);
+        assert!(!report.can_add());
+
+        let report = can_add_embedding(&tx, EmbeddingType::BareMultisig, &signatures[..2]);
+        assert!(report.can_add());
+        assert!(report.anchors.is_empty());
+
+        let mut tx = tx;
+        tx.output.insert(0, anchor::p2a_output(Amount::ZERO));
+        let report = can_add_embedding(&tx, EmbeddingType::OpReturn, &signatures);
+        assert_eq!(report.anchors, vec![0]);
+        assert_eq!(report.annex_candidates(), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_add_input() {
+        let tx = transaction(3, 1);
+        let signatures = [
+            InputSignature::Taproot(TapSighashType::AllPlusAnyoneCanPay),
+            InputSignature::SegwitV0(EcdsaSighashType::None),
+            InputSignature::UnsignedTaproot,
+        ];
+
+        let report = can_add_embedding(
+            &tx,
+            EmbeddingType::WitnessEnvelope(ScriptType::Tapscript),
+            &signatures,
+        );
+        assert_eq!(report.invalidated(), vec![1]);
+        assert!(!report.can_add());
+
+        let report = can_add_embedding(
+            &tx,
+            EmbeddingType::WitnessEnvelope(ScriptType::Legacy),
+            &[signatures[0]],
+        );
+        assert!(report.can_add());
+    }
+
+    #[test]
+    fn test_add_annex() {
+        let tx = transaction(4, 1);
+        let signatures = [
+            InputSignature::Taproot(TapSighashType::NonePlusAnyoneCanPay),
+            InputSignature::SegwitV0(EcdsaSighashType::All),
+            InputSignature::UnsignedTaproot,
+            InputSignature::Unsigned,
+        ];
+
+        let report = can_add_embedding(&tx, EmbeddingType::TaprootAnnex, &signatures);
+        assert_eq!(
+            report.inputs,
+            vec![
+                InputImpact::Invalidated,
+                InputImpact::Unsupported,
+                InputImpact::Unaffected,
+                InputImpact::Unsupported,
+            ]
+        );
+        assert_eq!(report.annex_candidates(), vec![2]);
+        assert!(report.can_add());
+
+        let report = can_add_embedding(&tx, EmbeddingType::TaprootAnnex, &signatures[..2]);
+        assert!(!report.can_add());
+    }
+
+    fn op_return(len: usize) -> ScriptBuf {
+        ScriptBuf::new_op_return(PushBytesBuf::try_from(vec![1; len]).unwrap())
+    }
+
+    #[test]
+    fn test_op_return_policy() {
+        let mut tx = transaction(1, 1);
+        tx.output[0].script_pubkey = op_return(80);
+        assert_eq!(tx.output[0].script_pubkey.len(), DEFAULT_DATACARRIER_SIZE);
+
+        let policy = OpReturnPolicy::default();
+        assert_eq!(policy.check(&tx), Ok(()));
+        assert_eq!(
+            policy.can_add(&tx, &ScriptBuf::new_op_return([2; 4])),
+            Err(OpReturnError::MultipleOutputs)
+        );
+        assert_eq!(policy.can_add(&tx, &ScriptBuf::new()), Ok(()));
+
+        tx.output[0].script_pubkey = op_return(81);
+        assert_eq!(
+            policy.check(&tx),
+            Err(OpReturnError::TooLarge {
+                size: 84,
+                limit: 83
+            })
+        );
+
+        // Bitcoin Core v30 limits the total across outputs
+        let policy = OpReturnPolicy::CORE_V30;
+        let large = op_return(60_000);
+        assert_eq!(policy.check(&tx), Ok(()));
+        assert_eq!(policy.can_add(&tx, &large), Ok(()));
+        tx.output[0].script_pubkey = large.clone();
+        assert_eq!(
+            policy.can_add(&tx, &large),
+            Err(OpReturnError::TooLarge {
+                size: 2 * large.len(),
+                limit: 100_000
+            })
+        );
+        assert_eq!(OpReturnPolicy::UNLIMITED.can_add(&tx, &large), Ok(()));
+    }
+
+    #[test]
+    fn test_annex_policy() {
+        let mut tx = transaction(4, 0);
+        let spend = |annex: Vec<u8>| Witness::from_slice(&[vec![1; 64], annex]);
+        tx.input[0].witness = spend(vec![0x50, 0, 1, 2]);
+        tx.input[1].witness = spend([vec![0x50, 0], vec![7; 300]].concat());
+        tx.input[2].witness = spend(vec![0x50, 1, 1]);
+        tx.input[3].witness = Witness::from_slice(&[vec![1; 64]]);
+
+        let checks = validate_annex(&tx);
+        assert_eq!(checks.len(), 3);
+        assert_eq!(checks[0].issues, vec![AnnexIssue::NotRelayed]);
+        assert_eq!(checks[1].size, 302);
+
+        let checks = AnnexPolicy::LIMITED.validate(&tx, &[]);
+        assert!(checks[0].is_acceptable());
+        assert_eq!(
+            checks[1].issues,
+            vec![AnnexIssue::TooLarge {
+                size: 302,
+                limit: DEFAULT_MAX_ANNEX_SIZE
+            }]
+        );
+        assert_eq!(checks[2].issues, vec![AnnexIssue::MissingDataTag]);
+        assert_eq!(
+            checks.iter().map(|check| check.input).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+
+        // A P2WPKH prevout, or a scriptSig without prevouts, is not a taproot input
+        let p2tr = TxOut {
+            value: Amount::ZERO,
+            script_pubkey: ScriptBuf::from_bytes([vec![0x51, 0x20], vec![2; 32]].concat()),
+        };
+        let p2wpkh = TxOut {
+            value: Amount::ZERO,
+            script_pubkey: ScriptBuf::from_bytes([vec![0x00, 0x14], vec![2; 20]].concat()),
+        };
+        let checks = AnnexPolicy::UNLIMITED.validate(&tx, &[p2wpkh, p2tr.clone(), p2tr]);
+        assert_eq!(checks[0].issues, vec![AnnexIssue::NonTaprootInput]);
+        assert!(checks[1].is_acceptable() && checks[2].is_acceptable());
+
+        tx.input[0].script_sig = ScriptBuf::from_bytes(vec![0x51]);
+        let checks = AnnexPolicy::UNLIMITED.validate(&tx, &[]);
+        assert_eq!(checks[0].issues, vec![AnnexIssue::NonTaprootInput]);
+    }
+
+    #[test]
+    fn test_policy_violation() {
+        assert_eq!(
+            PolicyViolation::from_reject_reason("datacarrier"),
+            PolicyViolation::DatacarrierTooLarge
+        );
+        assert_eq!(
+            PolicyViolation::from_reject_reason("scriptsig-not-pushonly"),
+            PolicyViolation::ScriptSigNotPushOnly
+        );
+        assert_eq!(
+            PolicyViolation::from_reject_reason("tx-size"),
+            PolicyViolation::TxTooLarge
+        );
+        assert_eq!(
+            PolicyViolation::from_reject_reason("mempool min fee not met"),
+            PolicyViolation::InsufficientFee
+        );
+        assert_eq!(
+            PolicyViolation::from_reject_reason("non-final"),
+            PolicyViolation::Other("non-final".to_string())
+        );
+    }
+}
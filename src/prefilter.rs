@@ -0,0 +1,271 @@
+//! # Filter Pre-scanning
+//!
+//! A [`FilterScanner`] uses BIP-158 compact block filters to decide which blocks a light-client
+//! indexer needs to download, instead of fetching every block. Basic filters cover every output
+//! scriptPubKey except `OP_RETURN` outputs, plus the scriptPubKey of every spent output, so the
+//! scanner watches the scripts that protocol transactions pay to or spend from: commit outputs
+//! whose reveals carry envelopes, or addresses known to fund `OP_RETURN` transactions.
+//!
+//! A filter match may be a false positive (about one in 784,931 per watched script), so a
+//! matched block can still hold no relevant transaction. A block that does not match spends
+//! from and pays to none of the watched scripts.
+
+use crate::follower::ChainSource;
+use crate::resolver::{ConfirmedEmbedding, ResolveError};
+
+use bitcoin::bip158::BlockFilter;
+use bitcoin::{BlockHash, ScriptBuf};
+use std::ops::RangeInclusive;
+
+/// A source of BIP-158 basic block filters, such as Bitcoin Core with `-blockfilterindex`
+pub trait FilterSource {
+    /// Returns the basic filter of a block
+    fn block_filter(&self, hash: &BlockHash) -> Result<BlockFilter, ResolveError>;
+}
+
+/// Selects the blocks whose filters match a set of watched scriptPubKeys
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilterScanner {
+    scripts: Vec<ScriptBuf>,
+}
+
+impl FilterScanner {
+    /// Returns a scanner watching `scripts`
+    pub fn new(scripts: impl IntoIterator<Item = ScriptBuf>) -> Self {
+        let mut scanner = Self::default();
+        scripts.into_iter().for_each(|script| scanner.watch(script));
+        scanner
+    }
+
+    /// Adds a script to the watch list, if not already watched
+    pub fn watch(&mut self, script: ScriptBuf) {
+        if !self.scripts.contains(&script) {
+            self.scripts.push(script);
+        }
+    }
+
+    /// Returns the watched scripts
+    pub fn scripts(&self) -> &[ScriptBuf] {
+        &self.scripts
+    }
+
+    /// Returns whether the filter of the block `hash` matches any watched script. A scanner
+    /// watching nothing matches no block.
+    pub fn matches(&self, hash: &BlockHash, filter: &BlockFilter) -> Result<bool, ResolveError> {
+        if self.scripts.is_empty() {
+            return Ok(false);
+        }
+
+        filter
+            .match_any(hash, self.scripts.iter().map(|script| script.as_bytes()))
+            .map_err(|e| ResolveError::Backend(format!("invalid filter for block {hash}: {e}")))
+    }
+
+    /// Returns the height and hash of each block in `heights` whose filter matches, fetching
+    /// only filters
+    pub fn blocks_to_fetch<S: ChainSource + FilterSource>(
+        &self,
+        source: &S,
+        heights: RangeInclusive<u64>,
+    ) -> Result<Vec<(u64, BlockHash)>, ResolveError> {
+        let mut blocks = Vec::new();
+        for height in heights {
+            let hash = source.block_hash_at(height)?;
+            if self.matches(&hash, &source.block_filter(&hash)?)? {
+                blocks.push((height, hash));
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Returns the embeddings in the blocks in `heights` whose filters match, downloading only
+    /// those blocks. Embeddings in matched blocks are returned whether or not their transaction
+    /// touches a watched script.
+    pub fn scan<S: ChainSource + FilterSource>(
+        &self,
+        source: &S,
+        heights: RangeInclusive<u64>,
+    ) -> Result<Vec<ConfirmedEmbedding>, ResolveError> {
+        let mut embeddings = Vec::new();
+        for (height, hash) in self.blocks_to_fetch(source, heights)? {
+            embeddings.extend(source.confirmed_embeddings_in_block(&hash, height)?);
+        }
+        Ok(embeddings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::Resolver;
+
+    use bitcoin::bip158;
+    use bitcoin::block::{Header, Version as BlockVersion};
+    use bitcoin::hashes::Hash;
+    use bitcoin::{
+        Amount, Block, CompactTarget, OutPoint, Sequence, Transaction, TxIn, TxMerkleNode, TxOut,
+        Txid, Witness, absolute::LockTime, transaction::Version,
+    };
+    use std::cell::Cell;
+
+    fn watched() -> ScriptBuf {
+        ScriptBuf::new_p2wsh(&bitcoin::WScriptHash::from_byte_array([7; 32]))
+    }
+
+    fn other() -> ScriptBuf {
+        ScriptBuf::new_p2wsh(&bitcoin::WScriptHash::from_byte_array([8; 32]))
+    }
+
+    fn transaction(previous_output: OutPoint, script_pubkey: ScriptBuf) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(1_000),
+                    script_pubkey,
+                },
+                TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey: ScriptBuf::new_op_return([1, 2]),
+                },
+            ],
+        }
+    }
+
+    /// Four blocks: one paying to the watched script, one spending that output, and two
+    /// touching neither
+    struct MockChain {
+        blocks: Vec<Block>,
+        downloads: Cell<usize>,
+    }
+
+    impl MockChain {
+        fn new() -> Self {
+            let coinbase = Transaction {
+                input: vec![TxIn::default()],
+                ..transaction(OutPoint::null(), other())
+            };
+            let funding = transaction(OutPoint::new(coinbase.compute_txid(), 0), other());
+            let pay = transaction(OutPoint::new(funding.compute_txid(), 0), watched());
+            let spend = transaction(OutPoint::new(pay.compute_txid(), 0), other());
+            let unrelated = transaction(OutPoint::new(spend.compute_txid(), 0), other());
+
+            let blocks = [funding, pay, spend, unrelated]
+                .into_iter()
+                .enumerate()
+                .map(|(nonce, tx)| Block {
+                    header: Header {
+                        version: BlockVersion::ONE,
+                        prev_blockhash: BlockHash::all_zeros(),
+                        merkle_root: TxMerkleNode::all_zeros(),
+                        time: 0,
+                        bits: CompactTarget::from_consensus(0),
+                        nonce: nonce as u32,
+                    },
+                    txdata: vec![coinbase.clone(), tx],
+                })
+                .collect();
+
+            Self {
+                blocks,
+                downloads: Cell::new(0),
+            }
+        }
+
+        fn find(&self, hash: &BlockHash) -> Result<&Block, ResolveError> {
+            self.blocks
+                .iter()
+                .find(|block| block.block_hash() == *hash)
+                .ok_or(ResolveError::BlockNotFound(*hash))
+        }
+    }
+
+    impl Resolver for MockChain {
+        fn transaction(&self, txid: &Txid) -> Result<Transaction, ResolveError> {
+            self.blocks
+                .iter()
+                .flat_map(|block| &block.txdata)
+                .find(|tx| tx.compute_txid() == *txid)
+                .cloned()
+                .ok_or(ResolveError::TransactionNotFound(*txid))
+        }
+
+        fn block(&self, hash: &BlockHash) -> Result<Block, ResolveError> {
+            self.downloads.set(self.downloads.get() + 1);
+            self.find(hash).cloned()
+        }
+    }
+
+    impl ChainSource for MockChain {
+        fn best_height(&self) -> Result<u64, ResolveError> {
+            Ok(self.blocks.len() as u64 - 1)
+        }
+
+        fn block_hash_at(&self, height: u64) -> Result<BlockHash, ResolveError> {
+            self.blocks
+                .get(height as usize)
+                .map(Block::block_hash)
+                .ok_or(ResolveError::Backend("height out of range".to_string()))
+        }
+
+        fn block_header(&self, hash: &BlockHash) -> Result<Header, ResolveError> {
+            Ok(self.find(hash)?.header)
+        }
+    }
+
+    impl FilterSource for MockChain {
+        fn block_filter(&self, hash: &BlockHash) -> Result<BlockFilter, ResolveError> {
+            BlockFilter::new_script_filter(self.find(hash)?, |outpoint| {
+                self.transaction(&outpoint.txid)
+                    .ok()
+                    .and_then(|tx| tx.output.get(outpoint.vout as usize).cloned())
+                    .map(|txout| txout.script_pubkey)
+                    .ok_or(bip158::Error::UtxoMissing(*outpoint))
+            })
+            .map_err(|e| ResolveError::Backend(e.to_string()))
+        }
+    }
+
+    #[test]
+    fn test_blocks_to_fetch() {
+        let chain = MockChain::new();
+        let scanner = FilterScanner::new([watched(), watched()]);
+        assert_eq!(scanner.scripts().len(), 1);
+
+        let blocks = scanner.blocks_to_fetch(&chain, 0..=3).unwrap();
+        let heights = blocks.iter().map(|(height, _)| *height).collect::<Vec<_>>();
+        assert_eq!(heights, vec![1, 2]);
+        assert_eq!(blocks[0].1, chain.blocks[1].block_hash());
+
+        // Filters never cover OP_RETURN outputs
+        let op_return = FilterScanner::new([ScriptBuf::new_op_return([1, 2])]);
+        assert!(op_return.blocks_to_fetch(&chain, 0..=3).unwrap().is_empty());
+        assert!(
+            FilterScanner::default()
+                .blocks_to_fetch(&chain, 0..=3)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(scanner.blocks_to_fetch(&chain, 0..=4).is_err());
+    }
+
+    #[test]
+    fn test_scan() {
+        let chain = MockChain::new();
+        let scanner = FilterScanner::new([watched()]);
+
+        let embeddings = scanner.scan(&chain, 0..=3).unwrap();
+        assert_eq!(chain.downloads.get(), 2);
+        // An OP_RETURN in the coinbase and in the transaction of each matched block
+        assert_eq!(embeddings.len(), 4);
+        assert_eq!(embeddings[0].height, 1);
+        assert_eq!(embeddings[3].height, 2);
+    }
+}
@@ -0,0 +1,21 @@
+//! # Protocol Codecs
+//!
+//! Decoders for third-party protocols that carry data in the same places as embeddings. Each
+//! protocol is behind its own feature.
+
+#[cfg(feature = "asset")]
+pub mod asset;
+#[cfg(feature = "did")]
+pub mod did;
+#[cfg(feature = "dlc")]
+pub mod dlc;
+#[cfg(feature = "inscriptions")]
+pub mod inscriptions;
+#[cfg(feature = "nostr")]
+pub mod nostr;
+#[cfg(feature = "omni")]
+pub mod omni;
+#[cfg(feature = "ots")]
+pub mod ots;
+#[cfg(feature = "runes")]
+pub mod runes;
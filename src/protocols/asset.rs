@@ -0,0 +1,404 @@
+//! # Asset Messages
+//!
+//! A starting point for token protocols: messages that issue an asset, transfer it to outputs
+//! of the carrying transaction, and burn it, encoded as tagged [`Message`]s so they can share an
+//! embedding with other messages. Assets are identified by the txid of their issuance.
+//!
+//! This module defines only the encoding. Protocols built on it decide how balances are
+//! tracked, what happens to unallocated amounts, and how invalid transfers are treated.
+//!
+//! Bodies are laid out as:
+//! - Issue: the LEB128 supply, the LEB128 output receiving it, a divisibility byte, and the
+//!   UTF-8 ticker in the remaining bytes
+//! - Transfer: the 32-byte asset id, the LEB128 edict count, the LEB128 amount of each edict,
+//!   and the outputs as a delta-encoded list ([`varint::list`])
+//! - Burn: the 32-byte asset id and the LEB128 amount
+
+use crate::message::{Message, tags};
+use crate::varint;
+
+use bitcoin::Txid;
+use bitcoin::hashes::Hash;
+use std::fmt;
+
+/// An asset id: the txid of the transaction issuing the asset
+pub type AssetId = Txid;
+
+/// The maximum length of a ticker in bytes
+pub const MAX_TICKER_LENGTH: usize = 32;
+
+/// An asset message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssetMessage {
+    /// Creates an asset identified by the carrying transaction
+    Issue(Issue),
+    /// Moves amounts of an asset to outputs of the carrying transaction
+    Transfer(Transfer),
+    /// Destroys an amount of an asset
+    Burn(Burn),
+}
+
+/// The issuance of an asset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// The amount created, in base units
+    pub supply: u128,
+    /// The output receiving the supply
+    pub output: u32,
+    /// The number of decimal places used to display amounts
+    pub divisibility: u8,
+    /// The ticker, at most [`MAX_TICKER_LENGTH`] bytes
+    pub ticker: String,
+}
+
+/// An allocation of an amount to an output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edict {
+    /// The output index
+    pub output: u32,
+    /// The amount, in base units
+    pub amount: u128,
+}
+
+/// A transfer of an asset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transfer {
+    /// The asset
+    pub asset: AssetId,
+    /// The allocations, in strictly ascending output order
+    pub edicts: Vec<Edict>,
+}
+
+/// A burn of an asset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Burn {
+    /// The asset
+    pub asset: AssetId,
+    /// The amount destroyed, in base units
+    pub amount: u128,
+}
+
+/// Error types for encoding and decoding asset messages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssetError {
+    /// The body ends before a required field
+    Truncated,
+    /// The body has bytes after its fields
+    TrailingBytes,
+    /// The ticker is too long or not UTF-8
+    InvalidTicker,
+    /// The edicts are not in strictly ascending output order
+    UnsortedEdicts,
+    /// The edict amounts sum to more than `u128::MAX`
+    AmountOverflow,
+    /// LEB128 decoding error
+    VarInt(varint::Error),
+}
+
+impl AssetMessage {
+    /// Returns the message tag
+    pub fn tag(&self) -> u128 {
+        match self {
+            AssetMessage::Issue(_) => tags::ASSET_ISSUE,
+            AssetMessage::Transfer(_) => tags::ASSET_TRANSFER,
+            AssetMessage::Burn(_) => tags::ASSET_BURN,
+        }
+    }
+
+    /// Encodes the asset message as a [`Message`]
+    pub fn to_message(&self) -> Result<Message, AssetError> {
+        let mut body = Vec::new();
+        match self {
+            AssetMessage::Issue(issue) => {
+                if issue.ticker.len() > MAX_TICKER_LENGTH {
+                    return Err(AssetError::InvalidTicker);
+                }
+                varint::encode_to_vec(issue.supply, &mut body);
+                varint::encode_to_vec(issue.output.into(), &mut body);
+                body.push(issue.divisibility);
+                body.extend(issue.ticker.as_bytes());
+            }
+            AssetMessage::Transfer(transfer) => {
+                transfer.total()?;
+                body.extend(transfer.asset.as_byte_array());
+                varint::encode_to_vec(transfer.edicts.len() as u128, &mut body);
+                for edict in &transfer.edicts {
+                    varint::encode_to_vec(edict.amount, &mut body);
+                }
+                let outputs = transfer
+                    .edicts
+                    .iter()
+                    .map(|edict| edict.output.into())
+                    .collect::<Vec<_>>();
+                body.extend(varint::list::encode(&outputs)?);
+            }
+            AssetMessage::Burn(burn) => {
+                body.extend(burn.asset.as_byte_array());
+                varint::encode_to_vec(burn.amount, &mut body);
+            }
+        }
+
+        Ok(Message::new(self.tag(), body).expect("valid tag and size"))
+    }
+
+    /// Decodes an asset message, or returns `None` if the message has another tag
+    pub fn from_message(message: &Message) -> Result<Option<Self>, AssetError> {
+        let body = message.body.as_slice();
+        let asset_message = match message.tag {
+            tags::ASSET_ISSUE => {
+                let (supply, n) = varint::decode_strict(body)?;
+                let (output, m) = varint::decode_strict(&body[n..])?;
+                let (&divisibility, ticker) =
+                    body[n + m..].split_first().ok_or(AssetError::Truncated)?;
+                if ticker.len() > MAX_TICKER_LENGTH {
+                    return Err(AssetError::InvalidTicker);
+                }
+
+                AssetMessage::Issue(Issue {
+                    supply,
+                    output: to_u32(output)?,
+                    divisibility,
+                    ticker: String::from_utf8(ticker.to_vec())
+                        .map_err(|_| AssetError::InvalidTicker)?,
+                })
+            }
+            tags::ASSET_TRANSFER => {
+                let (asset, mut rest) = read_asset(body)?;
+                let (count, n) = varint::decode_strict(rest)?;
+                rest = &rest[n..];
+
+                let mut amounts = Vec::new();
+                for _ in 0..count.min(rest.len() as u128) {
+                    let (amount, n) = varint::decode_strict(rest)?;
+                    amounts.push(amount);
+                    rest = &rest[n..];
+                }
+
+                let outputs = varint::list::decode(rest)?;
+                match outputs.len().cmp(&amounts.len()) {
+                    std::cmp::Ordering::Less => return Err(AssetError::Truncated),
+                    std::cmp::Ordering::Greater => return Err(AssetError::TrailingBytes),
+                    std::cmp::Ordering::Equal if amounts.len() as u128 != count => {
+                        return Err(AssetError::Truncated);
+                    }
+                    std::cmp::Ordering::Equal => {}
+                }
+
+                let transfer = Transfer {
+                    asset,
+                    edicts: outputs
+                        .into_iter()
+                        .zip(amounts)
+                        .map(|(output, amount)| {
+                            Ok(Edict {
+                                output: to_u32(output)?,
+                                amount,
+                            })
+                        })
+                        .collect::<Result<_, AssetError>>()?,
+                };
+                transfer.total()?;
+                AssetMessage::Transfer(transfer)
+            }
+            tags::ASSET_BURN => {
+                let (asset, rest) = read_asset(body)?;
+                let (amount, n) = varint::decode_strict(rest)?;
+                if n != rest.len() {
+                    return Err(AssetError::TrailingBytes);
+                }
+                AssetMessage::Burn(Burn { asset, amount })
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(Some(asset_message))
+    }
+}
+
+impl Transfer {
+    /// Returns the total amount transferred, checking that the edicts are in strictly
+    /// ascending output order
+    pub fn total(&self) -> Result<u128, AssetError> {
+        if self
+            .edicts
+            .windows(2)
+            .any(|pair| pair[0].output >= pair[1].output)
+        {
+            return Err(AssetError::UnsortedEdicts);
+        }
+
+        self.edicts
+            .iter()
+            .try_fold(0u128, |total, edict| total.checked_add(edict.amount))
+            .ok_or(AssetError::AmountOverflow)
+    }
+}
+
+/// Encodes asset messages
+pub fn encode(asset_messages: &[AssetMessage]) -> Result<Vec<Message>, AssetError> {
+    asset_messages
+        .iter()
+        .map(AssetMessage::to_message)
+        .collect()
+}
+
+/// Decodes the asset messages among `messages`, ignoring messages with other tags
+pub fn decode(messages: &[Message]) -> Result<Vec<AssetMessage>, AssetError> {
+    messages
+        .iter()
+        .filter_map(|message| AssetMessage::from_message(message).transpose())
+        .collect()
+}
+
+fn read_asset(body: &[u8]) -> Result<(AssetId, &[u8]), AssetError> {
+    let (asset, rest) = body.split_first_chunk().ok_or(AssetError::Truncated)?;
+    Ok((AssetId::from_byte_array(*asset), rest))
+}
+
+fn to_u32(n: u128) -> Result<u32, AssetError> {
+    u32::try_from(n).map_err(|_| AssetError::VarInt(varint::Error::Overflow))
+}
+
+impl From<varint::Error> for AssetError {
+    fn from(e: varint::Error) -> Self {
+        match e {
+            varint::Error::Unterminated => AssetError::Truncated,
+            e => AssetError::VarInt(e),
+        }
+    }
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::Truncated => write!(f, "Asset message is truncated"),
+            AssetError::TrailingBytes => write!(f, "Asset message has trailing bytes"),
+            AssetError::InvalidTicker => write!(f, "Invalid asset ticker"),
+            AssetError::UnsortedEdicts => {
+                write!(f, "Edicts are not in strictly ascending output order")
+            }
+            AssetError::AmountOverflow => write!(f, "Edict amounts overflow"),
+            AssetError::VarInt(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asset() -> AssetId {
+        AssetId::from_byte_array([7; 32])
+    }
+
+    fn transfer() -> Transfer {
+        Transfer {
+            asset: asset(),
+            edicts: vec![
+                Edict {
+                    output: 1,
+                    amount: 500,
+                },
+                Edict {
+                    output: 3,
+                    amount: 1_000_000,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let asset_messages = vec![
+            AssetMessage::Issue(Issue {
+                supply: 21_000_000 * 100_000_000,
+                output: 0,
+                divisibility: 8,
+                ticker: "COIN".to_string(),
+            }),
+            AssetMessage::Transfer(transfer()),
+            AssetMessage::Burn(Burn {
+                asset: asset(),
+                amount: 10,
+            }),
+        ];
+
+        let mut messages = encode(&asset_messages).unwrap();
+        messages.insert(
+            1,
+            Message::new(tags::CONTENT_TYPE, b"text/plain".to_vec()).unwrap(),
+        );
+        assert_eq!(decode(&messages).unwrap(), asset_messages);
+
+        // The messages survive the wire encoding
+        let bytes = Message::encode(messages);
+        assert_eq!(
+            decode(&Message::decode(&bytes).unwrap()).unwrap(),
+            asset_messages
+        );
+    }
+
+    #[test]
+    fn test_transfer() {
+        let transfer = transfer();
+        assert_eq!(transfer.total(), Ok(1_000_500));
+
+        let message = AssetMessage::Transfer(transfer.clone())
+            .to_message()
+            .unwrap();
+        // Asset id, count, amounts, then the output deltas 1 and 2
+        assert_eq!(message.body.len(), 32 + 1 + 2 + 3 + 2);
+        assert_eq!(message.body[38..], [1, 2]);
+
+        let mut unsorted = transfer.clone();
+        unsorted.edicts.swap(0, 1);
+        assert_eq!(
+            AssetMessage::Transfer(unsorted).to_message(),
+            Err(AssetError::UnsortedEdicts)
+        );
+
+        let mut overflowing = transfer;
+        overflowing.edicts[0].amount = u128::MAX;
+        assert_eq!(overflowing.total(), Err(AssetError::AmountOverflow));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let decode_body = |tag, body: &[u8]| {
+            AssetMessage::from_message(&Message::new(tag, body.to_vec()).unwrap())
+        };
+
+        let body = AssetMessage::Transfer(transfer())
+            .to_message()
+            .unwrap()
+            .body;
+        assert_eq!(
+            decode_body(tags::ASSET_TRANSFER, &body[..body.len() - 1]),
+            Err(AssetError::Truncated)
+        );
+        assert_eq!(
+            decode_body(tags::ASSET_TRANSFER, &[&body[..], &[1]].concat()),
+            Err(AssetError::TrailingBytes)
+        );
+        assert_eq!(
+            decode_body(tags::ASSET_TRANSFER, &body[..20]),
+            Err(AssetError::Truncated)
+        );
+
+        assert_eq!(
+            decode_body(tags::ASSET_BURN, &[&[7; 32][..], &[10, 0]].concat()),
+            Err(AssetError::TrailingBytes)
+        );
+        assert_eq!(
+            decode_body(tags::ASSET_ISSUE, &[1, 0, 8, 0xff]),
+            Err(AssetError::InvalidTicker)
+        );
+        assert_eq!(
+            decode_body(tags::ASSET_ISSUE, &[1, 0]),
+            Err(AssetError::Truncated)
+        );
+        assert_eq!(decode_body(tags::CONTENT_TYPE, b"text/plain"), Ok(None));
+    }
+}
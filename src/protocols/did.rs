@@ -0,0 +1,440 @@
+//! # DID Anchoring
+//!
+//! Anchors the operations of a decentralized identifier in `OP_RETURN` outputs or annexes, in
+//! the style of `did:btcr`: a create operation names the controller key and the hash of the
+//! initial DID document, and update and deactivate operations reference the DID by the id of
+//! its create embedding. Each operation is a [`tags::DID_OPERATION`] message followed by a
+//! BIP-340 signature by the controller ([`message::sign`]), so only the controller can change
+//! the DID. Documents themselves are kept off chain and checked against the anchored hashes.
+//!
+//! DIDs are resolved from operation embeddings fetched through a [`Resolver`] with
+//! [`resolve`], or tracked as a [`Follower`](crate::follower::Follower) moves along the chain
+//! with a [`DidRegistry`].
+
+use crate::follower::Handler;
+use crate::message::{self, Message, tagged_hash, tags};
+use crate::resolver::{ConfirmedEmbedding, ResolveError, Resolver};
+use crate::{CONTENT_HASH_TAG, Embedding, EmbeddingId};
+
+use bitcoin::secp256k1::{Keypair, XOnlyPublicKey};
+use std::fmt;
+
+const CREATE: u8 = 0;
+const UPDATE: u8 = 1;
+const DEACTIVATE: u8 = 2;
+
+/// An operation on a DID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DidOperation {
+    /// Creates a DID, identified by the id of the embedding holding this operation
+    Create {
+        /// The key that signs this and later operations
+        controller: XOnlyPublicKey,
+        /// The content hash of the initial DID document
+        payload_hash: [u8; 32],
+    },
+    /// Replaces the DID document
+    Update {
+        /// The DID
+        did: EmbeddingId,
+        /// The content hash of the new DID document
+        payload_hash: [u8; 32],
+    },
+    /// Deactivates the DID, after which it cannot be updated
+    Deactivate {
+        /// The DID
+        did: EmbeddingId,
+    },
+}
+
+/// The state of a DID after applying its operations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DidState {
+    /// The DID: the id of the create embedding
+    pub did: EmbeddingId,
+    /// The controller key
+    pub controller: XOnlyPublicKey,
+    /// The content hash of the current DID document
+    pub payload_hash: [u8; 32],
+    /// Whether the DID has been deactivated
+    pub deactivated: bool,
+    /// The ids of the embeddings holding the operations applied, starting with the create
+    pub operations: Vec<EmbeddingId>,
+}
+
+/// Follows the operations of every DID as a follower reports embeddings, rewinding on reorgs
+#[derive(Debug, Clone, Default)]
+pub struct DidRegistry {
+    /// Each operation with the height confirming it, in chain order
+    operations: Vec<(u64, EmbeddingId, DidOperation, Vec<Message>)>,
+}
+
+/// Error types for DID operations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DidError {
+    /// The payload could not be decoded as messages
+    Message(message::Error),
+    /// The payload has no DID operation
+    MissingOperation,
+    /// The DID operation is malformed
+    InvalidOperation,
+    /// The operation is not signed by the controller
+    Unauthorized,
+    /// The embedding does not hold a create operation
+    NotCreate(EmbeddingId),
+    /// The operation is for a different DID
+    WrongDid(EmbeddingId),
+    /// The DID has been deactivated
+    Deactivated,
+    /// An operation could not be resolved
+    Resolve(ResolveError),
+}
+
+impl DidOperation {
+    /// Returns the operation as a [`tags::DID_OPERATION`] message
+    pub fn to_message(&self) -> Message {
+        let body = match self {
+            DidOperation::Create {
+                controller,
+                payload_hash,
+            } => [&[CREATE][..], &controller.serialize(), payload_hash].concat(),
+            DidOperation::Update { did, payload_hash } => {
+                [&[UPDATE][..], payload_hash, &did.to_bytes()].concat()
+            }
+            DidOperation::Deactivate { did } => [&[DEACTIVATE][..], &did.to_bytes()].concat(),
+        };
+        Message::new(tags::DID_OPERATION, body).expect("valid tag and size")
+    }
+
+    /// Decodes an operation, or returns `None` if the message has another tag
+    pub fn from_message(message: &Message) -> Result<Option<Self>, DidError> {
+        if message.tag != tags::DID_OPERATION {
+            return Ok(None);
+        }
+
+        let (&kind, rest) = message
+            .body
+            .split_first()
+            .ok_or(DidError::InvalidOperation)?;
+        let did = |bytes| EmbeddingId::from_bytes(bytes).map_err(|_| DidError::InvalidOperation);
+
+        let operation = match kind {
+            CREATE => {
+                let (controller, payload_hash) = rest
+                    .split_first_chunk::<32>()
+                    .ok_or(DidError::InvalidOperation)?;
+                DidOperation::Create {
+                    controller: XOnlyPublicKey::from_slice(controller)
+                        .map_err(|_| DidError::InvalidOperation)?,
+                    payload_hash: payload_hash
+                        .try_into()
+                        .map_err(|_| DidError::InvalidOperation)?,
+                }
+            }
+            UPDATE => {
+                let (payload_hash, id) = rest
+                    .split_first_chunk::<32>()
+                    .ok_or(DidError::InvalidOperation)?;
+                DidOperation::Update {
+                    did: did(id)?,
+                    payload_hash: *payload_hash,
+                }
+            }
+            DEACTIVATE => DidOperation::Deactivate { did: did(rest)? },
+            _ => return Err(DidError::InvalidOperation),
+        };
+
+        Ok(Some(operation))
+    }
+
+    /// Returns the operation followed by the controller's signature, ready to embed
+    pub fn sign(&self, controller: &Keypair) -> Vec<Message> {
+        message::sign(vec![self.to_message()], controller)
+    }
+
+    /// Returns the operation in an embedding, together with the embedding's messages for
+    /// checking the signature
+    pub fn from_embedding(embedding: &Embedding) -> Result<(Self, Vec<Message>), DidError> {
+        let messages = Message::decode(&embedding.payload()).map_err(DidError::Message)?;
+        let operation = messages
+            .iter()
+            .find_map(|message| Self::from_message(message).transpose())
+            .ok_or(DidError::MissingOperation)??;
+        Ok((operation, messages))
+    }
+}
+
+/// Returns the content hash of a DID document, as anchored by create and update operations
+pub fn payload_hash(document: &[u8]) -> [u8; 32] {
+    tagged_hash(CONTENT_HASH_TAG, document)
+}
+
+impl DidState {
+    /// Returns the state created by the operation in `embedding`
+    pub fn create(embedding: &Embedding) -> Result<Self, DidError> {
+        let (operation, messages) = DidOperation::from_embedding(embedding)?;
+        Self::from_create(embedding.id(), operation, &messages)
+    }
+
+    /// Applies the operation in `embedding`
+    pub fn apply(&mut self, embedding: &Embedding) -> Result<(), DidError> {
+        let (operation, messages) = DidOperation::from_embedding(embedding)?;
+        self.apply_operation(embedding.id(), operation, &messages)
+    }
+
+    /// Returns whether `document` is the current DID document
+    pub fn verify_document(&self, document: &[u8]) -> bool {
+        !self.deactivated && payload_hash(document) == self.payload_hash
+    }
+
+    fn from_create(
+        id: EmbeddingId,
+        operation: DidOperation,
+        messages: &[Message],
+    ) -> Result<Self, DidError> {
+        let DidOperation::Create {
+            controller,
+            payload_hash,
+        } = operation
+        else {
+            return Err(DidError::NotCreate(id));
+        };
+        message::verify(messages, &controller).map_err(|_| DidError::Unauthorized)?;
+
+        Ok(Self {
+            did: id,
+            controller,
+            payload_hash,
+            deactivated: false,
+            operations: vec![id],
+        })
+    }
+
+    fn apply_operation(
+        &mut self,
+        id: EmbeddingId,
+        operation: DidOperation,
+        messages: &[Message],
+    ) -> Result<(), DidError> {
+        let did = match &operation {
+            DidOperation::Create { .. } => return Err(DidError::WrongDid(id)),
+            DidOperation::Update { did, .. } | DidOperation::Deactivate { did } => *did,
+        };
+        if did != self.did {
+            return Err(DidError::WrongDid(did));
+        }
+        if self.deactivated {
+            return Err(DidError::Deactivated);
+        }
+        message::verify(messages, &self.controller).map_err(|_| DidError::Unauthorized)?;
+
+        match operation {
+            DidOperation::Update { payload_hash, .. } => self.payload_hash = payload_hash,
+            _ => self.deactivated = true,
+        }
+        self.operations.push(id);
+        Ok(())
+    }
+}
+
+/// Resolves a DID from its create embedding and its later operations, in chain order,
+/// fetching them from `resolver`. Any invalid operation is an error.
+pub fn resolve<R: Resolver + ?Sized>(
+    resolver: &R,
+    did: &EmbeddingId,
+    operations: &[EmbeddingId],
+) -> Result<DidState, DidError> {
+    let mut state = DidState::create(&resolver.embedding(did)?)?;
+    for embedding in resolver.embeddings(operations)? {
+        state.apply(&embedding)?;
+    }
+    Ok(state)
+}
+
+impl DidRegistry {
+    /// Returns an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the state of `did`, applying the valid operations seen so far and skipping
+    /// invalid ones. Returns `None` if no valid create operation has been seen.
+    pub fn resolve(&self, did: &EmbeddingId) -> Option<DidState> {
+        let mut operations = self.operations.iter();
+        let mut state = operations.find_map(|(_, id, operation, messages)| {
+            (id == did)
+                .then(|| DidState::from_create(*id, operation.clone(), messages).ok())
+                .flatten()
+        })?;
+
+        for (_, id, operation, messages) in operations {
+            let _ = state.apply_operation(*id, operation.clone(), messages);
+        }
+        Some(state)
+    }
+}
+
+impl Handler for DidRegistry {
+    fn on_embedding_confirmed(&mut self, confirmed: &ConfirmedEmbedding) {
+        if let Ok((operation, messages)) = DidOperation::from_embedding(&confirmed.embedding) {
+            self.operations.push((
+                confirmed.height,
+                confirmed.embedding.id(),
+                operation,
+                messages,
+            ));
+        }
+    }
+
+    fn on_block_disconnected(&mut self, height: u64) {
+        self.operations.retain(|(h, ..)| *h < height);
+    }
+}
+
+impl From<ResolveError> for DidError {
+    fn from(e: ResolveError) -> Self {
+        DidError::Resolve(e)
+    }
+}
+
+impl fmt::Display for DidError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DidError::Message(e) => write!(f, "{e}"),
+            DidError::MissingOperation => write!(f, "No DID operation in the payload"),
+            DidError::InvalidOperation => write!(f, "Invalid DID operation"),
+            DidError::Unauthorized => write!(f, "DID operation is not signed by the controller"),
+            DidError::NotCreate(id) => write!(f, "Embedding {id} does not create a DID"),
+            DidError::WrongDid(id) => write!(f, "Operation is for another DID: {id}"),
+            DidError::Deactivated => write!(f, "DID is deactivated"),
+            DidError::Resolve(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for DidError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EmbeddingLocation;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::script::PushBytesBuf;
+    use bitcoin::secp256k1::Secp256k1;
+    use bitcoin::{BlockHash, ScriptBuf, Txid};
+
+    fn keypair(n: u8) -> Keypair {
+        Keypair::from_seckey_slice(&Secp256k1::new(), &[n; 32]).unwrap()
+    }
+
+    fn embedding(n: u8, messages: Vec<Message>) -> Embedding {
+        let payload = PushBytesBuf::try_from(Message::encode(messages)).unwrap();
+        Embedding {
+            bytes: ScriptBuf::new_op_return(payload).into_bytes()[1..].to_vec(),
+            txid: Txid::from_byte_array([n; 32]),
+            location: EmbeddingLocation::OpReturn { output: 0 },
+        }
+    }
+
+    fn confirmed(embedding: Embedding, height: u64) -> ConfirmedEmbedding {
+        ConfirmedEmbedding {
+            embedding,
+            block_hash: BlockHash::all_zeros(),
+            height,
+            time: 0,
+            tx_index: 0,
+        }
+    }
+
+    #[test]
+    fn test_operations() {
+        let controller = keypair(1);
+        let create = DidOperation::Create {
+            controller: controller.x_only_public_key().0,
+            payload_hash: payload_hash(b"document v1"),
+        };
+        let created = embedding(1, create.sign(&controller));
+        let did = created.id();
+
+        let update = DidOperation::Update {
+            did,
+            payload_hash: payload_hash(b"document v2"),
+        };
+        let deactivate = DidOperation::Deactivate { did };
+        for operation in [&create, &update, &deactivate] {
+            assert_eq!(
+                DidOperation::from_message(&operation.to_message()),
+                Ok(Some(operation.clone()))
+            );
+        }
+
+        let mut state = DidState::create(&created).unwrap();
+        assert!(state.verify_document(b"document v1"));
+
+        // Only the controller can update
+        let forged = embedding(2, update.sign(&keypair(2)));
+        assert_eq!(state.apply(&forged), Err(DidError::Unauthorized));
+        let unsigned = embedding(2, vec![update.to_message()]);
+        assert_eq!(state.apply(&unsigned), Err(DidError::Unauthorized));
+
+        let updated = embedding(3, update.sign(&controller));
+        state.apply(&updated).unwrap();
+        assert!(state.verify_document(b"document v2"));
+        assert!(!state.verify_document(b"document v1"));
+
+        state
+            .apply(&embedding(4, deactivate.sign(&controller)))
+            .unwrap();
+        assert!(!state.verify_document(b"document v2"));
+        assert_eq!(
+            state.apply(&embedding(5, update.sign(&controller))),
+            Err(DidError::Deactivated)
+        );
+        assert_eq!(state.operations.len(), 3);
+
+        assert_eq!(
+            DidState::create(&updated),
+            Err(DidError::NotCreate(updated.id()))
+        );
+    }
+
+    #[test]
+    fn test_registry() {
+        let controller = keypair(1);
+        let created = embedding(
+            1,
+            DidOperation::Create {
+                controller: controller.x_only_public_key().0,
+                payload_hash: payload_hash(b"document v1"),
+            }
+            .sign(&controller),
+        );
+        let did = created.id();
+        let update = DidOperation::Update {
+            did,
+            payload_hash: payload_hash(b"document v2"),
+        };
+
+        let mut registry = DidRegistry::new();
+        registry.on_embedding_confirmed(&confirmed(created, 10));
+        registry.on_embedding_confirmed(&confirmed(embedding(2, update.sign(&keypair(2))), 11));
+        registry.on_embedding_confirmed(&confirmed(embedding(3, update.sign(&controller)), 12));
+
+        // The forged update is skipped
+        let state = registry.resolve(&did).unwrap();
+        assert_eq!(state.operations.len(), 2);
+        assert!(state.verify_document(b"document v2"));
+
+        registry.on_block_disconnected(12);
+        assert!(
+            registry
+                .resolve(&did)
+                .unwrap()
+                .verify_document(b"document v1")
+        );
+
+        registry.on_block_disconnected(10);
+        assert_eq!(registry.resolve(&did), None);
+    }
+}
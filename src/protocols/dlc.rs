@@ -0,0 +1,516 @@
+//! # DLC Oracle Messages
+//!
+//! Carries DLC oracle announcements and attestations, serialized as in the dlcspecs
+//! (`Messaging.md`, `Oracle.md`), in tagged messages. Each message body is the complete
+//! type-length-value record: a BigSize type, a BigSize length, and the value.
+//!
+//! Event descriptors are kept as undecoded TLV records. Signatures are not checked.
+
+// Based on dlcspecs Messaging.md (oracle_announcement, oracle_event, oracle_attestation)
+
+use crate::Embedding;
+use crate::message::{Message, tags};
+
+use bitcoin::secp256k1::{XOnlyPublicKey, schnorr};
+use std::fmt;
+
+/// The TLV type of an oracle announcement
+pub const ANNOUNCEMENT_TYPE: u64 = 55332;
+
+/// The TLV type of an oracle event
+pub const EVENT_TYPE: u64 = 55330;
+
+/// The TLV type of an oracle attestation
+pub const ATTESTATION_TYPE: u64 = 55400;
+
+/// An event an oracle will attest to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OracleEvent {
+    /// The nonces the oracle will sign outcomes with
+    pub nonces: Vec<XOnlyPublicKey>,
+    /// The time of the event, in seconds since the Unix epoch
+    pub maturity_epoch: u32,
+    /// The event descriptor TLV record, undecoded
+    pub descriptor: Vec<u8>,
+    /// The event id
+    pub event_id: String,
+}
+
+/// An oracle's signed announcement of an event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OracleAnnouncement {
+    /// The oracle's signature over the event
+    pub signature: schnorr::Signature,
+    /// The oracle's public key
+    pub oracle_public_key: XOnlyPublicKey,
+    /// The event
+    pub event: OracleEvent,
+}
+
+/// An oracle's signatures over the outcome of an event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OracleAttestation {
+    /// The event id
+    pub event_id: String,
+    /// The oracle's public key
+    pub oracle_public_key: XOnlyPublicKey,
+    /// The signatures, one per nonce
+    pub signatures: Vec<schnorr::Signature>,
+    /// The outcomes signed
+    pub outcomes: Vec<String>,
+}
+
+/// A DLC oracle message found in an embedding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OracleMessage {
+    /// An announcement
+    Announcement(OracleAnnouncement),
+    /// An attestation
+    Attestation(OracleAttestation),
+}
+
+/// Error types for decoding DLC oracle messages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DlcError {
+    /// The record ends before a required field
+    Truncated,
+    /// The record has bytes after its fields
+    TrailingBytes,
+    /// The record has a different TLV type
+    UnexpectedType(u64),
+    /// A BigSize is not minimally encoded
+    NonMinimal,
+    /// A public key or signature is invalid
+    InvalidKey,
+    /// A string is not UTF-8
+    InvalidString,
+}
+
+impl OracleEvent {
+    /// Returns the `oracle_event` TLV record
+    pub fn encode(&self) -> Vec<u8> {
+        let mut value = Vec::new();
+        value.extend((self.nonces.len() as u16).to_be_bytes());
+        for nonce in &self.nonces {
+            value.extend(nonce.serialize());
+        }
+        value.extend(self.maturity_epoch.to_be_bytes());
+        value.extend(&self.descriptor);
+        write_string(&self.event_id, &mut value);
+        tlv(EVENT_TYPE, &value)
+    }
+
+    /// Decodes an `oracle_event` TLV record
+    pub fn decode(bytes: &[u8]) -> Result<Self, DlcError> {
+        let mut reader = Reader::record(bytes, EVENT_TYPE)?;
+
+        let count = reader.u16()?;
+        let nonces = (0..count)
+            .map(|_| reader.x_only())
+            .collect::<Result<_, _>>()?;
+        let maturity_epoch = reader.u32()?;
+        let descriptor = reader.raw_record()?.to_vec();
+        let event_id = reader.string()?;
+        reader.finish()?;
+
+        Ok(Self {
+            nonces,
+            maturity_epoch,
+            descriptor,
+            event_id,
+        })
+    }
+}
+
+impl OracleAnnouncement {
+    /// Returns the `oracle_announcement` TLV record
+    pub fn encode(&self) -> Vec<u8> {
+        let mut value = self.signature.as_ref().to_vec();
+        value.extend(self.oracle_public_key.serialize());
+        value.extend(self.event.encode());
+        tlv(ANNOUNCEMENT_TYPE, &value)
+    }
+
+    /// Decodes an `oracle_announcement` TLV record
+    pub fn decode(bytes: &[u8]) -> Result<Self, DlcError> {
+        let mut reader = Reader::record(bytes, ANNOUNCEMENT_TYPE)?;
+
+        let signature = reader.signature()?;
+        let oracle_public_key = reader.x_only()?;
+        let event = OracleEvent::decode(reader.raw_record()?)?;
+        reader.finish()?;
+
+        Ok(Self {
+            signature,
+            oracle_public_key,
+            event,
+        })
+    }
+
+    /// Returns the announcement as a message tagged [`tags::DLC_ANNOUNCEMENT`]
+    pub fn to_message(&self) -> Message {
+        Message::new(tags::DLC_ANNOUNCEMENT, self.encode()).expect("valid tag and size")
+    }
+}
+
+impl OracleAttestation {
+    /// Returns the `oracle_attestation` TLV record
+    pub fn encode(&self) -> Vec<u8> {
+        let mut value = Vec::new();
+        write_string(&self.event_id, &mut value);
+        value.extend(self.oracle_public_key.serialize());
+        value.extend((self.signatures.len() as u16).to_be_bytes());
+        for signature in &self.signatures {
+            value.extend(signature.as_ref());
+        }
+        value.extend((self.outcomes.len() as u16).to_be_bytes());
+        for outcome in &self.outcomes {
+            write_string(outcome, &mut value);
+        }
+        tlv(ATTESTATION_TYPE, &value)
+    }
+
+    /// Decodes an `oracle_attestation` TLV record
+    pub fn decode(bytes: &[u8]) -> Result<Self, DlcError> {
+        let mut reader = Reader::record(bytes, ATTESTATION_TYPE)?;
+
+        let event_id = reader.string()?;
+        let oracle_public_key = reader.x_only()?;
+        let count = reader.u16()?;
+        let signatures = (0..count)
+            .map(|_| reader.signature())
+            .collect::<Result<_, _>>()?;
+        let count = reader.u16()?;
+        let outcomes = (0..count)
+            .map(|_| reader.string())
+            .collect::<Result<_, _>>()?;
+        reader.finish()?;
+
+        Ok(Self {
+            event_id,
+            oracle_public_key,
+            signatures,
+            outcomes,
+        })
+    }
+
+    /// Returns the attestation as a message tagged [`tags::DLC_ATTESTATION`]
+    pub fn to_message(&self) -> Message {
+        Message::new(tags::DLC_ATTESTATION, self.encode()).expect("valid tag and size")
+    }
+}
+
+impl OracleMessage {
+    /// Decodes a message tagged [`tags::DLC_ANNOUNCEMENT`] or [`tags::DLC_ATTESTATION`], or
+    /// returns `None` if the message has another tag
+    pub fn from_message(message: &Message) -> Result<Option<Self>, DlcError> {
+        match message.tag {
+            tags::DLC_ANNOUNCEMENT => OracleAnnouncement::decode(&message.body)
+                .map(|announcement| Some(OracleMessage::Announcement(announcement))),
+            tags::DLC_ATTESTATION => OracleAttestation::decode(&message.body)
+                .map(|attestation| Some(OracleMessage::Attestation(attestation))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the oracle messages in an embedding's payload, skipping malformed ones
+    pub fn from_embedding(embedding: &Embedding) -> Vec<Self> {
+        Message::decode(&embedding.payload())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|message| Self::from_message(message).ok().flatten())
+            .collect()
+    }
+
+    /// Returns the oracle's public key
+    pub fn oracle_public_key(&self) -> &XOnlyPublicKey {
+        match self {
+            OracleMessage::Announcement(announcement) => &announcement.oracle_public_key,
+            OracleMessage::Attestation(attestation) => &attestation.oracle_public_key,
+        }
+    }
+
+    /// Returns the event id
+    pub fn event_id(&self) -> &str {
+        match self {
+            OracleMessage::Announcement(announcement) => &announcement.event.event_id,
+            OracleMessage::Attestation(attestation) => &attestation.event_id,
+        }
+    }
+}
+
+/// Returns the first attestation by `oracle` of `event_id` among `embeddings`
+pub fn find_attestation<'a>(
+    embeddings: impl IntoIterator<Item = &'a Embedding>,
+    oracle: &XOnlyPublicKey,
+    event_id: &str,
+) -> Option<OracleAttestation> {
+    embeddings
+        .into_iter()
+        .flat_map(OracleMessage::from_embedding)
+        .find_map(|message| match message {
+            OracleMessage::Attestation(attestation)
+                if attestation.oracle_public_key == *oracle && attestation.event_id == event_id =>
+            {
+                Some(attestation)
+            }
+            _ => None,
+        })
+}
+
+fn tlv(tlv_type: u64, value: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(value.len() + 8);
+    write_bigsize(tlv_type, &mut bytes);
+    write_bigsize(value.len() as u64, &mut bytes);
+    bytes.extend(value);
+    bytes
+}
+
+/// Writes a BigSize: a big-endian CompactSize
+fn write_bigsize(n: u64, bytes: &mut Vec<u8>) {
+    match n {
+        0..=0xfc => bytes.push(n as u8),
+        0xfd..=0xffff => {
+            bytes.push(0xfd);
+            bytes.extend((n as u16).to_be_bytes());
+        }
+        0x10000..=0xffff_ffff => {
+            bytes.push(0xfe);
+            bytes.extend((n as u32).to_be_bytes());
+        }
+        _ => {
+            bytes.push(0xff);
+            bytes.extend(n.to_be_bytes());
+        }
+    }
+}
+
+fn write_string(s: &str, bytes: &mut Vec<u8>) {
+    write_bigsize(s.len() as u64, bytes);
+    bytes.extend(s.as_bytes());
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    /// Returns a reader over the value of a TLV record of `expected` type spanning `bytes`
+    fn record(bytes: &'a [u8], expected: u64) -> Result<Self, DlcError> {
+        let mut reader = Reader(bytes);
+        let tlv_type = reader.bigsize()?;
+        if tlv_type != expected {
+            return Err(DlcError::UnexpectedType(tlv_type));
+        }
+        let value = reader.bytes()?;
+        reader.finish()?;
+        Ok(Reader(value))
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], DlcError> {
+        let (bytes, rest) = self.0.split_first_chunk().ok_or(DlcError::Truncated)?;
+        self.0 = rest;
+        Ok(*bytes)
+    }
+
+    fn slice(&mut self, n: usize) -> Result<&'a [u8], DlcError> {
+        if n > self.0.len() {
+            return Err(DlcError::Truncated);
+        }
+        let (bytes, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, DlcError> {
+        self.take().map(u16::from_be_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, DlcError> {
+        self.take().map(u32::from_be_bytes)
+    }
+
+    fn bigsize(&mut self) -> Result<u64, DlcError> {
+        let [prefix] = self.take()?;
+        let (n, min) = match prefix {
+            0xfd => (self.take().map(u16::from_be_bytes)?.into(), 0xfd),
+            0xfe => (self.take().map(u32::from_be_bytes)?.into(), 0x10000),
+            0xff => (self.take().map(u64::from_be_bytes)?, 0x1_0000_0000),
+            n => return Ok(n.into()),
+        };
+        if n < min {
+            return Err(DlcError::NonMinimal);
+        }
+        Ok(n)
+    }
+
+    /// Reads BigSize-length-prefixed bytes
+    fn bytes(&mut self) -> Result<&'a [u8], DlcError> {
+        let length = usize::try_from(self.bigsize()?).map_err(|_| DlcError::Truncated)?;
+        self.slice(length)
+    }
+
+    fn string(&mut self) -> Result<String, DlcError> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| DlcError::InvalidString)
+    }
+
+    /// Reads a complete TLV record, including its type and length
+    fn raw_record(&mut self) -> Result<&'a [u8], DlcError> {
+        let start = self.0;
+        self.bigsize()?;
+        self.bytes()?;
+        Ok(&start[..start.len() - self.0.len()])
+    }
+
+    fn x_only(&mut self) -> Result<XOnlyPublicKey, DlcError> {
+        XOnlyPublicKey::from_slice(&self.take::<32>()?).map_err(|_| DlcError::InvalidKey)
+    }
+
+    fn signature(&mut self) -> Result<schnorr::Signature, DlcError> {
+        schnorr::Signature::from_slice(&self.take::<64>()?).map_err(|_| DlcError::InvalidKey)
+    }
+
+    fn finish(&self) -> Result<(), DlcError> {
+        match self.0.is_empty() {
+            true => Ok(()),
+            false => Err(DlcError::TrailingBytes),
+        }
+    }
+}
+
+impl fmt::Display for DlcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DlcError::Truncated => write!(f, "Truncated DLC record"),
+            DlcError::TrailingBytes => write!(f, "DLC record has trailing bytes"),
+            DlcError::UnexpectedType(tlv_type) => {
+                write!(f, "Unexpected DLC record type {tlv_type}")
+            }
+            DlcError::NonMinimal => write!(f, "BigSize is not minimally encoded"),
+            DlcError::InvalidKey => write!(f, "Invalid public key or signature"),
+            DlcError::InvalidString => write!(f, "DLC string is not UTF-8"),
+        }
+    }
+}
+
+impl std::error::Error for DlcError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EmbeddingLocation;
+
+    use bitcoin::ScriptBuf;
+    use bitcoin::hashes::Hash;
+    use bitcoin::script::PushBytesBuf;
+    use bitcoin::secp256k1::{Keypair, Secp256k1};
+
+    fn key(n: u8) -> XOnlyPublicKey {
+        Keypair::from_seckey_slice(&Secp256k1::new(), &[n; 32])
+            .unwrap()
+            .x_only_public_key()
+            .0
+    }
+
+    fn signature(n: u8) -> schnorr::Signature {
+        schnorr::Signature::from_slice(&[n; 64]).unwrap()
+    }
+
+    fn announcement() -> OracleAnnouncement {
+        // An enum_event_descriptor with the outcomes "yes" and "no"
+        let descriptor = tlv(55302, &[0, 2, 3, b'y', b'e', b's', 2, b'n', b'o']);
+        OracleAnnouncement {
+            signature: signature(1),
+            oracle_public_key: key(1),
+            event: OracleEvent {
+                nonces: vec![key(2)],
+                maturity_epoch: 1_700_000_000,
+                descriptor,
+                event_id: "election-2028".to_string(),
+            },
+        }
+    }
+
+    fn attestation() -> OracleAttestation {
+        OracleAttestation {
+            event_id: "election-2028".to_string(),
+            oracle_public_key: key(1),
+            signatures: vec![signature(3)],
+            outcomes: vec!["yes".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let announcement = announcement();
+        let bytes = announcement.encode();
+        // Type 55332 and the length as BigSizes
+        assert_eq!(bytes[..3], [0xfd, 0xd8, 0x24]);
+        assert_eq!(OracleAnnouncement::decode(&bytes), Ok(announcement.clone()));
+
+        let attestation = attestation();
+        let bytes = attestation.encode();
+        assert_eq!(bytes[..3], [0xfd, 0xd8, 0x68]);
+        assert_eq!(OracleAttestation::decode(&bytes), Ok(attestation.clone()));
+
+        assert_eq!(
+            OracleMessage::from_message(&announcement.to_message()),
+            Ok(Some(OracleMessage::Announcement(announcement)))
+        );
+        assert_eq!(OracleEvent::decode(&[]), Err(DlcError::Truncated));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let bytes = attestation().encode();
+        assert_eq!(
+            OracleAnnouncement::decode(&bytes),
+            Err(DlcError::UnexpectedType(ATTESTATION_TYPE))
+        );
+        assert_eq!(
+            OracleAttestation::decode(&bytes[..bytes.len() - 1]),
+            Err(DlcError::Truncated)
+        );
+        assert_eq!(
+            OracleAttestation::decode(&[&bytes[..], &[0]].concat()),
+            Err(DlcError::TrailingBytes)
+        );
+
+        // The type 55400 with a non-minimal BigSize
+        let mut non_minimal = vec![0xfe, 0, 0, 0xd8, 0x68];
+        non_minimal.extend(&bytes[3..]);
+        assert_eq!(
+            OracleAttestation::decode(&non_minimal),
+            Err(DlcError::NonMinimal)
+        );
+    }
+
+    #[test]
+    fn test_find_attestation() {
+        let payload = Message::encode(vec![
+            Message::new(tags::CONTENT_TYPE, b"text/plain".to_vec()).unwrap(),
+            announcement().to_message(),
+            attestation().to_message(),
+        ]);
+        let embedding = Embedding {
+            bytes: ScriptBuf::new_op_return(PushBytesBuf::try_from(payload).unwrap()).into_bytes()
+                [1..]
+                .to_vec(),
+            txid: bitcoin::Txid::all_zeros(),
+            location: EmbeddingLocation::OpReturn { output: 0 },
+        };
+
+        let messages = OracleMessage::from_embedding(&embedding);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].event_id(), "election-2028");
+        assert_eq!(messages[1].oracle_public_key(), &key(1));
+
+        assert_eq!(
+            find_attestation([&embedding], &key(1), "election-2028"),
+            Some(attestation())
+        );
+        assert_eq!(
+            find_attestation([&embedding], &key(2), "election-2028"),
+            None
+        );
+        assert_eq!(find_attestation([&embedding], &key(1), "other"), None);
+    }
+}
@@ -0,0 +1,443 @@
+//! # Inscriptions
+//!
+//! Decodes ordinals inscriptions from tapscript witness envelopes in the field/body layout:
+//! `OP_FALSE OP_IF "ord" (<tag> <value>)* [OP_0 <body>*] OP_ENDIF`. Tags are single-byte
+//! pushes; unrecognized even tags make an inscription unbound, as in `ord`.
+
+// Based on ordinals/inscriptions/inscription.rs
+
+use crate::envelope::FieldEnvelope;
+use crate::{Embedding, EmbeddingLocation, ScriptType};
+
+use bitcoin::blockdata::constants::MAX_SCRIPT_ELEMENT_SIZE;
+use bitcoin::hashes::Hash;
+use bitcoin::opcodes::{OP_FALSE, all::OP_ENDIF, all::OP_IF};
+use bitcoin::script::{Builder, PushBytesBuf};
+use bitcoin::{Transaction, Txid};
+use std::fmt;
+use std::str::FromStr;
+
+/// The protocol identifier that opens an inscription envelope
+pub const PROTOCOL_ID: &[u8] = b"ord";
+
+/// Inscription field tags
+pub mod tags {
+    /// MIME type of the body
+    pub const CONTENT_TYPE: u8 = 1;
+    /// Sat offset in the reveal transaction's inputs at which to inscribe
+    pub const POINTER: u8 = 2;
+    /// Parent inscription id; may repeat
+    pub const PARENT: u8 = 3;
+    /// CBOR metadata; may be split across repeated fields
+    pub const METADATA: u8 = 5;
+    /// Metaprotocol identifier
+    pub const METAPROTOCOL: u8 = 7;
+    /// Content encoding of the body (e.g. `br`)
+    pub const CONTENT_ENCODING: u8 = 9;
+    /// Inscription whose content this inscription uses
+    pub const DELEGATE: u8 = 11;
+    /// Rune to be etched with this inscription
+    pub const RUNE: u8 = 13;
+}
+
+/// The id of an inscription: the reveal txid and the position of the envelope among the
+/// transaction's inscriptions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InscriptionId {
+    /// The reveal transaction
+    pub txid: Txid,
+    /// The index of the inscription in the transaction
+    pub index: u32,
+}
+
+impl InscriptionId {
+    /// Returns the field encoding: the txid in internal byte order followed by the index as a
+    /// little-endian integer without trailing zero bytes
+    pub fn to_value(&self) -> Vec<u8> {
+        let mut value = self.txid.to_byte_array().to_vec();
+        let index = self.index.to_le_bytes();
+        let len = index
+            .iter()
+            .rposition(|byte| *byte != 0)
+            .map_or(0, |i| i + 1);
+        value.extend(&index[..len]);
+        value
+    }
+
+    /// Parses the field encoding, rejecting trailing zero bytes
+    pub fn from_value(value: &[u8]) -> Option<Self> {
+        if value.len() < Txid::LEN || value.len() > Txid::LEN + 4 {
+            return None;
+        }
+
+        let (txid, index) = value.split_at(Txid::LEN);
+        if index.last() == Some(&0) {
+            return None;
+        }
+
+        let mut bytes = [0; 4];
+        bytes[..index.len()].copy_from_slice(index);
+
+        Some(Self {
+            txid: Txid::from_slice(txid).ok()?,
+            index: u32::from_le_bytes(bytes),
+        })
+    }
+}
+
+/// A decoded inscription
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Inscription {
+    /// The content, if the envelope has a body separator
+    pub body: Option<Vec<u8>>,
+    /// The content encoding of the body
+    pub content_encoding: Option<Vec<u8>>,
+    /// The MIME type of the body
+    pub content_type: Option<Vec<u8>>,
+    /// The inscription whose content this inscription uses
+    pub delegate: Option<InscriptionId>,
+    /// CBOR metadata, concatenated from every metadata field
+    pub metadata: Option<Vec<u8>>,
+    /// The metaprotocol identifier
+    pub metaprotocol: Option<Vec<u8>>,
+    /// Parent inscriptions, in order
+    pub parents: Vec<InscriptionId>,
+    /// The sat offset at which to inscribe
+    pub pointer: Option<u64>,
+    /// The rune to be etched, as a little-endian integer
+    pub rune: Option<Vec<u8>>,
+    /// A non-repeatable field appears more than once
+    pub duplicate_field: bool,
+    /// The final tag is missing its value
+    pub incomplete_field: bool,
+    /// The envelope contains an unrecognized even tag
+    pub unrecognized_even_field: bool,
+}
+
+impl Inscription {
+    /// Decodes an inscription from a tapscript envelope embedding. Returns `None` for other
+    /// embeddings and for envelopes with a different protocol identifier.
+    pub fn from_embedding(embedding: &Embedding) -> Option<Self> {
+        let EmbeddingLocation::WitnessEnvelope {
+            script_type: ScriptType::Tapscript,
+            ..
+        } = embedding.location
+        else {
+            return None;
+        };
+
+        Self::from_fields(&embedding.fields()?)
+    }
+
+    /// Decodes every inscription in a transaction, numbering them in input and envelope order
+    pub fn from_transaction(tx: &Transaction) -> Vec<(InscriptionId, Self)> {
+        let txid = tx.compute_txid();
+
+        Embedding::from_transaction(tx)
+            .iter()
+            .filter_map(Self::from_embedding)
+            .zip(0..)
+            .map(|(inscription, index)| (InscriptionId { txid, index }, inscription))
+            .collect()
+    }
+
+    /// Decodes an inscription from an envelope parsed with the field/body layout
+    pub fn from_fields(envelope: &FieldEnvelope) -> Option<Self> {
+        if envelope.protocol != PROTOCOL_ID {
+            return None;
+        }
+
+        let mut inscription = Self {
+            body: envelope.body.clone(),
+            incomplete_field: envelope.incomplete_field,
+            ..Default::default()
+        };
+
+        let mut seen = Vec::new();
+        for (tag, value) in &envelope.fields {
+            let repeatable = matches!(tag[..], [tags::PARENT] | [tags::METADATA]);
+            if !repeatable && seen.contains(tag) {
+                inscription.duplicate_field = true;
+                continue;
+            }
+            seen.push(tag.clone());
+
+            match tag[..] {
+                [tags::CONTENT_TYPE] => inscription.content_type = Some(value.clone()),
+                [tags::POINTER] => inscription.pointer = pointer_from_value(value),
+                [tags::PARENT] => inscription.parents.extend(InscriptionId::from_value(value)),
+                [tags::METADATA] => inscription
+                    .metadata
+                    .get_or_insert_with(Vec::new)
+                    .extend(value),
+                [tags::METAPROTOCOL] => inscription.metaprotocol = Some(value.clone()),
+                [tags::CONTENT_ENCODING] => inscription.content_encoding = Some(value.clone()),
+                [tags::DELEGATE] => inscription.delegate = InscriptionId::from_value(value),
+                [tags::RUNE] => inscription.rune = Some(value.clone()),
+                _ if tag.first().is_some_and(|lsb| lsb % 2 == 0) => {
+                    inscription.unrecognized_even_field = true;
+                }
+                _ => {}
+            }
+        }
+
+        Some(inscription)
+    }
+
+    /// Returns the content type as a string, if it is valid UTF-8
+    pub fn content_type_str(&self) -> Option<&str> {
+        std::str::from_utf8(self.content_type.as_deref()?).ok()
+    }
+
+    /// Appends the inscription envelope to a script, splitting the body and metadata into
+    /// pushes of at most `MAX_SCRIPT_ELEMENT_SIZE` bytes
+    pub fn append_to_builder(&self, builder: Builder) -> Builder {
+        let push = |builder: Builder, bytes: &[u8]| {
+            builder.push_slice(PushBytesBuf::try_from(bytes.to_vec()).expect("push within limit"))
+        };
+        let field = |builder: Builder, tag: u8, value: &[u8]| {
+            if value.is_empty() {
+                return push(push(builder, &[tag]), value);
+            }
+            value
+                .chunks(MAX_SCRIPT_ELEMENT_SIZE)
+                .fold(builder, |builder, chunk| push(push(builder, &[tag]), chunk))
+        };
+
+        let mut builder = push(
+            builder.push_opcode(OP_FALSE).push_opcode(OP_IF),
+            PROTOCOL_ID,
+        );
+
+        if let Some(content_type) = &self.content_type {
+            builder = field(builder, tags::CONTENT_TYPE, content_type);
+        }
+        if let Some(pointer) = self.pointer {
+            builder = field(builder, tags::POINTER, &pointer_to_value(pointer));
+        }
+        for parent in &self.parents {
+            builder = field(builder, tags::PARENT, &parent.to_value());
+        }
+        if let Some(metadata) = &self.metadata {
+            builder = field(builder, tags::METADATA, metadata);
+        }
+        if let Some(metaprotocol) = &self.metaprotocol {
+            builder = field(builder, tags::METAPROTOCOL, metaprotocol);
+        }
+        if let Some(content_encoding) = &self.content_encoding {
+            builder = field(builder, tags::CONTENT_ENCODING, content_encoding);
+        }
+        if let Some(delegate) = &self.delegate {
+            builder = field(builder, tags::DELEGATE, &delegate.to_value());
+        }
+        if let Some(rune) = &self.rune {
+            builder = field(builder, tags::RUNE, rune);
+        }
+
+        if let Some(body) = &self.body {
+            builder = push(builder, &[]);
+            for chunk in body.chunks(MAX_SCRIPT_ELEMENT_SIZE) {
+                builder = push(builder, chunk);
+            }
+        }
+
+        builder.push_opcode(OP_ENDIF)
+    }
+}
+
+/// Parses a little-endian pointer, ignoring trailing zero bytes beyond the eighth
+fn pointer_from_value(value: &[u8]) -> Option<u64> {
+    if value.iter().skip(8).any(|byte| *byte != 0) {
+        return None;
+    }
+
+    let mut bytes = [0; 8];
+    let len = value.len().min(8);
+    bytes[..len].copy_from_slice(&value[..len]);
+    Some(u64::from_le_bytes(bytes))
+}
+
+fn pointer_to_value(pointer: u64) -> Vec<u8> {
+    let bytes = pointer.to_le_bytes();
+    let len = bytes
+        .iter()
+        .rposition(|byte| *byte != 0)
+        .map_or(0, |i| i + 1);
+    bytes[..len].to_vec()
+}
+
+impl fmt::Display for InscriptionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}i{}", self.txid, self.index)
+    }
+}
+
+impl FromStr for InscriptionId {
+    type Err = InscriptionIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (txid, index) = s.split_once('i').ok_or(InscriptionIdError)?;
+
+        Ok(Self {
+            txid: txid.parse().map_err(|_| InscriptionIdError)?,
+            index: index.parse().map_err(|_| InscriptionIdError)?,
+        })
+    }
+}
+
+/// An inscription id is not `<txid>i<index>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InscriptionIdError;
+
+impl fmt::Display for InscriptionIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid inscription id")
+    }
+}
+
+impl std::error::Error for InscriptionIdError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::key::{Secp256k1, UntweakedPublicKey};
+    use bitcoin::taproot::{LeafVersion, TaprootBuilder};
+    use bitcoin::{
+        Amount, OutPoint, ScriptBuf, Sequence, TxIn, TxOut, Witness, absolute::LockTime,
+        transaction::Version,
+    };
+
+    fn reveal_tx(scripts: Vec<ScriptBuf>) -> Transaction {
+        let secp = Secp256k1::verification_only();
+        let key = UntweakedPublicKey::from_slice(&[2; 32]).unwrap();
+
+        let input = scripts
+            .into_iter()
+            .map(|script| {
+                let spend_info = TaprootBuilder::new()
+                    .add_leaf(0, script.clone())
+                    .unwrap()
+                    .finalize(&secp, key)
+                    .unwrap();
+                let control_block = spend_info
+                    .control_block(&(script.clone(), LeafVersion::TapScript))
+                    .unwrap();
+
+                TxIn {
+                    previous_output: OutPoint::null(),
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::from_slice(&[
+                        vec![1; 64],
+                        script.into_bytes(),
+                        control_block.serialize(),
+                    ]),
+                }
+            })
+            .collect();
+
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input,
+            output: vec![TxOut {
+                value: Amount::from_sat(546),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let parent = InscriptionId {
+            txid: Txid::from_byte_array([7; 32]),
+            index: 256,
+        };
+
+        let inscription = Inscription {
+            body: Some(vec![0xab; 1200]),
+            content_encoding: Some(b"br".to_vec()),
+            content_type: Some(b"text/plain;charset=utf-8".to_vec()),
+            delegate: Some(InscriptionId {
+                txid: Txid::from_byte_array([9; 32]),
+                index: 0,
+            }),
+            metadata: Some(vec![0xa0; 600]),
+            metaprotocol: Some(b"brc-20".to_vec()),
+            parents: vec![parent, InscriptionId { index: 1, ..parent }],
+            pointer: Some(1 << 40),
+            rune: Some(vec![1, 2, 3]),
+            ..Default::default()
+        };
+        let plain = Inscription {
+            content_type: Some(Vec::new()),
+            body: Some(Vec::new()),
+            ..Default::default()
+        };
+
+        let tx = reveal_tx(vec![
+            inscription.append_to_builder(Builder::new()).into_script(),
+            plain.append_to_builder(Builder::new()).into_script(),
+        ]);
+        let txid = tx.compute_txid();
+
+        assert_eq!(
+            Inscription::from_transaction(&tx),
+            vec![
+                (InscriptionId { txid, index: 0 }, inscription.clone()),
+                (InscriptionId { txid, index: 1 }, plain),
+            ]
+        );
+        assert_eq!(
+            inscription.content_type_str(),
+            Some("text/plain;charset=utf-8")
+        );
+    }
+
+    #[test]
+    fn test_field_flags() {
+        let parse = |fields: &[&[u8]]| {
+            let mut pushes = vec![PROTOCOL_ID];
+            pushes.extend(fields);
+            Inscription::from_fields(&FieldEnvelope::from_pushes(&pushes).unwrap()).unwrap()
+        };
+
+        let inscription = parse(&[&[1], b"a", &[1], b"b", &[2], &[1, 0, 0, 0, 0, 0, 0, 0, 0]]);
+        assert_eq!(inscription.content_type, Some(b"a".to_vec()));
+        assert_eq!(inscription.pointer, Some(1));
+        assert!(inscription.duplicate_field);
+        assert!(!inscription.unrecognized_even_field);
+
+        assert!(parse(&[&[4], b"x"]).unrecognized_even_field);
+        assert!(!parse(&[&[15], b"x"]).unrecognized_even_field);
+        assert!(parse(&[&[1]]).incomplete_field);
+        assert_eq!(parse(&[&[2], &[0, 0, 0, 0, 0, 0, 0, 0, 1]]).pointer, None);
+
+        let body = parse(&[&[], b"hello", b" world"]);
+        assert_eq!(body.body, Some(b"hello world".to_vec()));
+
+        let other = FieldEnvelope::from_pushes(&[b"xyz"]).unwrap();
+        assert_eq!(Inscription::from_fields(&other), None);
+    }
+
+    #[test]
+    fn test_inscription_id() {
+        let id = InscriptionId {
+            txid: Txid::from_byte_array([1; 32]),
+            index: 0x0100,
+        };
+
+        let value = id.to_value();
+        assert_eq!(value.len(), 34);
+        assert_eq!(InscriptionId::from_value(&value), Some(id));
+
+        let mut trailing = value.clone();
+        trailing.push(0);
+        assert_eq!(InscriptionId::from_value(&trailing), None);
+        assert_eq!(InscriptionId::from_value(&value[..31]), None);
+
+        assert_eq!(id.to_string().parse(), Ok(id));
+        assert_eq!("nope".parse::<InscriptionId>(), Err(InscriptionIdError));
+    }
+}
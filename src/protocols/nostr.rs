@@ -0,0 +1,236 @@
+//! # Nostr Anchors
+//!
+//! Anchors Nostr events in embeddings: a single event id in a [`tags::NOSTR_EVENT`] message,
+//! or the root of a [`MerkleTree`] over many event ids in a [`tags::NOSTR_ROOT`] message, with
+//! a [`MerkleProof`] for each event. Relays publishing checkpoints hand out the proofs, and
+//! clients check an event against the anchor by recomputing its NIP-01 id.
+
+// Based on NIP-01 (event serialization and id)
+
+use crate::Embedding;
+use crate::merkle::{MerkleProof, MerkleTree};
+use crate::message::{Message, tags};
+
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::hex::DisplayHex;
+use bitcoin::secp256k1::{self, Secp256k1, XOnlyPublicKey, schnorr};
+use std::fmt::Write;
+
+/// A Nostr event, without its id and signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// The author's public key
+    pub pubkey: XOnlyPublicKey,
+    /// The creation time, in seconds since the Unix epoch
+    pub created_at: u64,
+    /// The event kind
+    pub kind: u32,
+    /// The tags, each a list of strings
+    pub tags: Vec<Vec<String>>,
+    /// The content
+    pub content: String,
+}
+
+/// An anchor of Nostr events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    /// The id of one event
+    Event([u8; 32]),
+    /// The root of a Merkle tree over event ids
+    Root([u8; 32]),
+}
+
+impl Event {
+    /// Returns the NIP-01 serialization `[0,<pubkey>,<created_at>,<kind>,<tags>,<content>]`
+    pub fn serialize(&self) -> String {
+        let mut json = format!(
+            "[0,\"{}\",{},{},[",
+            self.pubkey.serialize().to_lower_hex_string(),
+            self.created_at,
+            self.kind
+        );
+        for (i, tag) in self.tags.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            json.push('[');
+            for (j, value) in tag.iter().enumerate() {
+                if j > 0 {
+                    json.push(',');
+                }
+                write_string(value, &mut json);
+            }
+            json.push(']');
+        }
+        json.push_str("],");
+        write_string(&self.content, &mut json);
+        json.push(']');
+        json
+    }
+
+    /// Returns the event id: the SHA-256 of the serialization
+    pub fn id(&self) -> [u8; 32] {
+        sha256::Hash::hash(self.serialize().as_bytes()).to_byte_array()
+    }
+
+    /// Returns whether `signature` is the author's BIP-340 signature over the event id
+    pub fn verify_signature(&self, signature: &schnorr::Signature) -> bool {
+        let msg = secp256k1::Message::from_digest(self.id());
+        Secp256k1::verification_only()
+            .verify_schnorr(signature, &msg, &self.pubkey)
+            .is_ok()
+    }
+}
+
+impl Anchor {
+    /// Returns an anchor of one or more event ids and, for more than one, the tree holding
+    /// the proof of each. Returns `None` if there are no ids.
+    pub fn new(ids: &[[u8; 32]]) -> Option<(Self, Option<MerkleTree>)> {
+        match ids {
+            [] => None,
+            [id] => Some((Anchor::Event(*id), None)),
+            ids => {
+                let tree = MerkleTree::new(ids)?;
+                Some((Anchor::Root(tree.root()), Some(tree)))
+            }
+        }
+    }
+
+    /// Returns the anchor as a [`tags::NOSTR_EVENT`] or [`tags::NOSTR_ROOT`] message
+    pub fn to_message(&self) -> Message {
+        let (tag, hash) = match self {
+            Anchor::Event(id) => (tags::NOSTR_EVENT, id),
+            Anchor::Root(root) => (tags::NOSTR_ROOT, root),
+        };
+        Message::new(tag, hash.to_vec()).expect("valid tag and size")
+    }
+
+    /// Decodes an anchor message. Returns `None` for other tags and malformed bodies.
+    pub fn from_message(message: &Message) -> Option<Self> {
+        let hash = message.body.as_slice().try_into().ok()?;
+        match message.tag {
+            tags::NOSTR_EVENT => Some(Anchor::Event(hash)),
+            tags::NOSTR_ROOT => Some(Anchor::Root(hash)),
+            _ => None,
+        }
+    }
+
+    /// Returns the anchors in an embedding's payload
+    pub fn from_embedding(embedding: &Embedding) -> Vec<Self> {
+        Message::decode(&embedding.payload())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(Self::from_message)
+            .collect()
+    }
+
+    /// Returns whether `event` is anchored: its id is the anchored id, or is included under the
+    /// anchored root by `proof`
+    pub fn verify(&self, event: &Event, proof: Option<&MerkleProof>) -> bool {
+        let id = event.id();
+        match (self, proof) {
+            (Anchor::Event(anchored), None) => *anchored == id,
+            (Anchor::Root(root), Some(proof)) => proof.verify(root, &id),
+            _ => false,
+        }
+    }
+}
+
+/// Writes a JSON string, escaping the characters NIP-01 requires and any other control
+/// characters
+fn write_string(s: &str, json: &mut String) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '\n' => json.push_str("\\n"),
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            '\u{8}' => json.push_str("\\b"),
+            '\u{c}' => json.push_str("\\f"),
+            c if (c as u32) < 0x20 => {
+                write!(json, "\\u{:04x}", c as u32).expect("writing to a string")
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EmbeddingLocation;
+
+    use bitcoin::Txid;
+    use bitcoin::secp256k1::Keypair;
+
+    fn keypair() -> Keypair {
+        Keypair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap()
+    }
+
+    fn event(content: &str) -> Event {
+        Event {
+            pubkey: keypair().x_only_public_key().0,
+            created_at: 1_700_000_000,
+            kind: 1,
+            tags: vec![vec!["t".to_string(), "bitcoin".to_string()]],
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_serialize() {
+        let event = event("line\n\"quoted\" \\ tab\t é");
+        let pubkey = event.pubkey.serialize().to_lower_hex_string();
+        assert_eq!(
+            event.serialize(),
+            format!(
+                "[0,\"{pubkey}\",1700000000,1,[[\"t\",\"bitcoin\"]],\"line\\n\\\"quoted\\\" \\\\ tab\\t é\"]"
+            )
+        );
+        assert_eq!(
+            event.id(),
+            sha256::Hash::hash(event.serialize().as_bytes()).to_byte_array()
+        );
+
+        let signature = Secp256k1::new()
+            .sign_schnorr_no_aux_rand(&secp256k1::Message::from_digest(event.id()), &keypair());
+        assert!(event.verify_signature(&signature));
+        assert!(!self::event("other").verify_signature(&signature));
+    }
+
+    #[test]
+    fn test_anchor() {
+        let events = ["gm", "gn", "checkpoint"].map(event);
+        let ids = events.iter().map(Event::id).collect::<Vec<_>>();
+
+        assert_eq!(Anchor::new(&[]), None);
+        let (single, tree) = Anchor::new(&ids[..1]).unwrap();
+        assert_eq!(single, Anchor::Event(ids[0]));
+        assert!(tree.is_none());
+        assert!(single.verify(&events[0], None));
+        assert!(!single.verify(&events[1], None));
+
+        let (anchor, tree) = Anchor::new(&ids).unwrap();
+        let tree = tree.unwrap();
+        for (i, event) in events.iter().enumerate() {
+            assert!(anchor.verify(event, Some(&tree.proof(i).unwrap())));
+        }
+        assert!(!anchor.verify(&events[0], Some(&tree.proof(1).unwrap())));
+        assert!(!anchor.verify(&events[0], None));
+
+        let payload = Message::encode(vec![single.to_message(), anchor.to_message()]);
+        let embedding = Embedding {
+            bytes: payload,
+            txid: Txid::from_byte_array([1; 32]),
+            location: EmbeddingLocation::TaprootAnnex {
+                input: 0,
+                index: 0,
+                tag: 0,
+            },
+        };
+        assert_eq!(Anchor::from_embedding(&embedding), vec![single, anchor]);
+    }
+}
@@ -0,0 +1,303 @@
+//! # Omni Layer
+//!
+//! Decodes Omni Layer class C transactions: an `OP_RETURN` output whose data starts with the
+//! `omni` marker, followed by a big-endian version and transaction type and the
+//! type-specific fields. The sender is the first input and the reference output is the last
+//! non-`OP_RETURN` output; both are left to the caller.
+
+use crate::{Embedding, EmbeddingLocation};
+
+use bitcoin::ScriptBuf;
+use bitcoin::script::{Instruction, PushBytesBuf, Script};
+use std::fmt;
+
+/// The marker that opens an Omni payload
+pub const MARKER: &[u8] = b"omni";
+
+/// Omni transaction types decoded by this module
+pub mod types {
+    /// Transfers an amount of a property to the reference output
+    pub const SIMPLE_SEND: u16 = 0;
+    /// Distributes an amount of a property to all holders of another
+    pub const SEND_TO_OWNERS: u16 = 3;
+    /// Transfers every property of an ecosystem to the reference output
+    pub const SEND_ALL: u16 = 4;
+}
+
+/// An Omni Layer payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OmniTransaction {
+    /// The transaction version
+    pub version: u16,
+    /// The type-specific fields
+    pub payload: Payload,
+}
+
+/// The type-specific fields of an Omni transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Payload {
+    /// Type 0
+    SimpleSend {
+        /// The property id (31 is Tether USD)
+        property: u32,
+        /// The amount in the property's smallest unit
+        amount: u64,
+    },
+    /// Type 3
+    SendToOwners {
+        /// The property to distribute
+        property: u32,
+        /// The amount in the property's smallest unit
+        amount: u64,
+        /// The property whose holders receive the distribution (version 1 and later)
+        distribution_property: Option<u32>,
+    },
+    /// Type 4
+    SendAll {
+        /// The ecosystem (1 for main, 2 for test)
+        ecosystem: u8,
+    },
+    /// Any other type, with its undecoded fields
+    Other {
+        /// The transaction type
+        tx_type: u16,
+        /// The fields following the type
+        data: Vec<u8>,
+    },
+}
+
+/// Error types for decoding Omni payloads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OmniError {
+    /// The data does not start with the `omni` marker
+    MissingMarker,
+    /// The payload ends before a required field
+    Truncated,
+}
+
+impl OmniTransaction {
+    /// Decodes an `OP_RETURN` embedding. Returns `None` for other embeddings, for data without
+    /// the marker, and for malformed payloads.
+    pub fn from_embedding(embedding: &Embedding) -> Option<Self> {
+        let EmbeddingLocation::OpReturn { .. } = embedding.location else {
+            return None;
+        };
+
+        let mut data = Vec::new();
+        for instruction in Script::from_bytes(&embedding.bytes).instructions() {
+            match instruction.ok()? {
+                Instruction::PushBytes(push) => data.extend_from_slice(push.as_bytes()),
+                Instruction::Op(_) => return None,
+            }
+        }
+
+        Self::decode(&data).ok()
+    }
+
+    /// Decodes marker-prefixed data
+    pub fn decode(data: &[u8]) -> Result<Self, OmniError> {
+        let mut reader = Reader(data.strip_prefix(MARKER).ok_or(OmniError::MissingMarker)?);
+
+        let version = reader.u16()?;
+        let tx_type = reader.u16()?;
+
+        let payload = match tx_type {
+            types::SIMPLE_SEND => Payload::SimpleSend {
+                property: reader.u32()?,
+                amount: reader.u64()?,
+            },
+            types::SEND_TO_OWNERS => Payload::SendToOwners {
+                property: reader.u32()?,
+                amount: reader.u64()?,
+                distribution_property: match version {
+                    0 => None,
+                    _ => Some(reader.u32()?),
+                },
+            },
+            types::SEND_ALL => Payload::SendAll {
+                ecosystem: reader.take::<1>()?[0],
+            },
+            tx_type => Payload::Other {
+                tx_type,
+                data: reader.0.to_vec(),
+            },
+        };
+
+        Ok(Self { version, payload })
+    }
+
+    /// Returns the marker-prefixed data
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = MARKER.to_vec();
+        data.extend(self.version.to_be_bytes());
+        data.extend(self.tx_type().to_be_bytes());
+
+        match &self.payload {
+            Payload::SimpleSend { property, amount } => {
+                data.extend(property.to_be_bytes());
+                data.extend(amount.to_be_bytes());
+            }
+            Payload::SendToOwners {
+                property,
+                amount,
+                distribution_property,
+            } => {
+                data.extend(property.to_be_bytes());
+                data.extend(amount.to_be_bytes());
+                if let Some(distribution_property) = distribution_property {
+                    data.extend(distribution_property.to_be_bytes());
+                }
+            }
+            Payload::SendAll { ecosystem } => data.push(*ecosystem),
+            Payload::Other { data: fields, .. } => data.extend(fields),
+        }
+
+        data
+    }
+
+    /// Returns the `OP_RETURN` script carrying this payload
+    pub fn to_script(&self) -> ScriptBuf {
+        let data = PushBytesBuf::try_from(self.encode()).expect("payload within push limit");
+        ScriptBuf::new_op_return(data)
+    }
+
+    /// Returns the transaction type
+    pub fn tx_type(&self) -> u16 {
+        match &self.payload {
+            Payload::SimpleSend { .. } => types::SIMPLE_SEND,
+            Payload::SendToOwners { .. } => types::SEND_TO_OWNERS,
+            Payload::SendAll { .. } => types::SEND_ALL,
+            Payload::Other { tx_type, .. } => *tx_type,
+        }
+    }
+}
+
+/// Reads big-endian fields from the front of a payload
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], OmniError> {
+        let (bytes, rest) = self.0.split_first_chunk().ok_or(OmniError::Truncated)?;
+        self.0 = rest;
+        Ok(*bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, OmniError> {
+        self.take().map(u16::from_be_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, OmniError> {
+        self.take().map(u32::from_be_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, OmniError> {
+        self.take().map(u64::from_be_bytes)
+    }
+}
+
+impl fmt::Display for OmniError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OmniError::MissingMarker => write!(f, "Missing omni marker"),
+            OmniError::Truncated => write!(f, "Truncated omni payload"),
+        }
+    }
+}
+
+impl std::error::Error for OmniError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::hex::FromHex;
+    use bitcoin::{Amount, Transaction, TxOut, absolute::LockTime, transaction::Version};
+
+    #[test]
+    fn test_simple_send() {
+        // A Tether USD simple send of 1 USDT
+        let data = Vec::from_hex("6f6d6e69000000000000001f0000000005f5e100").unwrap();
+        let omni = OmniTransaction::decode(&data).unwrap();
+
+        assert_eq!(
+            omni,
+            OmniTransaction {
+                version: 0,
+                payload: Payload::SimpleSend {
+                    property: 31,
+                    amount: 100_000_000,
+                },
+            }
+        );
+        assert_eq!(omni.encode(), data);
+
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![
+                TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey: ScriptBuf::new_op_return([1, 2, 3]),
+                },
+                TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey: omni.to_script(),
+                },
+            ],
+        };
+
+        let decoded = Embedding::from_transaction(&tx)
+            .iter()
+            .filter_map(OmniTransaction::from_embedding)
+            .collect::<Vec<_>>();
+        assert_eq!(decoded, vec![omni]);
+    }
+
+    #[test]
+    fn test_payload_types() {
+        for omni in [
+            OmniTransaction {
+                version: 0,
+                payload: Payload::SendToOwners {
+                    property: 3,
+                    amount: 5,
+                    distribution_property: None,
+                },
+            },
+            OmniTransaction {
+                version: 1,
+                payload: Payload::SendToOwners {
+                    property: 3,
+                    amount: 5,
+                    distribution_property: Some(31),
+                },
+            },
+            OmniTransaction {
+                version: 0,
+                payload: Payload::SendAll { ecosystem: 1 },
+            },
+            OmniTransaction {
+                version: 0,
+                payload: Payload::Other {
+                    tx_type: 50,
+                    data: vec![1, 2, 3],
+                },
+            },
+        ] {
+            assert_eq!(OmniTransaction::decode(&omni.encode()), Ok(omni));
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(
+            OmniTransaction::decode(b"omn"),
+            Err(OmniError::MissingMarker)
+        );
+        assert_eq!(
+            OmniTransaction::decode(b"omni\x00\x00\x00\x00\x00"),
+            Err(OmniError::Truncated)
+        );
+    }
+}
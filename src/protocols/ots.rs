@@ -0,0 +1,317 @@
+//! # OpenTimestamps
+//!
+//! Encodes and decodes OpenTimestamps attestations, and recognizes the anchors that calendar
+//! servers publish: an `OP_RETURN` output with a single 32-byte push of the commitment. A
+//! Bitcoin attestation names the block whose merkle root is the final commitment of a
+//! timestamp; a pending attestation names the calendar that will publish it.
+
+// Based on python-opentimestamps/opentimestamps/core/notary.py
+
+use crate::{Embedding, EmbeddingLocation, varint};
+
+use bitcoin::block::Header;
+use bitcoin::hashes::Hash;
+use bitcoin::script::{Instruction, Script};
+use bitcoin::{ScriptBuf, Transaction};
+use std::fmt;
+
+/// The tag of a Bitcoin block header attestation
+pub const BITCOIN_TAG: [u8; 8] = [0x05, 0x88, 0x96, 0x0d, 0x73, 0xd7, 0x19, 0x01];
+
+/// The tag of a pending attestation
+pub const PENDING_TAG: [u8; 8] = [0x83, 0xdf, 0xe3, 0x0d, 0x2e, 0xf9, 0x0c, 0x8e];
+
+/// The maximum length of an attestation payload
+pub const MAX_PAYLOAD_SIZE: usize = 8192;
+
+/// The maximum length of a pending attestation's calendar URI
+pub const MAX_URI_LENGTH: usize = 1000;
+
+/// An OpenTimestamps attestation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Attestation {
+    /// The commitment is the merkle root of the block at this height
+    Bitcoin {
+        /// The block height
+        height: u64,
+    },
+    /// The commitment was submitted to a calendar and awaits confirmation
+    Pending {
+        /// The calendar URI
+        uri: String,
+    },
+    /// An attestation of another kind, with its undecoded payload
+    Unknown {
+        /// The attestation tag
+        tag: [u8; 8],
+        /// The payload
+        payload: Vec<u8>,
+    },
+}
+
+/// An OpenTimestamps anchor published in a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Anchor {
+    /// The output index
+    pub output: usize,
+    /// The committed digest
+    pub commitment: [u8; 32],
+}
+
+/// Error types for decoding attestations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OtsError {
+    /// The attestation ends before a required field
+    Truncated,
+    /// The payload exceeds [`MAX_PAYLOAD_SIZE`]
+    PayloadTooLarge,
+    /// The payload has bytes after its fields
+    TrailingBytes,
+    /// The calendar URI is too long or has disallowed characters
+    InvalidUri,
+    /// LEB128 decoding error
+    VarInt(varint::Error),
+}
+
+impl Attestation {
+    /// Returns the attestation tag
+    pub fn tag(&self) -> [u8; 8] {
+        match self {
+            Attestation::Bitcoin { .. } => BITCOIN_TAG,
+            Attestation::Pending { .. } => PENDING_TAG,
+            Attestation::Unknown { tag, .. } => *tag,
+        }
+    }
+
+    /// Returns the tag followed by the length-prefixed payload
+    pub fn encode(&self) -> Vec<u8> {
+        let payload = match self {
+            Attestation::Bitcoin { height } => varint::encode((*height).into()),
+            Attestation::Pending { uri } => {
+                let mut payload = varint::encode(uri.len() as u128);
+                payload.extend(uri.as_bytes());
+                payload
+            }
+            Attestation::Unknown { payload, .. } => payload.clone(),
+        };
+
+        let mut bytes = self.tag().to_vec();
+        varint::encode_to_vec(payload.len() as u128, &mut bytes);
+        bytes.extend(payload);
+        bytes
+    }
+
+    /// Decodes an attestation from the front of `bytes`, returning it and the number of bytes
+    /// read
+    pub fn decode(bytes: &[u8]) -> Result<(Self, usize), OtsError> {
+        let (tag, rest) = bytes.split_first_chunk::<8>().ok_or(OtsError::Truncated)?;
+        let (payload, length) = read_varbytes(rest, MAX_PAYLOAD_SIZE, OtsError::PayloadTooLarge)?;
+
+        let attestation = match *tag {
+            BITCOIN_TAG => {
+                let (height, n) = varint::decode(payload)?;
+                if n != payload.len() {
+                    return Err(OtsError::TrailingBytes);
+                }
+                Attestation::Bitcoin {
+                    height: u64::try_from(height).map_err(|_| varint::Error::Overflow)?,
+                }
+            }
+            PENDING_TAG => {
+                let (uri, n) = read_varbytes(payload, MAX_URI_LENGTH, OtsError::InvalidUri)?;
+                if n != payload.len() {
+                    return Err(OtsError::TrailingBytes);
+                }
+                if !uri
+                    .iter()
+                    .all(|c| c.is_ascii_alphanumeric() || b"._/:-".contains(c))
+                {
+                    return Err(OtsError::InvalidUri);
+                }
+                Attestation::Pending {
+                    uri: String::from_utf8(uri.to_vec()).expect("ascii"),
+                }
+            }
+            tag => Attestation::Unknown {
+                tag,
+                payload: payload.to_vec(),
+            },
+        };
+
+        Ok((attestation, 8 + length))
+    }
+
+    /// Returns whether this is a Bitcoin attestation that `header` satisfies for `digest`,
+    /// the final commitment of a timestamp. The caller checks that `header` is at the
+    /// attested height.
+    pub fn verify_header(&self, digest: &[u8; 32], header: &Header) -> bool {
+        matches!(self, Attestation::Bitcoin { .. }) && header.merkle_root.to_byte_array() == *digest
+    }
+}
+
+impl Anchor {
+    /// Returns the anchor in an `OP_RETURN` embedding, if any
+    pub fn from_embedding(embedding: &Embedding) -> Option<Self> {
+        let EmbeddingLocation::OpReturn { output } = embedding.location else {
+            return None;
+        };
+
+        let mut instructions = Script::from_bytes(&embedding.bytes).instructions();
+        let Some(Ok(Instruction::PushBytes(push))) = instructions.next() else {
+            return None;
+        };
+        if instructions.next().is_some() {
+            return None;
+        }
+
+        Some(Self {
+            output,
+            commitment: push.as_bytes().try_into().ok()?,
+        })
+    }
+
+    /// Returns the anchors in a transaction
+    pub fn from_transaction(tx: &Transaction) -> Vec<Self> {
+        Embedding::from_transaction(tx)
+            .iter()
+            .filter_map(Self::from_embedding)
+            .collect()
+    }
+
+    /// Returns the `OP_RETURN` script publishing `commitment`
+    pub fn script(commitment: [u8; 32]) -> ScriptBuf {
+        ScriptBuf::new_op_return(commitment)
+    }
+}
+
+fn read_varbytes(
+    bytes: &[u8],
+    max_length: usize,
+    too_long: OtsError,
+) -> Result<(&[u8], usize), OtsError> {
+    let (length, n) = varint::decode(bytes).map_err(|e| match e {
+        varint::Error::Unterminated => OtsError::Truncated,
+        e => e.into(),
+    })?;
+    let length = usize::try_from(length)
+        .ok()
+        .filter(|length| *length <= max_length)
+        .ok_or(too_long)?;
+    let value = bytes.get(n..n + length).ok_or(OtsError::Truncated)?;
+    Ok((value, n + length))
+}
+
+impl From<varint::Error> for OtsError {
+    fn from(e: varint::Error) -> Self {
+        OtsError::VarInt(e)
+    }
+}
+
+impl fmt::Display for OtsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OtsError::Truncated => write!(f, "Truncated attestation"),
+            OtsError::PayloadTooLarge => write!(f, "Attestation payload too large"),
+            OtsError::TrailingBytes => write!(f, "Trailing bytes in attestation payload"),
+            OtsError::InvalidUri => write!(f, "Invalid calendar URI"),
+            OtsError::VarInt(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for OtsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::hex::FromHex;
+    use bitcoin::{
+        Amount, BlockHash, CompactTarget, TxMerkleNode, TxOut, absolute::LockTime,
+        block::Version as BlockVersion, transaction::Version,
+    };
+
+    #[test]
+    fn test_attestations() {
+        // Bitcoin attestation at height 358391
+        let bytes = Vec::from_hex("0588960d73d7190103f7ef15").unwrap();
+        let (attestation, n) = Attestation::decode(&bytes).unwrap();
+        assert_eq!(attestation, Attestation::Bitcoin { height: 358391 });
+        assert_eq!(n, bytes.len());
+        assert_eq!(attestation.encode(), bytes);
+
+        for attestation in [
+            Attestation::Pending {
+                uri: "https://alice.btc.calendar.opentimestamps.org".into(),
+            },
+            Attestation::Unknown {
+                tag: [1; 8],
+                payload: vec![1, 2, 3],
+            },
+        ] {
+            let mut bytes = attestation.encode();
+            let length = bytes.len();
+            bytes.push(0xff);
+            assert_eq!(Attestation::decode(&bytes), Ok((attestation, length)));
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(Attestation::decode(&BITCOIN_TAG), Err(OtsError::Truncated));
+        assert_eq!(
+            Attestation::decode(&[&BITCOIN_TAG[..], &[2, 1, 0]].concat()),
+            Err(OtsError::TrailingBytes)
+        );
+
+        let bytes = Attestation::Pending {
+            uri: "https://example.com/?q".into(),
+        }
+        .encode();
+        assert_eq!(Attestation::decode(&bytes), Err(OtsError::InvalidUri));
+
+        let mut bytes = PENDING_TAG.to_vec();
+        varint::encode_to_vec(MAX_PAYLOAD_SIZE as u128 + 1, &mut bytes);
+        assert_eq!(Attestation::decode(&bytes), Err(OtsError::PayloadTooLarge));
+    }
+
+    #[test]
+    fn test_anchor() {
+        let commitment = [7; 32];
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![
+                TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey: ScriptBuf::new_op_return([7; 31]),
+                },
+                TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey: Anchor::script(commitment),
+                },
+            ],
+        };
+
+        assert_eq!(
+            Anchor::from_transaction(&tx),
+            vec![Anchor {
+                output: 1,
+                commitment
+            }]
+        );
+
+        let header = Header {
+            version: BlockVersion::TWO,
+            prev_blockhash: BlockHash::all_zeros(),
+            merkle_root: TxMerkleNode::from_byte_array(commitment),
+            time: 0,
+            bits: CompactTarget::from_consensus(0),
+            nonce: 0,
+        };
+        let attestation = Attestation::Bitcoin { height: 1 };
+        assert!(attestation.verify_header(&commitment, &header));
+        assert!(!attestation.verify_header(&[0; 32], &header));
+    }
+}
@@ -0,0 +1,889 @@
+//! # Runestones
+//!
+//! A runestone is the first output of a transaction whose script is `OP_RETURN OP_13` followed
+//! by data pushes. The pushes concatenate to a sequence of LEB128 integers: tag/value pairs,
+//! then an optional body tag followed by delta-encoded edicts. A malformed runestone is a
+//! [`Cenotaph`], which burns the runes sent to the transaction.
+
+// Based on ordinals/runes/runestone.rs
+
+use crate::message::Message;
+use crate::{Embedding, EmbeddingLocation, varint};
+
+use bitcoin::blockdata::constants::MAX_SCRIPT_ELEMENT_SIZE;
+use bitcoin::opcodes::all::{OP_PUSHNUM_13, OP_RETURN};
+use bitcoin::script::{Builder, Instruction, Instructions, PushBytesBuf};
+use bitcoin::{Script, ScriptBuf, Transaction};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+
+/// The opcode that follows `OP_RETURN` in a runestone
+pub const MAGIC_NUMBER: bitcoin::Opcode = OP_PUSHNUM_13;
+
+/// The maximum number of decimal places of a rune
+pub const MAX_DIVISIBILITY: u8 = 38;
+
+/// The maximum value of the spacers bitfield
+pub const MAX_SPACERS: u32 = 0b0000_0111_1111_1111_1111_1111_1111_1111;
+
+/// Runestone field tags. Unrecognized even tags make a runestone a cenotaph; unrecognized odd
+/// tags are ignored.
+pub mod tags {
+    /// Marks the end of the fields; the remaining integers are edicts
+    pub const BODY: u128 = 0;
+    /// Decimal places of an etched rune
+    pub const DIVISIBILITY: u128 = 1;
+    /// Bitfield of etching, terms, and turbo flags
+    pub const FLAGS: u128 = 2;
+    /// Bitfield of spacers between the letters of an etched rune's name
+    pub const SPACERS: u128 = 3;
+    /// Name of an etched rune
+    pub const RUNE: u128 = 4;
+    /// Currency symbol of an etched rune
+    pub const SYMBOL: u128 = 5;
+    /// Units allocated to the etcher
+    pub const PREMINE: u128 = 6;
+    /// Maximum number of mints
+    pub const CAP: u128 = 8;
+    /// Units created by each mint
+    pub const AMOUNT: u128 = 10;
+    /// Absolute height at which minting opens
+    pub const HEIGHT_START: u128 = 12;
+    /// Absolute height at which minting closes
+    pub const HEIGHT_END: u128 = 14;
+    /// Height relative to the etching at which minting opens
+    pub const OFFSET_START: u128 = 16;
+    /// Height relative to the etching at which minting closes
+    pub const OFFSET_END: u128 = 18;
+    /// Rune id to mint, as two values
+    pub const MINT: u128 = 20;
+    /// Output that receives unallocated runes
+    pub const POINTER: u128 = 22;
+    /// Makes the runestone a cenotaph
+    pub const CENOTAPH: u128 = 126;
+    /// Ignored
+    pub const NOP: u128 = 127;
+}
+
+mod flags {
+    pub const ETCHING: u32 = 0;
+    pub const TERMS: u32 = 1;
+    pub const TURBO: u32 = 2;
+}
+
+/// The id of a rune: the height and position of its etching transaction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RuneId {
+    /// The block height
+    pub block: u64,
+    /// The position in the block
+    pub tx: u32,
+}
+
+impl RuneId {
+    /// Returns a rune id, or `None` for the invalid `0:<n>` ids with a nonzero position
+    pub fn new(block: u64, tx: u32) -> Option<Self> {
+        (block > 0 || tx == 0).then_some(Self { block, tx })
+    }
+
+    /// Applies an edict's delta to the previous rune id
+    fn next(self, block: u128, tx: u128) -> Option<Self> {
+        let block = self.block.checked_add(u64::try_from(block).ok()?)?;
+        let tx = if block == self.block {
+            self.tx.checked_add(u32::try_from(tx).ok()?)?
+        } else {
+            u32::try_from(tx).ok()?
+        };
+        Self::new(block, tx)
+    }
+
+    /// Returns the delta from the previous rune id
+    fn delta(self, prev: Self) -> (u128, u128) {
+        let block = self.block - prev.block;
+        let tx = if block == 0 {
+            self.tx - prev.tx
+        } else {
+            self.tx
+        };
+        (block.into(), tx.into())
+    }
+}
+
+/// The name of a rune, stored as an integer in bijective base 26 (`A` is 0, `Z` is 25, `AA`
+/// is 26)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Rune(pub u128);
+
+/// A transfer of runes to an output. An output equal to the number of outputs splits the
+/// amount among the non-`OP_RETURN` outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edict {
+    /// The rune to transfer
+    pub id: RuneId,
+    /// The number of units, or zero for all remaining units
+    pub amount: u128,
+    /// The receiving output
+    pub output: u32,
+}
+
+/// The conditions under which an etched rune can be minted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Terms {
+    /// Units created by each mint
+    pub amount: Option<u128>,
+    /// Maximum number of mints
+    pub cap: Option<u128>,
+    /// Absolute heights at which minting opens and closes
+    pub height: (Option<u64>, Option<u64>),
+    /// Heights relative to the etching at which minting opens and closes
+    pub offset: (Option<u64>, Option<u64>),
+}
+
+/// The creation of a new rune
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Etching {
+    /// Decimal places
+    pub divisibility: Option<u8>,
+    /// Units allocated to the etcher
+    pub premine: Option<u128>,
+    /// The name, or `None` for a reserved name
+    pub rune: Option<Rune>,
+    /// Bitfield of spacers between the letters of the name
+    pub spacers: Option<u32>,
+    /// Currency symbol
+    pub symbol: Option<char>,
+    /// Open minting terms
+    pub terms: Option<Terms>,
+    /// Opts into future protocol changes
+    pub turbo: bool,
+}
+
+impl Etching {
+    /// Returns the maximum supply, or `None` if it overflows
+    pub fn supply(&self) -> Option<u128> {
+        let terms = self.terms.unwrap_or_default();
+        self.premine.unwrap_or_default().checked_add(
+            terms
+                .cap
+                .unwrap_or_default()
+                .checked_mul(terms.amount.unwrap_or_default())?,
+        )
+    }
+}
+
+/// A well-formed runestone
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Runestone {
+    /// Transfers, in the order they are applied
+    pub edicts: Vec<Edict>,
+    /// A new rune
+    pub etching: Option<Etching>,
+    /// A rune to mint
+    pub mint: Option<RuneId>,
+    /// The output that receives unallocated runes
+    pub pointer: Option<u32>,
+}
+
+/// A malformed runestone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cenotaph {
+    /// The name of a rune being etched, which is burned
+    pub etching: Option<Rune>,
+    /// The reason the runestone is malformed
+    pub flaw: Flaw,
+    /// A rune being minted, whose minted units are burned
+    pub mint: Option<RuneId>,
+}
+
+/// The reason a runestone is a cenotaph
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flaw {
+    /// An edict refers to an output that does not exist
+    EdictOutput,
+    /// An edict's rune id is invalid
+    EdictRuneId,
+    /// The script could not be parsed
+    InvalidScript,
+    /// The payload contains a non-push opcode
+    Opcode,
+    /// The etched rune's maximum supply overflows
+    SupplyOverflow,
+    /// The edicts are not a multiple of four integers
+    TrailingIntegers,
+    /// A tag has no value
+    TruncatedField,
+    /// The payload contains an unrecognized even tag
+    UnrecognizedEvenTag,
+    /// The flags contain an unrecognized flag
+    UnrecognizedFlag,
+    /// The payload contains an invalid LEB128 integer
+    Varint,
+}
+
+/// The result of deciphering a runestone output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Artifact {
+    /// A well-formed runestone
+    Runestone(Box<Runestone>),
+    /// A malformed runestone
+    Cenotaph(Cenotaph),
+}
+
+impl Runestone {
+    /// Deciphers the runestone in a transaction, if any
+    pub fn decipher(tx: &Transaction) -> Option<Artifact> {
+        tx.output.iter().find_map(|output| {
+            let mut instructions = output.script_pubkey.instructions();
+            if instructions.next() != Some(Ok(Instruction::Op(OP_RETURN))) {
+                return None;
+            }
+            Self::decipher_after_op_return(instructions, tx.output.len())
+        })
+    }
+
+    /// Deciphers an `OP_RETURN` embedding from a transaction with `outputs` outputs. Returns
+    /// `None` for other embeddings and for `OP_RETURN` outputs without the runestone magic
+    /// number. Only the first runestone output of a transaction is recognized by indexers.
+    pub fn decipher_embedding(embedding: &Embedding, outputs: usize) -> Option<Artifact> {
+        match embedding.location {
+            EmbeddingLocation::OpReturn { .. } => Self::decipher_after_op_return(
+                Script::from_bytes(&embedding.bytes).instructions(),
+                outputs,
+            ),
+            _ => None,
+        }
+    }
+
+    /// Returns the `OP_RETURN` script encoding this runestone
+    pub fn encipher(&self) -> ScriptBuf {
+        let payload = self
+            .integers()
+            .into_iter()
+            .flat_map(varint::encode)
+            .collect::<Vec<_>>();
+
+        let mut builder = Builder::new()
+            .push_opcode(OP_RETURN)
+            .push_opcode(MAGIC_NUMBER);
+        for chunk in payload.chunks(MAX_SCRIPT_ELEMENT_SIZE) {
+            let push = PushBytesBuf::try_from(chunk.to_vec()).expect("chunk is within push limit");
+            builder = builder.push_slice(push);
+        }
+
+        builder.into_script()
+    }
+
+    /// Returns the tagged fields as messages whose bodies are LEB128 values. Edicts follow the
+    /// body tag rather than being tagged fields, so they are not included.
+    pub fn to_messages(&self) -> Vec<Message> {
+        let integers = self.integers();
+        integers
+            .chunks_exact(2)
+            .take_while(|pair| pair[0] != tags::BODY)
+            .map(|pair| {
+                Message::new(pair[0], varint::encode(pair[1])).expect("runestone tags are valid")
+            })
+            .collect()
+    }
+
+    fn decipher_after_op_return(
+        mut instructions: Instructions,
+        outputs: usize,
+    ) -> Option<Artifact> {
+        if instructions.next() != Some(Ok(Instruction::Op(MAGIC_NUMBER))) {
+            return None;
+        }
+
+        let mut payload = Vec::new();
+        for instruction in instructions {
+            let flaw = match instruction {
+                Ok(Instruction::PushBytes(push)) => {
+                    payload.extend_from_slice(push.as_bytes());
+                    continue;
+                }
+                Ok(Instruction::Op(_)) => Flaw::Opcode,
+                Err(_) => Flaw::InvalidScript,
+            };
+            return Some(Artifact::Cenotaph(Cenotaph::from_flaw(flaw)));
+        }
+
+        Some(Self::from_payload(&payload, outputs))
+    }
+
+    fn from_payload(payload: &[u8], outputs: usize) -> Artifact {
+        let mut integers = Vec::new();
+        let mut i = 0;
+        while i < payload.len() {
+            match varint::decode(&payload[i..]) {
+                Ok((integer, length)) => {
+                    integers.push(integer);
+                    i += length;
+                }
+                Err(_) => return Artifact::Cenotaph(Cenotaph::from_flaw(Flaw::Varint)),
+            }
+        }
+
+        let mut flaw = None;
+        let mut edicts = Vec::new();
+        let mut fields = Fields::default();
+
+        for i in (0..integers.len()).step_by(2) {
+            let tag = integers[i];
+
+            if tag == tags::BODY {
+                let mut id = RuneId::default();
+                for chunk in integers[i + 1..].chunks(4) {
+                    if chunk.len() != 4 {
+                        flaw.get_or_insert(Flaw::TrailingIntegers);
+                        break;
+                    }
+
+                    let Some(next) = id.next(chunk[0], chunk[1]) else {
+                        flaw.get_or_insert(Flaw::EdictRuneId);
+                        break;
+                    };
+
+                    let Some(output) = u32::try_from(chunk[3])
+                        .ok()
+                        .filter(|output| *output as usize <= outputs)
+                    else {
+                        flaw.get_or_insert(Flaw::EdictOutput);
+                        break;
+                    };
+
+                    edicts.push(Edict {
+                        id: next,
+                        amount: chunk[2],
+                        output,
+                    });
+                    id = next;
+                }
+                break;
+            }
+
+            let Some(&value) = integers.get(i + 1) else {
+                flaw.get_or_insert(Flaw::TruncatedField);
+                break;
+            };
+
+            fields.0.entry(tag).or_default().push_back(value);
+        }
+
+        let mut flags = fields
+            .take(tags::FLAGS, |[flags]| u32::try_from(flags).ok())
+            .unwrap_or_default();
+        let mut take_flag = |flag: u32| {
+            let set = flags & (1 << flag) != 0;
+            flags &= !(1 << flag);
+            set
+        };
+
+        let etching = take_flag(flags::ETCHING).then(|| Etching {
+            divisibility: fields.take(tags::DIVISIBILITY, |[divisibility]| {
+                u8::try_from(divisibility)
+                    .ok()
+                    .filter(|divisibility| *divisibility <= MAX_DIVISIBILITY)
+            }),
+            premine: fields.take(tags::PREMINE, |[premine]| Some(premine)),
+            rune: fields.take(tags::RUNE, |[rune]| Some(Rune(rune))),
+            spacers: fields.take(tags::SPACERS, |[spacers]| {
+                u32::try_from(spacers)
+                    .ok()
+                    .filter(|spacers| *spacers <= MAX_SPACERS)
+            }),
+            symbol: fields.take(tags::SYMBOL, |[symbol]| {
+                char::from_u32(u32::try_from(symbol).ok()?)
+            }),
+            terms: take_flag(flags::TERMS).then(|| Terms {
+                amount: fields.take(tags::AMOUNT, |[amount]| Some(amount)),
+                cap: fields.take(tags::CAP, |[cap]| Some(cap)),
+                height: (
+                    fields.take(tags::HEIGHT_START, |[start]| u64::try_from(start).ok()),
+                    fields.take(tags::HEIGHT_END, |[end]| u64::try_from(end).ok()),
+                ),
+                offset: (
+                    fields.take(tags::OFFSET_START, |[start]| u64::try_from(start).ok()),
+                    fields.take(tags::OFFSET_END, |[end]| u64::try_from(end).ok()),
+                ),
+            }),
+            turbo: take_flag(flags::TURBO),
+        });
+
+        let mint = fields.take(tags::MINT, |[block, tx]| {
+            RuneId::new(u64::try_from(block).ok()?, u32::try_from(tx).ok()?)
+        });
+
+        let pointer = fields.take(tags::POINTER, |[pointer]| {
+            u32::try_from(pointer)
+                .ok()
+                .filter(|pointer| (*pointer as usize) < outputs)
+        });
+
+        if etching.is_some_and(|etching| etching.supply().is_none()) {
+            flaw.get_or_insert(Flaw::SupplyOverflow);
+        }
+
+        if flags != 0 {
+            flaw.get_or_insert(Flaw::UnrecognizedFlag);
+        }
+
+        if fields.0.keys().any(|tag| tag % 2 == 0) {
+            flaw.get_or_insert(Flaw::UnrecognizedEvenTag);
+        }
+
+        match flaw {
+            Some(flaw) => Artifact::Cenotaph(Cenotaph {
+                etching: etching.and_then(|etching| etching.rune),
+                flaw,
+                mint,
+            }),
+            None => Artifact::Runestone(Box::new(Runestone {
+                edicts,
+                etching,
+                mint,
+                pointer,
+            })),
+        }
+    }
+
+    fn integers(&self) -> Vec<u128> {
+        let mut integers = Vec::new();
+        let mut push = |tag: u128, value: Option<u128>| {
+            if let Some(value) = value {
+                integers.extend([tag, value]);
+            }
+        };
+
+        if let Some(etching) = &self.etching {
+            let mut flags = 1 << flags::ETCHING;
+            if etching.terms.is_some() {
+                flags |= 1 << flags::TERMS;
+            }
+            if etching.turbo {
+                flags |= 1 << flags::TURBO;
+            }
+
+            push(tags::FLAGS, Some(flags));
+            push(tags::RUNE, etching.rune.map(|rune| rune.0));
+            push(tags::DIVISIBILITY, etching.divisibility.map(u128::from));
+            push(tags::SPACERS, etching.spacers.map(u128::from));
+            push(tags::SYMBOL, etching.symbol.map(u128::from));
+            push(tags::PREMINE, etching.premine);
+
+            if let Some(terms) = etching.terms {
+                push(tags::AMOUNT, terms.amount);
+                push(tags::CAP, terms.cap);
+                push(tags::HEIGHT_START, terms.height.0.map(u128::from));
+                push(tags::HEIGHT_END, terms.height.1.map(u128::from));
+                push(tags::OFFSET_START, terms.offset.0.map(u128::from));
+                push(tags::OFFSET_END, terms.offset.1.map(u128::from));
+            }
+        }
+
+        if let Some(mint) = self.mint {
+            push(tags::MINT, Some(mint.block.into()));
+            push(tags::MINT, Some(mint.tx.into()));
+        }
+
+        push(tags::POINTER, self.pointer.map(u128::from));
+
+        if !self.edicts.is_empty() {
+            integers.push(tags::BODY);
+
+            let mut edicts = self.edicts.clone();
+            edicts.sort_by_key(|edict| edict.id);
+
+            let mut prev = RuneId::default();
+            for edict in edicts {
+                let (block, tx) = edict.id.delta(prev);
+                integers.extend([block, tx, edict.amount, edict.output.into()]);
+                prev = edict.id;
+            }
+        }
+
+        integers
+    }
+}
+
+impl Cenotaph {
+    fn from_flaw(flaw: Flaw) -> Self {
+        Self {
+            etching: None,
+            flaw,
+            mint: None,
+        }
+    }
+}
+
+/// Field values by tag, in payload order
+#[derive(Default)]
+struct Fields(HashMap<u128, VecDeque<u128>>);
+
+impl Fields {
+    /// Removes and converts the first `N` values of a field, leaving them in place if there are
+    /// fewer than `N` or the conversion fails
+    fn take<const N: usize, T>(
+        &mut self,
+        tag: u128,
+        convert: impl FnOnce([u128; N]) -> Option<T>,
+    ) -> Option<T> {
+        let values = self.0.get_mut(&tag)?;
+
+        let mut array = [0; N];
+        for (i, value) in array.iter_mut().enumerate() {
+            *value = *values.get(i)?;
+        }
+
+        let value = convert(array)?;
+        values.drain(..N);
+        if values.is_empty() {
+            self.0.remove(&tag);
+        }
+
+        Some(value)
+    }
+}
+
+impl fmt::Display for RuneId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.block, self.tx)
+    }
+}
+
+impl FromStr for RuneId {
+    type Err = RuneError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (block, tx) = s.split_once(':').ok_or(RuneError::InvalidRuneId)?;
+        let block = block.parse().map_err(|_| RuneError::InvalidRuneId)?;
+        let tx = tx.parse().map_err(|_| RuneError::InvalidRuneId)?;
+        Self::new(block, tx).ok_or(RuneError::InvalidRuneId)
+    }
+}
+
+impl fmt::Display for Rune {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut n = self.0;
+        if n == u128::MAX {
+            return write!(f, "BCGDENLQRQWDSLRUGSNLBTMFIJAV");
+        }
+
+        n += 1;
+        let mut name = Vec::new();
+        while n > 0 {
+            name.push(b'A' + ((n - 1) % 26) as u8);
+            n = (n - 1) / 26;
+        }
+        name.reverse();
+
+        f.write_str(&String::from_utf8_lossy(&name))
+    }
+}
+
+impl FromStr for Rune {
+    type Err = RuneError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut x = 0u128;
+        for (i, c) in s.chars().enumerate() {
+            if i > 0 {
+                x = x.checked_add(1).ok_or(RuneError::InvalidRune)?;
+            }
+            x = x.checked_mul(26).ok_or(RuneError::InvalidRune)?;
+            match c {
+                'A'..='Z' => {
+                    x = x
+                        .checked_add(c as u128 - 'A' as u128)
+                        .ok_or(RuneError::InvalidRune)?;
+                }
+                _ => return Err(RuneError::InvalidRune),
+            }
+        }
+
+        if s.is_empty() {
+            return Err(RuneError::InvalidRune);
+        }
+
+        Ok(Rune(x))
+    }
+}
+
+/// Error types for parsing rune names and ids
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuneError {
+    /// A rune name contains characters other than `A`-`Z` or overflows
+    InvalidRune,
+    /// A rune id is not `<block>:<tx>`
+    InvalidRuneId,
+}
+
+impl fmt::Display for RuneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuneError::InvalidRune => write!(f, "Invalid rune name"),
+            RuneError::InvalidRuneId => write!(f, "Invalid rune id"),
+        }
+    }
+}
+
+impl std::error::Error for RuneError {}
+
+impl fmt::Display for Flaw {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Flaw::EdictOutput => write!(f, "Edict output greater than transaction output count"),
+            Flaw::EdictRuneId => write!(f, "Invalid rune id in edict"),
+            Flaw::InvalidScript => write!(f, "Invalid script in runestone output"),
+            Flaw::Opcode => write!(f, "Non-pushdata opcode in runestone"),
+            Flaw::SupplyOverflow => write!(f, "Supply overflows u128"),
+            Flaw::TrailingIntegers => write!(f, "Trailing integers in body"),
+            Flaw::TruncatedField => write!(f, "Field with missing value"),
+            Flaw::UnrecognizedEvenTag => write!(f, "Unrecognized even tag"),
+            Flaw::UnrecognizedFlag => write!(f, "Unrecognized flag"),
+            Flaw::Varint => write!(f, "Invalid varint"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::{Amount, TxOut, absolute::LockTime, transaction::Version};
+
+    fn transaction(scripts: Vec<ScriptBuf>) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: scripts
+                .into_iter()
+                .map(|script_pubkey| TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey,
+                })
+                .collect(),
+        }
+    }
+
+    fn payload(integers: &[u128]) -> ScriptBuf {
+        let payload = integers
+            .iter()
+            .flat_map(|n| varint::encode(*n))
+            .collect::<Vec<_>>();
+        Builder::new()
+            .push_opcode(OP_RETURN)
+            .push_opcode(MAGIC_NUMBER)
+            .push_slice(PushBytesBuf::try_from(payload).unwrap())
+            .into_script()
+    }
+
+    fn decipher(integers: &[u128]) -> Artifact {
+        let tx = transaction(vec![payload(integers), ScriptBuf::new(), ScriptBuf::new()]);
+        Runestone::decipher(&tx).unwrap()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let runestone = Runestone {
+            edicts: vec![
+                Edict {
+                    id: RuneId::new(840000, 3).unwrap(),
+                    amount: 1000,
+                    output: 1,
+                },
+                Edict {
+                    id: RuneId::new(840000, 1).unwrap(),
+                    amount: 0,
+                    output: 2,
+                },
+                Edict {
+                    id: RuneId::new(840001, 7).unwrap(),
+                    amount: u128::MAX,
+                    output: 0,
+                },
+            ],
+            etching: Some(Etching {
+                divisibility: Some(2),
+                premine: Some(1_000_000),
+                rune: Some("UNCOMMONGOODS".parse().unwrap()),
+                spacers: Some(0b1000),
+                symbol: Some('⧉'),
+                terms: Some(Terms {
+                    amount: Some(1),
+                    cap: Some(u64::MAX.into()),
+                    height: (Some(840000), Some(1_050_000)),
+                    offset: (None, Some(100)),
+                }),
+                turbo: true,
+            }),
+            mint: RuneId::new(1, 0),
+            pointer: Some(2),
+        };
+
+        let script = runestone.encipher();
+        let tx = transaction(vec![ScriptBuf::new(), script, ScriptBuf::new()]);
+
+        let mut sorted = runestone.clone();
+        sorted.edicts.sort_by_key(|edict| edict.id);
+        assert_eq!(
+            Runestone::decipher(&tx),
+            Some(Artifact::Runestone(Box::new(sorted.clone())))
+        );
+
+        let embedding = &Embedding::from_transaction(&tx)[0];
+        assert_eq!(
+            Runestone::decipher_embedding(embedding, tx.output.len()),
+            Some(Artifact::Runestone(Box::new(sorted)))
+        );
+
+        let messages = runestone.to_messages();
+        assert_eq!(messages[0].tag, tags::FLAGS);
+        assert_eq!(messages[0].body, varint::encode(0b111));
+        assert!(messages.iter().all(|message| message.tag != tags::BODY));
+    }
+
+    #[test]
+    fn test_large_payload_is_chunked() {
+        let runestone = Runestone {
+            edicts: (0..200)
+                .map(|i| Edict {
+                    id: RuneId::new(840000 + i, 1).unwrap(),
+                    amount: u128::MAX,
+                    output: 0,
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        let tx = transaction(vec![runestone.encipher()]);
+        assert_eq!(
+            Runestone::decipher(&tx),
+            Some(Artifact::Runestone(Box::new(runestone)))
+        );
+    }
+
+    #[test]
+    fn test_not_a_runestone() {
+        let op_return = ScriptBuf::new_op_return([1, 2, 3]);
+        assert_eq!(Runestone::decipher(&transaction(vec![op_return])), None);
+        assert_eq!(Runestone::decipher(&transaction(vec![])), None);
+    }
+
+    #[test]
+    fn test_cenotaphs() {
+        let flaw = |artifact| match artifact {
+            Artifact::Cenotaph(cenotaph) => Some(cenotaph.flaw),
+            Artifact::Runestone(_) => None,
+        };
+
+        assert_eq!(flaw(decipher(&[tags::POINTER])), Some(Flaw::TruncatedField));
+        assert_eq!(
+            flaw(decipher(&[tags::CENOTAPH, 0])),
+            Some(Flaw::UnrecognizedEvenTag)
+        );
+        assert_eq!(
+            flaw(decipher(&[tags::FLAGS, 1 << 5])),
+            Some(Flaw::UnrecognizedFlag)
+        );
+        assert_eq!(
+            flaw(decipher(&[tags::BODY, 1, 0, 0])),
+            Some(Flaw::TrailingIntegers)
+        );
+        assert_eq!(
+            flaw(decipher(&[tags::BODY, 0, 1, 0, 0])),
+            Some(Flaw::EdictRuneId)
+        );
+        assert_eq!(
+            flaw(decipher(&[tags::BODY, 1, 0, 0, 4])),
+            Some(Flaw::EdictOutput)
+        );
+        assert_eq!(
+            flaw(decipher(&[
+                tags::FLAGS,
+                0b11,
+                tags::PREMINE,
+                1,
+                tags::CAP,
+                2,
+                tags::AMOUNT,
+                u128::MAX
+            ])),
+            Some(Flaw::SupplyOverflow)
+        );
+
+        // Odd tags are ignored and an edict may target the split output
+        assert_eq!(
+            flaw(decipher(&[tags::NOP, 5, tags::BODY, 1, 0, 0, 3])),
+            None
+        );
+
+        let opcode = Builder::new()
+            .push_opcode(OP_RETURN)
+            .push_opcode(MAGIC_NUMBER)
+            .push_opcode(OP_RETURN)
+            .into_script();
+        assert_eq!(
+            Runestone::decipher(&transaction(vec![opcode])).and_then(flaw),
+            Some(Flaw::Opcode)
+        );
+
+        let varint = Builder::new()
+            .push_opcode(OP_RETURN)
+            .push_opcode(MAGIC_NUMBER)
+            .push_slice([0x80])
+            .into_script();
+        assert_eq!(
+            Runestone::decipher(&transaction(vec![varint])).and_then(flaw),
+            Some(Flaw::Varint)
+        );
+
+        // Cenotaphs keep the etched rune and the minted id so they can be burned
+        assert_eq!(
+            decipher(&[
+                tags::FLAGS,
+                1,
+                tags::RUNE,
+                5,
+                tags::MINT,
+                1,
+                tags::MINT,
+                2,
+                2,
+                0
+            ]),
+            Artifact::Cenotaph(Cenotaph {
+                etching: Some(Rune(5)),
+                flaw: Flaw::UnrecognizedEvenTag,
+                mint: RuneId::new(1, 2),
+            })
+        );
+    }
+
+    #[test]
+    fn test_rune_names() {
+        for (n, name) in [
+            (0, "A"),
+            (25, "Z"),
+            (26, "AA"),
+            (27, "AB"),
+            (701, "ZZ"),
+            (702, "AAA"),
+            (u128::MAX, "BCGDENLQRQWDSLRUGSNLBTMFIJAV"),
+        ] {
+            assert_eq!(Rune(n).to_string(), name);
+            assert_eq!(name.parse::<Rune>(), Ok(Rune(n)));
+        }
+
+        assert_eq!("".parse::<Rune>(), Err(RuneError::InvalidRune));
+        assert_eq!("a".parse::<Rune>(), Err(RuneError::InvalidRune));
+        assert_eq!(
+            "BCGDENLQRQWDSLRUGSNLBTMFIJAW".parse::<Rune>(),
+            Err(RuneError::InvalidRune)
+        );
+
+        assert_eq!("840000:3".parse(), Ok(RuneId::new(840000, 3).unwrap()));
+        assert_eq!("0:1".parse::<RuneId>(), Err(RuneError::InvalidRuneId));
+    }
+}
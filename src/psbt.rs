@@ -0,0 +1,717 @@
+//! # PSBT Embeddings
+//!
+//! Helpers for building embedding transactions collaboratively with PSBTs. Annexes have no
+//! PSBT field, so the annex data planned for an input is carried in a proprietary field
+//! ([`set_annex_plan`]) until the input is finalized.
+//!
+//! [`merge`] joins PSBTs that parties built from a common base, each adding inputs and
+//! outputs, and detects when both attach data: different `OP_RETURN` outputs, or different
+//! annex plans for the same input. A [`MergePolicy`] rejects these conflicts or resolves them
+//! in favor of one party, and checks the merged `OP_RETURN` outputs against relay policy.
+//! Merging changes the unsigned transaction, so parties merge before signing.
+//!
+//! Everything else uses fields signing devices already understand: `OP_RETURN` data as
+//! output scripts ([`add_op_return`]), commit outputs as their internal key and tap tree
+//! ([`set_commit_output`]), and reveals as tapscript leaves with control blocks
+//! ([`set_reveal_input`]). [`summarize_for_signer`] describes the embedded data in a few
+//! lines for a confirmation screen.
+
+use crate::descriptor::CommitOutput;
+use crate::envelope;
+use crate::planner::Layout;
+use crate::policy::{OpReturnError, OpReturnPolicy};
+use crate::{Embedding, EmbeddingLocation, EmbeddingType};
+
+use bitcoin::hex::DisplayHex;
+use bitcoin::psbt::{Input, Output, Psbt, raw};
+use bitcoin::script::PushBytesBuf;
+use bitcoin::taproot::LeafVersion;
+use bitcoin::{Amount, OutPoint, Script, ScriptBuf, TxOut};
+use std::fmt;
+
+/// The prefix of this crate's proprietary PSBT keys
+pub const PROPRIETARY_PREFIX: &[u8] = b"embed";
+
+/// The proprietary subtype of an input's planned annex data
+pub const PSBT_IN_ANNEX_DATA: u8 = 0x00;
+
+/// How [`merge`] resolves conflicting embeddings
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// Fail on any conflict
+    #[default]
+    Reject,
+    /// Keep the first PSBT's embeddings
+    KeepFirst,
+    /// Keep the second PSBT's embeddings
+    KeepSecond,
+    /// Keep the `OP_RETURN` outputs of both PSBTs. Annexes cannot be combined, so annex
+    /// conflicts still fail.
+    KeepBoth,
+}
+
+/// The policy [`merge`] applies to embeddings
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MergePolicy {
+    /// How conflicts are resolved
+    pub conflicts: ConflictPolicy,
+    /// The relay policy the merged `OP_RETURN` outputs must meet
+    pub op_return: OpReturnPolicy,
+}
+
+/// Embeddings both PSBTs attach, identified by their indices in the PSBTs being merged
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Conflict {
+    /// Both PSBTs add the same `OP_RETURN` output, which is kept once
+    DuplicateOpReturn {
+        /// The output index in the first PSBT
+        first: usize,
+        /// The output index in the second PSBT
+        second: usize,
+    },
+    /// Both PSBTs add different `OP_RETURN` outputs (the first of each)
+    OpReturn {
+        /// The output index in the first PSBT
+        first: usize,
+        /// The output index in the second PSBT
+        second: usize,
+    },
+    /// Both PSBTs plan different annexes for an input
+    Annex(OutPoint),
+}
+
+/// The number of payload characters shown in a signer summary
+const PREVIEW_CHARS: usize = 32;
+
+/// The result of a merge
+#[derive(Debug, Clone, PartialEq)]
+pub struct Merged {
+    /// The merged PSBT
+    pub psbt: Psbt,
+    /// The conflicts that were resolved
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Error types for merging PSBTs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeError {
+    /// The transactions have different versions or lock times
+    IncompatibleTransactions,
+    /// A conflict the policy does not resolve
+    Conflict(Conflict),
+    /// The merged `OP_RETURN` outputs violate the relay policy
+    Policy(OpReturnError),
+}
+
+/// Error types for adding embeddings to PSBTs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PsbtError {
+    /// The PSBT has no input at this index
+    NoSuchInput(usize),
+    /// The leaf is not committed to by the commit output
+    LeafNotCommitted,
+    /// The input reveals no envelope with a planned segment's bytes
+    MissingEnvelope,
+}
+
+impl Default for MergePolicy {
+    fn default() -> Self {
+        Self {
+            conflicts: ConflictPolicy::Reject,
+            op_return: OpReturnPolicy::STANDARD,
+        }
+    }
+}
+
+/// Sets the annex data planned for an input, to be appended with
+/// [`annex::append_data`](crate::annex::append_data) when it is finalized
+pub fn set_annex_plan(input: &mut Input, data: Vec<u8>) {
+    input.proprietary.insert(annex_key(), data);
+}
+
+/// Returns the annex data planned for an input
+pub fn annex_plan(input: &Input) -> Option<&[u8]> {
+    input.proprietary.get(&annex_key()).map(Vec::as_slice)
+}
+
+/// Removes the annex data planned for an input, returning it
+pub fn take_annex_plan(input: &mut Input) -> Option<Vec<u8>> {
+    input.proprietary.remove(&annex_key())
+}
+
+/// Appends a zero-value `OP_RETURN` output carrying `data`, returning its index
+pub fn add_op_return(psbt: &mut Psbt, data: &[u8]) -> usize {
+    let data = PushBytesBuf::try_from(data.to_vec()).expect("data fits in a push");
+    psbt.unsigned_tx.output.push(TxOut {
+        value: Amount::ZERO,
+        script_pubkey: ScriptBuf::new_op_return(data),
+    });
+    psbt.outputs.push(Output::default());
+    psbt.outputs.len() - 1
+}
+
+/// Sets the internal key and tap tree of a commit output, so signers can check the output
+/// key and show the envelope leaves it commits to
+pub fn set_commit_output(output: &mut Output, commit: &CommitOutput) {
+    output.tap_internal_key = Some(commit.internal_key());
+    output.tap_tree = Some(commit.tap_tree());
+}
+
+/// Sets the internal key, merkle root, and leaf script with its control block for an input
+/// revealing `leaf` of a commit output
+pub fn set_reveal_input(
+    input: &mut Input,
+    commit: &CommitOutput,
+    leaf: &Script,
+) -> Result<(), PsbtError> {
+    let control_block = commit
+        .control_block(leaf)
+        .ok_or(PsbtError::LeafNotCommitted)?;
+    input.tap_internal_key = Some(commit.internal_key());
+    input.tap_merkle_root = commit.merkle_root();
+    input
+        .tap_scripts
+        .insert(control_block, (leaf.to_owned(), LeafVersion::TapScript));
+    Ok(())
+}
+
+/// Adds a planned layout to a PSBT: its `OP_RETURN` segments as outputs, and its annex
+/// segment or control record as the annex plan of `input`. Its envelope segment must
+/// already be revealed by `input` ([`set_reveal_input`]).
+pub fn add_layout(psbt: &mut Psbt, layout: &Layout, input: usize) -> Result<(), PsbtError> {
+    let revealed = revealed_payloads(
+        psbt.inputs
+            .get(input)
+            .ok_or(PsbtError::NoSuchInput(input))?,
+    );
+    if layout
+        .segments
+        .iter()
+        .filter(|s| matches!(s.embedding_type, EmbeddingType::WitnessEnvelope(_)))
+        .any(|s| !revealed.contains(&s.bytes))
+    {
+        return Err(PsbtError::MissingEnvelope);
+    }
+
+    for segment in &layout.segments {
+        match segment.embedding_type {
+            EmbeddingType::OpReturn => {
+                add_op_return(psbt, &segment.bytes);
+            }
+            EmbeddingType::TaprootAnnex => {
+                set_annex_plan(&mut psbt.inputs[input], segment.bytes.clone())
+            }
+            _ => {}
+        }
+    }
+    if let Some(payload) = layout.control_payload() {
+        set_annex_plan(&mut psbt.inputs[input], payload);
+    }
+    Ok(())
+}
+
+/// Returns a short description of the data a PSBT embeds, for a signing device's
+/// confirmation screen: a total, then one line per `OP_RETURN` output, revealed envelope,
+/// and planned annex with a preview of its payload, and one per commit output
+pub fn summarize_for_signer(psbt: &Psbt) -> String {
+    let mut lines = Vec::new();
+    let mut locations = 0;
+    let mut bytes = 0;
+    let mut describe = |location: String, payload: &[u8]| {
+        locations += 1;
+        bytes += payload.len();
+        format!("{location}: {} bytes {}", payload.len(), preview(payload))
+    };
+
+    for embedding in Embedding::from_transaction(&psbt.unsigned_tx) {
+        if let EmbeddingLocation::OpReturn { output } = embedding.location {
+            lines.push(describe(
+                format!("Output {output} OP_RETURN"),
+                &embedding.payload(),
+            ));
+        }
+    }
+
+    for (i, output) in psbt.outputs.iter().enumerate() {
+        let Some(tap_tree) = &output.tap_tree else {
+            continue;
+        };
+        let committed = tap_tree
+            .script_leaves()
+            .flat_map(|leaf| envelope::from_script(leaf.script()))
+            .map(|envelope| envelope.concat().len())
+            .collect::<Vec<_>>();
+        if !committed.is_empty() {
+            lines.push(format!(
+                "Output {i}: commits to {} envelope(s) with {} bytes, revealed later",
+                committed.len(),
+                committed.iter().sum::<usize>()
+            ));
+        }
+    }
+
+    for (i, input) in psbt.inputs.iter().enumerate() {
+        for payload in revealed_payloads(input) {
+            lines.push(describe(format!("Input {i} envelope"), &payload));
+        }
+        if let Some(data) = annex_plan(input) {
+            lines.push(describe(format!("Input {i} annex"), data));
+        }
+    }
+
+    let total = match locations {
+        0 => "No embedded data".to_string(),
+        1 => format!("Embeds {bytes} bytes in 1 location"),
+        n => format!("Embeds {bytes} bytes in {n} locations"),
+    };
+    lines.insert(0, total);
+    lines.join("\n")
+}
+
+/// Joins two PSBTs built from a common base. Inputs spending the same outpoint are combined
+/// as in BIP 174, and outputs identical to an output of the first PSBT are kept once; the
+/// second PSBT's other inputs and outputs are appended. Conflicting embeddings are resolved
+/// by `policy`, and the merged `OP_RETURN` outputs are checked against its relay policy.
+pub fn merge(first: &Psbt, second: &Psbt, policy: &MergePolicy) -> Result<Merged, MergeError> {
+    let (a, b) = (&first.unsigned_tx, &second.unsigned_tx);
+    if a.version != b.version || a.lock_time != b.lock_time {
+        return Err(MergeError::IncompatibleTransactions);
+    }
+
+    let mut psbt = first.clone();
+    let mut conflicts = Vec::new();
+
+    for (txin, input) in b.input.iter().zip(&second.inputs) {
+        let outpoint = txin.previous_output;
+        let Some(i) = a.input.iter().position(|i| i.previous_output == outpoint) else {
+            psbt.unsigned_tx.input.push(txin.clone());
+            psbt.inputs.push(input.clone());
+            continue;
+        };
+
+        let mut input = input.clone();
+        let planned = (annex_plan(&psbt.inputs[i]), annex_plan(&input));
+        match planned {
+            (Some(x), Some(y)) if x != y => {
+                let conflict = Conflict::Annex(outpoint);
+                match policy.conflicts {
+                    ConflictPolicy::KeepFirst => {
+                        take_annex_plan(&mut input);
+                    }
+                    ConflictPolicy::KeepSecond => {
+                        take_annex_plan(&mut psbt.inputs[i]);
+                    }
+                    ConflictPolicy::Reject | ConflictPolicy::KeepBoth => {
+                        return Err(MergeError::Conflict(conflict));
+                    }
+                }
+                conflicts.push(conflict);
+            }
+            _ => {}
+        }
+        psbt.inputs[i].combine(input);
+    }
+
+    // Match each output of the second PSBT with an identical, unmatched output of the first
+    let mut matched = vec![false; a.output.len()];
+    let mut added = Vec::new();
+    for (j, txout) in b.output.iter().enumerate() {
+        match (0..a.output.len()).find(|&i| !matched[i] && a.output[i] == *txout) {
+            Some(i) => {
+                matched[i] = true;
+                if txout.script_pubkey.is_op_return() {
+                    conflicts.push(Conflict::DuplicateOpReturn {
+                        first: i,
+                        second: j,
+                    });
+                }
+                psbt.outputs[i].combine(second.outputs[j].clone());
+            }
+            None => added.push(j),
+        }
+    }
+
+    let first_data = (0..a.output.len())
+        .filter(|&i| !matched[i] && a.output[i].script_pubkey.is_op_return())
+        .collect::<Vec<_>>();
+    let second_data = added
+        .iter()
+        .copied()
+        .filter(|&j| b.output[j].script_pubkey.is_op_return())
+        .collect::<Vec<_>>();
+    if let (Some(&i), Some(&j)) = (first_data.first(), second_data.first()) {
+        let conflict = Conflict::OpReturn {
+            first: i,
+            second: j,
+        };
+        match policy.conflicts {
+            ConflictPolicy::Reject => return Err(MergeError::Conflict(conflict)),
+            ConflictPolicy::KeepFirst => added.retain(|j| !second_data.contains(j)),
+            ConflictPolicy::KeepSecond => {
+                for &i in first_data.iter().rev() {
+                    psbt.unsigned_tx.output.remove(i);
+                    psbt.outputs.remove(i);
+                }
+            }
+            ConflictPolicy::KeepBoth => {}
+        }
+        conflicts.push(conflict);
+    }
+
+    for j in added {
+        psbt.unsigned_tx.output.push(b.output[j].clone());
+        psbt.outputs.push(second.outputs[j].clone());
+    }
+
+    policy
+        .op_return
+        .check(&psbt.unsigned_tx)
+        .map_err(MergeError::Policy)?;
+
+    Ok(Merged { psbt, conflicts })
+}
+
+/// Returns the payloads of the envelopes in an input's tapscript leaves
+fn revealed_payloads(input: &Input) -> Vec<Vec<u8>> {
+    input
+        .tap_scripts
+        .values()
+        .flat_map(|(script, _)| envelope::from_script(script))
+        .map(|envelope| envelope.concat())
+        .collect()
+}
+
+/// Returns a payload as quoted text if it is printable UTF-8, or as hex, truncated
+fn preview(payload: &[u8]) -> String {
+    match std::str::from_utf8(payload) {
+        Ok(text) if !text.chars().any(char::is_control) => {
+            let mut preview = text.chars().take(PREVIEW_CHARS).collect::<String>();
+            if text.chars().count() > PREVIEW_CHARS {
+                preview.push_str("...");
+            }
+            format!("\"{preview}\"")
+        }
+        _ => {
+            let len = payload.len().min(PREVIEW_CHARS / 2);
+            let mut preview = payload[..len].to_lower_hex_string();
+            if payload.len() > len {
+                preview.push_str("...");
+            }
+            preview
+        }
+    }
+}
+
+fn annex_key() -> raw::ProprietaryKey {
+    raw::ProprietaryKey {
+        prefix: PROPRIETARY_PREFIX.to_vec(),
+        subtype: PSBT_IN_ANNEX_DATA,
+        key: Vec::new(),
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Conflict::DuplicateOpReturn { first, second } => write!(
+                f,
+                "OP_RETURN output {first} of the first PSBT duplicates output {second} of the second"
+            ),
+            Conflict::OpReturn { first, second } => write!(
+                f,
+                "OP_RETURN output {first} of the first PSBT conflicts with output {second} of the second"
+            ),
+            Conflict::Annex(outpoint) => write!(f, "Conflicting annex plans for input {outpoint}"),
+        }
+    }
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeError::IncompatibleTransactions => {
+                write!(f, "PSBTs have different transaction versions or lock times")
+            }
+            MergeError::Conflict(conflict) => write!(f, "{conflict}"),
+            MergeError::Policy(e) => write!(f, "Merged OP_RETURN outputs violate policy: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+impl fmt::Display for PsbtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PsbtError::NoSuchInput(input) => write!(f, "PSBT has no input {input}"),
+            PsbtError::LeafNotCommitted => write!(f, "Leaf is not committed to by the output"),
+            PsbtError::MissingEnvelope => write!(f, "Input does not reveal the planned envelope"),
+        }
+    }
+}
+
+impl std::error::Error for PsbtError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::{
+        Amount, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, WScriptHash, Witness,
+        absolute::LockTime, transaction::Version,
+    };
+
+    fn txin(n: u8) -> TxIn {
+        TxIn {
+            previous_output: OutPoint::new(Txid::from_byte_array([n; 32]), 0),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }
+    }
+
+    fn payment(sats: u64) -> TxOut {
+        TxOut {
+            value: Amount::from_sat(sats),
+            script_pubkey: ScriptBuf::new_p2wsh(&WScriptHash::all_zeros()),
+        }
+    }
+
+    fn data(bytes: &[u8]) -> TxOut {
+        TxOut {
+            value: Amount::ZERO,
+            script_pubkey: ScriptBuf::new_op_return(
+                <&bitcoin::script::PushBytes>::try_from(bytes).unwrap(),
+            ),
+        }
+    }
+
+    fn psbt(input: Vec<TxIn>, output: Vec<TxOut>) -> Psbt {
+        Psbt::from_unsigned_tx(Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input,
+            output,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_merge() {
+        // Both parties start from a base paying 1000 sats, and each adds an input and output
+        let first = psbt(vec![txin(1), txin(2)], vec![payment(1000), payment(2000)]);
+        let second = psbt(vec![txin(1), txin(3)], vec![payment(1000), data(b"gm")]);
+
+        let merged = merge(&first, &second, &MergePolicy::default()).unwrap();
+        assert_eq!(merged.conflicts, vec![]);
+        let tx = &merged.psbt.unsigned_tx;
+        assert_eq!(tx.input, vec![txin(1), txin(2), txin(3)]);
+        assert_eq!(tx.output, vec![payment(1000), payment(2000), data(b"gm")]);
+        assert_eq!(merged.psbt.inputs.len(), 3);
+        assert_eq!(merged.psbt.outputs.len(), 3);
+
+        // The same OP_RETURN output from both is kept once
+        let first = psbt(vec![txin(1)], vec![data(b"gm")]);
+        let merged = merge(&first, &second, &MergePolicy::default()).unwrap();
+        assert_eq!(
+            merged.conflicts,
+            vec![Conflict::DuplicateOpReturn {
+                first: 0,
+                second: 1
+            }]
+        );
+        assert_eq!(
+            merged.psbt.unsigned_tx.output,
+            vec![data(b"gm"), payment(1000)]
+        );
+
+        let mut other = first.clone();
+        other.unsigned_tx.lock_time = LockTime::from_height(1).unwrap();
+        assert_eq!(
+            merge(&other, &second, &MergePolicy::default()),
+            Err(MergeError::IncompatibleTransactions)
+        );
+    }
+
+    #[test]
+    fn test_op_return_conflicts() {
+        let first = psbt(vec![txin(1)], vec![payment(1000), data(b"first")]);
+        let second = psbt(vec![txin(2)], vec![data(b"second"), payment(500)]);
+        let conflict = Conflict::OpReturn {
+            first: 1,
+            second: 0,
+        };
+
+        let policy = |conflicts, op_return| MergePolicy {
+            conflicts,
+            op_return,
+        };
+        assert_eq!(
+            merge(&first, &second, &MergePolicy::default()),
+            Err(MergeError::Conflict(conflict))
+        );
+
+        let merged = merge(
+            &first,
+            &second,
+            &policy(ConflictPolicy::KeepFirst, OpReturnPolicy::STANDARD),
+        )
+        .unwrap();
+        assert_eq!(merged.conflicts, vec![conflict]);
+        assert_eq!(
+            merged.psbt.unsigned_tx.output,
+            vec![payment(1000), data(b"first"), payment(500)]
+        );
+
+        let merged = merge(
+            &first,
+            &second,
+            &policy(ConflictPolicy::KeepSecond, OpReturnPolicy::STANDARD),
+        )
+        .unwrap();
+        assert_eq!(
+            merged.psbt.unsigned_tx.output,
+            vec![payment(1000), data(b"second"), payment(500)]
+        );
+        assert_eq!(merged.psbt.outputs.len(), 3);
+
+        // Keeping both needs a policy allowing several OP_RETURN outputs
+        assert_eq!(
+            merge(
+                &first,
+                &second,
+                &policy(ConflictPolicy::KeepBoth, OpReturnPolicy::STANDARD)
+            ),
+            Err(MergeError::Policy(OpReturnError::MultipleOutputs))
+        );
+        let merged = merge(
+            &first,
+            &second,
+            &policy(ConflictPolicy::KeepBoth, OpReturnPolicy::CORE_V30),
+        )
+        .unwrap();
+        assert_eq!(merged.psbt.unsigned_tx.output.len(), 4);
+    }
+
+    #[test]
+    fn test_annex_conflicts() {
+        let mut first = psbt(vec![txin(1)], vec![payment(1000)]);
+        let mut second = first.clone();
+        set_annex_plan(&mut first.inputs[0], b"first".to_vec());
+        assert_eq!(annex_plan(&first.inputs[0]), Some(&b"first"[..]));
+
+        // A plan from one party is kept
+        let merged = merge(&first, &second, &MergePolicy::default()).unwrap();
+        assert_eq!(annex_plan(&merged.psbt.inputs[0]), Some(&b"first"[..]));
+
+        set_annex_plan(&mut second.inputs[0], b"second".to_vec());
+        let conflict = Conflict::Annex(txin(1).previous_output);
+        for conflicts in [ConflictPolicy::Reject, ConflictPolicy::KeepBoth] {
+            let policy = MergePolicy {
+                conflicts,
+                ..MergePolicy::default()
+            };
+            assert_eq!(
+                merge(&first, &second, &policy),
+                Err(MergeError::Conflict(conflict))
+            );
+        }
+
+        for (conflicts, expected) in [
+            (ConflictPolicy::KeepFirst, &b"first"[..]),
+            (ConflictPolicy::KeepSecond, &b"second"[..]),
+        ] {
+            let policy = MergePolicy {
+                conflicts,
+                ..MergePolicy::default()
+            };
+            let mut merged = merge(&first, &second, &policy).unwrap();
+            assert_eq!(merged.conflicts, vec![conflict]);
+            assert_eq!(
+                take_annex_plan(&mut merged.psbt.inputs[0]),
+                Some(expected.to_vec())
+            );
+            assert_eq!(annex_plan(&merged.psbt.inputs[0]), None);
+        }
+    }
+
+    #[test]
+    fn test_signer_summary() {
+        use crate::envelope::EnvelopeBuilder;
+        use crate::planner::Planner;
+
+        use bitcoin::opcodes::all::OP_CHECKSIG;
+        use bitcoin::script::Builder;
+        use bitcoin::secp256k1::{Keypair, Secp256k1};
+
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_seckey_slice(&secp, &[1; 32]).unwrap();
+        let (internal_key, _) = keypair.x_only_public_key();
+
+        let payload = [
+            b"Hello from a collaborative transaction! ".repeat(3),
+            vec![0xff; 100],
+        ]
+        .concat();
+        let layout = Planner::new().control_record(true).plan(&payload).unwrap();
+        let prefix = Builder::new()
+            .push_x_only_key(&internal_key)
+            .push_opcode(OP_CHECKSIG);
+        let leaf = EnvelopeBuilder::new()
+            .append_to_builder(vec![layout.segments[1].bytes.clone()], prefix)
+            .into_script();
+        let commit = CommitOutput::new(&secp, internal_key, vec![leaf.clone()]).unwrap();
+
+        // The commit transaction shows the envelope it commits to
+        let mut commit_psbt = psbt(vec![txin(1)], vec![]);
+        let mut output = Output::default();
+        set_commit_output(&mut output, &commit);
+        commit_psbt.unsigned_tx.output.push(TxOut {
+            value: Amount::from_sat(1000),
+            script_pubkey: commit.script_pubkey(),
+        });
+        commit_psbt.outputs.push(output);
+        assert_eq!(
+            summarize_for_signer(&commit_psbt),
+            "No embedded data\nOutput 0: commits to 1 envelope(s) with 140 bytes, revealed later"
+        );
+
+        // The reveal carries the header, envelope, and control record
+        let mut reveal = psbt(vec![txin(2)], vec![payment(500)]);
+        assert_eq!(
+            add_layout(&mut reveal, &layout, 0),
+            Err(PsbtError::MissingEnvelope)
+        );
+        assert_eq!(
+            set_reveal_input(&mut reveal.inputs[0], &commit, Script::new()),
+            Err(PsbtError::LeafNotCommitted)
+        );
+        set_reveal_input(&mut reveal.inputs[0], &commit, &leaf).unwrap();
+        assert_eq!(
+            add_layout(&mut reveal, &layout, 1),
+            Err(PsbtError::NoSuchInput(1))
+        );
+        add_layout(&mut reveal, &layout, 0).unwrap();
+        assert_eq!(reveal.unsigned_tx.output.len(), 2);
+        assert_eq!(reveal.inputs[0].tap_merkle_root, commit.merkle_root());
+
+        let summary = summarize_for_signer(&reveal);
+        let lines = summary.lines().collect::<Vec<_>>();
+        let control = layout.control_payload().unwrap().len();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            format!("Embeds {} bytes in 3 locations", payload.len() + control)
+        );
+        assert_eq!(
+            lines[1],
+            "Output 1 OP_RETURN: 80 bytes \"Hello from a collaborative trans...\""
+        );
+        assert_eq!(
+            lines[2],
+            "Input 0 envelope: 140 bytes 48656c6c6f2066726f6d206120636f6c..."
+        );
+        assert!(lines[3].starts_with(&format!("Input 0 annex: {control} bytes ")));
+    }
+}
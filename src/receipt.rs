@@ -0,0 +1,520 @@
+//! # Inclusion Receipts
+//!
+//! A [`Receipt`] is a self-contained SPV proof that an embedding was confirmed in a block:
+//! the block header and the headers built on it, the merkle branch from the transaction to the
+//! block's merkle root, the transaction, and the embedding id. Anchoring services hand receipts
+//! to their customers, who check them with [`verify`] without trusting the service or running a
+//! node.
+//!
+//! A receipt proves that the headers carry the proof-of-work they claim, not that they belong
+//! to the best chain, so verifiers should require a minimum amount of chain work, or check the
+//! receipt against a validated chain with [`HeaderChain::verify_receipt`].
+//!
+//! [`HeaderChain::verify_receipt`]: crate::header_chain::HeaderChain::verify_receipt
+
+use crate::{Embedding, EmbeddingId, EmbeddingIdError, varint};
+
+use bitcoin::block::Header;
+use bitcoin::consensus::encode::{deserialize_partial, serialize};
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::pow::Work;
+use bitcoin::{Block, BlockHash, Transaction, TxMerkleNode, Txid};
+use std::fmt;
+
+/// The version of the receipt encoding
+pub const RECEIPT_VERSION: u8 = 1;
+
+/// The merkle branch from a transaction to its block's merkle root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleBranch {
+    /// The position of the transaction in the block
+    pub position: u32,
+    /// The sibling hashes from the transaction to the root
+    pub hashes: Vec<TxMerkleNode>,
+}
+
+/// A proof that an embedding was confirmed in a block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Receipt {
+    /// The header of the block holding the transaction, followed by the headers built on it
+    pub headers: Vec<Header>,
+    /// The merkle branch from the transaction to the first header's merkle root
+    pub branch: MerkleBranch,
+    /// The transaction holding the embedding
+    pub tx: Transaction,
+    /// The id of the embedding
+    pub id: EmbeddingId,
+}
+
+/// The facts established by a valid receipt
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifiedReceipt {
+    /// The embedding
+    pub embedding: Embedding,
+    /// The hash of the block holding the transaction
+    pub block_hash: BlockHash,
+    /// The number of headers in the receipt, counting the block holding the transaction
+    pub confirmations: usize,
+    /// The work of the headers in the receipt
+    pub chain_work: Work,
+}
+
+/// Error types for creating, decoding, and verifying receipts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReceiptError {
+    /// The encoding has an unknown version
+    UnsupportedVersion(u8),
+    /// The encoding is truncated or malformed
+    InvalidFormat,
+    /// The embedding id could not be decoded
+    InvalidId(EmbeddingIdError),
+    /// LEB128 decoding error
+    VarInt(varint::Error),
+    /// The receipt has no headers
+    NoHeaders,
+    /// The header at this position does not build on the one before it
+    BrokenChain(usize),
+    /// The header at this position does not meet its own difficulty target
+    InvalidProofOfWork(usize),
+    /// The headers have less work than required
+    InsufficientWork,
+    /// The transaction is 64 bytes without its witness, and so could be mistaken for an inner
+    /// node of the merkle tree
+    AmbiguousTransaction,
+    /// The merkle branch does not lead to the first header's merkle root
+    MerkleMismatch,
+    /// The embedding id does not reference an embedding in the transaction
+    EmbeddingNotFound(EmbeddingId),
+}
+
+impl MerkleBranch {
+    /// Returns the branch for the transaction at `position` among a block's `txids`, or `None`
+    /// if there is no such transaction
+    pub fn from_txids(txids: &[Txid], position: usize) -> Option<Self> {
+        if position >= txids.len() {
+            return None;
+        }
+
+        let mut level = txids
+            .iter()
+            .map(|txid| TxMerkleNode::from_byte_array(txid.to_byte_array()))
+            .collect::<Vec<_>>();
+        let mut hashes = Vec::new();
+        let mut index = position;
+
+        while level.len() > 1 {
+            // An unpaired node is paired with itself, as in Bitcoin's merkle tree
+            if level.len() % 2 == 1 {
+                level.push(*level.last().expect("non-empty level"));
+            }
+            hashes.push(level[index ^ 1]);
+            level = level
+                .chunks_exact(2)
+                .map(|pair| parent(&pair[0], &pair[1]))
+                .collect();
+            index /= 2;
+        }
+
+        Some(Self {
+            position: u32::try_from(position).ok()?,
+            hashes,
+        })
+    }
+
+    /// Returns the branch for the transaction `txid` in `block`, or `None` if it is not there
+    pub fn from_block(block: &Block, txid: &Txid) -> Option<Self> {
+        let txids = block
+            .txdata
+            .iter()
+            .map(Transaction::compute_txid)
+            .collect::<Vec<_>>();
+        let position = txids.iter().position(|t| t == txid)?;
+        Self::from_txids(&txids, position)
+    }
+
+    /// Returns the merkle root implied by `txid` and this branch
+    pub fn root(&self, txid: &Txid) -> TxMerkleNode {
+        let mut hash = TxMerkleNode::from_byte_array(txid.to_byte_array());
+        let mut index = self.position;
+        for sibling in &self.hashes {
+            hash = match index % 2 {
+                0 => parent(&hash, sibling),
+                _ => parent(sibling, &hash),
+            };
+            index /= 2;
+        }
+        hash
+    }
+}
+
+/// Returns a receipt for the embedding `id` in `tx`, checking that the transaction holds the
+/// embedding and that `branch` leads from it to the merkle root of the first header. The
+/// headers start with the block holding `tx` and continue with the blocks built on it.
+pub fn create(
+    headers: Vec<Header>,
+    branch: MerkleBranch,
+    tx: Transaction,
+    id: EmbeddingId,
+) -> Result<Receipt, ReceiptError> {
+    let receipt = Receipt {
+        headers,
+        branch,
+        tx,
+        id,
+    };
+    receipt.check_inclusion()?;
+    Ok(receipt)
+}
+
+/// Verifies a receipt: the headers form a chain, each meets its difficulty target, together
+/// they carry at least `min_work`, and the transaction is included in the first one and holds
+/// the embedding
+pub fn verify(receipt: &Receipt, min_work: Work) -> Result<VerifiedReceipt, ReceiptError> {
+    let embedding = receipt.check_inclusion()?;
+
+    for (i, header) in receipt.headers.iter().enumerate() {
+        if i > 0 && header.prev_blockhash != receipt.headers[i - 1].block_hash() {
+            return Err(ReceiptError::BrokenChain(i));
+        }
+        header
+            .validate_pow(header.target())
+            .map_err(|_| ReceiptError::InvalidProofOfWork(i))?;
+    }
+
+    let chain_work = receipt.chain_work();
+    if chain_work < min_work {
+        return Err(ReceiptError::InsufficientWork);
+    }
+
+    Ok(VerifiedReceipt {
+        embedding,
+        block_hash: receipt.block_hash(),
+        confirmations: receipt.headers.len(),
+        chain_work,
+    })
+}
+
+impl Receipt {
+    /// Returns the hash of the block holding the transaction
+    pub fn block_hash(&self) -> BlockHash {
+        self.headers
+            .first()
+            .map_or(BlockHash::all_zeros(), Header::block_hash)
+    }
+
+    /// Returns the total work claimed by the headers
+    pub fn chain_work(&self) -> Work {
+        self.headers
+            .iter()
+            .map(Header::work)
+            .reduce(|total, work| total + work)
+            .unwrap_or(Work::from_be_bytes([0; 32]))
+    }
+
+    /// Serializes the receipt as the version byte, the LEB128 header count and the headers,
+    /// the LEB128 position and hash count and the branch hashes, the LEB128 length of the
+    /// transaction and the transaction, and the bytes of the embedding id
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![RECEIPT_VERSION];
+        varint::encode_to_vec(self.headers.len() as u128, &mut bytes);
+        for header in &self.headers {
+            bytes.extend(serialize(header));
+        }
+
+        varint::encode_to_vec(self.branch.position.into(), &mut bytes);
+        varint::encode_to_vec(self.branch.hashes.len() as u128, &mut bytes);
+        for hash in &self.branch.hashes {
+            bytes.extend(hash.as_byte_array());
+        }
+
+        let tx = serialize(&self.tx);
+        varint::encode_to_vec(tx.len() as u128, &mut bytes);
+        bytes.extend(tx);
+        bytes.extend(self.id.to_bytes());
+        bytes
+    }
+
+    /// Deserializes a receipt produced by [`Receipt::to_bytes`]. The receipt is not verified.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReceiptError> {
+        let (&version, mut rest) = bytes.split_first().ok_or(ReceiptError::InvalidFormat)?;
+        if version != RECEIPT_VERSION {
+            return Err(ReceiptError::UnsupportedVersion(version));
+        }
+
+        let mut headers = Vec::new();
+        for _ in 0..read_length(&mut rest)? {
+            headers.push(read_consensus(&mut rest, Header::SIZE)?);
+        }
+
+        let position = u32::try_from(read_varint(&mut rest)?)
+            .map_err(|_| ReceiptError::VarInt(varint::Error::Overflow))?;
+        let mut hashes = Vec::new();
+        for _ in 0..read_length(&mut rest)? {
+            let (hash, tail) = rest
+                .split_first_chunk()
+                .ok_or(ReceiptError::InvalidFormat)?;
+            hashes.push(TxMerkleNode::from_byte_array(*hash));
+            rest = tail;
+        }
+
+        let length = read_length(&mut rest)?;
+        let tx = read_consensus(&mut rest, length)?;
+        let id = EmbeddingId::from_bytes(rest).map_err(ReceiptError::InvalidId)?;
+
+        Ok(Self {
+            headers,
+            branch: MerkleBranch { position, hashes },
+            tx,
+            id,
+        })
+    }
+
+    /// Checks that the transaction is included in the first header and holds the embedding,
+    /// and returns the embedding
+    fn check_inclusion(&self) -> Result<Embedding, ReceiptError> {
+        let header = self.headers.first().ok_or(ReceiptError::NoHeaders)?;
+
+        let txid = self.tx.compute_txid();
+        if serialize(&stripped(&self.tx)).len() == 64 {
+            return Err(ReceiptError::AmbiguousTransaction);
+        }
+        if self.branch.root(&txid) != header.merkle_root {
+            return Err(ReceiptError::MerkleMismatch);
+        }
+
+        self.id
+            .locate(&self.tx)
+            .ok_or(ReceiptError::EmbeddingNotFound(self.id))
+    }
+}
+
+/// Returns the parent of two nodes in Bitcoin's merkle tree
+fn parent(left: &TxMerkleNode, right: &TxMerkleNode) -> TxMerkleNode {
+    let mut engine = TxMerkleNode::engine();
+    engine.input(left.as_byte_array());
+    engine.input(right.as_byte_array());
+    TxMerkleNode::from_engine(engine)
+}
+
+/// Returns a transaction without its witnesses
+fn stripped(tx: &Transaction) -> Transaction {
+    let mut tx = tx.clone();
+    tx.input.iter_mut().for_each(|input| input.witness.clear());
+    tx
+}
+
+fn read_varint(rest: &mut &[u8]) -> Result<u128, ReceiptError> {
+    let (n, length) = varint::decode_strict(rest)?;
+    *rest = &rest[length..];
+    Ok(n)
+}
+
+fn read_length(rest: &mut &[u8]) -> Result<usize, ReceiptError> {
+    let length = usize::try_from(read_varint(rest)?)
+        .map_err(|_| ReceiptError::VarInt(varint::Error::Overflow))?;
+    if length > rest.len() {
+        return Err(ReceiptError::InvalidFormat);
+    }
+    Ok(length)
+}
+
+fn read_consensus<T: bitcoin::consensus::Decodable>(
+    rest: &mut &[u8],
+    length: usize,
+) -> Result<T, ReceiptError> {
+    let bytes = rest.get(..length).ok_or(ReceiptError::InvalidFormat)?;
+    match deserialize_partial(bytes) {
+        Ok((value, n)) if n == length => {
+            *rest = &rest[length..];
+            Ok(value)
+        }
+        _ => Err(ReceiptError::InvalidFormat),
+    }
+}
+
+impl From<varint::Error> for ReceiptError {
+    fn from(e: varint::Error) -> Self {
+        ReceiptError::VarInt(e)
+    }
+}
+
+impl fmt::Display for ReceiptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReceiptError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported receipt version: {version}")
+            }
+            ReceiptError::InvalidFormat => write!(f, "Invalid receipt format"),
+            ReceiptError::InvalidId(e) => write!(f, "Invalid embedding id: {e}"),
+            ReceiptError::VarInt(e) => write!(f, "{e}"),
+            ReceiptError::NoHeaders => write!(f, "Receipt has no headers"),
+            ReceiptError::BrokenChain(i) => {
+                write!(f, "Header {i} does not build on the previous header")
+            }
+            ReceiptError::InvalidProofOfWork(i) => {
+                write!(f, "Header {i} does not meet its difficulty target")
+            }
+            ReceiptError::InsufficientWork => write!(f, "Receipt headers have too little work"),
+            ReceiptError::AmbiguousTransaction => {
+                write!(f, "Transaction is 64 bytes and could be a merkle node")
+            }
+            ReceiptError::MerkleMismatch => {
+                write!(f, "Merkle branch does not match the block's merkle root")
+            }
+            ReceiptError::EmbeddingNotFound(id) => {
+                write!(f, "Embedding {id} not found in the transaction")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReceiptError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::op_return_tx;
+
+    use bitcoin::CompactTarget;
+    use bitcoin::block::Version as BlockVersion;
+
+    /// Returns a regtest header mined on `prev_blockhash`
+    fn mine(prev_blockhash: BlockHash, merkle_root: TxMerkleNode) -> Header {
+        let mut header = Header {
+            version: BlockVersion::TWO,
+            prev_blockhash,
+            merkle_root,
+            time: 1_700_000_000,
+            bits: CompactTarget::from_consensus(0x207fffff),
+            nonce: 0,
+        };
+        while header.validate_pow(header.target()).is_err() {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    fn receipt() -> Receipt {
+        let txdata = (0..5).map(|n| op_return_tx(&[n; 20])).collect::<Vec<_>>();
+        let txids = txdata
+            .iter()
+            .map(Transaction::compute_txid)
+            .collect::<Vec<_>>();
+        let root = bitcoin::merkle_tree::calculate_root(txids.iter().copied())
+            .map(|root: Txid| TxMerkleNode::from_byte_array(root.to_byte_array()))
+            .unwrap();
+
+        let mut headers = vec![mine(BlockHash::all_zeros(), root)];
+        for _ in 0..2 {
+            let prev = headers.last().unwrap().block_hash();
+            headers.push(mine(prev, TxMerkleNode::all_zeros()));
+        }
+
+        let block = Block {
+            header: headers[0],
+            txdata: txdata.clone(),
+        };
+        let branch = MerkleBranch::from_block(&block, &txids[4]).unwrap();
+        let id = Embedding::from_transaction(&txdata[4])[0].id();
+        create(headers, branch, txdata[4].clone(), id).unwrap()
+    }
+
+    #[test]
+    fn test_branch() {
+        let txids = (0..7)
+            .map(|n| op_return_tx(&[n; 20]).compute_txid())
+            .collect::<Vec<_>>();
+        let root = bitcoin::merkle_tree::calculate_root(txids.iter().copied())
+            .map(|root: Txid| TxMerkleNode::from_byte_array(root.to_byte_array()))
+            .unwrap();
+
+        for (position, txid) in txids.iter().enumerate() {
+            let branch = MerkleBranch::from_txids(&txids, position).unwrap();
+            assert_eq!(branch.hashes.len(), 3);
+            assert_eq!(branch.root(txid), root);
+        }
+        assert_eq!(MerkleBranch::from_txids(&txids, 7), None);
+
+        let single = MerkleBranch::from_txids(&txids[..1], 0).unwrap();
+        assert_eq!(
+            single.root(&txids[0]).to_byte_array(),
+            txids[0].to_byte_array()
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let receipt = receipt();
+        let verified = verify(&receipt, receipt.headers[0].work()).unwrap();
+        assert_eq!(
+            verified.embedding,
+            Embedding::from_transaction(&receipt.tx)[0]
+        );
+        assert_eq!(verified.block_hash, receipt.headers[0].block_hash());
+        assert_eq!(verified.confirmations, 3);
+        assert_eq!(verified.chain_work, receipt.chain_work());
+
+        let bytes = receipt.to_bytes();
+        assert_eq!(bytes[0], RECEIPT_VERSION);
+        assert_eq!(Receipt::from_bytes(&bytes), Ok(receipt.clone()));
+        assert_eq!(
+            Receipt::from_bytes(&bytes[..bytes.len() - 40]),
+            Err(ReceiptError::InvalidFormat)
+        );
+
+        let mut other = bytes.clone();
+        other[0] = 2;
+        assert_eq!(
+            Receipt::from_bytes(&other),
+            Err(ReceiptError::UnsupportedVersion(2))
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let receipt = receipt();
+
+        let more_work = receipt.chain_work() + receipt.headers[0].work();
+        assert_eq!(
+            verify(&receipt, more_work),
+            Err(ReceiptError::InsufficientWork)
+        );
+
+        let mut unlinked = receipt.clone();
+        unlinked.headers.swap(1, 2);
+        assert_eq!(
+            verify(&unlinked, Work::from_be_bytes([0; 32])),
+            Err(ReceiptError::BrokenChain(1))
+        );
+
+        let mut unmined = receipt.clone();
+        unmined.headers[2].bits = CompactTarget::from_consensus(0x1d00ffff);
+        assert_eq!(
+            verify(&unmined, Work::from_be_bytes([0; 32])),
+            Err(ReceiptError::InvalidProofOfWork(2))
+        );
+
+        let mut moved = receipt.clone();
+        moved.branch.position = 3;
+        assert_eq!(
+            verify(&moved, Work::from_be_bytes([0; 32])),
+            Err(ReceiptError::MerkleMismatch)
+        );
+
+        let mut id = receipt.id;
+        id.index = 1;
+        assert_eq!(
+            create(
+                receipt.headers.clone(),
+                receipt.branch.clone(),
+                receipt.tx.clone(),
+                id
+            ),
+            Err(ReceiptError::EmbeddingNotFound(id))
+        );
+        assert_eq!(
+            create(vec![], receipt.branch, receipt.tx, receipt.id),
+            Err(ReceiptError::NoHeaders)
+        );
+    }
+}
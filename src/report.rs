@@ -0,0 +1,199 @@
+//! # Data Footprint Reports
+//!
+//! Summarizes how much of a transaction is devoted to embedded data. Each embedding is
+//! counted by its data bytes, weighted four units per byte in outputs and one unit per byte in
+//! witnesses, so the report shows both the raw size and the witness-discounted cost. Bare
+//! multisig outputs are not included, as in [`Embedding::from_transaction`].
+
+use crate::{Embedding, EmbeddingType};
+
+use bitcoin::{Transaction, TxOut, Weight};
+use std::collections::BTreeMap;
+
+/// The data carried by one type of embedding in a transaction
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TypeFootprint {
+    /// The number of embeddings
+    pub count: usize,
+    /// The total data bytes
+    pub bytes: usize,
+    /// The weight of the data bytes
+    pub weight: Weight,
+}
+
+/// The data carried by a transaction, by embedding type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FootprintReport {
+    /// The footprint of each embedding type present, ordered by type code
+    pub types: BTreeMap<EmbeddingType, TypeFootprint>,
+    /// The weight of the whole transaction
+    pub tx_weight: Weight,
+}
+
+impl TypeFootprint {
+    /// Returns the witness-discounted size of the data, in virtual bytes
+    pub fn vsize(&self) -> f64 {
+        self.weight.to_wu() as f64 / 4.0
+    }
+}
+
+impl FootprintReport {
+    /// Returns the number of embeddings
+    pub fn count(&self) -> usize {
+        self.types.values().map(|footprint| footprint.count).sum()
+    }
+
+    /// Returns the total data bytes
+    pub fn bytes(&self) -> usize {
+        self.types.values().map(|footprint| footprint.bytes).sum()
+    }
+
+    /// Returns the total weight of the data bytes
+    pub fn weight(&self) -> Weight {
+        self.types.values().map(|footprint| footprint.weight).sum()
+    }
+
+    /// Returns the witness-discounted size of the data, in virtual bytes
+    pub fn vsize(&self) -> f64 {
+        self.weight().to_wu() as f64 / 4.0
+    }
+
+    /// Returns the percentage of the transaction's weight devoted to data
+    pub fn percentage(&self) -> f64 {
+        if self.tx_weight == Weight::ZERO {
+            return 0.0;
+        }
+        100.0 * self.weight().to_wu() as f64 / self.tx_weight.to_wu() as f64
+    }
+}
+
+/// Returns the data footprint of `tx`
+pub fn data_footprint(tx: &Transaction) -> FootprintReport {
+    data_footprint_with_prevouts(tx, &[])
+}
+
+/// Returns the data footprint of `tx`, using the outputs spent by each input to classify
+/// witness data as in [`Embedding::from_transaction_with_prevouts`]
+pub fn data_footprint_with_prevouts(tx: &Transaction, prevouts: &[TxOut]) -> FootprintReport {
+    let mut types = BTreeMap::new();
+    for embedding in Embedding::from_transaction_with_prevouts(tx, prevouts) {
+        let embedding_type = embedding.to_type();
+        let scale = match embedding_type {
+            EmbeddingType::OpReturn | EmbeddingType::BareMultisig => 4,
+            EmbeddingType::TaprootAnnex | EmbeddingType::WitnessEnvelope(_) => 1,
+        };
+
+        let footprint = types.entry(embedding_type).or_insert(TypeFootprint {
+            count: 0,
+            bytes: 0,
+            weight: Weight::ZERO,
+        });
+        footprint.count += 1;
+        footprint.bytes += embedding.bytes.len();
+        footprint.weight += Weight::from_wu(scale * embedding.bytes.len() as u64);
+    }
+
+    FootprintReport {
+        types,
+        tx_weight: tx.weight(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ScriptType;
+    use crate::envelope::EnvelopeBuilder;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::script::Builder;
+    use bitcoin::{
+        Amount, OutPoint, ScriptBuf, Sequence, TxIn, Txid, Witness, absolute::LockTime,
+        transaction::Version,
+    };
+
+    #[test]
+    fn test_data_footprint() {
+        let tapscript = EnvelopeBuilder::new()
+            .append_to_builder(vec![vec![1; 100], vec![2; 50]], Builder::new())
+            .into_script();
+        let control_block = [vec![0xc0], vec![3; 32]].concat();
+        let annex = [vec![0x50, 0], vec![4; 30]].concat();
+
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::from_slice(&[tapscript.to_bytes(), control_block, annex]),
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey: ScriptBuf::new_op_return([5; 20]),
+                },
+                TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey: ScriptBuf::new_op_return([6; 10]),
+                },
+            ],
+        };
+
+        let report = data_footprint(&tx);
+        assert_eq!(report.count(), 4);
+        assert_eq!(
+            report.types[&EmbeddingType::OpReturn],
+            TypeFootprint {
+                count: 2,
+                bytes: 21 + 11,
+                weight: Weight::from_wu(4 * 32),
+            }
+        );
+        assert_eq!(
+            report.types[&EmbeddingType::WitnessEnvelope(ScriptType::Tapscript)],
+            TypeFootprint {
+                count: 1,
+                bytes: 150,
+                weight: Weight::from_wu(150),
+            }
+        );
+        assert_eq!(
+            report.types[&EmbeddingType::TaprootAnnex].weight,
+            Weight::from_wu(30)
+        );
+        assert_eq!(
+            report.types.keys().copied().collect::<Vec<_>>(),
+            vec![
+                EmbeddingType::OpReturn,
+                EmbeddingType::TaprootAnnex,
+                EmbeddingType::WitnessEnvelope(ScriptType::Tapscript),
+            ]
+        );
+
+        assert_eq!(report.bytes(), 32 + 150 + 30);
+        assert_eq!(report.weight(), Weight::from_wu(128 + 180));
+        assert_eq!(report.vsize(), 77.0);
+        assert_eq!(report.tx_weight, tx.weight());
+        assert_eq!(
+            report.percentage(),
+            100.0 * 308.0 / tx.weight().to_wu() as f64
+        );
+    }
+
+    #[test]
+    fn test_empty_footprint() {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![],
+        };
+
+        let report = data_footprint(&tx);
+        assert_eq!(report.count(), 0);
+        assert_eq!(report.weight(), Weight::ZERO);
+        assert!(report.percentage() < 1.0);
+    }
+}
@@ -0,0 +1,501 @@
+//! # Embedding Resolvers
+//!
+//! A [`Resolver`] looks up embeddings from a data source (RPC, Esplora, local files, ...). Sources
+//! only need to provide transactions and blocks; extraction is handled by the provided methods.
+
+use crate::message::{self, Message};
+use crate::{Embedding, EmbeddingId};
+
+use bitcoin::{Block, BlockHash, Transaction, TxOut, Txid};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// The default maximum number of pointers followed by [`Resolver::resolve_pointer`]
+pub const DEFAULT_POINTER_DEPTH: usize = 8;
+
+/// Error types for resolving embeddings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolveError {
+    /// The transaction is unknown to the source
+    TransactionNotFound(Txid),
+    /// The block is unknown to the source
+    BlockNotFound(BlockHash),
+    /// The transaction exists but does not contain the referenced embedding
+    EmbeddingNotFound(EmbeddingId),
+    /// The source failed to respond
+    Backend(String),
+    /// A chain of pointers revisits this embedding
+    PointerCycle(EmbeddingId),
+    /// A chain of pointers is longer than this depth limit
+    PointerDepthExceeded(usize),
+}
+
+/// An embedding together with the block that confirmed it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfirmedEmbedding {
+    /// The embedding
+    pub embedding: Embedding,
+    /// The hash of the confirming block
+    pub block_hash: BlockHash,
+    /// The height of the confirming block
+    pub height: u64,
+    /// The timestamp in the confirming block's header
+    pub time: u32,
+    /// The position of the embedding's transaction in the block
+    pub tx_index: usize,
+}
+
+impl ConfirmedEmbedding {
+    /// Returns every embedding in a block at `height`, in transaction order
+    pub fn from_block(block: &Block, height: u64) -> Vec<Self> {
+        Self::from_transactions(
+            block.block_hash(),
+            height,
+            block.header.time,
+            block.txdata.iter().map(|tx| (tx, &[][..])),
+        )
+    }
+
+    /// Returns every embedding in a block's transactions, classifying inputs by their prevouts
+    pub(crate) fn from_transactions<'a>(
+        block_hash: BlockHash,
+        height: u64,
+        time: u32,
+        txs: impl IntoIterator<Item = (&'a Transaction, &'a [TxOut])>,
+    ) -> Vec<Self> {
+        txs.into_iter()
+            .enumerate()
+            .flat_map(|(tx_index, (tx, prevouts))| {
+                Embedding::from_transaction_with_prevouts(tx, prevouts)
+                    .into_iter()
+                    .map(move |embedding| Self {
+                        embedding,
+                        block_hash,
+                        height,
+                        time,
+                        tx_index,
+                    })
+            })
+            .collect()
+    }
+}
+
+/// A source of transactions and blocks from which embeddings can be resolved
+pub trait Resolver {
+    /// Returns the transaction with the given txid
+    fn transaction(&self, txid: &Txid) -> Result<Transaction, ResolveError>;
+
+    /// Returns the block with the given hash
+    fn block(&self, hash: &BlockHash) -> Result<Block, ResolveError>;
+
+    /// Returns multiple transactions, in order. Backends that support batching should override
+    /// this to fetch them in a single round trip.
+    fn transactions(&self, txids: &[Txid]) -> Result<Vec<Transaction>, ResolveError> {
+        txids.iter().map(|txid| self.transaction(txid)).collect()
+    }
+
+    /// Returns the embedding referenced by an id
+    fn embedding(&self, id: &EmbeddingId) -> Result<Embedding, ResolveError> {
+        let tx = self.transaction(&id.txid)?;
+        id.locate(&tx).ok_or(ResolveError::EmbeddingNotFound(*id))
+    }
+
+    /// Returns the embeddings referenced by multiple ids, in order. Each transaction is fetched
+    /// once, however many ids reference it.
+    fn embeddings(&self, ids: &[EmbeddingId]) -> Result<Vec<Embedding>, ResolveError> {
+        let txids = unique_txids(ids);
+        let txs = self.transactions(&txids)?;
+        locate_all(ids, &txids, &txs)
+    }
+
+    /// Returns the embedding holding the content of the embedding referenced by `id`,
+    /// following pointer messages ([`message::pointer_to`]) at most `max_depth` times (e.g.
+    /// [`DEFAULT_POINTER_DEPTH`])
+    fn resolve_pointer(
+        &self,
+        id: &EmbeddingId,
+        max_depth: usize,
+    ) -> Result<Embedding, ResolveError> {
+        let mut chain = PointerChain::new(id, max_depth);
+        let mut embedding = self.embedding(id)?;
+        while let Some(target) = chain.next(&embedding)? {
+            embedding = self.embedding(&target)?;
+        }
+        Ok(embedding)
+    }
+
+    /// Returns every embedding in a transaction
+    fn embeddings_in_tx(&self, txid: &Txid) -> Result<Vec<Embedding>, ResolveError> {
+        let tx = self.transaction(txid)?;
+        Ok(Embedding::from_transaction(&tx))
+    }
+
+    /// Returns every embedding in a block, in transaction order
+    fn embeddings_in_block(&self, hash: &BlockHash) -> Result<Vec<Embedding>, ResolveError> {
+        let block = self.block(hash)?;
+        Ok(block
+            .txdata
+            .iter()
+            .flat_map(Embedding::from_transaction)
+            .collect())
+    }
+
+    /// Returns every embedding in the block at `height`, in transaction order, with its
+    /// confirmation metadata
+    fn confirmed_embeddings_in_block(
+        &self,
+        hash: &BlockHash,
+        height: u64,
+    ) -> Result<Vec<ConfirmedEmbedding>, ResolveError> {
+        let block = self.block(hash)?;
+        Ok(ConfirmedEmbedding::from_block(&block, height))
+    }
+}
+
+/// A resolver over transactions and blocks held in memory
+#[derive(Debug, Clone, Default)]
+pub struct MemoryResolver {
+    transactions: HashMap<Txid, Transaction>,
+    blocks: HashMap<BlockHash, Block>,
+}
+
+impl MemoryResolver {
+    /// Returns an empty resolver
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a transaction
+    pub fn insert_transaction(&mut self, tx: Transaction) {
+        self.transactions.insert(tx.compute_txid(), tx);
+    }
+
+    /// Adds a block and each of its transactions
+    pub fn insert_block(&mut self, block: Block) {
+        for tx in &block.txdata {
+            self.insert_transaction(tx.clone());
+        }
+        self.blocks.insert(block.block_hash(), block);
+    }
+}
+
+impl Resolver for MemoryResolver {
+    fn transaction(&self, txid: &Txid) -> Result<Transaction, ResolveError> {
+        self.transactions
+            .get(txid)
+            .cloned()
+            .ok_or(ResolveError::TransactionNotFound(*txid))
+    }
+
+    fn block(&self, hash: &BlockHash) -> Result<Block, ResolveError> {
+        self.blocks
+            .get(hash)
+            .cloned()
+            .ok_or(ResolveError::BlockNotFound(*hash))
+    }
+}
+
+/// Returns the distinct txids referenced by `ids`, in order of first reference
+pub(crate) fn unique_txids(ids: &[EmbeddingId]) -> Vec<Txid> {
+    let mut seen = HashSet::new();
+    ids.iter()
+        .map(|id| id.txid)
+        .filter(|txid| seen.insert(*txid))
+        .collect()
+}
+
+/// Locates each id in the transaction fetched for its txid
+pub(crate) fn locate_all(
+    ids: &[EmbeddingId],
+    txids: &[Txid],
+    txs: &[Transaction],
+) -> Result<Vec<Embedding>, ResolveError> {
+    let txs = txids.iter().zip(txs).collect::<HashMap<_, _>>();
+    ids.iter()
+        .map(|id| {
+            let tx = txs
+                .get(&id.txid)
+                .ok_or(ResolveError::TransactionNotFound(id.txid))?;
+            id.locate(tx).ok_or(ResolveError::EmbeddingNotFound(*id))
+        })
+        .collect()
+}
+
+/// Tracks the embeddings visited while following pointers
+pub(crate) struct PointerChain {
+    visited: HashSet<EmbeddingId>,
+    max_depth: usize,
+}
+
+impl PointerChain {
+    pub(crate) fn new(id: &EmbeddingId, max_depth: usize) -> Self {
+        Self {
+            visited: HashSet::from([*id]),
+            max_depth,
+        }
+    }
+
+    /// Returns the pointer target of `embedding`, or `None` if it holds content
+    pub(crate) fn next(
+        &mut self,
+        embedding: &Embedding,
+    ) -> Result<Option<EmbeddingId>, ResolveError> {
+        let Some(target) = Message::decode(&embedding.payload())
+            .ok()
+            .and_then(|messages| message::pointer_target(&messages))
+        else {
+            return Ok(None);
+        };
+
+        if self.visited.len() > self.max_depth {
+            return Err(ResolveError::PointerDepthExceeded(self.max_depth));
+        }
+
+        if !self.visited.insert(target) {
+            return Err(ResolveError::PointerCycle(target));
+        }
+
+        Ok(Some(target))
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveError::TransactionNotFound(txid) => write!(f, "Transaction not found: {txid}"),
+            ResolveError::BlockNotFound(hash) => write!(f, "Block not found: {hash}"),
+            ResolveError::EmbeddingNotFound(id) => write!(f, "Embedding not found: {id}"),
+            ResolveError::Backend(e) => write!(f, "Backend error: {e}"),
+            ResolveError::PointerCycle(id) => write!(f, "Pointer cycle at embedding: {id}"),
+            ResolveError::PointerDepthExceeded(depth) => {
+                write!(f, "Pointer chain exceeds depth limit of {depth}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{FixtureBuilder, chain, op_return_tx};
+
+    use bitcoin::hashes::Hash;
+    use std::str::FromStr;
+
+    /// Returns a transaction with an `OP_RETURN` and a witness envelope embedding
+    fn transaction(data: &[u8]) -> Transaction {
+        FixtureBuilder::new(0)
+            .op_return(&data[..2])
+            .legacy_envelope(vec![data.to_vec()])
+            .build()
+            .tx
+    }
+
+    #[test]
+    fn test_memory_resolver() {
+        let tx1 = transaction(b"first");
+        let tx2 = transaction(b"second");
+        let block = chain(vec![vec![tx1.clone(), tx2.clone()]]).remove(0);
+        let hash = block.block_hash();
+
+        let mut resolver = MemoryResolver::new();
+        resolver.insert_block(block);
+
+        let embeddings = resolver.embeddings_in_tx(&tx1.compute_txid()).unwrap();
+        assert_eq!(embeddings, Embedding::from_transaction(&tx1));
+        assert_eq!(embeddings.len(), 2);
+
+        for embedding in &embeddings {
+            assert_eq!(resolver.embedding(&embedding.id()).as_ref(), Ok(embedding));
+        }
+
+        let in_block = resolver.embeddings_in_block(&hash).unwrap();
+        assert_eq!(in_block.len(), 4);
+        assert_eq!(in_block[..2], embeddings[..]);
+        assert_eq!(in_block[2..], Embedding::from_transaction(&tx2)[..]);
+
+        let confirmed = resolver.confirmed_embeddings_in_block(&hash, 7).unwrap();
+        assert_eq!(
+            confirmed.iter().map(|c| &c.embedding).collect::<Vec<_>>(),
+            in_block.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            confirmed.iter().map(|c| c.tx_index).collect::<Vec<_>>(),
+            [0, 0, 1, 1]
+        );
+        assert!(
+            confirmed
+                .iter()
+                .all(|c| c.block_hash == hash && c.height == 7 && c.time == 1_700_000_000)
+        );
+    }
+
+    /// Counts calls to [`Resolver::transactions`]
+    struct Counting(MemoryResolver, std::cell::Cell<usize>);
+
+    impl Resolver for Counting {
+        fn transaction(&self, txid: &Txid) -> Result<Transaction, ResolveError> {
+            self.0.transaction(txid)
+        }
+
+        fn block(&self, hash: &BlockHash) -> Result<Block, ResolveError> {
+            self.0.block(hash)
+        }
+
+        fn transactions(&self, txids: &[Txid]) -> Result<Vec<Transaction>, ResolveError> {
+            self.1.set(self.1.get() + txids.len());
+            self.0.transactions(txids)
+        }
+    }
+
+    #[test]
+    fn test_embeddings_batch() {
+        let tx1 = transaction(b"first");
+        let tx2 = transaction(b"second");
+
+        let mut inner = MemoryResolver::new();
+        inner.insert_transaction(tx1.clone());
+        inner.insert_transaction(tx2.clone());
+        let resolver = Counting(inner, Default::default());
+
+        let e1 = Embedding::from_transaction(&tx1);
+        let e2 = Embedding::from_transaction(&tx2);
+        let ids = [e2[1].id(), e1[0].id(), e2[0].id(), e1[1].id()];
+
+        assert_eq!(
+            resolver.embeddings(&ids),
+            Ok(vec![
+                e2[1].clone(),
+                e1[0].clone(),
+                e2[0].clone(),
+                e1[1].clone()
+            ])
+        );
+        assert_eq!(resolver.1.get(), 2);
+
+        let missing = EmbeddingId::from_str(&format!("{}:ta:0", tx1.compute_txid())).unwrap();
+        assert_eq!(
+            resolver.embeddings(&[e2[0].id(), missing]),
+            Err(ResolveError::EmbeddingNotFound(missing))
+        );
+        assert_eq!(resolver.embeddings(&[]), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_memory_resolver_not_found() {
+        let tx = transaction(b"data");
+        let txid = tx.compute_txid();
+
+        let mut resolver = MemoryResolver::new();
+        assert_eq!(
+            resolver.embeddings_in_tx(&txid),
+            Err(ResolveError::TransactionNotFound(txid))
+        );
+        assert_eq!(
+            resolver.embeddings_in_block(&BlockHash::all_zeros()),
+            Err(ResolveError::BlockNotFound(BlockHash::all_zeros()))
+        );
+
+        resolver.insert_transaction(tx);
+
+        let id = EmbeddingId::from_str(&format!("{txid}:ta:0")).unwrap();
+        assert_eq!(
+            resolver.embedding(&id),
+            Err(ResolveError::EmbeddingNotFound(id))
+        );
+    }
+
+    /// Serves embeddings directly by id
+    struct Embeddings(HashMap<EmbeddingId, Embedding>);
+
+    impl Resolver for Embeddings {
+        fn transaction(&self, txid: &Txid) -> Result<Transaction, ResolveError> {
+            Err(ResolveError::TransactionNotFound(*txid))
+        }
+
+        fn block(&self, hash: &BlockHash) -> Result<Block, ResolveError> {
+            Err(ResolveError::BlockNotFound(*hash))
+        }
+
+        fn embedding(&self, id: &EmbeddingId) -> Result<Embedding, ResolveError> {
+            self.0
+                .get(id)
+                .cloned()
+                .ok_or(ResolveError::EmbeddingNotFound(*id))
+        }
+    }
+
+    fn annex(byte: u8, bytes: Vec<u8>) -> Embedding {
+        Embedding {
+            bytes,
+            txid: Txid::from_byte_array([byte; 32]),
+            location: crate::EmbeddingLocation::TaprootAnnex {
+                input: 0,
+                index: 0,
+                tag: 0,
+            },
+        }
+    }
+
+    fn pointer(byte: u8, target: &Embedding) -> Embedding {
+        annex(
+            byte,
+            Message::encode(vec![message::pointer_to(&target.id())]),
+        )
+    }
+
+    #[test]
+    fn test_resolve_pointer() {
+        let content = annex(0, b"content".to_vec());
+        let first = pointer(1, &content);
+        let second = pointer(2, &first);
+
+        let resolver = Embeddings(HashMap::from(
+            [&content, &first, &second].map(|embedding| (embedding.id(), embedding.clone())),
+        ));
+
+        assert_eq!(
+            resolver.resolve_pointer(&content.id(), 0),
+            Ok(content.clone())
+        );
+        assert_eq!(
+            resolver.resolve_pointer(&first.id(), 1),
+            Ok(content.clone())
+        );
+        assert_eq!(
+            resolver.resolve_pointer(&second.id(), DEFAULT_POINTER_DEPTH),
+            Ok(content.clone())
+        );
+        assert_eq!(
+            resolver.resolve_pointer(&second.id(), 1),
+            Err(ResolveError::PointerDepthExceeded(1))
+        );
+
+        // Pointers in an OP_RETURN output are read from its pushes
+        let tx = op_return_tx(&first.bytes);
+        let mut resolver = MemoryResolver::new();
+        resolver.insert_transaction(tx.clone());
+        assert_eq!(
+            resolver.resolve_pointer(&Embedding::from_transaction(&tx)[0].id(), 1),
+            Err(ResolveError::TransactionNotFound(content.txid))
+        );
+    }
+
+    #[test]
+    fn test_resolve_pointer_cycle() {
+        let first = annex(1, Vec::new());
+        let second = pointer(2, &first);
+        let first = pointer(1, &second);
+
+        let resolver = Embeddings(HashMap::from(
+            [&first, &second].map(|embedding| (embedding.id(), embedding.clone())),
+        ));
+
+        assert_eq!(
+            resolver.resolve_pointer(&first.id(), DEFAULT_POINTER_DEPTH),
+            Err(ResolveError::PointerCycle(first.id()))
+        );
+    }
+}
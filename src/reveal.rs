@@ -0,0 +1,318 @@
+//! # Reveal Signing
+//!
+//! Signs the input that reveals an envelope leaf. The input must carry the leaf and its
+//! control block in its `tap_scripts` ([`psbt::set_reveal_input`](crate::psbt::set_reveal_input)),
+//! and every input its `witness_utxo`, since a taproot sighash commits to all spent outputs.
+//! A planned annex ([`psbt::set_annex_plan`](crate::psbt::set_annex_plan)) is committed to by
+//! the sighash and appended to the witness, so it cannot be added after signing.
+
+use crate::annex::{self, AnnexError};
+use crate::psbt::annex_plan;
+
+use bitcoin::hashes::Hash;
+use bitcoin::key::Keypair;
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1::{self, Secp256k1};
+use bitcoin::sighash::{Annex, Prevouts, SighashCache, TapSighashType};
+use bitcoin::taproot::{self, LeafVersion, TapLeafHash};
+use bitcoin::{Script, Witness};
+use std::fmt;
+
+/// Error types for signing reveal inputs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RevealError {
+    /// The PSBT has no input at this index
+    NoSuchInput(usize),
+    /// The input at this index has no `witness_utxo`
+    MissingPrevout(usize),
+    /// The input has no control block for the leaf
+    LeafNotFound,
+    /// The input's sighash type is not a taproot sighash type
+    InvalidSighashType,
+    /// The planned annex could not be appended
+    Annex(AnnexError),
+    /// The sighash could not be computed
+    Sighash(String),
+}
+
+/// Signs the reveal of `leaf` at `input_index` with `keypair`'s (untweaked) key, using the
+/// input's sighash type or `SIGHASH_DEFAULT`. Records the signature in `tap_script_sigs` and
+/// sets the final witness: the signature, the leaf script, its control block, and the planned
+/// annex, if any. Leaves that need more than one signature are not finalized this way.
+pub fn sign_input(
+    psbt: &mut Psbt,
+    input_index: usize,
+    keypair: &Keypair,
+    leaf: &Script,
+) -> Result<(), RevealError> {
+    let input = psbt
+        .inputs
+        .get(input_index)
+        .ok_or(RevealError::NoSuchInput(input_index))?;
+    let control_block = input
+        .tap_scripts
+        .iter()
+        .find(|(_, (script, version))| {
+            script.as_script() == leaf && *version == LeafVersion::TapScript
+        })
+        .map(|(control_block, _)| control_block.clone())
+        .ok_or(RevealError::LeafNotFound)?;
+    let sighash_type = match input.sighash_type {
+        Some(sighash_type) => sighash_type
+            .taproot_hash_ty()
+            .map_err(|_| RevealError::InvalidSighashType)?,
+        None => TapSighashType::Default,
+    };
+
+    // The witness after the signature, which the annex is appended to
+    let mut witness = Witness::from_slice(&[leaf.to_bytes(), control_block.serialize()]);
+    if let Some(data) = annex_plan(input) {
+        annex::append_data(&mut witness, data).map_err(RevealError::Annex)?;
+    }
+    let annex = witness
+        .taproot_annex()
+        .map(|annex| Annex::new(annex).expect("annex prefix"));
+
+    let prevouts = psbt
+        .inputs
+        .iter()
+        .enumerate()
+        .map(|(i, input)| {
+            input
+                .witness_utxo
+                .clone()
+                .ok_or(RevealError::MissingPrevout(i))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let leaf_hash = TapLeafHash::from_script(leaf, LeafVersion::TapScript);
+    let sighash = SighashCache::new(&psbt.unsigned_tx)
+        .taproot_signature_hash(
+            input_index,
+            &Prevouts::All(&prevouts),
+            annex,
+            Some((leaf_hash, u32::MAX)),
+            sighash_type,
+        )
+        .map_err(|e| RevealError::Sighash(e.to_string()))?;
+
+    let secp = Secp256k1::signing_only();
+    let msg = secp256k1::Message::from_digest(sighash.to_byte_array());
+    let signature = taproot::Signature {
+        signature: secp.sign_schnorr_no_aux_rand(&msg, keypair),
+        sighash_type,
+    };
+
+    let input = &mut psbt.inputs[input_index];
+    input
+        .tap_script_sigs
+        .insert((keypair.x_only_public_key().0, leaf_hash), signature);
+    input.final_script_witness = Some([vec![signature.to_vec()], witness.to_vec()].concat().into());
+    Ok(())
+}
+
+impl fmt::Display for RevealError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RevealError::NoSuchInput(input) => write!(f, "PSBT has no input {input}"),
+            RevealError::MissingPrevout(input) => write!(f, "Input {input} has no witness UTXO"),
+            RevealError::LeafNotFound => write!(f, "Input has no control block for the leaf"),
+            RevealError::InvalidSighashType => write!(f, "Invalid taproot sighash type"),
+            RevealError::Annex(e) => write!(f, "Annex error: {e}"),
+            RevealError::Sighash(e) => write!(f, "Sighash error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for RevealError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::{self, CommitOutput};
+    use crate::envelope::EnvelopeBuilder;
+    use crate::psbt::{set_annex_plan, set_reveal_input};
+    use crate::{Embedding, EmbeddingType, ScriptType};
+
+    use bitcoin::opcodes::all::OP_CHECKSIG;
+    use bitcoin::psbt::PsbtSighashType;
+    use bitcoin::script::Builder;
+    use bitcoin::{
+        Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, absolute::LockTime,
+        transaction::Version,
+    };
+
+    struct Reveal {
+        psbt: Psbt,
+        keypair: Keypair,
+        leaf: ScriptBuf,
+        commit: CommitOutput,
+    }
+
+    fn reveal() -> Reveal {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_seckey_slice(&secp, &[2; 32]).unwrap();
+        let (key, _) = keypair.x_only_public_key();
+
+        let prefix = Builder::new()
+            .push_x_only_key(&key)
+            .push_opcode(OP_CHECKSIG);
+        let leaf = EnvelopeBuilder::new()
+            .append_to_builder(vec![b"revealed".to_vec()], prefix)
+            .into_script();
+        let commit = CommitOutput::new(&secp, key, vec![leaf.clone()]).unwrap();
+
+        let mut psbt = Psbt::from_unsigned_tx(Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: [1, 2]
+                .map(|n| TxIn {
+                    previous_output: OutPoint::new(Txid::from_byte_array([n; 32]), 0),
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                })
+                .to_vec(),
+            output: vec![TxOut {
+                value: Amount::from_sat(1000),
+                script_pubkey: commit.script_pubkey(),
+            }],
+        })
+        .unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: Amount::from_sat(2000),
+            script_pubkey: commit.script_pubkey(),
+        });
+        set_reveal_input(&mut psbt.inputs[0], &commit, &leaf).unwrap();
+
+        Reveal {
+            psbt,
+            keypair,
+            leaf,
+            commit,
+        }
+    }
+
+    /// Checks the signature in the final witness against the sighash with `annex`
+    fn verify(reveal: &Reveal, annex: Option<&[u8]>) -> bool {
+        let witness = reveal.psbt.inputs[0].final_script_witness.as_ref().unwrap();
+        let signature = taproot::Signature::from_slice(&witness[0]).unwrap();
+        let prevouts = reveal
+            .psbt
+            .inputs
+            .iter()
+            .map(|input| input.witness_utxo.clone().unwrap())
+            .collect::<Vec<_>>();
+        let sighash = SighashCache::new(&reveal.psbt.unsigned_tx)
+            .taproot_signature_hash(
+                0,
+                &Prevouts::All(&prevouts),
+                annex.map(|annex| Annex::new(annex).unwrap()),
+                Some((
+                    TapLeafHash::from_script(&reveal.leaf, LeafVersion::TapScript),
+                    u32::MAX,
+                )),
+                signature.sighash_type,
+            )
+            .unwrap();
+        Secp256k1::verification_only()
+            .verify_schnorr(
+                &signature.signature,
+                &secp256k1::Message::from_digest(sighash.to_byte_array()),
+                &reveal.keypair.x_only_public_key().0,
+            )
+            .is_ok()
+    }
+
+    #[test]
+    fn test_sign_input() {
+        let mut reveal = reveal();
+        let (keypair, leaf) = (reveal.keypair, reveal.leaf.clone());
+        assert_eq!(
+            sign_input(&mut reveal.psbt, 0, &keypair, &leaf),
+            Err(RevealError::MissingPrevout(1))
+        );
+
+        reveal.psbt.inputs[1].witness_utxo = Some(TxOut {
+            value: Amount::from_sat(3000),
+            script_pubkey: ScriptBuf::new_op_return([]),
+        });
+        sign_input(&mut reveal.psbt, 0, &keypair, &leaf).unwrap();
+        assert!(verify(&reveal, None));
+        assert_eq!(reveal.psbt.inputs[0].tap_script_sigs.len(), 1);
+
+        let witness = reveal.psbt.inputs[0].final_script_witness.clone().unwrap();
+        assert_eq!(witness.len(), 3);
+        assert_eq!(witness[0].len(), 64);
+        assert_eq!(
+            witness.taproot_leaf_script().unwrap().script,
+            leaf.as_script()
+        );
+        assert_eq!(
+            Some(witness.taproot_control_block().unwrap().to_vec()),
+            reveal
+                .commit
+                .control_block(&leaf)
+                .map(|control_block| control_block.serialize())
+        );
+        assert!(descriptor::verify_tapscript_commitment(
+            &witness,
+            &reveal.commit.script_pubkey()
+        ));
+
+        let mut tx = reveal.psbt.unsigned_tx.clone();
+        tx.input[0].witness = witness;
+        let embeddings = Embedding::from_transaction(&tx);
+        assert_eq!(embeddings.len(), 1);
+        assert_eq!(
+            embeddings[0].to_type(),
+            EmbeddingType::WitnessEnvelope(ScriptType::Tapscript)
+        );
+        assert_eq!(embeddings[0].bytes, b"revealed");
+
+        // An explicit sighash type is appended to the signature
+        reveal.psbt.inputs[0].sighash_type = Some(PsbtSighashType::from(TapSighashType::All));
+        sign_input(&mut reveal.psbt, 0, &keypair, &leaf).unwrap();
+        assert_eq!(
+            reveal.psbt.inputs[0].final_script_witness.as_ref().unwrap()[0].len(),
+            65
+        );
+        assert!(verify(&reveal, None));
+
+        assert_eq!(
+            sign_input(&mut reveal.psbt, 2, &keypair, &leaf),
+            Err(RevealError::NoSuchInput(2))
+        );
+        assert_eq!(
+            sign_input(&mut reveal.psbt, 1, &keypair, &leaf),
+            Err(RevealError::LeafNotFound)
+        );
+    }
+
+    #[test]
+    fn test_sign_input_with_annex() {
+        let mut reveal = reveal();
+        let (keypair, leaf) = (reveal.keypair, reveal.leaf.clone());
+        reveal.psbt.inputs[1].witness_utxo = Some(TxOut {
+            value: Amount::from_sat(3000),
+            script_pubkey: ScriptBuf::new_op_return([]),
+        });
+        set_annex_plan(&mut reveal.psbt.inputs[0], b"annex data".to_vec());
+
+        sign_input(&mut reveal.psbt, 0, &keypair, &leaf).unwrap();
+        let witness = reveal.psbt.inputs[0].final_script_witness.clone().unwrap();
+        assert_eq!(witness.len(), 4);
+        let annex = witness.taproot_annex().unwrap();
+        assert!(verify(&reveal, Some(annex)));
+        assert!(!verify(&reveal, None));
+
+        let mut tx = reveal.psbt.unsigned_tx.clone();
+        tx.input[0].witness = witness;
+        let embeddings = Embedding::from_transaction(&tx);
+        assert!(
+            embeddings
+                .iter()
+                .any(|e| e.to_type() == EmbeddingType::TaprootAnnex && e.bytes == b"annex data")
+        );
+    }
+}
@@ -0,0 +1,608 @@
+//! # Bitcoin Core RPC Backend
+//!
+//! A [`Resolver`] backed by Bitcoin Core's JSON-RPC interface. Transactions are fetched with
+//! `getrawtransaction` (requires `-txindex` for confirmed transactions outside the wallet) and
+//! blocks with `getblock`. Block scans use verbosity 3 (Bitcoin Core 23.0+) so each input's
+//! prevout is available for accurate classification.
+
+use crate::Embedding;
+use crate::follower::ChainSource;
+use crate::policy::PolicyViolation;
+use crate::prefilter::FilterSource;
+use crate::resolver::{ConfirmedEmbedding, ResolveError, Resolver};
+
+use bitcoin::bip158::BlockFilter;
+use bitcoin::block::Header;
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bitcoin::hex::FromHex;
+use bitcoin::{Amount, Block, BlockHash, ScriptBuf, Transaction, TxOut, Txid};
+use jsonrpc::simple_http::SimpleHttpTransport;
+use jsonrpc::{Client, Request};
+use serde_json::{Value, json};
+
+#[cfg(feature = "async")]
+mod nonblocking;
+#[cfg(feature = "async")]
+pub use nonblocking::AsyncRpcResolver;
+
+/// Bitcoin Core's error code for unknown transactions and blocks (`RPC_INVALID_ADDRESS_OR_KEY`)
+const RPC_NOT_FOUND: i32 = -5;
+
+/// Whether the node's mempool would accept a transaction, as reported by `testmempoolaccept`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Acceptance {
+    /// The transaction would be accepted
+    Accepted {
+        /// The virtual size, in vbytes
+        vsize: u64,
+        /// The fee paid
+        fee: Amount,
+    },
+    /// The transaction would be rejected
+    Rejected(PolicyViolation),
+}
+
+impl Acceptance {
+    /// Returns whether the transaction would be accepted
+    pub fn is_accepted(&self) -> bool {
+        matches!(self, Acceptance::Accepted { .. })
+    }
+}
+
+/// A resolver backed by Bitcoin Core's JSON-RPC interface
+#[derive(Debug)]
+pub struct RpcResolver {
+    client: Client,
+}
+
+impl RpcResolver {
+    /// Connects to a node over HTTP with optional user/password authentication
+    pub fn new(url: &str, user: Option<&str>, pass: Option<&str>) -> Result<Self, ResolveError> {
+        let mut builder = SimpleHttpTransport::builder()
+            .url(url)
+            .map_err(|e| ResolveError::Backend(e.to_string()))?;
+
+        if let Some(user) = user {
+            builder = builder.auth(user, pass);
+        }
+
+        Ok(Self::with_client(Client::with_transport(builder.build())))
+    }
+
+    /// Wraps an existing JSON-RPC client
+    pub fn with_client(client: Client) -> Self {
+        Self { client }
+    }
+
+    /// Returns the hash of the block at a height in the best chain
+    pub fn block_hash(&self, height: u64) -> Result<BlockHash, ResolveError> {
+        let hash = self
+            .call("getblockhash", json!([height]))
+            .map_err(backend_error)?;
+        hash.as_str()
+            .and_then(|hash| hash.parse().ok())
+            .ok_or_else(|| invalid_response("invalid block hash"))
+    }
+
+    /// Returns the transactions in a block with the outputs spent by each input. Coinbase
+    /// transactions have no prevouts.
+    pub fn block_with_prevouts(
+        &self,
+        hash: &BlockHash,
+    ) -> Result<Vec<(Transaction, Vec<TxOut>)>, ResolveError> {
+        parse_block_with_prevouts(&self.verbose_block(hash)?)
+    }
+
+    /// Checks whether the node's mempool would accept `tx` with `testmempoolaccept`, without
+    /// broadcasting it, mapping a rejection to a [`PolicyViolation`]
+    pub fn check_acceptance(&self, tx: &Transaction) -> Result<Acceptance, ResolveError> {
+        let results = self
+            .call("testmempoolaccept", json!([[serialize_hex(tx)]]))
+            .map_err(backend_error)?;
+        parse_acceptance(&results[0])
+    }
+
+    fn verbose_block(&self, hash: &BlockHash) -> Result<Value, ResolveError> {
+        self.call("getblock", json!([hash.to_string(), 3]))
+            .map_err(|e| rpc_error(e, ResolveError::BlockNotFound(*hash)))
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value, jsonrpc::Error> {
+        let params = jsonrpc::arg(params);
+        self.client.call(method, Some(&params))
+    }
+}
+
+impl Resolver for RpcResolver {
+    fn transaction(&self, txid: &Txid) -> Result<Transaction, ResolveError> {
+        let hex = self
+            .call("getrawtransaction", json!([txid.to_string(), false]))
+            .map_err(|e| rpc_error(e, ResolveError::TransactionNotFound(*txid)))?;
+        decode_hex(&hex)
+    }
+
+    /// Fetches all transactions in a single batched request
+    fn transactions(&self, txids: &[Txid]) -> Result<Vec<Transaction>, ResolveError> {
+        if txids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let params = txids
+            .iter()
+            .map(|txid| jsonrpc::arg(json!([txid.to_string(), false])))
+            .collect::<Vec<_>>();
+        let requests = params
+            .iter()
+            .map(|params| self.client.build_request("getrawtransaction", Some(params)))
+            .collect::<Vec<Request>>();
+
+        let responses = self.client.send_batch(&requests).map_err(backend_error)?;
+
+        txids
+            .iter()
+            .zip(responses)
+            .map(|(txid, response)| {
+                let response = response.ok_or_else(|| {
+                    ResolveError::Backend(format!("missing batch response for {txid}"))
+                })?;
+                let hex: Value = response
+                    .result()
+                    .map_err(|e| rpc_error(e, ResolveError::TransactionNotFound(*txid)))?;
+                decode_hex(&hex)
+            })
+            .collect()
+    }
+
+    fn block(&self, hash: &BlockHash) -> Result<Block, ResolveError> {
+        let hex = self
+            .call("getblock", json!([hash.to_string(), 0]))
+            .map_err(|e| rpc_error(e, ResolveError::BlockNotFound(*hash)))?;
+        decode_hex(&hex)
+    }
+
+    fn embeddings_in_block(&self, hash: &BlockHash) -> Result<Vec<Embedding>, ResolveError> {
+        Ok(self
+            .block_with_prevouts(hash)?
+            .iter()
+            .flat_map(|(tx, prevouts)| Embedding::from_transaction_with_prevouts(tx, prevouts))
+            .collect())
+    }
+
+    fn confirmed_embeddings_in_block(
+        &self,
+        hash: &BlockHash,
+        height: u64,
+    ) -> Result<Vec<ConfirmedEmbedding>, ResolveError> {
+        parse_confirmed_embeddings(&self.verbose_block(hash)?, hash, height)
+    }
+}
+
+impl ChainSource for RpcResolver {
+    fn best_height(&self) -> Result<u64, ResolveError> {
+        self.call("getblockcount", json!([]))
+            .map_err(backend_error)?
+            .as_u64()
+            .ok_or_else(|| invalid_response("invalid block count"))
+    }
+
+    fn block_hash_at(&self, height: u64) -> Result<BlockHash, ResolveError> {
+        self.block_hash(height)
+    }
+
+    fn block_header(&self, hash: &BlockHash) -> Result<Header, ResolveError> {
+        let hex = self
+            .call("getblockheader", json!([hash.to_string(), false]))
+            .map_err(|e| rpc_error(e, ResolveError::BlockNotFound(*hash)))?;
+        decode_hex(&hex)
+    }
+}
+
+impl FilterSource for RpcResolver {
+    /// Fetches the filter with `getblockfilter`, which requires `-blockfilterindex`
+    fn block_filter(&self, hash: &BlockHash) -> Result<BlockFilter, ResolveError> {
+        let response = self
+            .call("getblockfilter", json!([hash.to_string(), "basic"]))
+            .map_err(|e| rpc_error(e, ResolveError::BlockNotFound(*hash)))?;
+        response["filter"]
+            .as_str()
+            .and_then(|filter| Vec::<u8>::from_hex(filter).ok())
+            .map(|filter| BlockFilter::new(&filter))
+            .ok_or_else(|| invalid_response("invalid block filter"))
+    }
+}
+
+fn parse_acceptance(result: &Value) -> Result<Acceptance, ResolveError> {
+    match result["allowed"].as_bool() {
+        Some(true) => {
+            let vsize = result["vsize"]
+                .as_u64()
+                .ok_or_else(|| invalid_response("invalid vsize"))?;
+            let fee = result["fees"]["base"]
+                .as_f64()
+                .and_then(|fee| Amount::from_btc(fee).ok())
+                .ok_or_else(|| invalid_response("invalid fee"))?;
+            Ok(Acceptance::Accepted { vsize, fee })
+        }
+        Some(false) => {
+            let reason = result["reject-reason"]
+                .as_str()
+                .ok_or_else(|| invalid_response("missing reject reason"))?;
+            Ok(Acceptance::Rejected(PolicyViolation::from_reject_reason(
+                reason,
+            )))
+        }
+        None => Err(invalid_response("missing acceptance result")),
+    }
+}
+
+fn parse_block_with_prevouts(
+    block: &Value,
+) -> Result<Vec<(Transaction, Vec<TxOut>)>, ResolveError> {
+    block["tx"]
+        .as_array()
+        .ok_or_else(|| invalid_response("missing tx array"))?
+        .iter()
+        .map(parse_transaction_with_prevouts)
+        .collect()
+}
+
+fn parse_confirmed_embeddings(
+    block: &Value,
+    hash: &BlockHash,
+    height: u64,
+) -> Result<Vec<ConfirmedEmbedding>, ResolveError> {
+    let time = block["time"]
+        .as_u64()
+        .and_then(|time| u32::try_from(time).ok())
+        .ok_or_else(|| invalid_response("invalid block time"))?;
+    let txs = parse_block_with_prevouts(block)?;
+
+    Ok(ConfirmedEmbedding::from_transactions(
+        *hash,
+        height,
+        time,
+        txs.iter().map(|(tx, prevouts)| (tx, &prevouts[..])),
+    ))
+}
+
+fn parse_transaction_with_prevouts(tx: &Value) -> Result<(Transaction, Vec<TxOut>), ResolveError> {
+    let transaction: Transaction = decode_hex(&tx["hex"])?;
+
+    let prevouts = tx["vin"]
+        .as_array()
+        .ok_or_else(|| invalid_response("missing vin array"))?
+        .iter()
+        .map(|vin| parse_prevout(&vin["prevout"]))
+        .collect::<Option<Vec<_>>>()
+        .unwrap_or_default();
+
+    Ok((transaction, prevouts))
+}
+
+fn parse_prevout(prevout: &Value) -> Option<TxOut> {
+    let value = Amount::from_btc(prevout["value"].as_f64()?).ok()?;
+    let script_pubkey = ScriptBuf::from_hex(prevout["scriptPubKey"]["hex"].as_str()?).ok()?;
+
+    Some(TxOut {
+        value,
+        script_pubkey,
+    })
+}
+
+fn decode_hex<T: bitcoin::consensus::Decodable>(hex: &Value) -> Result<T, ResolveError> {
+    let hex = hex
+        .as_str()
+        .ok_or_else(|| invalid_response("expected hex string"))?;
+    deserialize_hex(hex).map_err(|e| invalid_response(&e.to_string()))
+}
+
+fn invalid_response(reason: &str) -> ResolveError {
+    ResolveError::Backend(format!("invalid response: {reason}"))
+}
+
+fn backend_error(e: jsonrpc::Error) -> ResolveError {
+    ResolveError::Backend(e.to_string())
+}
+
+fn rpc_error(e: jsonrpc::Error, not_found: ResolveError) -> ResolveError {
+    match e {
+        jsonrpc::Error::Rpc(ref rpc) if rpc.code == RPC_NOT_FOUND => not_found,
+        e => backend_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::envelope_tx;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::key::{Secp256k1, UntweakedPublicKey};
+    use jsonrpc::error::RpcError;
+    use jsonrpc::{Response, Transport};
+    use serde_json::value::to_raw_value;
+    use std::collections::HashMap;
+    use std::fmt;
+
+    /// Responds to `(method, params)` pairs with canned results or RPC error codes
+    #[derive(Default)]
+    struct MockTransport {
+        responses: HashMap<(String, String), Result<Value, i32>>,
+    }
+
+    impl MockTransport {
+        fn with(mut self, method: &str, params: Value, result: Result<Value, i32>) -> Self {
+            self.responses
+                .insert((method.to_string(), params.to_string()), result);
+            self
+        }
+
+        fn respond(&self, req: &Request) -> Response {
+            let params = req.params.map(|p| p.get().to_string()).unwrap_or_default();
+            let (result, error) = match self.responses.get(&(req.method.to_string(), params)) {
+                Some(Ok(value)) => (Some(to_raw_value(value).unwrap()), None),
+                Some(Err(code)) => (None, Some(*code)),
+                None => (None, Some(-32601)),
+            };
+
+            Response {
+                result,
+                error: error.map(|code| RpcError {
+                    code,
+                    message: "error".to_string(),
+                    data: None,
+                }),
+                id: req.id.clone(),
+                jsonrpc: Some("2.0".to_string()),
+            }
+        }
+    }
+
+    impl Transport for MockTransport {
+        fn send_request(&self, req: Request) -> Result<Response, jsonrpc::Error> {
+            Ok(self.respond(&req))
+        }
+
+        fn send_batch(&self, reqs: &[Request]) -> Result<Vec<Response>, jsonrpc::Error> {
+            Ok(reqs.iter().rev().map(|req| self.respond(req)).collect())
+        }
+
+        fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "mock")
+        }
+    }
+
+    fn resolver(transport: MockTransport) -> RpcResolver {
+        RpcResolver::with_client(Client::with_transport(transport))
+    }
+
+    fn prevout_json(script_pubkey: &ScriptBuf) -> Value {
+        json!({ "value": 0.0001, "scriptPubKey": { "hex": script_pubkey.to_hex_string() } })
+    }
+
+    #[test]
+    fn test_check_acceptance() {
+        let accepted = envelope_tx(b"ok");
+        let rejected = envelope_tx(b"too large");
+        let nonstandard = envelope_tx(b"too large before v30");
+        let resolver = resolver(
+            MockTransport::default()
+                .with(
+                    "testmempoolaccept",
+                    json!([[serialize_hex(&accepted)]]),
+                    Ok(json!([{
+                        "txid": accepted.compute_txid().to_string(),
+                        "allowed": true,
+                        "vsize": 150,
+                        "fees": { "base": 0.00000300 },
+                    }])),
+                )
+                .with(
+                    "testmempoolaccept",
+                    json!([[serialize_hex(&rejected)]]),
+                    Ok(json!([{
+                        "txid": rejected.compute_txid().to_string(),
+                        "allowed": false,
+                        "reject-reason": "datacarrier",
+                    }])),
+                )
+                .with(
+                    "testmempoolaccept",
+                    json!([[serialize_hex(&nonstandard)]]),
+                    Ok(json!([{
+                        "txid": nonstandard.compute_txid().to_string(),
+                        "allowed": false,
+                        "reject-reason": "scriptpubkey",
+                    }])),
+                ),
+        );
+
+        let acceptance = resolver.check_acceptance(&accepted).unwrap();
+        assert!(acceptance.is_accepted());
+        assert_eq!(
+            acceptance,
+            Acceptance::Accepted {
+                vsize: 150,
+                fee: Amount::from_sat(300),
+            }
+        );
+        assert_eq!(
+            resolver.check_acceptance(&rejected),
+            Ok(Acceptance::Rejected(PolicyViolation::DatacarrierTooLarge))
+        );
+        assert_eq!(
+            resolver.check_acceptance(&nonstandard),
+            Ok(Acceptance::Rejected(
+                PolicyViolation::NonStandardScriptPubKey
+            ))
+        );
+        assert!(matches!(
+            resolver.check_acceptance(&envelope_tx(b"unknown")),
+            Err(ResolveError::Backend(_))
+        ));
+    }
+
+    #[test]
+    fn test_block_filter() {
+        let hash = BlockHash::all_zeros();
+        let missing = BlockHash::from_byte_array([1; 32]);
+        let resolver = resolver(
+            MockTransport::default()
+                .with(
+                    "getblockfilter",
+                    json!([hash.to_string(), "basic"]),
+                    Ok(json!({ "filter": "0102", "header": hash.to_string() })),
+                )
+                .with(
+                    "getblockfilter",
+                    json!([missing.to_string(), "basic"]),
+                    Err(RPC_NOT_FOUND),
+                ),
+        );
+
+        assert_eq!(resolver.block_filter(&hash).unwrap().content, vec![1, 2]);
+        assert_eq!(
+            resolver.block_filter(&missing),
+            Err(ResolveError::BlockNotFound(missing))
+        );
+    }
+
+    #[test]
+    fn test_transaction() {
+        let tx = envelope_tx(b"data");
+        let txid = tx.compute_txid();
+        let missing = Txid::all_zeros();
+
+        let resolver = resolver(
+            MockTransport::default()
+                .with(
+                    "getrawtransaction",
+                    json!([txid.to_string(), false]),
+                    Ok(json!(serialize_hex(&tx))),
+                )
+                .with(
+                    "getrawtransaction",
+                    json!([missing.to_string(), false]),
+                    Err(RPC_NOT_FOUND),
+                ),
+        );
+
+        assert_eq!(resolver.transaction(&txid), Ok(tx.clone()));
+        assert_eq!(
+            resolver.embeddings_in_tx(&txid),
+            Ok(Embedding::from_transaction(&tx))
+        );
+        assert_eq!(
+            resolver.transaction(&missing),
+            Err(ResolveError::TransactionNotFound(missing))
+        );
+
+        // Other RPC errors are reported as backend errors
+        assert!(matches!(
+            resolver.block(&BlockHash::all_zeros()),
+            Err(ResolveError::Backend(_))
+        ));
+    }
+
+    #[test]
+    fn test_transactions_batch() {
+        let tx1 = envelope_tx(b"first");
+        let tx2 = envelope_tx(b"second");
+        let (txid1, txid2) = (tx1.compute_txid(), tx2.compute_txid());
+
+        let resolver = resolver(
+            MockTransport::default()
+                .with(
+                    "getrawtransaction",
+                    json!([txid1.to_string(), false]),
+                    Ok(json!(serialize_hex(&tx1))),
+                )
+                .with(
+                    "getrawtransaction",
+                    json!([txid2.to_string(), false]),
+                    Ok(json!(serialize_hex(&tx2))),
+                ),
+        );
+
+        assert_eq!(resolver.transactions(&[]), Ok(vec![]));
+        assert_eq!(
+            resolver.transactions(&[txid2, txid1]),
+            Ok(vec![tx2, tx1.clone()])
+        );
+
+        let missing = Txid::all_zeros();
+        assert!(matches!(
+            resolver.transactions(&[txid1, missing]),
+            Err(ResolveError::Backend(_))
+        ));
+    }
+
+    #[test]
+    fn test_block_with_prevouts() {
+        let hash = BlockHash::all_zeros();
+        let header = Header {
+            version: bitcoin::block::Version::ONE,
+            prev_blockhash: hash,
+            merkle_root: bitcoin::TxMerkleNode::all_zeros(),
+            time: 0,
+            bits: bitcoin::CompactTarget::from_consensus(0),
+            nonce: 0,
+        };
+        let tx1 = envelope_tx(b"first");
+        let tx2 = envelope_tx(b"second");
+
+        let secp = Secp256k1::verification_only();
+        let key = UntweakedPublicKey::from_slice(&[2; 32]).unwrap();
+        let p2tr = ScriptBuf::new_p2tr(&secp, key, None);
+        let p2wsh = ScriptBuf::new_p2wsh(&ScriptBuf::new().wscript_hash());
+
+        let resolver = resolver(
+            MockTransport::default()
+                .with(
+                    "getblock",
+                    json!([hash.to_string(), 3]),
+                    Ok(json!({
+                        "time": 1_700_000_000,
+                        "tx": [
+                            { "hex": serialize_hex(&tx1), "vin": [{ "prevout": prevout_json(&p2tr) }] },
+                            { "hex": serialize_hex(&tx2), "vin": [{ "prevout": prevout_json(&p2wsh) }] },
+                        ]
+                    })),
+                )
+                .with("getblockhash", json!([840000]), Ok(json!(hash.to_string())))
+                .with("getblockcount", json!([]), Ok(json!(840000)))
+                .with(
+                    "getblockheader",
+                    json!([hash.to_string(), false]),
+                    Ok(json!(serialize_hex(&header))),
+                ),
+        );
+
+        assert_eq!(resolver.block_hash(840000), Ok(hash));
+        assert_eq!(resolver.best_height(), Ok(840000));
+        assert_eq!(resolver.block_hash_at(840000), Ok(hash));
+        assert_eq!(resolver.block_header(&hash), Ok(header));
+
+        let block = resolver.block_with_prevouts(&hash).unwrap();
+        assert_eq!(block.len(), 2);
+        assert_eq!(block[0].0, tx1);
+        assert_eq!(block[0].1[0].script_pubkey, p2tr);
+        assert_eq!(block[1].1[0].value, Amount::from_sat(10_000));
+
+        // The first input spends a P2TR output, so its P2WSH-shaped witness is not an envelope
+        assert_eq!(Embedding::from_transaction(&tx1).len(), 1);
+        assert_eq!(
+            resolver.embeddings_in_block(&hash),
+            Ok(Embedding::from_transaction(&tx2))
+        );
+
+        let confirmed = resolver
+            .confirmed_embeddings_in_block(&hash, 840000)
+            .unwrap();
+        assert_eq!(confirmed.len(), Embedding::from_transaction(&tx2).len());
+        assert!(confirmed.iter().all(|c| c.block_hash == hash
+            && c.height == 840000
+            && c.time == 1_700_000_000
+            && c.tx_index == 1));
+    }
+}
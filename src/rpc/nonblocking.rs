@@ -0,0 +1,390 @@
+//! An async Bitcoin Core RPC client over plain HTTP, for use with the `async` feature
+
+use super::{
+    RPC_NOT_FOUND, decode_hex, invalid_response, parse_block_with_prevouts,
+    parse_confirmed_embeddings,
+};
+use crate::Embedding;
+use crate::async_resolver::{AsyncChainSource, AsyncResolver};
+use crate::resolver::{ConfirmedEmbedding, ResolveError};
+
+use bitcoin::base64::{Engine, engine::general_purpose::STANDARD};
+use bitcoin::block::Header;
+use bitcoin::{Block, BlockHash, Transaction, TxOut, Txid};
+use serde_json::{Value, json};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// An async resolver backed by Bitcoin Core's JSON-RPC interface. Each request opens a new
+/// HTTP connection; TLS is not supported.
+#[derive(Debug)]
+pub struct AsyncRpcResolver {
+    addr: String,
+    path: String,
+    auth: Option<String>,
+    nonce: AtomicU64,
+}
+
+impl AsyncRpcResolver {
+    /// Returns a client for `http://<host>:<port>[/<path>]` with optional user/password
+    /// authentication
+    pub fn new(url: &str, user: Option<&str>, pass: Option<&str>) -> Result<Self, ResolveError> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| ResolveError::Backend(format!("unsupported url: {url}")))?;
+
+        let (addr, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+
+        let auth = user.map(|user| {
+            let credentials = format!("{user}:{}", pass.unwrap_or_default());
+            format!("Basic {}", STANDARD.encode(credentials))
+        });
+
+        Ok(Self {
+            addr: addr.to_string(),
+            path: path.to_string(),
+            auth,
+            nonce: AtomicU64::new(1),
+        })
+    }
+
+    /// Returns the transactions in a block with the outputs spent by each input. Coinbase
+    /// transactions have no prevouts.
+    pub async fn block_with_prevouts(
+        &self,
+        hash: &BlockHash,
+    ) -> Result<Vec<(Transaction, Vec<TxOut>)>, ResolveError> {
+        parse_block_with_prevouts(&self.verbose_block(hash).await?)
+    }
+
+    async fn verbose_block(&self, hash: &BlockHash) -> Result<Value, ResolveError> {
+        self.call(
+            "getblock",
+            json!([hash.to_string(), 3]),
+            Some(ResolveError::BlockNotFound(*hash)),
+        )
+        .await
+    }
+
+    async fn call(
+        &self,
+        method: &str,
+        params: Value,
+        not_found: Option<ResolveError>,
+    ) -> Result<Value, ResolveError> {
+        let id = self.nonce.fetch_add(1, Ordering::Relaxed);
+        let response = self.post(&request(id, method, params)).await?;
+        result(&response, not_found)
+    }
+
+    async fn post(&self, body: &Value) -> Result<Value, ResolveError> {
+        let body = body.to_string();
+
+        let mut head = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.path,
+            self.addr,
+            body.len()
+        );
+        if let Some(auth) = &self.auth {
+            head.push_str(&format!("Authorization: {auth}\r\n"));
+        }
+        head.push_str("\r\n");
+
+        let mut stream = TcpStream::connect(&self.addr).await.map_err(io_error)?;
+        stream.write_all(head.as_bytes()).await.map_err(io_error)?;
+        stream.write_all(body.as_bytes()).await.map_err(io_error)?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.map_err(io_error)?;
+
+        let split = response
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .ok_or_else(|| invalid_response("malformed HTTP response"))?;
+        let status = String::from_utf8_lossy(&response[..split])
+            .split_whitespace()
+            .nth(1)
+            .unwrap_or_default()
+            .to_string();
+
+        // Bitcoin Core reports RPC errors with a non-200 status and a JSON body
+        serde_json::from_slice(&response[split + 4..])
+            .map_err(|_| ResolveError::Backend(format!("HTTP status {status}")))
+    }
+}
+
+impl AsyncResolver for AsyncRpcResolver {
+    async fn transaction(&self, txid: &Txid) -> Result<Transaction, ResolveError> {
+        let hex = self
+            .call(
+                "getrawtransaction",
+                json!([txid.to_string(), false]),
+                Some(ResolveError::TransactionNotFound(*txid)),
+            )
+            .await?;
+        decode_hex(&hex)
+    }
+
+    /// Fetches all transactions in a single batched request
+    async fn transactions(&self, txids: &[Txid]) -> Result<Vec<Transaction>, ResolveError> {
+        if txids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let start = self.nonce.fetch_add(txids.len() as u64, Ordering::Relaxed);
+        let requests = txids
+            .iter()
+            .zip(start..)
+            .map(|(txid, id)| request(id, "getrawtransaction", json!([txid.to_string(), false])))
+            .collect::<Vec<_>>();
+
+        let responses = self.post(&Value::Array(requests)).await?;
+        let responses = responses
+            .as_array()
+            .ok_or_else(|| invalid_response("expected batch response"))?;
+
+        txids
+            .iter()
+            .zip(start..)
+            .map(|(txid, id)| {
+                let response = responses
+                    .iter()
+                    .find(|response| response["id"] == id)
+                    .ok_or_else(|| {
+                        ResolveError::Backend(format!("missing batch response for {txid}"))
+                    })?;
+                let hex = result(response, Some(ResolveError::TransactionNotFound(*txid)))?;
+                decode_hex(&hex)
+            })
+            .collect()
+    }
+
+    async fn block(&self, hash: &BlockHash) -> Result<Block, ResolveError> {
+        let hex = self
+            .call(
+                "getblock",
+                json!([hash.to_string(), 0]),
+                Some(ResolveError::BlockNotFound(*hash)),
+            )
+            .await?;
+        decode_hex(&hex)
+    }
+
+    async fn embeddings_in_block(&self, hash: &BlockHash) -> Result<Vec<Embedding>, ResolveError> {
+        Ok(self
+            .block_with_prevouts(hash)
+            .await?
+            .iter()
+            .flat_map(|(tx, prevouts)| Embedding::from_transaction_with_prevouts(tx, prevouts))
+            .collect())
+    }
+
+    async fn confirmed_embeddings_in_block(
+        &self,
+        hash: &BlockHash,
+        height: u64,
+    ) -> Result<Vec<ConfirmedEmbedding>, ResolveError> {
+        parse_confirmed_embeddings(&self.verbose_block(hash).await?, hash, height)
+    }
+}
+
+impl AsyncChainSource for AsyncRpcResolver {
+    async fn best_height(&self) -> Result<u64, ResolveError> {
+        self.call("getblockcount", json!([]), None)
+            .await?
+            .as_u64()
+            .ok_or_else(|| invalid_response("invalid block count"))
+    }
+
+    async fn block_hash_at(&self, height: u64) -> Result<BlockHash, ResolveError> {
+        let hash = self.call("getblockhash", json!([height]), None).await?;
+        hash.as_str()
+            .and_then(|hash| hash.parse().ok())
+            .ok_or_else(|| invalid_response("invalid block hash"))
+    }
+
+    async fn block_header(&self, hash: &BlockHash) -> Result<Header, ResolveError> {
+        let hex = self
+            .call(
+                "getblockheader",
+                json!([hash.to_string(), false]),
+                Some(ResolveError::BlockNotFound(*hash)),
+            )
+            .await?;
+        decode_hex(&hex)
+    }
+}
+
+fn request(id: u64, method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "1.0", "id": id, "method": method, "params": params })
+}
+
+/// Extracts the result of a response, mapping Bitcoin Core's not-found error to `not_found`
+fn result(response: &Value, not_found: Option<ResolveError>) -> Result<Value, ResolveError> {
+    match (&response["error"], not_found) {
+        (Value::Null, _) => Ok(response["result"].clone()),
+        (error, Some(not_found)) if error["code"] == RPC_NOT_FOUND => Err(not_found),
+        (error, _) => Err(ResolveError::Backend(format!("RPC error: {error}"))),
+    }
+}
+
+fn io_error(e: std::io::Error) -> ResolveError {
+    ResolveError::Backend(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::op_return_tx;
+
+    use bitcoin::consensus::encode::serialize_hex;
+    use bitcoin::hashes::Hash;
+    use tokio::net::TcpListener;
+
+    /// Serves JSON-RPC over HTTP, answering each request with `handler(method, params)` and
+    /// recording the `Authorization` header of the last request
+    async fn serve<F>(handler: F) -> String
+    where
+        F: Fn(&str, &Value) -> Result<Value, i32> + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = Vec::new();
+
+                let body = loop {
+                    let mut chunk = [0; 4096];
+                    let n = stream.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+
+                    let Some(split) = buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+                        continue;
+                    };
+                    let head = String::from_utf8_lossy(&buf[..split]).to_lowercase();
+                    assert!(head.contains("authorization: basic dxnlcjpwyxnz"));
+
+                    let len = head
+                        .lines()
+                        .find_map(|line| line.strip_prefix("content-length: "))
+                        .unwrap()
+                        .parse::<usize>()
+                        .unwrap();
+                    if buf.len() >= split + 4 + len {
+                        break buf[split + 4..split + 4 + len].to_vec();
+                    }
+                };
+
+                let respond = |request: &Value| match handler(
+                    request["method"].as_str().unwrap(),
+                    &request["params"],
+                ) {
+                    Ok(result) => json!({ "result": result, "error": null, "id": request["id"] }),
+                    Err(code) => json!({
+                        "result": null,
+                        "error": { "code": code, "message": "error" },
+                        "id": request["id"],
+                    }),
+                };
+
+                let request: Value = serde_json::from_slice(&body).unwrap();
+                let response = match &request {
+                    Value::Array(requests) => requests.iter().rev().map(respond).collect(),
+                    request => respond(request),
+                };
+
+                let status = if response["error"].is_null() {
+                    200
+                } else {
+                    500
+                };
+                let body = response.to_string();
+                let reply = format!(
+                    "HTTP/1.1 {status} OK\r\nContent-Length: {}\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+
+        format!("http://{addr}/")
+    }
+
+    #[tokio::test]
+    async fn test_async_rpc() {
+        let txs = [op_return_tx(&[1]), op_return_tx(&[2])];
+        let hash = BlockHash::all_zeros();
+        let served = txs.clone();
+
+        let url = serve(move |method, params| match method {
+            "getrawtransaction" => served
+                .iter()
+                .find(|tx| params[0] == tx.compute_txid().to_string())
+                .map(|tx| json!(serialize_hex(tx)))
+                .ok_or(RPC_NOT_FOUND),
+            "getblockcount" => Ok(json!(100)),
+            "getblockhash" => Ok(json!(hash.to_string())),
+            "getblock" => Ok(json!({
+                "tx": [{ "hex": serialize_hex(&served[0]), "vin": [] }]
+            })),
+            _ => Err(-32601),
+        })
+        .await;
+
+        let resolver = AsyncRpcResolver::new(&url, Some("user"), Some("pass")).unwrap();
+        let txid = txs[0].compute_txid();
+
+        assert_eq!(resolver.transaction(&txid).await, Ok(txs[0].clone()));
+        assert_eq!(
+            resolver.embeddings_in_tx(&txid).await,
+            Ok(Embedding::from_transaction(&txs[0]))
+        );
+        assert_eq!(
+            resolver.transaction(&Txid::all_zeros()).await,
+            Err(ResolveError::TransactionNotFound(Txid::all_zeros()))
+        );
+        assert_eq!(
+            resolver.transactions(&[txs[1].compute_txid(), txid]).await,
+            Ok(vec![txs[1].clone(), txs[0].clone()])
+        );
+
+        let ids = Embedding::from_transaction(&txs[1])
+            .iter()
+            .chain(&Embedding::from_transaction(&txs[0]))
+            .map(Embedding::id)
+            .collect::<Vec<_>>();
+        let embeddings = resolver.embeddings(&ids).await.unwrap();
+        assert_eq!(
+            embeddings.iter().map(Embedding::id).collect::<Vec<_>>(),
+            ids
+        );
+
+        assert_eq!(resolver.best_height().await, Ok(100));
+        assert_eq!(resolver.block_hash_at(100).await, Ok(hash));
+        assert_eq!(
+            resolver.embeddings_in_block(&hash).await,
+            Ok(Embedding::from_transaction(&txs[0]))
+        );
+        assert!(matches!(
+            resolver.block_header(&hash).await,
+            Err(ResolveError::Backend(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_url() {
+        assert!(AsyncRpcResolver::new("https://127.0.0.1:8332", None, None).is_err());
+
+        let resolver = AsyncRpcResolver::new("http://127.0.0.1:8332/wallet/a", None, None).unwrap();
+        assert_eq!(resolver.addr, "127.0.0.1:8332");
+        assert_eq!(resolver.path, "/wallet/a");
+        assert_eq!(resolver.auth, None);
+    }
+}
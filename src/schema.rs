@@ -0,0 +1,509 @@
+//! # JSON Schema
+//!
+//! A versioned JSON model of decoded embeddings, shared by the `serde` impls of [`Embedding`],
+//! [`Message`], and [`ConfirmedEmbedding`], the JavaScript bindings, and the command-line tool.
+//! Every embedding record carries [`SCHEMA_VERSION`]; fields are only added within a version,
+//! and a change to an existing field bumps it.
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "id": "<txid>:rt:0",
+//!   "type": "rt",
+//!   "location": { "kind": "op_return", "output": 0 },
+//!   "bytes": "0568656c6c6f",
+//!   "payload": "68656c6c6f",
+//!   "classification": "text"
+//! }
+//! ```
+//!
+//! Byte strings are lowercase hex, and message tags are decimal strings since they can exceed
+//! the integers JSON parsers represent exactly.
+
+use crate::message::Message;
+use crate::resolver::ConfirmedEmbedding;
+use crate::{Embedding, EmbeddingId, EmbeddingLocation, ScriptType};
+
+use bitcoin::BlockHash;
+use bitcoin::hex::{DisplayHex, FromHex};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// The version of the JSON model
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Error types for converting records back into embeddings and messages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaError {
+    /// The record was written with an unsupported schema version
+    UnsupportedVersion(u32),
+    /// The id is malformed or does not match the location
+    InvalidId,
+    /// A byte string is not valid hex
+    InvalidHex,
+    /// A message tag is not a valid decimal tag
+    InvalidTag,
+    /// The envelope push sizes do not sum to the length of the bytes
+    InvalidPushes,
+}
+
+/// What the payload of an embedding appears to contain
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Classification {
+    /// No payload bytes
+    Empty,
+    /// Printable UTF-8 text
+    Text,
+    /// TLV messages with a canonical encoding
+    Messages,
+    /// Anything else
+    Binary,
+}
+
+/// The JSON record of an embedding
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddingRecord {
+    /// The schema version, [`SCHEMA_VERSION`] when written by this crate
+    pub version: u32,
+    /// The embedding id, as displayed
+    pub id: String,
+    /// The two-letter type code
+    #[serde(rename = "type")]
+    pub embedding_type: String,
+    /// Where the embedding is located in its transaction
+    pub location: LocationRecord,
+    /// The hex data bytes, as extracted
+    pub bytes: String,
+    /// The hex payload (see [`Embedding::payload`])
+    pub payload: String,
+    /// What the payload appears to contain
+    pub classification: Classification,
+    /// The decoded messages, if the payload is classified as messages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub messages: Option<Vec<MessageRecord>>,
+}
+
+/// The JSON record of an embedding's location
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LocationRecord {
+    /// An `OP_RETURN` output
+    OpReturn {
+        /// The output index
+        output: usize,
+    },
+    /// A taproot annex
+    TaprootAnnex {
+        /// The input index
+        input: usize,
+        /// The index of the payload in a batch annex, omitted when 0
+        #[serde(default, skip_serializing_if = "is_zero")]
+        index: usize,
+        /// The tag of the record holding the payload, omitted when 0
+        #[serde(default, skip_serializing_if = "is_zero")]
+        tag: u8,
+    },
+    /// A witness script envelope
+    WitnessEnvelope {
+        /// The input index
+        input: usize,
+        /// The index of the envelope in the script
+        index: usize,
+        /// The size of each push
+        pushes: Vec<usize>,
+        /// `legacy` or `tapscript`
+        script: String,
+    },
+    /// A bare multisig output
+    BareMultisig {
+        /// The output index
+        output: usize,
+    },
+}
+
+/// The JSON record of a message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageRecord {
+    /// The decimal tag
+    pub tag: String,
+    /// The hex body
+    pub body: String,
+}
+
+/// The JSON record of a confirmed embedding: the embedding record with its block fields
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfirmedRecord {
+    /// The embedding
+    #[serde(flatten)]
+    pub embedding: EmbeddingRecord,
+    /// The hash of the confirming block
+    pub block_hash: String,
+    /// The height of the confirming block
+    pub height: u64,
+    /// The timestamp in the confirming block's header
+    pub time: u32,
+    /// The position of the embedding's transaction in the block
+    pub tx_index: usize,
+}
+
+/// Returns what the payload of `embedding` appears to contain
+pub fn classify(embedding: &Embedding) -> Classification {
+    let payload = embedding.payload();
+    if payload.is_empty() {
+        Classification::Empty
+    } else if std::str::from_utf8(&payload)
+        .is_ok_and(|text| text.chars().all(|c| !c.is_control() || c.is_whitespace()))
+    {
+        Classification::Text
+    } else if Message::decode_strict(&payload).is_ok() {
+        Classification::Messages
+    } else {
+        Classification::Binary
+    }
+}
+
+impl From<&Embedding> for EmbeddingRecord {
+    fn from(embedding: &Embedding) -> Self {
+        let classification = classify(embedding);
+        let payload = embedding.payload();
+        let messages = match classification {
+            Classification::Messages => Message::decode_strict(&payload)
+                .ok()
+                .map(|messages| messages.iter().map(MessageRecord::from).collect()),
+            _ => None,
+        };
+
+        Self {
+            version: SCHEMA_VERSION,
+            id: embedding.id().to_string(),
+            embedding_type: embedding.to_type().code().to_string(),
+            location: LocationRecord::from(&embedding.location),
+            bytes: embedding.bytes.to_lower_hex_string(),
+            payload: payload.to_lower_hex_string(),
+            classification,
+            messages,
+        }
+    }
+}
+
+impl From<&EmbeddingLocation> for LocationRecord {
+    fn from(location: &EmbeddingLocation) -> Self {
+        match location {
+            EmbeddingLocation::OpReturn { output } => Self::OpReturn { output: *output },
+            EmbeddingLocation::TaprootAnnex { input, index, tag } => Self::TaprootAnnex {
+                input: *input,
+                index: *index,
+                tag: *tag,
+            },
+            EmbeddingLocation::BareMultisig { output } => Self::BareMultisig { output: *output },
+            EmbeddingLocation::WitnessEnvelope {
+                input,
+                index,
+                pushes,
+                script_type,
+            } => Self::WitnessEnvelope {
+                input: *input,
+                index: *index,
+                pushes: pushes.clone(),
+                script: match script_type {
+                    ScriptType::Legacy => "legacy",
+                    ScriptType::Tapscript => "tapscript",
+                }
+                .to_string(),
+            },
+        }
+    }
+}
+
+impl From<&Message> for MessageRecord {
+    fn from(message: &Message) -> Self {
+        Self {
+            tag: message.tag.to_string(),
+            body: message.body.to_lower_hex_string(),
+        }
+    }
+}
+
+impl From<&ConfirmedEmbedding> for ConfirmedRecord {
+    fn from(confirmed: &ConfirmedEmbedding) -> Self {
+        Self {
+            embedding: EmbeddingRecord::from(&confirmed.embedding),
+            block_hash: confirmed.block_hash.to_string(),
+            height: confirmed.height,
+            time: confirmed.time,
+            tx_index: confirmed.tx_index,
+        }
+    }
+}
+
+impl TryFrom<&EmbeddingRecord> for Embedding {
+    type Error = SchemaError;
+
+    /// Rebuilds the embedding from its id, location, and bytes. The payload, classification,
+    /// and messages are derived, so they are not checked.
+    fn try_from(record: &EmbeddingRecord) -> Result<Self, SchemaError> {
+        if record.version != SCHEMA_VERSION {
+            return Err(SchemaError::UnsupportedVersion(record.version));
+        }
+
+        let id = EmbeddingId::from_str(&record.id).map_err(|_| SchemaError::InvalidId)?;
+        let location = match &record.location {
+            LocationRecord::OpReturn { output } => EmbeddingLocation::OpReturn { output: *output },
+            LocationRecord::TaprootAnnex { input, index, tag } => EmbeddingLocation::TaprootAnnex {
+                input: *input,
+                index: *index,
+                tag: *tag,
+            },
+            LocationRecord::BareMultisig { output } => {
+                EmbeddingLocation::BareMultisig { output: *output }
+            }
+            LocationRecord::WitnessEnvelope {
+                input,
+                index,
+                pushes,
+                script,
+            } => EmbeddingLocation::WitnessEnvelope {
+                input: *input,
+                index: *index,
+                pushes: pushes.clone(),
+                script_type: match script.as_str() {
+                    "legacy" => ScriptType::Legacy,
+                    "tapscript" => ScriptType::Tapscript,
+                    _ => return Err(SchemaError::InvalidId),
+                },
+            },
+        };
+
+        let embedding = Embedding {
+            bytes: Vec::from_hex(&record.bytes).map_err(|_| SchemaError::InvalidHex)?,
+            txid: id.txid,
+            location,
+        };
+        if embedding.id() != id {
+            return Err(SchemaError::InvalidId);
+        }
+        if !embedding.has_consistent_pushes() {
+            return Err(SchemaError::InvalidPushes);
+        }
+        Ok(embedding)
+    }
+}
+
+impl TryFrom<&MessageRecord> for Message {
+    type Error = SchemaError;
+
+    fn try_from(record: &MessageRecord) -> Result<Self, SchemaError> {
+        let tag = record.tag.parse().map_err(|_| SchemaError::InvalidTag)?;
+        let body = Vec::from_hex(&record.body).map_err(|_| SchemaError::InvalidHex)?;
+        Message::new(tag, body).map_err(|_| SchemaError::InvalidTag)
+    }
+}
+
+impl TryFrom<&ConfirmedRecord> for ConfirmedEmbedding {
+    type Error = SchemaError;
+
+    fn try_from(record: &ConfirmedRecord) -> Result<Self, SchemaError> {
+        Ok(Self {
+            embedding: Embedding::try_from(&record.embedding)?,
+            block_hash: BlockHash::from_str(&record.block_hash)
+                .map_err(|_| SchemaError::InvalidHex)?,
+            height: record.height,
+            time: record.time,
+            tx_index: record.tx_index,
+        })
+    }
+}
+
+impl Serialize for Embedding {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        EmbeddingRecord::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Embedding {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let record = EmbeddingRecord::deserialize(deserializer)?;
+        Embedding::try_from(&record).map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for Message {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        MessageRecord::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Message {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let record = MessageRecord::deserialize(deserializer)?;
+        Message::try_from(&record).map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for ConfirmedEmbedding {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ConfirmedRecord::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ConfirmedEmbedding {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let record = ConfirmedRecord::deserialize(deserializer)?;
+        ConfirmedEmbedding::try_from(&record).map_err(D::Error::custom)
+    }
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported schema version {version}")
+            }
+            SchemaError::InvalidId => write!(f, "Invalid or mismatched embedding id"),
+            SchemaError::InvalidHex => write!(f, "Invalid hex"),
+            SchemaError::InvalidTag => write!(f, "Invalid message tag"),
+            SchemaError::InvalidPushes => write!(f, "Push sizes do not match the bytes"),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+fn is_zero<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::Txid;
+    use bitcoin::hashes::Hash;
+    use serde_json::json;
+
+    fn embedding(location: EmbeddingLocation, bytes: &[u8]) -> Embedding {
+        Embedding {
+            bytes: bytes.to_vec(),
+            txid: Txid::from_byte_array([1; 32]),
+            location,
+        }
+    }
+
+    #[test]
+    fn test_record() {
+        let op_return = embedding(EmbeddingLocation::OpReturn { output: 0 }, b"\x05hello");
+        let txid = op_return.txid;
+        assert_eq!(
+            serde_json::to_value(&op_return).unwrap(),
+            json!({
+                "version": 1,
+                "id": format!("{txid}:rt:0"),
+                "type": "rt",
+                "location": { "kind": "op_return", "output": 0 },
+                "bytes": "0568656c6c6f",
+                "payload": "68656c6c6f",
+                "classification": "text",
+            })
+        );
+
+        let bytes = Message::encode(vec![Message::new(1, vec![0xff]).unwrap()]);
+        let envelope = embedding(
+            EmbeddingLocation::WitnessEnvelope {
+                input: 2,
+                index: 1,
+                pushes: vec![bytes.len()],
+                script_type: ScriptType::Tapscript,
+            },
+            &bytes,
+        );
+        let value = serde_json::to_value(&envelope).unwrap();
+        assert_eq!(value["classification"], "messages");
+        assert_eq!(value["messages"], json!([{ "tag": "1", "body": "ff" }]));
+        assert_eq!(
+            value["location"],
+            json!({ "kind": "witness_envelope", "input": 2, "index": 1, "pushes": [2], "script": "tapscript" })
+        );
+
+        let annex = embedding(
+            EmbeddingLocation::TaprootAnnex {
+                input: 1,
+                index: 2,
+                tag: 1,
+            },
+            b"two",
+        );
+        assert_eq!(
+            serde_json::to_value(&annex).unwrap()["location"],
+            json!({ "kind": "taproot_annex", "input": 1, "index": 2, "tag": 1 })
+        );
+
+        for embedding in [op_return, envelope, annex] {
+            let json = serde_json::to_string(&embedding).unwrap();
+            assert_eq!(serde_json::from_str::<Embedding>(&json).unwrap(), embedding);
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        let location = EmbeddingLocation::TaprootAnnex {
+            input: 0,
+            index: 0,
+            tag: 0,
+        };
+        assert_eq!(
+            classify(&embedding(location.clone(), b"")),
+            Classification::Empty
+        );
+        assert_eq!(
+            classify(&embedding(location.clone(), b"gm\n")),
+            Classification::Text
+        );
+        assert_eq!(
+            classify(&embedding(location.clone(), &[0x03, 0x00, 0x01])),
+            Classification::Messages
+        );
+        assert_eq!(
+            classify(&embedding(location, &[0x80, 0x00])),
+            Classification::Binary
+        );
+    }
+
+    #[test]
+    fn test_invalid_records() {
+        let op_return = embedding(EmbeddingLocation::OpReturn { output: 3 }, b"\x01x");
+        let mut record = EmbeddingRecord::from(&op_return);
+
+        record.location = LocationRecord::OpReturn { output: 4 };
+        assert_eq!(Embedding::try_from(&record), Err(SchemaError::InvalidId));
+
+        record.location = LocationRecord::OpReturn { output: 3 };
+        record.version = 2;
+        assert_eq!(
+            Embedding::try_from(&record),
+            Err(SchemaError::UnsupportedVersion(2))
+        );
+
+        let envelope = embedding(
+            EmbeddingLocation::WitnessEnvelope {
+                input: 0,
+                index: 0,
+                pushes: vec![1, 2],
+                script_type: ScriptType::Tapscript,
+            },
+            b"abc",
+        );
+        let mut record = EmbeddingRecord::from(&envelope);
+        assert_eq!(Embedding::try_from(&record), Ok(envelope));
+        record.bytes = "6162".to_string();
+        assert_eq!(
+            Embedding::try_from(&record),
+            Err(SchemaError::InvalidPushes)
+        );
+
+        let message: Result<Message, _> = serde_json::from_value(json!({ "tag": "0", "body": "" }));
+        assert!(message.is_err());
+    }
+}
@@ -0,0 +1,1202 @@
+//! # Persistent Embedding Store
+//!
+//! [`Store`] is a persistent embedding index backed by [redb](https://docs.rs/redb), with the same
+//! query surface as [`MemoryIndex`](crate::index::MemoryIndex). Embeddings are ingested one block
+//! at a time in a single atomic transaction, and can be deleted by block when it is disconnected
+//! during a reorg.
+//!
+//! Keys are encoded so that the byte order of the embeddings table matches canonical id order.
+//!
+//! An index can be exported to a snapshot with [`Store::export`] and loaded into another store with
+//! [`Store::import`]. A snapshot is the magic `EMBEDIDX`, a version byte, the little-endian record
+//! count, the records, and a SHA-256 checksum of everything before it. Each record is the 50-byte
+//! id key, a flag byte followed by the indexing block hash if set, and the length-prefixed (LEB128)
+//! encoded embedding. With the `serde` feature, [`Store::export_jsonl`] writes the same records as
+//! JSON lines after a versioned header line.
+
+use crate::index::PREFIX_LEN;
+use crate::{
+    Embedding, EmbeddingId, EmbeddingLocation, EmbeddingType, ScriptType, TAPROOT_ANNEX_DATA_TAG,
+    varint,
+};
+
+use bitcoin::hashes::{Hash, HashEngine, sha256};
+use bitcoin::{BlockHash, Txid};
+use redb::{
+    Database, MultimapTableDefinition, ReadableTable, ReadableTableMetadata, TableDefinition,
+    WriteTransaction,
+};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Embedding id key -> encoded location and payload
+const EMBEDDINGS: TableDefinition<&[u8], &[u8]> = TableDefinition::new("embeddings");
+/// Embedding id key -> hash of the block that most recently indexed it
+const EMBEDDING_BLOCK: TableDefinition<&[u8], &[u8]> = TableDefinition::new("embedding_block");
+/// Block hash -> embedding id keys
+const BY_BLOCK: MultimapTableDefinition<&[u8], &[u8]> = MultimapTableDefinition::new("by_block");
+/// Type code -> embedding id keys
+const BY_TYPE: MultimapTableDefinition<&[u8], &[u8]> = MultimapTableDefinition::new("by_type");
+/// Leading payload bytes -> embedding id keys
+const BY_PREFIX: MultimapTableDefinition<&[u8], &[u8]> = MultimapTableDefinition::new("by_prefix");
+/// Payload content hash -> embedding id keys
+const BY_CONTENT_HASH: MultimapTableDefinition<&[u8], &[u8]> =
+    MultimapTableDefinition::new("by_content_hash");
+
+/// The magic bytes at the start of a snapshot
+pub const SNAPSHOT_MAGIC: [u8; 8] = *b"EMBEDIDX";
+/// The current snapshot format version
+pub const SNAPSHOT_VERSION: u8 = 1;
+/// The length of an encoded id key
+const KEY_LEN: usize = 50;
+
+/// Error types for the persistent store
+#[derive(Debug)]
+pub enum StoreError {
+    /// Database error
+    Database(Box<redb::Error>),
+    /// A stored record could not be decoded
+    Corrupt,
+    /// I/O error reading or writing a snapshot
+    Io(io::Error),
+    /// A snapshot is truncated, malformed, or fails its checksum
+    InvalidSnapshot,
+    /// A snapshot was written in an unsupported format version
+    UnsupportedVersion(u8),
+}
+
+/// A persistent embedding index
+pub struct Store {
+    db: Database,
+}
+
+impl Store {
+    /// Opens the store at `path`, creating it if it does not exist
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        Self::init(Database::create(path)?)
+    }
+
+    /// Returns a store held entirely in memory
+    pub fn in_memory() -> Result<Self, StoreError> {
+        let backend = redb::backends::InMemoryBackend::new();
+        Self::init(Database::builder().create_with_backend(backend)?)
+    }
+
+    fn init(db: Database) -> Result<Self, StoreError> {
+        let txn = db.begin_write()?;
+        txn.open_table(EMBEDDINGS)?;
+        txn.open_table(EMBEDDING_BLOCK)?;
+        txn.open_multimap_table(BY_BLOCK)?;
+        txn.open_multimap_table(BY_TYPE)?;
+        txn.open_multimap_table(BY_PREFIX)?;
+        txn.open_multimap_table(BY_CONTENT_HASH)?;
+        txn.commit()?;
+
+        Ok(Self { db })
+    }
+
+    /// Atomically indexes the embeddings in a block. Embeddings already indexed under the same
+    /// id are replaced and become associated with this block.
+    pub fn insert_block(
+        &self,
+        hash: &BlockHash,
+        embeddings: impl IntoIterator<Item = Embedding>,
+    ) -> Result<(), StoreError> {
+        let txn = self.db.begin_write()?;
+
+        for embedding in embeddings {
+            let key = id_key(&embedding.id());
+            remove_in(&txn, &key)?;
+            insert_in(&txn, &key, &embedding)?;
+
+            associate_in(&txn, &key, hash)?;
+        }
+
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Atomically deletes the embeddings indexed by a block, returning the number removed.
+    /// Embeddings that have since been re-indexed by another block are kept.
+    pub fn remove_block(&self, hash: &BlockHash) -> Result<usize, StoreError> {
+        let txn = self.db.begin_write()?;
+        let mut removed = 0;
+
+        let keys = txn
+            .open_multimap_table(BY_BLOCK)?
+            .remove_all(hash.as_byte_array().as_slice())?
+            .map(|key| key.map(|key| key.value().to_vec()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for key in keys {
+            let current = txn
+                .open_table(EMBEDDING_BLOCK)?
+                .get(key.as_slice())?
+                .map(|block| block.value().to_vec());
+
+            if current.as_deref() == Some(hash.as_byte_array().as_slice()) {
+                removed += usize::from(remove_in(&txn, &key)?);
+            }
+        }
+
+        txn.commit()?;
+        Ok(removed)
+    }
+
+    /// Indexes an embedding outside of any block (e.g. from the mempool), returning true if
+    /// it replaced an existing embedding
+    pub fn insert(&self, embedding: &Embedding) -> Result<bool, StoreError> {
+        let txn = self.db.begin_write()?;
+        let key = id_key(&embedding.id());
+        let replaced = remove_in(&txn, &key)?;
+        insert_in(&txn, &key, embedding)?;
+        txn.commit()?;
+        Ok(replaced)
+    }
+
+    /// Removes an embedding, returning true if it existed
+    pub fn remove(&self, id: &EmbeddingId) -> Result<bool, StoreError> {
+        let txn = self.db.begin_write()?;
+        let removed = remove_in(&txn, &id_key(id))?;
+        txn.commit()?;
+        Ok(removed)
+    }
+
+    /// Returns the number of indexed embeddings
+    pub fn len(&self) -> Result<usize, StoreError> {
+        let txn = self.db.begin_read()?;
+        Ok(txn.open_table(EMBEDDINGS)?.len()? as usize)
+    }
+
+    /// Returns true if no embeddings are indexed
+    pub fn is_empty(&self) -> Result<bool, StoreError> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Returns the embedding with the given id
+    pub fn get(&self, id: &EmbeddingId) -> Result<Option<Embedding>, StoreError> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(EMBEDDINGS)?;
+        let key = id_key(id);
+
+        table
+            .get(key.as_slice())?
+            .map(|value| decode_embedding(&key, value.value()))
+            .transpose()
+    }
+
+    /// Returns true if an embedding with the given id is indexed
+    pub fn contains(&self, id: &EmbeddingId) -> Result<bool, StoreError> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(EMBEDDINGS)?;
+        Ok(table.get(id_key(id).as_slice())?.is_some())
+    }
+
+    /// Returns every embedding in canonical order
+    pub fn all(&self) -> Result<Vec<Embedding>, StoreError> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(EMBEDDINGS)?;
+
+        table
+            .iter()?
+            .map(|entry| {
+                let (key, value) = entry?;
+                decode_embedding(key.value(), value.value())
+            })
+            .collect()
+    }
+
+    /// Returns the embeddings in a transaction
+    pub fn by_txid(&self, txid: &Txid) -> Result<Vec<Embedding>, StoreError> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(EMBEDDINGS)?;
+        let start = txid.as_byte_array().as_slice();
+
+        let mut embeddings = Vec::new();
+        for entry in table.range(start..)? {
+            let (key, value) = entry?;
+            if !key.value().starts_with(start) {
+                break;
+            }
+            embeddings.push(decode_embedding(key.value(), value.value())?);
+        }
+
+        Ok(embeddings)
+    }
+
+    /// Returns the embeddings of a given type
+    pub fn by_type(&self, embedding_type: EmbeddingType) -> Result<Vec<Embedding>, StoreError> {
+        self.lookup(BY_TYPE, embedding_type.code().as_bytes())
+    }
+
+    /// Returns the embeddings whose payload starts with `prefix` (e.g. a protocol identifier)
+    pub fn by_prefix(&self, prefix: &[u8]) -> Result<Vec<Embedding>, StoreError> {
+        let txn = self.db.begin_read()?;
+        let index = txn.open_multimap_table(BY_PREFIX)?;
+        let key = &prefix[..prefix.len().min(PREFIX_LEN)];
+
+        let mut ids = BTreeSet::new();
+        for entry in index.range(key..)? {
+            let (k, values) = entry?;
+            if !k.value().starts_with(key) {
+                break;
+            }
+            for value in values {
+                ids.insert(value?.value().to_vec());
+            }
+        }
+
+        let mut embeddings = self.load(&txn, ids)?;
+        embeddings.retain(|embedding| embedding.payload().starts_with(prefix));
+        Ok(embeddings)
+    }
+
+    /// Returns the embeddings whose payload has the given [`Embedding::content_hash`]
+    pub fn by_content_hash(&self, hash: &[u8; 32]) -> Result<Vec<Embedding>, StoreError> {
+        self.lookup(BY_CONTENT_HASH, hash)
+    }
+
+    /// Returns the embeddings indexed by a block, in canonical order
+    pub fn by_block(&self, hash: &BlockHash) -> Result<Vec<Embedding>, StoreError> {
+        self.lookup(BY_BLOCK, hash.as_byte_array())
+    }
+
+    /// Writes a snapshot of every embedding and its indexing block to `path`, returning the
+    /// number of embeddings written
+    pub fn export(&self, path: impl AsRef<Path>) -> Result<usize, StoreError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let count = self.export_to(&mut writer)?;
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Writes a snapshot of every embedding and its indexing block to `writer`, returning the
+    /// number of embeddings written
+    pub fn export_to(&self, writer: impl Write) -> Result<usize, StoreError> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(EMBEDDINGS)?;
+        let blocks = txn.open_table(EMBEDDING_BLOCK)?;
+        let count = table.len()?;
+
+        let mut writer = HashingWriter::new(writer);
+        writer.write_all(&SNAPSHOT_MAGIC)?;
+        writer.write_all(&[SNAPSHOT_VERSION])?;
+        writer.write_all(&count.to_le_bytes())?;
+
+        for entry in table.iter()? {
+            let (key, value) = entry?;
+            let mut record = key.value().to_vec();
+            match blocks.get(key.value())? {
+                Some(block) => {
+                    record.push(1);
+                    record.extend_from_slice(block.value());
+                }
+                None => record.push(0),
+            }
+            varint::encode_to_vec(value.value().len() as u128, &mut record);
+            record.extend_from_slice(value.value());
+            writer.write_all(&record)?;
+        }
+
+        let checksum = sha256::Hash::from_engine(writer.engine).to_byte_array();
+        writer.inner.write_all(&checksum)?;
+        Ok(count as usize)
+    }
+
+    /// Atomically loads a snapshot from `path`, returning the number of embeddings imported.
+    /// Embeddings already indexed under the same id are replaced, and nothing is imported if
+    /// the snapshot is invalid.
+    pub fn import(&self, path: impl AsRef<Path>) -> Result<usize, StoreError> {
+        self.import_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Atomically loads a snapshot from `reader`, as in [`Store::import`]
+    pub fn import_from(&self, reader: impl Read) -> Result<usize, StoreError> {
+        let mut reader = HashingReader::new(reader);
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != SNAPSHOT_MAGIC {
+            return Err(StoreError::InvalidSnapshot);
+        }
+        let mut version = [0; 1];
+        reader.read_exact(&mut version)?;
+        if version[0] != SNAPSHOT_VERSION {
+            return Err(StoreError::UnsupportedVersion(version[0]));
+        }
+        let mut count = [0; 8];
+        reader.read_exact(&mut count)?;
+        let count = u64::from_le_bytes(count);
+
+        let txn = self.db.begin_write()?;
+        for _ in 0..count {
+            let mut key = [0; KEY_LEN];
+            reader.read_exact(&mut key)?;
+
+            let mut flag = [0; 1];
+            reader.read_exact(&mut flag)?;
+            let block = match flag[0] {
+                0 => None,
+                1 => {
+                    let mut hash = [0; 32];
+                    reader.read_exact(&mut hash)?;
+                    Some(BlockHash::from_byte_array(hash))
+                }
+                _ => return Err(StoreError::InvalidSnapshot),
+            };
+
+            let (len, _) = varint::decode_from(&mut reader)?;
+            let len = usize::try_from(len).map_err(|_| StoreError::InvalidSnapshot)?;
+            let mut value = Vec::new();
+            (&mut reader).take(len as u64).read_to_end(&mut value)?;
+            if value.len() != len {
+                return Err(StoreError::InvalidSnapshot);
+            }
+
+            let embedding = decode_embedding(&key, &value)?;
+            if id_key(&embedding.id()) != key || !embedding.has_consistent_pushes() {
+                return Err(StoreError::InvalidSnapshot);
+            }
+            import_in(&txn, &embedding, block.as_ref())?;
+        }
+
+        let expected = sha256::Hash::from_engine(reader.engine).to_byte_array();
+        let mut checksum = [0; 32];
+        reader.inner.read_exact(&mut checksum)?;
+        if checksum != expected {
+            return Err(StoreError::InvalidSnapshot);
+        }
+
+        txn.commit()?;
+        usize::try_from(count).map_err(|_| StoreError::InvalidSnapshot)
+    }
+
+    /// Writes every embedding and its indexing block to `path` as JSON lines, returning the
+    /// number of embeddings written (`serde` feature)
+    #[cfg(feature = "serde")]
+    pub fn export_jsonl(&self, path: impl AsRef<Path>) -> Result<usize, StoreError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let count = self.export_jsonl_to(&mut writer)?;
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Writes every embedding and its indexing block to `writer` as JSON lines: a header line
+    /// with the format version, then one object per embedding with its `id`, `block` (or
+    /// null), envelope `pushes`, annex `tag` (if not 0), and hex `bytes` (`serde` feature)
+    #[cfg(feature = "serde")]
+    pub fn export_jsonl_to(&self, mut writer: impl Write) -> Result<usize, StoreError> {
+        use bitcoin::hex::DisplayHex;
+        use serde_json::json;
+
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(EMBEDDINGS)?;
+        let blocks = txn.open_table(EMBEDDING_BLOCK)?;
+
+        let header = json!({ "format": "bitcoin-embed-index", "version": SNAPSHOT_VERSION });
+        writeln!(writer, "{header}")?;
+
+        let mut count = 0;
+        for entry in table.iter()? {
+            let (key, value) = entry?;
+            let embedding = decode_embedding(key.value(), value.value())?;
+            let block = blocks
+                .get(key.value())?
+                .map(|block| BlockHash::from_slice(block.value()))
+                .transpose()
+                .map_err(|_| StoreError::Corrupt)?;
+
+            let mut record = json!({
+                "id": embedding.id().to_string(),
+                "block": block.map(|block| block.to_string()),
+                "bytes": embedding.bytes.to_lower_hex_string(),
+            });
+            match &embedding.location {
+                EmbeddingLocation::WitnessEnvelope { pushes, .. } => {
+                    record["pushes"] = json!(pushes);
+                }
+                EmbeddingLocation::TaprootAnnex { tag, .. } if *tag != TAPROOT_ANNEX_DATA_TAG => {
+                    record["tag"] = json!(tag);
+                }
+                _ => {}
+            }
+            writeln!(writer, "{record}")?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Atomically loads JSON lines written by [`Store::export_jsonl`] from `path`, returning
+    /// the number of embeddings imported (`serde` feature)
+    #[cfg(feature = "serde")]
+    pub fn import_jsonl(&self, path: impl AsRef<Path>) -> Result<usize, StoreError> {
+        self.import_jsonl_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Atomically loads JSON lines from `reader`, as in [`Store::import_jsonl`] (`serde` feature)
+    #[cfg(feature = "serde")]
+    pub fn import_jsonl_from(&self, reader: impl io::BufRead) -> Result<usize, StoreError> {
+        use bitcoin::hex::FromHex;
+        use serde_json::Value;
+        use std::str::FromStr;
+
+        let mut lines = reader.lines();
+        let header: Value =
+            serde_json::from_str(&lines.next().ok_or(StoreError::InvalidSnapshot)??)
+                .map_err(|_| StoreError::InvalidSnapshot)?;
+        if header["format"] != "bitcoin-embed-index" {
+            return Err(StoreError::InvalidSnapshot);
+        }
+        match header["version"].as_u64() {
+            Some(version) if version == u64::from(SNAPSHOT_VERSION) => {}
+            Some(version) => {
+                return Err(StoreError::UnsupportedVersion(
+                    u8::try_from(version).unwrap_or(u8::MAX),
+                ));
+            }
+            None => return Err(StoreError::InvalidSnapshot),
+        }
+
+        let txn = self.db.begin_write()?;
+        let mut count = 0;
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: Value =
+                serde_json::from_str(&line).map_err(|_| StoreError::InvalidSnapshot)?;
+
+            let id = record["id"]
+                .as_str()
+                .and_then(|id| EmbeddingId::from_str(id).ok())
+                .ok_or(StoreError::InvalidSnapshot)?;
+            let bytes = record["bytes"]
+                .as_str()
+                .and_then(|bytes| Vec::<u8>::from_hex(bytes).ok())
+                .ok_or(StoreError::InvalidSnapshot)?;
+            let block = match &record["block"] {
+                Value::Null => None,
+                Value::String(hash) => {
+                    Some(BlockHash::from_str(hash).map_err(|_| StoreError::InvalidSnapshot)?)
+                }
+                _ => return Err(StoreError::InvalidSnapshot),
+            };
+
+            let location = match id.embedding_type {
+                EmbeddingType::OpReturn => EmbeddingLocation::OpReturn { output: id.index },
+                EmbeddingType::TaprootAnnex => EmbeddingLocation::TaprootAnnex {
+                    input: id.index,
+                    index: id.sub_index.unwrap_or(0),
+                    tag: match &record["tag"] {
+                        Value::Null => TAPROOT_ANNEX_DATA_TAG,
+                        tag => tag
+                            .as_u64()
+                            .and_then(|tag| u8::try_from(tag).ok())
+                            .ok_or(StoreError::InvalidSnapshot)?,
+                    },
+                },
+                EmbeddingType::BareMultisig => EmbeddingLocation::BareMultisig { output: id.index },
+                EmbeddingType::WitnessEnvelope(script_type) => {
+                    let pushes = record["pushes"]
+                        .as_array()
+                        .ok_or(StoreError::InvalidSnapshot)?
+                        .iter()
+                        .map(|push| {
+                            push.as_u64()
+                                .and_then(|push| usize::try_from(push).ok())
+                                .ok_or(StoreError::InvalidSnapshot)
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    EmbeddingLocation::WitnessEnvelope {
+                        input: id.index,
+                        index: id.sub_index.unwrap_or(0),
+                        pushes,
+                        script_type,
+                    }
+                }
+            };
+
+            let embedding = Embedding {
+                bytes,
+                txid: id.txid,
+                location,
+            };
+            if !embedding.has_consistent_pushes() {
+                return Err(StoreError::InvalidSnapshot);
+            }
+            import_in(&txn, &embedding, block.as_ref())?;
+            count += 1;
+        }
+
+        txn.commit()?;
+        Ok(count)
+    }
+
+    fn lookup(
+        &self,
+        definition: MultimapTableDefinition<&[u8], &[u8]>,
+        key: &[u8],
+    ) -> Result<Vec<Embedding>, StoreError> {
+        let txn = self.db.begin_read()?;
+        let index = txn.open_multimap_table(definition)?;
+
+        let ids = index
+            .get(key)?
+            .map(|value| value.map(|value| value.value().to_vec()))
+            .collect::<Result<BTreeSet<_>, _>>()?;
+
+        self.load(&txn, ids)
+    }
+
+    fn load(
+        &self,
+        txn: &redb::ReadTransaction,
+        ids: BTreeSet<Vec<u8>>,
+    ) -> Result<Vec<Embedding>, StoreError> {
+        let table = txn.open_table(EMBEDDINGS)?;
+
+        ids.into_iter()
+            .filter_map(|key| match table.get(key.as_slice()) {
+                Ok(Some(value)) => Some(decode_embedding(&key, value.value())),
+                Ok(None) => None,
+                Err(e) => Some(Err(e.into())),
+            })
+            .collect()
+    }
+}
+
+fn insert_in(txn: &WriteTransaction, key: &[u8], embedding: &Embedding) -> Result<(), StoreError> {
+    txn.open_table(EMBEDDINGS)?
+        .insert(key, encode_embedding(embedding).as_slice())?;
+    txn.open_multimap_table(BY_TYPE)?
+        .insert(embedding.to_type().code().as_bytes(), key)?;
+    txn.open_multimap_table(BY_PREFIX)?
+        .insert(prefix_key(&embedding.payload()), key)?;
+    txn.open_multimap_table(BY_CONTENT_HASH)?
+        .insert(embedding.content_hash().as_slice(), key)?;
+    Ok(())
+}
+
+/// Associates an indexed embedding with the block that indexed it
+fn associate_in(txn: &WriteTransaction, key: &[u8], hash: &BlockHash) -> Result<(), StoreError> {
+    txn.open_table(EMBEDDING_BLOCK)?
+        .insert(key, hash.as_byte_array().as_slice())?;
+    txn.open_multimap_table(BY_BLOCK)?
+        .insert(hash.as_byte_array().as_slice(), key)?;
+    Ok(())
+}
+
+/// Indexes an embedding from a snapshot, replacing any with the same id
+fn import_in(
+    txn: &WriteTransaction,
+    embedding: &Embedding,
+    block: Option<&BlockHash>,
+) -> Result<(), StoreError> {
+    let key = id_key(&embedding.id());
+    remove_in(txn, &key)?;
+    insert_in(txn, &key, embedding)?;
+    if let Some(block) = block {
+        associate_in(txn, &key, block)?;
+    }
+    Ok(())
+}
+
+/// Removes an embedding, its secondary index entries, and its membership in the block that
+/// indexed it
+fn remove_in(txn: &WriteTransaction, key: &[u8]) -> Result<bool, StoreError> {
+    let Some(value) = txn
+        .open_table(EMBEDDINGS)?
+        .remove(key)?
+        .map(|value| value.value().to_vec())
+    else {
+        return Ok(false);
+    };
+
+    let embedding = decode_embedding(key, &value)?;
+
+    txn.open_multimap_table(BY_TYPE)?
+        .remove(embedding.to_type().code().as_bytes(), key)?;
+    txn.open_multimap_table(BY_PREFIX)?
+        .remove(prefix_key(&embedding.payload()), key)?;
+    txn.open_multimap_table(BY_CONTENT_HASH)?
+        .remove(embedding.content_hash().as_slice(), key)?;
+    let block = txn
+        .open_table(EMBEDDING_BLOCK)?
+        .remove(key)?
+        .map(|block| block.value().to_vec());
+    if let Some(block) = block {
+        txn.open_multimap_table(BY_BLOCK)?
+            .remove(block.as_slice(), key)?;
+    }
+
+    Ok(true)
+}
+
+fn prefix_key(bytes: &[u8]) -> &[u8] {
+    &bytes[..bytes.len().min(PREFIX_LEN)]
+}
+
+/// Encodes an id as the txid (internal byte order), the two-letter type code, the big-endian
+/// index, and the big-endian sub-index plus one (zero if absent), so that byte order matches
+/// canonical order
+fn id_key(id: &EmbeddingId) -> Vec<u8> {
+    let mut key = Vec::with_capacity(KEY_LEN);
+    key.extend_from_slice(id.txid.as_byte_array());
+    key.extend_from_slice(id.embedding_type.code().as_bytes());
+    key.extend_from_slice(&(id.index as u64).to_be_bytes());
+    key.extend_from_slice(&id.sub_index.map_or(0, |i| i as u64 + 1).to_be_bytes());
+    key
+}
+
+/// Encodes the location (a type byte followed by LEB128 fields) and the payload
+fn encode_embedding(embedding: &Embedding) -> Vec<u8> {
+    let mut v = Vec::with_capacity(embedding.bytes.len() + 8);
+
+    match &embedding.location {
+        EmbeddingLocation::OpReturn { output } => {
+            v.push(0);
+            varint::encode_to_vec(*output as u128, &mut v);
+        }
+        EmbeddingLocation::TaprootAnnex {
+            input,
+            index: 0,
+            tag: TAPROOT_ANNEX_DATA_TAG,
+        } => {
+            v.push(1);
+            varint::encode_to_vec(*input as u128, &mut v);
+        }
+        EmbeddingLocation::TaprootAnnex { input, index, tag } => {
+            v.push(5);
+            varint::encode_to_vec(*input as u128, &mut v);
+            varint::encode_to_vec(*index as u128, &mut v);
+            varint::encode_to_vec(*tag as u128, &mut v);
+        }
+        EmbeddingLocation::BareMultisig { output } => {
+            v.push(4);
+            varint::encode_to_vec(*output as u128, &mut v);
+        }
+        EmbeddingLocation::WitnessEnvelope {
+            input,
+            index,
+            pushes,
+            script_type,
+        } => {
+            v.push(match script_type {
+                ScriptType::Legacy => 2,
+                ScriptType::Tapscript => 3,
+            });
+            varint::encode_to_vec(*input as u128, &mut v);
+            varint::encode_to_vec(*index as u128, &mut v);
+            varint::encode_to_vec(pushes.len() as u128, &mut v);
+            for push in pushes {
+                varint::encode_to_vec(*push as u128, &mut v);
+            }
+        }
+    }
+
+    v.extend_from_slice(&embedding.bytes);
+    v
+}
+
+fn decode_embedding(key: &[u8], value: &[u8]) -> Result<Embedding, StoreError> {
+    let txid = key
+        .get(..32)
+        .and_then(|txid| Txid::from_slice(txid).ok())
+        .ok_or(StoreError::Corrupt)?;
+
+    let (&type_byte, mut rest) = value.split_first().ok_or(StoreError::Corrupt)?;
+    let mut read = || -> Result<usize, StoreError> {
+        let (n, size) = varint::decode(rest).map_err(|_| StoreError::Corrupt)?;
+        rest = &rest[size..];
+        usize::try_from(n).map_err(|_| StoreError::Corrupt)
+    };
+
+    let location = match type_byte {
+        0 => EmbeddingLocation::OpReturn { output: read()? },
+        1 => EmbeddingLocation::TaprootAnnex {
+            input: read()?,
+            index: 0,
+            tag: TAPROOT_ANNEX_DATA_TAG,
+        },
+        5 => {
+            let input = read()?;
+            let index = read()?;
+            let tag = u8::try_from(read()?).map_err(|_| StoreError::Corrupt)?;
+            EmbeddingLocation::TaprootAnnex { input, index, tag }
+        }
+        4 => EmbeddingLocation::BareMultisig { output: read()? },
+        2 | 3 => {
+            let input = read()?;
+            let index = read()?;
+            let count = read()?;
+            let pushes = (0..count).map(|_| read()).collect::<Result<Vec<_>, _>>()?;
+            let script_type = if type_byte == 2 {
+                ScriptType::Legacy
+            } else {
+                ScriptType::Tapscript
+            };
+
+            EmbeddingLocation::WitnessEnvelope {
+                input,
+                index,
+                pushes,
+                script_type,
+            }
+        }
+        _ => return Err(StoreError::Corrupt),
+    };
+
+    Ok(Embedding {
+        bytes: rest.to_vec(),
+        txid,
+        location,
+    })
+}
+
+/// A writer that hashes everything written through it
+struct HashingWriter<W> {
+    inner: W,
+    engine: sha256::HashEngine,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            engine: sha256::Hash::engine(),
+        }
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.engine.input(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A reader that hashes everything read through it
+struct HashingReader<R> {
+    inner: R,
+    engine: sha256::HashEngine,
+}
+
+impl<R: Read> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            engine: sha256::Hash::engine(),
+        }
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.engine.input(&buf[..n]);
+        Ok(n)
+    }
+}
+
+impl From<io::Error> for StoreError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData => {
+                StoreError::InvalidSnapshot
+            }
+            _ => StoreError::Io(e),
+        }
+    }
+}
+
+impl From<redb::Error> for StoreError {
+    fn from(e: redb::Error) -> Self {
+        StoreError::Database(Box::new(e))
+    }
+}
+
+impl From<redb::DatabaseError> for StoreError {
+    fn from(e: redb::DatabaseError) -> Self {
+        StoreError::Database(Box::new(e.into()))
+    }
+}
+
+impl From<redb::TransactionError> for StoreError {
+    fn from(e: redb::TransactionError) -> Self {
+        StoreError::Database(Box::new(e.into()))
+    }
+}
+
+impl From<redb::TableError> for StoreError {
+    fn from(e: redb::TableError) -> Self {
+        StoreError::Database(Box::new(e.into()))
+    }
+}
+
+impl From<redb::StorageError> for StoreError {
+    fn from(e: redb::StorageError) -> Self {
+        StoreError::Database(Box::new(e.into()))
+    }
+}
+
+impl From<redb::CommitError> for StoreError {
+    fn from(e: redb::CommitError) -> Self {
+        StoreError::Database(Box::new(e.into()))
+    }
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Database(e) => write!(f, "Database error: {e}"),
+            StoreError::Corrupt => write!(f, "Stored record is corrupt"),
+            StoreError::Io(e) => write!(f, "I/O error: {e}"),
+            StoreError::InvalidSnapshot => write!(f, "Invalid snapshot"),
+            StoreError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported snapshot version {version}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for StoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StoreError::Database(e) => Some(&**e),
+            StoreError::Io(e) => Some(e),
+            StoreError::Corrupt
+            | StoreError::InvalidSnapshot
+            | StoreError::UnsupportedVersion(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn txid(byte: u8) -> Txid {
+        Txid::from_byte_array([byte; 32])
+    }
+
+    fn block(byte: u8) -> BlockHash {
+        BlockHash::from_byte_array([byte; 32])
+    }
+
+    fn embedding(txid: Txid, location: EmbeddingLocation, bytes: &[u8]) -> Embedding {
+        Embedding {
+            bytes: bytes.to_vec(),
+            txid,
+            location,
+        }
+    }
+
+    fn sample() -> Vec<Embedding> {
+        vec![
+            embedding(
+                txid(2),
+                EmbeddingLocation::OpReturn { output: 300 },
+                b"\x03ord",
+            ),
+            embedding(
+                txid(1),
+                EmbeddingLocation::WitnessEnvelope {
+                    input: 0,
+                    index: 1,
+                    pushes: vec![3, 3],
+                    script_type: ScriptType::Tapscript,
+                },
+                b"ordabc",
+            ),
+            embedding(
+                txid(1),
+                EmbeddingLocation::TaprootAnnex {
+                    input: 2,
+                    index: 0,
+                    tag: 0,
+                },
+                b"xyz",
+            ),
+            embedding(
+                txid(1),
+                EmbeddingLocation::WitnessEnvelope {
+                    input: 0,
+                    index: 0,
+                    pushes: vec![3],
+                    script_type: ScriptType::Legacy,
+                },
+                b"ord",
+            ),
+            embedding(txid(1), EmbeddingLocation::OpReturn { output: 1 }, b""),
+        ]
+    }
+
+    fn sorted(mut embeddings: Vec<Embedding>) -> Vec<Embedding> {
+        embeddings.sort_by_key(Embedding::id);
+        embeddings
+    }
+
+    #[test]
+    fn test_roundtrip_and_canonical_order() {
+        let store = Store::in_memory().unwrap();
+        assert!(store.is_empty().unwrap());
+
+        store.insert_block(&block(1), sample()).unwrap();
+
+        assert_eq!(store.len().unwrap(), 5);
+        assert_eq!(store.all().unwrap(), sorted(sample()));
+
+        for embedding in sample() {
+            assert_eq!(store.get(&embedding.id()).unwrap(), Some(embedding.clone()));
+            assert!(store.contains(&embedding.id()).unwrap());
+        }
+
+        let multisig = embedding(
+            txid(3),
+            EmbeddingLocation::BareMultisig { output: 2 },
+            &[7; 62],
+        );
+        store.insert(&multisig).unwrap();
+        assert_eq!(store.get(&multisig.id()).unwrap(), Some(multisig));
+
+        // The second payload of a batch annex
+        let batch = embedding(
+            txid(3),
+            EmbeddingLocation::TaprootAnnex {
+                input: 0,
+                index: 1,
+                tag: 1,
+            },
+            b"two",
+        );
+        store.insert(&batch).unwrap();
+        assert_eq!(store.get(&batch.id()).unwrap(), Some(batch));
+    }
+
+    #[test]
+    fn test_queries() {
+        let store = Store::in_memory().unwrap();
+        store.insert_block(&block(1), sample()).unwrap();
+        let sample = sample();
+
+        assert_eq!(
+            store.by_txid(&txid(1)).unwrap(),
+            sorted(sample[1..].to_vec())
+        );
+        assert_eq!(store.by_txid(&txid(3)).unwrap(), vec![]);
+
+        assert_eq!(store.by_type(EmbeddingType::OpReturn).unwrap().len(), 2);
+        assert_eq!(
+            store
+                .by_type(EmbeddingType::WitnessEnvelope(ScriptType::Legacy))
+                .unwrap(),
+            vec![sample[3].clone()]
+        );
+
+        assert_eq!(store.by_prefix(b"").unwrap().len(), 5);
+        assert_eq!(store.by_prefix(b"ord").unwrap().len(), 3);
+        assert_eq!(store.by_prefix(b"orda").unwrap(), vec![sample[1].clone()]);
+
+        assert_eq!(
+            store.by_content_hash(&sample[0].content_hash()).unwrap(),
+            vec![sample[3].clone(), sample[0].clone()]
+        );
+        assert_eq!(store.by_block(&block(1)).unwrap(), sorted(sample));
+    }
+
+    #[test]
+    fn test_remove_block() {
+        let store = Store::in_memory().unwrap();
+        let sample = sample();
+
+        store.insert_block(&block(1), sample[..3].to_vec()).unwrap();
+        store.insert_block(&block(2), sample[3..].to_vec()).unwrap();
+
+        // Block 1 is disconnected and its first transaction is re-confirmed in block 3 before
+        // block 1 is removed
+        store
+            .insert_block(&block(3), vec![sample[0].clone()])
+            .unwrap();
+        assert_eq!(
+            store.by_block(&block(1)).unwrap(),
+            vec![sample[2].clone(), sample[1].clone()]
+        );
+
+        assert_eq!(store.remove_block(&block(1)).unwrap(), 2);
+        assert_eq!(store.len().unwrap(), 3);
+        assert_eq!(store.get(&sample[0].id()).unwrap(), Some(sample[0].clone()));
+        assert_eq!(store.get(&sample[1].id()).unwrap(), None);
+        assert_eq!(store.by_prefix(b"orda").unwrap(), vec![]);
+        assert_eq!(store.by_block(&block(1)).unwrap(), vec![]);
+
+        assert_eq!(store.remove_block(&block(1)).unwrap(), 0);
+        assert_eq!(store.remove_block(&block(3)).unwrap(), 1);
+        assert_eq!(store.remove_block(&block(2)).unwrap(), 2);
+        assert!(store.is_empty().unwrap());
+        assert_eq!(store.by_type(EmbeddingType::OpReturn).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_insert_and_remove() {
+        let store = Store::in_memory().unwrap();
+        let sample = sample();
+
+        assert!(!store.insert(&sample[0]).unwrap());
+
+        let replacement = embedding(
+            txid(2),
+            EmbeddingLocation::OpReturn { output: 300 },
+            b"\x03new",
+        );
+        assert!(store.insert(&replacement).unwrap());
+        assert_eq!(store.by_prefix(b"ord").unwrap(), vec![]);
+        assert_eq!(store.by_prefix(b"new").unwrap(), vec![replacement.clone()]);
+
+        assert!(store.remove(&replacement.id()).unwrap());
+        assert!(!store.remove(&replacement.id()).unwrap());
+        assert!(store.is_empty().unwrap());
+    }
+
+    #[test]
+    fn test_persistence() {
+        let path = std::env::temp_dir().join(format!("bitcoin-embed-{}.redb", std::process::id()));
+
+        {
+            let store = Store::open(&path).unwrap();
+            store.insert_block(&block(1), sample()).unwrap();
+        }
+
+        let store = Store::open(&path).unwrap();
+        assert_eq!(store.all().unwrap(), sorted(sample()));
+
+        drop(store);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn populated() -> Store {
+        let store = Store::in_memory().unwrap();
+        let mut embeddings = sample();
+        let mempool = embeddings.pop().unwrap();
+        store.insert_block(&block(1), embeddings).unwrap();
+        store.insert(&mempool).unwrap();
+        store
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let store = populated();
+        let mut snapshot = Vec::new();
+        assert_eq!(store.export_to(&mut snapshot).unwrap(), 5);
+        assert_eq!(&snapshot[..8], b"EMBEDIDX");
+
+        let imported = Store::in_memory().unwrap();
+        assert_eq!(imported.import_from(snapshot.as_slice()).unwrap(), 5);
+        assert_eq!(imported.all().unwrap(), sorted(sample()));
+        assert_eq!(imported.by_block(&block(1)).unwrap().len(), 4);
+        assert_eq!(imported.by_type(EmbeddingType::OpReturn).unwrap().len(), 2);
+
+        // Block associations survive, so reorgs still apply after an import
+        assert_eq!(imported.remove_block(&block(1)).unwrap(), 4);
+        assert_eq!(imported.len().unwrap(), 1);
+
+        // Exports are deterministic
+        let mut again = Vec::new();
+        populated().export_to(&mut again).unwrap();
+        assert_eq!(snapshot, again);
+    }
+
+    #[test]
+    fn test_invalid_snapshot() {
+        let mut snapshot = Vec::new();
+        populated().export_to(&mut snapshot).unwrap();
+        let store = Store::in_memory().unwrap();
+
+        let mut corrupted = snapshot.clone();
+        corrupted[60] ^= 1;
+        assert!(matches!(
+            store.import_from(corrupted.as_slice()),
+            Err(StoreError::InvalidSnapshot)
+        ));
+
+        let truncated = &snapshot[..snapshot.len() - 1];
+        assert!(matches!(
+            store.import_from(truncated),
+            Err(StoreError::InvalidSnapshot)
+        ));
+
+        let mut version = snapshot.clone();
+        version[8] = 2;
+        assert!(matches!(
+            store.import_from(version.as_slice()),
+            Err(StoreError::UnsupportedVersion(2))
+        ));
+
+        // Failed imports leave the store untouched
+        assert!(store.is_empty().unwrap());
+    }
+
+    #[test]
+    fn test_snapshot_file() {
+        let path = std::env::temp_dir().join(format!("bitcoin-embed-{}.snap", std::process::id()));
+        assert_eq!(populated().export(&path).unwrap(), 5);
+
+        let store = Store::in_memory().unwrap();
+        assert_eq!(store.import(&path).unwrap(), 5);
+        assert_eq!(store.all().unwrap(), sorted(sample()));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_jsonl() {
+        let mut jsonl = Vec::new();
+        assert_eq!(populated().export_jsonl_to(&mut jsonl).unwrap(), 5);
+        let text = String::from_utf8(jsonl).unwrap();
+        assert_eq!(text.lines().count(), 6);
+        assert!(text.lines().next().unwrap().contains("\"version\":1"));
+
+        let store = Store::in_memory().unwrap();
+        assert_eq!(store.import_jsonl_from(text.as_bytes()).unwrap(), 5);
+        assert_eq!(store.all().unwrap(), sorted(sample()));
+        assert_eq!(store.by_block(&block(1)).unwrap().len(), 4);
+
+        let bad = text.replacen("\"version\":1", "\"version\":9", 1);
+        assert!(matches!(
+            Store::in_memory()
+                .unwrap()
+                .import_jsonl_from(bad.as_bytes()),
+            Err(StoreError::UnsupportedVersion(9))
+        ));
+
+        // An annex payload under another protocol's tag keeps its tag
+        let tagged = embedding(
+            txid(4),
+            EmbeddingLocation::TaprootAnnex {
+                input: 1,
+                index: 0,
+                tag: 0x42,
+            },
+            b"tagged",
+        );
+        let source = Store::in_memory().unwrap();
+        source.insert(&tagged).unwrap();
+        assert_eq!(source.get(&tagged.id()).unwrap(), Some(tagged.clone()));
+        let mut jsonl = Vec::new();
+        source.export_jsonl_to(&mut jsonl).unwrap();
+        let store = Store::in_memory().unwrap();
+        store.import_jsonl_from(jsonl.as_slice()).unwrap();
+        assert_eq!(store.all().unwrap(), vec![tagged]);
+    }
+}
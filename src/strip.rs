@@ -0,0 +1,443 @@
+//! # Embedding Removal
+//!
+//! Removes embeddings from a transaction, to study it with and without its payloads:
+//! `OP_RETURN` outputs are dropped, annexes popped from witnesses, and envelopes cut out of
+//! tapscript and P2WSH witness scripts. Bare multisig outputs carry value and are kept.
+//!
+//! [`strip`] reports what was removed and which inputs' signatures survive. Removing an
+//! output breaks every signature committing to it, including `SINGLE` signatures on later
+//! inputs, whose outputs shift. Removing an annex breaks its input's taproot signature.
+//! Rewriting a witness script always invalidates its input, whatever the signatures, since
+//! the spent output commits to the script. Signatures are recognized from the witness and
+//! scriptSig alone, so unusual scripts may be reported as unsigned.
+
+use crate::envelope;
+use crate::policy::{InputImpact, InputSignature};
+
+use bitcoin::taproot::{self, ControlBlock, LeafVersion};
+use bitcoin::{Script, Transaction, TxIn, Witness, ecdsa};
+
+/// The embeddings to remove
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Filter {
+    /// Whether to remove `OP_RETURN` outputs
+    pub op_return: bool,
+    /// Whether to remove annexes
+    pub annex: bool,
+    /// Whether to remove envelopes from witness scripts
+    pub envelope: bool,
+}
+
+/// An embedding removed from a transaction
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Removal {
+    /// An `OP_RETURN` output, by its index in the original transaction
+    OpReturn {
+        /// The output index
+        output: usize,
+        /// The size of the output script
+        size: usize,
+    },
+    /// An annex
+    Annex {
+        /// The input index
+        input: usize,
+        /// The size of the annex
+        size: usize,
+    },
+    /// The envelopes in an input's witness script
+    Envelope {
+        /// The input index
+        input: usize,
+        /// The number of envelopes
+        count: usize,
+        /// The number of script bytes removed
+        size: usize,
+    },
+}
+
+/// A transaction with embeddings removed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stripped {
+    /// The transaction without the removed embeddings
+    pub tx: Transaction,
+    /// The embeddings removed
+    pub removed: Vec<Removal>,
+    /// The signature recognized on each input of the original transaction
+    pub signatures: Vec<InputSignature>,
+    /// The effect of the removal on each input
+    pub inputs: Vec<InputImpact>,
+}
+
+impl Filter {
+    /// Removes every kind of embedding
+    pub const ALL: Self = Self {
+        op_return: true,
+        annex: true,
+        envelope: true,
+    };
+
+    /// Removes nothing
+    pub const NONE: Self = Self {
+        op_return: false,
+        annex: false,
+        envelope: false,
+    };
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl Stripped {
+    /// Returns the indices of inputs the removal invalidated
+    pub fn invalidated(&self) -> Vec<usize> {
+        self.inputs
+            .iter()
+            .enumerate()
+            .filter(|(_, impact)| **impact == InputImpact::Invalidated)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Returns whether the transaction still has outputs and no input was invalidated
+    pub fn is_valid(&self) -> bool {
+        !self.tx.output.is_empty() && self.invalidated().is_empty()
+    }
+
+    /// Returns whether the transaction is still valid with every input signed
+    pub fn is_signed(&self) -> bool {
+        self.is_valid()
+            && self.signatures.iter().all(|signature| {
+                !matches!(
+                    signature,
+                    InputSignature::Unsigned | InputSignature::UnsignedTaproot
+                )
+            })
+    }
+}
+
+/// Returns `tx` without the embeddings selected by `filter`
+pub fn remove_embeddings(tx: &Transaction, filter: Filter) -> Transaction {
+    strip(tx, filter).tx
+}
+
+/// Removes the embeddings selected by `filter` from `tx`, reporting what was removed and its
+/// effect on each input's signature
+pub fn strip(tx: &Transaction, filter: Filter) -> Stripped {
+    let mut stripped = tx.clone();
+    let mut removed = Vec::new();
+
+    let mut removed_outputs = Vec::new();
+    if filter.op_return {
+        for (output, txout) in tx.output.iter().enumerate() {
+            if txout.script_pubkey.is_op_return() {
+                removed_outputs.push(output);
+                removed.push(Removal::OpReturn {
+                    output,
+                    size: txout.script_pubkey.len(),
+                });
+            }
+        }
+        stripped
+            .output
+            .retain(|txout| !txout.script_pubkey.is_op_return());
+    }
+
+    let mut rewritten = vec![false; tx.input.len()];
+    let mut annex_removed = vec![false; tx.input.len()];
+    for (input, txin) in stripped.input.iter_mut().enumerate() {
+        let mut elements = txin.witness.to_vec();
+        let annex = txin.witness.taproot_annex().map(<[u8]>::len);
+
+        if filter.envelope {
+            if let Some(index) = script_index(&txin.witness) {
+                let script = Script::from_bytes(&elements[index]);
+                let envelopes = envelope::from_script(script);
+                if !envelopes.is_empty() {
+                    let mut bytes = Vec::with_capacity(script.len());
+                    let mut start = 0;
+                    for envelope in &envelopes {
+                        bytes.extend_from_slice(&script.as_bytes()[start..envelope.range.start]);
+                        start = envelope.range.end;
+                    }
+                    bytes.extend_from_slice(&script.as_bytes()[start..]);
+
+                    removed.push(Removal::Envelope {
+                        input,
+                        count: envelopes.len(),
+                        size: script.len() - bytes.len(),
+                    });
+                    elements[index] = bytes;
+                    rewritten[input] = true;
+                }
+            }
+        }
+
+        if let Some(size) = annex.filter(|_| filter.annex) {
+            elements.pop();
+            removed.push(Removal::Annex { input, size });
+            annex_removed[input] = true;
+        }
+
+        txin.witness = Witness::from_slice(&elements);
+    }
+
+    let signatures: Vec<_> = tx.input.iter().map(signature).collect();
+    let inputs = signatures
+        .iter()
+        .enumerate()
+        .map(|(index, signature)| {
+            let (all, single, _) = signature.commitments();
+            let invalidated = rewritten[index]
+                || (annex_removed[index] && matches!(signature, InputSignature::Taproot(_)))
+                || (all && !removed_outputs.is_empty())
+                || (single && removed_outputs.iter().any(|output| *output <= index));
+            if invalidated {
+                InputImpact::Invalidated
+            } else {
+                InputImpact::Unaffected
+            }
+        })
+        .collect();
+
+    Stripped {
+        tx: stripped,
+        removed,
+        signatures,
+        inputs,
+    }
+}
+
+/// Returns the index of the tapscript leaf or P2WSH witness script in a witness, classifying
+/// it as [`Embedding::from_transaction`](crate::Embedding::from_transaction) does
+fn script_index(witness: &Witness) -> Option<usize> {
+    let annexed = witness.taproot_annex().is_some();
+    if witness.taproot_leaf_script().is_some() {
+        Some(witness.len() - 2 - usize::from(annexed))
+    } else if !annexed && witness.len() > 1 {
+        Some(witness.len() - 1)
+    } else {
+        None
+    }
+}
+
+/// Recognizes the first signature on an input: a Schnorr signature in a key path spend or
+/// before a tapscript control block, or else a DER-encoded ECDSA signature in the witness or scriptSig
+fn signature(txin: &TxIn) -> InputSignature {
+    let witness = &txin.witness;
+    let annexed = witness.taproot_annex().is_some();
+    let elements: Vec<_> = witness
+        .iter()
+        .take(witness.len() - usize::from(annexed))
+        .collect();
+
+    let script_path = elements
+        .last()
+        .filter(|_| elements.len() > 1)
+        .and_then(|last| ControlBlock::decode(last).ok())
+        .is_some_and(|control_block| control_block.leaf_version == LeafVersion::TapScript);
+    let key_path = elements.len() == 1 && matches!(elements[0].len(), 64 | 65);
+    if annexed || script_path || key_path {
+        let end = if script_path { elements.len() - 2 } else { 1 };
+        return elements[..end]
+            .iter()
+            .filter(|element| matches!(element.len(), 64 | 65))
+            .find_map(|element| taproot::Signature::from_slice(element).ok())
+            .map_or(InputSignature::UnsignedTaproot, |signature| {
+                InputSignature::Taproot(signature.sighash_type)
+            });
+    }
+
+    if let Some(signature) = elements
+        .iter()
+        .find_map(|element| ecdsa::Signature::from_slice(element).ok())
+    {
+        return InputSignature::SegwitV0(signature.sighash_type);
+    }
+    txin.script_sig
+        .instructions()
+        .filter_map(|instruction| {
+            ecdsa::Signature::from_slice(instruction.ok()?.push_bytes()?.as_bytes()).ok()
+        })
+        .next()
+        .map_or(InputSignature::Unsigned, |signature| {
+            InputSignature::Legacy(signature.sighash_type)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Embedding;
+    use crate::annex;
+    use crate::descriptor::CommitOutput;
+    use crate::envelope::EnvelopeBuilder;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::key::Keypair;
+    use bitcoin::opcodes::all::OP_CHECKSIG;
+    use bitcoin::script::Builder;
+    use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+    use bitcoin::sighash::{EcdsaSighashType, TapSighashType};
+    use bitcoin::{
+        Amount, OutPoint, ScriptBuf, Sequence, TxOut, Txid, WScriptHash, absolute::LockTime,
+        transaction::Version,
+    };
+
+    fn txin(n: u8, witness: Witness) -> TxIn {
+        TxIn {
+            previous_output: OutPoint::new(Txid::from_byte_array([n; 32]), 0),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness,
+        }
+    }
+
+    /// A taproot key path spend with an annex, and a P2WPKH spend signed with `SINGLE`
+    fn transaction() -> Transaction {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[3; 32]).unwrap();
+        let signature = ecdsa::Signature {
+            signature: secp.sign_ecdsa(&Message::from_digest([4; 32]), &secret_key),
+            sighash_type: EcdsaSighashType::Single,
+        };
+
+        let mut key_path = Witness::from_slice(&[[1; 64]]);
+        annex::append_data(&mut key_path, b"annex data").unwrap();
+        let p2wpkh = Witness::from_slice(&[
+            signature.to_vec(),
+            secret_key.public_key(&secp).serialize().to_vec(),
+        ]);
+
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![txin(1, key_path), txin(2, p2wpkh)],
+            output: vec![
+                TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey: ScriptBuf::new_op_return(b"op_return data"),
+                },
+                TxOut {
+                    value: Amount::from_sat(1000),
+                    script_pubkey: ScriptBuf::new_p2wsh(&WScriptHash::all_zeros()),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_strip_op_return() {
+        let tx = transaction();
+        let stripped = strip(
+            &tx,
+            Filter {
+                op_return: true,
+                ..Filter::NONE
+            },
+        );
+        assert_eq!(
+            stripped.signatures,
+            vec![
+                InputSignature::Taproot(TapSighashType::Default),
+                InputSignature::SegwitV0(EcdsaSighashType::Single),
+            ]
+        );
+        assert_eq!(
+            stripped.removed,
+            vec![Removal::OpReturn {
+                output: 0,
+                size: 16
+            }]
+        );
+        assert_eq!(stripped.tx.output, tx.output[1..]);
+        // The SINGLE signature on input 1 now commits to a missing output
+        assert_eq!(stripped.invalidated(), vec![0, 1]);
+        assert!(!stripped.is_valid());
+
+        let embeddings = Embedding::from_transaction(&stripped.tx);
+        assert_eq!(embeddings.len(), 1);
+        assert_eq!(embeddings[0].bytes, b"annex data");
+    }
+
+    #[test]
+    fn test_strip_annex() {
+        let tx = transaction();
+        let stripped = strip(
+            &tx,
+            Filter {
+                annex: true,
+                ..Filter::NONE
+            },
+        );
+        assert_eq!(
+            stripped.removed,
+            vec![Removal::Annex { input: 0, size: 12 }]
+        );
+        assert_eq!(stripped.tx.input[0].witness.len(), 1);
+        assert_eq!(stripped.tx.compute_txid(), tx.compute_txid());
+        assert_eq!(stripped.invalidated(), vec![0]);
+
+        let stripped = strip(&tx, Filter::NONE);
+        assert_eq!(stripped.tx, tx);
+        assert!(stripped.removed.is_empty());
+        assert!(stripped.is_signed());
+    }
+
+    #[test]
+    fn test_strip_envelope() {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_seckey_slice(&secp, &[2; 32]).unwrap();
+        let (key, _) = keypair.x_only_public_key();
+        let prefix = Builder::new()
+            .push_x_only_key(&key)
+            .push_opcode(OP_CHECKSIG);
+        let leaf = EnvelopeBuilder::new()
+            .append_to_builder(vec![b"revealed".to_vec()], prefix.clone())
+            .into_script();
+        let commit = CommitOutput::new(&secp, key, vec![leaf.clone()]).unwrap();
+
+        let mut tx = transaction();
+        tx.input = vec![txin(
+            1,
+            Witness::from_slice(&[
+                vec![1; 64],
+                leaf.to_bytes(),
+                commit.control_block(&leaf).unwrap().serialize(),
+            ]),
+        )];
+        let stripped = strip(&tx, Filter::ALL);
+        assert_eq!(
+            stripped.signatures,
+            vec![InputSignature::Taproot(TapSighashType::Default)]
+        );
+        assert_eq!(
+            stripped.removed,
+            vec![
+                Removal::OpReturn {
+                    output: 0,
+                    size: 16
+                },
+                Removal::Envelope {
+                    input: 0,
+                    count: 1,
+                    size: leaf.len() - prefix.as_script().len()
+                },
+            ]
+        );
+        assert_eq!(
+            stripped.tx.input[0]
+                .witness
+                .taproot_leaf_script()
+                .unwrap()
+                .script,
+            prefix.as_script()
+        );
+        assert!(Embedding::from_transaction(&stripped.tx).is_empty());
+        assert_eq!(stripped.invalidated(), vec![0]);
+        assert_eq!(remove_embeddings(&tx, Filter::ALL), stripped.tx);
+    }
+}
@@ -0,0 +1,341 @@
+//! # Transaction Templates
+//!
+//! Builds a PSBT whose embeddings are fixed-size placeholders, so its weight and fee can be
+//! settled before the payload is known, then swaps in the real data once it is. Placeholders
+//! are zero bytes marked with a proprietary field ([`PSBT_OUT_PLACEHOLDER`],
+//! [`PSBT_IN_ANNEX_PLACEHOLDER`]), so a template survives being passed between parties.
+//!
+//! [`fill`] replaces every placeholder at once, and only with data of exactly the same size,
+//! so the filled transaction has the template's weight. Signatures commit to outputs and
+//! annexes, so templates are filled before they are signed. Envelopes are not templated: the
+//! leaf script is committed to by the commit output, so its data must be known to fund it.
+
+use crate::psbt::{self, PROPRIETARY_PREFIX};
+
+use bitcoin::ScriptBuf;
+use bitcoin::psbt::{Input, Output, Psbt, raw};
+use bitcoin::script::PushBytesBuf;
+use std::fmt;
+
+/// The proprietary subtype marking an output's `OP_RETURN` data as a placeholder
+pub const PSBT_OUT_PLACEHOLDER: u8 = 0x00;
+
+/// The proprietary subtype marking an input's planned annex data as a placeholder
+pub const PSBT_IN_ANNEX_PLACEHOLDER: u8 = 0x01;
+
+/// A placeholder in a template
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Placeholder {
+    /// `OP_RETURN` data in an output
+    OpReturn {
+        /// The output index
+        output: usize,
+        /// The size of the data
+        size: usize,
+    },
+    /// Annex data planned for an input
+    Annex {
+        /// The input index
+        input: usize,
+        /// The size of the data
+        size: usize,
+    },
+}
+
+/// Error types for building and filling templates
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    /// The PSBT has no input at this index
+    NoSuchInput(usize),
+    /// The number of payloads differs from the number of placeholders
+    CountMismatch {
+        /// The number of placeholders
+        expected: usize,
+        /// The number of payloads
+        found: usize,
+    },
+    /// A payload's size differs from its placeholder's
+    SizeMismatch {
+        /// The index of the placeholder
+        index: usize,
+        /// The size of the placeholder
+        expected: usize,
+        /// The size of the payload
+        found: usize,
+    },
+    /// An input has already been signed
+    AlreadySigned(usize),
+}
+
+impl Placeholder {
+    /// Returns the size of the data the placeholder stands for
+    pub fn size(&self) -> usize {
+        match self {
+            Placeholder::OpReturn { size, .. } | Placeholder::Annex { size, .. } => *size,
+        }
+    }
+}
+
+/// Appends a zero-value `OP_RETURN` output with a placeholder of `size` bytes, returning its
+/// index
+pub fn add_op_return(psbt: &mut Psbt, size: usize) -> usize {
+    let output = psbt::add_op_return(psbt, &vec![0; size]);
+    psbt.outputs[output]
+        .proprietary
+        .insert(placeholder_key(PSBT_OUT_PLACEHOLDER), Vec::new());
+    output
+}
+
+/// Plans a placeholder of `size` bytes as the annex data of `input`
+pub fn set_annex(psbt: &mut Psbt, input: usize, size: usize) -> Result<(), TemplateError> {
+    let input = psbt
+        .inputs
+        .get_mut(input)
+        .ok_or(TemplateError::NoSuchInput(input))?;
+    psbt::set_annex_plan(input, vec![0; size]);
+    input
+        .proprietary
+        .insert(placeholder_key(PSBT_IN_ANNEX_PLACEHOLDER), Vec::new());
+    Ok(())
+}
+
+/// Returns the placeholders of a template: its `OP_RETURN` outputs, then its annexes, each in
+/// index order
+pub fn placeholders(psbt: &Psbt) -> Vec<Placeholder> {
+    let outputs = psbt
+        .outputs
+        .iter()
+        .zip(&psbt.unsigned_tx.output)
+        .enumerate()
+        .filter(|(_, (output, _))| is_placeholder_output(output))
+        .map(|(i, (_, txout))| Placeholder::OpReturn {
+            output: i,
+            size: op_return_data(&txout.script_pubkey).len(),
+        });
+    let inputs = psbt
+        .inputs
+        .iter()
+        .enumerate()
+        .filter(|(_, input)| is_placeholder_input(input))
+        .filter_map(|(i, input)| {
+            psbt::annex_plan(input).map(|data| Placeholder::Annex {
+                input: i,
+                size: data.len(),
+            })
+        });
+    outputs.chain(inputs).collect()
+}
+
+/// Replaces the placeholders of a template with `payloads`, in the order of
+/// [`placeholders`]. Fails without changing the PSBT unless there is one payload per
+/// placeholder, each of the same size, and no input has been signed.
+pub fn fill(psbt: &mut Psbt, payloads: &[&[u8]]) -> Result<(), TemplateError> {
+    let placeholders = placeholders(psbt);
+    if placeholders.len() != payloads.len() {
+        return Err(TemplateError::CountMismatch {
+            expected: placeholders.len(),
+            found: payloads.len(),
+        });
+    }
+    for (index, (placeholder, payload)) in placeholders.iter().zip(payloads).enumerate() {
+        if placeholder.size() != payload.len() {
+            return Err(TemplateError::SizeMismatch {
+                index,
+                expected: placeholder.size(),
+                found: payload.len(),
+            });
+        }
+    }
+    if let Some(input) = psbt.inputs.iter().position(is_signed) {
+        return Err(TemplateError::AlreadySigned(input));
+    }
+
+    for (placeholder, payload) in placeholders.into_iter().zip(payloads) {
+        match placeholder {
+            Placeholder::OpReturn { output, .. } => {
+                let data = PushBytesBuf::try_from(payload.to_vec()).expect("data fits in a push");
+                psbt.unsigned_tx.output[output].script_pubkey = ScriptBuf::new_op_return(data);
+                psbt.outputs[output]
+                    .proprietary
+                    .remove(&placeholder_key(PSBT_OUT_PLACEHOLDER));
+            }
+            Placeholder::Annex { input, .. } => {
+                let input = &mut psbt.inputs[input];
+                psbt::set_annex_plan(input, payload.to_vec());
+                input
+                    .proprietary
+                    .remove(&placeholder_key(PSBT_IN_ANNEX_PLACEHOLDER));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_placeholder_output(output: &Output) -> bool {
+    output
+        .proprietary
+        .contains_key(&placeholder_key(PSBT_OUT_PLACEHOLDER))
+}
+
+fn is_placeholder_input(input: &Input) -> bool {
+    input
+        .proprietary
+        .contains_key(&placeholder_key(PSBT_IN_ANNEX_PLACEHOLDER))
+}
+
+fn is_signed(input: &Input) -> bool {
+    input.final_script_sig.is_some()
+        || input.final_script_witness.is_some()
+        || input.tap_key_sig.is_some()
+        || !input.tap_script_sigs.is_empty()
+        || !input.partial_sigs.is_empty()
+}
+
+/// Returns the data pushed by an `OP_RETURN` script
+fn op_return_data(script: &bitcoin::Script) -> Vec<u8> {
+    script
+        .instructions()
+        .skip(1)
+        .filter_map(|instruction| Some(instruction.ok()?.push_bytes()?.as_bytes().to_vec()))
+        .flatten()
+        .collect()
+}
+
+fn placeholder_key(subtype: u8) -> raw::ProprietaryKey {
+    raw::ProprietaryKey {
+        prefix: PROPRIETARY_PREFIX.to_vec(),
+        subtype,
+        key: Vec::new(),
+    }
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::NoSuchInput(input) => write!(f, "PSBT has no input {input}"),
+            TemplateError::CountMismatch { expected, found } => {
+                write!(f, "Expected {expected} payloads, found {found}")
+            }
+            TemplateError::SizeMismatch {
+                index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Payload {index} is {found} bytes, but its placeholder is {expected} bytes"
+            ),
+            TemplateError::AlreadySigned(input) => write!(f, "Input {input} is already signed"),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Embedding;
+    use crate::annex;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::{
+        Amount, OutPoint, Sequence, Transaction, TxIn, TxOut, Txid, WScriptHash, Witness,
+        absolute::LockTime, transaction::Version,
+    };
+
+    fn template() -> Psbt {
+        let mut psbt = Psbt::from_unsigned_tx(Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(1000),
+                script_pubkey: ScriptBuf::new_p2wsh(&WScriptHash::all_zeros()),
+            }],
+        })
+        .unwrap();
+        add_op_return(&mut psbt, 40);
+        set_annex(&mut psbt, 0, 100).unwrap();
+        psbt
+    }
+
+    /// Returns the weight of the transaction with a key path spend and planned annex in each
+    /// input's witness
+    fn weight(psbt: &Psbt) -> bitcoin::Weight {
+        let mut tx = psbt.unsigned_tx.clone();
+        for (txin, input) in tx.input.iter_mut().zip(&psbt.inputs) {
+            txin.witness = Witness::from_slice(&[[0; 64]]);
+            if let Some(data) = psbt::annex_plan(input) {
+                annex::append_data(&mut txin.witness, data).unwrap();
+            }
+        }
+        tx.weight()
+    }
+
+    #[test]
+    fn test_fill() {
+        let mut psbt = template();
+        assert_eq!(
+            placeholders(&psbt),
+            vec![
+                Placeholder::OpReturn {
+                    output: 1,
+                    size: 40
+                },
+                Placeholder::Annex {
+                    input: 0,
+                    size: 100
+                },
+            ]
+        );
+        assert_eq!(
+            set_annex(&mut psbt, 1, 100),
+            Err(TemplateError::NoSuchInput(1))
+        );
+
+        let template_weight = weight(&psbt);
+        let (op_return, annex_data) = ([7; 40], [9; 100]);
+        fill(&mut psbt, &[&op_return, &annex_data]).unwrap();
+        assert!(placeholders(&psbt).is_empty());
+        assert_eq!(weight(&psbt), template_weight);
+        assert_eq!(psbt::annex_plan(&psbt.inputs[0]), Some(&annex_data[..]));
+
+        let embeddings = Embedding::from_transaction(&psbt.unsigned_tx);
+        assert_eq!(embeddings.len(), 1);
+        assert_eq!(embeddings[0].payload(), &op_return[..]);
+    }
+
+    #[test]
+    fn test_fill_mismatch() {
+        let mut psbt = template();
+        let original = psbt.clone();
+        assert_eq!(
+            fill(&mut psbt, &[&[0; 40]]),
+            Err(TemplateError::CountMismatch {
+                expected: 2,
+                found: 1
+            })
+        );
+        assert_eq!(
+            fill(&mut psbt, &[&[0; 40], &[0; 101]]),
+            Err(TemplateError::SizeMismatch {
+                index: 1,
+                expected: 100,
+                found: 101
+            })
+        );
+
+        psbt.inputs[0].final_script_witness = Some(Witness::from_slice(&[[0; 64]]));
+        assert_eq!(
+            fill(&mut psbt, &[&[0; 40], &[0; 100]]),
+            Err(TemplateError::AlreadySigned(0))
+        );
+        psbt.inputs[0].final_script_witness = None;
+        assert_eq!(psbt, original);
+    }
+}
@@ -0,0 +1,412 @@
+//! # Testing Utilities
+//!
+//! Helpers for applications testing against this crate without a regtest node (`testing`
+//! feature). [`MockResolver`] serves fixture transactions and blocks, counts lookups, and
+//! fails on demand. [`FixtureBuilder`] fabricates transactions carrying any mix of embedding
+//! types, with outpoints and keys derived from a seed so the same seed always produces the
+//! same transactions.
+
+use crate::TAPROOT_ANNEX_DATA_TAG;
+use crate::descriptor::CommitOutput;
+use crate::envelope::EnvelopeBuilder;
+use crate::resolver::{MemoryResolver, ResolveError, Resolver};
+
+use bitcoin::block::{Header, Version as BlockVersion};
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::opcodes::OP_TRUE;
+use bitcoin::opcodes::all::{OP_CHECKMULTISIG, OP_PUSHNUM_1};
+use bitcoin::script::{Builder, PushBytesBuf};
+use bitcoin::secp256k1::{Keypair, Secp256k1, SecretKey};
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Amount, Block, BlockHash, CompactTarget, OutPoint, ScriptBuf, Sequence, Transaction, TxIn,
+    TxMerkleNode, TxOut, Txid, Witness, absolute::LockTime,
+};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The value of each fabricated prevout and output
+const FIXTURE_VALUE: Amount = Amount::from_sat(10_000);
+
+/// The number of data bytes carried by each fake key in a bare multisig output
+const MULTISIG_KEY_DATA: usize = 31;
+
+/// A resolver over fixtures that counts lookups and can be told to fail
+#[derive(Debug, Default)]
+pub struct MockResolver {
+    inner: MemoryResolver,
+    failures: HashMap<Txid, ResolveError>,
+    transaction_calls: AtomicUsize,
+    block_calls: AtomicUsize,
+}
+
+impl MockResolver {
+    /// Returns an empty resolver
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a transaction
+    pub fn insert_transaction(&mut self, tx: Transaction) {
+        self.inner.insert_transaction(tx);
+    }
+
+    /// Adds a block and each of its transactions
+    pub fn insert_block(&mut self, block: Block) {
+        self.inner.insert_block(block);
+    }
+
+    /// Makes lookups of `txid` fail with `error`
+    pub fn fail_transaction(&mut self, txid: Txid, error: ResolveError) {
+        self.failures.insert(txid, error);
+    }
+
+    /// Returns the number of transaction lookups so far
+    pub fn transaction_calls(&self) -> usize {
+        self.transaction_calls.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of block lookups so far
+    pub fn block_calls(&self) -> usize {
+        self.block_calls.load(Ordering::Relaxed)
+    }
+}
+
+impl Resolver for MockResolver {
+    fn transaction(&self, txid: &Txid) -> Result<Transaction, ResolveError> {
+        self.transaction_calls.fetch_add(1, Ordering::Relaxed);
+        if let Some(error) = self.failures.get(txid) {
+            return Err(error.clone());
+        }
+        self.inner.transaction(txid)
+    }
+
+    fn block(&self, hash: &BlockHash) -> Result<Block, ResolveError> {
+        self.block_calls.fetch_add(1, Ordering::Relaxed);
+        self.inner.block(hash)
+    }
+}
+
+/// A fabricated transaction together with the outputs spent by each of its inputs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fixture {
+    /// The transaction
+    pub tx: Transaction,
+    /// The outputs spent by each input, in order
+    pub prevouts: Vec<TxOut>,
+}
+
+/// Builds fixture transactions deterministically from a seed
+#[derive(Debug, Clone)]
+pub struct FixtureBuilder {
+    seed: u64,
+    inputs: Vec<TxIn>,
+    prevouts: Vec<TxOut>,
+    outputs: Vec<TxOut>,
+}
+
+impl FixtureBuilder {
+    /// Returns a builder whose outpoints and keys are derived from `seed`
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            inputs: Vec::new(),
+            prevouts: Vec::new(),
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Adds an `OP_RETURN` output pushing `data`
+    pub fn op_return(mut self, data: &[u8]) -> Self {
+        let data = PushBytesBuf::try_from(data.to_vec()).expect("data fits in one push");
+        self.outputs.push(TxOut {
+            value: Amount::ZERO,
+            script_pubkey: ScriptBuf::new_op_return(data),
+        });
+        self
+    }
+
+    /// Adds a bare multisig output carrying `data` in one or two fake keys. The data is padded
+    /// with zeros to a multiple of 31 bytes, and panics if longer than 62 bytes.
+    pub fn bare_multisig(mut self, data: &[u8]) -> Self {
+        let chunks = data.chunks(MULTISIG_KEY_DATA).collect::<Vec<_>>();
+        assert!(
+            (1..=2).contains(&chunks.len()),
+            "bare multisig carries 1 to {} bytes",
+            2 * MULTISIG_KEY_DATA
+        );
+
+        let mut builder = Builder::new().push_opcode(OP_PUSHNUM_1);
+        for chunk in &chunks {
+            let mut key = vec![0x02];
+            key.extend(*chunk);
+            key.resize(33, 0);
+            builder = builder.push_slice(<[u8; 33]>::try_from(key).expect("33 bytes"));
+        }
+        builder = builder
+            .push_slice(self.keypair().public_key().serialize())
+            .push_int(chunks.len() as i64 + 1)
+            .push_opcode(OP_CHECKMULTISIG);
+
+        self.outputs.push(TxOut {
+            value: FIXTURE_VALUE,
+            script_pubkey: builder.into_script(),
+        });
+        self
+    }
+
+    /// Adds a taproot key path input with an annex carrying `data`
+    pub fn annex(mut self, data: &[u8]) -> Self {
+        let annex = [&[0x50, TAPROOT_ANNEX_DATA_TAG][..], data].concat();
+        let signature = self.hash().to_vec().repeat(2);
+        let prevout = self.p2tr_prevout();
+        self.push_input(Witness::from_slice(&[signature, annex]), prevout);
+        self
+    }
+
+    /// Adds a taproot script path input revealing an envelope with `fields`
+    pub fn tapscript_envelope(mut self, fields: Vec<Vec<u8>>) -> Self {
+        let leaf = EnvelopeBuilder::new()
+            .append_to_builder(fields, Builder::new())
+            .push_opcode(OP_TRUE)
+            .into_script();
+
+        let secp = Secp256k1::new();
+        let (internal_key, _) = self.keypair().x_only_public_key();
+        let commit = CommitOutput::new(&secp, internal_key, vec![leaf.clone()]).expect("one leaf");
+        let control_block = commit.control_block(&leaf).expect("committed leaf");
+
+        let prevout = TxOut {
+            value: FIXTURE_VALUE,
+            script_pubkey: commit.script_pubkey(),
+        };
+        let witness = Witness::from_slice(&[leaf.to_bytes(), control_block.serialize()]);
+        self.push_input(witness, prevout);
+        self
+    }
+
+    /// Adds a P2WSH input revealing an envelope with `fields`
+    pub fn legacy_envelope(mut self, fields: Vec<Vec<u8>>) -> Self {
+        let witness_script = EnvelopeBuilder::new()
+            .append_to_builder(fields, Builder::new())
+            .push_opcode(OP_TRUE)
+            .into_script();
+
+        let prevout = TxOut {
+            value: FIXTURE_VALUE,
+            script_pubkey: ScriptBuf::new_p2wsh(&witness_script.wscript_hash()),
+        };
+        let witness = Witness::from_slice(&[vec![], witness_script.to_bytes()]);
+        self.push_input(witness, prevout);
+        self
+    }
+
+    /// Adds an output
+    pub fn output(mut self, output: TxOut) -> Self {
+        self.outputs.push(output);
+        self
+    }
+
+    /// Returns the transaction and its prevouts. A taproot key path input and a taproot output
+    /// are added if there are no inputs or no outputs.
+    pub fn build(mut self) -> Fixture {
+        if self.inputs.is_empty() {
+            let signature = self.hash().to_vec().repeat(2);
+            let prevout = self.p2tr_prevout();
+            self.push_input(Witness::from_slice(&[signature]), prevout);
+        }
+        if self.outputs.is_empty() {
+            let output = self.p2tr_prevout();
+            self.outputs.push(output);
+        }
+
+        Fixture {
+            tx: Transaction {
+                version: Version::TWO,
+                lock_time: LockTime::ZERO,
+                input: self.inputs,
+                output: self.outputs,
+            },
+            prevouts: self.prevouts,
+        }
+    }
+
+    /// Returns a hash unique to the seed and the builder's current state
+    fn hash(&self) -> [u8; 32] {
+        let mut preimage = b"bitcoin-embed/testing".to_vec();
+        preimage.extend(self.seed.to_le_bytes());
+        preimage.extend((self.inputs.len() as u32).to_le_bytes());
+        preimage.extend((self.outputs.len() as u32).to_le_bytes());
+        sha256::Hash::hash(&preimage).to_byte_array()
+    }
+
+    fn keypair(&self) -> Keypair {
+        let secret = SecretKey::from_slice(&self.hash()).expect("valid secret key");
+        Keypair::from_secret_key(&Secp256k1::signing_only(), &secret)
+    }
+
+    fn p2tr_prevout(&self) -> TxOut {
+        let secp = Secp256k1::verification_only();
+        let (key, _) = self.keypair().x_only_public_key();
+        TxOut {
+            value: FIXTURE_VALUE,
+            script_pubkey: ScriptBuf::new_p2tr(&secp, key, None),
+        }
+    }
+
+    fn push_input(&mut self, witness: Witness, prevout: TxOut) {
+        let txid = Txid::from_byte_array(self.hash());
+        self.inputs.push(TxIn {
+            previous_output: OutPoint::new(txid, 0),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness,
+        });
+        self.prevouts.push(prevout);
+    }
+}
+
+/// Returns a transaction with one embedding of every type: an `OP_RETURN`, a bare multisig
+/// output, a taproot annex, a tapscript envelope, and a P2WSH envelope
+pub fn every_embedding_type(seed: u64) -> Fixture {
+    FixtureBuilder::new(seed)
+        .op_return(b"op_return")
+        .bare_multisig(b"bare multisig")
+        .tapscript_envelope(vec![b"tapscript".to_vec(), vec![1; 600]])
+        .legacy_envelope(vec![b"legacy".to_vec()])
+        .annex(b"annex")
+        .build()
+}
+
+/// Returns a transaction whose only embedding is an `OP_RETURN` output pushing `data`
+pub fn op_return_tx(data: &[u8]) -> Transaction {
+    FixtureBuilder::new(0).op_return(data).build().tx
+}
+
+/// Returns a transaction whose only embedding is a P2WSH envelope holding `data`. The witness
+/// is not part of the txid, so the seed is derived from `data` to keep txids distinct.
+pub fn envelope_tx(data: &[u8]) -> Transaction {
+    let hash = sha256::Hash::hash(data).to_byte_array();
+    FixtureBuilder::new(u64::from_le_bytes(hash[..8].try_into().expect("8 bytes")))
+        .legacy_envelope(vec![data.to_vec()])
+        .build()
+        .tx
+}
+
+/// Returns a chain of blocks holding `blocks` of transactions, each linked to the one before,
+/// starting after the all-zero block hash
+pub fn chain(blocks: Vec<Vec<Transaction>>) -> Vec<Block> {
+    let mut prev_blockhash = BlockHash::all_zeros();
+    blocks
+        .into_iter()
+        .enumerate()
+        .map(|(height, txdata)| {
+            let mut block = Block {
+                header: Header {
+                    version: BlockVersion::TWO,
+                    prev_blockhash,
+                    merkle_root: TxMerkleNode::all_zeros(),
+                    time: 1_700_000_000 + 600 * height as u32,
+                    bits: CompactTarget::from_consensus(0x207fffff),
+                    nonce: 0,
+                },
+                txdata,
+            };
+            if let Some(root) = block.compute_merkle_root() {
+                block.header.merkle_root = root;
+            }
+            prev_blockhash = block.block_hash();
+            block
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Embedding, EmbeddingType, ScriptType};
+
+    #[test]
+    fn test_every_embedding_type() {
+        let fixture = every_embedding_type(7);
+        assert_eq!(fixture, every_embedding_type(7));
+        assert_ne!(
+            fixture.tx.compute_txid(),
+            every_embedding_type(8).tx.compute_txid()
+        );
+
+        let embeddings = Embedding::from_transaction_with_prevouts(&fixture.tx, &fixture.prevouts);
+        let types = embeddings
+            .iter()
+            .map(|embedding| embedding.to_type())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            vec![
+                EmbeddingType::OpReturn,
+                EmbeddingType::WitnessEnvelope(ScriptType::Tapscript),
+                EmbeddingType::WitnessEnvelope(ScriptType::Legacy),
+                EmbeddingType::TaprootAnnex,
+            ]
+        );
+        assert_eq!(embeddings[0].payload().as_ref(), b"op_return");
+        assert_eq!(embeddings[3].bytes, b"annex");
+
+        // Without prevouts, the witnesses classify the same way
+        assert_eq!(Embedding::from_transaction(&fixture.tx).len(), 4);
+
+        let multisig = Embedding::from_bare_multisig(&fixture.tx);
+        assert_eq!(multisig.len(), 1);
+        assert_eq!(&multisig[0].bytes[..13], b"bare multisig");
+        assert_eq!(multisig[0].bytes.len(), 31);
+    }
+
+    #[test]
+    fn test_build_defaults() {
+        let fixture = FixtureBuilder::new(1).build();
+        assert_eq!(fixture.tx.input.len(), 1);
+        assert_eq!(fixture.tx.output.len(), 1);
+        assert_eq!(fixture.prevouts.len(), 1);
+        assert!(Embedding::from_transaction(&fixture.tx).is_empty());
+    }
+
+    #[test]
+    fn test_mock_resolver() {
+        let txs = (0..3)
+            .map(|seed| {
+                FixtureBuilder::new(seed)
+                    .op_return(&[seed as u8])
+                    .build()
+                    .tx
+            })
+            .collect::<Vec<_>>();
+        let blocks = chain(vec![txs[..2].to_vec(), txs[2..].to_vec()]);
+        assert_eq!(blocks[1].header.prev_blockhash, blocks[0].block_hash());
+        assert!(blocks.iter().all(|block| block.check_merkle_root()));
+
+        let mut resolver = MockResolver::new();
+        for block in &blocks {
+            resolver.insert_block(block.clone());
+        }
+        let failing = txs[1].compute_txid();
+        resolver.fail_transaction(failing, ResolveError::Backend("offline".into()));
+
+        assert_eq!(
+            resolver.embeddings_in_tx(&txs[0].compute_txid()).unwrap()[0].bytes,
+            vec![1, 0]
+        );
+        assert_eq!(
+            resolver.transaction(&failing),
+            Err(ResolveError::Backend("offline".into()))
+        );
+        assert_eq!(
+            resolver
+                .embeddings_in_block(&blocks[0].block_hash())
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(resolver.transaction_calls(), 2);
+        assert_eq!(resolver.block_calls(), 1);
+    }
+}
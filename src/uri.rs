@@ -0,0 +1,260 @@
+//! # Embedding URIs
+//!
+//! Embeddings are referenced as `embed:<id>[?<params>]`, where `<id>` is any string form of an
+//! [`EmbeddingId`] and the optional query parameters are:
+//!
+//! - `network`: the network the transaction belongs to (e.g. `bitcoin`, `signet`)
+//! - `range`: a byte range `<start>-<end>` (end exclusive) within the payload
+//!
+//! Unknown parameters are ignored unless prefixed with `req-`, in which case the URI is rejected.
+
+use crate::{EmbeddingId, EmbeddingIdError};
+
+use bitcoin::Network;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// The URI scheme for embedding references
+pub const SCHEME: &str = "embed";
+
+/// A reference to an embedding, optionally scoped to a network and byte range
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EmbeddingUri {
+    /// The embedding id
+    pub id: EmbeddingId,
+    /// The network the transaction belongs to
+    pub network: Option<Network>,
+    /// The byte range within the payload (end exclusive)
+    pub range: Option<Range<usize>>,
+}
+
+impl EmbeddingUri {
+    /// Returns a URI referencing the entire payload on an unspecified network
+    pub fn new(id: EmbeddingId) -> Self {
+        Self {
+            id,
+            network: None,
+            range: None,
+        }
+    }
+
+    /// Sets the network
+    pub fn with_network(mut self, network: Network) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Sets the byte range
+    pub fn with_range(mut self, range: Range<usize>) -> Self {
+        self.range = Some(range);
+        self
+    }
+
+    /// Returns the referenced bytes of a payload, or `None` if the range is out of bounds
+    pub fn slice<'a>(&self, payload: &'a [u8]) -> Option<&'a [u8]> {
+        match &self.range {
+            Some(range) => payload.get(range.clone()),
+            None => Some(payload),
+        }
+    }
+}
+
+impl From<EmbeddingId> for EmbeddingUri {
+    fn from(id: EmbeddingId) -> Self {
+        Self::new(id)
+    }
+}
+
+impl fmt::Display for EmbeddingUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{SCHEME}:{}", self.id)?;
+
+        let mut separator = '?';
+
+        if let Some(network) = self.network {
+            write!(f, "{separator}network={network}")?;
+            separator = '&';
+        }
+
+        if let Some(range) = &self.range {
+            write!(f, "{separator}range={}-{}", range.start, range.end)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for EmbeddingUri {
+    type Err = EmbeddingIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = s.split_once(':').ok_or(EmbeddingIdError::InvalidFormat)?;
+
+        if !scheme.eq_ignore_ascii_case(SCHEME) {
+            return Err(EmbeddingIdError::InvalidFormat);
+        }
+
+        let (id, query) = match rest.split_once('?') {
+            Some((id, query)) => (id, Some(query)),
+            None => (rest, None),
+        };
+
+        let mut uri = Self::new(EmbeddingId::from_str(id)?);
+
+        for param in query.into_iter().flat_map(|query| query.split('&')) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+
+            match key {
+                "network" if uri.network.is_none() => {
+                    uri.network = Some(
+                        Network::from_str(value).map_err(|_| EmbeddingIdError::InvalidFormat)?,
+                    );
+                }
+                "range" if uri.range.is_none() => {
+                    let (start, end) = value
+                        .split_once('-')
+                        .ok_or(EmbeddingIdError::InvalidFormat)?;
+                    let start = start
+                        .parse::<usize>()
+                        .map_err(|_| EmbeddingIdError::InvalidIndex)?;
+                    let end = end
+                        .parse::<usize>()
+                        .map_err(|_| EmbeddingIdError::InvalidIndex)?;
+
+                    if start > end {
+                        return Err(EmbeddingIdError::InvalidIndex);
+                    }
+
+                    uri.range = Some(start..end);
+                }
+                "network" | "range" => return Err(EmbeddingIdError::InvalidFormat),
+                key if key.starts_with("req-") => return Err(EmbeddingIdError::InvalidFormat),
+                _ => {}
+            }
+        }
+
+        Ok(uri)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TXID: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    fn id(s: &str) -> EmbeddingId {
+        EmbeddingId::from_str(&format!("{TXID}:{s}")).unwrap()
+    }
+
+    #[test]
+    fn test_to_uri() {
+        assert_eq!(id("rt:2").to_uri(), format!("embed:{TXID}:rt:2"));
+
+        let uri = EmbeddingUri::new(id("te:0:1"))
+            .with_network(Network::Signet)
+            .with_range(10..20);
+        assert_eq!(
+            uri.to_string(),
+            format!("embed:{TXID}:te:0:1?network=signet&range=10-20")
+        );
+
+        let uri = EmbeddingUri::new(id("ta:0")).with_range(0..5);
+        assert_eq!(uri.to_string(), format!("embed:{TXID}:ta:0?range=0-5"));
+    }
+
+    #[test]
+    fn test_from_uri_roundtrip() {
+        let uris = [
+            EmbeddingUri::new(id("rt:2")),
+            EmbeddingUri::new(id("le:1")).with_network(Network::Bitcoin),
+            EmbeddingUri::new(id("te:0:3")).with_range(5..5),
+            EmbeddingUri::new(id("ta:4"))
+                .with_network(Network::Regtest)
+                .with_range(1..100),
+        ];
+
+        for uri in uris {
+            assert_eq!(EmbeddingId::from_uri(&uri.to_string()), Ok(uri.clone()));
+        }
+
+        // Bech32m ids are accepted as well
+        let uri = format!("EMBED:{}?range=0-1", id("te:1").to_bech32m());
+        assert_eq!(
+            EmbeddingId::from_uri(&uri),
+            Ok(EmbeddingUri::new(id("te:1")).with_range(0..1))
+        );
+    }
+
+    #[test]
+    fn test_from_uri_parameters() {
+        // Unknown optional parameters are ignored
+        let uri = format!("embed:{TXID}:rt:0?label=hello&network=testnet");
+        assert_eq!(
+            EmbeddingId::from_uri(&uri),
+            Ok(EmbeddingUri::new(id("rt:0")).with_network(Network::Testnet))
+        );
+
+        // Unknown required parameters are rejected
+        let uri = format!("embed:{TXID}:rt:0?req-version=2");
+        assert_eq!(
+            EmbeddingId::from_uri(&uri),
+            Err(EmbeddingIdError::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn test_from_uri_invalid() {
+        let cases = [
+            (
+                format!("bitcoin:{TXID}:rt:0"),
+                EmbeddingIdError::InvalidFormat,
+            ),
+            ("embed".to_string(), EmbeddingIdError::InvalidFormat),
+            (format!("embed:{TXID}"), EmbeddingIdError::InvalidFormat),
+            (
+                format!("embed:{TXID}:rt:0?network=moon"),
+                EmbeddingIdError::InvalidFormat,
+            ),
+            (
+                format!("embed:{TXID}:rt:0?range=5"),
+                EmbeddingIdError::InvalidFormat,
+            ),
+            (
+                format!("embed:{TXID}:rt:0?range=5-1"),
+                EmbeddingIdError::InvalidIndex,
+            ),
+            (
+                format!("embed:{TXID}:rt:0?range=0-1&range=0-2"),
+                EmbeddingIdError::InvalidFormat,
+            ),
+        ];
+
+        for (uri, err) in cases {
+            assert_eq!(EmbeddingId::from_uri(&uri), Err(err), "{uri}");
+        }
+    }
+
+    #[test]
+    fn test_slice() {
+        let payload = b"hello world";
+
+        assert_eq!(
+            EmbeddingUri::new(id("rt:0")).slice(payload),
+            Some(&payload[..])
+        );
+        assert_eq!(
+            EmbeddingUri::new(id("rt:0"))
+                .with_range(6..11)
+                .slice(payload),
+            Some(&b"world"[..])
+        );
+        assert_eq!(
+            EmbeddingUri::new(id("rt:0"))
+                .with_range(6..12)
+                .slice(payload),
+            None
+        );
+    }
+}
@@ -2,6 +2,8 @@
 
 // Source: ordinals/varint.rs
 
+use std::io;
+
 /// Adds an integer to a byte array with LEB128 encoding
 pub fn encode_to_vec(mut n: u128, v: &mut Vec<u8>) {
     while n >> 7 > 0 {
@@ -36,6 +38,18 @@ pub fn decode(buffer: &[u8]) -> Result<(u128, usize), Error> {
     Err(Error::Unterminated)
 }
 
+/// Decodes value from a LEB128-encoded integer, rejecting non-minimal encodings
+/// (e.g. `0x80 0x00` for zero) so that every value has exactly one valid encoding
+pub fn decode_strict(buffer: &[u8]) -> Result<(u128, usize), Error> {
+    let (n, length) = decode(buffer)?;
+
+    if length > 1 && buffer[length - 1] == 0 {
+        return Err(Error::NonMinimal);
+    }
+
+    Ok((n, length))
+}
+
 /// Returns a LEB128-encoded integer
 pub fn encode(n: u128) -> Vec<u8> {
     let mut v = Vec::new();
@@ -43,8 +57,179 @@ pub fn encode(n: u128) -> Vec<u8> {
     v
 }
 
+/// Writes a LEB128-encoded integer, returning the number of bytes written
+pub fn encode_into<W: io::Write>(n: u128, writer: &mut W) -> io::Result<usize> {
+    let mut buffer = [0u8; 19];
+    let mut i = 0;
+    let mut n = n;
+
+    while n >> 7 > 0 {
+        buffer[i] = n.to_le_bytes()[0] | 0b1000_0000;
+        n >>= 7;
+        i += 1;
+    }
+    buffer[i] = n.to_le_bytes()[0];
+
+    writer.write_all(&buffer[..=i])?;
+    Ok(i + 1)
+}
+
+/// Reads a LEB128-encoded integer one byte at a time, returning the value and the number
+/// of bytes read.
+///
+/// Invalid encodings are returned as `InvalidData` errors wrapping [`Error`], and a stream
+/// that ends mid-integer as `UnexpectedEof`.
+pub fn decode_from<R: io::Read>(reader: &mut R) -> io::Result<(u128, usize)> {
+    let mut buffer = Vec::with_capacity(19);
+    let mut byte = [0u8; 1];
+
+    loop {
+        reader.read_exact(&mut byte)?;
+        buffer.push(byte[0]);
+
+        match decode(&buffer) {
+            Ok(result) => return Ok(result),
+            Err(Error::Unterminated) => continue,
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+    }
+}
+
+/// Returns the number of bytes in the LEB128 encoding of an integer
+pub fn encoded_len(n: u128) -> usize {
+    let bits = 128 - n.leading_zeros() as usize;
+    bits.div_ceil(7).max(1)
+}
+
+/// Adds a signed integer to a byte array with zigzag LEB128 encoding
+pub fn encode_signed_to_vec(n: i128, v: &mut Vec<u8>) {
+    encode_to_vec(zigzag(n), v);
+}
+
+/// Decodes value from a zigzag LEB128-encoded integer
+pub fn decode_signed(buffer: &[u8]) -> Result<(i128, usize), Error> {
+    let (n, length) = decode(buffer)?;
+    Ok((unzigzag(n), length))
+}
+
+/// Returns a zigzag LEB128-encoded integer
+pub fn encode_signed(n: i128) -> Vec<u8> {
+    let mut v = Vec::new();
+    encode_signed_to_vec(n, &mut v);
+    v
+}
+
+/// Maps signed integers to unsigned integers so that small magnitudes encode compactly
+/// (0 => 0, -1 => 1, 1 => 2, -2 => 3, ...)
+fn zigzag(n: i128) -> u128 {
+    ((n << 1) ^ (n >> 127)) as u128
+}
+
+fn unzigzag(n: u128) -> i128 {
+    ((n >> 1) as i128) ^ -((n & 1) as i128)
+}
+
+/// Bitcoin's native CompactSize encoding
+pub mod compact_size {
+    use super::Error;
+
+    /// Returns a CompactSize-encoded integer
+    pub fn encode(n: u64) -> Vec<u8> {
+        let mut v = Vec::new();
+        encode_to_vec(n, &mut v);
+        v
+    }
+
+    /// Adds an integer to a byte array with CompactSize encoding
+    pub fn encode_to_vec(n: u64, v: &mut Vec<u8>) {
+        match n {
+            0..=0xfc => v.push(n as u8),
+            0xfd..=0xffff => {
+                v.push(0xfd);
+                v.extend((n as u16).to_le_bytes());
+            }
+            0x10000..=0xffff_ffff => {
+                v.push(0xfe);
+                v.extend((n as u32).to_le_bytes());
+            }
+            _ => {
+                v.push(0xff);
+                v.extend(n.to_le_bytes());
+            }
+        }
+    }
+
+    /// Decodes value from a CompactSize-encoded integer, rejecting non-canonical encodings
+    pub fn decode(buffer: &[u8]) -> Result<(u64, usize), Error> {
+        let Some(&prefix) = buffer.first() else {
+            return Err(Error::Unterminated);
+        };
+
+        let (width, min) = match prefix {
+            0..=0xfc => return Ok((prefix.into(), 1)),
+            0xfd => (2, 0xfd),
+            0xfe => (4, 0x10000),
+            0xff => (8, 0x1_0000_0000),
+        };
+
+        let Some(bytes) = buffer.get(1..=width) else {
+            return Err(Error::Unterminated);
+        };
+
+        let mut le = [0u8; 8];
+        le[..width].copy_from_slice(bytes);
+        let n = u64::from_le_bytes(le);
+
+        if n < min {
+            return Err(Error::NonMinimal);
+        }
+
+        Ok((n, width + 1))
+    }
+}
+
+/// Delta encoding of sorted integer sequences
+pub mod list {
+    use super::Error;
+
+    /// Returns a list of ascending integers encoded as LEB128 deltas, where the first
+    /// value is encoded as-is and each following value as the difference from its
+    /// predecessor. Throws an error if the values are not sorted in ascending order.
+    pub fn encode(values: &[u128]) -> Result<Vec<u8>, Error> {
+        let mut v = Vec::new();
+        let mut last = 0;
+
+        for &value in values {
+            let Some(delta) = value.checked_sub(last) else {
+                return Err(Error::Unsorted);
+            };
+            super::encode_to_vec(delta, &mut v);
+            last = value;
+        }
+
+        Ok(v)
+    }
+
+    /// Decodes a list of delta-encoded integers spanning the entire buffer. Throws an
+    /// error if the running total exceeds `u128::MAX`.
+    pub fn decode(buffer: &[u8]) -> Result<Vec<u128>, Error> {
+        let mut values = Vec::new();
+        let mut index = 0;
+        let mut last = 0u128;
+
+        while index < buffer.len() {
+            let (delta, length) = super::decode(&buffer[index..])?;
+            last = last.checked_add(delta).ok_or(Error::Overflow)?;
+            values.push(last);
+            index += length;
+        }
+
+        Ok(values)
+    }
+}
+
 /// VarInt Error
-#[derive(PartialEq, Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
     /// Too long
     Overlong,
@@ -52,6 +237,10 @@ pub enum Error {
     Overflow,
     /// Unterminated
     Unterminated,
+    /// Not minimally encoded
+    NonMinimal,
+    /// Not sorted in ascending order
+    Unsorted,
 }
 
 impl std::fmt::Display for Error {
@@ -60,6 +249,8 @@ impl std::fmt::Display for Error {
             Self::Overlong => write!(f, "too long"),
             Self::Overflow => write!(f, "overflow"),
             Self::Unterminated => write!(f, "unterminated"),
+            Self::NonMinimal => write!(f, "non-minimal"),
+            Self::Unsorted => write!(f, "unsorted"),
         }
     }
 }
@@ -178,4 +369,191 @@ mod tests {
     fn varints_must_be_terminated() {
         assert_eq!(decode(&[128]), Err(Error::Unterminated));
     }
+
+    #[test]
+    fn signed_values_use_zigzag_mapping() {
+        assert_eq!(encode_signed(0), vec![0]);
+        assert_eq!(encode_signed(-1), vec![1]);
+        assert_eq!(encode_signed(1), vec![2]);
+        assert_eq!(encode_signed(-2), vec![3]);
+        assert_eq!(encode_signed(63), vec![126]);
+        assert_eq!(encode_signed(-64), vec![127]);
+        assert_eq!(encode_signed(64), vec![128, 1]);
+    }
+
+    #[test]
+    fn signed_extremes_round_trip_successfully() {
+        for n in [0, 1, -1, i128::MAX, i128::MIN, i128::MAX - 1, i128::MIN + 1] {
+            let encoded = encode_signed(n);
+            let (decoded, length) = decode_signed(&encoded).unwrap();
+            assert_eq!(decoded, n);
+            assert_eq!(length, encoded.len());
+        }
+    }
+
+    #[test]
+    fn signed_powers_of_two_round_trip_successfully() {
+        for i in 0..127 {
+            for n in [1i128 << i, -(1i128 << i)] {
+                let encoded = encode_signed(n);
+                let (decoded, length) = decode_signed(&encoded).unwrap();
+                assert_eq!(decoded, n);
+                assert_eq!(length, encoded.len());
+            }
+        }
+    }
+
+    #[test]
+    fn signed_decode_propagates_errors() {
+        assert_eq!(decode_signed(&[128]), Err(Error::Unterminated));
+    }
+
+    #[test]
+    fn encoded_len_matches_encoding() {
+        assert_eq!(encoded_len(0), 1);
+        assert_eq!(encoded_len(127), 1);
+        assert_eq!(encoded_len(128), 2);
+        assert_eq!(encoded_len(u128::MAX), 19);
+
+        for i in 0..128 {
+            let n = 1 << i;
+            assert_eq!(encoded_len(n), encode(n).len());
+            assert_eq!(encoded_len(n - 1), encode(n - 1).len());
+        }
+    }
+
+    #[test]
+    fn encode_into_matches_encode() {
+        for n in [0, 1, 127, 128, 300, u64::MAX as u128, u128::MAX] {
+            let mut v = vec![0xff];
+            let written = encode_into(n, &mut v).unwrap();
+            assert_eq!(written, encoded_len(n));
+            assert_eq!(v[1..], encode(n)[..]);
+        }
+    }
+
+    #[test]
+    fn decode_from_reads_consecutive_values() {
+        let mut bytes = Vec::new();
+        for n in [0, 300, u128::MAX] {
+            encode_to_vec(n, &mut bytes);
+        }
+
+        let mut reader = io::Cursor::new(bytes);
+        assert_eq!(decode_from(&mut reader).unwrap(), (0, 1));
+        assert_eq!(decode_from(&mut reader).unwrap(), (300, 2));
+        assert_eq!(decode_from(&mut reader).unwrap(), (u128::MAX, 19));
+
+        let err = decode_from(&mut reader).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn decode_from_rejects_invalid_encodings() {
+        let err = decode_from(&mut io::Cursor::new([128])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let err = decode_from(&mut io::Cursor::new([128; 20])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "too long");
+    }
+
+    #[test]
+    fn compact_size_boundaries_round_trip_successfully() {
+        let cases: [(u64, usize); 9] = [
+            (0, 1),
+            (0xfc, 1),
+            (0xfd, 3),
+            (0xffff, 3),
+            (0x10000, 5),
+            (0xffff_ffff, 5),
+            (0x1_0000_0000, 9),
+            (u64::MAX, 9),
+            (1000, 3),
+        ];
+
+        for (n, len) in cases {
+            let encoded = compact_size::encode(n);
+            assert_eq!(encoded.len(), len);
+            assert_eq!(compact_size::decode(&encoded), Ok((n, len)));
+            assert_eq!(encoded, bitcoin::consensus::serialize(&bitcoin::VarInt(n)));
+        }
+    }
+
+    #[test]
+    fn compact_size_rejects_non_canonical_encodings() {
+        assert_eq!(
+            compact_size::decode(&[0xfd, 0xfc, 0x00]),
+            Err(Error::NonMinimal)
+        );
+        assert_eq!(
+            compact_size::decode(&[0xfe, 0xff, 0xff, 0x00, 0x00]),
+            Err(Error::NonMinimal)
+        );
+        assert_eq!(
+            compact_size::decode(&[0xff, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0]),
+            Err(Error::NonMinimal)
+        );
+    }
+
+    #[test]
+    fn compact_size_must_be_complete() {
+        assert_eq!(compact_size::decode(&[]), Err(Error::Unterminated));
+        assert_eq!(
+            compact_size::decode(&[0xfd, 0x00]),
+            Err(Error::Unterminated)
+        );
+        assert_eq!(compact_size::decode(&[0xff; 8]), Err(Error::Unterminated));
+    }
+
+    #[test]
+    fn strict_decoding_rejects_non_minimal_encodings() {
+        assert_eq!(decode_strict(&[0x80, 0x00]), Err(Error::NonMinimal));
+        assert_eq!(decode_strict(&[0xff, 0x80, 0x00]), Err(Error::NonMinimal));
+        assert_eq!(decode(&[0x80, 0x00]), Ok((0, 2)));
+        assert_eq!(decode_strict(&[128]), Err(Error::Unterminated));
+    }
+
+    #[test]
+    fn strict_decoding_accepts_minimal_encodings() {
+        for i in 0..128 {
+            let n = 1 << i;
+            let encoded = encode(n);
+            assert_eq!(decode_strict(&encoded), Ok((n, encoded.len())));
+        }
+
+        assert_eq!(decode_strict(&[0]), Ok((0, 1)));
+        assert_eq!(decode_strict(&encode(u128::MAX)), Ok((u128::MAX, 19)));
+    }
+
+    #[test]
+    fn lists_encode_as_deltas() {
+        assert_eq!(list::encode(&[]), Ok(vec![]));
+        assert_eq!(list::encode(&[5, 5, 7, 300]), Ok(vec![5, 0, 2, 165, 2]));
+        assert_eq!(list::decode(&[5, 0, 2, 165, 2]), Ok(vec![5, 5, 7, 300]));
+    }
+
+    #[test]
+    fn lists_round_trip_successfully() {
+        let values = [0, 1, 1000, 1 << 64, u128::MAX - 1, u128::MAX];
+        let encoded = list::encode(&values).unwrap();
+        assert_eq!(list::decode(&encoded).unwrap(), values);
+    }
+
+    #[test]
+    fn lists_must_be_sorted() {
+        assert_eq!(list::encode(&[2, 1]), Err(Error::Unsorted));
+    }
+
+    #[test]
+    fn lists_may_not_overflow_u128() {
+        let mut encoded = encode(u128::MAX);
+        encoded.extend(encode(1));
+        assert_eq!(list::decode(&encoded), Err(Error::Overflow));
+    }
+
+    #[test]
+    fn lists_propagate_varint_errors() {
+        assert_eq!(list::decode(&[1, 128]), Err(Error::Unterminated));
+    }
 }
@@ -0,0 +1,197 @@
+//! # JavaScript Bindings
+//!
+//! [wasm-bindgen](https://docs.rs/wasm-bindgen) exports for decoding and building embeddings in the
+//! browser. Bytes cross the boundary as hex strings and structured results as JSON strings in the
+//! [`schema`](crate::schema) model, so the bindings need no JavaScript glue beyond `JSON.parse`.
+//! Build with `cargo rustc --lib --release --crate-type cdylib --target wasm32-unknown-unknown
+//! --features wasm` and generate the JavaScript module with `wasm-bindgen --target web`.
+
+use crate::envelope::EnvelopeBuilder;
+use crate::message::Message;
+use crate::{Embedding, TAPROOT_ANNEX_DATA_TAG};
+
+use bitcoin::consensus::encode::deserialize_hex;
+use bitcoin::hex::{DisplayHex, FromHex};
+use bitcoin::script::{Builder, PushBytesBuf};
+use bitcoin::taproot::TAPROOT_ANNEX_PREFIX;
+use bitcoin::{ScriptBuf, Transaction};
+use serde_json::Value;
+use wasm_bindgen::prelude::*;
+
+/// Returns the embeddings in a hex-encoded transaction as a JSON array of
+/// [`EmbeddingRecord`](crate::schema::EmbeddingRecord)s
+#[wasm_bindgen]
+pub fn extract_from_tx_hex(tx_hex: &str) -> Result<String, JsError> {
+    extract(tx_hex).map_err(|e| JsError::new(&e))
+}
+
+/// Decodes hex-encoded TLV messages into a JSON array of
+/// [`MessageRecord`](crate::schema::MessageRecord)s
+#[wasm_bindgen]
+pub fn decode_messages(hex: &str) -> Result<String, JsError> {
+    decode(hex).map_err(|e| JsError::new(&e))
+}
+
+/// Encodes a JSON array of objects with a `tag` and hex `body` as hex-encoded TLV messages
+#[wasm_bindgen]
+pub fn encode_messages(json: &str) -> Result<String, JsError> {
+    encode(json).map_err(|e| JsError::new(&e))
+}
+
+/// Returns the hex-encoded `OP_RETURN` script pubkey carrying hex `data`
+#[wasm_bindgen]
+pub fn op_return_script(data_hex: &str) -> Result<String, JsError> {
+    op_return(data_hex).map_err(|e| JsError::new(&e))
+}
+
+/// Returns the hex-encoded taproot annex carrying hex `data`
+#[wasm_bindgen]
+pub fn annex(data_hex: &str) -> Result<String, JsError> {
+    data_annex(data_hex).map_err(|e| JsError::new(&e))
+}
+
+/// Returns the hex-encoded envelope script carrying the hex `fields`, as built by
+/// [`EnvelopeBuilder`] with its default settings
+#[wasm_bindgen]
+pub fn envelope_script(fields: Vec<String>) -> Result<String, JsError> {
+    envelope(&fields).map_err(|e| JsError::new(&e))
+}
+
+fn extract(tx_hex: &str) -> Result<String, String> {
+    let tx: Transaction = deserialize_hex(tx_hex).map_err(|e| e.to_string())?;
+
+    let embeddings = Embedding::from_transaction(&tx)
+        .into_iter()
+        .chain(Embedding::from_bare_multisig(&tx))
+        .collect::<Vec<_>>();
+
+    serde_json::to_string(&embeddings).map_err(|e| e.to_string())
+}
+
+fn decode(hex: &str) -> Result<String, String> {
+    let bytes = Vec::<u8>::from_hex(hex).map_err(|e| e.to_string())?;
+    let messages = Message::decode(&bytes).map_err(|e| e.to_string())?;
+    serde_json::to_string(&messages).map_err(|e| e.to_string())
+}
+
+fn encode(json: &str) -> Result<String, String> {
+    let value: Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let entries = value.as_array().ok_or("expected an array of messages")?;
+
+    let messages = entries
+        .iter()
+        .map(|entry| {
+            // Tags may be numbers or decimal strings, since tags can exceed 2^53
+            let tag = match &entry["tag"] {
+                Value::Number(tag) => tag.as_u64().map(u128::from),
+                Value::String(tag) => tag.parse().ok(),
+                _ => None,
+            }
+            .ok_or("invalid message tag")?;
+            let body = entry["body"]
+                .as_str()
+                .ok_or("missing message body")
+                .and_then(|body| Vec::from_hex(body).map_err(|_| "invalid message body"))?;
+
+            Message::new(tag, body).map_err(|e| e.to_string())
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(Message::encode(messages).to_lower_hex_string())
+}
+
+fn op_return(data_hex: &str) -> Result<String, String> {
+    let data = Vec::<u8>::from_hex(data_hex).map_err(|e| e.to_string())?;
+    let data = PushBytesBuf::try_from(data).map_err(|e| e.to_string())?;
+    Ok(ScriptBuf::new_op_return(data).to_hex_string())
+}
+
+fn data_annex(data_hex: &str) -> Result<String, String> {
+    let data = Vec::<u8>::from_hex(data_hex).map_err(|e| e.to_string())?;
+    Ok([&[TAPROOT_ANNEX_PREFIX, TAPROOT_ANNEX_DATA_TAG][..], &data]
+        .concat()
+        .to_lower_hex_string())
+}
+
+fn envelope(fields: &[String]) -> Result<String, String> {
+    let fields = fields
+        .iter()
+        .map(|field| Vec::<u8>::from_hex(field).map_err(|e| e.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    EnvelopeBuilder::new()
+        .try_append_to_builder(fields, Builder::new())
+        .map(|builder| builder.into_script().to_hex_string())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::consensus::encode::serialize_hex;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{
+        Amount, OutPoint, Sequence, TxIn, TxOut, Txid, Witness, absolute::LockTime,
+        transaction::Version,
+    };
+    use serde_json::json;
+
+    #[test]
+    fn test_extract() {
+        let annex = Vec::from_hex(&data_annex("cafe").unwrap()).unwrap();
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::from_slice(&[vec![1; 64], annex]),
+            }],
+            output: vec![TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::from_hex(&op_return("0102").unwrap()).unwrap(),
+            }],
+        };
+
+        let value: Value = serde_json::from_str(&extract(&serialize_hex(&tx)).unwrap()).unwrap();
+        let embeddings = value.as_array().unwrap();
+        assert_eq!(embeddings.len(), 2);
+        assert_eq!(embeddings[0]["version"], 1);
+        assert_eq!(embeddings[0]["type"], "rt");
+        assert_eq!(embeddings[0]["location"]["output"], 0);
+        assert_eq!(embeddings[0]["bytes"], "020102");
+        assert_eq!(embeddings[1]["type"], "ta");
+        assert_eq!(embeddings[1]["bytes"], "cafe");
+        assert_eq!(
+            embeddings[1]["id"],
+            format!("{}:ta:0", tx.compute_txid()).as_str()
+        );
+
+        assert!(extract("00").is_err());
+    }
+
+    #[test]
+    fn test_messages() {
+        let hex = encode(r#"[{"tag": 1, "body": "ff"}, {"tag": "2", "body": ""}]"#).unwrap();
+        let value: Value = serde_json::from_str(&decode(&hex).unwrap()).unwrap();
+        assert_eq!(
+            value,
+            json!([{ "tag": "1", "body": "ff" }, { "tag": "2", "body": "" }])
+        );
+
+        assert!(encode(r#"[{"tag": 0, "body": ""}]"#).is_err());
+        assert!(encode(r#"{"tag": 1}"#).is_err());
+    }
+
+    #[test]
+    fn test_envelope() {
+        let script = envelope(&["6f7264".to_string(), "01".to_string()]).unwrap();
+        let expected = EnvelopeBuilder::new()
+            .append_to_builder(vec![b"ord".to_vec(), vec![1]], Builder::new())
+            .into_script();
+        assert_eq!(script, expected.to_hex_string());
+        assert!(envelope(&["zz".to_string()]).is_err());
+    }
+}
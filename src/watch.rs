@@ -0,0 +1,150 @@
+//! # Watch Lists
+//!
+//! A [`WatchList`] selects the embeddings a service cares about by embedding type, payload prefix,
+//! and message tag. Each kind of predicate matches if any of its values does, and an embedding must
+//! satisfy every kind that has values; an empty watch list matches everything.
+//!
+//! Predicates are evaluated on borrowed [`EmbeddingRef`]s, so [`WatchList::extract`] copies only
+//! the embeddings that match.
+
+use crate::message::{Message, Tag};
+use crate::{Embedding, EmbeddingRef, EmbeddingType};
+
+use bitcoin::Transaction;
+use std::ops::ControlFlow;
+
+/// A set of predicates selecting embeddings
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WatchList {
+    types: Vec<EmbeddingType>,
+    prefixes: Vec<Vec<u8>>,
+    tags: Vec<Tag>,
+}
+
+impl WatchList {
+    /// Returns a watch list matching every embedding
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also matches embeddings of type `embedding_type`
+    pub fn embedding_type(mut self, embedding_type: EmbeddingType) -> Self {
+        self.types.push(embedding_type);
+        self
+    }
+
+    /// Also matches payloads beginning with `prefix`
+    pub fn prefix(mut self, prefix: impl Into<Vec<u8>>) -> Self {
+        self.prefixes.push(prefix.into());
+        self
+    }
+
+    /// Also matches payloads holding a message with `tag`, as decoded by [`Message::decode`]
+    pub fn tag(mut self, tag: Tag) -> Self {
+        self.tags.push(tag);
+        self
+    }
+
+    /// Returns whether the watch list has no predicates, and so matches every embedding
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty() && self.prefixes.is_empty() && self.tags.is_empty()
+    }
+
+    /// Returns whether `embedding` satisfies the watch list. The payload is only read if the
+    /// type matches, and messages are only decoded if the prefix matches.
+    pub fn matches(&self, embedding: &EmbeddingRef<'_>) -> bool {
+        if !self.types.is_empty() && !self.types.contains(&embedding.to_type()) {
+            return false;
+        }
+        if self.prefixes.is_empty() && self.tags.is_empty() {
+            return true;
+        }
+
+        let payload = embedding.payload();
+        if !self.prefixes.is_empty()
+            && !self
+                .prefixes
+                .iter()
+                .any(|prefix| payload.starts_with(prefix))
+        {
+            return false;
+        }
+
+        self.tags.is_empty()
+            || Message::decode(&payload).is_ok_and(|messages| {
+                messages
+                    .iter()
+                    .any(|message| self.tags.contains(&message.tag))
+            })
+    }
+
+    /// Returns whether an owned `embedding` satisfies the watch list, as in
+    /// [`WatchList::matches`]
+    pub fn matches_embedding(&self, embedding: &Embedding) -> bool {
+        self.matches(&embedding.to_ref())
+    }
+
+    /// Returns the embeddings in `tx` that satisfy the watch list, in the order of
+    /// [`Embedding::from_transaction`], copying only those
+    pub fn extract(&self, tx: &Transaction) -> Vec<Embedding> {
+        let mut embeddings = Vec::new();
+        let _: ControlFlow<()> = Embedding::visit(tx, |embedding| {
+            if self.matches(&embedding) {
+                embeddings.push(embedding.to_embedding());
+            }
+            ControlFlow::Continue(())
+        });
+        embeddings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ScriptType;
+    use crate::testing::FixtureBuilder;
+
+    fn transaction() -> Transaction {
+        let messages = Message::encode(vec![
+            Message::new(7, b"body".to_vec()).unwrap(),
+            Message::new(9, vec![]).unwrap(),
+        ]);
+        FixtureBuilder::new(0)
+            .tapscript_envelope(vec![messages])
+            .op_return(b"ord1")
+            .op_return(b"abc")
+            .build()
+            .tx
+    }
+
+    #[test]
+    fn test_matches() {
+        let tx = transaction();
+        let all = Embedding::from_transaction(&tx);
+        assert_eq!(all.len(), 3);
+
+        assert!(WatchList::new().is_empty());
+        assert_eq!(WatchList::new().extract(&tx), all);
+
+        let op_returns = WatchList::new().embedding_type(EmbeddingType::OpReturn);
+        assert_eq!(op_returns.extract(&tx), all[..2]);
+
+        let prefixed = WatchList::new().prefix(*b"ord").prefix(*b"xyz");
+        assert_eq!(prefixed.extract(&tx), all[..1]);
+
+        let tagged = WatchList::new().tag(9);
+        assert_eq!(tagged.extract(&tx), all[2..]);
+        assert!(tagged.matches_embedding(&all[2]));
+        assert!(WatchList::new().tag(8).extract(&tx).is_empty());
+
+        // Every kind of predicate must match
+        let both = WatchList::new()
+            .embedding_type(EmbeddingType::WitnessEnvelope(ScriptType::Legacy))
+            .tag(7);
+        assert!(both.extract(&tx).is_empty());
+        let both = WatchList::new()
+            .embedding_type(EmbeddingType::WitnessEnvelope(ScriptType::Tapscript))
+            .tag(7);
+        assert_eq!(both.extract(&tx), all[2..]);
+    }
+}
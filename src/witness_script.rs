@@ -0,0 +1,215 @@
+//! # Miniscript Witness Scripts
+//!
+//! Combines an envelope with real spending conditions in one tapleaf or P2WSH witness script:
+//! the envelope comes first and the miniscript policy after it. An envelope pushes nothing
+//! onto the stack, so the combined script is satisfied by exactly the witnesses that satisfy
+//! the policy (`miniscript` feature).
+
+use crate::envelope::{self, Envelope, EnvelopeBuilder, EnvelopeError};
+
+use bitcoin::script::Builder;
+use bitcoin::{Script, ScriptBuf};
+use miniscript::{Miniscript, ScriptContext, ToPublicKey};
+use std::fmt;
+
+/// A miniscript policy in the script context `Ctx`
+pub type Policy<Ctx> = Miniscript<<Ctx as ScriptContext>::Key, Ctx>;
+
+/// Builds witness scripts with an envelope followed by a miniscript policy
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WitnessScriptBuilder {
+    envelope: EnvelopeBuilder,
+}
+
+/// Error types for building and splitting witness scripts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WitnessScriptError {
+    /// The envelope could not be built, or the combined script exceeds the size limit
+    Envelope(EnvelopeError),
+    /// The policy cannot be satisfied
+    Unsatisfiable,
+    /// The policy fails miniscript's sanity checks
+    Insane(String),
+    /// The script after the envelopes is not a valid miniscript in this context
+    Miniscript(String),
+}
+
+impl WitnessScriptBuilder {
+    /// Returns a builder that encodes envelopes with `envelope`. The envelope builder's
+    /// script size limit applies to the combined script.
+    pub fn new(envelope: EnvelopeBuilder) -> Self {
+        Self { envelope }
+    }
+
+    /// Returns `OP_FALSE OP_IF <fields> OP_ENDIF <policy>`, checking that the policy is
+    /// satisfiable and sane and that the combined script is within the size limit and parses
+    /// back to the same policy. Use `Tap` for a tapleaf or `Segwitv0` for a P2WSH witness
+    /// script.
+    pub fn build<Ctx: ScriptContext>(
+        &self,
+        fields: Vec<Vec<u8>>,
+        policy: &Policy<Ctx>,
+    ) -> Result<ScriptBuf, WitnessScriptError>
+    where
+        Ctx::Key: ToPublicKey,
+    {
+        policy
+            .max_satisfaction_witness_elements()
+            .map_err(|_| WitnessScriptError::Unsatisfiable)?;
+        policy
+            .sanity_check()
+            .map_err(|e| WitnessScriptError::Insane(e.to_string()))?;
+
+        let mut script = self
+            .envelope
+            .append_to_builder(fields, Builder::new())
+            .into_bytes();
+        script.extend(policy.encode().as_bytes());
+
+        let limit = self.envelope.script_size_limit();
+        if script.len() > limit {
+            return Err(EnvelopeError::ScriptTooLarge {
+                size: script.len(),
+                limit,
+            }
+            .into());
+        }
+
+        let script = ScriptBuf::from_bytes(script);
+        let (_, parsed) = split::<Ctx>(&script)?;
+        if parsed != *policy {
+            return Err(WitnessScriptError::Miniscript(
+                "policy changed when combined with envelope".into(),
+            ));
+        }
+
+        Ok(script)
+    }
+}
+
+/// Splits a witness script into its leading envelopes and the miniscript policy after them
+pub fn split<Ctx: ScriptContext>(
+    script: &Script,
+) -> Result<(Vec<Envelope>, Policy<Ctx>), WitnessScriptError> {
+    let mut end = 0;
+    let envelopes = envelope::from_script(script)
+        .into_iter()
+        .take_while(|envelope| {
+            let leading = envelope.range.start == end;
+            if leading {
+                end = envelope.range.end;
+            }
+            leading
+        })
+        .collect();
+
+    let policy = Miniscript::parse(Script::from_bytes(&script.as_bytes()[end..]))
+        .map_err(|e| WitnessScriptError::Miniscript(e.to_string()))?;
+
+    Ok((envelopes, policy))
+}
+
+impl From<EnvelopeError> for WitnessScriptError {
+    fn from(e: EnvelopeError) -> Self {
+        WitnessScriptError::Envelope(e)
+    }
+}
+
+impl fmt::Display for WitnessScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WitnessScriptError::Envelope(e) => write!(f, "{e}"),
+            WitnessScriptError::Unsatisfiable => write!(f, "Policy cannot be satisfied"),
+            WitnessScriptError::Insane(e) => write!(f, "Policy fails sanity check: {e}"),
+            WitnessScriptError::Miniscript(e) => write!(f, "Miniscript error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for WitnessScriptError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WitnessScriptError::Envelope(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::{PublicKey, XOnlyPublicKey};
+    use miniscript::{Segwitv0, Tap};
+    use std::str::FromStr;
+
+    const KEY: &str = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+    const X_ONLY_KEY: &str = "c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+
+    #[test]
+    fn test_tapscript() {
+        let policy = Miniscript::<XOnlyPublicKey, Tap>::from_str(&format!(
+            "and_v(v:pk({X_ONLY_KEY}),older(144))"
+        ))
+        .unwrap();
+
+        let fields = vec![b"ord".to_vec(), vec![7; 600]];
+        let script = WitnessScriptBuilder::default()
+            .build(fields.clone(), &policy)
+            .unwrap();
+
+        let envelope = EnvelopeBuilder::new().append_to_builder(fields, Builder::new());
+        assert!(script.as_bytes().starts_with(envelope.as_bytes()));
+        assert!(script.as_bytes().ends_with(policy.encode().as_bytes()));
+
+        let (envelopes, parsed) = split::<Tap>(&script).unwrap();
+        assert_eq!(envelopes.len(), 1);
+        assert_eq!(
+            envelopes[0].concat(),
+            [b"ord".to_vec(), vec![7; 600]].concat()
+        );
+        assert_eq!(parsed, policy);
+    }
+
+    #[test]
+    fn test_witness_script() {
+        let policy = Miniscript::<PublicKey, Segwitv0>::from_str(&format!("pk({KEY})")).unwrap();
+        let builder = WitnessScriptBuilder::new(EnvelopeBuilder::new().max_script_size(100));
+
+        let script = builder.build(vec![vec![1; 50]], &policy).unwrap();
+        assert_eq!(split::<Segwitv0>(&script).unwrap().1, policy);
+
+        assert_eq!(
+            builder.build(vec![vec![1; 70]], &policy),
+            Err(WitnessScriptError::Envelope(
+                EnvelopeError::ScriptTooLarge {
+                    size: 4 + 70 + 35,
+                    limit: 100
+                }
+            ))
+        );
+
+        // Compressed keys are invalid in tapscript
+        assert!(matches!(
+            split::<Tap>(&script),
+            Err(WitnessScriptError::Miniscript(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_policy() {
+        let builder = WitnessScriptBuilder::default();
+
+        let unsatisfiable = Miniscript::<XOnlyPublicKey, Tap>::from_str_insane("0").unwrap();
+        assert_eq!(
+            builder.build(vec![], &unsatisfiable),
+            Err(WitnessScriptError::Unsatisfiable)
+        );
+
+        let sigless = Miniscript::<XOnlyPublicKey, Tap>::from_str_insane("older(144)").unwrap();
+        assert!(matches!(
+            builder.build(vec![], &sigless),
+            Err(WitnessScriptError::Insane(_))
+        ));
+    }
+}
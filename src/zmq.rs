@@ -0,0 +1,271 @@
+//! # ZMQ Subscriber
+//!
+//! Listens to Bitcoin Core's `rawtx` and `rawblock` ZMQ notifications (`-zmqpubrawtx` and
+//! `-zmqpubrawblock`) and extracts embeddings as transactions and blocks arrive.
+//!
+//! Bitcoin Core publishes `rawtx` both when a transaction enters the mempool and when it is
+//! connected in a block, so the same embedding may be emitted more than once.
+//!
+//! [`Subscriber::watch`] limits the emitted embeddings to those satisfying a [`WatchList`],
+//! which is checked before each embedding is copied.
+
+use crate::Embedding;
+use crate::watch::WatchList;
+
+use bitcoin::consensus::encode;
+use bitcoin::{Block, BlockHash, Transaction};
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+const RAWTX: &[u8] = b"rawtx";
+const RAWBLOCK: &[u8] = b"rawblock";
+
+/// An embedding received over ZMQ
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// An embedding in a transaction published on `rawtx`
+    Transaction(Embedding),
+    /// An embedding in a block published on `rawblock`
+    Block(BlockHash, Embedding),
+}
+
+/// Error types for the ZMQ subscriber
+#[derive(Debug)]
+pub enum ZmqError {
+    /// Socket error
+    Zmq(::zmq::Error),
+    /// A transaction or block could not be decoded
+    Decode(encode::Error),
+    /// A message did not have the expected `[topic, body, sequence]` parts
+    InvalidMessage,
+}
+
+/// The background thread forwarding embeddings from a [`Subscriber`]
+pub type SubscriberHandle = JoinHandle<Result<(), ZmqError>>;
+
+/// A subscriber to Bitcoin Core's `rawtx` and `rawblock` notifications
+pub struct Subscriber {
+    socket: ::zmq::Socket,
+    watch: WatchList,
+}
+
+impl Subscriber {
+    /// Connects to one or more ZMQ endpoints (e.g. `tcp://127.0.0.1:28332`)
+    pub fn connect(endpoints: &[&str]) -> Result<Self, ZmqError> {
+        let socket = ::zmq::Context::new().socket(::zmq::SUB)?;
+
+        socket.set_subscribe(RAWTX)?;
+        socket.set_subscribe(RAWBLOCK)?;
+
+        for endpoint in endpoints {
+            socket.connect(endpoint)?;
+        }
+
+        Ok(Self {
+            socket,
+            watch: WatchList::new(),
+        })
+    }
+
+    /// Emits only the embeddings that satisfy `watch`
+    pub fn watch(mut self, watch: WatchList) -> Self {
+        self.watch = watch;
+        self
+    }
+
+    /// Blocks until the next notification and returns the embeddings it contains
+    pub fn recv(&self) -> Result<Vec<Event>, ZmqError> {
+        let parts = self.socket.recv_multipart(0)?;
+
+        let [topic, body, _sequence] = parts.as_slice() else {
+            return Err(ZmqError::InvalidMessage);
+        };
+
+        match topic.as_slice() {
+            RAWTX => {
+                let tx: Transaction = encode::deserialize(body)?;
+                Ok(self
+                    .watch
+                    .extract(&tx)
+                    .into_iter()
+                    .map(Event::Transaction)
+                    .collect())
+            }
+            RAWBLOCK => {
+                let block: Block = encode::deserialize(body)?;
+                let hash = block.block_hash();
+                Ok(block
+                    .txdata
+                    .iter()
+                    .flat_map(|tx| self.watch.extract(tx))
+                    .map(|embedding| Event::Block(hash, embedding))
+                    .collect())
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Forwards embeddings to `sender` on a background thread until the receiver is dropped or
+    /// a socket error occurs. Undecodable messages are skipped.
+    pub fn spawn(self, sender: Sender<Event>) -> SubscriberHandle {
+        thread::spawn(move || {
+            loop {
+                let events = match self.recv() {
+                    Ok(events) => events,
+                    Err(ZmqError::Zmq(e)) => return Err(ZmqError::Zmq(e)),
+                    Err(_) => continue,
+                };
+
+                for event in events {
+                    if sender.send(event).is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Connects to one or more ZMQ endpoints and returns a channel of embeddings, fed by a
+/// background thread
+pub fn subscribe(endpoints: &[&str]) -> Result<(Receiver<Event>, SubscriberHandle), ZmqError> {
+    let subscriber = Subscriber::connect(endpoints)?;
+    let (sender, receiver) = mpsc::channel();
+    Ok((receiver, subscriber.spawn(sender)))
+}
+
+impl From<::zmq::Error> for ZmqError {
+    fn from(e: ::zmq::Error) -> Self {
+        ZmqError::Zmq(e)
+    }
+}
+
+impl From<encode::Error> for ZmqError {
+    fn from(e: encode::Error) -> Self {
+        ZmqError::Decode(e)
+    }
+}
+
+impl fmt::Display for ZmqError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ZmqError::Zmq(e) => write!(f, "ZMQ error: {e}"),
+            ZmqError::Decode(e) => write!(f, "Decode error: {e}"),
+            ZmqError::InvalidMessage => write!(f, "Invalid ZMQ message"),
+        }
+    }
+}
+
+impl std::error::Error for ZmqError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ZmqError::Zmq(e) => Some(e),
+            ZmqError::Decode(e) => Some(e),
+            ZmqError::InvalidMessage => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{FixtureBuilder, chain};
+
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    fn embedding_tx(data: &[u8]) -> Transaction {
+        FixtureBuilder::new(0)
+            .op_return(data)
+            .legacy_envelope(vec![data.to_vec()])
+            .build()
+            .tx
+    }
+
+    /// Binds a publisher and repeatedly publishes `messages` until stopped, since subscriptions
+    /// take effect asynchronously
+    fn publish(messages: Vec<Vec<Vec<u8>>>) -> (String, Arc<AtomicBool>) {
+        let socket = ::zmq::Context::new().socket(::zmq::PUB).unwrap();
+        socket.bind("tcp://127.0.0.1:*").unwrap();
+        let endpoint = socket.get_last_endpoint().unwrap().unwrap();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+
+        thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                for message in &messages {
+                    socket.send_multipart(message, 0).unwrap();
+                }
+                thread::sleep(Duration::from_millis(20));
+            }
+        });
+
+        (endpoint, stop)
+    }
+
+    #[test]
+    fn test_recv() {
+        let tx = embedding_tx(b"mempool");
+        let block = chain(vec![vec![embedding_tx(b"confirmed")]]).remove(0);
+
+        let (endpoint, stop) = publish(vec![
+            vec![b"hashtx".to_vec(), vec![0; 32], vec![0; 4]],
+            vec![RAWTX.to_vec(), encode::serialize(&tx), vec![0; 4]],
+            vec![
+                RAWBLOCK.to_vec(),
+                encode::serialize(&block),
+                vec![1, 0, 0, 0],
+            ],
+        ]);
+
+        let subscriber = Subscriber::connect(&[&endpoint]).unwrap();
+
+        let mut events = Vec::new();
+        while events.len() < 4 {
+            events.extend(subscriber.recv().unwrap());
+        }
+        stop.store(true, Ordering::Relaxed);
+
+        let tx_events = Embedding::from_transaction(&tx)
+            .into_iter()
+            .map(Event::Transaction)
+            .collect::<Vec<_>>();
+        let block_events = Embedding::from_transaction(&block.txdata[0])
+            .into_iter()
+            .map(|embedding| Event::Block(block.block_hash(), embedding))
+            .collect::<Vec<_>>();
+
+        assert!(tx_events.iter().all(|event| events.contains(event)));
+        assert!(block_events.iter().all(|event| events.contains(event)));
+    }
+
+    #[test]
+    fn test_subscribe_channel() {
+        let tx = embedding_tx(b"channel");
+        let (endpoint, stop) = publish(vec![
+            vec![RAWTX.to_vec(), vec![0xff; 3], vec![0; 4]],
+            vec![RAWTX.to_vec(), encode::serialize(&tx), vec![0; 4]],
+        ]);
+
+        let (receiver, handle) = subscribe(&[&endpoint]).unwrap();
+
+        // Undecodable messages are skipped
+        let expected = Embedding::from_transaction(&tx);
+        assert_eq!(
+            receiver.recv().unwrap(),
+            Event::Transaction(expected[0].clone())
+        );
+        assert_eq!(
+            receiver.recv().unwrap(),
+            Event::Transaction(expected[1].clone())
+        );
+
+        // The thread exits on the next message after the receiver is dropped
+        drop(receiver);
+        assert!(handle.join().unwrap().is_ok());
+        stop.store(true, Ordering::Relaxed);
+    }
+}